@@ -0,0 +1,140 @@
+/// backups.rs - for naming and rotating timestamped copies of a file
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A stored copy of a file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Backup {
+    /// The name the copy is stored under
+    pub name: String,
+    /// When the copy was taken (in seconds since the unix epoch)
+    pub time: u64,
+    /// How many bytes the copy takes up
+    pub size: u64,
+}
+
+/// Get the current time in seconds since the unix epoch
+#[must_use]
+pub fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+/// Convert a number of days since the unix epoch into a (year, month, day) date
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = u32::try_from(doy - (153 * mp + 2) / 5 + 1).unwrap_or(1);
+    let month = u32::try_from(if mp < 10 { mp + 3 } else { mp - 9 }).unwrap_or(1);
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    (year, month, day)
+}
+
+/// Convert a (year, month, day) date into a number of days since the unix epoch
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let mp = i64::from((month + 9) % 12);
+    let doy = (153 * mp + 2) / 5 + i64::from(day) - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+/// Split a time into its date and time of day (in UTC)
+fn split_time(time: u64) -> ((i64, u32, u32), (u64, u64, u64)) {
+    let days = i64::try_from(time / 86400).unwrap_or(i64::MAX);
+    let secs = time % 86400;
+    (
+        civil_from_days(days),
+        (secs / 3600, secs % 3600 / 60, secs % 60),
+    )
+}
+
+/// Name a copy after the time it was taken, as a filesystem safe ISO 8601 timestamp
+/// (e.g. 20241031T174500Z), so that sorting the names sorts the copies by age
+#[must_use]
+pub fn backup_name(time: u64) -> String {
+    let ((year, month, day), (hour, minute, second)) = split_time(time);
+    format!("{year:04}{month:02}{day:02}T{hour:02}{minute:02}{second:02}Z")
+}
+
+/// Read the time a copy was taken back out of its name
+#[must_use]
+pub fn parse_backup_name(name: &str) -> Option<u64> {
+    let digits = |s: &str| !s.is_empty() && s.chars().all(|c| c.is_ascii_digit());
+    let (date, time) = name.strip_suffix('Z')?.split_once('T')?;
+    if date.len() != 8 || time.len() != 6 || !digits(date) || !digits(time) {
+        return None;
+    }
+    let year: i64 = date[0..4].parse().ok()?;
+    let month: u32 = date[4..6].parse().ok()?;
+    let day: u32 = date[6..8].parse().ok()?;
+    let hour: u64 = time[0..2].parse().ok()?;
+    let minute: u64 = time[2..4].parse().ok()?;
+    let second: u64 = time[4..6].parse().ok()?;
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+    if hour > 23 || minute > 59 || second > 59 {
+        return None;
+    }
+    let days = u64::try_from(days_from_civil(year, month, day)).ok()?;
+    Some(days * 86400 + hour * 3600 + minute * 60 + second)
+}
+
+/// Describe when a copy was taken, for showing to the user (e.g. 2024-10-31 17:45:00)
+#[must_use]
+pub fn format_time(time: u64) -> String {
+    let ((year, month, day), (hour, minute, second)) = split_time(time);
+    format!("{year:04}-{month:02}-{day:02} {hour:02}:{minute:02}:{second:02}")
+}
+
+/// Describe how big a copy is, for showing to the user (e.g. 1.5 KB)
+#[must_use]
+pub fn format_size(size: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KB", "MB", "GB"];
+    #[allow(clippy::cast_precision_loss)]
+    let mut value = size as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit + 1 < UNITS.len() {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{size} B")
+    } else {
+        format!("{value:.1} {}", UNITS[unit])
+    }
+}
+
+/// Work out whether enough time has passed since the last copy to take another
+/// (an interval of 0 means copies are never taken on a timer)
+#[must_use]
+pub fn backup_due(last: u64, now: u64, interval: u64) -> bool {
+    interval > 0 && now.saturating_sub(last) >= interval
+}
+
+/// Work out which copies to remove so that no more than `max_count` copies are kept,
+/// taking up no more than `max_bytes` between them. The newest copies are kept first,
+/// and the newest copy is always kept, even if it is bigger than `max_bytes` on its own.
+/// Returns the names of the copies to remove
+#[must_use]
+pub fn prune(backups: &[Backup], max_count: usize, max_bytes: u64) -> Vec<String> {
+    let mut order: Vec<&Backup> = backups.iter().collect();
+    order.sort_by(|a, b| b.time.cmp(&a.time).then_with(|| b.name.cmp(&a.name)));
+    let mut total = 0;
+    let mut result = vec![];
+    for (i, backup) in order.into_iter().enumerate() {
+        total += backup.size;
+        let keep = i == 0 || (i < max_count && total <= max_bytes);
+        if !keep {
+            result.push(backup.name.clone());
+        }
+    }
+    result
+}
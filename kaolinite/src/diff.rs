@@ -0,0 +1,420 @@
+/// diff.rs - for working out how one version of a text differs from another
+use ropey::Rope;
+use std::fmt::Write;
+use std::ops::Range;
+
+/// The most cells the comparison table may have before the differing region
+/// is treated as wholly replaced (keeps very different large files fast)
+const MAX_TABLE: usize = 4_000_000;
+
+/// A line in the difference between two texts
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DiffLine {
+    /// The line is in both texts
+    Same(String),
+    /// The line is only in the new text
+    Added(String),
+    /// The line is only in the old text
+    Removed(String),
+}
+
+/// Work out the differences between two lists of lines (based on their longest common subsequence)
+#[must_use]
+pub fn diff_lines<S: AsRef<str>>(old: &[S], new: &[S]) -> Vec<DiffLine> {
+    let old: Vec<&str> = old.iter().map(AsRef::as_ref).collect();
+    let new: Vec<&str> = new.iter().map(AsRef::as_ref).collect();
+    // Lines shared at the start and end don't need comparing
+    let prefix = old.iter().zip(&new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let (before, after) = (
+        &old[prefix..old.len() - suffix],
+        &new[prefix..new.len() - suffix],
+    );
+    let mut result: Vec<DiffLine> = old[..prefix]
+        .iter()
+        .map(|l| DiffLine::Same((*l).to_string()))
+        .collect();
+    if (before.len() + 1) * (after.len() + 1) > MAX_TABLE {
+        result.extend(before.iter().map(|l| DiffLine::Removed((*l).to_string())));
+        result.extend(after.iter().map(|l| DiffLine::Added((*l).to_string())));
+    } else {
+        // table[i][j] is the length of the longest common subsequence of before[i..] and after[j..]
+        let width = after.len() + 1;
+        let mut table = vec![0_usize; (before.len() + 1) * width];
+        for i in (0..before.len()).rev() {
+            for j in (0..after.len()).rev() {
+                table[i * width + j] = if before[i] == after[j] {
+                    table[(i + 1) * width + j + 1] + 1
+                } else {
+                    table[(i + 1) * width + j].max(table[i * width + j + 1])
+                };
+            }
+        }
+        let (mut i, mut j) = (0, 0);
+        while i < before.len() || j < after.len() {
+            if i < before.len() && j < after.len() && before[i] == after[j] {
+                result.push(DiffLine::Same(before[i].to_string()));
+                i += 1;
+                j += 1;
+            } else if i < before.len()
+                && (j == after.len() || table[(i + 1) * width + j] >= table[i * width + j + 1])
+            {
+                result.push(DiffLine::Removed(before[i].to_string()));
+                i += 1;
+            } else {
+                result.push(DiffLine::Added(after[j].to_string()));
+                j += 1;
+            }
+        }
+    }
+    result.extend(
+        old[old.len() - suffix..]
+            .iter()
+            .map(|l| DiffLine::Same((*l).to_string())),
+    );
+    result
+}
+
+/// How the lines on a row of a side-by-side diff differ
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RowKind {
+    /// The line is in both texts
+    Same,
+    /// The line is only in the new text (there is a filler row opposite it)
+    Added,
+    /// The line is only in the old text (there is a filler row opposite it)
+    Removed,
+    /// The line of the old text was replaced by the line of the new text
+    Changed,
+}
+
+/// A row of a side-by-side diff, giving the line of each text shown on it
+/// (None where a filler row keeps the texts lined up)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DiffRow {
+    pub old: Option<usize>,
+    pub new: Option<usize>,
+    pub kind: RowKind,
+}
+
+/// Lay out the differences between two lists of lines side by side, so that lines in both
+/// are on the same row. Removed lines are paired up with the lines added in their place
+#[must_use]
+pub fn side_by_side<S: AsRef<str>>(old: &[S], new: &[S]) -> Vec<DiffRow> {
+    let lines = diff_lines(old, new);
+    let mut rows = vec![];
+    let (mut old_y, mut new_y) = (0, 0);
+    let mut i = 0;
+    while i < lines.len() {
+        if let DiffLine::Same(_) = lines[i] {
+            rows.push(DiffRow {
+                old: Some(old_y),
+                new: Some(new_y),
+                kind: RowKind::Same,
+            });
+            (old_y, new_y, i) = (old_y + 1, new_y + 1, i + 1);
+            continue;
+        }
+        let run = lines[i..]
+            .iter()
+            .take_while(|l| !matches!(l, DiffLine::Same(_)))
+            .count();
+        let removed = lines[i..i + run]
+            .iter()
+            .filter(|l| matches!(l, DiffLine::Removed(_)))
+            .count();
+        let added = run - removed;
+        for n in 0..removed.max(added) {
+            let (old, new) = (n < removed, n < added);
+            rows.push(DiffRow {
+                old: old.then_some(old_y + n),
+                new: new.then_some(new_y + n),
+                kind: match (old, new) {
+                    (true, true) => RowKind::Changed,
+                    (true, false) => RowKind::Removed,
+                    _ => RowKind::Added,
+                },
+            });
+        }
+        (old_y, new_y, i) = (old_y + removed, new_y + added, i + run);
+    }
+    rows
+}
+
+/// Find the hunks of a side-by-side diff (each run of rows that differ)
+#[must_use]
+pub fn hunks(rows: &[DiffRow]) -> Vec<Range<usize>> {
+    let mut result: Vec<Range<usize>> = vec![];
+    for (y, row) in rows.iter().enumerate() {
+        if row.kind == RowKind::Same {
+            continue;
+        }
+        match result.last_mut() {
+            Some(last) if last.end == y => last.end = y + 1,
+            _ => result.push(y..y + 1),
+        }
+    }
+    result
+}
+
+/// Write out the differences between two texts in the unified diff format,
+/// showing `context` unchanged lines around each change
+#[must_use]
+pub fn unified_diff(
+    old: &str,
+    new: &str,
+    old_name: &str,
+    new_name: &str,
+    context: usize,
+) -> String {
+    let old_lines: Vec<&str> = old.lines().collect();
+    let new_lines: Vec<&str> = new.lines().collect();
+    let lines = diff_lines(&old_lines, &new_lines);
+    let changed: Vec<usize> = (0..lines.len())
+        .filter(|i| !matches!(lines[*i], DiffLine::Same(_)))
+        .collect();
+    let mut result = String::new();
+    if changed.is_empty() {
+        return result;
+    }
+    let _ = writeln!(result, "--- {old_name}");
+    let _ = writeln!(result, "+++ {new_name}");
+    // Group changes that are close enough to share their context into hunks
+    let mut hunks: Vec<(usize, usize)> = vec![];
+    for i in changed {
+        let start = i.saturating_sub(context);
+        let end = (i + context + 1).min(lines.len());
+        match hunks.last_mut() {
+            Some(last) if start <= last.1 => last.1 = end,
+            _ => hunks.push((start, end)),
+        }
+    }
+    for (start, end) in hunks {
+        // Work out which lines of each text the hunk starts on
+        let before = &lines[..start];
+        let old_at = before
+            .iter()
+            .filter(|l| !matches!(l, DiffLine::Added(_)))
+            .count();
+        let new_at = before
+            .iter()
+            .filter(|l| !matches!(l, DiffLine::Removed(_)))
+            .count();
+        let hunk = &lines[start..end];
+        let old_len = hunk
+            .iter()
+            .filter(|l| !matches!(l, DiffLine::Added(_)))
+            .count();
+        let new_len = hunk
+            .iter()
+            .filter(|l| !matches!(l, DiffLine::Removed(_)))
+            .count();
+        let old_start = if old_len == 0 { old_at } else { old_at + 1 };
+        let new_start = if new_len == 0 { new_at } else { new_at + 1 };
+        let _ = writeln!(
+            result,
+            "@@ -{old_start},{old_len} +{new_start},{new_len} @@"
+        );
+        for line in hunk {
+            let _ = match line {
+                DiffLine::Same(l) => writeln!(result, " {l}"),
+                DiffLine::Added(l) => writeln!(result, "+{l}"),
+                DiffLine::Removed(l) => writeln!(result, "-{l}"),
+            };
+        }
+    }
+    result
+}
+
+/// How a line differs from the version of its document that was last saved
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LineChange {
+    /// The line wasn't there before
+    Added,
+    /// The line has taken the place of a different one
+    Modified,
+    /// Lines were removed just above this one (only happens on the first line)
+    RemovedAbove,
+    /// Lines were removed just below this one
+    RemovedBelow,
+}
+
+/// What is known about a line of a document compared with its saved version
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Known {
+    /// The line is unchanged, and is at this index in the saved version
+    Same(usize),
+    Added,
+    Modified,
+}
+
+/// A line of a rope, without its line ending
+fn rope_line(rope: &Rope, y: usize) -> String {
+    rope.line(y)
+        .to_string()
+        .trim_end_matches(['\n', '\r'])
+        .to_string()
+}
+
+/// Follows how the lines of a document differ from the version that was last saved.
+/// Edits only forget what is known about the lines they touch, and the differences
+/// are worked out again a region at a time, only for the lines that are asked about
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ChangeTracker {
+    /// The saved version (None if there isn't one to compare against)
+    saved: Option<Rope>,
+    /// What is known about each line of the document (None if it needs working out)
+    lines: Vec<Option<Known>>,
+}
+
+impl ChangeTracker {
+    /// Start following a document that is the same as its saved version
+    #[must_use]
+    pub fn new(saved: &Rope) -> Self {
+        let mut tracker = Self::default();
+        tracker.reset(saved);
+        tracker
+    }
+
+    /// Take `content` as the saved version, so that no lines are changed
+    pub fn reset(&mut self, content: &Rope) {
+        self.lines = (0..content.len_lines())
+            .map(|y| Some(Known::Same(y)))
+            .collect();
+        self.saved = Some(content.clone());
+    }
+
+    /// Forget everything worked out so far, for after the whole document has changed
+    pub fn invalidate(&mut self, len: usize) {
+        self.lines = vec![None; len];
+    }
+
+    /// Note that the line at `y` has been edited
+    pub fn edited(&mut self, y: usize) {
+        if let Some(line) = self.lines.get_mut(y) {
+            *line = None;
+        }
+    }
+
+    /// Note that a line has been inserted at `y`
+    pub fn inserted(&mut self, y: usize) {
+        self.lines.insert(y.min(self.lines.len()), None);
+    }
+
+    /// Note that the line at `y` has been removed
+    pub fn removed(&mut self, y: usize) {
+        if y < self.lines.len() {
+            self.lines.remove(y);
+        }
+        // The lines either side may now line up differently
+        self.edited(y.saturating_sub(1));
+        self.edited(y);
+    }
+
+    /// Work out how the lines in `range` of `current` have changed, where that isn't known
+    pub fn refresh(&mut self, range: Range<usize>, current: &Rope) {
+        let Some(saved) = self.saved.clone() else {
+            return;
+        };
+        // Start over if the document has been changed without the tracker knowing
+        if self.lines.len() != current.len_lines() {
+            self.invalidate(current.len_lines());
+        }
+        let same = |known: Option<Known>| match known {
+            Some(Known::Same(at)) => Some(at),
+            _ => None,
+        };
+        let end = range.end.min(self.lines.len());
+        let mut y = range.start;
+        while y < end {
+            if self.lines[y].is_some() {
+                y += 1;
+                continue;
+            }
+            // Widen out to the closest unchanged lines, which are known to line up
+            let mut start = y;
+            while start > 0 && same(self.lines[start - 1]).is_none() {
+                start -= 1;
+            }
+            let mut stop = y;
+            while stop < self.lines.len() && same(self.lines[stop]).is_none() {
+                stop += 1;
+            }
+            let saved_start = start
+                .checked_sub(1)
+                .and_then(|above| same(self.lines[above]))
+                .map_or(0, |at| at + 1);
+            let saved_stop = self
+                .lines
+                .get(stop)
+                .and_then(|below| same(*below))
+                .unwrap_or(saved.len_lines());
+            let old: Vec<String> = (saved_start..saved_stop)
+                .map(|i| rope_line(&saved, i))
+                .collect();
+            let new: Vec<String> = (start..stop).map(|i| rope_line(current, i)).collect();
+            self.align(start, saved_start, &diff_lines(&old, &new));
+            y = stop;
+        }
+    }
+
+    /// Record what the difference of a region starting at line `y` (and `saved_y`) shows
+    fn align(&mut self, mut y: usize, mut saved_y: usize, diff: &[DiffLine]) {
+        let mut i = 0;
+        while i < diff.len() {
+            if let DiffLine::Same(_) = diff[i] {
+                self.lines[y] = Some(Known::Same(saved_y));
+                (y, saved_y, i) = (y + 1, saved_y + 1, i + 1);
+                continue;
+            }
+            // Added lines in a run of changes take the place of the removed lines first
+            let run = diff[i..]
+                .iter()
+                .take_while(|l| !matches!(l, DiffLine::Same(_)))
+                .count();
+            let removed = diff[i..i + run]
+                .iter()
+                .filter(|l| matches!(l, DiffLine::Removed(_)))
+                .count();
+            let added = run - removed;
+            for n in 0..added {
+                self.lines[y + n] = Some(if n < removed {
+                    Known::Modified
+                } else {
+                    Known::Added
+                });
+            }
+            (y, saved_y, i) = (y + added, saved_y + removed, i + run);
+        }
+    }
+
+    /// How a line has changed (None if it hasn't, or if that hasn't been worked out yet)
+    #[must_use]
+    pub fn get(&self, y: usize) -> Option<LineChange> {
+        let saved = self.saved.as_ref()?;
+        match (*self.lines.get(y)?)? {
+            Known::Added => Some(LineChange::Added),
+            Known::Modified => Some(LineChange::Modified),
+            Known::Same(at) => {
+                // Saved lines skipped over between two unchanged lines have been removed
+                let next = match self.lines.get(y + 1) {
+                    Some(Some(Known::Same(next))) => Some(*next),
+                    Some(_) => None,
+                    None => Some(saved.len_lines()),
+                };
+                if next.is_some_and(|next| next > at + 1) {
+                    Some(LineChange::RemovedBelow)
+                } else if y == 0 && at > 0 {
+                    Some(LineChange::RemovedAbove)
+                } else {
+                    None
+                }
+            }
+        }
+    }
+}
@@ -1,13 +1,16 @@
 use crate::event::Status;
 use crate::utils::{tab_boundaries_backward, tab_boundaries_forward, width};
 use crate::{Document, Loc};
+use serde::{Deserialize, Serialize};
 use std::ops::Range;
 
 /// Defines a cursor's position and any selection it may be covering
-#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
 pub struct Cursor {
     pub loc: Loc,
     pub selection_end: Loc,
+    /// The display column of the moving edge while a block selection is active
+    pub block: Option<usize>,
 }
 
 impl Document {
@@ -146,6 +149,23 @@ impl Document {
         self.bring_cursor_in_viewport();
     }
 
+    /// Move to the first character on the line that isn't whitespace,
+    /// or to the start of the line if the cursor is already there
+    pub fn move_smart_home(&mut self) {
+        self.select_smart_home();
+        self.cancel_selection();
+    }
+
+    /// Select to the first character on the line that isn't whitespace,
+    /// or to the start of the line if the cursor is already there
+    pub fn select_smart_home(&mut self) {
+        let line = self.line(self.loc().y).unwrap_or_default();
+        let first = line.chars().take_while(|c| c.is_whitespace()).count();
+        let target = if self.char_ptr == first { 0 } else { first };
+        self.select_to_x(target);
+        self.old_cursor = self.loc().x;
+    }
+
     /// Move to the end of the line
     pub fn move_end(&mut self) {
         self.select_end();
@@ -233,9 +253,11 @@ impl Document {
     }
 
     /// Function to go to a specific position
+    /// (which becomes the column moving up and down tries to stay in)
     pub fn select_to(&mut self, loc: &Loc) {
         self.select_to_y(loc.y);
         self.select_to_x(loc.x);
+        self.old_cursor = self.loc().x;
     }
 
     /// Function to go to a specific x position
@@ -300,6 +322,16 @@ impl Document {
         self.load_to(self.offset.y + self.size.h);
     }
 
+    /// Move the view right
+    pub fn scroll_right(&mut self) {
+        self.offset.x += 1;
+    }
+
+    /// Move the view left
+    pub fn scroll_left(&mut self) {
+        self.offset.x = self.offset.x.saturating_sub(1);
+    }
+
     /// Get the current position within the document, including offset
     #[must_use]
     pub const fn loc(&self) -> Loc {
@@ -368,6 +400,9 @@ impl Document {
     /// Returns true if the provided location is within the current active selection
     #[must_use]
     pub fn is_loc_selected(&self, loc: Loc) -> bool {
+        if let Some((rows, cols)) = self.block_bounds() {
+            return rows.contains(&loc.y) && cols.contains(&self.display_idx(&loc));
+        }
         self.is_this_loc_selected(loc, self.selection_loc_bound())
     }
 
@@ -406,6 +441,16 @@ impl Document {
         self.file.slice(self.selection_range()).to_string()
     }
 
+    /// How many characters and lines the current selection covers (None if nothing is selected)
+    #[must_use]
+    pub fn selection_size(&self) -> Option<(usize, usize)> {
+        if self.is_selection_empty() {
+            return None;
+        }
+        let (left, right) = self.selection_loc_bound_disp();
+        Some((self.selection_range().len(), right.y - left.y + 1))
+    }
+
     /// Delete the currently selected text
     pub fn remove_selection(&mut self) {
         self.file.remove(self.selection_range());
@@ -421,6 +466,115 @@ impl Document {
     /// Cancels the current selection
     pub fn cancel_selection(&mut self) {
         self.cursor.selection_end = self.cursor.loc;
+        self.cursor.block = None;
+    }
+
+    /// Start a block (rectangular) selection, anchored at the end of the current selection
+    pub fn start_block(&mut self) {
+        if self.cursor.block.is_none() {
+            self.cursor.block = Some(self.cursor.loc.x);
+        }
+    }
+
+    /// Extend a block selection to a position (where x is a display column)
+    pub fn select_block_to(&mut self, loc: &Loc) {
+        self.start_block();
+        self.select_to_y(loc.y);
+        self.cursor.block = Some(loc.x);
+    }
+
+    /// Will return the rows and display columns covered by the block selection (if active)
+    #[must_use]
+    pub fn block_bounds(&self) -> Option<(Range<usize>, Range<usize>)> {
+        let edge = self.cursor.block?;
+        let anchor = self.cursor.selection_end;
+        let rows = anchor.y.min(self.cursor.loc.y)..anchor.y.max(self.cursor.loc.y) + 1;
+        let cols = anchor.x.min(edge)..anchor.x.max(edge);
+        Some((rows, cols))
+    }
+
+    /// Will return the size of the block selection as (rows, columns)
+    #[must_use]
+    pub fn block_size(&self) -> Option<(usize, usize)> {
+        let (rows, cols) = self.block_bounds()?;
+        Some((rows.len(), cols.len()))
+    }
+
+    /// Snap the moving edge of a block selection to the start of the line
+    pub fn block_home(&mut self) {
+        if self.cursor.block.is_some() {
+            self.select_to_x(0);
+            self.cursor.block = Some(0);
+        }
+    }
+
+    /// Snap the moving edge of a block selection to the end of the longest line in it
+    pub fn block_end(&mut self) {
+        let Some((rows, _)) = self.block_bounds() else {
+            return;
+        };
+        let longest = rows
+            .filter_map(|y| self.line(y))
+            .map(|line| width(&line, self.tab_width))
+            .max()
+            .unwrap_or(0);
+        let length = self.line(self.loc().y).unwrap_or_default().chars().count();
+        self.select_to_x(length);
+        self.cursor.block = Some(longest);
+    }
+
+    /// Leave a block selection, returning to a normal cursor at the anchor
+    pub fn exit_block(&mut self) {
+        // The anchor can be past the end of its line after editing a block
+        self.cursor.loc = self.cursor.selection_end;
+        self.fix_dangling_cursor();
+        self.fix_split();
+        self.update_char_ptr();
+        self.old_cursor = self.cursor.loc.x;
+        self.cancel_selection();
+        self.bring_cursor_in_viewport();
+    }
+
+    /// Move the moving edge of a block selection up a row (starting one if needed)
+    pub fn block_up(&mut self) {
+        self.start_block();
+        let edge = self.cursor.block.unwrap_or_default();
+        self.select_block_to(&Loc {
+            x: edge,
+            y: self.loc().y.saturating_sub(1),
+        });
+    }
+
+    /// Move the moving edge of a block selection down a row (starting one if needed)
+    pub fn block_down(&mut self) {
+        self.start_block();
+        let edge = self.cursor.block.unwrap_or_default();
+        self.select_block_to(&Loc {
+            x: edge,
+            y: self.loc().y + 1,
+        });
+    }
+
+    /// Move the moving edge of a block selection a column left (starting one if needed)
+    pub fn block_left(&mut self) {
+        self.start_block();
+        let edge = self.cursor.block.unwrap_or_default().saturating_sub(1);
+        self.follow_block_edge(edge);
+    }
+
+    /// Move the moving edge of a block selection a column right (starting one if needed),
+    /// which can go past the end of the line
+    pub fn block_right(&mut self) {
+        self.start_block();
+        let edge = self.cursor.block.unwrap_or_default() + 1;
+        self.follow_block_edge(edge);
+    }
+
+    /// Set the moving edge of a block selection, with the cursor as close to it as the line allows
+    fn follow_block_edge(&mut self, edge: usize) {
+        let x = self.block_chars(self.loc().y, &(edge..edge)).start;
+        self.select_to_x(x);
+        self.cursor.block = Some(edge);
     }
 
     /// Create a new alternative cursor
@@ -435,6 +589,7 @@ impl Document {
     /// Clear all secondary cursors
     pub fn clear_cursors(&mut self) {
         self.secondary_cursors.clear();
+        self.occurrence = None;
     }
 
     /// Determine if there is a secondary cursor at a certain position
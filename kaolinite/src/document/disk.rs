@@ -1,11 +1,19 @@
-use crate::document::Cursor;
+use crate::diff::ChangeTracker;
+use crate::document::{Cursor, DEFAULT_WORD_SEPARATORS};
 use crate::event::{Error, EventMgmt, Result};
 use crate::map::{form_map, CharMap};
 use crate::utils::get_absolute_path;
 use crate::{Document, Loc, Size};
-use ropey::Rope;
+use encoding_rs::{EncoderResult, Encoding, UTF_16BE, UTF_16LE, UTF_8, WINDOWS_1252};
+use ropey::{Rope, RopeBuilder};
+use serde::{Deserialize, Serialize};
 use std::fs::File;
-use std::io::{BufRead, BufReader, BufWriter, Read};
+use std::io::{BufRead, BufWriter, Read, Seek, SeekFrom, Write};
+
+/// The number of bytes shown on each row of a hex preview
+pub const HEX_WIDTH: usize = 16;
+/// How many bytes are read at a time when a file is read in chunks
+const READ_CHUNK: usize = 1 << 20;
 
 /// A document info struct to store information about the file it represents
 #[derive(Clone, PartialEq, Eq, Debug)]
@@ -16,6 +24,83 @@ pub struct DocumentInfo {
     pub eol: bool,
     /// Contains the number of lines buffered into the document
     pub loaded_to: usize,
+    /// The dominant line ending used in this document
+    pub line_ending: LineEnding,
+    /// The encoding of the file on the disk
+    pub encoding: &'static Encoding,
+    /// Whether or not the file on the disk starts with a byte order mark
+    pub bom: bool,
+    /// Whether every byte of the file on the disk could be decoded
+    pub decoding: Decoding,
+}
+
+/// Represents how well the bytes of a file could be decoded into text
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum Decoding {
+    /// Every byte was decoded
+    #[default]
+    Complete,
+    /// Some bytes couldn't be decoded and were replaced, so saving would lose them
+    Lossy,
+}
+
+impl Decoding {
+    /// Describe a decoding, given whether any bytes couldn't be decoded
+    #[must_use]
+    pub fn new(undecodable: bool) -> Self {
+        if undecodable {
+            Self::Lossy
+        } else {
+            Self::Complete
+        }
+    }
+}
+
+/// Represents the line endings that a document can use
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default, Serialize, Deserialize)]
+pub enum LineEnding {
+    #[default]
+    Lf,
+    Crlf,
+}
+
+impl LineEnding {
+    /// Get the characters that make up this line ending
+    #[must_use]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Lf => "\n",
+            Self::Crlf => "\r\n",
+        }
+    }
+
+    /// Determine the line ending that is used the most in a rope
+    #[must_use]
+    pub fn detect(rope: &Rope) -> Self {
+        let (mut lf, mut crlf) = (0, 0);
+        for line in rope.lines() {
+            let len = line.len_chars();
+            if len >= 2 && line.char(len - 2) == '\r' && line.char(len - 1) == '\n' {
+                crlf += 1;
+            } else if len >= 1 && line.char(len - 1) == '\n' {
+                lf += 1;
+            }
+        }
+        if crlf > lf {
+            Self::Crlf
+        } else {
+            Self::Lf
+        }
+    }
+}
+
+impl std::fmt::Display for LineEnding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Lf => write!(f, "LF"),
+            Self::Crlf => write!(f, "CRLF"),
+        }
+    }
 }
 
 impl Document {
@@ -35,14 +120,25 @@ impl Document {
             char_ptr: 0,
             event_mgmt: EventMgmt::default(),
             tab_width: 4,
+            horizontal_margin: 0,
+            word_separators: DEFAULT_WORD_SEPARATORS.to_string(),
             old_cursor: 0,
             in_redo: false,
             info: DocumentInfo {
                 loaded_to: 1,
                 eol: false,
                 read_only: false,
+                line_ending: LineEnding::Lf,
+                encoding: UTF_8,
+                bom: false,
+                decoding: Decoding::Complete,
             },
             secondary_cursors: vec![],
+            occurrence: None,
+            marks: vec![],
+            generation: 0,
+            binary: None,
+            changes: ChangeTracker::default(),
         }
     }
 
@@ -53,36 +149,80 @@ impl Document {
     /// disk errors.
     #[cfg(not(tarpaulin_include))]
     pub fn open<S: Into<String>>(size: Size, file_name: S) -> Result<Self> {
-        // Try to find the absolute path and load it into the reader
+        // Try to find the absolute path and load it in
         let file_name = file_name.into();
         let full_path = std::fs::canonicalize(&file_name)?;
-        let file = load_rope_from_reader(BufReader::new(File::open(&full_path)?));
-        // Find the string representation of the absolute path
-        let file_name = get_absolute_path(&file_name);
-        Ok(Self {
+        let bytes = std::fs::read(&full_path)?;
+        // Work out the encoding and decode the file
+        let (encoding, bom) = detect_encoding(&bytes);
+        if is_binary(&bytes) {
+            // Rows of the hex preview are generated from the bytes as they are loaded
+            let rows = bytes.len().div_ceil(HEX_WIDTH);
+            let file = Rope::from_str(&"\n".repeat(rows));
+            let mut doc = Self::from_rope(size, &file_name, file, encoding, bom != 0, false);
+            doc.info.read_only = true;
+            doc.binary = Some(bytes);
+            return Ok(doc);
+        }
+        let (text, undecodable) = decode(&bytes[bom..], encoding);
+        let file = Rope::from_str(&text);
+        Ok(Self::from_rope(
+            size,
+            &file_name,
+            file,
+            encoding,
+            bom != 0,
+            undecodable,
+        ))
+    }
+
+    /// Create a document from the contents of a file that have already been read in
+    /// (as when a file has been read in the background)
+    #[must_use]
+    pub fn from_rope(
+        size: Size,
+        file_name: &str,
+        file: Rope,
+        encoding: &'static Encoding,
+        bom: bool,
+        undecodable: bool,
+    ) -> Self {
+        Self {
             info: DocumentInfo {
                 loaded_to: 0,
                 eol: !file
                     .line(file.len_lines().saturating_sub(1))
                     .to_string()
                     .is_empty(),
-                read_only: false,
+                // Prevent any undecodable bytes from being lost on save
+                read_only: undecodable,
+                line_ending: LineEnding::detect(&file),
+                encoding,
+                bom,
+                decoding: Decoding::new(undecodable),
             },
+            changes: ChangeTracker::new(&file),
             file,
             lines: vec![],
             dbl_map: CharMap::default(),
             tab_map: CharMap::default(),
-            file_name,
+            file_name: get_absolute_path(file_name),
             cursor: Cursor::default(),
             offset: Loc::default(),
             size,
             char_ptr: 0,
             event_mgmt: EventMgmt::default(),
             tab_width: 4,
+            horizontal_margin: 0,
+            word_separators: DEFAULT_WORD_SEPARATORS.to_string(),
             old_cursor: 0,
             in_redo: false,
             secondary_cursors: vec![],
-        })
+            occurrence: None,
+            marks: vec![],
+            generation: 0,
+            binary: None,
+        }
     }
 
     /// Save back to the file the document was opened from.
@@ -90,12 +230,14 @@ impl Document {
     /// Returns an error if the file fails to write, due to permissions
     /// or character set issues.
     pub fn save(&mut self) -> Result<()> {
-        if self.info.read_only {
+        if self.binary.is_some() {
+            Err(Error::BinaryPreview)
+        } else if self.info.read_only {
             Err(Error::ReadOnlyFile)
         } else if let Some(file_name) = &self.file_name {
-            self.file
-                .write_to(BufWriter::new(File::create(file_name)?))?;
+            self.write_to(file_name)?;
             self.event_mgmt.disk_write(&self.take_snapshot());
+            self.changes.reset(&self.file);
             Ok(())
         } else {
             Err(Error::NoFileName)
@@ -107,13 +249,157 @@ impl Document {
     /// Returns an error if the file fails to write, due to permissions
     /// or character set issues.
     pub fn save_as(&self, file_name: &str) -> Result<()> {
-        if self.info.read_only {
+        if self.binary.is_some() {
+            Err(Error::BinaryPreview)
+        } else if self.info.read_only {
             Err(Error::ReadOnlyFile)
         } else {
+            self.write_to(file_name)
+        }
+    }
+
+    /// Write the contents of this document to a file, in the encoding of the document
+    fn write_to(&self, file_name: &str) -> Result<()> {
+        if self.info.encoding == UTF_8 && !self.info.bom {
+            // No need to transcode anything
             self.file
                 .write_to(BufWriter::new(File::create(file_name)?))?;
-            Ok(())
+        } else {
+            // Encode before touching the file so it isn't lost if encoding fails
+            let bytes =
+                encode(&self.file.to_string(), self.info.encoding).ok_or(Error::Unrepresentable)?;
+            let mut writer = BufWriter::new(File::create(file_name)?);
+            if self.info.bom {
+                writer.write_all(bom_for(self.info.encoding))?;
+            }
+            writer.write_all(&bytes)?;
+            writer.flush()?;
         }
+        Ok(())
+    }
+
+    /// Reload this document from the disk, decoding it using a different encoding.
+    /// Any unsaved changes and undo history will be discarded.
+    /// # Errors
+    /// Returns an error if the encoding isn't recognised or the file couldn't be read.
+    pub fn reload_with_encoding(&mut self, label: &str) -> Result<()> {
+        let encoding =
+            Encoding::for_label(label.trim().as_bytes()).ok_or(Error::UnknownEncoding)?;
+        let file_name = self.file_name.clone().ok_or(Error::NoFileName)?;
+        let bytes = std::fs::read(file_name)?;
+        // Only skip over a byte order mark if it belongs to the requested encoding
+        let bom = match Encoding::for_bom(&bytes) {
+            Some((bom_encoding, len)) if bom_encoding == encoding => len,
+            _ => 0,
+        };
+        let (text, undecodable) = decode(&bytes[bom..], encoding);
+        // Update the document
+        self.binary = None;
+        self.file = Rope::from_str(&text);
+        let was_lossy = self.info.decoding == Decoding::Lossy;
+        self.info.read_only = undecodable || (self.info.read_only && !was_lossy);
+        self.info.encoding = encoding;
+        self.info.bom = bom != 0;
+        self.info.decoding = Decoding::new(undecodable);
+        self.info.line_ending = LineEnding::detect(&self.file);
+        self.info.eol = !self
+            .file
+            .line(self.file.len_lines().saturating_sub(1))
+            .to_string()
+            .is_empty();
+        self.event_mgmt = EventMgmt {
+            limit: self.event_mgmt.limit,
+            ..EventMgmt::default()
+        };
+        self.reload_lines();
+        self.reanchor_marks(true);
+        self.changes.reset(&self.file);
+        self.move_to(&Loc::at(0, 0));
+        Ok(())
+    }
+
+    /// Turn a hex preview of a binary file into a regular text document
+    pub fn force_text(&mut self) {
+        let Some(bytes) = self.binary.take() else {
+            return;
+        };
+        let (encoding, bom) = detect_encoding(&bytes);
+        let (text, undecodable) = decode(&bytes[bom..], encoding);
+        self.file = Rope::from_str(&text);
+        // Prevent any undecodable bytes from being lost on save
+        self.info.read_only = undecodable;
+        self.info.encoding = encoding;
+        self.info.bom = bom != 0;
+        self.info.decoding = Decoding::new(undecodable);
+        self.info.line_ending = LineEnding::detect(&self.file);
+        self.info.eol = !self
+            .file
+            .line(self.file.len_lines().saturating_sub(1))
+            .to_string()
+            .is_empty();
+        self.event_mgmt = EventMgmt {
+            limit: self.event_mgmt.limit,
+            ..EventMgmt::default()
+        };
+        self.reload_lines();
+        self.move_to(&Loc::at(0, 0));
+    }
+
+    /// Switch this document over to being saved as UTF-8 (without a byte order mark)
+    pub fn convert_to_utf8(&mut self) {
+        self.info.encoding = UTF_8;
+        self.info.bom = false;
+    }
+
+    /// Replace every character that can't be represented in this document's encoding,
+    /// returns how many were replaced.
+    /// Call `commit` before and after this to make it a single undoable change.
+    pub fn replace_unrepresentable(&mut self, substitute: char) -> usize {
+        let offenders = unrepresentable(&self.file, self.info.encoding, usize::MAX);
+        // Work backwards so that earlier locations stay correct
+        for (loc, _) in offenders.iter().rev() {
+            let idx = self.loc_to_file_pos(loc);
+            self.file.remove(idx..=idx);
+            self.file.insert_char(idx, substitute);
+        }
+        if !offenders.is_empty() {
+            self.reload_lines();
+        }
+        offenders.len()
+    }
+
+    /// Convert every line ending in this document to a certain line ending.
+    /// Call `commit` before and after this to make it a single undoable change.
+    pub fn convert_line_endings(&mut self, ending: LineEnding) {
+        let mut result = String::new();
+        for line in self.file.lines() {
+            let line = line.to_string();
+            let stripped = line.trim_end_matches(['\n', '\r']);
+            result.push_str(stripped);
+            if stripped.len() != line.len() {
+                result.push_str(ending.as_str());
+            }
+        }
+        self.file = Rope::from_str(&result);
+        self.info.line_ending = ending;
+        self.reload_lines();
+    }
+
+    /// Replace the whole contents of this document (e.g. with an older version of it),
+    /// keeping the cursor and marks as close to where they were as possible.
+    /// Call `commit` before and after this to make it a single undoable change.
+    pub fn replace_contents(&mut self, text: &str) {
+        let loc = self.char_loc();
+        self.file = Rope::from_str(text);
+        self.info.eol = !self
+            .file
+            .line(self.file.len_lines().saturating_sub(1))
+            .to_string()
+            .is_empty();
+        self.reload_lines();
+        self.reanchor_marks(true);
+        self.changes.reset(&self.file);
+        self.move_to(&loc);
     }
 
     /// Load lines in this document up to a specified index.
@@ -129,7 +415,11 @@ impl Document {
         if to > self.info.loaded_to {
             // For each line, run through each character and make note of any double width characters
             for i in self.info.loaded_to..to {
-                let line: String = self.file.line(i).chars().collect();
+                let line: String = if let Some(bytes) = &self.binary {
+                    hex_row(bytes, i)
+                } else {
+                    self.file.line(i).chars().collect()
+                };
                 // Add to char maps
                 let (dbl_map, tab_map) = form_map(&line, self.tab_width);
                 self.dbl_map.insert(i, dbl_map);
@@ -144,6 +434,219 @@ impl Document {
     }
 }
 
+/// Work out the encoding of some bytes, returns the encoding and the length of any byte order mark
+#[must_use]
+pub fn detect_encoding(bytes: &[u8]) -> (&'static Encoding, usize) {
+    // Byte order marks give the encoding away
+    if let Some((encoding, bom)) = Encoding::for_bom(bytes) {
+        return (encoding, bom);
+    }
+    // UTF-16 without a byte order mark has lots of NUL bytes on one side of each pair
+    if let Some(encoding) = guess_utf16(bytes) {
+        return (encoding, 0);
+    }
+    // Valid UTF-8 is by far the most likely
+    if std::str::from_utf8(bytes).is_ok() {
+        return (UTF_8, 0);
+    }
+    // Otherwise fall back to the most common single byte encoding (a superset of Latin-1)
+    (WINDOWS_1252, 0)
+}
+
+/// Guess if some bytes are UTF-16 without a byte order mark, based on where NUL bytes appear
+fn guess_utf16(bytes: &[u8]) -> Option<&'static Encoding> {
+    let sample = &bytes[..bytes.len().min(4096)];
+    let pairs = sample.len() / 2;
+    let even = sample.iter().step_by(2).filter(|b| **b == 0).count();
+    let odd = sample
+        .iter()
+        .skip(1)
+        .step_by(2)
+        .filter(|b| **b == 0)
+        .count();
+    if pairs > 0 && odd > pairs / 2 && even <= pairs / 8 {
+        Some(UTF_16LE)
+    } else if pairs > 0 && even > pairs / 2 && odd <= pairs / 8 {
+        Some(UTF_16BE)
+    } else {
+        None
+    }
+}
+
+/// Determine if some bytes are likely to be binary rather than text (NUL bytes near the start)
+#[must_use]
+pub fn is_binary(bytes: &[u8]) -> bool {
+    let sample = &bytes[..bytes.len().min(8192)];
+    Encoding::for_bom(sample).is_none() && guess_utf16(sample).is_none() && sample.contains(&0)
+}
+
+/// Read a file into a rope a chunk at a time, calling `progress` with the number of bytes read
+/// so far after each chunk. Alongside the rope, this returns the encoding of the file,
+/// the length of any byte order mark and whether any bytes couldn't be decoded
+/// (the encoding is worked out in the same way as `detect_encoding`).
+/// # Errors
+/// Returns an error if the file can't be read
+pub fn read_rope(
+    path: &str,
+    mut progress: impl FnMut(u64),
+) -> Result<(Rope, &'static Encoding, usize, bool)> {
+    let mut file = File::open(path)?;
+    // The start of the file is enough to tell what the encoding is likely to be
+    let mut sample = vec![];
+    (&mut file).take(4096).read_to_end(&mut sample)?;
+    let (mut encoding, bom) = Encoding::for_bom(&sample)
+        .or_else(|| guess_utf16(&sample).map(|e| (e, 0)))
+        .unwrap_or((UTF_8, 0));
+    let mut buffer = vec![0; READ_CHUNK];
+    'attempt: loop {
+        file.seek(SeekFrom::Start(bom as u64))?;
+        let mut decoder = encoding.new_decoder_without_bom_handling();
+        let mut builder = RopeBuilder::new();
+        let mut text = String::new();
+        let mut read = bom as u64;
+        let mut undecodable = false;
+        loop {
+            let len = file.read(&mut buffer)?;
+            let last = len == 0;
+            text.clear();
+            text.reserve(decoder.max_utf8_buffer_length(len).unwrap_or(len * 3));
+            let (_, _, malformed) = decoder.decode_to_string(&buffer[..len], &mut text, last);
+            if malformed && encoding == UTF_8 && bom == 0 {
+                // This isn't UTF-8 after all, so start again with the fallback encoding
+                encoding = WINDOWS_1252;
+                continue 'attempt;
+            }
+            undecodable |= malformed || has_undefined(&buffer[..len], encoding);
+            builder.append(&text);
+            read += len as u64;
+            progress(read);
+            if last {
+                return Ok((builder.finish(), encoding, bom, undecodable));
+            }
+        }
+    }
+}
+
+/// Render a row of a hex dump: the offset, the bytes in hex and then as ASCII
+#[must_use]
+pub fn hex_row(bytes: &[u8], row: usize) -> String {
+    let start = (row * HEX_WIDTH).min(bytes.len());
+    let chunk = &bytes[start..(start + HEX_WIDTH).min(bytes.len())];
+    let hex: String = (0..HEX_WIDTH)
+        .map(|i| {
+            let gap = if i % 8 == 0 { " " } else { "" };
+            chunk
+                .get(i)
+                .map_or(format!("{gap}   "), |byte| format!("{gap}{byte:02x} "))
+        })
+        .collect();
+    let ascii: String = chunk
+        .iter()
+        .map(|byte| {
+            if byte.is_ascii_graphic() || *byte == b' ' {
+                char::from(*byte)
+            } else {
+                '.'
+            }
+        })
+        .collect();
+    format!("{start:08x} {hex}|{ascii}|")
+}
+
+/// Decode bytes into a string, returns true alongside if any bytes couldn't be decoded
+#[must_use]
+pub fn decode(bytes: &[u8], encoding: &'static Encoding) -> (String, bool) {
+    let (text, malformed) = encoding.decode_without_bom_handling(bytes);
+    (
+        text.into_owned(),
+        malformed || has_undefined(bytes, encoding),
+    )
+}
+
+/// Check for bytes that an encoding doesn't assign a character to, but which are decoded
+/// without complaint anyway (Windows-1252 turns them into control characters)
+fn has_undefined(bytes: &[u8], encoding: &'static Encoding) -> bool {
+    encoding == WINDOWS_1252
+        && bytes
+            .iter()
+            .any(|b| matches!(b, 0x81 | 0x8D | 0x8F | 0x90 | 0x9D))
+}
+
+/// Encode a string into bytes, returns None if any characters can't be represented
+#[must_use]
+pub fn encode(text: &str, encoding: &'static Encoding) -> Option<Vec<u8>> {
+    // UTF-16 is only supported for decoding, so handle it separately
+    if encoding == UTF_16LE {
+        return Some(text.encode_utf16().flat_map(u16::to_le_bytes).collect());
+    }
+    if encoding == UTF_16BE {
+        return Some(text.encode_utf16().flat_map(u16::to_be_bytes).collect());
+    }
+    let (bytes, _, unrepresentable) = encoding.encode(text);
+    if unrepresentable {
+        None
+    } else {
+        Some(bytes.into_owned())
+    }
+}
+
+/// Find characters in some text that can't be represented in an encoding, along with
+/// their locations (x being a character index). Stops once `limit` characters have been found
+#[must_use]
+pub fn unrepresentable(text: &Rope, encoding: &'static Encoding, limit: usize) -> Vec<(Loc, char)> {
+    let mut result = vec![];
+    // Unicode encodings can represent everything
+    if encoding == UTF_8 || encoding == UTF_16LE || encoding == UTF_16BE {
+        return result;
+    }
+    let mut encoder = encoding.new_encoder();
+    let mut buffer = [0; 4096];
+    let mut loc = Loc::at(0, 0);
+    let advance = |loc: &mut Loc, st: &str| {
+        for c in st.chars() {
+            if c == '\n' {
+                loc.y += 1;
+                loc.x = 0;
+            } else {
+                loc.x += 1;
+            }
+        }
+    };
+    for chunk in text.chunks() {
+        let mut rest = chunk;
+        while !rest.is_empty() {
+            let (status, read, _) =
+                encoder.encode_from_utf8_without_replacement(rest, &mut buffer, false);
+            if let EncoderResult::Unmappable(c) = status {
+                advance(&mut loc, &rest[..read - c.len_utf8()]);
+                result.push((loc, c));
+                if result.len() >= limit {
+                    return result;
+                }
+                loc.x += 1;
+            } else {
+                advance(&mut loc, &rest[..read]);
+            }
+            rest = &rest[read..];
+        }
+    }
+    result
+}
+
+/// Get the byte order mark for an encoding
+#[must_use]
+pub fn bom_for(encoding: &'static Encoding) -> &'static [u8] {
+    if encoding == UTF_16LE {
+        &[0xFF, 0xFE]
+    } else if encoding == UTF_16BE {
+        &[0xFE, 0xFF]
+    } else if encoding == UTF_8 {
+        &[0xEF, 0xBB, 0xBF]
+    } else {
+        &[]
+    }
+}
+
 pub fn load_rope_from_reader<T: Read + BufRead>(mut reader: T) -> Rope {
     let mut buffer = [0u8; 2048]; // Buffer to read chunks
     let mut valid_string = String::new();
@@ -1,3 +1,4 @@
+use crate::document::LineMark;
 use crate::event::{Error, Event, Result};
 use crate::map::form_map;
 use crate::utils::{get_range, tab_boundaries_backward};
@@ -15,9 +16,12 @@ impl Document {
         // Update rope
         let idx = self.loc_to_file_pos(loc);
         self.file.insert(idx, st);
+        self.generation += 1;
         // Update cache
         let line: String = self.file.line(loc.y).chars().collect();
         self.lines[loc.y] = line.trim_end_matches(['\n', '\r']).to_string();
+        self.refresh_marks(loc.y);
+        self.changes.edited(loc.y);
         // Update unicode map
         let dbl_start = self.dbl_map.shift_insertion(loc, st, self.tab_width);
         let tab_start = self.tab_map.shift_insertion(loc, st, self.tab_width);
@@ -93,9 +97,12 @@ impl Document {
         );
         // Update rope
         self.file.remove(start..end);
+        self.generation += 1;
         // Update cache
         let line: String = self.file.line(y).chars().collect();
         self.lines[y] = line.trim_end_matches(['\n', '\r']).to_string();
+        self.refresh_marks(y);
+        self.changes.edited(y);
         self.old_cursor = self.loc().x;
         Ok(())
     }
@@ -116,9 +123,13 @@ impl Document {
         self.tab_map.insert(loc, tab_map);
         // Update cache
         self.lines.insert(loc, contents.to_string());
+        self.shift_marks_down(loc);
+        self.changes.inserted(loc);
         // Update rope
         let char_idx = self.file.line_to_char(loc);
-        self.file.insert(char_idx, &(contents + "\n"));
+        self.file
+            .insert(char_idx, &(contents + self.info.line_ending.as_str()));
+        self.generation += 1;
         self.info.loaded_to += 1;
         // Goto line
         self.move_to_y(loc);
@@ -143,7 +154,10 @@ impl Document {
         let idx_start = self.file.line_to_char(loc);
         let idx_end = self.file.line_to_char(loc + 1);
         self.file.remove(idx_start..idx_end);
+        self.generation += 1;
         self.info.loaded_to = self.info.loaded_to.saturating_sub(1);
+        self.shift_marks_up(loc);
+        self.changes.removed(loc);
         // Goto line
         self.move_to_y(loc);
         self.old_cursor = self.loc().x;
@@ -161,6 +175,13 @@ impl Document {
         let rhs: String = line.chars().skip(loc.x).collect();
         self.delete(loc.x.., loc.y)?;
         self.insert_line(loc.y + 1, rhs)?;
+        // Marks follow the text down when a line is split at its very start
+        if loc.x == 0 {
+            for mark in self.marks.iter_mut().filter(|m| m.y == loc.y) {
+                mark.y += 1;
+            }
+            self.refresh_marks(loc.y + 1);
+        }
         self.move_to(&Loc::at(0, loc.y + 1));
         self.old_cursor = self.loc().x;
         Ok(())
@@ -175,6 +196,16 @@ impl Document {
         // Gather context
         let length = self.line(y).ok_or(Error::OutOfRange)?.chars().count();
         let below = self.line(y + 1).ok_or(Error::OutOfRange)?;
+        // Marks on the line below join the line it is spliced onto (unless it has one already)
+        for bookmark in [false, true] {
+            let same_kind = |m: &LineMark| m.bookmark == bookmark;
+            if self.marks.iter().any(|m| m.y == y && same_kind(m)) {
+                self.marks.retain(|m| m.y != y + 1 || !same_kind(m));
+            } else if let Some(mark) = self.marks.iter_mut().find(|m| m.y == y + 1 && same_kind(m))
+            {
+                mark.y = y;
+            }
+        }
         self.delete_line(y + 1)?;
         self.insert(&Loc::at(length, y), &below)?;
         self.move_to(&Loc::at(length, y));
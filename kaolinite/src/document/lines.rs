@@ -1,6 +1,10 @@
-use crate::event::{Error, Result};
-use crate::utils::trim;
+use crate::diff::LineChange;
+use crate::document::LineMark;
+use crate::event::{Error, Event, Result};
+use crate::utils::{expand_tabs, trim, width, width_char};
 use crate::{Document, Loc};
+use ropey::Rope;
+use std::ops::Range;
 
 impl Document {
     /// Get the line at a specified index
@@ -16,6 +20,39 @@ impl Document {
         Some(trim(&line?, start, length, self.tab_width))
     }
 
+    /// Work out which lines differ between an older version of this document and the current one.
+    /// Returns the first line that changed, followed by where the change ends (exclusive)
+    /// in the old version and in the current version.
+    #[must_use]
+    pub fn changed_region(&self, old: &Rope) -> (usize, usize, usize) {
+        let (old_len, new_len) = (old.len_lines(), self.file.len_lines());
+        // Skip over lines that are the same at the start
+        let mut start = 0;
+        while start < old_len.min(new_len) && old.line(start) == self.file.line(start) {
+            start += 1;
+        }
+        // Skip over lines that are the same at the end
+        let mut same = 0;
+        while same < (old_len - start).min(new_len - start)
+            && old.line(old_len - same - 1) == self.file.line(new_len - same - 1)
+        {
+            same += 1;
+        }
+        (start, old_len - same, new_len - same)
+    }
+
+    /// Work out which lines in a range have changed since the document was last saved
+    pub fn refresh_changes(&mut self, range: Range<usize>) {
+        self.changes.refresh(range, &self.file);
+    }
+
+    /// How a line has changed since the document was last saved
+    /// (call `refresh_changes` on it first)
+    #[must_use]
+    pub fn line_change(&self, y: usize) -> Option<LineChange> {
+        self.changes.get(y)
+    }
+
     /// Returns the number of lines in the document
     #[must_use]
     pub fn len_lines(&self) -> usize {
@@ -40,8 +77,20 @@ impl Document {
     pub fn swap_line_up(&mut self) -> Result<()> {
         let cursor = self.char_loc();
         let line = self.line(cursor.y).ok_or(Error::OutOfRange)?;
-        self.insert_line(cursor.y.saturating_sub(1), line)?;
-        self.delete_line(cursor.y + 1)?;
+        // Marks on the line travel with it
+        let carried = self.take_marks(cursor.y);
+        let result = self
+            .insert_line(cursor.y.saturating_sub(1), line)
+            .and_then(|()| self.delete_line(cursor.y + 1));
+        for mark in carried {
+            let y = if result.is_ok() {
+                cursor.y.saturating_sub(1)
+            } else {
+                cursor.y
+            };
+            self.put_mark(LineMark { y, ..mark });
+        }
+        result?;
         self.move_to(&Loc {
             x: cursor.x,
             y: cursor.y.saturating_sub(1),
@@ -55,8 +104,20 @@ impl Document {
     pub fn swap_line_down(&mut self) -> Result<()> {
         let cursor = self.char_loc();
         let line = self.line(cursor.y).ok_or(Error::OutOfRange)?;
-        self.insert_line(cursor.y + 2, line)?;
-        self.delete_line(cursor.y)?;
+        // Marks on the line travel with it
+        let carried = self.take_marks(cursor.y);
+        let result = self
+            .insert_line(cursor.y + 2, line)
+            .and_then(|()| self.delete_line(cursor.y));
+        for mark in carried {
+            let y = if result.is_ok() {
+                cursor.y + 1
+            } else {
+                cursor.y
+            };
+            self.put_mark(LineMark { y, ..mark });
+        }
+        result?;
         self.move_to(&Loc {
             x: cursor.x,
             y: cursor.y + 1,
@@ -64,10 +125,371 @@ impl Document {
         Ok(())
     }
 
+    /// The lines the selection covers, leaving out the last one if the selection
+    /// only reaches its very start (the current line if nothing is selected)
+    #[must_use]
+    pub fn selected_lines(&self) -> (usize, usize) {
+        let (left, right) = self.selection_loc_bound();
+        if right.y > left.y && right.x == 0 {
+            (left.y, right.y - 1)
+        } else {
+            (left.y, right.y)
+        }
+    }
+
+    /// Join the current line with the one below it, or all the selected lines into one.
+    /// The indentation of each joined line is collapsed into a single space
+    /// # Errors
+    /// When out of bounds
+    pub fn join_lines(&mut self) -> Result<()> {
+        let (start, end) = self.selected_lines();
+        let end = end.max(start + 1).min(self.len_lines().saturating_sub(1));
+        for _ in start..end {
+            let line = self.line(start).ok_or(Error::OutOfRange)?;
+            let next = self.line(start + 1).ok_or(Error::OutOfRange)?;
+            let below = next.trim_start();
+            let gap = if line.is_empty() || line.ends_with(char::is_whitespace) || below.is_empty()
+            {
+                ""
+            } else {
+                " "
+            };
+            let x = line.chars().count();
+            let joined = format!("{gap}{below}");
+            self.exe(Event::DeleteLine(start + 1, next))?;
+            self.exe(Event::Insert(Loc { x, y: start }, joined))?;
+            // Leave the cursor where the lines were joined
+            self.move_to(&Loc { x, y: start });
+        }
+        Ok(())
+    }
+
+    /// Sort the selected lines alphabetically (or in reverse),
+    /// optionally leaving out lines that are the same as one before them
+    /// # Errors
+    /// When out of bounds
+    pub fn sort_lines(&mut self, reverse: bool, unique: bool) -> Result<()> {
+        let (start, end) = self.selected_lines();
+        let old: Vec<String> = (start..=end).filter_map(|y| self.line(y)).collect();
+        let mut new = old.clone();
+        new.sort();
+        if unique {
+            new.dedup();
+        }
+        if reverse {
+            new.reverse();
+        }
+        if new == old {
+            return Ok(());
+        }
+        for y in (start..=end).rev() {
+            let line = self.line(y).ok_or(Error::OutOfRange)?;
+            self.exe(Event::DeleteLine(y, line))?;
+        }
+        for (y, line) in (start..).zip(&new) {
+            self.exe(Event::InsertLine(y, line.clone()))?;
+        }
+        // Select the sorted lines
+        let last = start + new.len().saturating_sub(1);
+        let width = self.line(last).map_or(0, |l| l.chars().count());
+        self.move_to(&Loc { x: 0, y: start });
+        self.select_to(&Loc { x: width, y: last });
+        Ok(())
+    }
+
+    /// Change the selected text with a function (such as making it upper case),
+    /// keeping the changed text selected
+    /// # Errors
+    /// When out of bounds
+    pub fn transform_selection(&mut self, f: impl Fn(&str) -> String) -> Result<()> {
+        if self.is_selection_empty() {
+            return Ok(());
+        }
+        let (left, mut right) = self.selection_loc_bound();
+        for y in left.y..=right.y {
+            let line = self.line(y).ok_or(Error::OutOfRange)?;
+            let len = line.chars().count();
+            let from = if y == left.y { left.x } else { 0 };
+            let to = if y == right.y { right.x.min(len) } else { len };
+            let old: String = line
+                .chars()
+                .skip(from)
+                .take(to.saturating_sub(from))
+                .collect();
+            let new = f(&old);
+            if new == old {
+                continue;
+            }
+            // Only replace what has changed, so the rest of the line is left alone
+            let same_start = old
+                .chars()
+                .zip(new.chars())
+                .take_while(|(a, b)| a == b)
+                .count();
+            let same_end = old
+                .chars()
+                .rev()
+                .zip(new.chars().rev())
+                .take_while(|(a, b)| a == b)
+                .count()
+                .min(old.chars().count() - same_start)
+                .min(new.chars().count() - same_start);
+            let removed: String = old
+                .chars()
+                .skip(same_start)
+                .take(old.chars().count() - same_start - same_end)
+                .collect();
+            let added: String = new
+                .chars()
+                .skip(same_start)
+                .take(new.chars().count() - same_start - same_end)
+                .collect();
+            let at = Loc {
+                x: from + same_start,
+                y,
+            };
+            if !removed.is_empty() {
+                self.exe(Event::Delete(at, removed))?;
+            }
+            if !added.is_empty() {
+                self.exe(Event::Insert(at, added))?;
+            }
+            if y == right.y {
+                right.x = (right.x + new.chars().count()).saturating_sub(old.chars().count());
+            }
+        }
+        self.move_to(&left);
+        self.select_to(&right);
+        Ok(())
+    }
+
     /// Select a line at a location
     pub fn select_line_at(&mut self, y: usize) {
         let len = self.line(y).unwrap_or_default().chars().count();
         self.move_to(&Loc { x: 0, y });
         self.select_to(&Loc { x: len, y });
     }
+
+    /// Rewrite the indentation of every line with spaces, or with tabs (where anything
+    /// short of a full tab stays as spaces). Tabs after the indentation are left alone,
+    /// unless `everywhere` is set while converting to spaces.
+    /// Returns the lines that changed
+    /// # Errors
+    /// When out of bounds
+    pub fn convert_indentation(&mut self, to_spaces: bool, everywhere: bool) -> Result<Vec<usize>> {
+        let tab_width = self.tab_width.max(1);
+        let cursor = self.char_loc();
+        let mut cursor_x = cursor.x;
+        self.load_to(self.len_lines());
+        let mut changed = vec![];
+        for y in 0..self.lines.len() {
+            let line = self.lines[y].clone();
+            let indent: String = line
+                .chars()
+                .take_while(|c| matches!(c, ' ' | '\t'))
+                .collect();
+            let indent_len = indent.chars().count();
+            let width = expand_tabs(&indent, 0, tab_width).len();
+            let new_indent = if to_spaces {
+                " ".repeat(width)
+            } else {
+                "\t".repeat(width / tab_width) + &" ".repeat(width % tab_width)
+            };
+            let rest: String = line.chars().skip(indent_len).collect();
+            let new_rest = if to_spaces && everywhere {
+                expand_tabs(&rest, width, tab_width)
+            } else {
+                rest.clone()
+            };
+            let new = format!("{new_indent}{new_rest}");
+            if new == line {
+                continue;
+            }
+            self.exe(Event::Delete(Loc { x: 0, y }, line))?;
+            self.exe(Event::Insert(Loc { x: 0, y }, new.clone()))?;
+            // Keep the cursor on the same character where possible
+            if y == cursor.y {
+                cursor_x = if cursor.x >= indent_len {
+                    let before: String = rest.chars().take(cursor.x - indent_len).collect();
+                    let before = if to_spaces && everywhere {
+                        expand_tabs(&before, width, tab_width)
+                    } else {
+                        before
+                    };
+                    new_indent.chars().count() + before.chars().count()
+                } else {
+                    cursor.x.min(new_indent.chars().count())
+                };
+            }
+            changed.push(y);
+        }
+        self.move_to(&Loc {
+            x: cursor_x,
+            y: cursor.y,
+        });
+        Ok(changed)
+    }
+
+    /// The characters of a line that start within a range of display columns
+    #[must_use]
+    pub(crate) fn block_chars(&self, y: usize, cols: &Range<usize>) -> Range<usize> {
+        let line = self.line(y).unwrap_or_default();
+        let len = line.chars().count();
+        let (mut start, mut end) = (None, len);
+        let mut col = 0;
+        for (i, ch) in line.chars().enumerate() {
+            if start.is_none() && col >= cols.start {
+                start = Some(i);
+            }
+            if col >= cols.end {
+                end = i;
+                break;
+            }
+            col += width_char(&ch, self.tab_width);
+        }
+        let start = start.unwrap_or(len);
+        start..end.max(start)
+    }
+
+    /// Will return the text covered by the block selection, with a line for each row.
+    /// Rows that are too short to reach across the block are padded with spaces if `pad` is set
+    #[must_use]
+    pub fn block_text(&self, pad: bool) -> Option<String> {
+        let (rows, cols) = self.block_bounds()?;
+        let text: Vec<String> = rows
+            .map(|y| {
+                let chars = self.block_chars(y, &cols);
+                let line = self.line(y).unwrap_or_default();
+                let text: String = line.chars().skip(chars.start).take(chars.len()).collect();
+                if pad {
+                    let short = cols.len().saturating_sub(width(&text, self.tab_width));
+                    text + &" ".repeat(short)
+                } else {
+                    text
+                }
+            })
+            .collect();
+        Some(text.join("\n"))
+    }
+
+    /// Remove the text covered by the block selection from every row, leaving a block
+    /// with no width at its left edge (ready to type into every row)
+    /// # Errors
+    /// When out of bounds
+    pub fn remove_block(&mut self) -> Result<()> {
+        let Some((rows, cols)) = self.block_bounds() else {
+            return Ok(());
+        };
+        let (anchor_y, edge_y) = (self.cursor.selection_end.y, self.loc().y);
+        for y in rows {
+            let chars = self.block_chars(y, &cols);
+            if chars.is_empty() {
+                continue;
+            }
+            let line = self.line(y).ok_or(Error::OutOfRange)?;
+            let text: String = line.chars().skip(chars.start).take(chars.len()).collect();
+            self.exe(Event::Delete(Loc { x: chars.start, y }, text))?;
+        }
+        self.restore_block(anchor_y, edge_y, cols.start);
+        Ok(())
+    }
+
+    /// Type text into every row of the block selection at its left edge, replacing what it covers.
+    /// Rows too short to reach the block are skipped, or padded with spaces if `pad` is set
+    /// # Errors
+    /// When out of bounds
+    pub fn insert_block(&mut self, text: &str, pad: bool) -> Result<()> {
+        let Some((rows, cols)) = self.block_bounds() else {
+            return Ok(());
+        };
+        if !cols.is_empty() {
+            self.remove_block()?;
+        }
+        let (anchor_y, edge_y) = (self.cursor.selection_end.y, self.loc().y);
+        let col = cols.start;
+        for y in rows {
+            let Some(line) = self.line(y) else {
+                continue;
+            };
+            let line_width = width(&line, self.tab_width);
+            if line_width >= col {
+                let x = self.block_chars(y, &(col..col)).start;
+                self.exe(Event::Insert(Loc { y, x }, text.to_string()))?;
+            } else if pad {
+                let x = line.chars().count();
+                let padding = " ".repeat(col - line_width);
+                self.exe(Event::Insert(Loc { y, x }, padding + text))?;
+            }
+        }
+        self.restore_block(anchor_y, edge_y, col + width(text, self.tab_width));
+        Ok(())
+    }
+
+    /// Remove the character just before the left edge of the block selection on every row
+    /// (or what the block covers, if it has any width)
+    /// # Errors
+    /// When out of bounds
+    pub fn backspace_block(&mut self) -> Result<()> {
+        let Some((rows, cols)) = self.block_bounds() else {
+            return Ok(());
+        };
+        if !cols.is_empty() {
+            return self.remove_block();
+        }
+        let (anchor_y, edge_y) = (self.cursor.selection_end.y, self.loc().y);
+        let mut new_col = cols.start;
+        for y in rows {
+            let Some(line) = self.line(y) else {
+                continue;
+            };
+            let x = self.block_chars(y, &cols).start;
+            // Rows that end before the block have nothing to remove
+            if x == 0 || width(&line, self.tab_width) < cols.start {
+                continue;
+            }
+            let before: String = line.chars().take(x - 1).collect();
+            let ch = line.chars().nth(x - 1).ok_or(Error::OutOfRange)?;
+            new_col = new_col.min(width(&before, self.tab_width));
+            self.exe(Event::Delete(Loc { x: x - 1, y }, ch.to_string()))?;
+        }
+        self.restore_block(anchor_y, edge_y, new_col);
+        Ok(())
+    }
+
+    /// Remove the character at the left edge of the block selection on every row
+    /// (or what the block covers, if it has any width)
+    /// # Errors
+    /// When out of bounds
+    pub fn delete_block(&mut self) -> Result<()> {
+        let Some((rows, cols)) = self.block_bounds() else {
+            return Ok(());
+        };
+        if !cols.is_empty() {
+            return self.remove_block();
+        }
+        let (anchor_y, edge_y) = (self.cursor.selection_end.y, self.loc().y);
+        for y in rows {
+            let Some(line) = self.line(y) else {
+                continue;
+            };
+            let x = self.block_chars(y, &cols).start;
+            if let Some(ch) = line.chars().nth(x) {
+                self.exe(Event::Delete(Loc { y, x }, ch.to_string()))?;
+            }
+        }
+        self.restore_block(anchor_y, edge_y, cols.start);
+        Ok(())
+    }
+
+    /// Put the block selection back after editing (which cancels it),
+    /// as a column with no width at `col` across the same rows
+    fn restore_block(&mut self, anchor_y: usize, edge_y: usize, col: usize) {
+        let x = self.block_chars(edge_y, &(col..col)).start;
+        self.move_to(&Loc { x, y: edge_y });
+        self.cursor.selection_end = Loc {
+            x: col,
+            y: anchor_y,
+        };
+        self.cursor.block = Some(col);
+    }
 }
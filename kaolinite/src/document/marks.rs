@@ -0,0 +1,292 @@
+/// marks.rs - for following lines of a document as it is edited around them
+use crate::Document;
+use std::fmt::Write;
+
+/// How far either side of a mark's last position a similar line is looked for
+const REANCHOR_REACH: usize = 100;
+/// How alike a line needs to be to a mark's text to be taken as the same line (0 to 1)
+const REANCHOR_SIMILARITY: f64 = 0.5;
+
+/// A line of a document that is followed as lines are inserted and removed around it
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct LineMark {
+    /// The index of the marked line
+    pub y: usize,
+    /// A short label attached to the line
+    pub label: String,
+    /// The text of the line, so it can be found again after changes made outside of the editor
+    pub text: String,
+    /// Set when the line's text couldn't be found again, so the mark sits on the closest guess
+    pub moved: bool,
+    /// Bookmarks are kept apart from annotations, so a line can have one of each
+    pub bookmark: bool,
+}
+
+/// Find the index closest to `y` that satisfies a predicate (earlier lines win ties)
+fn nearest(y: usize, len: usize, reach: usize, pred: impl Fn(usize) -> bool) -> Option<usize> {
+    let y = y.min(len.saturating_sub(1));
+    (0..=reach.min(len)).find_map(|d| {
+        let before = y.checked_sub(d).filter(|i| pred(*i));
+        let after = Some(y + d).filter(|i| d > 0 && *i < len && pred(*i));
+        before.or(after)
+    })
+}
+
+/// Work out how alike two lines are, based on how much they share at their start and end
+fn similarity(a: &str, b: &str) -> f64 {
+    let (a, b): (Vec<char>, Vec<char>) = (a.trim().chars().collect(), b.trim().chars().collect());
+    let longest = a.len().max(b.len());
+    if longest == 0 {
+        return 0.0;
+    }
+    let prefix = a.iter().zip(&b).take_while(|(x, y)| x == y).count();
+    let suffix = a[prefix..]
+        .iter()
+        .rev()
+        .zip(b[prefix..].iter().rev())
+        .take_while(|(x, y)| x == y)
+        .count();
+    #[allow(clippy::cast_precision_loss)]
+    let result = (prefix + suffix) as f64 / longest as f64;
+    result
+}
+
+/// Work out where a line has got to after its document has changed.
+/// Returns the new index of the line, along with whether its text couldn't be found exactly.
+/// Identical lines are looked for first, then ones that only differ in indentation,
+/// then the most similar line nearby (closer lines are always preferred)
+#[must_use]
+pub fn reanchor<S: AsRef<str>>(y: usize, text: &str, lines: &[S]) -> Option<(usize, bool)> {
+    let len = lines.len();
+    if len == 0 {
+        return None;
+    }
+    let line = |i: usize| lines[i].as_ref();
+    if let Some(i) = nearest(y, len, len, |i| line(i) == text) {
+        return Some((i, false));
+    }
+    if let Some(i) = nearest(y, len, len, |i| line(i).trim() == text.trim()) {
+        return Some((i, false));
+    }
+    // Fall back to the most similar line near where the mark was
+    let start = y.saturating_sub(REANCHOR_REACH).min(len - 1);
+    let end = (y + REANCHOR_REACH).min(len - 1);
+    let best = (start..=end)
+        .map(|i| (i, similarity(line(i), text)))
+        .filter(|(_, score)| *score >= REANCHOR_SIMILARITY)
+        .max_by(|a, b| {
+            a.1.total_cmp(&b.1)
+                .then(b.0.abs_diff(y).cmp(&a.0.abs_diff(y)))
+        });
+    Some((best.map_or(y.min(len - 1), |(i, _)| i), true))
+}
+
+/// Escape a field so it fits on a single line with no tabs
+fn escape(field: &str) -> String {
+    field
+        .replace('\\', "\\\\")
+        .replace('\t', "\\t")
+        .replace('\n', "\\n")
+        .replace('\r', "\\r")
+}
+
+/// Undo the escaping of a field
+fn unescape(field: &str) -> String {
+    let mut result = String::new();
+    let mut chars = field.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            result.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('t') => result.push('\t'),
+            Some('n') => result.push('\n'),
+            Some('r') => result.push('\r'),
+            Some(other) => result.push(other),
+            None => result.push('\\'),
+        }
+    }
+    result
+}
+
+/// Write out marks so they can be stored, one per line
+#[must_use]
+pub fn save_marks(marks: &[LineMark]) -> String {
+    let mut result = String::new();
+    for mark in marks {
+        let moved = u8::from(mark.moved);
+        let _ = writeln!(
+            result,
+            "{}\t{moved}\t{}\t{}",
+            mark.y,
+            escape(&mark.label),
+            escape(&mark.text)
+        );
+    }
+    result
+}
+
+/// Read in marks written out by `save_marks`, skipping over any lines that are malformed
+#[must_use]
+pub fn load_marks(data: &str) -> Vec<LineMark> {
+    data.lines()
+        .filter_map(|line| {
+            let mut fields = line.splitn(4, '\t');
+            let y = fields.next()?.parse().ok()?;
+            let moved = fields.next()? == "1";
+            let label = unescape(fields.next()?);
+            let text = unescape(fields.next()?);
+            Some(LineMark {
+                y,
+                label,
+                text,
+                moved,
+                bookmark: false,
+            })
+        })
+        .collect()
+}
+
+impl Document {
+    /// Find the annotation on a line
+    #[must_use]
+    pub fn mark_at(&self, y: usize) -> Option<&LineMark> {
+        self.marks.iter().find(|m| m.y == y && !m.bookmark)
+    }
+
+    /// Find the bookmark on a line
+    #[must_use]
+    pub fn bookmark_at(&self, y: usize) -> Option<&LineMark> {
+        self.marks.iter().find(|m| m.y == y && m.bookmark)
+    }
+
+    /// The bookmarks in the document, from top to bottom
+    pub fn bookmarks(&self) -> impl Iterator<Item = &LineMark> {
+        self.marks.iter().filter(|m| m.bookmark)
+    }
+
+    /// Bookmark a line, or remove the bookmark if it has one already.
+    /// Returns whether the line is now bookmarked
+    pub fn toggle_bookmark(&mut self, y: usize) -> bool {
+        if let Some(idx) = self.marks.iter().position(|m| m.y == y && m.bookmark) {
+            self.marks.remove(idx);
+            return false;
+        }
+        let text = self.line(y).unwrap_or_default();
+        self.put_mark(LineMark {
+            y,
+            text,
+            bookmark: true,
+            ..LineMark::default()
+        });
+        true
+    }
+
+    /// Find the next bookmarked line after `y` (or before it if not `forward`),
+    /// wrapping around the ends of the document
+    #[must_use]
+    pub fn next_bookmark(&self, y: usize, forward: bool) -> Option<usize> {
+        let lines: Vec<usize> = self.bookmarks().map(|m| m.y).collect();
+        if forward {
+            lines.iter().find(|l| **l > y).or(lines.first()).copied()
+        } else {
+            lines
+                .iter()
+                .rev()
+                .find(|l| **l < y)
+                .or(lines.last())
+                .copied()
+        }
+    }
+
+    /// Mark a line with a label (replacing any mark already on it)
+    pub fn add_mark(&mut self, y: usize, label: &str) {
+        self.remove_mark(y);
+        let text = self.line(y).unwrap_or_default();
+        let label = label.to_string();
+        self.put_mark(LineMark {
+            y,
+            label,
+            text,
+            moved: false,
+            bookmark: false,
+        });
+    }
+
+    /// Put a mark back into the document, keeping the marks in order
+    pub(crate) fn put_mark(&mut self, mark: LineMark) {
+        self.marks.push(mark);
+        self.marks.sort_by_key(|m| (m.y, m.bookmark));
+    }
+
+    /// Remove the annotation on a line, returning it if there was one
+    pub fn remove_mark(&mut self, y: usize) -> Option<LineMark> {
+        let idx = self.marks.iter().position(|m| m.y == y && !m.bookmark)?;
+        Some(self.marks.remove(idx))
+    }
+
+    /// Remove every mark on a line (of either kind), so they can be put back elsewhere
+    pub(crate) fn take_marks(&mut self, y: usize) -> Vec<LineMark> {
+        let (taken, kept) = std::mem::take(&mut self.marks)
+            .into_iter()
+            .partition(|m| m.y == y);
+        self.marks = kept;
+        taken
+    }
+
+    /// Keep the text of marks on a line up to date after it has been edited
+    pub(crate) fn refresh_marks(&mut self, y: usize) {
+        let text = self.lines.get(y).cloned().unwrap_or_default();
+        for mark in self.marks.iter_mut().filter(|m| m.y == y) {
+            mark.text.clone_from(&text);
+        }
+    }
+
+    /// Move marks down to make way for a line inserted at `y`
+    pub(crate) fn shift_marks_down(&mut self, y: usize) {
+        for mark in self.marks.iter_mut().filter(|m| m.y >= y) {
+            mark.y += 1;
+        }
+    }
+
+    /// Move marks up to fill the space left by a line removed at `y`
+    /// (marks on the removed line end up on the line that took its place)
+    pub(crate) fn shift_marks_up(&mut self, y: usize) {
+        let last = self.len_lines().saturating_sub(1);
+        for mark in &mut self.marks {
+            if mark.y > y {
+                mark.y -= 1;
+            } else if mark.y == y {
+                mark.y = y.min(last);
+                mark.moved = true;
+            }
+        }
+    }
+
+    /// Move every mark to wherever its line has got to, for after the document has changed
+    /// in a way that couldn't be followed (e.g. undoing or reloading from disk).
+    /// Marks whose lines can't be found are flagged as moved if `flag` is set
+    pub fn reanchor_marks(&mut self, flag: bool) {
+        if self.marks.is_empty() {
+            return;
+        }
+        let lines: Vec<String> = self
+            .file
+            .lines()
+            .take(self.len_lines())
+            .map(|l| l.to_string().trim_end_matches(['\n', '\r']).to_string())
+            .collect();
+        for mark in &mut self.marks {
+            if let Some((y, changed)) = reanchor(mark.y, &mark.text, &lines) {
+                mark.y = y;
+                if changed {
+                    mark.text.clone_from(&lines[y]);
+                    mark.moved |= flag;
+                }
+            }
+        }
+        self.marks.sort_by_key(|m| (m.y, m.bookmark));
+        self.marks.dedup_by_key(|m| (m.y, m.bookmark));
+    }
+}
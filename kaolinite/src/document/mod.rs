@@ -1,7 +1,8 @@
 /// document.rs - has Document, for opening, editing and saving documents
-use crate::event::{Error, Event, EventMgmt, Result};
+use crate::diff::ChangeTracker;
+use crate::event::{Change, Error, Event, EventMgmt, Result};
 use crate::map::CharMap;
-use crate::searching::{Match, Searcher};
+use crate::searching::{LooseSearcher, Match, Searcher};
 use crate::utils::{modeline, width, Loc, Size};
 use ropey::Rope;
 use std::path::Path;
@@ -10,10 +11,16 @@ pub mod cursor;
 pub mod disk;
 pub mod editing;
 pub mod lines;
+pub mod marks;
 pub mod words;
 
 pub use cursor::Cursor;
-pub use disk::DocumentInfo;
+pub use disk::{
+    bom_for, decode, detect_encoding, encode, hex_row, is_binary, read_rope, unrepresentable,
+    Decoding, DocumentInfo, LineEnding, HEX_WIDTH,
+};
+pub use marks::{load_marks, reanchor, save_marks, LineMark};
+pub use words::{CharKind, Stats, DEFAULT_WORD_SEPARATORS};
 
 /// A document struct manages a file.
 /// It has tools to read, write and traverse a document.
@@ -50,8 +57,22 @@ pub struct Document {
     pub in_redo: bool,
     /// The number of spaces a tab should be rendered as
     pub tab_width: usize,
+    /// How many columns are kept between the cursor and the left and right edges of the view
+    pub horizontal_margin: usize,
+    /// The characters (besides whitespace) that end a word when moving or deleting by words
+    pub word_separators: String,
     /// Secondary cursor (for multi-cursors)
     pub secondary_cursors: Vec<Loc>,
+    /// The text that secondary cursors are being added at the occurances of
+    pub occurrence: Option<String>,
+    /// Raw bytes of a binary file, when it is being previewed as a hex dump
+    pub binary: Option<Vec<u8>>,
+    /// Lines that are followed as the document is edited (e.g. for annotations)
+    pub marks: Vec<LineMark>,
+    /// Goes up whenever the contents change, so views of the document know to redraw
+    pub generation: u64,
+    /// Which lines have changed since the document was last saved
+    pub changes: ChangeTracker,
 }
 
 impl Document {
@@ -91,24 +112,34 @@ impl Document {
         Ok(())
     }
 
-    /// Undo the last patch in the document.
+    /// Undo the last patch in the document, returning what was undone (None if nothing was).
     /// # Errors
     /// Will return an error if any of the events failed to be reversed.
-    pub fn undo(&mut self) -> Result<()> {
-        if let Some(s) = self.event_mgmt.undo(self.take_snapshot()) {
-            self.apply_snapshot(s);
-        }
-        Ok(())
+    pub fn undo(&mut self) -> Result<Option<Change>> {
+        let Some((s, change)) = self.event_mgmt.undo(self.take_snapshot()) else {
+            return Ok(None);
+        };
+        // Keep the view where it was, so it only moves if the change is out of sight
+        let offset = self.offset;
+        self.apply_snapshot(s);
+        self.offset = offset;
+        self.land_on(&change);
+        Ok(Some(change))
     }
 
-    /// Redo the last patch in the document.
+    /// Redo the last patch in the document, returning what was redone (None if nothing was).
     /// # Errors
     /// Will return an error if any of the events failed to be re-executed.
-    pub fn redo(&mut self) -> Result<()> {
-        if let Some(s) = self.event_mgmt.redo(&self.take_snapshot()) {
-            self.apply_snapshot(s);
-        }
-        Ok(())
+    pub fn redo(&mut self) -> Result<Option<Change>> {
+        let Some((s, change)) = self.event_mgmt.redo(&self.take_snapshot()) else {
+            return Ok(None);
+        };
+        // Keep the view where it was, so it only moves if the change is out of sight
+        let offset = self.offset;
+        self.apply_snapshot(s);
+        self.offset = offset;
+        self.land_on(&change);
+        Ok(Some(change))
     }
 
     /// Handle an editing event, use the method `exe` for executing events.
@@ -191,6 +222,53 @@ impl Document {
         None
     }
 
+    /// Find every occurance of some literal text, ignoring differences in unicode normalisation
+    /// and invisible characters (only the lines that have already been loaded are checked)
+    #[must_use]
+    pub fn loose_matches(&self, target: &str) -> Vec<Match> {
+        let srch = LooseSearcher::new(target);
+        let mut result = vec![];
+        let mut line_no = 0;
+        while line_no < self.info.loaded_to {
+            let Some(line) = self.line(line_no) else {
+                break;
+            };
+            for mut mtch in srch.finds(&line) {
+                mtch.loc.y = line_no;
+                result.push(mtch);
+            }
+            line_no += 1;
+        }
+        result
+    }
+
+    /// Find where the next occurance of some literal text ends, after a certain location.
+    /// This wraps around to the top of the document and skips places that already have a cursor
+    pub fn next_occurrence(&mut self, text: &str, after: Loc) -> Option<Loc> {
+        if text.is_empty() {
+            return None;
+        }
+        self.load_to(self.len_lines());
+        let len = text.chars().count();
+        let mut found = vec![];
+        let mut line_no = 0;
+        while let Some(line) = self.line(line_no) {
+            for (byte, _) in line.match_indices(text) {
+                let x = Searcher::raw_to_char(byte, &line) + len;
+                found.push(Loc::at(x, line_no));
+            }
+            line_no += 1;
+        }
+        let primary = self.char_loc();
+        let free = |loc: &&Loc| **loc != primary && self.has_cursor(**loc).is_none();
+        found
+            .iter()
+            .filter(|loc| **loc > after)
+            .find(free)
+            .or_else(|| found.iter().find(free))
+            .copied()
+    }
+
     /// Replace a specific part of the document with another string.
     /// # Errors
     /// Will error if the replacement failed to be executed.
@@ -200,12 +278,105 @@ impl Document {
         Ok(())
     }
 
-    /// Replace all instances of a regex with another string
-    pub fn replace_all(&mut self, target: &str, into: &str) {
-        self.move_to(&Loc::at(0, 0));
-        while let Some(mtch) = self.next_match(target, 1) {
-            drop(self.replace(mtch.loc, &mtch.text, into));
+    /// Count the occurances of a regex, stopping once the limit is reached.
+    /// This leaves the cursor and viewport where they are
+    pub fn count_matches(&mut self, regex: &str, limit: usize) -> usize {
+        let srch = Searcher::new(regex);
+        self.load_to(self.len_lines());
+        let mut count = 0;
+        let mut line_no = 0;
+        while let Some(line) = self.line(line_no) {
+            if count >= limit {
+                break;
+            }
+            count += srch.re.captures_iter(&line).take(limit - count).count();
+            line_no += 1;
         }
+        count
+    }
+
+    /// Find every match of a regex that lies wholly between two locations
+    /// (where x is a character index)
+    pub fn matches_within(&mut self, regex: &str, start: Loc, end: Loc) -> Vec<Match> {
+        let mut srch = Searcher::new(regex);
+        self.load_to(end.y + 1);
+        let mut result = vec![];
+        for line_no in start.y..=end.y {
+            let Some(line) = self.line(line_no) else {
+                break;
+            };
+            for mut mtch in srch.lfinds(&line) {
+                mtch.loc.y = line_no;
+                let finish = Loc::at(mtch.loc.x + mtch.text.chars().count(), line_no);
+                if mtch.loc >= start && finish <= end {
+                    result.push(mtch);
+                }
+            }
+        }
+        result
+    }
+
+    /// Replace all instances of a regex with another string, returning how many were replaced
+    /// and where the last replacement was made
+    pub fn replace_all(&mut self, target: &str, into: &str) -> (usize, Option<Loc>) {
+        self.load_to(self.len_lines());
+        let end = Loc::at(usize::MAX, self.len_lines());
+        let (count, last, _) = self.replace_within(target, into, Loc::at(0, 0), end);
+        (count, last)
+    }
+
+    /// Replace the instances of a regex that lie wholly between two locations,
+    /// returning how many were replaced, where the last replacement was made
+    /// and where the end location has moved to as a result
+    pub fn replace_within(
+        &mut self,
+        target: &str,
+        into: &str,
+        start: Loc,
+        end: Loc,
+    ) -> (usize, Option<Loc>, Loc) {
+        self.replace_within_until(target, into, start, end, |_, _| true)
+    }
+
+    /// Replace the instances of a regex that lie wholly between two locations, like
+    /// `replace_within`, asking `keep_going` before each replacement whether to carry on
+    /// (it is given how many of the instances have been got through, and how many there are)
+    pub fn replace_within_until(
+        &mut self,
+        target: &str,
+        into: &str,
+        start: Loc,
+        mut end: Loc,
+        mut keep_going: impl FnMut(usize, usize) -> bool,
+    ) -> (usize, Option<Loc>, Loc) {
+        let into_len = into.chars().count();
+        let mut count = 0;
+        let mut last = None;
+        // Earlier replacements on a line move the later matches along
+        let (mut line_no, mut grown, mut shrunk) = (start.y, 0, 0);
+        let matches = self.matches_within(target, start, end);
+        let total = matches.len();
+        for (done, mtch) in matches.into_iter().enumerate() {
+            if !keep_going(done, total) {
+                break;
+            }
+            if mtch.loc.y != line_no {
+                (line_no, grown, shrunk) = (mtch.loc.y, 0, 0);
+            }
+            let loc = Loc::at(mtch.loc.x + grown - shrunk, line_no);
+            if self.replace(loc, &mtch.text, into).is_ok() {
+                count += 1;
+                last = Some(loc);
+            }
+            let len = mtch.text.chars().count();
+            grown += into_len.saturating_sub(len);
+            shrunk += len.saturating_sub(into_len);
+        }
+        // Only the replacements on the last line move the end along
+        if line_no == end.y {
+            end.x = end.x.saturating_add(grown).saturating_sub(shrunk);
+        }
+        (count, last, end)
     }
 
     /// Brings the cursor into the viewport so it can be seen
@@ -216,11 +387,16 @@ impl Document {
         if self.offset.y + self.size.h <= self.cursor.loc.y {
             self.offset.y = self.cursor.loc.y.saturating_sub(self.size.h) + 1;
         }
-        if self.offset.x > self.cursor.loc.x {
-            self.offset.x = self.cursor.loc.x;
+        // The view scrolls sideways before the cursor reaches its edge
+        // (the margin is kept small enough on narrow views for the cursor to fit between)
+        let margin = self
+            .horizontal_margin
+            .min(self.size.w.saturating_sub(1) / 2);
+        if self.offset.x + margin > self.cursor.loc.x {
+            self.offset.x = self.cursor.loc.x.saturating_sub(margin);
         }
-        if self.offset.x + self.size.w <= self.cursor.loc.x {
-            self.offset.x = self.cursor.loc.x.saturating_sub(self.size.w) + 1;
+        if self.offset.x + self.size.w <= self.cursor.loc.x + margin {
+            self.offset.x = (self.cursor.loc.x + margin + 1).saturating_sub(self.size.w);
         }
         self.load_to(self.offset.y + self.size.h);
     }
@@ -367,14 +543,48 @@ impl Document {
     }
 
     /// Commit a change to the undo management system
+    /// (this waits until the end of the batch if a batch of edits is open)
     pub fn commit(&mut self) {
+        if self.event_mgmt.batch > 0 {
+            return;
+        }
         let s = self.take_snapshot();
         self.event_mgmt.commit(s);
     }
 
+    /// Start a batch of edits, which are undone and redone as a single change.
+    /// Anything edited beforehand is committed first, then commits are held back until
+    /// `end_batch` is called (batches can be nested, only the outermost one counts)
+    pub fn begin_batch(&mut self) {
+        self.commit();
+        self.event_mgmt.batch += 1;
+    }
+
+    /// Finish a batch of edits started with `begin_batch`, committing them together
+    pub fn end_batch(&mut self) {
+        self.event_mgmt.batch = self.event_mgmt.batch.saturating_sub(1);
+        self.commit();
+    }
+
+    /// Throw away the edits made since the last commit, putting the document back as it was then
+    pub fn rollback(&mut self) {
+        if let Some(snapshot) = self.event_mgmt.current.clone() {
+            self.apply_snapshot(snapshot);
+        }
+    }
+
+    /// Make some edits as a single change in the undo history
+    pub fn batch<T>(&mut self, edits: impl FnOnce(&mut Self) -> T) -> T {
+        self.begin_batch();
+        let result = edits(self);
+        self.end_batch();
+        result
+    }
+
     /// Completely reload the file
     pub fn reload_lines(&mut self) {
         let to = std::mem::take(&mut self.info.loaded_to);
+        self.generation += 1;
         self.lines.clear();
         self.load_to(to);
     }
@@ -1,8 +1,29 @@
 use crate::event::{Result, Status};
-use crate::searching::Match;
 use crate::searching::Searcher;
 use crate::{Document, Loc};
 
+/// The characters that end a word by default (besides whitespace)
+pub const DEFAULT_WORD_SEPARATORS: &str = "`~!@#$%^&*()-=+[{]}\\|;:'\",.<>/?";
+
+/// The part a character plays in a word
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CharKind {
+    Whitespace,
+    /// Characters that end a word without being part of one (see `word_separators`)
+    Separator,
+    Word,
+}
+
+/// Whether a word splits into subwords between two characters, which happens where
+/// lowercase (or a digit) goes into uppercase and at the end of a run of capitals
+/// (so `parseHTTPRequest` is made up of `parse`, `HTTP` and `Request`)
+fn subword_break(prev: char, c: char, next: Option<char>) -> bool {
+    let rising = (prev.is_lowercase() || prev.is_numeric()) && c.is_uppercase();
+    let acronym_end =
+        prev.is_uppercase() && c.is_uppercase() && next.is_some_and(char::is_lowercase);
+    rising || acronym_end
+}
+
 /// State of a word
 pub enum WordState {
     AtStart(usize),
@@ -11,19 +32,133 @@ pub enum WordState {
     Out,
 }
 
+/// How much text there is in a document (or part of one)
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Stats {
+    /// Lines with text on them or ended by a line break
+    pub lines: usize,
+    /// Runs of characters that aren't whitespace
+    pub words: usize,
+    pub chars: usize,
+    pub chars_without_whitespace: usize,
+    /// The size of the text when encoded as UTF-8
+    pub bytes: usize,
+}
+
+impl Stats {
+    /// Count up the figures for some text, reading it a character at a time
+    #[must_use]
+    pub fn of(text: impl Iterator<Item = char>) -> Self {
+        let mut stats = Self::default();
+        let mut in_word = false;
+        let mut in_line = false;
+        for c in text {
+            stats.chars += 1;
+            stats.bytes += c.len_utf8();
+            if c == '\n' {
+                stats.lines += 1;
+            }
+            in_line = c != '\n';
+            if !c.is_whitespace() {
+                stats.chars_without_whitespace += 1;
+                if !in_word {
+                    stats.words += 1;
+                }
+            }
+            in_word = !c.is_whitespace();
+        }
+        // The last line may not have a line break at the end of it
+        stats.lines += usize::from(in_line);
+        stats
+    }
+}
+
 impl Document {
-    /// Find the word boundaries
+    /// Count the words in the document (runs of characters that aren't whitespace)
+    #[must_use]
+    pub fn word_count(&self) -> usize {
+        self.stats().words
+    }
+
+    /// Work out how much text is in the document (straight from the rope, without copying it)
+    #[must_use]
+    pub fn stats(&self) -> Stats {
+        Stats::of(self.file.chars())
+    }
+
+    /// Work out how much text is selected (None if nothing is), counting the lines
+    /// the selection reaches onto
+    #[must_use]
+    pub fn selection_stats(&self) -> Option<Stats> {
+        let (_, lines) = self.selection_size()?;
+        let stats = Stats::of(self.file.slice(self.selection_range()).chars());
+        Some(Stats { lines, ..stats })
+    }
+
+    /// Work out the part a character plays in a word
+    #[must_use]
+    pub fn char_kind(&self, c: char) -> CharKind {
+        if c.is_whitespace() {
+            CharKind::Whitespace
+        } else if self.word_separators.contains(c) {
+            CharKind::Separator
+        } else {
+            CharKind::Word
+        }
+    }
+
+    /// Find the word boundaries (as byte indices): runs of word characters,
+    /// runs of two or more whitespace characters and full stops (so `a.b` is stepped through)
     #[must_use]
     pub fn word_boundaries(&self, line: &str) -> Vec<(usize, usize)> {
-        let re = r"(\s{2,}|[A-Za-z0-9_]+|\.)";
-        let mut searcher = Searcher::new(re);
-        let starts: Vec<Match> = searcher.lfinds_raw(line);
-        let mut ends: Vec<Match> = starts.clone();
-        ends.iter_mut()
-            .for_each(|m| m.loc.x += m.text.chars().count());
-        let starts: Vec<usize> = starts.iter().map(|m| m.loc.x).collect();
-        let ends: Vec<usize> = ends.iter().map(|m| m.loc.x).collect();
-        starts.into_iter().zip(ends).collect()
+        self.boundaries(line, false)
+    }
+
+    /// Find the subword boundaries (as byte indices), which are like word boundaries
+    /// except that words are also split at underscores and changes of case
+    #[must_use]
+    pub fn subword_boundaries(&self, line: &str) -> Vec<(usize, usize)> {
+        self.boundaries(line, true)
+    }
+
+    /// Find the word (or subword) boundaries
+    fn boundaries(&self, line: &str, subwords: bool) -> Vec<(usize, usize)> {
+        let chars: Vec<(usize, char)> = line.char_indices().collect();
+        let kind = |c: char| match self.char_kind(c) {
+            CharKind::Word if subwords && c == '_' => CharKind::Separator,
+            kind => kind,
+        };
+        let byte_at = |i: usize| chars.get(i).map_or(line.len(), |(b, _)| *b);
+        let mut result = vec![];
+        let mut i = 0;
+        while i < chars.len() {
+            let (start, c) = chars[i];
+            let mut end = i + 1;
+            match kind(c) {
+                CharKind::Whitespace => {
+                    while end < chars.len() && kind(chars[end].1) == CharKind::Whitespace {
+                        end += 1;
+                    }
+                    if end - i >= 2 {
+                        result.push((start, byte_at(end)));
+                    }
+                }
+                CharKind::Separator if c == '.' => result.push((start, byte_at(end))),
+                CharKind::Separator => (),
+                CharKind::Word => {
+                    while end < chars.len() && kind(chars[end].1) == CharKind::Word {
+                        let next = chars.get(end + 1).map(|(_, c)| *c);
+                        if subwords && subword_break(chars[end - 1].1, chars[end].1, next) {
+                            break;
+                        }
+                        end += 1;
+                    }
+                    result.push((start, byte_at(end)));
+                }
+            }
+            i = end;
+        }
+        result
     }
 
     /// Find the current state of the cursor in relation to words
@@ -79,12 +214,23 @@ impl Document {
         }
     }
 
-    /// Find the index of the next word
+    /// Find the index of the previous word
     #[must_use]
     pub fn prev_word_index(&self, from: Loc) -> usize {
+        self.prev_stop(from, false)
+    }
+
+    /// Find the index of the previous subword
+    #[must_use]
+    pub fn prev_subword_index(&self, from: Loc) -> usize {
+        self.prev_stop(from, true)
+    }
+
+    /// Find where moving back by a word (or subword) stops
+    fn prev_stop(&self, from: Loc, subwords: bool) -> usize {
         let Loc { x, y } = from;
         let line = self.line(y).unwrap_or_default();
-        let words = self.word_boundaries(&line);
+        let words = self.boundaries(&line, subwords);
         let state = self.cursor_word_state(&line, &words, x);
         match state {
             // Go to start of line if at beginning
@@ -113,13 +259,23 @@ impl Document {
 
     /// Moves to the previous word in the document
     pub fn move_prev_word(&mut self) -> Status {
+        self.move_back(false)
+    }
+
+    /// Moves to the previous subword in the document
+    pub fn move_prev_subword(&mut self) -> Status {
+        self.move_back(true)
+    }
+
+    /// Move back by a word (or subword)
+    fn move_back(&mut self, subwords: bool) -> Status {
         let Loc { x, y } = self.char_loc();
         // Handle case where we're at the beginning of the line
         if x == 0 && y != 0 {
             return Status::StartOfLine;
         }
         // Work out where to move to
-        let new_x = self.prev_word_index(self.char_loc());
+        let new_x = self.prev_stop(self.char_loc(), subwords);
         // Perform the move
         self.move_to_x(new_x);
         // Clean up
@@ -173,9 +329,20 @@ impl Document {
     /// Find the index of the next word
     #[must_use]
     pub fn next_word_index(&self, from: Loc) -> usize {
+        self.next_stop(from, false)
+    }
+
+    /// Find the index of the next subword
+    #[must_use]
+    pub fn next_subword_index(&self, from: Loc) -> usize {
+        self.next_stop(from, true)
+    }
+
+    /// Find where moving forward by a word (or subword) stops
+    fn next_stop(&self, from: Loc, subwords: bool) -> usize {
         let Loc { x, y } = from;
         let line = self.line(y).unwrap_or_default();
-        let words = self.word_boundaries(&line);
+        let words = self.boundaries(&line, subwords);
         let state = self.cursor_word_state(&line, &words, x);
         match state {
             // Cursor is at the middle / end of a word, move to next end
@@ -215,6 +382,16 @@ impl Document {
 
     /// Moves to the next word in the document
     pub fn move_next_word(&mut self) -> Status {
+        self.move_forward(false)
+    }
+
+    /// Moves to the next subword in the document
+    pub fn move_next_subword(&mut self) -> Status {
+        self.move_forward(true)
+    }
+
+    /// Move forward by a word (or subword)
+    fn move_forward(&mut self, subwords: bool) -> Status {
         let Loc { x, y } = self.char_loc();
         let line = self.line(y).unwrap_or_default();
         // Handle case where we're at the end of the line
@@ -222,7 +399,7 @@ impl Document {
             return Status::EndOfLine;
         }
         // Work out where to move to
-        let new_x = self.next_word_index(self.char_loc());
+        let new_x = self.next_stop(self.char_loc(), subwords);
         // Perform the move
         self.move_to_x(new_x);
         // Clean up
@@ -234,9 +411,21 @@ impl Document {
     /// # Errors
     /// Errors if out of range
     pub fn delete_word(&mut self) -> Result<()> {
+        self.delete_back(false)
+    }
+
+    /// Delete back to the start of the subword before the cursor
+    /// # Errors
+    /// Errors if out of range
+    pub fn delete_subword(&mut self) -> Result<()> {
+        self.delete_back(true)
+    }
+
+    /// Delete back by a word (or subword)
+    fn delete_back(&mut self, subwords: bool) -> Result<()> {
         let Loc { x, y } = self.char_loc();
         let line = self.line(y).unwrap_or_default();
-        let words = self.word_boundaries(&line);
+        let words = self.boundaries(&line, subwords);
         let state = self.cursor_word_state(&line, &words, x);
         let delete_upto = match state {
             WordState::InCenter(idx) | WordState::AtEnd(idx) => {
@@ -277,27 +466,20 @@ impl Document {
     pub fn select_word_at(&mut self, loc: &Loc) {
         let y = loc.y;
         let x = self.character_idx(loc);
-        let re = format!("(\t| {{{}}}|^|\\W| )", self.tab_width);
-        let start = if let Some(mut mtch) = self.prev_match(&re) {
-            let len = mtch.text.chars().count();
-            let same = mtch.loc.x + len == x;
-            if !same {
-                mtch.loc.x += len;
-            }
-            self.move_to(&mtch.loc);
-            if same && self.loc().x != 0 {
-                self.move_prev_word();
-            }
-            mtch.loc.x
-        } else {
-            0
-        };
-        let re = format!("(\t| {{{}}}|\\W|$|^ +| )", self.tab_width);
-        let end = if let Some(mtch) = self.next_match(&re, 0) {
-            mtch.loc.x
-        } else {
-            self.line(y).unwrap_or_default().chars().count()
-        };
+        let line = self.line(y).unwrap_or_default();
+        let byte_x = Searcher::char_to_raw(x, &line);
+        // The word the location is in (or just after), leaving out runs of whitespace
+        let word = self
+            .word_boundaries(&line)
+            .into_iter()
+            .filter(|(start, _)| !line[*start..].starts_with(char::is_whitespace))
+            .find(|(start, end)| *start <= byte_x && byte_x <= *end);
+        let (start, end) = word.map_or((x, x), |(start, end)| {
+            (
+                Searcher::raw_to_char(start, &line),
+                Searcher::raw_to_char(end, &line),
+            )
+        });
         self.move_to(&Loc { x: start, y });
         self.select_to(&Loc { x: end, y });
         self.old_cursor = self.loc().x;
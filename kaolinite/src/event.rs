@@ -1,13 +1,17 @@
 /// event.rs - manages editing events and provides tools for error handling
-use crate::{document::Cursor, utils::Loc, Document};
+use crate::document::{Cursor, LineEnding};
+use crate::{utils::Loc, Document};
+use bincode::Options;
 use error_set::error_set;
 use ropey::Rope;
+use serde::{Deserialize, Serialize};
 
 /// A snapshot stores the state of a document at a certain time
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Snapshot {
     pub content: Rope,
     pub cursor: Cursor,
+    pub line_ending: LineEnding,
 }
 
 /// Represents an editing event.
@@ -85,23 +89,266 @@ error_set! {
         Rope(ropey::Error),
         NoFileName,
         OutOfRange,
-        ReadOnlyFile
+        ReadOnlyFile,
+        Unrepresentable,
+        UnknownEncoding,
+        BinaryPreview
     };
 }
 
-/// For managing events for purposes of undo and redo
+/// A change between two committed states of a document, which can be applied in either direction
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Patch {
+    /// The character index where the change starts
+    pub at: usize,
+    /// The text that was there before the change
+    pub removed: String,
+    /// The text that is there after the change
+    pub inserted: String,
+    /// The cursor before and after the change
+    pub cursor: (Cursor, Cursor),
+    /// The line ending before and after the change
+    pub line_ending: (LineEnding, LineEnding),
+}
+
+impl Patch {
+    /// Work out the patch that turns one snapshot into another
+    #[must_use]
+    pub fn between(old: &Snapshot, new: &Snapshot) -> Self {
+        let (a, b) = (&old.content, &new.content);
+        // Find how much is shared at the start and end of both versions
+        let prefix = common_prefix(a.chunks(), b.chunks());
+        let shortest = a.len_bytes().min(b.len_bytes());
+        let suffix = common_suffix(a, b).min(shortest - prefix);
+        // Round outwards onto character boundaries
+        let at = a.byte_to_char(prefix);
+        let end_byte = a.len_bytes() - suffix;
+        let mut end = a.byte_to_char(end_byte);
+        if a.char_to_byte(end) != end_byte {
+            end += 1;
+        }
+        let suffix_chars = a.len_chars() - end;
+        Self {
+            at,
+            removed: a.slice(at..end).to_string(),
+            inserted: b.slice(at..b.len_chars() - suffix_chars).to_string(),
+            cursor: (old.cursor, new.cursor),
+            line_ending: (old.line_ending, new.line_ending),
+        }
+    }
+
+    /// Determine if this patch doesn't change the content at all
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.removed == self.inserted
+    }
+
+    /// Apply this patch to a snapshot (forwards or backwards)
+    #[must_use]
+    pub fn apply(&self, snapshot: &Snapshot, forward: bool) -> Snapshot {
+        let (from, to, cursor, line_ending) = if forward {
+            (
+                &self.removed,
+                &self.inserted,
+                self.cursor.1,
+                self.line_ending.1,
+            )
+        } else {
+            (
+                &self.inserted,
+                &self.removed,
+                self.cursor.0,
+                self.line_ending.0,
+            )
+        };
+        let mut content = snapshot.content.clone();
+        content.remove(self.at..self.at + from.chars().count());
+        content.insert(self.at, to);
+        Snapshot {
+            content,
+            cursor,
+            line_ending,
+        }
+    }
+}
+
+/// Marks a stored undo history (bump the version when the format changes)
+const HISTORY_FORMAT: &str = "ox undo history v2";
+
+/// An undo history as it is stored between sessions
+#[derive(Serialize, Deserialize)]
+struct SavedHistory {
+    format: String,
+    /// A hash of the contents the history was saved against (it only applies to them)
+    hash: u64,
+    patches: Vec<Patch>,
+}
+
+/// How undo histories are encoded (anything left over after one is treated as corruption)
+fn history_options() -> impl bincode::Options {
+    bincode::DefaultOptions::new().reject_trailing_bytes()
+}
+
+/// Work out a hash of some text, this is stable between versions and platforms
+#[must_use]
+pub fn stable_hash<'a>(chunks: impl IntoIterator<Item = &'a str>) -> u64 {
+    let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+    for chunk in chunks {
+        for byte in chunk.bytes() {
+            hash ^= u64::from(byte);
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+    hash
+}
+
+/// Work out a hash of the contents of a document
+#[must_use]
+pub fn content_hash(content: &Rope) -> u64 {
+    stable_hash(content.chunks())
+}
+
+/// Describes an edit that has just been undone or redone
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Change {
+    /// Where the cursor should land (the end of the affected text in the document as it is now)
+    pub loc: Loc,
+    /// How many characters the original edit removed
+    pub removed: usize,
+    /// How many characters the original edit inserted
+    pub inserted: usize,
+}
+
+impl Change {
+    /// Work out the change made by applying a patch, given the resulting content
+    fn new(patch: &Patch, content: &Rope, forward: bool) -> Self {
+        let removed = patch.removed.chars().count();
+        let inserted = patch.inserted.chars().count();
+        let end = patch.at + if forward { inserted } else { removed };
+        let end = end.min(content.len_chars());
+        let y = content.char_to_line(end);
+        let x = end - content.line_to_char(y);
+        Self {
+            loc: Loc { y, x },
+            removed,
+            inserted,
+        }
+    }
+
+    /// Describe the original edit (e.g. "insertion of 12 characters on line 42")
+    #[must_use]
+    pub fn describe(&self) -> String {
+        let plural = |n: usize| if n == 1 { "" } else { "s" };
+        let (removed, inserted) = (self.removed, self.inserted);
+        let edit = if removed == 0 {
+            format!("insertion of {inserted} character{}", plural(inserted))
+        } else if inserted == 0 {
+            format!("deletion of {removed} character{}", plural(removed))
+        } else {
+            format!(
+                "replacement of {removed} character{} with {inserted}",
+                plural(removed)
+            )
+        };
+        format!("{edit} on line {}", self.loc.y + 1)
+    }
+}
+
+/// Count how many bytes two sequences of chunks share at the start
+fn common_prefix<'a>(
+    mut a: impl Iterator<Item = &'a str>,
+    mut b: impl Iterator<Item = &'a str>,
+) -> usize {
+    let (mut ca, mut cb): (&[u8], &[u8]) = (&[], &[]);
+    let mut total = 0;
+    loop {
+        if ca.is_empty() {
+            let Some(chunk) = a.next() else {
+                return total;
+            };
+            ca = chunk.as_bytes();
+            continue;
+        }
+        if cb.is_empty() {
+            let Some(chunk) = b.next() else {
+                return total;
+            };
+            cb = chunk.as_bytes();
+            continue;
+        }
+        let n = ca.len().min(cb.len());
+        let same = ca[..n]
+            .iter()
+            .zip(&cb[..n])
+            .take_while(|(x, y)| x == y)
+            .count();
+        total += same;
+        if same < n {
+            return total;
+        }
+        ca = &ca[n..];
+        cb = &cb[n..];
+    }
+}
+
+/// Count how many bytes two ropes share at the end
+fn common_suffix(a: &Rope, b: &Rope) -> usize {
+    let mut a = a.chunks_at_byte(a.len_bytes()).0.reversed();
+    let mut b = b.chunks_at_byte(b.len_bytes()).0.reversed();
+    let (mut ca, mut cb): (&[u8], &[u8]) = (&[], &[]);
+    let mut total = 0;
+    loop {
+        if ca.is_empty() {
+            let Some(chunk) = a.next() else {
+                return total;
+            };
+            ca = chunk.as_bytes();
+            continue;
+        }
+        if cb.is_empty() {
+            let Some(chunk) = b.next() else {
+                return total;
+            };
+            cb = chunk.as_bytes();
+            continue;
+        }
+        let n = ca.len().min(cb.len());
+        let (ta, tb) = (&ca[ca.len() - n..], &cb[cb.len() - n..]);
+        let same = ta
+            .iter()
+            .rev()
+            .zip(tb.iter().rev())
+            .take_while(|(x, y)| x == y)
+            .count();
+        total += same;
+        if same < n {
+            return total;
+        }
+        ca = &ca[..ca.len() - n];
+        cb = &cb[..cb.len() - n];
+    }
+}
+
+/// For managing events for purposes of undo and redo.
+/// Only the changes between commits are kept, rather than copies of the whole document
 #[derive(Default, Debug, Clone, PartialEq, Eq)]
 pub struct EventMgmt {
-    /// Contains all the snapshots in the current timeline
-    pub history: Vec<Snapshot>,
-    /// Stores where the document currently is
-    pub ptr: Option<usize>,
-    /// Store where the file on the disk is currently at
-    pub on_disk: Option<usize>,
+    /// Contains all the patches in the current timeline
+    pub history: Vec<Patch>,
+    /// Stores how many patches in the history are currently applied
+    pub ptr: usize,
+    /// The state of the document as of the last commit (None if nothing has been committed)
+    pub current: Option<Snapshot>,
+    /// Store the contents of the file on the disk
+    pub on_disk: Option<Rope>,
     /// Store the last event to occur (so that we can see if there is a change)
     pub last_event: Option<Event>,
     /// Flag to force the file not to be with disk (i.e. file only exists in memory)
     pub force_not_with_disk: bool,
+    /// The maximum number of patches to keep (0 means there is no limit)
+    pub limit: usize,
+    /// How many batches of edits are open (commits wait until they have all been closed)
+    pub batch: usize,
 }
 
 impl Document {
@@ -110,34 +357,127 @@ impl Document {
         Snapshot {
             content: self.file.clone(),
             cursor: self.cursor,
+            line_ending: self.info.line_ending,
         }
     }
 
     pub fn apply_snapshot(&mut self, snapshot: Snapshot) {
         self.file = snapshot.content;
         self.cursor = snapshot.cursor;
+        self.info.line_ending = snapshot.line_ending;
         self.char_ptr = self.character_idx(&snapshot.cursor.loc);
         self.reload_lines();
+        self.reanchor_marks(false);
+        self.changes.invalidate(self.file.len_lines());
         self.bring_cursor_in_viewport();
     }
+
+    /// Write out the undo history so that it can be restored in a later session.
+    /// The oldest changes are left out to keep it within a certain number of bytes
+    /// and changes that have been undone aren't kept
+    #[must_use]
+    pub fn save_history(&self, max_bytes: usize) -> Vec<u8> {
+        let mgmt = &self.event_mgmt;
+        let content = mgmt.current.as_ref().map_or(&self.file, |s| &s.content);
+        let mut patches = vec![];
+        let mut total = 0;
+        for patch in mgmt.history[..mgmt.ptr].iter().rev() {
+            total += history_options()
+                .serialized_size(patch)
+                .map_or(usize::MAX, |size| {
+                    usize::try_from(size).unwrap_or(usize::MAX)
+                });
+            if total > max_bytes {
+                break;
+            }
+            patches.push(patch.clone());
+        }
+        patches.reverse();
+        let saved = SavedHistory {
+            format: HISTORY_FORMAT.to_string(),
+            hash: content_hash(content),
+            patches,
+        };
+        history_options().serialize(&saved).unwrap_or_default()
+    }
+
+    /// Restore an undo history written by `save_history`. Histories that are corrupt, from
+    /// another version or that were saved against different contents are ignored.
+    /// Returns true if the history was restored
+    pub fn load_history(&mut self, data: &[u8]) -> bool {
+        let Ok(saved) = history_options().deserialize::<SavedHistory>(data) else {
+            return false;
+        };
+        if saved.format != HISTORY_FORMAT || saved.hash != content_hash(&self.file) {
+            return false;
+        }
+        let mut history = saved.patches;
+        // Make sure every patch can be undone, all the way back to the oldest
+        let snapshot = self.take_snapshot();
+        let mut state = snapshot.clone();
+        for patch in history.iter().rev() {
+            let end = patch.at + patch.inserted.chars().count();
+            let fits = end <= state.content.len_chars();
+            if !fits || state.content.slice(patch.at..end) != patch.inserted.as_str() {
+                return false;
+            }
+            state = patch.apply(&state, false);
+        }
+        let mgmt = &mut self.event_mgmt;
+        if mgmt.limit != 0 && history.len() > mgmt.limit {
+            history.drain(..history.len() - mgmt.limit);
+        }
+        mgmt.ptr = history.len();
+        mgmt.history = history;
+        if mgmt.on_disk.is_none() {
+            mgmt.on_disk = Some(snapshot.content.clone());
+        }
+        mgmt.current = Some(snapshot);
+        true
+    }
+
+    /// Put the cursor where an undone or redone edit happened,
+    /// recentring the view on it if it is out of sight
+    pub fn land_on(&mut self, change: &Change) {
+        let y = change.loc.y;
+        let visible = self.offset.y <= y && y < self.offset.y + self.size.h;
+        self.load_to(y + 1);
+        if !visible {
+            self.offset.y = y.saturating_sub(self.size.h / 2);
+        }
+        self.move_to(&change.loc);
+    }
 }
 
 impl EventMgmt {
     /// In the event of some changes, redo should be cleared
     pub fn clear_redo(&mut self) {
-        if let Some(ptr) = self.ptr {
-            self.history.drain(ptr + 1..);
-        }
+        self.history.truncate(self.ptr);
     }
 
     /// To be called when a snapshot needs to be registered
     pub fn commit(&mut self, snapshot: Snapshot) {
+        let Some(current) = &self.current else {
+            // The first commit is where the history starts from
+            if self.on_disk.is_none() {
+                self.on_disk = Some(snapshot.content.clone());
+            }
+            self.current = Some(snapshot);
+            return;
+        };
         // Only commit when previous snapshot differs
-        let ptr = self.ptr.unwrap_or(0);
-        if self.history.get(ptr).map(|s| &s.content) != Some(&snapshot.content) {
+        let patch = Patch::between(current, &snapshot);
+        if !patch.is_empty() {
             self.clear_redo();
-            self.history.push(snapshot);
-            self.ptr = Some(self.history.len().saturating_sub(1));
+            self.history.push(patch);
+            self.ptr = self.history.len();
+            // Forget the oldest changes when there are too many
+            if self.limit != 0 && self.history.len() > self.limit {
+                let excess = self.history.len() - self.limit;
+                self.history.drain(..excess);
+                self.ptr -= excess;
+            }
+            self.current = Some(snapshot);
         }
     }
 
@@ -145,7 +485,7 @@ impl EventMgmt {
     pub fn disk_write(&mut self, snapshot: &Snapshot) {
         self.force_not_with_disk = false;
         self.commit(snapshot.clone());
-        self.on_disk = self.ptr;
+        self.on_disk = Some(snapshot.content.clone());
     }
 
     /// A way to query whether we're currently up to date with the disk
@@ -153,49 +493,40 @@ impl EventMgmt {
     pub fn with_disk(&self, snapshot: &Snapshot) -> bool {
         if self.force_not_with_disk {
             false
-        } else if let Some(disk) = self.on_disk {
-            self.history.get(disk).map(|s| &s.content) == Some(&snapshot.content)
-        } else if self.history.is_empty() {
-            true
+        } else if let Some(disk) = &self.on_disk {
+            disk == &snapshot.content
         } else {
-            self.history.first().map(|s| &s.content) == Some(&snapshot.content)
+            true
         }
     }
 
     /// Get previous snapshot to restore to
-    pub fn undo(&mut self, snapshot: Snapshot) -> Option<Snapshot> {
+    pub fn undo(&mut self, snapshot: Snapshot) -> Option<(Snapshot, Change)> {
         // Push cursor back by 1
         self.commit(snapshot);
-        if let Some(ptr) = self.ptr {
-            if ptr != 0 {
-                let new_ptr = ptr.saturating_sub(1);
-                self.ptr = Some(new_ptr);
-                self.history.get(new_ptr).cloned()
-            } else {
-                None
-            }
-        } else {
-            None
+        if self.ptr == 0 {
+            return None;
         }
+        self.ptr -= 1;
+        let patch = &self.history[self.ptr];
+        let previous = patch.apply(self.current.as_ref()?, false);
+        let change = Change::new(patch, &previous.content, false);
+        self.current = Some(previous.clone());
+        Some((previous, change))
     }
 
     /// Get snapshot that used to be in place
-    pub fn redo(&mut self, snapshot: &Snapshot) -> Option<Snapshot> {
-        if let Some(ptr) = self.ptr {
-            // If the user has edited since the undo, wipe the redo stack
-            if self.history.get(ptr).map(|s| &s.content) != Some(&snapshot.content) {
-                self.clear_redo();
-            }
-            // Perform the redo
-            let new_ptr = if ptr + 1 < self.history.len() {
-                ptr + 1
-            } else {
-                return None;
-            };
-            self.ptr = Some(new_ptr);
-            self.history.get(new_ptr).cloned()
-        } else {
-            None
+    pub fn redo(&mut self, snapshot: &Snapshot) -> Option<(Snapshot, Change)> {
+        // If the user has edited since the undo, wipe the redo stack
+        if self.current.as_ref()?.content != snapshot.content {
+            self.clear_redo();
         }
+        // Perform the redo
+        let patch = self.history.get(self.ptr)?;
+        let next = patch.apply(self.current.as_ref()?, true);
+        let change = Change::new(patch, &next.content, true);
+        self.ptr += 1;
+        self.current = Some(next.clone());
+        Some((next, change))
     }
 }
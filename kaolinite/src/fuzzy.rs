@@ -0,0 +1,286 @@
+/// fuzzy.rs - utilities for fuzzy matching short patterns against paths
+use std::cmp::Ordering;
+use std::ops::Range;
+
+/// Score for each character of the pattern that is matched
+const SCORE_MATCH: i64 = 16;
+/// Bonus for a match at the start of a word (after a separator or a lower to upper case change)
+const BONUS_BOUNDARY: i64 = 10;
+/// Bonus for a match directly after the previous one
+const BONUS_CONSECUTIVE: i64 = 8;
+/// Bonus for a match in the last component of a path (the file name)
+const BONUS_FILE_NAME: i64 = 4;
+/// Penalty for each character skipped over in between the first and last match
+const PENALTY_GAP: i64 = 2;
+/// The most candidates that will be looked at for a single pattern
+pub const MAX_SCANNED: usize = 200_000;
+
+/// Stores information about a fuzzy match
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct FuzzyMatch {
+    /// The higher the score, the better the match
+    pub score: i64,
+    /// Character indices of the candidate that the pattern matched
+    pub indices: Vec<usize>,
+}
+
+/// Work out whether a pattern character matches a candidate character
+fn matches(pattern: char, candidate: char, case_sensitive: bool) -> bool {
+    if pattern == candidate {
+        true
+    } else if case_sensitive {
+        false
+    } else if pattern.is_ascii() && candidate.is_ascii() {
+        pattern.eq_ignore_ascii_case(&candidate)
+    } else {
+        candidate.to_lowercase().eq(pattern.to_lowercase())
+    }
+}
+
+/// Patterns are only case sensitive if they contain an upper case letter
+fn is_case_sensitive(pattern: &str) -> bool {
+    pattern.chars().any(char::is_uppercase)
+}
+
+/// Work out whether a character of a candidate starts a new word
+fn is_boundary(prev: Option<char>, c: char) -> bool {
+    match prev {
+        None => true,
+        Some(prev) => {
+            matches!(prev, '/' | '\\' | '_' | '-' | '.' | ' ')
+                || (prev.is_lowercase() && c.is_uppercase())
+        }
+    }
+}
+
+/// Quickly check that every character of a pattern appears in a candidate in order
+#[must_use]
+pub fn is_subsequence(pattern: &str, candidate: &str) -> bool {
+    Matcher::new(pattern).is_subsequence(candidate)
+}
+
+/// Fuzzy match a pattern against a candidate, returns None if the candidate doesn't match.
+/// Matches that are close together, start words or sit in the file name score highest
+#[must_use]
+pub fn fuzzy_match(pattern: &str, candidate: &str) -> Option<FuzzyMatch> {
+    let mut matcher = Matcher::new(pattern);
+    let score = matcher.score(candidate)?;
+    Some(FuzzyMatch {
+        score,
+        indices: matcher.indices,
+    })
+}
+
+/// Matches one pattern against many candidates, reusing its buffers between them
+struct Matcher {
+    pattern: Vec<char>,
+    case_sensitive: bool,
+    chars: Vec<char>,
+    indices: Vec<usize>,
+}
+
+impl Matcher {
+    fn new(pattern: &str) -> Self {
+        Self {
+            pattern: pattern.chars().collect(),
+            case_sensitive: is_case_sensitive(pattern),
+            chars: vec![],
+            indices: vec![],
+        }
+    }
+
+    /// Check that every character of the pattern appears in a candidate in order
+    fn is_subsequence(&self, candidate: &str) -> bool {
+        let mut pattern = self.pattern.iter().peekable();
+        for c in candidate.chars() {
+            match pattern.peek() {
+                Some(p) if matches(**p, c, self.case_sensitive) => {
+                    pattern.next();
+                }
+                Some(_) => (),
+                None => break,
+            }
+        }
+        pattern.peek().is_none()
+    }
+
+    /// Score a candidate, leaving the matched character indices in `self.indices`
+    fn score(&mut self, candidate: &str) -> Option<i64> {
+        self.indices.clear();
+        if self.pattern.is_empty() {
+            return Some(0);
+        }
+        // Bail out early on candidates that can't match
+        if !self.is_subsequence(candidate) {
+            return None;
+        }
+        let (pattern, case_sensitive) = (&self.pattern, self.case_sensitive);
+        self.chars.clear();
+        self.chars.extend(candidate.chars());
+        let chars = &self.chars;
+        // Find where the earliest complete match ends
+        let mut p = 0;
+        let mut end = 0;
+        for (i, c) in chars.iter().enumerate() {
+            if matches(pattern[p], *c, case_sensitive) {
+                p += 1;
+                if p == pattern.len() {
+                    end = i;
+                    break;
+                }
+            }
+        }
+        // Work backwards from there to find the tightest window holding the match
+        let mut start = end;
+        for i in (0..=end).rev() {
+            if matches(pattern[p - 1], chars[i], case_sensitive) {
+                p -= 1;
+                if p == 0 {
+                    start = i;
+                    break;
+                }
+            }
+        }
+        // Pick out the characters within the window, preferring word starts where possible
+        let indices = &mut self.indices;
+        let mut at = start;
+        for (n, p) in pattern.iter().enumerate() {
+            // Don't let a jump to a word start leave too few characters for the rest of the pattern
+            let remaining = &pattern[n + 1..];
+            let mut pick = None;
+            for (i, c) in chars.iter().enumerate().take(end + 1).skip(at) {
+                if !matches(*p, *c, case_sensitive) {
+                    continue;
+                }
+                if pick.is_none() {
+                    pick = Some(i);
+                }
+                let prev = i.checked_sub(1).map(|i| chars[i]);
+                if is_boundary(prev, *c) && fits(remaining, &chars[i + 1..=end], case_sensitive) {
+                    pick = Some(i);
+                    break;
+                }
+                if indices.last().is_some_and(|last| last + 1 == i) {
+                    break;
+                }
+            }
+            let pick = pick?;
+            indices.push(pick);
+            at = pick + 1;
+        }
+        // Score the chosen characters
+        let file_name = chars
+            .iter()
+            .rposition(|c| matches!(c, '/' | '\\'))
+            .map_or(0, |i| i + 1);
+        let mut score = 0;
+        for (n, i) in indices.iter().enumerate() {
+            score += SCORE_MATCH;
+            let prev = i.checked_sub(1).map(|i| chars[i]);
+            if is_boundary(prev, chars[*i]) {
+                score += BONUS_BOUNDARY;
+            }
+            if n > 0 && indices[n - 1] + 1 == *i {
+                score += BONUS_CONSECUTIVE;
+            }
+            if *i >= file_name {
+                score += BONUS_FILE_NAME;
+            }
+        }
+        let span = indices.last()? - indices.first()? + 1;
+        score -= PENALTY_GAP * i64::try_from(span - indices.len()).unwrap_or(0);
+        Some(score)
+    }
+}
+
+/// Check that a pattern can still be matched within some characters
+fn fits(pattern: &[char], chars: &[char], case_sensitive: bool) -> bool {
+    let mut pattern = pattern.iter().peekable();
+    for c in chars {
+        if pattern
+            .peek()
+            .is_some_and(|p| matches(**p, *c, case_sensitive))
+        {
+            pattern.next();
+        }
+    }
+    pattern.peek().is_none()
+}
+
+/// Turn the character indices of a match into ranges, so they can be highlighted in one go
+#[must_use]
+pub fn highlight_spans(indices: &[usize]) -> Vec<Range<usize>> {
+    let mut result: Vec<Range<usize>> = vec![];
+    for i in indices {
+        match result.last_mut() {
+            Some(span) if span.end == *i => span.end += 1,
+            _ => result.push(*i..*i + 1),
+        }
+    }
+    result
+}
+
+/// Order two matches, best first (ties go to the shorter candidate, then the earlier one)
+/// (each is the index of the candidate, its length and its score)
+fn compare(a: (usize, usize, i64), b: (usize, usize, i64)) -> Ordering {
+    b.2.cmp(&a.2).then(a.1.cmp(&b.1)).then(a.0.cmp(&b.0))
+}
+
+/// Ranks a large list of candidates against a pattern as it is typed out.
+/// When the pattern is extended, only the candidates that matched last time are looked at again
+#[derive(Debug, Default, Clone)]
+pub struct FuzzyRanker {
+    pattern: String,
+    /// Indices of the candidates that matched the last pattern
+    hits: Option<Vec<usize>>,
+}
+
+impl FuzzyRanker {
+    /// Forget about previous patterns (for when the candidates change)
+    pub fn reset(&mut self) {
+        self.pattern.clear();
+        self.hits = None;
+    }
+
+    /// Find the best matches for a pattern, returning the index of each candidate and its match
+    pub fn rank<S: AsRef<str>>(
+        &mut self,
+        pattern: &str,
+        candidates: &[S],
+        limit: usize,
+    ) -> Vec<(usize, FuzzyMatch)> {
+        let narrowing = self.hits.is_some() && pattern.starts_with(&self.pattern);
+        let scan: Box<dyn Iterator<Item = usize>> = match self.hits.take() {
+            Some(hits) if narrowing => Box::new(hits.into_iter()),
+            _ => Box::new(0..candidates.len()),
+        };
+        let mut matcher = Matcher::new(pattern);
+        let mut hits = vec![];
+        let mut best: Vec<(usize, usize, FuzzyMatch)> = Vec::with_capacity(limit + 1);
+        let mut scanned = 0;
+        for idx in scan.take(MAX_SCANNED) {
+            scanned += 1;
+            let Some(candidate) = candidates.get(idx).map(AsRef::as_ref) else {
+                continue;
+            };
+            let Some(score) = matcher.score(candidate) else {
+                continue;
+            };
+            hits.push(idx);
+            // Only keep hold of the match if it makes the cut
+            let entry = (idx, candidate.len(), score);
+            let at = best
+                .binary_search_by(|other| compare((other.0, other.1, other.2.score), entry))
+                .unwrap_or_else(|at| at);
+            if at < limit {
+                let indices = matcher.indices.clone();
+                best.insert(at, (idx, candidate.len(), FuzzyMatch { score, indices }));
+                best.truncate(limit);
+            }
+        }
+        // Narrowing down a scan that was cut short would lose candidates that were never looked at
+        self.pattern = pattern.to_string();
+        self.hits = (scanned < MAX_SCANNED).then_some(hits);
+        best.into_iter().map(|(idx, _, m)| (idx, m)).collect()
+    }
+}
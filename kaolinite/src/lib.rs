@@ -14,6 +14,8 @@
 //! - Searching & Replacing
 //! - Handles tabs, different line endings and double width characters perfectly
 //! - File buffering for larger files
+//! - Spell checking against Hunspell dictionaries or word lists
+//! - Rendering markdown into styled lines for previews
 //!
 //! It removes a lot of complexity from your text editor and allows the creation of an advanced
 //! text editor in very few lines of idiomatic code.
@@ -26,10 +28,16 @@
 
 #![warn(clippy::all, clippy::pedantic)]
 #![allow(clippy::module_name_repetitions)]
+pub mod backups;
+pub mod diff;
 pub mod document;
 pub mod event;
+pub mod fuzzy;
 pub mod map;
+pub mod markdown;
 pub mod searching;
+pub mod signs;
+pub mod spelling;
 pub mod utils;
 
 pub use document::Document;
@@ -0,0 +1,386 @@
+/// markdown.rs - for turning markdown into lines of styled text that fit a terminal
+use crate::utils::width;
+
+/// How text within a line is styled
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct Style {
+    pub strong: bool,
+    pub emphasis: bool,
+    pub code: bool,
+    pub link: bool,
+}
+
+/// What kind of block a line is part of
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LineKind {
+    Text,
+    /// A heading, along with its level (1 to 6)
+    Heading(usize),
+    Quote,
+    Rule,
+    /// A line of a code block, along with the language given for the block (if any)
+    Code(Option<String>),
+    Blank,
+}
+
+/// A line of the rendered document
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Line {
+    pub kind: LineKind,
+    pub spans: Vec<(String, Style)>,
+    /// The line of the markdown this came from (starting from 0)
+    pub source: usize,
+}
+
+impl Line {
+    /// Create a line with no styling
+    fn plain(kind: LineKind, text: String, source: usize) -> Self {
+        Self {
+            kind,
+            spans: vec![(text, Style::default())],
+            source,
+        }
+    }
+
+    /// The text of the line, without any styling
+    #[must_use]
+    pub fn text(&self) -> String {
+        self.spans.iter().map(|(text, _)| text.as_str()).collect()
+    }
+}
+
+/// Find the end of a span of code or a link part, starting the search after `from`
+fn find_from(chars: &[char], from: usize, target: char) -> Option<usize> {
+    chars
+        .iter()
+        .skip(from)
+        .position(|c| *c == target)
+        .map(|p| p + from)
+}
+
+/// Split the inline markup of some text (emphasis, code and links) into styled spans
+#[must_use]
+pub fn inline(text: &str) -> Vec<(String, Style)> {
+    let chars: Vec<char> = text.chars().collect();
+    let mut result: Vec<(String, Style)> = vec![];
+    let mut style = Style::default();
+    let push = |result: &mut Vec<(String, Style)>, text: &str, style: Style| match result.last_mut()
+    {
+        Some((last, last_style)) if *last_style == style => last.push_str(text),
+        _ if text.is_empty() => (),
+        _ => result.push((text.to_string(), style)),
+    };
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+        let before = i.checked_sub(1).map(|b| chars[b]);
+        match c {
+            '\\' if next.is_some_and(|n| n.is_ascii_punctuation()) => {
+                if let Some(escaped) = next {
+                    push(&mut result, &escaped.to_string(), style);
+                }
+                i += 2;
+            }
+            '`' => {
+                if let Some(end) = find_from(&chars, i + 1, '`') {
+                    let code: String = chars[i + 1..end].iter().collect();
+                    push(
+                        &mut result,
+                        &code,
+                        Style {
+                            code: true,
+                            ..style
+                        },
+                    );
+                    i = end + 1;
+                } else {
+                    push(&mut result, "`", style);
+                    i += 1;
+                }
+            }
+            '!' | '[' if c == '[' || next == Some('[') => {
+                // Links (and images, where the description is shown) keep their text
+                let open = if c == '!' { i + 1 } else { i };
+                let link = find_from(&chars, open + 1, ']').and_then(|close| {
+                    (chars.get(close + 1) == Some(&'('))
+                        .then(|| find_from(&chars, close + 2, ')'))
+                        .flatten()
+                        .map(|end| (close, end))
+                });
+                if let Some((close, end)) = link {
+                    let text: String = chars[open + 1..close].iter().collect();
+                    push(
+                        &mut result,
+                        &text,
+                        Style {
+                            link: true,
+                            ..style
+                        },
+                    );
+                    i = end + 1;
+                } else {
+                    push(&mut result, &c.to_string(), style);
+                    i += 1;
+                }
+            }
+            '*' | '_' if next == Some(c) => {
+                style.strong = !style.strong;
+                i += 2;
+            }
+            // Underscores inside words (like snake_case) are left alone
+            '_' if before.is_some_and(char::is_alphanumeric)
+                && next.is_some_and(char::is_alphanumeric) =>
+            {
+                push(&mut result, "_", style);
+                i += 1;
+            }
+            '*' | '_' => {
+                style.emphasis = !style.emphasis;
+                i += 1;
+            }
+            _ => {
+                push(&mut result, &c.to_string(), style);
+                i += 1;
+            }
+        }
+    }
+    result
+}
+
+/// Fit styled text into lines no wider than `max`, where every line starts with `first`
+/// (or `rest` after the first line)
+fn wrap(
+    spans: &[(String, Style)],
+    first: &str,
+    rest: &str,
+    max: usize,
+) -> Vec<Vec<(String, Style)>> {
+    // Break the text up into words, remembering whether each follows a space
+    let mut words: Vec<(String, Style, bool)> = vec![];
+    let mut spaced = false;
+    for (text, style) in spans {
+        for (n, part) in text.split(' ').enumerate() {
+            spaced |= n > 0;
+            if !part.is_empty() {
+                words.push((part.to_string(), *style, spaced));
+                spaced = false;
+            }
+        }
+    }
+    let mut lines = vec![];
+    let start = |prefix: &str| {
+        if prefix.is_empty() {
+            vec![]
+        } else {
+            vec![(prefix.to_string(), Style::default())]
+        }
+    };
+    let mut line = start(first);
+    let mut used = width(first, 4);
+    let mut empty = true;
+    for (word, style, spaced) in words {
+        let gap = usize::from(spaced && !empty);
+        let len = width(&word, 4);
+        if !empty && used + gap + len > max {
+            lines.push(std::mem::replace(&mut line, start(rest)));
+            used = width(rest, 4);
+            empty = true;
+        }
+        let text = if spaced && !empty {
+            format!(" {word}")
+        } else {
+            word
+        };
+        used += width(&text, 4);
+        empty = false;
+        match line.last_mut() {
+            Some((last, last_style)) if *last_style == style => last.push_str(&text),
+            _ => line.push((text, style)),
+        }
+    }
+    lines.push(line);
+    lines
+}
+
+/// Work out the level and text of a heading line
+fn heading(line: &str) -> Option<(usize, &str)> {
+    let level = line.chars().take_while(|c| *c == '#').count();
+    let rest = &line[level..];
+    if !(1..=6).contains(&level) || !(rest.is_empty() || rest.starts_with(' ')) {
+        return None;
+    }
+    Some((level, rest.trim().trim_end_matches('#').trim_end()))
+}
+
+/// Work out the marker (shown as a bullet or number) and text of a list item,
+/// along with how deeply it is nested
+fn list_item(line: &str) -> Option<(usize, String, &str)> {
+    let indent = line.len() - line.trim_start().len();
+    let line = line.trim_start();
+    let (marker, rest) = if let Some(rest) = line
+        .strip_prefix("- ")
+        .or_else(|| line.strip_prefix("* "))
+        .or_else(|| line.strip_prefix("+ "))
+    {
+        ("•".to_string(), rest)
+    } else {
+        let digits = line.chars().take_while(char::is_ascii_digit).count();
+        let rest = &line[digits..];
+        let rest = rest
+            .strip_prefix(". ")
+            .or_else(|| rest.strip_prefix(") "))
+            .filter(|_| digits > 0)?;
+        (format!("{}.", &line[..digits]), rest)
+    };
+    // Task lists show whether each task is done
+    let (marker, rest) = if let Some(rest) = rest.strip_prefix("[ ] ") {
+        (format!("{marker} ☐"), rest)
+    } else if let Some(rest) = rest
+        .strip_prefix("[x] ")
+        .or_else(|| rest.strip_prefix("[X] "))
+    {
+        (format!("{marker} ☑"), rest)
+    } else {
+        (marker, rest)
+    };
+    Some((indent / 2, marker, rest.trim()))
+}
+
+/// Whether a line is a horizontal rule (like `---` or `* * *`)
+fn is_rule(line: &str) -> bool {
+    let line: String = line.chars().filter(|c| !c.is_whitespace()).collect();
+    line.len() >= 3
+        && ['-', '*', '_']
+            .iter()
+            .any(|m| line.chars().all(|c| c == *m))
+}
+
+/// The fence that opens (or closes) a code block, along with the language given
+fn fence(line: &str) -> Option<(String, &str)> {
+    let line = line.trim_start();
+    let marker = line.chars().next().filter(|c| *c == '`' || *c == '~')?;
+    let len = line.chars().take_while(|c| *c == marker).count();
+    (len >= 3).then(|| (marker.to_string().repeat(len), line[len..].trim()))
+}
+
+/// Whether a line starts a block of its own (so it doesn't carry on a paragraph)
+fn starts_block(line: &str) -> bool {
+    let trimmed = line.trim_start();
+    heading(trimmed).is_some()
+        || list_item(line).is_some()
+        || is_rule(line)
+        || fence(line).is_some()
+        || trimmed.starts_with('>')
+        || trimmed.starts_with('|')
+}
+
+/// Turn markdown into lines of styled text, wrapping paragraphs so they are no wider than `max`
+#[must_use]
+pub fn render(markdown: &str, max: usize) -> Vec<Line> {
+    let source: Vec<&str> = markdown.lines().collect();
+    let mut result: Vec<Line> = vec![];
+    let mut y = 0;
+    while y < source.len() {
+        let line = source[y];
+        let trimmed = line.trim();
+        let start = y;
+        y += 1;
+        if trimmed.is_empty() {
+            if result.last().is_some_and(|l| l.kind != LineKind::Blank) {
+                result.push(Line::plain(LineKind::Blank, String::new(), start));
+            }
+        } else if let Some((marker, language)) = fence(line) {
+            // Code is shown as it is, up to the fence that closes it
+            let language = language
+                .split_whitespace()
+                .next()
+                .map(str::to_string)
+                .filter(|l| !l.is_empty());
+            while y < source.len() && !source[y].trim_start().starts_with(&marker) {
+                let code = Line {
+                    kind: LineKind::Code(language.clone()),
+                    spans: vec![(
+                        source[y].to_string(),
+                        Style {
+                            code: true,
+                            ..Style::default()
+                        },
+                    )],
+                    source: y,
+                };
+                result.push(code);
+                y += 1;
+            }
+            y += 1;
+        } else if let Some((level, text)) = heading(trimmed) {
+            for spans in wrap(&inline(text), "", "", max) {
+                result.push(Line {
+                    kind: LineKind::Heading(level),
+                    spans,
+                    source: start,
+                });
+            }
+        } else if is_rule(line) {
+            result.push(Line::plain(LineKind::Rule, "─".repeat(max), start));
+        } else if let Some((depth, marker, text)) = list_item(line) {
+            // Lines that are indented under an item carry it on
+            let mut text = text.to_string();
+            while y < source.len()
+                && source[y].starts_with(' ')
+                && !source[y].trim().is_empty()
+                && !starts_block(source[y])
+            {
+                text = format!("{text} {}", source[y].trim());
+                y += 1;
+            }
+            let first = format!("{}{marker} ", "  ".repeat(depth));
+            let rest = " ".repeat(width(&first, 4));
+            for spans in wrap(&inline(&text), &first, &rest, max) {
+                result.push(Line {
+                    kind: LineKind::Text,
+                    spans,
+                    source: start,
+                });
+            }
+        } else if trimmed.starts_with('>') {
+            let mut text = vec![];
+            y = start;
+            while y < source.len() && source[y].trim_start().starts_with('>') {
+                let quoted = source[y].trim_start()[1..].trim();
+                text.push(quoted.to_string());
+                y += 1;
+            }
+            for spans in wrap(&inline(&text.join(" ")), "│ ", "│ ", max) {
+                result.push(Line {
+                    kind: LineKind::Quote,
+                    spans,
+                    source: start,
+                });
+            }
+        } else if trimmed.starts_with('|') {
+            // Tables are shown as they are written
+            result.push(Line {
+                kind: LineKind::Text,
+                spans: inline(line),
+                source: start,
+            });
+        } else {
+            // A paragraph runs until a blank line or the start of another block
+            let mut text = trimmed.to_string();
+            while y < source.len() && !source[y].trim().is_empty() && !starts_block(source[y]) {
+                text = format!("{text} {}", source[y].trim());
+                y += 1;
+            }
+            for spans in wrap(&inline(&text), "", "", max) {
+                result.push(Line {
+                    kind: LineKind::Text,
+                    spans,
+                    source: start,
+                });
+            }
+        }
+    }
+    result
+}
@@ -2,6 +2,8 @@
 use crate::regex;
 use crate::utils::Loc;
 use regex::Regex;
+use unicode_normalization::char::canonical_combining_class;
+use unicode_normalization::UnicodeNormalization;
 
 /// Stores information about a match in a document
 #[derive(Debug, PartialEq, Eq, Clone)]
@@ -115,3 +117,117 @@ impl Searcher {
         st.char_indices().nth(x).map_or(st.len(), |(byte, _)| byte)
     }
 }
+
+/// Searcher that ignores differences in unicode normalisation and invisible characters.
+/// This is slower than the regular searcher and is intended to be used after an exact miss.
+pub struct LooseSearcher {
+    pub target: Vec<char>,
+}
+
+impl LooseSearcher {
+    /// Create a new loose searcher (the target is treated literally)
+    #[must_use]
+    pub fn new(target: &str) -> Self {
+        Self {
+            target: loosen(target).into_iter().map(|(c, _)| c).collect(),
+        }
+    }
+
+    /// Finds all the matches in a string, with character indices into the original string
+    #[must_use]
+    pub fn finds(&self, st: &str) -> Vec<Match> {
+        let mut result = vec![];
+        if self.target.is_empty() {
+            return result;
+        }
+        let original: Vec<char> = st.chars().collect();
+        let loose = loosen(st);
+        let mut i = 0;
+        while i + self.target.len() <= loose.len() {
+            let window = &loose[i..i + self.target.len()];
+            if window.iter().map(|(c, _)| c).eq(self.target.iter()) {
+                let start = window[0].1;
+                let end = window[window.len() - 1].1 + 1;
+                // Don't split a character away from combining marks that follow it
+                let starts_clean = i == 0 || loose[i - 1].1 != start;
+                let ends_clean = loose.get(i + self.target.len()).is_none_or(|n| {
+                    n.1 > end || (n.1 == end && canonical_combining_class(n.0) == 0)
+                });
+                if starts_clean && ends_clean {
+                    result.push(Match {
+                        loc: Loc::at(start, 0),
+                        text: original[start..end].iter().collect(),
+                    });
+                    i += self.target.len();
+                    continue;
+                }
+            }
+            i += 1;
+        }
+        result
+    }
+}
+
+/// Returns true if a character is invisible and should be ignored in loose searching
+#[must_use]
+pub fn is_invisible(c: char) -> bool {
+    matches!(
+        c,
+        '\u{200B}'..='\u{200D}' | '\u{2060}' | '\u{FEFF}' | '\u{00AD}' | '\u{180E}'
+    )
+}
+
+/// Returns true if a character is a space that looks like a regular space
+#[must_use]
+pub fn is_space_like(c: char) -> bool {
+    matches!(
+        c,
+        '\u{00A0}' | '\u{2000}'..='\u{200A}' | '\u{202F}' | '\u{205F}' | '\u{3000}'
+    )
+}
+
+/// Describe a character by its code point, e.g. U+00A0
+#[must_use]
+pub fn describe_char(c: char) -> String {
+    format!("U+{:04X}", u32::from(c))
+}
+
+/// Work out which code points differ between a search target and what was found in the document.
+/// Returns the code points only found in the document, and those only found in the target
+#[must_use]
+pub fn loose_differences(target: &str, found: &str) -> (Vec<char>, Vec<char>) {
+    let only_found = found.chars().filter(|c| !target.contains(*c)).collect();
+    let only_target = target.chars().filter(|c| !found.contains(*c)).collect();
+    (only_found, only_target)
+}
+
+/// Canonically decompose a string, drop invisible characters and treat space-like characters
+/// as spaces. Each resulting character is paired with the index of the character it came from.
+/// Comparing decomposed forms is equivalent to comparing NFC forms.
+fn loosen(st: &str) -> Vec<(char, usize)> {
+    let mut result: Vec<(char, usize)> = vec![];
+    for (idx, c) in st.chars().enumerate() {
+        if is_invisible(c) {
+            continue;
+        }
+        let c = if is_space_like(c) { ' ' } else { c };
+        for d in std::iter::once(c).nfd() {
+            result.push((d, idx));
+        }
+    }
+    // Put runs of combining marks into canonical order
+    let mut start = 0;
+    while start < result.len() {
+        if canonical_combining_class(result[start].0) == 0 {
+            start += 1;
+            continue;
+        }
+        let mut end = start;
+        while end < result.len() && canonical_combining_class(result[end].0) != 0 {
+            end += 1;
+        }
+        result[start..end].sort_by_key(|(c, _)| canonical_combining_class(*c));
+        start = end;
+    }
+    result
+}
@@ -0,0 +1,385 @@
+/// signs.rs - for reading what external tools (linters, test runners) report about lines of files
+use crate::utils::get_path_identity;
+use std::collections::HashMap;
+use std::path::Path;
+
+/// How serious a sign is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    Error,
+    Warning,
+    Info,
+    Hint,
+}
+
+impl Severity {
+    /// Work out a severity from the name a tool gives it (anything unknown is treated as info)
+    #[must_use]
+    pub fn parse(name: &str) -> Self {
+        match name.trim().to_lowercase().as_str() {
+            "error" | "err" | "fatal" | "failure" | "failed" => Self::Error,
+            "warning" | "warn" => Self::Warning,
+            "hint" | "help" => Self::Hint,
+            _ => Self::Info,
+        }
+    }
+}
+
+/// A message a tool has reported about a line of a file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sign {
+    /// The (canonical) path of the file the sign is in
+    pub file: String,
+    /// The line the sign is on (starting from 0)
+    pub line: usize,
+    pub severity: Severity,
+    pub message: String,
+    /// Whether the line is beyond the end of the file (e.g. after the file was cut short)
+    pub stale: bool,
+}
+
+/// Move signs that are beyond the end of a file onto its last line, flagging them as stale
+#[must_use]
+pub fn clamp_signs(signs: &[Sign], lines: usize) -> Vec<Sign> {
+    let last = lines.saturating_sub(1);
+    signs
+        .iter()
+        .map(|sign| {
+            let mut sign = sign.clone();
+            if sign.line > last {
+                sign.line = last;
+                sign.stale = true;
+            }
+            sign
+        })
+        .collect()
+}
+
+/// Find the signs on a line (once clamped to the file), the most severe first
+#[must_use]
+pub fn signs_on(signs: &[Sign], y: usize, lines: usize) -> Vec<Sign> {
+    let mut result: Vec<Sign> = clamp_signs(signs, lines)
+        .into_iter()
+        .filter(|sign| sign.line == y)
+        .collect();
+    result.sort_by_key(|sign| sign.severity);
+    result
+}
+
+/// Keeps track of the signs reported by each sign file, so they can be found by the file they are in
+#[derive(Debug, Default, Clone)]
+pub struct SignIndex {
+    sources: HashMap<String, Vec<Sign>>,
+}
+
+impl SignIndex {
+    /// Load (or reload) the signs in a sign file, returning how many entries had to be skipped.
+    /// Relative paths are taken from the sign file's directory, and entries without a file
+    /// are put in `default_file` (the file a `<file>.ox-signs.json` sign file sits beside)
+    pub fn load(&mut self, source: &str, text: &str, default_file: Option<&str>) -> usize {
+        let base = Path::new(source).parent().unwrap_or(Path::new(""));
+        let (entries, mut skipped) = parse_entries(text);
+        let mut signs = vec![];
+        for entry in entries {
+            let file = match (entry.file, default_file) {
+                (Some(file), _) if Path::new(&file).is_absolute() => file,
+                (Some(file), _) => base.join(file).to_string_lossy().to_string(),
+                (None, Some(file)) => file.to_string(),
+                (None, None) => {
+                    skipped += 1;
+                    continue;
+                }
+            };
+            signs.push(Sign {
+                file: get_path_identity(&file),
+                line: entry.line,
+                severity: entry.severity,
+                message: entry.message,
+                stale: false,
+            });
+        }
+        self.sources.insert(source.to_string(), signs);
+        skipped
+    }
+
+    /// Forget the signs from a sign file (e.g. when it has been removed)
+    pub fn remove(&mut self, source: &str) -> bool {
+        self.sources.remove(source).is_some()
+    }
+
+    /// The sign files currently loaded
+    #[must_use]
+    pub fn sources(&self) -> Vec<String> {
+        self.sources.keys().cloned().collect()
+    }
+
+    /// Find all the signs in a file, in order of line
+    #[must_use]
+    pub fn for_file(&self, file: &str) -> Vec<Sign> {
+        let file = get_path_identity(file);
+        let mut result: Vec<Sign> = self
+            .sources
+            .values()
+            .flatten()
+            .filter(|sign| sign.file == file)
+            .cloned()
+            .collect();
+        result.sort_by_key(|sign| (sign.line, sign.severity));
+        result
+    }
+}
+
+/// An entry in a sign file, before its file has been resolved
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignEntry {
+    pub file: Option<String>,
+    /// The line the entry is on (starting from 0)
+    pub line: usize,
+    pub severity: Severity,
+    pub message: String,
+}
+
+/// Read the entries in a sign file, returning them along with how many had to be skipped.
+/// This accepts a JSON array of objects, or one object per line (as `jq -c` writes out).
+/// Entries that are malformed or missing a line are skipped without losing the rest
+#[must_use]
+pub fn parse_entries(text: &str) -> (Vec<SignEntry>, usize) {
+    let mut parser = Parser {
+        chars: text.chars().collect(),
+        at: 0,
+    };
+    let mut values = vec![];
+    let mut skipped = 0;
+    parser.skip_space();
+    let in_array = parser.eat('[');
+    loop {
+        parser.skip_space();
+        while parser.eat(',') {
+            parser.skip_space();
+        }
+        match parser.peek() {
+            None => break,
+            Some(']') if in_array => break,
+            _ => (),
+        }
+        let start = parser.at;
+        if let Some(value) = parser.value() {
+            values.push(value);
+        } else {
+            skipped += 1;
+            parser.at = start;
+            parser.recover(in_array);
+            // Always make progress, even past a stray closing bracket
+            if parser.at == start {
+                parser.at += 1;
+            }
+        }
+    }
+    let mut result = vec![];
+    for value in values {
+        match SignEntry::from_json(&value) {
+            Some(entry) => result.push(entry),
+            None => skipped += 1,
+        }
+    }
+    (result, skipped)
+}
+
+impl SignEntry {
+    /// Read an entry out of a JSON object of the form `{file, line, severity, message}`
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    fn from_json(value: &Json) -> Option<Self> {
+        let Json::Object(fields) = value else {
+            return None;
+        };
+        let get = |name: &str| fields.iter().find(|(k, _)| k == name).map(|(_, v)| v);
+        let line = match get("line")? {
+            Json::Number(n) if *n >= 1.0 => *n as usize,
+            Json::Str(s) => s.trim().parse::<usize>().ok().filter(|n| *n >= 1)?,
+            _ => return None,
+        };
+        let text = |name: &str| match get(name) {
+            Some(Json::Str(s)) => Some(s.clone()),
+            _ => None,
+        };
+        Some(Self {
+            file: text("file"),
+            line: line - 1,
+            severity: text("severity").map_or(Severity::Error, |s| Severity::parse(&s)),
+            message: text("message").unwrap_or_default(),
+        })
+    }
+}
+
+/// A JSON value (only the parts of it that signs are read from)
+#[derive(Debug, Clone, PartialEq)]
+enum Json {
+    Number(f64),
+    Str(String),
+    Object(Vec<(String, Json)>),
+    /// Arrays, booleans and null, which aren't needed beyond checking they are well formed
+    Other,
+}
+
+/// A small JSON reader, which is lenient about trailing commas
+struct Parser {
+    chars: Vec<char>,
+    at: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.at).copied()
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        let found = self.peek() == Some(c);
+        if found {
+            self.at += 1;
+        }
+        found
+    }
+
+    fn skip_space(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.at += 1;
+        }
+    }
+
+    /// Skip past a malformed value, to the next comma or closing bracket at the same depth
+    /// (or to the next line, when there is one value per line)
+    fn recover(&mut self, in_array: bool) {
+        if !in_array {
+            while self.peek().is_some_and(|c| c != '\n') {
+                self.at += 1;
+            }
+            return;
+        }
+        let mut depth = 0_usize;
+        let mut in_string = false;
+        while let Some(c) = self.peek() {
+            if in_string {
+                match c {
+                    '\\' => self.at += 1,
+                    // Strings can't span lines, so a line break ends a broken one
+                    '"' | '\n' => in_string = false,
+                    _ => (),
+                }
+            } else {
+                match c {
+                    '"' => in_string = true,
+                    '{' | '[' => depth += 1,
+                    '}' | ']' | ',' if depth == 0 => return,
+                    '}' | ']' => depth -= 1,
+                    _ => (),
+                }
+            }
+            self.at += 1;
+        }
+    }
+
+    fn value(&mut self) -> Option<Json> {
+        self.skip_space();
+        match self.peek()? {
+            '{' => self.object(),
+            '[' => self.array(),
+            '"' => self.string().map(Json::Str),
+            't' => self.word("true"),
+            'f' => self.word("false"),
+            'n' => self.word("null"),
+            _ => self.number(),
+        }
+    }
+
+    fn word(&mut self, word: &str) -> Option<Json> {
+        for c in word.chars() {
+            if !self.eat(c) {
+                return None;
+            }
+        }
+        Some(Json::Other)
+    }
+
+    fn number(&mut self) -> Option<Json> {
+        let start = self.at;
+        while self
+            .peek()
+            .is_some_and(|c| c.is_ascii_digit() || matches!(c, '-' | '+' | '.' | 'e' | 'E'))
+        {
+            self.at += 1;
+        }
+        let text: String = self.chars[start..self.at].iter().collect();
+        text.parse().ok().map(Json::Number)
+    }
+
+    fn string(&mut self) -> Option<String> {
+        if !self.eat('"') {
+            return None;
+        }
+        let mut result = String::new();
+        loop {
+            let c = self.peek()?;
+            self.at += 1;
+            match c {
+                '"' => return Some(result),
+                '\\' => {
+                    let escape = self.peek()?;
+                    self.at += 1;
+                    match escape {
+                        'n' => result.push('\n'),
+                        't' => result.push('\t'),
+                        'r' => result.push('\r'),
+                        'b' => result.push('\u{8}'),
+                        'f' => result.push('\u{c}'),
+                        'u' => {
+                            let hex: String =
+                                self.chars.get(self.at..self.at + 4)?.iter().collect();
+                            self.at += 4;
+                            let code = u32::from_str_radix(&hex, 16).ok()?;
+                            result.push(char::from_u32(code).unwrap_or('\u{fffd}'));
+                        }
+                        other => result.push(other),
+                    }
+                }
+                '\n' => return None,
+                c => result.push(c),
+            }
+        }
+    }
+
+    fn array(&mut self) -> Option<Json> {
+        self.eat('[');
+        loop {
+            self.skip_space();
+            if self.eat(']') {
+                return Some(Json::Other);
+            }
+            self.value()?;
+            self.skip_space();
+            if !self.eat(',') && self.peek() != Some(']') {
+                return None;
+            }
+        }
+    }
+
+    fn object(&mut self) -> Option<Json> {
+        self.eat('{');
+        let mut result = vec![];
+        loop {
+            self.skip_space();
+            if self.eat('}') {
+                return Some(Json::Object(result));
+            }
+            let key = self.string()?;
+            self.skip_space();
+            if !self.eat(':') {
+                return None;
+            }
+            result.push((key, self.value()?));
+            self.skip_space();
+            if !self.eat(',') && self.peek() != Some('}') {
+                return None;
+            }
+        }
+    }
+}
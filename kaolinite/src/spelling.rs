@@ -0,0 +1,460 @@
+/// spelling.rs - checking the spelling of words against a dictionary and suggesting corrections
+use std::collections::{HashMap, HashSet};
+use std::ops::Range;
+
+/// How far (in edits) a word in the dictionary can be from a misspelling to be suggested
+const MAX_DISTANCE: usize = 2;
+
+/// Stores the words that are spelled correctly
+#[derive(Debug, Default, Clone)]
+pub struct Dictionary {
+    words: HashSet<String>,
+}
+
+impl Dictionary {
+    /// Read a plain list of words (one on each line)
+    #[must_use]
+    pub fn from_word_list(list: &str) -> Self {
+        let mut result = Self::default();
+        for word in list.lines().map(str::trim).filter(|w| !w.is_empty()) {
+            result.insert(word);
+        }
+        result
+    }
+
+    /// Read a Hunspell dictionary, given the contents of its .dic and .aff files.
+    /// Each word is stored along with every form its prefixes and suffixes allow
+    #[must_use]
+    pub fn from_hunspell(dic: &str, aff: &str) -> Self {
+        let affixes = Affixes::parse(aff);
+        let mut result = Self::default();
+        let mut lines = dic.lines();
+        // The first line is (roughly) how many words there are
+        if let Some(first) = lines.next() {
+            if first.trim().parse::<usize>().is_err() {
+                result.add_entry(first, &affixes);
+            }
+        }
+        for line in lines {
+            result.add_entry(line, &affixes);
+        }
+        result
+    }
+
+    /// Add a word from a Hunspell dictionary (`word/flags`), along with its affixed forms
+    fn add_entry(&mut self, line: &str, affixes: &Affixes) {
+        let Some(entry) = line.split_whitespace().next() else {
+            return;
+        };
+        let (word, flags) = entry.split_once('/').unwrap_or((entry, ""));
+        if word.is_empty() {
+            return;
+        }
+        self.insert(word);
+        let flags = affixes.style.split(flags);
+        let classes: Vec<&AffixClass> = flags.iter().filter_map(|f| affixes.get(f)).collect();
+        for suffix in classes.iter().filter(|c| !c.prefix) {
+            for stem in suffix.apply(word) {
+                // Prefixes can go on the front of suffixed words if both allow it
+                if suffix.cross {
+                    for prefix in classes.iter().filter(|c| c.prefix && c.cross) {
+                        for form in prefix.apply(&stem) {
+                            self.insert(&form);
+                        }
+                    }
+                }
+                self.insert(&stem);
+            }
+        }
+        for prefix in classes.iter().filter(|c| c.prefix) {
+            for form in prefix.apply(word) {
+                self.insert(&form);
+            }
+        }
+    }
+
+    /// Add a word to the dictionary
+    pub fn insert(&mut self, word: &str) {
+        self.words.insert(normalise(word));
+    }
+
+    /// The number of words (and forms of words) in the dictionary
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.words.len()
+    }
+
+    /// Whether the dictionary has no words in it
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.words.is_empty()
+    }
+
+    /// Check whether a word is spelled correctly.
+    /// Words can be capitalised (or written in capitals) even if the dictionary has them in lower
+    /// case, but words the dictionary has capitalised (like names) must be capitalised
+    #[must_use]
+    pub fn check(&self, word: &str) -> bool {
+        let word = normalise(word);
+        if self.words.contains(&word) {
+            return true;
+        }
+        let lower = word.to_lowercase();
+        let mut chars = word.chars();
+        let starts_upper = chars.next().is_some_and(char::is_uppercase);
+        let all_upper = word.chars().all(|c| !c.is_lowercase());
+        if starts_upper && (all_upper || chars.all(|c| !c.is_uppercase())) {
+            self.words.contains(&lower) || (all_upper && self.words.contains(&capitalise(&lower)))
+        } else {
+            false
+        }
+    }
+
+    /// Suggest up to `limit` words that a misspelled word might have been meant to be,
+    /// closest first, written in the same case as the misspelling
+    #[must_use]
+    pub fn suggest(&self, word: &str, limit: usize) -> Vec<String> {
+        let target: Vec<char> = normalise(word).to_lowercase().chars().collect();
+        let mut found: HashMap<String, (usize, bool, usize)> = HashMap::new();
+        for candidate in &self.words {
+            let lower = candidate.to_lowercase();
+            let chars: Vec<char> = lower.chars().collect();
+            let length_diff = chars.len().abs_diff(target.len());
+            if length_diff > MAX_DISTANCE || lower.chars().eq(target.iter().copied()) {
+                continue;
+            }
+            let Some(distance) = edit_distance(&target, &chars, MAX_DISTANCE) else {
+                continue;
+            };
+            // Misspellings rarely get the first letter wrong
+            let first_differs = chars.first() != target.first();
+            let rank = (distance, first_differs, length_diff);
+            found
+                .entry(candidate.clone())
+                .and_modify(|r| *r = (*r).min(rank))
+                .or_insert(rank);
+        }
+        let mut found: Vec<(String, (usize, bool, usize))> = found.into_iter().collect();
+        found.sort_by(|(a, ra), (b, rb)| ra.cmp(rb).then_with(|| a.cmp(b)));
+        let mut result: Vec<String> = vec![];
+        for (candidate, _) in found {
+            let candidate = match_case(word, &candidate);
+            if !result.contains(&candidate) {
+                result.push(candidate);
+            }
+            if result.len() >= limit {
+                break;
+            }
+        }
+        result
+    }
+}
+
+/// Write curly apostrophes as straight ones, so either spelling is found
+fn normalise(word: &str) -> String {
+    word.replace('’', "'")
+}
+
+/// Make the first letter of a word a capital
+fn capitalise(word: &str) -> String {
+    let mut chars = word.chars();
+    chars.next().map_or_else(String::new, |first| {
+        first.to_uppercase().chain(chars).collect()
+    })
+}
+
+/// Write a suggestion in the same case as the word it is replacing
+fn match_case(original: &str, suggestion: &str) -> String {
+    let letters = original.chars().filter(|c| c.is_alphabetic()).count();
+    if letters > 1 && original.chars().all(|c| !c.is_lowercase()) {
+        suggestion.to_uppercase()
+    } else if original.chars().next().is_some_and(char::is_uppercase) {
+        capitalise(suggestion)
+    } else {
+        suggestion.to_string()
+    }
+}
+
+/// Work out how many edits (insertions, deletions, substitutions or swapping two neighbouring
+/// characters) it takes to turn one word into another, giving up if it is more than `max`
+fn edit_distance(a: &[char], b: &[char], max: usize) -> Option<usize> {
+    let mut before: Vec<usize> = vec![];
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for i in 1..=a.len() {
+        let mut current = vec![i; b.len() + 1];
+        for j in 1..=b.len() {
+            let cost = usize::from(a[i - 1] != b[j - 1]);
+            current[j] = (previous[j] + 1)
+                .min(current[j - 1] + 1)
+                .min(previous[j - 1] + cost);
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                current[j] = current[j].min(before[j - 2] + 1);
+            }
+        }
+        if current.iter().all(|d| *d > max) {
+            return None;
+        }
+        before = std::mem::replace(&mut previous, current);
+    }
+    Some(previous[b.len()]).filter(|d| *d <= max)
+}
+
+/// Find the words in a line that should have their spelling checked (as character ranges).
+/// Anything that looks like code is left alone: parts of identifiers (next to digits or
+/// underscores, or with capitals part way through) and anything in a path, address or link
+#[must_use]
+pub fn words(line: &str) -> Vec<Range<usize>> {
+    let chars: Vec<char> = line.chars().collect();
+    let mut result = vec![];
+    let mut chunk_start = 0;
+    while chunk_start < chars.len() {
+        // Look at each run of text between spaces at a time
+        let chunk_end = chars[chunk_start..]
+            .iter()
+            .position(|c| c.is_whitespace())
+            .map_or(chars.len(), |p| chunk_start + p);
+        let chunk: String = chars[chunk_start..chunk_end].iter().collect();
+        let code_like = chunk.contains("://")
+            || chunk.contains(['@', '/', '\\'])
+            || chunk.contains("::")
+            || chunk.contains("->");
+        if !code_like {
+            result.extend(words_in_chunk(&chars, chunk_start..chunk_end));
+        }
+        chunk_start = chunk_end + 1;
+    }
+    result
+}
+
+/// Find the words in a run of text that doesn't have any spaces in it
+fn words_in_chunk(chars: &[char], chunk: Range<usize>) -> Vec<Range<usize>> {
+    let is_apostrophe = |c: char| c == '\'' || c == '’';
+    let mut result = vec![];
+    let mut x = chunk.start;
+    while x < chunk.end {
+        if !chars[x].is_alphabetic() {
+            x += 1;
+            continue;
+        }
+        let start = x;
+        // Apostrophes are part of a word when there are letters either side
+        while x < chunk.end
+            && (chars[x].is_alphabetic()
+                || (is_apostrophe(chars[x])
+                    && x + 1 < chunk.end
+                    && chars[x + 1].is_alphabetic()
+                    && x > start))
+        {
+            x += 1;
+        }
+        let word = &chars[start..x];
+        let touches_code = |c: Option<&char>| c.is_some_and(|c| c.is_numeric() || *c == '_');
+        let before = start.checked_sub(1).and_then(|p| chars.get(p));
+        let identifier = touches_code(before) || touches_code(chars.get(x));
+        let letters = word.iter().filter(|c| c.is_alphabetic()).count();
+        let all_upper = word.iter().all(|c| !c.is_lowercase());
+        let mixed_case = !all_upper && word.iter().skip(1).any(|c| c.is_uppercase());
+        if !identifier && !mixed_case && letters > 1 {
+            result.push(start..x);
+        }
+    }
+    result
+}
+
+/// Find the words in a line that are misspelled (as character ranges),
+/// only looking at those that are entirely within one of the given regions
+#[must_use]
+pub fn misspellings(line: &str, regions: &[Range<usize>], dict: &Dictionary) -> Vec<Range<usize>> {
+    let chars: Vec<char> = line.chars().collect();
+    words(line)
+        .into_iter()
+        .filter(|w| regions.iter().any(|r| r.start <= w.start && w.end <= r.end))
+        .filter(|w| {
+            let word: String = chars[w.clone()].iter().collect();
+            !dict.check(&word)
+        })
+        .collect()
+}
+
+/// How the flags of words are written in a Hunspell dictionary
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FlagStyle {
+    /// Each character is a flag
+    Char,
+    /// Each pair of characters is a flag
+    Long,
+    /// Flags are numbers separated by commas
+    Num,
+}
+
+impl FlagStyle {
+    /// Split up the flags of a word
+    fn split(self, flags: &str) -> Vec<String> {
+        match self {
+            Self::Char => flags.chars().map(String::from).collect(),
+            Self::Long => {
+                let chars: Vec<char> = flags.chars().collect();
+                chars.chunks(2).map(|c| c.iter().collect()).collect()
+            }
+            Self::Num => flags.split(',').map(|f| f.trim().to_string()).collect(),
+        }
+    }
+}
+
+/// A part of the condition an affix has on the words it can go on
+#[derive(Debug, Clone)]
+enum Condition {
+    /// Any character
+    Any,
+    /// One of these characters
+    OneOf(Vec<char>),
+    /// Any character but these
+    NoneOf(Vec<char>),
+}
+
+impl Condition {
+    /// Read a condition, where `.` is None (no condition at all)
+    fn parse(text: &str) -> Option<Vec<Self>> {
+        if text == "." {
+            return None;
+        }
+        let mut result = vec![];
+        let mut chars = text.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '.' => result.push(Self::Any),
+                '[' => {
+                    let set: String = chars.by_ref().take_while(|c| *c != ']').collect();
+                    result.push(match set.strip_prefix('^') {
+                        Some(set) => Self::NoneOf(set.chars().collect()),
+                        None => Self::OneOf(set.chars().collect()),
+                    });
+                }
+                c => result.push(Self::OneOf(vec![c])),
+            }
+        }
+        Some(result)
+    }
+
+    /// Check whether a character meets this part of a condition
+    fn matches(&self, c: char) -> bool {
+        match self {
+            Self::Any => true,
+            Self::OneOf(set) => set.contains(&c),
+            Self::NoneOf(set) => !set.contains(&c),
+        }
+    }
+}
+
+/// A single way of adding a prefix or suffix to a word
+#[derive(Debug, Clone)]
+struct Affix {
+    /// Taken off the word before adding
+    strip: String,
+    /// Added on to the word
+    add: String,
+    /// What the word has to start (or end) with for this to apply
+    condition: Option<Vec<Condition>>,
+}
+
+/// All the affixes that go with a flag
+#[derive(Debug, Clone)]
+struct AffixClass {
+    prefix: bool,
+    /// Whether this can be combined with affixes on the other end of the word
+    cross: bool,
+    rules: Vec<Affix>,
+}
+
+impl AffixClass {
+    /// Give every form of a word this class allows
+    fn apply(&self, word: &str) -> Vec<String> {
+        let chars: Vec<char> = word.chars().collect();
+        let mut result = vec![];
+        for rule in &self.rules {
+            if let Some(condition) = &rule.condition {
+                if condition.len() > chars.len() {
+                    continue;
+                }
+                let part = if self.prefix {
+                    &chars[..condition.len()]
+                } else {
+                    &chars[chars.len() - condition.len()..]
+                };
+                if !condition.iter().zip(part).all(|(cond, c)| cond.matches(*c)) {
+                    continue;
+                }
+            }
+            let form = if self.prefix {
+                word.strip_prefix(rule.strip.as_str())
+                    .map(|rest| format!("{}{rest}", rule.add))
+            } else {
+                word.strip_suffix(rule.strip.as_str())
+                    .map(|rest| format!("{rest}{}", rule.add))
+            };
+            if let Some(form) = form.filter(|f| !f.is_empty()) {
+                result.push(form);
+            }
+        }
+        result
+    }
+}
+
+/// The affix rules of a Hunspell dictionary (from its .aff file)
+#[derive(Debug)]
+struct Affixes {
+    style: FlagStyle,
+    classes: HashMap<String, AffixClass>,
+}
+
+impl Affixes {
+    /// Read the parts of an .aff file needed to work out the forms of words
+    fn parse(aff: &str) -> Self {
+        let mut result = Self {
+            style: FlagStyle::Char,
+            classes: HashMap::new(),
+        };
+        for line in aff.lines() {
+            let parts: Vec<&str> = line.split_whitespace().collect();
+            match parts.as_slice() {
+                ["FLAG", "long", ..] => result.style = FlagStyle::Long,
+                ["FLAG", "num", ..] => result.style = FlagStyle::Num,
+                // The header of a class (how many rules follow is not needed)
+                [kind @ ("PFX" | "SFX"), flag, cross, count] if count.parse::<usize>().is_ok() => {
+                    let class = AffixClass {
+                        prefix: *kind == "PFX",
+                        cross: *cross == "Y",
+                        rules: vec![],
+                    };
+                    result.classes.insert((*flag).to_string(), class);
+                }
+                ["PFX" | "SFX", flag, strip, add, rest @ ..] => {
+                    let Some(class) = result.classes.get_mut(*flag) else {
+                        continue;
+                    };
+                    let zero = |s: &str| {
+                        if s == "0" {
+                            String::new()
+                        } else {
+                            s.to_string()
+                        }
+                    };
+                    // Any flags on the affix itself (for adding more affixes) are ignored
+                    let add = add.split('/').next().unwrap_or_default();
+                    class.rules.push(Affix {
+                        strip: zero(strip),
+                        add: zero(add),
+                        condition: rest.first().and_then(|c| Condition::parse(c)),
+                    });
+                }
+                _ => (),
+            }
+        }
+        result
+    }
+
+    /// Find the class that goes with a flag
+    fn get(&self, flag: &str) -> Option<&AffixClass> {
+        self.classes.get(flag)
+    }
+}
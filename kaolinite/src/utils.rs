@@ -1,4 +1,5 @@
 /// utils.rs - utilities to assist in editing and keep code in document.rs readable
+use serde::{Deserialize, Serialize};
 use std::ops::{Bound, RangeBounds};
 use std::path::Path;
 use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
@@ -20,7 +21,7 @@ macro_rules! regex {
 }
 
 /// Represents a location
-#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 pub struct Loc {
     pub y: usize,
     pub x: usize,
@@ -109,6 +110,25 @@ pub fn width_char(ch: &char, tab_width: usize) -> usize {
     }
 }
 
+/// Replace the tabs in some text with spaces up to the next tab stop,
+/// given the column the text starts at
+#[must_use]
+pub fn expand_tabs(st: &str, mut col: usize, tab_width: usize) -> String {
+    let tab_width = tab_width.max(1);
+    let mut result = String::with_capacity(st.len());
+    for ch in st.chars() {
+        if ch == '\t' {
+            let spaces = tab_width - col % tab_width;
+            result.push_str(&" ".repeat(spaces));
+            col += spaces;
+        } else {
+            result.push(ch);
+            col += width_char(&ch, tab_width);
+        }
+    }
+    result
+}
+
 /// Utility function to take a line and determine where spaces should be treated as tabs (forwards)
 #[must_use]
 pub fn tab_boundaries_forward(line: &str, tab_width: usize) -> Vec<usize> {
@@ -158,6 +178,24 @@ pub fn get_absolute_path(path: &str) -> Option<String> {
     Some(abs)
 }
 
+/// Will get a path that identifies a file, even if the file doesn't exist on disk yet
+#[must_use]
+pub fn get_path_identity(path: &str) -> String {
+    if let Some(abs) = get_absolute_path(path) {
+        return abs;
+    }
+    // File doesn't exist yet, resolve the parent directory instead
+    let p = Path::new(path);
+    let parent = match p.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent.to_string_lossy().to_string(),
+        _ => ".".to_string(),
+    };
+    match (get_absolute_path(&parent), p.file_name()) {
+        (Some(parent), Some(name)) => Path::new(&parent).join(name).display().to_string(),
+        _ => path.to_string(),
+    }
+}
+
 /// Will get the file name from a file
 #[must_use]
 pub fn get_file_name(path: &str) -> Option<String> {
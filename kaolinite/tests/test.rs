@@ -1,6 +1,10 @@
 use kaolinite::regex;
 #[cfg(test)]
-use kaolinite::{document::*, event::*, map::*, searching::*, utils::*};
+use kaolinite::{
+    backups::*, diff::*, document::*, event::*, fuzzy::*, map::*, markdown::*, searching::*,
+    signs::*, spelling::*, utils::*,
+};
+use ropey::Rope;
 use std::io::Write;
 use std::ops::{Range, RangeBounds};
 use sugars::hmap;
@@ -153,6 +157,67 @@ fn searching() {
     );
 }
 
+#[test]
+fn loose_searching() {
+    // Decomposed accents match precomposed ones (and vice versa)
+    let precomposed = LooseSearcher::new("caf\u{e9}");
+    let text = st!("a cafe\u{301} here");
+    assert_eq!(
+        precomposed.finds(&text),
+        vec![Match {
+            loc: Loc { x: 2, y: 0 },
+            text: st!("cafe\u{301}")
+        }]
+    );
+    let decomposed = LooseSearcher::new("cafe\u{301}");
+    assert_eq!(decomposed.finds("caf\u{e9}").len(), 1);
+    // A bare letter shouldn't match half of an accented character
+    assert!(LooseSearcher::new("cafe").finds(&text).is_empty());
+    // Non-breaking and figure spaces are treated as spaces
+    let spaced = LooseSearcher::new("hello world");
+    assert_eq!(
+        spaced.finds("hello\u{a0}world and hello\u{2007}world"),
+        vec![
+            Match {
+                loc: Loc { x: 0, y: 0 },
+                text: st!("hello\u{a0}world")
+            },
+            Match {
+                loc: Loc { x: 16, y: 0 },
+                text: st!("hello\u{2007}world")
+            },
+        ]
+    );
+    // Zero width characters are ignored
+    let joined = LooseSearcher::new("ab");
+    assert_eq!(
+        joined.finds("xa\u{200d}b"),
+        vec![Match {
+            loc: Loc { x: 1, y: 0 },
+            text: st!("a\u{200d}b")
+        }]
+    );
+    assert!(LooseSearcher::new("").finds("anything").is_empty());
+    // Describing differences
+    assert_eq!(describe_char('\u{a0}'), st!("U+00A0"));
+    assert_eq!(
+        loose_differences("caf\u{e9}", "cafe\u{301}"),
+        (vec!['e', '\u{301}'], vec!['\u{e9}'])
+    );
+    // Searching a whole document
+    let mut doc = Document::new(Size::is(100, 10));
+    doc.exe(Event::Insert(Loc { x: 0, y: 0 }, st!("no\u{a0}match")))
+        .unwrap();
+    assert_eq!(doc.next_match("no match", 0), None);
+    assert_eq!(
+        doc.loose_matches("no match"),
+        vec![Match {
+            loc: Loc { x: 0, y: 0 },
+            text: st!("no\u{a0}match")
+        }]
+    );
+}
+
 #[test]
 fn char_mapping() {
     let mut test1_map = CharMap::new(hmap! { 0 => vec![]});
@@ -440,6 +505,443 @@ fn document_undo_redo() {
     assert!(doc.event_mgmt.with_disk(&doc.take_snapshot()));
 }
 
+#[test]
+fn document_undo_patches() {
+    // Patches only hold the part that changed
+    let old = Snapshot {
+        content: Rope::from_str("hello 你好 world\nbye"),
+        cursor: Cursor::default(),
+        line_ending: LineEnding::Lf,
+    };
+    let mut new = old.clone();
+    new.content = Rope::from_str("hello 你们好 world\nbye");
+    new.cursor.loc = Loc { x: 8, y: 0 };
+    let patch = Patch::between(&old, &new);
+    assert_eq!(patch.at, 7);
+    assert_eq!(patch.removed, st!(""));
+    assert_eq!(patch.inserted, st!("们"));
+    assert_eq!(patch.apply(&old, true), new);
+    assert_eq!(patch.apply(&new, false), old);
+    // Multi-byte characters that only partly match are kept whole
+    new.content = Rope::from_str("hello 你妈 world\nbye");
+    let patch = Patch::between(&old, &new);
+    assert_eq!(
+        (patch.removed.as_str(), patch.inserted.as_str()),
+        ("好", "妈")
+    );
+    assert_eq!(patch.apply(&new, false).content, old.content);
+    assert!(Patch::between(&old, &old).is_empty());
+    // History is capped by the limit
+    let mut doc = Document::new(Size::is(100, 10));
+    doc.event_mgmt.limit = 2;
+    doc.commit();
+    for word in ["a", "b", "c"] {
+        doc.exe(Event::Insert(Loc { x: 0, y: 0 }, st!(word)))
+            .unwrap();
+        doc.commit();
+    }
+    assert_eq!(doc.line(0), Some(st!("cba")));
+    assert_eq!(doc.event_mgmt.history.len(), 2);
+    doc.undo().unwrap();
+    doc.undo().unwrap();
+    assert_eq!(doc.line(0), Some(st!("a")));
+    doc.undo().unwrap();
+    assert_eq!(doc.line(0), Some(st!("a")));
+    doc.redo().unwrap();
+    doc.redo().unwrap();
+    assert_eq!(doc.line(0), Some(st!("cba")));
+    // The modified state follows the contents of the disk
+    assert!(!doc.event_mgmt.with_disk(&doc.take_snapshot()));
+}
+
+#[test]
+fn document_undo_batches() {
+    let mut doc = Document::new(Size::is(100, 10));
+    doc.commit();
+    doc.exe(Event::Insert(Loc { x: 0, y: 0 }, st!("one two one")))
+        .unwrap();
+    // Commits made part of the way through a batch (even a nested one) are held back
+    doc.begin_batch();
+    doc.replace_all("one", "three");
+    doc.commit();
+    doc.batch(|doc| {
+        doc.exe(Event::Insert(Loc { x: 0, y: 0 }, st!(">")))
+            .unwrap();
+        doc.commit();
+    });
+    doc.exe(Event::Delete(Loc { x: 0, y: 0 }, st!(">")))
+        .unwrap();
+    doc.end_batch();
+    assert_eq!(doc.line(0), Some(st!("three two three")));
+    assert_eq!(doc.event_mgmt.batch, 0);
+    // Typing before the batch, then the batch itself
+    assert_eq!(doc.event_mgmt.history.len(), 2);
+    // A single undo takes back the whole batch, and a single redo puts it back
+    doc.undo().unwrap();
+    assert_eq!(doc.line(0), Some(st!("one two one")));
+    doc.redo().unwrap();
+    assert_eq!(doc.line(0), Some(st!("three two three")));
+    assert_eq!(doc.event_mgmt.ptr, 2);
+    // Removing a selection counts as a change of its own
+    doc.move_to(&Loc { x: 0, y: 0 });
+    doc.select_to(&Loc { x: 6, y: 0 });
+    doc.batch(Document::remove_selection);
+    assert_eq!(doc.line(0), Some(st!("two three")));
+    assert_eq!(doc.event_mgmt.history.len(), 3);
+}
+
+#[test]
+fn document_undo_landing() {
+    std::fs::write("tests/data/undo_ghost.txt", "line\n".repeat(100)).unwrap();
+    let mut doc = Document::open(Size::is(100, 10), "tests/data/undo_ghost.txt").unwrap();
+    std::fs::remove_file("tests/data/undo_ghost.txt").unwrap();
+    doc.load_to(100);
+    doc.commit();
+    // Nothing to undo or redo yet
+    assert_eq!(doc.undo().unwrap(), None);
+    assert_eq!(doc.redo().unwrap(), None);
+    // Make an edit far down the document, then go back to the top
+    doc.move_to(&Loc { x: 0, y: 80 });
+    doc.exe(Event::Insert(Loc { x: 2, y: 80 }, st!("hello")))
+        .unwrap();
+    doc.commit();
+    doc.move_top();
+    assert_eq!(doc.offset.y, 0);
+    // Undoing lands on the edit and recentres the view
+    let change = doc.undo().unwrap().unwrap();
+    assert_eq!(change.loc, Loc { x: 2, y: 80 });
+    assert_eq!(
+        change.describe(),
+        st!("insertion of 5 characters on line 81")
+    );
+    assert_eq!(doc.char_loc(), Loc { x: 2, y: 80 });
+    assert_eq!(doc.offset.y, 75);
+    assert_eq!(doc.line(80), Some(st!("line")));
+    // Redoing lands after the redone text, without moving the view when it's already visible
+    let change = doc.redo().unwrap().unwrap();
+    assert_eq!(change.loc, Loc { x: 7, y: 80 });
+    assert_eq!(doc.char_loc(), Loc { x: 7, y: 80 });
+    assert_eq!(doc.offset.y, 75);
+    assert_eq!(doc.redo().unwrap(), None);
+    // Deletions and replacements are described too
+    doc.exe(Event::Delete(Loc { x: 2, y: 80 }, st!("h")))
+        .unwrap();
+    doc.commit();
+    let change = doc.undo().unwrap().unwrap();
+    assert_eq!(change.describe(), st!("deletion of 1 character on line 81"));
+    assert_eq!(doc.char_loc(), Loc { x: 3, y: 80 });
+    doc.move_to(&Loc { x: 0, y: 3 });
+    doc.commit();
+    doc.replace(Loc { x: 0, y: 3 }, "line", "row").unwrap();
+    doc.commit();
+    let change = doc.undo().unwrap().unwrap();
+    assert_eq!(
+        change.describe(),
+        st!("replacement of 4 characters with 3 on line 4")
+    );
+}
+
+#[test]
+fn document_persistent_undo() {
+    std::fs::write("tests/data/history_ghost.txt", "hello\nworld\n").unwrap();
+    let mut doc = Document::open(Size::is(100, 10), "tests/data/history_ghost.txt").unwrap();
+    doc.load_to(100);
+    doc.commit();
+    doc.exe(Event::Insert(Loc { x: 5, y: 0 }, st!(" there")))
+        .unwrap();
+    doc.commit();
+    doc.exe(Event::Delete(Loc { x: 0, y: 1 }, st!("w")))
+        .unwrap();
+    doc.commit();
+    doc.exe(Event::Insert(Loc { x: 0, y: 1 }, st!("W")))
+        .unwrap();
+    doc.commit();
+    let data = doc.save_history(1024 * 1024);
+    let contents = doc.file.to_string();
+    assert_eq!(contents, "hello there\nWorld\n");
+    // Restoring against the same contents allows the edits to be undone
+    std::fs::write("tests/data/history_ghost.txt", &contents).unwrap();
+    let mut doc = Document::open(Size::is(100, 10), "tests/data/history_ghost.txt").unwrap();
+    doc.load_to(100);
+    assert!(doc.load_history(&data));
+    assert_eq!(doc.event_mgmt.history.len(), 3);
+    doc.undo().unwrap().unwrap();
+    doc.undo().unwrap().unwrap();
+    doc.undo().unwrap().unwrap();
+    assert_eq!(doc.undo().unwrap(), None);
+    assert_eq!(doc.file.to_string(), "hello\nworld\n");
+    doc.redo().unwrap().unwrap();
+    assert_eq!(doc.file.to_string(), "hello there\nworld\n");
+    // Histories saved against other contents are ignored
+    std::fs::write("tests/data/history_ghost.txt", "changed outside\n").unwrap();
+    let mut doc = Document::open(Size::is(100, 10), "tests/data/history_ghost.txt").unwrap();
+    assert!(!doc.load_history(&data));
+    assert!(doc.event_mgmt.history.is_empty());
+    std::fs::write("tests/data/history_ghost.txt", &contents).unwrap();
+    let mut doc = Document::open(Size::is(100, 10), "tests/data/history_ghost.txt").unwrap();
+    // Corrupt, truncated or other version histories are ignored
+    let mut other = data.clone();
+    let version = other.windows(2).position(|w| w == b"v2").unwrap();
+    other[version + 1] = b'1';
+    assert!(!doc.load_history(&other));
+    assert!(!doc.load_history(&data[..data.len() - 3]));
+    assert!(!doc.load_history(b"complete nonsense"));
+    assert!(!doc.load_history(b""));
+    let mut junk = data.clone();
+    junk.extend_from_slice(b"junk");
+    assert!(!doc.load_history(&junk));
+    assert!(doc.event_mgmt.history.is_empty());
+    // Storage is bounded, dropping the oldest edits first
+    let small = doc.save_history(0);
+    assert!(doc.load_history(&small));
+    assert!(doc.event_mgmt.history.is_empty());
+    let mut doc = Document::open(Size::is(100, 10), "tests/data/history_ghost.txt").unwrap();
+    let two = {
+        let mut full = Document::open(Size::is(100, 10), "tests/data/history_ghost.txt").unwrap();
+        full.load_history(&data);
+        let all = full.save_history(data.len()).len() - full.save_history(0).len();
+        full.save_history(all - 1)
+    };
+    assert!(doc.load_history(&two));
+    assert_eq!(doc.event_mgmt.history.len(), 2);
+    doc.undo().unwrap().unwrap();
+    doc.undo().unwrap().unwrap();
+    assert_eq!(doc.undo().unwrap(), None);
+    assert_eq!(doc.file.to_string(), "hello there\nworld\n");
+    // The undo limit still applies to restored histories
+    let mut doc = Document::open(Size::is(100, 10), "tests/data/history_ghost.txt").unwrap();
+    doc.event_mgmt.limit = 1;
+    assert!(doc.load_history(&data));
+    assert_eq!(doc.event_mgmt.history.len(), 1);
+    std::fs::remove_file("tests/data/history_ghost.txt").unwrap();
+}
+
+#[test]
+fn document_changed_region() {
+    // Create a huge document and only load the top of it
+    std::fs::write("tests/data/huge_ghost.txt", "line\n".repeat(200_000)).unwrap();
+    let mut doc = Document::open(Size::is(100, 10), "tests/data/huge_ghost.txt").unwrap();
+    std::fs::remove_file("tests/data/huge_ghost.txt").unwrap();
+    doc.load_to(10);
+    // Editing near the top should only report the top as changed
+    doc.commit();
+    let old = doc.file.clone();
+    doc.exe(Event::Insert(Loc { x: 0, y: 0 }, st!("x")));
+    assert_eq!(doc.changed_region(&old), (0, 1, 1));
+    let old = doc.file.clone();
+    doc.exe(Event::InsertLine(2, st!("new")));
+    assert_eq!(doc.changed_region(&old), (2, 2, 3));
+    doc.commit();
+    // Undo and redo swap in whole snapshots, but the change is still found to be at the top
+    let old = doc.file.clone();
+    doc.undo().unwrap();
+    assert_eq!(doc.changed_region(&old), (0, 3, 2));
+    let old = doc.file.clone();
+    doc.redo().unwrap();
+    assert_eq!(doc.changed_region(&old), (0, 2, 3));
+    // Nothing near the bottom was loaded in (just the top and the inserted line)
+    assert_eq!(doc.info.loaded_to, 11);
+    assert_eq!(
+        doc.changed_region(&doc.file.clone()),
+        (200_002, 200_002, 200_002)
+    );
+}
+
+#[test]
+fn document_line_endings() {
+    let mut doc = Document::open(Size::is(100, 10), "tests/data/crlf.txt").unwrap();
+    doc.load_to(100);
+    assert_eq!(doc.info.line_ending, LineEnding::Crlf);
+    assert_eq!(doc.info.line_ending.to_string(), st!("CRLF"));
+    assert_eq!(doc.line(0), Some(st!("hello")));
+    assert_eq!(doc.line(2), Some(st!("mixed")));
+    // New lines take on the dominant line ending, existing lines keep theirs
+    doc.exe(Event::SplitDown(Loc { x: 2, y: 0 }));
+    assert_eq!(doc.line(1), Some(st!("llo")));
+    assert_eq!(doc.file.to_string(), st!("he\r\nllo\r\nworld\r\nmixed\n"));
+    // Conversion
+    doc.commit();
+    doc.convert_line_endings(LineEnding::Lf);
+    doc.commit();
+    assert_eq!(doc.info.line_ending, LineEnding::Lf);
+    assert_eq!(doc.file.to_string(), st!("he\nllo\nworld\nmixed\n"));
+    assert_eq!(doc.line(2), Some(st!("world")));
+    doc.undo();
+    assert_eq!(doc.info.line_ending, LineEnding::Crlf);
+    assert_eq!(doc.file.to_string(), st!("he\r\nllo\r\nworld\r\nmixed\n"));
+    // Blank documents default to LF
+    let doc = Document::new(Size::is(100, 10));
+    assert_eq!(doc.info.line_ending, LineEnding::Lf);
+}
+
+#[test]
+fn document_encodings() {
+    // Detection
+    assert_eq!(detect_encoding(b"hello"), (encoding_rs::UTF_8, 0));
+    assert_eq!(
+        detect_encoding(b"\xEF\xBB\xBFhello"),
+        (encoding_rs::UTF_8, 3)
+    );
+    assert_eq!(detect_encoding(b"h\0i\0"), (encoding_rs::UTF_16LE, 0));
+    assert_eq!(detect_encoding(b"\0h\0i"), (encoding_rs::UTF_16BE, 0));
+    assert_eq!(detect_encoding(b"caf\xE9"), (encoding_rs::WINDOWS_1252, 0));
+    // Round trips
+    assert_eq!(
+        encode("café", encoding_rs::WINDOWS_1252),
+        Some(b"caf\xE9".to_vec())
+    );
+    assert_eq!(
+        encode("hi", encoding_rs::UTF_16LE),
+        Some(b"h\0i\0".to_vec())
+    );
+    assert_eq!(encode("你", encoding_rs::WINDOWS_1252), None);
+    assert!(decode(b"\xFF\xFF", encoding_rs::UTF_8).1);
+    assert!(!decode(b"caf\xE9", encoding_rs::WINDOWS_1252).1);
+    // Windows-1252 leaves a few bytes without characters
+    assert!(decode(b"caf\x81", encoding_rs::WINDOWS_1252).1);
+    // Latin-1 documents
+    let mut doc = Document::open(Size::is(100, 10), "tests/data/latin1.txt").unwrap();
+    doc.load_to(100);
+    assert_eq!(doc.info.encoding, encoding_rs::WINDOWS_1252);
+    assert_eq!(doc.line(0), Some(st!("café")));
+    assert!(!doc.info.read_only);
+    doc.save_as("tests/data/latin1_ghost.txt").unwrap();
+    let result = std::fs::read("tests/data/latin1_ghost.txt").unwrap();
+    assert_eq!(result, b"caf\xE9\n".to_vec());
+    // Unrepresentable characters shouldn't touch the file
+    doc.exe(Event::Insert(Loc { x: 0, y: 0 }, st!("你")));
+    assert!(doc.save_as("tests/data/latin1_ghost.txt").is_err());
+    let result = std::fs::read("tests/data/latin1_ghost.txt").unwrap();
+    std::fs::remove_file("tests/data/latin1_ghost.txt").unwrap();
+    assert_eq!(result, b"caf\xE9\n".to_vec());
+    // UTF-16 documents with byte order marks
+    let mut doc = Document::open(Size::is(100, 10), "tests/data/utf16.txt").unwrap();
+    doc.load_to(100);
+    assert_eq!(doc.info.encoding, encoding_rs::UTF_16LE);
+    assert!(doc.info.bom);
+    assert_eq!(doc.line(1), Some(st!("wörld")));
+    doc.save_as("tests/data/utf16_ghost.txt").unwrap();
+    let result = std::fs::read("tests/data/utf16_ghost.txt").unwrap();
+    std::fs::remove_file("tests/data/utf16_ghost.txt").unwrap();
+    assert_eq!(result, std::fs::read("tests/data/utf16.txt").unwrap());
+    // Overriding the encoding
+    let mut doc = Document::open(Size::is(100, 10), "tests/data/latin1.txt").unwrap();
+    assert!(doc.reload_with_encoding("utf-8").is_ok());
+    assert_eq!(doc.info.decoding, Decoding::Lossy);
+    assert!(doc.info.read_only);
+    assert!(doc.reload_with_encoding("latin1").is_ok());
+    assert!(!doc.info.read_only);
+    assert_eq!(doc.line(0), Some(st!("café")));
+    assert!(doc.reload_with_encoding("not an encoding").is_err());
+    // Finding unrepresentable characters
+    let text = Rope::from_str("héllo\nwörld 😀");
+    assert_eq!(unrepresentable(&text, encoding_rs::UTF_8, 10), vec![]);
+    assert_eq!(
+        unrepresentable(&text, encoding_rs::WINDOWS_1252, 10),
+        vec![(Loc { x: 6, y: 1 }, '😀')]
+    );
+    let text = Rope::from_str("日本語\n한국");
+    assert_eq!(
+        unrepresentable(&text, encoding_rs::SHIFT_JIS, 10),
+        vec![(Loc { x: 0, y: 1 }, '한'), (Loc { x: 1, y: 1 }, '국')]
+    );
+    let text = Rope::from_str("😀😀😀");
+    assert_eq!(
+        unrepresentable(&text, encoding_rs::WINDOWS_1252, 2).len(),
+        2
+    );
+    // Replacing them
+    let mut doc = Document::new(Size::is(100, 10));
+    doc.exe(Event::Insert(Loc { x: 0, y: 0 }, st!("a😀b")))
+        .unwrap();
+    doc.info.encoding = encoding_rs::WINDOWS_1252;
+    assert!(doc.save_as("tests/data/ghost_latin1.txt").is_err());
+    assert_eq!(doc.replace_unrepresentable('?'), 1);
+    assert_eq!(doc.line(0), Some(st!("a?b")));
+    assert_eq!(doc.replace_unrepresentable('?'), 0);
+}
+
+#[test]
+fn document_streamed_reading() {
+    // Progress is reported as the file is read
+    let mut progress = vec![];
+    let (file, encoding, bom, undecodable) =
+        read_rope("tests/data/utf16.txt", |read| progress.push(read)).unwrap();
+    assert_eq!(encoding, encoding_rs::UTF_16LE);
+    assert_eq!(bom, 2);
+    assert!(!undecodable);
+    assert_eq!(
+        progress.last(),
+        Some(&std::fs::metadata("tests/data/utf16.txt").unwrap().len())
+    );
+    // Documents made from what was read match those opened directly
+    let mut doc = Document::from_rope(
+        Size::is(100, 10),
+        "tests/data/utf16.txt",
+        file,
+        encoding,
+        bom != 0,
+        undecodable,
+    );
+    let mut opened = Document::open(Size::is(100, 10), "tests/data/utf16.txt").unwrap();
+    doc.load_to(100);
+    opened.load_to(100);
+    assert_eq!(doc.file, opened.file);
+    assert_eq!(doc.info, opened.info);
+    assert_eq!(doc.file_name, opened.file_name);
+    // Files that aren't UTF-8 fall back to Latin-1
+    let (file, encoding, _, undecodable) = read_rope("tests/data/latin1.txt", |_| ()).unwrap();
+    assert_eq!(encoding, encoding_rs::WINDOWS_1252);
+    assert!(!undecodable);
+    assert_eq!(file.to_string(), "café\n");
+    assert!(read_rope("tests/data/ghost_missing.txt", |_| ()).is_err());
+}
+
+#[test]
+fn document_binary() {
+    // Detection
+    assert!(is_binary(b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR"));
+    assert!(!is_binary(b"hello world"));
+    assert!(!is_binary(b"h\0i\0"));
+    assert!(!is_binary(&std::fs::read("tests/data/utf16.txt").unwrap()));
+    // Hex rows
+    let bytes = std::fs::read("tests/data/binary.bin").unwrap();
+    assert_eq!(
+        hex_row(&bytes, 0),
+        st!("00000000  89 50 4e 47 0d 0a 1a 0a  00 00 00 0d 49 48 44 52 |.PNG........IHDR|")
+    );
+    assert_eq!(
+        hex_row(&bytes, 1),
+        st!("00000010  41 00 42                                         |A.B|")
+    );
+    // Hex previews are loaded lazily and can't be edited or saved
+    let mut doc = Document::open(Size::is(100, 10), "tests/data/binary.bin").unwrap();
+    assert!(doc.binary.is_some());
+    assert!(doc.info.read_only);
+    assert_eq!(doc.len_lines(), 2);
+    doc.load_to(1);
+    assert_eq!(doc.lines.len(), 1);
+    doc.load_to(100);
+    assert_eq!(doc.line(1), Some(hex_row(&bytes, 1)));
+    assert!(doc.exe(Event::Insert(Loc { x: 0, y: 0 }, st!("x"))).is_ok());
+    assert_eq!(doc.line(0), Some(hex_row(&bytes, 0)));
+    assert!(matches!(doc.save(), Err(Error::BinaryPreview)));
+    assert!(matches!(
+        doc.save_as("tests/data/binary_ghost.bin"),
+        Err(Error::BinaryPreview)
+    ));
+    // Forcing it open as text
+    doc.force_text();
+    assert!(doc.binary.is_none());
+    assert!(!doc.info.read_only);
+    doc.load_to(100);
+    // (the lone carriage return in the header counts as a line break)
+    assert_eq!(doc.len_lines(), 4);
+    assert_eq!(doc.line(0), Some(st!("\u{2030}PNG")));
+    assert_eq!(doc.line(2), Some(st!("\0\0\0")));
+    assert_eq!(doc.line(3), Some(st!("IHDRA\0B")));
+}
+
 #[test]
 fn document_moving() {
     let mut doc = Document::open(Size::is(10, 10), "tests/data/big.txt").unwrap();
@@ -721,6 +1223,37 @@ fn document_selection() {
     );
 }
 
+#[test]
+fn document_block_selection() {
+    let mut doc = Document::new(Size::is(100, 10));
+    doc.exe(Event::Insert(Loc { x: 0, y: 0 }, st!("hello there")))
+        .unwrap();
+    doc.exe(Event::InsertLine(1, st!("hi"))).unwrap();
+    doc.exe(Event::InsertLine(2, st!("a longer line"))).unwrap();
+    doc.move_to(&Loc { x: 2, y: 0 });
+    assert_eq!(doc.block_bounds(), None);
+    doc.start_block();
+    assert_eq!(doc.block_size(), Some((1, 0)));
+    // Lines shorter than the block don't change its size
+    doc.select_block_to(&Loc { x: 6, y: 2 });
+    assert_eq!(doc.block_bounds(), Some((0..3, 2..6)));
+    assert_eq!(doc.block_size(), Some((3, 4)));
+    doc.block_home();
+    assert_eq!(doc.loc(), Loc { x: 0, y: 2 });
+    assert_eq!(doc.block_bounds(), Some((0..3, 0..2)));
+    doc.block_end();
+    assert_eq!(doc.loc(), Loc { x: 13, y: 2 });
+    assert_eq!(doc.block_size(), Some((3, 11)));
+    doc.exit_block();
+    assert_eq!(doc.loc(), Loc { x: 2, y: 0 });
+    assert!(doc.is_selection_empty());
+    assert_eq!(doc.block_size(), None);
+    // Moving normally ends the block
+    doc.start_block();
+    doc.move_right();
+    assert_eq!(doc.block_size(), None);
+}
+
 #[test]
 fn document_scrolling() {
     let mut doc = Document::open(Size::is(10, 10), "tests/data/big.txt").unwrap();
@@ -735,6 +1268,35 @@ fn document_scrolling() {
     doc.scroll_up();
     assert_eq!(doc.offset.y, 0);
     assert_eq!(doc.info.loaded_to, 11);
+    // Scrolling sideways leaves the cursor where it is
+    doc.scroll_right();
+    doc.scroll_right();
+    assert_eq!(doc.offset.x, 2);
+    doc.scroll_left();
+    assert_eq!(doc.offset.x, 1);
+    assert_eq!(doc.loc(), Loc { x: 0, y: 0 });
+    // The view moves before the cursor reaches the edge when there is a margin
+    let mut doc = Document::open(Size::is(10, 10), "tests/data/big.txt").unwrap();
+    doc.load_to(10);
+    doc.horizontal_margin = 3;
+    for _ in 0..6 {
+        doc.move_right();
+    }
+    assert_eq!(doc.offset.x, 0);
+    doc.move_right();
+    assert_eq!(doc.offset.x, 1);
+    doc.move_end();
+    assert_eq!(doc.offset.x, 25);
+    for _ in 0..4 {
+        doc.move_left();
+    }
+    assert_eq!(doc.offset.x, 24);
+    // Margins too wide for the view are narrowed so the cursor still fits between them
+    doc.horizontal_margin = 100;
+    doc.move_home();
+    doc.move_end();
+    assert_eq!(doc.offset.x, 26);
+    assert!(doc.cursor_loc_in_screen().is_some());
 }
 
 #[test]
@@ -902,11 +1464,153 @@ fn document_searching() {
     );
 }
 
+#[test]
+fn document_occurrences() {
+    let mut doc = Document::open(Size::is(100, 10), "tests/data/unicode.txt").unwrap();
+    doc.load_to(1000);
+    // Occurances are found by where they end, after a location
+    doc.move_to(&Loc { x: 5, y: 1 });
+    assert_eq!(
+        doc.next_occurrence("hello", Loc { x: 6, y: 1 }),
+        Some(Loc { x: 9, y: 2 })
+    );
+    assert_eq!(
+        doc.next_occurrence("hello", Loc { x: 9, y: 2 }),
+        Some(Loc { x: 5, y: 4 })
+    );
+    assert_eq!(
+        doc.next_occurrence("hello", Loc { x: 5, y: 4 }),
+        Some(Loc { x: 17, y: 4 })
+    );
+    // Searching wraps around, skipping places with cursors
+    doc.new_cursor(Loc { x: 9, y: 2 });
+    doc.new_cursor(Loc { x: 5, y: 4 });
+    assert_eq!(
+        doc.next_occurrence("hello", Loc { x: 6, y: 1 }),
+        Some(Loc { x: 17, y: 4 })
+    );
+    doc.new_cursor(Loc { x: 17, y: 4 });
+    assert_eq!(
+        doc.next_occurrence("hello", Loc { x: 17, y: 4 }),
+        Some(Loc { x: 6, y: 1 })
+    );
+    doc.move_to(&Loc { x: 6, y: 1 });
+    assert_eq!(doc.next_occurrence("hello", Loc { x: 17, y: 4 }), None);
+    assert_eq!(
+        doc.next_occurrence("好", Loc { x: 17, y: 4 }),
+        Some(Loc { x: 6, y: 0 })
+    );
+    assert_eq!(doc.next_occurrence("missing", Loc::at(0, 0)), None);
+    assert_eq!(doc.next_occurrence("", Loc::at(0, 0)), None);
+    // Clearing cursors forgets what was being looked for
+    doc.occurrence = Some(st!("hello"));
+    doc.clear_cursors();
+    assert!(doc.secondary_cursors.is_empty());
+    assert_eq!(doc.occurrence, None);
+}
+
+#[test]
+fn document_marks() {
+    let mut doc = Document::open(Size::is(100, 10), "tests/data/marks_before.txt").unwrap();
+    doc.load_to(1000);
+    doc.add_mark(2, "greeting");
+    doc.add_mark(8, "sum");
+    assert_eq!(doc.mark_at(2).unwrap().label, st!("greeting"));
+    assert_eq!(
+        doc.mark_at(2).unwrap().text,
+        st!("    println!(\"hello {name}\");")
+    );
+    assert_eq!(doc.mark_at(3), None);
+    doc.commit();
+    // Marks follow their lines as lines are inserted and removed
+    doc.exe(Event::InsertLine(0, st!("use std::env;"))).unwrap();
+    assert_eq!(
+        doc.marks.iter().map(|m| m.y).collect::<Vec<_>>(),
+        vec![3, 9]
+    );
+    doc.exe(Event::SplitDown(Loc::at(0, 3))).unwrap();
+    assert_eq!(doc.mark_at(4).unwrap().label, st!("greeting"));
+    doc.exe(Event::SpliceUp(Loc::at(0, 3))).unwrap();
+    assert_eq!(doc.mark_at(3).unwrap().label, st!("greeting"));
+    doc.exe(Event::Insert(Loc::at(0, 3), st!("// "))).unwrap();
+    assert!(doc.mark_at(3).unwrap().text.starts_with("// "));
+    doc.move_to(&Loc::at(0, 3));
+    doc.swap_line_down().unwrap();
+    assert_eq!(doc.mark_at(4).unwrap().label, st!("greeting"));
+    doc.swap_line_up().unwrap();
+    assert_eq!(doc.mark_at(3).unwrap().label, st!("greeting"));
+    doc.exe(Event::DeleteLine(0, st!("use std::env;"))).unwrap();
+    assert_eq!(
+        doc.marks.iter().map(|m| m.y).collect::<Vec<_>>(),
+        vec![2, 8]
+    );
+    assert!(doc.marks.iter().all(|m| !m.moved));
+    // Undoing finds the lines again by their text
+    doc.exe(Event::InsertLine(0, st!(""))).unwrap();
+    doc.commit();
+    doc.undo().unwrap();
+    assert_eq!(
+        doc.marks.iter().map(|m| m.y).collect::<Vec<_>>(),
+        vec![2, 8]
+    );
+    assert!(doc.marks.iter().all(|m| !m.moved));
+    // Removing a marked line leaves the mark on the line that took its place
+    doc.exe(Event::DeleteLine(8, st!("    a + b"))).unwrap();
+    assert!(doc.mark_at(8).unwrap().moved);
+    assert_eq!(doc.remove_mark(8).unwrap().label, st!("sum"));
+    assert_eq!(doc.remove_mark(8), None);
+    // Marks can be stored and read back in
+    doc.add_mark(0, "tab\there \\ back\\t");
+    let stored = save_marks(&doc.marks);
+    assert_eq!(stored.lines().count(), 2);
+    assert_eq!(load_marks(&stored), doc.marks);
+    assert_eq!(load_marks("garbage\n1\t0\n"), vec![]);
+    // Lines are found again in a version of the file edited elsewhere
+    let after = std::fs::read_to_string("tests/data/marks_after.txt").unwrap();
+    let after: Vec<&str> = after.lines().collect();
+    assert_eq!(
+        reanchor(1, "    let name = \"world\";", &after),
+        Some((4, false))
+    );
+    assert_eq!(
+        reanchor(2, "    println!(\"hello {name}\");", &after),
+        Some((6, false))
+    );
+    assert_eq!(
+        reanchor(3, "    let total = add(2, 3);", &after),
+        Some((8, true))
+    );
+    assert_eq!(reanchor(8, "    a + b", &after), Some((13, false)));
+    assert_eq!(reanchor(20, "    a + b", &after), Some((13, false)));
+    assert_eq!(reanchor(2, "unrelated", &after), Some((2, true)));
+    assert_eq!(reanchor(30, "unrelated", &after), Some((14, true)));
+    assert_eq!(reanchor::<&str>(0, "anything", &[]), None);
+    // Identical lines nearest to the mark are picked
+    let lines = ["}", "a", "}", "b", "}"];
+    assert_eq!(reanchor(3, "}", &lines), Some((2, false)));
+    assert_eq!(reanchor(4, "}", &lines), Some((4, false)));
+}
+
 #[test]
 fn document_replacing() {
     let mut doc = Document::open(Size::is(100, 10), "tests/data/unicode.txt").unwrap();
     doc.load_to(1000);
-    doc.replace_all("hello", "你好");
+    // Counting is bounded and leaves the cursor and viewport alone
+    doc.move_to(&Loc { x: 2, y: 3 });
+    let (cursor, offset) = (doc.cursor, doc.offset);
+    assert_eq!(doc.count_matches("hello", 100), 4);
+    assert_eq!(doc.count_matches("hello", 3), 3);
+    assert_eq!(doc.count_matches("l", 100), 9);
+    assert_eq!(doc.count_matches("nowhere", 100), 0);
+    assert_eq!(doc.cursor, cursor);
+    assert_eq!(doc.offset, offset);
+    assert_eq!(doc.line(1), Some(st!("\thello")));
+    // Replacing reports how many were replaced and where the last one was
+    assert_eq!(
+        doc.replace_all("hello", "你好"),
+        (4, Some(Loc { x: 9, y: 4 }))
+    );
+    assert_eq!(doc.replace_all("hello", "hi"), (0, None));
     assert_eq!(doc.line(0), Some(st!("    你好")));
     assert_eq!(doc.line(1), Some(st!("\t你好")));
     assert_eq!(doc.line(2), Some(st!("    你好")));
@@ -914,6 +1618,61 @@ fn document_replacing() {
     assert_eq!(doc.line(4), Some(st!("你好你world好你好")));
 }
 
+#[test]
+fn document_replacing_within() {
+    let mut doc = Document::open(Size::is(100, 10), "tests/data/unicode.txt").unwrap();
+    doc.load_to(1000);
+    // Only matches that lie wholly inside the bounds are found
+    let found = doc.matches_within("hello", Loc::at(0, 2), Loc::at(5, 4));
+    let locs: Vec<Loc> = found.iter().map(|m| m.loc).collect();
+    assert_eq!(locs, vec![Loc::at(4, 2), Loc::at(0, 4)]);
+    assert_eq!(
+        doc.matches_within("hello", Loc::at(5, 2), Loc::at(4, 4)),
+        vec![]
+    );
+    assert_eq!(
+        doc.matches_within("hello", Loc::at(5, 2), Loc::at(18, 4))
+            .len(),
+        2
+    );
+    // Replacing moves the end along with the text on the last line
+    assert_eq!(
+        doc.replace_within("hello", "hi", Loc::at(0, 2), Loc::at(5, 4)),
+        (2, Some(Loc::at(0, 4)), Loc::at(2, 4))
+    );
+    assert_eq!(doc.line(1), Some(st!("\thello")));
+    assert_eq!(doc.line(2), Some(st!("    hi")));
+    assert_eq!(doc.line(4), Some(st!("hi你world好hello")));
+    assert_eq!(
+        doc.replace_within("hello", "hey there", Loc::at(0, 4), Loc::at(15, 4)),
+        (1, Some(Loc::at(9, 4)), Loc::at(19, 4))
+    );
+}
+
+#[test]
+fn document_replacing_until() {
+    let mut doc = Document::open(Size::is(100, 10), "tests/data/unicode.txt").unwrap();
+    doc.load_to(1000);
+    let end = Loc::at(usize::MAX, doc.len_lines());
+    doc.commit();
+    // Replacing can be stopped part of the way through
+    let mut asked = vec![];
+    let (count, last, _) =
+        doc.replace_within_until("hello", "hi", Loc::at(0, 0), end, |done, total| {
+            asked.push((done, total));
+            done < 2
+        });
+    assert_eq!((count, last), (2, Some(Loc::at(4, 2))));
+    assert_eq!(asked, vec![(0, 4), (1, 4), (2, 4)]);
+    assert_eq!(doc.line(1), Some(st!("\thi")));
+    assert_eq!(doc.line(2), Some(st!("    hi")));
+    assert_eq!(doc.line(4), Some(st!("hello你world好hello")));
+    // Rolling back throws away everything since the last commit
+    doc.rollback();
+    assert_eq!(doc.line(1), Some(st!("\thello")));
+    assert_eq!(doc.line(2), Some(st!("    hello")));
+}
+
 #[test]
 fn document_validation() {
     let mut doc = Document::open(Size::is(100, 10), "tests/data/unicode.txt").unwrap();
@@ -952,6 +1711,20 @@ fn file_paths() {
     assert_eq!(get_file_name("src/document.rs"), Some(st!("document.rs")));
     assert_eq!(get_file_ext("tests/data/unicode.txt"), Some(st!("txt")));
     assert_eq!(get_file_ext("src/document.rs"), Some(st!("rs")));
+    // Path identities
+    assert_eq!(
+        get_path_identity("tests/data/unicode.txt"),
+        get_path_identity("tests/../tests/data/unicode.txt")
+    );
+    assert_eq!(
+        get_path_identity("tests/data/ghost_identity.txt"),
+        get_path_identity("tests/data/../data/ghost_identity.txt")
+    );
+    assert!(get_path_identity("ghost_identity.txt").ends_with("ghost_identity.txt"));
+    assert_ne!(
+        get_path_identity("tests/data/unicode.txt"),
+        get_path_identity("tests/data/big.txt")
+    );
 }
 
 #[test]
@@ -1043,8 +1816,8 @@ fn fuzz() {
                     doc.commit();
                     Ok(())
                 }
-                23 => doc.undo(),
-                24 => doc.redo(),
+                23 => doc.undo().map(drop),
+                24 => doc.redo().map(drop),
                 _ => Ok(()),
             };
             println!("{} | {}", doc.loc().x, doc.char_ptr);
@@ -1053,6 +1826,896 @@ fn fuzz() {
     }
 }
 
+#[test]
+fn fuzzy_finding() {
+    // Subsequences
+    assert!(is_subsequence("smr", "src/main.rs"));
+    assert!(is_subsequence("", "src/main.rs"));
+    assert!(!is_subsequence("rsm", "src/main.rs"));
+    assert!(is_subsequence("SMR", "Src/Main.Rs"));
+    assert!(!is_subsequence("SMR", "src/main.rs"));
+    // Matching
+    assert_eq!(fuzzy_match("xyz", "src/main.rs"), None);
+    assert_eq!(fuzzy_match("", "src/main.rs").unwrap().indices, vec![]);
+    assert_eq!(
+        fuzzy_match("main", "src/main.rs").unwrap().indices,
+        vec![4, 5, 6, 7]
+    );
+    assert_eq!(
+        fuzzy_match("MAIN", "src/MAIN.rs").unwrap().indices,
+        vec![4, 5, 6, 7]
+    );
+    assert_eq!(fuzzy_match("Main", "src/main.rs"), None);
+    assert_eq!(fuzzy_match("é", "É.txt").unwrap().indices, vec![0]);
+    // Word starts are preferred over the first occurrence
+    assert_eq!(
+        fuzzy_match("em", "src/editor/mod.rs").unwrap().indices,
+        vec![4, 11]
+    );
+    assert_eq!(fuzzy_match("fb", "foo_bar").unwrap().indices, vec![0, 4]);
+    assert_eq!(fuzzy_match("fb", "fooBar").unwrap().indices, vec![0, 3]);
+    // Highlight spans
+    assert_eq!(highlight_spans(&[]), Vec::<Range<usize>>::new());
+    assert_eq!(highlight_spans(&[4, 5, 6, 7]), vec![4..8]);
+    assert_eq!(highlight_spans(&[0, 3, 4, 9]), vec![0..1, 3..5, 9..10]);
+    // Orderings
+    let candidates = vec![
+        "src/editor/mod.rs",
+        "src/main.rs",
+        "kaolinite/src/document/mod.rs",
+        "README.md",
+        "src/editor/interface.rs",
+        "config/.oxrc",
+        "src/config/editor.rs",
+        "domain/main_thing.rs",
+        "src/ui.rs",
+    ];
+    let order = |ranker: &mut FuzzyRanker, pattern: &str| -> Vec<&str> {
+        ranker
+            .rank(pattern, &candidates, 15)
+            .iter()
+            .map(|(idx, _)| candidates[*idx])
+            .collect()
+    };
+    let mut ranker = FuzzyRanker::default();
+    assert_eq!(
+        order(&mut ranker, "main"),
+        vec!["src/main.rs", "domain/main_thing.rs"]
+    );
+    assert_eq!(
+        order(&mut ranker, "edmod"),
+        vec!["src/editor/mod.rs", "kaolinite/src/document/mod.rs"]
+    );
+    assert_eq!(order(&mut ranker, "ui"), vec!["src/ui.rs"]);
+    assert_eq!(
+        order(&mut ranker, "rdm"),
+        vec![
+            "README.md",
+            "kaolinite/src/document/mod.rs",
+            "src/editor/mod.rs"
+        ]
+    );
+    // Ties go to the shorter path
+    assert_eq!(
+        order(&mut ranker, "mod"),
+        vec!["src/editor/mod.rs", "kaolinite/src/document/mod.rs"]
+    );
+    assert_eq!(order(&mut ranker, "").len(), candidates.len());
+    assert_eq!(ranker.rank("s", &candidates, 2).len(), 2);
+    // Narrowing down gives the same results as starting afresh
+    let mut ranker = FuzzyRanker::default();
+    let mut pattern = String::new();
+    for c in "srced".chars() {
+        pattern.push(c);
+        let narrowed = ranker.rank(&pattern, &candidates, 15);
+        let fresh = FuzzyRanker::default().rank(&pattern, &candidates, 15);
+        assert_eq!(narrowed, fresh);
+    }
+    ranker.reset();
+    assert_eq!(order(&mut ranker, "ui"), vec!["src/ui.rs"]);
+    // A large project, narrowed down as the pattern is typed
+    let mut project = vec![];
+    for a in 0..50 {
+        for b in 0..40 {
+            for f in 0..25 {
+                project.push(format!("dir{a}/sub_{b}/module{f}_file.rs"));
+            }
+        }
+    }
+    let mut ranker = FuzzyRanker::default();
+    let mut pattern = String::new();
+    for c in "d3s1m2fil".chars() {
+        pattern.push(c);
+        let results = ranker.rank(&pattern, &project, 15);
+        assert_eq!(results.len(), 15);
+    }
+    let results = ranker.rank("d3s1m2fil", &project, 15);
+    assert_eq!(project[results[0].0], "dir3/sub_1/module2_file.rs");
+}
+
+#[test]
+fn backups() {
+    // Naming copies after the time they were taken
+    assert_eq!(backup_name(0), st!("19700101T000000Z"));
+    assert_eq!(backup_name(1_730_396_700), st!("20241031T174500Z"));
+    assert_eq!(backup_name(951_782_400), st!("20000229T000000Z"));
+    assert_eq!(parse_backup_name("20241031T174500Z"), Some(1_730_396_700));
+    for time in [0, 951_782_400, 1_709_164_800, 4_102_444_799] {
+        assert_eq!(parse_backup_name(&backup_name(time)), Some(time));
+    }
+    assert_eq!(parse_backup_name("20241331T174500Z"), None);
+    assert_eq!(parse_backup_name("20241031T246000Z"), None);
+    assert_eq!(parse_backup_name("2024-10-31"), None);
+    assert_eq!(parse_backup_name("path"), None);
+    // Names sort in the same order as the times they were taken
+    assert!(backup_name(999_999_999) < backup_name(1_000_000_000));
+    // Describing copies
+    assert_eq!(format_time(1_730_396_700), st!("2024-10-31 17:45:00"));
+    assert_eq!(format_size(512), st!("512 B"));
+    assert_eq!(format_size(1536), st!("1.5 KB"));
+    assert_eq!(format_size(3 * 1024 * 1024), st!("3.0 MB"));
+    // Taking copies on a timer
+    assert!(backup_due(100, 400, 300));
+    assert!(!backup_due(100, 399, 300));
+    assert!(!backup_due(100, 10_000, 0));
+    // Pruning by count and by size (the newest are kept)
+    let backup = |name: &str, time, size| Backup {
+        name: st!(name),
+        time,
+        size,
+    };
+    let stored = vec![
+        backup("a", 1, 10),
+        backup("c", 3, 10),
+        backup("b", 2, 10),
+        backup("d", 4, 10),
+    ];
+    assert_eq!(prune(&stored, 10, 1000), Vec::<String>::new());
+    assert_eq!(prune(&stored, 2, 1000), vec![st!("b"), st!("a")]);
+    assert_eq!(prune(&stored, 10, 25), vec![st!("b"), st!("a")]);
+    assert_eq!(prune(&stored, 3, 20), vec![st!("b"), st!("a")]);
+    // The newest copy is always kept
+    assert_eq!(prune(&[backup("big", 1, 100)], 0, 1), Vec::<String>::new());
+    assert_eq!(prune(&stored, 0, 0), vec![st!("c"), st!("b"), st!("a")]);
+}
+
+#[test]
+fn diffing() {
+    // Line differences
+    assert_eq!(
+        diff_lines(&["a", "b", "c", "d"], &["a", "x", "c", "d", "e"]),
+        vec![
+            DiffLine::Same(st!("a")),
+            DiffLine::Removed(st!("b")),
+            DiffLine::Added(st!("x")),
+            DiffLine::Same(st!("c")),
+            DiffLine::Same(st!("d")),
+            DiffLine::Added(st!("e")),
+        ]
+    );
+    assert_eq!(
+        diff_lines::<&str>(&[], &["a"]),
+        vec![DiffLine::Added(st!("a"))]
+    );
+    assert_eq!(
+        diff_lines(&["a", "b"], &["a", "b"]),
+        vec![DiffLine::Same(st!("a")), DiffLine::Same(st!("b"))]
+    );
+    // Unified diffs
+    let old = "1\n2\n3\n4\n5\n6\n7\n8\n9\n";
+    let new = "1\n2\n3\nfour\n5\n6\n7\n8\n9\nten\n";
+    assert_eq!(
+        unified_diff(old, new, "old", "new", 2),
+        st!("--- old\n+++ new\n@@ -2,5 +2,5 @@\n 2\n 3\n-4\n+four\n 5\n 6\n@@ -8,2 +8,3 @@\n 8\n 9\n+ten\n")
+    );
+    // Changes close together share a hunk
+    assert_eq!(
+        unified_diff(old, new, "old", "new", 3),
+        st!(
+            "--- old\n+++ new\n@@ -1,9 +1,10 @@\n 1\n 2\n 3\n-4\n+four\n 5\n 6\n 7\n 8\n 9\n+ten\n"
+        )
+    );
+    assert_eq!(
+        unified_diff("", "a\n", "old", "new", 3),
+        st!("--- old\n+++ new\n@@ -0,0 +1,1 @@\n+a\n")
+    );
+    assert_eq!(unified_diff("a\n", "a\n", "old", "new", 3), st!(""));
+    // Side by side diffs pair removed lines with the ones added in their place
+    let row = |old, new, kind| DiffRow { old, new, kind };
+    let rows = side_by_side(&["a", "b", "c", "d"], &["a", "x", "y", "c"]);
+    assert_eq!(
+        rows,
+        vec![
+            row(Some(0), Some(0), RowKind::Same),
+            row(Some(1), Some(1), RowKind::Changed),
+            row(None, Some(2), RowKind::Added),
+            row(Some(2), Some(3), RowKind::Same),
+            row(Some(3), None, RowKind::Removed),
+        ]
+    );
+    assert_eq!(hunks(&rows), vec![1..3, 4..5]);
+    assert!(hunks(&side_by_side(&["a"], &["a"])).is_empty());
+    // Replacing the contents of a document in one undoable step
+    let mut doc = Document::new(Size::is(100, 10));
+    doc.exe(Event::Insert(Loc { x: 0, y: 0 }, st!("hello world")))
+        .unwrap();
+    doc.move_to(&Loc { x: 6, y: 0 });
+    doc.commit();
+    doc.replace_contents("first\nsecond line\n");
+    doc.commit();
+    assert_eq!(doc.line(1), Some(st!("second line")));
+    assert_eq!(doc.char_loc(), Loc { x: 5, y: 0 });
+    // (a new document starts with a line break, which comes back too)
+    doc.undo().unwrap();
+    assert_eq!(doc.file.to_string(), st!("hello world\n"));
+    doc.redo().unwrap();
+    assert_eq!(doc.file.to_string(), st!("first\nsecond line\n"));
+}
+
+#[test]
+fn signs() {
+    // Reading severities
+    assert_eq!(Severity::parse("Error"), Severity::Error);
+    assert_eq!(Severity::parse("warn"), Severity::Warning);
+    assert_eq!(Severity::parse("help"), Severity::Hint);
+    assert_eq!(Severity::parse("note"), Severity::Info);
+    assert!(Severity::Error < Severity::Warning);
+    // Reading entries (1 based lines become 0 based, severity defaults to error)
+    let (entries, skipped) = parse_entries(r#"[{"file": "a.rs", "line": 3, "message": "x"}]"#);
+    assert_eq!(
+        entries,
+        vec![SignEntry {
+            file: Some(st!("a.rs")),
+            line: 2,
+            severity: Severity::Error,
+            message: st!("x"),
+        }]
+    );
+    assert_eq!(skipped, 0);
+    assert_eq!(parse_entries("[]"), (vec![], 0));
+    assert_eq!(parse_entries(""), (vec![], 0));
+    // Malformed entries are skipped without losing the rest
+    let (entries, skipped) = parse_entries(
+        r#"[{"line": 1}, {"line": tru}, {"line": 2, "message": "ok"}, {"line": 0}, "text", {"line": 3},]"#,
+    );
+    assert_eq!(
+        entries.iter().map(|e| e.line).collect::<Vec<_>>(),
+        vec![0, 1, 2]
+    );
+    assert_eq!(skipped, 3);
+    let (entries, skipped) = parse_entries(r#"[{"line": 1}, } {"line": 2}]"#);
+    assert_eq!(entries.len(), 2);
+    assert_eq!(skipped, 1);
+    // A file cut short keeps what was read before it broke off
+    let (entries, skipped) = parse_entries(r#"[{"line": 5, "message": "a"}, {"line": 6, "mess"#);
+    assert_eq!(entries.len(), 1);
+    assert_eq!(skipped, 1);
+    // One object per line (as jq -c writes out), with a broken line in the middle
+    let text = "{\"line\": 1, \"severity\": \"warning\"}\n{\"line\": 2,\n{\"line\": 3}\n";
+    let (entries, skipped) = parse_entries(text);
+    assert_eq!(
+        entries.iter().map(|e| e.line).collect::<Vec<_>>(),
+        vec![0, 2]
+    );
+    assert_eq!(entries[0].severity, Severity::Warning);
+    assert_eq!(skipped, 1);
+    // Escapes in strings
+    let (entries, _) = parse_entries(r#"[{"line": 1, "message": "a\tb \"c\" \u00e9 \\"}]"#);
+    assert_eq!(entries[0].message, st!("a\tb \"c\" é \\"));
+    // Indexing signs by the canonical path of their file
+    let mut index = SignIndex::default();
+    let text = std::fs::read_to_string("tests/data/signs.json").unwrap();
+    let skipped = index.load("tests/data/signs.json", &text, None);
+    assert_eq!(skipped, 3);
+    let unicode = index.for_file("tests/data/unicode.txt");
+    assert_eq!(
+        unicode
+            .iter()
+            .map(|s| (s.line, s.severity))
+            .collect::<Vec<_>>(),
+        vec![
+            (0, Severity::Error),
+            (1, Severity::Error),
+            (1, Severity::Warning),
+            (39, Severity::Error)
+        ]
+    );
+    assert_eq!(unicode[3].message, st!("\"quoted\" é"));
+    assert_eq!(unicode[0].file, get_path_identity("tests/data/unicode.txt"));
+    assert_eq!(index.for_file("./tests/data/../data/unicode.txt").len(), 4);
+    let crlf = index.for_file("tests/data/crlf.txt");
+    assert_eq!(crlf.len(), 1);
+    assert_eq!((crlf[0].line, crlf[0].severity), (2, Severity::Info));
+    assert!(index.for_file("tests/data/empty.txt").is_empty());
+    // Entries without a file go in the file the sign file sits beside
+    index.load(
+        "tests/data/empty.txt.ox-signs.json",
+        r#"[{"line": 1, "message": "here"}]"#,
+        Some("tests/data/empty.txt"),
+    );
+    assert_eq!(index.for_file("tests/data/empty.txt").len(), 1);
+    assert_eq!(index.sources().len(), 2);
+    // Reloading a sign file replaces its signs, removing it forgets them
+    index.load("tests/data/signs.json", "[]", None);
+    assert!(index.for_file("tests/data/unicode.txt").is_empty());
+    assert!(index.remove("tests/data/empty.txt.ox-signs.json"));
+    assert!(!index.remove("tests/data/empty.txt.ox-signs.json"));
+    assert!(index.for_file("tests/data/empty.txt").is_empty());
+    // Signs beyond the end of a file are moved onto its last line and flagged
+    let clamped = clamp_signs(&unicode, 10);
+    assert_eq!(clamped[3].line, 9);
+    assert!(clamped[3].stale);
+    assert!(!clamped[0].stale);
+    assert_eq!(clamp_signs(&unicode, 0)[0].line, 0);
+    // Finding the signs on a line, the most severe first
+    let on = signs_on(&unicode, 1, 10);
+    assert_eq!(on.len(), 2);
+    assert_eq!(on[0].message, st!("cannot find value"));
+    assert_eq!(signs_on(&unicode, 9, 10).len(), 1);
+    assert!(signs_on(&unicode, 9, 100).is_empty());
+}
+
+#[test]
+fn document_generation() {
+    let mut doc = Document::open(Size::is(100, 10), "tests/data/unicode.txt").unwrap();
+    doc.load_to(100);
+    doc.commit();
+    // Moving around and loading lines leaves the contents (and generation) alone
+    let start = doc.generation;
+    doc.move_down();
+    doc.select_right();
+    doc.load_to(1000);
+    assert_eq!(doc.generation, start);
+    // Every kind of edit moves it on
+    let mut last = start;
+    let mut moved_on = |doc: &Document| {
+        let result = doc.generation > last;
+        last = doc.generation;
+        result
+    };
+    doc.exe(Event::Insert(Loc { x: 0, y: 0 }, st!("a")))
+        .unwrap();
+    assert!(moved_on(&doc));
+    doc.exe(Event::Delete(Loc { x: 0, y: 0 }, st!("a")))
+        .unwrap();
+    assert!(moved_on(&doc));
+    doc.exe(Event::InsertLine(1, st!("line"))).unwrap();
+    assert!(moved_on(&doc));
+    doc.exe(Event::DeleteLine(1, st!("line"))).unwrap();
+    assert!(moved_on(&doc));
+    doc.exe(Event::SplitDown(Loc { x: 1, y: 0 })).unwrap();
+    assert!(moved_on(&doc));
+    doc.exe(Event::SpliceUp(Loc { x: 1, y: 0 })).unwrap();
+    assert!(moved_on(&doc));
+    // (the edits above cancel out, so make a real change for there to be something to undo)
+    doc.exe(Event::Insert(Loc { x: 0, y: 0 }, st!("b")))
+        .unwrap();
+    assert!(moved_on(&doc));
+    doc.commit();
+    // Undoing, redoing and replacing the contents do too
+    doc.undo().unwrap();
+    assert!(moved_on(&doc));
+    doc.redo().unwrap();
+    assert!(moved_on(&doc));
+    doc.replace_contents("new contents\n");
+    assert!(moved_on(&doc));
+}
+
+#[test]
+fn status_stats() {
+    let mut doc = Document::new(Size::is(100, 10));
+    doc.replace_contents("one two  three\n\tfour你好 five\n");
+    assert_eq!(doc.word_count(), 5);
+    // Nothing is selected to begin with
+    assert_eq!(doc.selection_size(), None);
+    doc.move_to(&Loc { x: 4, y: 0 });
+    doc.select_to(&Loc { x: 7, y: 0 });
+    assert_eq!(doc.selection_size(), Some((3, 1)));
+    // Selections over several lines count the line breaks, in either direction
+    doc.move_to(&Loc { x: 4, y: 1 });
+    doc.select_to(&Loc { x: 4, y: 0 });
+    assert_eq!(doc.selection_size(), Some((15, 2)));
+    // The rest of the figures come from the same pass over the text
+    let stats = doc.stats();
+    assert_eq!((stats.lines, stats.words), (2, 5));
+    assert_eq!((stats.chars, stats.chars_without_whitespace), (28, 21));
+    assert_eq!(stats.bytes, 32);
+    let selected = doc.selection_stats().unwrap();
+    assert_eq!((selected.lines, selected.words, selected.chars), (2, 3, 15));
+    doc.replace_contents("no line break");
+    assert_eq!(doc.stats().lines, 1);
+    doc.replace_contents("");
+    assert_eq!(doc.word_count(), 0);
+    assert_eq!(doc.stats(), Stats::default());
+}
+
+#[test]
+fn line_operations() {
+    let mut doc = Document::new(Size::is(100, 10));
+    // Joining collapses the indentation of the joined line into a single space
+    doc.replace_contents("one\n    two\nthree\n");
+    doc.move_to(&Loc { x: 0, y: 0 });
+    doc.join_lines().unwrap();
+    assert_eq!(doc.line(0), Some(st!("one two")));
+    assert_eq!(doc.line(1), Some(st!("three")));
+    assert_eq!(doc.char_loc(), Loc { x: 3, y: 0 });
+    // Every selected line is joined into one
+    doc.replace_contents("a\n  b\n\tc\nd\n");
+    doc.move_to(&Loc { x: 0, y: 0 });
+    doc.select_to(&Loc { x: 1, y: 2 });
+    doc.join_lines().unwrap();
+    assert_eq!(doc.line(0), Some(st!("a b c")));
+    assert_eq!(doc.line(1), Some(st!("d")));
+    // Sorting leaves out a line the selection only reaches the start of
+    doc.replace_contents("pear\napple\npear\nfig\nbanana\n");
+    doc.move_to(&Loc { x: 0, y: 0 });
+    doc.select_to(&Loc { x: 0, y: 4 });
+    doc.sort_lines(false, false).unwrap();
+    let lines: Vec<String> = (0..5).filter_map(|y| doc.line(y)).collect();
+    assert_eq!(lines, vec!["apple", "fig", "pear", "pear", "banana"]);
+    assert_eq!(doc.selection_text(), "apple\nfig\npear\npear");
+    // In reverse and without duplicates, as one undoable change
+    doc.commit();
+    doc.sort_lines(true, true).unwrap();
+    doc.commit();
+    let lines: Vec<String> = (0..4).filter_map(|y| doc.line(y)).collect();
+    assert_eq!(lines, vec!["pear", "fig", "apple", "banana"]);
+    doc.undo().unwrap();
+    let lines: Vec<String> = (0..5).filter_map(|y| doc.line(y)).collect();
+    assert_eq!(lines, vec!["apple", "fig", "pear", "pear", "banana"]);
+    // Changing case keeps the changed text selected
+    doc.replace_contents("hello World\nsecond line\n");
+    doc.move_to(&Loc { x: 6, y: 0 });
+    doc.select_to(&Loc { x: 6, y: 1 });
+    doc.transform_selection(str::to_uppercase).unwrap();
+    assert_eq!(doc.line(0), Some(st!("hello WORLD")));
+    assert_eq!(doc.line(1), Some(st!("SECOND line")));
+    assert_eq!(doc.selection_text(), "WORLD\nSECOND");
+    doc.transform_selection(str::to_lowercase).unwrap();
+    assert_eq!(doc.selection_text(), "world\nsecond");
+    // Even when the text changes length
+    doc.replace_contents("straße\n");
+    doc.move_to(&Loc { x: 0, y: 0 });
+    doc.select_to(&Loc { x: 6, y: 0 });
+    doc.transform_selection(str::to_uppercase).unwrap();
+    assert_eq!(doc.line(0), Some(st!("STRASSE")));
+    assert_eq!(doc.selection_text(), "STRASSE");
+}
+
+#[test]
+fn document_bookmarks() {
+    let mut doc = Document::new(Size::is(100, 10));
+    doc.replace_contents("zero\none\ntwo\nthree\nfour\n");
+    assert_eq!(doc.next_bookmark(0, true), None);
+    assert!(doc.toggle_bookmark(1));
+    assert!(doc.toggle_bookmark(3));
+    // A line can have a bookmark and an annotation at once
+    doc.add_mark(3, "note");
+    assert_eq!(doc.bookmark_at(3).unwrap().text, st!("three"));
+    assert_eq!(doc.mark_at(3).unwrap().label, st!("note"));
+    assert_eq!(doc.bookmarks().count(), 2);
+    // Jumping between bookmarks wraps around the ends of the document
+    assert_eq!(doc.next_bookmark(1, true), Some(3));
+    assert_eq!(doc.next_bookmark(3, true), Some(1));
+    assert_eq!(doc.next_bookmark(1, false), Some(3));
+    assert_eq!(doc.next_bookmark(2, false), Some(1));
+    // Bookmarks follow their lines as lines are inserted and removed above them
+    doc.exe(Event::InsertLine(0, st!("new"))).unwrap();
+    assert_eq!(doc.bookmarks().map(|m| m.y).collect::<Vec<_>>(), vec![2, 4]);
+    doc.exe(Event::DeleteLine(1, st!("zero"))).unwrap();
+    assert_eq!(doc.bookmarks().map(|m| m.y).collect::<Vec<_>>(), vec![1, 3]);
+    assert_eq!(doc.mark_at(3).unwrap().label, st!("note"));
+    doc.move_to(&Loc::at(0, 3));
+    doc.swap_line_up().unwrap();
+    assert!(doc.bookmark_at(2).is_some() && doc.mark_at(2).is_some());
+    doc.exe(Event::SpliceUp(Loc::at(0, 1))).unwrap();
+    assert_eq!(doc.bookmarks().map(|m| m.y).collect::<Vec<_>>(), vec![1]);
+    assert_eq!(doc.mark_at(1).unwrap().label, st!("note"));
+    // Removing an annotation leaves the bookmark alone
+    doc.remove_mark(1);
+    assert!(doc.bookmark_at(1).is_some());
+    assert!(!doc.toggle_bookmark(1));
+    assert_eq!(doc.bookmarks().count(), 0);
+}
+
+#[test]
+fn change_markers() {
+    let mut doc = Document::new(Size::is(100, 10));
+    // Nothing is marked until there is a saved version to compare against
+    doc.exe(Event::Insert(Loc::at(0, 0), st!("hello"))).unwrap();
+    doc.refresh_changes(0..10);
+    assert_eq!(doc.line_change(0), None);
+    doc.replace_contents("one\ntwo\nthree\nfour\n");
+    doc.refresh_changes(0..10);
+    assert!((0..5).all(|y| doc.line_change(y).is_none()));
+    doc.commit();
+    // Edits are marked once the lines they touched are worked out again
+    doc.exe(Event::Insert(Loc::at(3, 0), st!("!"))).unwrap();
+    doc.exe(Event::InsertLine(2, st!("new"))).unwrap();
+    doc.exe(Event::DeleteLine(4, st!("four"))).unwrap();
+    assert_eq!(doc.line_change(0), None);
+    doc.refresh_changes(0..10);
+    assert_eq!(
+        (0..4).map(|y| doc.line_change(y)).collect::<Vec<_>>(),
+        vec![
+            Some(LineChange::Modified),
+            None,
+            Some(LineChange::Added),
+            Some(LineChange::RemovedBelow),
+        ]
+    );
+    doc.exe(Event::DeleteLine(0, st!("one!"))).unwrap();
+    doc.refresh_changes(0..10);
+    assert_eq!(doc.line_change(0), Some(LineChange::RemovedAbove));
+    // Undoing back to the saved version clears the markers
+    doc.commit();
+    doc.undo().unwrap();
+    doc.refresh_changes(0..10);
+    assert!((0..5).all(|y| doc.line_change(y).is_none()));
+    // As does saving
+    doc.exe(Event::Insert(Loc::at(0, 1), st!("2"))).unwrap();
+    doc.refresh_changes(0..10);
+    assert_eq!(doc.line_change(1), Some(LineChange::Modified));
+    doc.changes.reset(&doc.file);
+    doc.refresh_changes(0..10);
+    assert_eq!(doc.line_change(1), None);
+}
+
+#[test]
+fn indentation_conversion() {
+    // Tabs expand to the next tab stop
+    assert_eq!(expand_tabs("\tx", 0, 4), "    x");
+    assert_eq!(expand_tabs("ab\tc", 0, 4), "ab  c");
+    assert_eq!(expand_tabs("\tc", 6, 4), "  c");
+    let mut doc = Document::new(Size::is(100, 10));
+    doc.set_tab_width(4);
+    doc.replace_contents("fn main() {\n\tlet s = \"a\tb\";\n  \tx\n\t\t\n}\n");
+    doc.move_to(&Loc { x: 5, y: 1 });
+    // Converting to spaces leaves tabs after the indentation alone
+    doc.commit();
+    let changed = doc.convert_indentation(true, false).unwrap();
+    doc.commit();
+    assert_eq!(changed, vec![1, 2, 3]);
+    assert_eq!(doc.line(1), Some(st!("    let s = \"a\tb\";")));
+    assert_eq!(doc.line(2), Some(st!("    x")));
+    assert_eq!(doc.line(3), Some(st!("        ")));
+    assert_eq!(doc.char_loc(), Loc { x: 8, y: 1 });
+    // Converting back leaves what doesn't make up a full tab as spaces
+    doc.replace_contents("      a\n    b\tc\n  d\n");
+    let changed = doc.convert_indentation(false, false).unwrap();
+    assert_eq!(changed, vec![0, 1]);
+    assert_eq!(doc.line(0), Some(st!("\t  a")));
+    assert_eq!(doc.line(1), Some(st!("\tb\tc")));
+    assert_eq!(doc.line(2), Some(st!("  d")));
+    // Nothing changes if the indentation is already converted
+    assert!(doc.convert_indentation(false, false).unwrap().is_empty());
+    // Tabs anywhere can be converted when asked for
+    let changed = doc.convert_indentation(true, true).unwrap();
+    assert_eq!(changed, vec![0, 1]);
+    assert_eq!(doc.line(1), Some(st!("    b   c")));
+    // The conversion is a single undoable change
+    doc.replace_contents("\ta\n\tb\n");
+    doc.commit();
+    doc.convert_indentation(true, false).unwrap();
+    doc.commit();
+    doc.undo().unwrap();
+    assert_eq!(doc.line(0), Some(st!("\ta")));
+    assert_eq!(doc.line(1), Some(st!("\tb")));
+}
+
+#[test]
+fn goal_column() {
+    let mut doc = Document::new(Size::is(100, 10));
+    doc.replace_contents("a long line here\n\nshort\n你好世界 and more\nx\n");
+    // Passing over blank and short lines keeps the column to go back to
+    doc.move_to(&Loc { x: 10, y: 0 });
+    doc.move_down();
+    assert_eq!(doc.loc(), Loc { x: 0, y: 1 });
+    doc.move_down();
+    assert_eq!(doc.loc(), Loc { x: 5, y: 2 });
+    doc.move_down();
+    assert_eq!(doc.loc(), Loc { x: 10, y: 3 });
+    assert_eq!(doc.char_loc(), Loc { x: 6, y: 3 });
+    doc.move_up();
+    doc.move_up();
+    assert_eq!(doc.loc(), Loc { x: 0, y: 1 });
+    doc.move_up();
+    assert_eq!(doc.loc(), Loc { x: 10, y: 0 });
+    // Landing in the middle of a wide character moves to its start, but not for good
+    doc.move_to(&Loc { x: 3, y: 0 });
+    doc.move_down();
+    doc.move_down();
+    doc.move_down();
+    assert_eq!(doc.loc(), Loc { x: 2, y: 3 });
+    doc.move_down();
+    assert_eq!(doc.loc(), Loc { x: 1, y: 4 });
+    doc.move_up();
+    assert_eq!(doc.loc(), Loc { x: 2, y: 3 });
+    doc.move_up();
+    assert_eq!(doc.loc(), Loc { x: 3, y: 2 });
+    // Selecting up and down remembers the column too
+    doc.select_up();
+    doc.select_down();
+    doc.select_down();
+    assert_eq!(doc.loc(), Loc { x: 2, y: 3 });
+    assert!(!doc.is_selection_empty());
+    doc.select_up();
+    assert_eq!(doc.loc(), Loc { x: 3, y: 2 });
+    doc.cancel_selection();
+    // Moving sideways sets a new column
+    doc.move_to(&Loc { x: 10, y: 0 });
+    doc.move_down();
+    doc.move_down();
+    doc.move_left();
+    doc.move_down();
+    assert_eq!(doc.loc(), Loc { x: 4, y: 3 });
+    // As does editing
+    doc.move_to(&Loc { x: 10, y: 0 });
+    doc.move_down();
+    doc.move_down();
+    doc.exe(Event::Insert(doc.char_loc(), st!("!"))).unwrap();
+    doc.move_up();
+    doc.move_up();
+    assert_eq!(doc.loc(), Loc { x: 6, y: 0 });
+    // Smart home goes to the indentation first, then the start of the line
+    doc.replace_contents("    indented\n");
+    doc.move_to(&Loc { x: 8, y: 0 });
+    doc.move_smart_home();
+    assert_eq!(doc.loc(), Loc { x: 4, y: 0 });
+    doc.move_smart_home();
+    assert_eq!(doc.loc(), Loc { x: 0, y: 0 });
+    doc.move_smart_home();
+    assert_eq!(doc.loc(), Loc { x: 4, y: 0 });
+    doc.move_to(&Loc { x: 8, y: 0 });
+    doc.select_smart_home();
+    assert_eq!(doc.selection_text(), "inde");
+}
+
+#[test]
+fn block_editing() {
+    let mut doc = Document::new(Size::is(100, 10));
+    doc.replace_contents("abcdef\nab\nabcdef");
+    doc.move_to(&Loc { x: 1, y: 0 });
+    doc.block_down();
+    doc.block_down();
+    doc.block_right();
+    doc.block_right();
+    assert_eq!(doc.block_bounds(), Some((0..3, 1..3)));
+    assert!(doc.is_loc_selected(Loc { x: 2, y: 1 }));
+    assert!(!doc.is_loc_selected(Loc { x: 0, y: 1 }));
+    assert!(!doc.is_loc_selected(Loc { x: 3, y: 0 }));
+    // Copying gives a line for each row
+    assert_eq!(doc.block_text(false), Some(st!("bc\nb\nbc")));
+    assert_eq!(doc.block_text(true), Some(st!("bc\nb \nbc")));
+    // Deleting leaves a block with no width to type into
+    doc.remove_block().unwrap();
+    assert_eq!(doc.block_size(), Some((3, 0)));
+    doc.insert_block("X", false).unwrap();
+    assert_eq!(doc.line(0), Some(st!("aXdef")));
+    assert_eq!(doc.line(1), Some(st!("aX")));
+    assert_eq!(doc.line(2), Some(st!("aXdef")));
+    assert_eq!(doc.block_bounds(), Some((0..3, 2..2)));
+    // Short lines are padded or skipped
+    doc.exit_block();
+    doc.move_to(&Loc { x: 4, y: 0 });
+    doc.block_down();
+    doc.block_down();
+    doc.insert_block("|", true).unwrap();
+    assert_eq!(doc.line(0), Some(st!("aXde|f")));
+    assert_eq!(doc.line(1), Some(st!("aX  |")));
+    doc.backspace_block().unwrap();
+    assert_eq!(doc.line(1), Some(st!("aX  ")));
+    assert_eq!(doc.line(2), Some(st!("aXdef")));
+    doc.exit_block();
+    doc.move_to(&Loc { x: 5, y: 0 });
+    doc.block_down();
+    doc.block_down();
+    doc.insert_block("!", false).unwrap();
+    assert_eq!(doc.line(0), Some(st!("aXdef!")));
+    assert_eq!(doc.line(1), Some(st!("aX  ")));
+    assert_eq!(doc.line(2), Some(st!("aXdef!")));
+    doc.exit_block();
+    assert_eq!(doc.loc(), Loc { x: 6, y: 0 });
+    assert_eq!(doc.block_size(), None);
+}
+
+#[test]
+fn spell_checking() {
+    let aff = "PFX A Y 1\nPFX A 0 re .\nSFX D Y 3\nSFX D 0 d e\nSFX D y ied [^aeiou]y\nSFX D 0 ed [^ey]\n";
+    let dict = Dictionary::from_hunspell("3\nwork/AD\ncarry/D\nLondon\n", aff);
+    assert!(dict.check("work"));
+    assert!(dict.check("reworked"));
+    assert!(dict.check("carried"));
+    assert!(!dict.check("carryed"));
+    assert!(dict.check("Work"));
+    assert!(dict.check("WORKED"));
+    assert!(dict.check("LONDON"));
+    assert!(!dict.check("london"));
+    assert!(!dict.check("wOrk"));
+    assert_eq!(dict.suggest("wrok", 5), vec![st!("work")]);
+    assert_eq!(
+        dict.suggest("Carryed", 5),
+        vec![st!("Carried"), st!("Carry")]
+    );
+    let mut dict = Dictionary::from_word_list("the\ncat\ndon't\n");
+    let line = "// teh cat_name x2 HashMap don’t https://teh.com teh";
+    let words: Vec<String> = words(line)
+        .into_iter()
+        .map(|r| line.chars().skip(r.start).take(r.len()).collect())
+        .collect();
+    assert_eq!(words, vec!["teh", "don’t", "teh"]);
+    assert_eq!(misspellings(line, &[0..10], &dict), vec![3..6]);
+    assert_eq!(
+        misspellings(line, &[0..line.len()], &dict),
+        vec![3..6, 49..52]
+    );
+    dict.insert("teh");
+    assert!(misspellings(line, &[0..line.len()], &dict).is_empty());
+}
+
+#[test]
+fn markdown() {
+    let plain = Style::default();
+    let strong = Style {
+        strong: true,
+        ..plain
+    };
+    // Inline markup
+    assert_eq!(
+        inline("a **b** `c` [d](e) snake_case *f*"),
+        vec![
+            (st!("a "), plain),
+            (st!("b"), strong),
+            (st!(" "), plain),
+            (
+                st!("c"),
+                Style {
+                    code: true,
+                    ..plain
+                }
+            ),
+            (st!(" "), plain),
+            (
+                st!("d"),
+                Style {
+                    link: true,
+                    ..plain
+                }
+            ),
+            (st!(" snake_case "), plain),
+            (
+                st!("f"),
+                Style {
+                    emphasis: true,
+                    ..plain
+                }
+            ),
+        ]
+    );
+    assert_eq!(
+        inline(r"\*not emphasis\*"),
+        vec![(st!("*not emphasis*"), plain)]
+    );
+    // Blocks
+    let doc = "# Title #\n\nSome words that wrap\naround\n\n- one\n  - two\n1. three\n\n> quoted\n---\n```rust\nfn main() {}\n```";
+    let lines = render(doc, 12);
+    let text: Vec<String> = lines.iter().map(Line::text).collect();
+    assert_eq!(
+        text,
+        vec![
+            "Title",
+            "",
+            "Some words",
+            "that wrap",
+            "around",
+            "",
+            "• one",
+            "  • two",
+            "1. three",
+            "",
+            "│ quoted",
+            "────────────",
+            "fn main() {}",
+        ]
+    );
+    assert_eq!(lines[0].kind, LineKind::Heading(1));
+    assert_eq!(lines[2].source, 2);
+    assert_eq!(lines[8].source, 7);
+    assert_eq!(lines[10].kind, LineKind::Quote);
+    assert_eq!(lines[11].kind, LineKind::Rule);
+    assert_eq!(lines[12].kind, LineKind::Code(Some(st!("rust"))));
+    assert_eq!(lines[12].source, 12);
+    // Long list items keep their indentation when they wrap
+    let text: Vec<String> = render("- a list item", 8).iter().map(Line::text).collect();
+    assert_eq!(text, vec!["• a list", "  item"]);
+}
+
+#[test]
+fn word_classes() {
+    let mut doc = Document::new(Size::is(100, 10));
+    doc.replace_contents("let parseHTTPRequest = snake_case2Value(kebab-case, été_Ünïcode);\n");
+    let line = doc.line(0).unwrap();
+    let parts = |bounds: Vec<(usize, usize)>| -> Vec<String> {
+        bounds
+            .iter()
+            .map(|(s, e)| line[*s..*e].to_string())
+            .collect()
+    };
+    // Words hold letters of any kind, digits and underscores, and end at separators
+    assert_eq!(
+        parts(doc.word_boundaries(&line)),
+        [
+            "let",
+            "parseHTTPRequest",
+            "snake_case2Value",
+            "kebab",
+            "case",
+            "été_Ünïcode"
+        ]
+    );
+    // Subwords are also split at underscores and changes of case
+    assert_eq!(
+        parts(doc.subword_boundaries(&line)),
+        [
+            "let",
+            "parse",
+            "HTTP",
+            "Request",
+            "snake",
+            "case2",
+            "Value",
+            "kebab",
+            "case",
+            "été",
+            "Ünïcode"
+        ]
+    );
+    // Moving by subwords stops at each of them (counting in characters)
+    doc.move_to(&Loc { x: 0, y: 0 });
+    let mut stops = vec![];
+    for _ in 0..12 {
+        doc.move_next_subword();
+        stops.push(doc.char_loc().x);
+    }
+    assert_eq!(stops, [4, 9, 13, 20, 28, 34, 39, 45, 50, 55, 63, 65]);
+    stops.clear();
+    for _ in 0..12 {
+        doc.move_prev_subword();
+        stops.push(doc.char_loc().x);
+    }
+    assert_eq!(stops, [63, 55, 50, 45, 39, 34, 28, 20, 13, 9, 3, 0]);
+    // Deleting by subwords only takes the last part of an identifier
+    doc.move_to(&Loc { x: 20, y: 0 });
+    doc.delete_subword().unwrap();
+    assert_eq!(
+        doc.line(0).unwrap(),
+        "let parseHTTP = snake_case2Value(kebab-case, été_Ünïcode);"
+    );
+    // Which characters separate words can be changed, which selecting words follows too
+    doc.word_separators = "(),;=".to_string();
+    let line = doc.line(0).unwrap();
+    let words: Vec<String> = doc
+        .word_boundaries(&line)
+        .iter()
+        .map(|(s, e)| line[*s..*e].to_string())
+        .collect();
+    assert_eq!(
+        words,
+        [
+            "let",
+            "parseHTTP",
+            "snake_case2Value",
+            "kebab-case",
+            "été_Ünïcode"
+        ]
+    );
+    doc.select_word_at(&Loc { x: 38, y: 0 });
+    assert_eq!(
+        doc.selection_loc_bound(),
+        (Loc { x: 33, y: 0 }, Loc { x: 43, y: 0 })
+    );
+}
+
 /*
 Template:
 
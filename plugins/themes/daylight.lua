@@ -0,0 +1,90 @@
+-- Pallette --
+local white = '#fafafc'
+local grey1 = '#ebebf2'
+local grey2 = '#d5d5e2'
+local grey3 = '#8c8ca6'
+local black = '#2b2b3d'
+local brown = '#8c5a4a'
+local red = '#c9364a'
+local orange = '#b85c14'
+local yellow = '#8f7400'
+local green = '#2e8540'
+local lightblue = '#0f7b8f'
+local darkblue = '#2c5fc9'
+local purple = '#7440c9'
+local pink = '#b0359c'
+
+-- Configure Colours --
+colors.editor_bg = white
+colors.editor_fg = black
+colors.line_number_fg = grey3
+colors.line_number_bg = white
+
+colors.status_bg = grey1
+colors.status_fg = green
+
+colors.highlight = green
+
+colors.tab_inactive_bg = grey1
+colors.tab_inactive_fg = black
+colors.tab_active_bg = white
+colors.tab_active_fg = black
+
+colors.split_bg = white
+colors.split_fg = grey2
+
+colors.info_bg = white
+colors.info_fg = darkblue
+colors.warning_bg = white
+colors.warning_fg = orange
+colors.error_bg = white
+colors.error_fg = red
+
+colors.selection_bg = grey2
+colors.selection_fg = black
+
+colors.file_tree_bg = white
+colors.file_tree_fg = black
+colors.file_tree_selection_bg = grey2
+colors.file_tree_selection_fg = black
+
+colors.file_tree_red = red
+colors.file_tree_orange = orange
+colors.file_tree_yellow = yellow
+colors.file_tree_green = green
+colors.file_tree_lightblue = lightblue
+colors.file_tree_darkblue = darkblue
+colors.file_tree_purple = purple
+colors.file_tree_pink = pink
+colors.file_tree_brown = brown
+colors.file_tree_grey = grey3
+
+-- Configure Syntax Highlighting Colours --
+syntax:set("string", green)  -- Strings
+syntax:set("comment", grey3)  -- Comments
+syntax:set("digit", lightblue)  -- Digits
+syntax:set("keyword", purple)  -- Keywords
+syntax:set("attribute", lightblue)  -- Attributes
+syntax:set("character", lightblue)  -- Characters
+syntax:set("type", darkblue)  -- Types
+syntax:set("function", darkblue)  -- Function names
+syntax:set("header", lightblue)  -- Headers
+syntax:set("macro", pink)  -- Macros
+syntax:set("namespace", darkblue)  -- Namespaces
+syntax:set("struct", darkblue)  -- Structs, classes, and enums
+syntax:set("operator", grey3)  -- Operators
+syntax:set("boolean", green)  -- Booleans
+syntax:set("table", darkblue)  -- Tables
+syntax:set("reference", purple)  -- References
+syntax:set("tag", lightblue)  -- Tags (e.g. HTML tags)
+syntax:set("heading", darkblue)  -- Headings
+syntax:set("link", pink)  -- Links
+syntax:set("key", pink)  -- Keys
+syntax:set("quote", grey3)  -- Quotes
+syntax:set("bold", lightblue)  -- Bold text
+syntax:set("italic", lightblue)  -- Italic text
+syntax:set("block", lightblue)  -- Code blocks
+syntax:set("image", lightblue)  -- Images in markup languages
+syntax:set("list", green)  -- Lists
+syntax:set("insertion", green)  -- Insertions (e.g. diff highlight)
+syntax:set("deletion", red)  -- Deletions (e.g. diff highlight)
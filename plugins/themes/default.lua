@@ -0,0 +1,83 @@
+
+-- Configure Colours --
+colors.editor_bg = {41, 41, 61}
+colors.editor_fg = {255, 255, 255}
+colors.line_number_fg = {65, 65, 98}
+colors.line_number_bg = {41, 41, 61}
+
+colors.status_bg = {59, 59, 84}
+colors.status_fg = {35, 240, 144}
+
+colors.highlight = {35, 240, 144}
+
+colors.tab_inactive_fg = {255, 255, 255}
+colors.tab_inactive_bg = {59, 59, 84}
+colors.tab_active_fg = {255, 255, 255}
+colors.tab_active_bg = {41, 41, 61}
+
+colors.split_bg = {41, 41, 61}
+colors.split_fg = {59, 59, 84}
+
+colors.info_fg = {99, 162, 255}
+colors.info_bg = {41, 41, 61}
+colors.warning_fg = {255, 182, 99}
+colors.warning_bg = {41, 41, 61}
+colors.error_fg = {255, 100, 100}
+colors.error_bg = {41, 41, 61}
+
+colors.selection_fg = {255, 255, 255}
+colors.selection_bg = {59, 59, 130}
+
+colors.file_tree_bg = {41, 41, 61}
+colors.file_tree_fg = {255, 255, 255}
+colors.file_tree_selection_fg = {255, 255, 255}
+colors.file_tree_selection_bg = {59, 59, 130}
+
+colors.file_tree_red = {240, 104, 89}
+colors.file_tree_orange = {240, 142, 89}
+colors.file_tree_yellow = {240, 237, 89}
+colors.file_tree_green = {89, 240, 169}
+colors.file_tree_lightblue = {89, 225, 240}
+colors.file_tree_darkblue = {89, 149, 240}
+colors.file_tree_purple = {139, 89, 240}
+colors.file_tree_pink = {215, 89, 240}
+colors.file_tree_brown = {158, 94, 94}
+colors.file_tree_grey = {150, 144, 201}
+
+colors.line_added_fg = {89, 240, 169}
+colors.line_modified_fg = {240, 237, 89}
+colors.line_removed_fg = {240, 104, 89}
+
+colors.diff_added_bg = {41, 74, 66}
+colors.diff_removed_bg = {79, 41, 58}
+colors.diff_changed_bg = {69, 66, 50}
+
+-- Configure Syntax Highlighting Colours --
+syntax:set("string", {39, 222, 145}) -- Strings in various programming languages
+syntax:set("comment", {113, 113, 169}) -- Comments in various programming languages
+syntax:set("digit", {40, 198, 232}) -- Digits in various programming languages
+syntax:set("keyword", {134, 76, 232}) -- Keywords in various programming languages
+syntax:set("attribute", {40, 198, 232}) -- Attributes in various programming languages
+syntax:set("character", {40, 198, 232}) -- Characters in various programming languages
+syntax:set("type", {47, 141, 252}) -- Types in various programming languages
+syntax:set("function", {47, 141, 252}) -- Function names in various programming languages
+syntax:set("header", {40, 198, 232}) -- Headers in various programming language
+syntax:set("macro", {223, 52, 249}) -- Macro names in various programming languages
+syntax:set("namespace", {47, 141, 252}) -- Namespaces in various programming languages
+syntax:set("struct", {47, 141, 252}) -- The names of structs, classes, enums in various programming languages
+syntax:set("operator", {113, 113, 169}) -- Operators in various programming languages e.g. +, -, * etc
+syntax:set("boolean", {86, 217, 178}) -- Booleans in various programming langauges e.g. true / false
+syntax:set("table", {47, 141, 252}) -- Tables in various programming languages
+syntax:set("reference", {134, 76, 232}) -- References in various programming languages
+syntax:set("tag", {40, 198, 232}) -- Tags in various markup langauges e.g. HTML <p> tags
+syntax:set("heading", {47, 141, 252}) -- Headings in various markup languages e.g. # in markdown
+syntax:set("link", {223, 52, 249}) -- Links in various markup languages e.g. URLs
+syntax:set("key", {223, 52, 249}) -- Keys in various markup languages
+syntax:set("quote", {113, 113, 169}) -- Quotes in various markup languages e.g. > in markdown
+syntax:set("bold", {40, 198, 232}) -- Bold text in various markup languages e.g. * in markdown
+syntax:set("italic", {40, 198, 232}) -- Italic text in various markup languages e.g. ** in markdown
+syntax:set("block", {40, 198, 232}) -- Code blocks in various markup languages e.g. `````` in markdown
+syntax:set("image", {40, 198, 232}) -- Images in various markup languages e.g. ![]() in markdown
+syntax:set("list", {86, 217, 178}) -- Lists in various markup languages e.g. - in markdown
+syntax:set("insertion", {39, 222, 145}) -- Images in various markup languages e.g. ![]() in markdown
+syntax:set("deletion", {255, 100, 100}) -- Lists in various markup languages e.g. - in markdown
@@ -0,0 +1,91 @@
+
+-- Pallette --
+black = '#282828'
+grey1 = '#3c3836'
+grey2 = '#504945'
+grey3 = '#928374'
+white = '#ebdbb2'
+brown = '#d65d0e'
+red = '#fb4934'
+orange = '#fe8019'
+yellow = '#fabd2f'
+green = '#b8bb26'
+lightblue = '#8ec07c'
+darkblue = '#83a598'
+purple = '#d3869b'
+pink = '#d3869b'
+
+-- Configure Colours --
+colors.editor_bg = black
+colors.editor_fg = white
+colors.line_number_fg = grey2
+colors.line_number_bg = black
+
+colors.status_bg = grey1
+colors.status_fg = yellow
+
+colors.highlight = yellow
+
+colors.tab_inactive_bg = grey1
+colors.tab_inactive_fg = white
+colors.tab_active_bg = grey2
+colors.tab_active_fg = yellow
+
+colors.split_bg = black
+colors.split_fg = yellow
+
+colors.info_bg = black
+colors.info_fg = darkblue
+colors.warning_bg = black
+colors.warning_fg = yellow
+colors.error_bg = black
+colors.error_fg = red
+
+colors.selection_bg = grey2
+colors.selection_fg = white
+
+colors.file_tree_bg = black
+colors.file_tree_fg = white
+colors.file_tree_selection_bg = yellow
+colors.file_tree_selection_fg = black
+
+colors.file_tree_red = red
+colors.file_tree_orange = orange
+colors.file_tree_yellow = yellow
+colors.file_tree_green = green
+colors.file_tree_lightblue = lightblue
+colors.file_tree_darkblue = darkblue
+colors.file_tree_purple = purple
+colors.file_tree_pink = pink
+colors.file_tree_brown = brown
+colors.file_tree_grey = grey3
+
+-- Configure Syntax Highlighting Colours --
+syntax:set("string", green)  -- Strings, green
+syntax:set("comment", grey3)  -- Comments, grey
+syntax:set("digit", purple)  -- Digits, purple
+syntax:set("keyword", red)  -- Keywords, red
+syntax:set("attribute", lightblue)  -- Attributes, aqua
+syntax:set("character", purple)  -- Characters, purple
+syntax:set("type", yellow)  -- Types, yellow
+syntax:set("function", green)  -- Function names, green
+syntax:set("header", lightblue)  -- Headers, aqua
+syntax:set("macro", lightblue)  -- Macros, aqua
+syntax:set("namespace", darkblue)  -- Namespaces, blue
+syntax:set("struct", yellow)  -- Structs, classes, and enums, yellow
+syntax:set("operator", white)  -- Operators, foreground
+syntax:set("boolean", purple)  -- Booleans, purple
+syntax:set("table", darkblue)  -- Tables, blue
+syntax:set("reference", orange)  -- References, orange
+syntax:set("tag", darkblue)  -- Tags (e.g. HTML tags), blue
+syntax:set("heading", yellow)  -- Headings, yellow
+syntax:set("link", darkblue)  -- Links, blue
+syntax:set("key", orange)  -- Keys, orange
+syntax:set("quote", grey3)  -- Quotes, grey
+syntax:set("bold", orange)  -- Bold text, orange
+syntax:set("italic", purple)  -- Italic text, purple
+syntax:set("block", lightblue)  -- Code blocks, aqua
+syntax:set("image", lightblue)  -- Images in markup languages, aqua
+syntax:set("list", green)  -- Lists, green
+syntax:set("insertion", green)  -- Insertions (e.g. diff highlight), green
+syntax:set("deletion", red)  -- Deletions (e.g. diff highlight), red
@@ -0,0 +1,10 @@
+# Show the warnings and errors from clippy in ox's gutter
+# Run this from the root of a cargo project, and add this to your .oxrc:
+#   document.sign_files = {".ox-signs.json"}
+cargo clippy --message-format=json 2>/dev/null \
+  | jq -c 'select(.reason == "compiler-message") | .message as $msg
+      | $msg.spans[] | select(.is_primary)
+      | {file: .file_name, line: .line_start, severity: $msg.level, message: $msg.message}' \
+  > .ox-signs.json.tmp
+# Swap the file in whole, so ox never reads it half written
+mv .ox-signs.json.tmp .ox-signs.json
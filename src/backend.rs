@@ -0,0 +1,527 @@
+/// Backends: where the editor is drawn, either the real terminal or a grid kept in memory
+use crate::error::Result;
+use crate::ui::size;
+use crossterm::{
+    cursor::{Hide, MoveTo, Show},
+    event::{
+        DisableBracketedPaste, DisableFocusChange, DisableMouseCapture, EnableBracketedPaste,
+        EnableFocusChange, EnableMouseCapture, KeyboardEnhancementFlags,
+        PopKeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
+    },
+    execute, queue,
+    style::{Color, Print},
+    terminal::{
+        self, Clear, ClearType, DisableLineWrap, EnableLineWrap, EnterAlternateScreen,
+        LeaveAlternateScreen,
+    },
+};
+use kaolinite::utils::{width_char, Size};
+use std::cell::RefCell;
+use std::io::{stdout, Stdout, Write};
+use std::rc::Rc;
+
+/// Somewhere the editor can be drawn
+pub trait Backend {
+    /// Move the cursor to a position on screen (starting from the top left at 0, 0)
+    fn goto(&mut self, x: usize, y: usize) -> Result<()>;
+    /// Write text at the cursor (this can contain escape codes for styling and moving around)
+    fn write(&mut self, text: &str) -> Result<()>;
+    /// Clear everything from the screen
+    fn clear(&mut self) -> Result<()>;
+    /// Make sure everything written so far is shown
+    fn flush(&mut self) -> Result<()>;
+    /// The size of the screen (where the height leaves out the feedback line at the bottom)
+    fn size(&self) -> Result<Size>;
+    fn show_cursor(&mut self) -> Result<()>;
+    fn hide_cursor(&mut self) -> Result<()>;
+    /// Take the screen over for the editor (capturing the mouse if asked to)
+    fn enter(&mut self, mouse: bool) -> Result<()>;
+    /// Hand the screen back as it was before `enter`
+    fn leave(&mut self, mouse: bool) -> Result<()>;
+}
+
+/// The terminal the editor is running in
+pub struct CrosstermBackend {
+    stdout: Stdout,
+}
+
+impl Default for CrosstermBackend {
+    fn default() -> Self {
+        Self { stdout: stdout() }
+    }
+}
+
+impl Backend for CrosstermBackend {
+    fn goto(&mut self, x: usize, y: usize) -> Result<()> {
+        let x = u16::try_from(x).unwrap_or(u16::MAX);
+        let y = u16::try_from(y).unwrap_or(u16::MAX);
+        queue!(self.stdout, MoveTo(x, y))?;
+        Ok(())
+    }
+
+    fn write(&mut self, text: &str) -> Result<()> {
+        queue!(self.stdout, Print(text))?;
+        Ok(())
+    }
+
+    fn clear(&mut self) -> Result<()> {
+        queue!(self.stdout, Clear(ClearType::All))?;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        self.stdout.flush()?;
+        Ok(())
+    }
+
+    fn size(&self) -> Result<Size> {
+        size()
+    }
+
+    fn show_cursor(&mut self) -> Result<()> {
+        queue!(self.stdout, Show)?;
+        Ok(())
+    }
+
+    fn hide_cursor(&mut self) -> Result<()> {
+        queue!(self.stdout, Hide)?;
+        Ok(())
+    }
+
+    fn enter(&mut self, mouse: bool) -> Result<()> {
+        std::panic::set_hook(Box::new(|e| {
+            // Keep hold of any unsaved changes before anything else can go wrong
+            crate::editor::write_pending_swaps();
+            terminal::disable_raw_mode().unwrap();
+            execute!(
+                stdout(),
+                LeaveAlternateScreen,
+                Show,
+                DisableMouseCapture,
+                DisableBracketedPaste,
+                DisableFocusChange,
+            )
+            .unwrap();
+            eprintln!("{e}");
+        }));
+        execute!(
+            self.stdout,
+            EnterAlternateScreen,
+            Clear(ClearType::All),
+            DisableLineWrap,
+        )?;
+        if mouse {
+            execute!(self.stdout, EnableMouseCapture)?;
+        }
+        terminal::enable_raw_mode()?;
+        if cfg!(not(target_os = "windows")) {
+            execute!(
+                self.stdout,
+                PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::DISAMBIGUATE_ESCAPE_CODES),
+                EnableBracketedPaste,
+                // Terminals that don't report focus changes just ignore this
+                EnableFocusChange,
+            )?;
+            // Keep the title the terminal had, so it can be put back when the editor ends
+            write!(self.stdout, "\x1b[22;0t")?;
+        }
+        Ok(())
+    }
+
+    fn leave(&mut self, mouse: bool) -> Result<()> {
+        terminal::disable_raw_mode()?;
+        execute!(self.stdout, LeaveAlternateScreen, EnableLineWrap)?;
+        if cfg!(not(target_os = "windows")) {
+            execute!(
+                self.stdout,
+                PopKeyboardEnhancementFlags,
+                DisableBracketedPaste,
+                DisableFocusChange
+            )?;
+            write!(self.stdout, "\x1b[23;0t")?;
+        }
+        if mouse {
+            execute!(self.stdout, DisableMouseCapture)?;
+        }
+        Ok(())
+    }
+}
+
+/// A character on screen, along with how it is styled
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct Cell {
+    /// The character shown (the cell after a double width character holds a `\0`)
+    pub ch: char,
+    pub fg: Color,
+    pub bg: Color,
+    pub bold: bool,
+    pub italic: bool,
+    pub underlined: bool,
+    pub reversed: bool,
+}
+
+impl Default for Cell {
+    fn default() -> Self {
+        Self {
+            ch: ' ',
+            fg: Color::Reset,
+            bg: Color::Reset,
+            bold: false,
+            italic: false,
+            underlined: false,
+            reversed: false,
+        }
+    }
+}
+
+/// What a screen kept in memory looks like
+#[derive(Debug, Clone)]
+pub struct Screen {
+    pub w: usize,
+    pub h: usize,
+    /// The rows of the screen, from top to bottom
+    pub cells: Vec<Vec<Cell>>,
+    /// Where the cursor is
+    pub cursor: (usize, usize),
+    pub cursor_visible: bool,
+    /// The shape the cursor was last given (as numbered by the sequence that sets it,
+    /// 0 being the terminal's own)
+    pub cursor_shape: usize,
+    /// How many escape codes have been written to the screen
+    pub escapes: usize,
+    /// Whether the editor has taken the screen over (between `enter` and `leave`)
+    pub entered: bool,
+    /// Whether the mouse is being captured
+    pub mouse_captured: bool,
+    /// The style text is currently written in
+    pen: Cell,
+    /// Text left over from the last write that stopped part of the way through an escape code
+    pending: String,
+}
+
+impl Screen {
+    fn new(w: usize, h: usize) -> Self {
+        Self {
+            w,
+            h,
+            cells: vec![vec![Cell::default(); w]; h],
+            cursor: (0, 0),
+            cursor_visible: true,
+            cursor_shape: 0,
+            escapes: 0,
+            entered: false,
+            mouse_captured: false,
+            pen: Cell::default(),
+            pending: String::new(),
+        }
+    }
+
+    /// The text of a row (without styling, and with trailing spaces left in)
+    #[must_use]
+    pub fn row(&self, y: usize) -> String {
+        self.cells
+            .get(y)
+            .map(|row| row.iter().map(|c| c.ch).filter(|c| *c != '\0').collect())
+            .unwrap_or_default()
+    }
+
+    /// The text of every row, one per line, with trailing spaces taken off
+    #[must_use]
+    pub fn text(&self) -> String {
+        (0..self.h)
+            .map(|y| self.row(y).trim_end().to_string())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// The cell at a position on screen
+    #[must_use]
+    pub fn cell(&self, x: usize, y: usize) -> Option<&Cell> {
+        self.cells.get(y).and_then(|row| row.get(x))
+    }
+
+    /// Clear a range of a row, leaving the current background colour behind
+    fn blank(&mut self, y: usize, from: usize, to: usize) {
+        let blank = Cell {
+            ch: ' ',
+            bg: self.pen.bg,
+            ..Cell::default()
+        };
+        if let Some(row) = self.cells.get_mut(y) {
+            for cell in row.iter_mut().take(to).skip(from) {
+                *cell = blank;
+            }
+        }
+    }
+
+    /// Put a character down at the cursor (lines don't wrap, like the real terminal is set up)
+    fn put(&mut self, ch: char) {
+        let (x, y) = self.cursor;
+        let w = width_char(&ch, 4);
+        if w == 0 || x + w > self.w || y >= self.h {
+            self.cursor.0 += w;
+            return;
+        }
+        self.cells[y][x] = Cell { ch, ..self.pen };
+        for extra in 1..w {
+            self.cells[y][x + extra] = Cell {
+                ch: '\0',
+                ..self.pen
+            };
+        }
+        self.cursor.0 += w;
+    }
+
+    /// Apply a control sequence (`ESC [ params final`)
+    fn control(&mut self, params: &str, last: char) {
+        let numbers: Vec<usize> = params.split(';').map(|n| n.parse().unwrap_or(0)).collect();
+        let first = numbers.first().copied().unwrap_or(0);
+        match (params, last) {
+            ("?25", 'h') => self.cursor_visible = true,
+            ("?25", 'l') => self.cursor_visible = false,
+            (p, _) if p.starts_with('?') => (),
+            (_, 'H') => {
+                let y = first.max(1) - 1;
+                let x = numbers.get(1).copied().unwrap_or(1).max(1) - 1;
+                self.cursor = (x, y);
+            }
+            (_, 'J') if first == 2 => {
+                for y in 0..self.h {
+                    self.blank(y, 0, self.w);
+                }
+            }
+            (_, 'K') => {
+                let (x, y) = self.cursor;
+                match first {
+                    0 => self.blank(y, x, self.w),
+                    1 => self.blank(y, 0, x + 1),
+                    _ => self.blank(y, 0, self.w),
+                }
+            }
+            (_, 'm') => self.style(&numbers),
+            _ => (),
+        }
+    }
+
+    /// Apply a styling sequence
+    fn style(&mut self, numbers: &[usize]) {
+        let mut i = 0;
+        while i < numbers.len() {
+            match numbers[i] {
+                0 => self.pen = Cell::default(),
+                1 => self.pen.bold = true,
+                22 => self.pen.bold = false,
+                3 => self.pen.italic = true,
+                23 => self.pen.italic = false,
+                4 => self.pen.underlined = true,
+                24 => self.pen.underlined = false,
+                7 => self.pen.reversed = true,
+                27 => self.pen.reversed = false,
+                n @ (30..=37 | 90..=97) => self.pen.fg = ansi(n % 10 + 8 * usize::from(n >= 90)),
+                n @ (40..=47 | 100..=107) => {
+                    self.pen.bg = ansi(n % 10 + 8 * usize::from(n >= 100));
+                }
+                39 => self.pen.fg = Color::Reset,
+                49 => self.pen.bg = Color::Reset,
+                n @ (38 | 48) => {
+                    let (color, used) = extended(&numbers[i + 1..]);
+                    if n == 38 {
+                        self.pen.fg = color;
+                    } else {
+                        self.pen.bg = color;
+                    }
+                    i += used;
+                }
+                _ => (),
+            }
+            i += 1;
+        }
+    }
+
+    /// Work through text written to the screen, escape codes and all
+    fn feed(&mut self, text: &str) {
+        let text = std::mem::take(&mut self.pending) + text;
+        let chars: Vec<char> = text.chars().collect();
+        let mut i = 0;
+        while i < chars.len() {
+            match chars[i] {
+                '\x1b' => {
+                    let Some(used) = self.escape(&chars[i..]) else {
+                        // The rest of the escape code will come with the next write
+                        self.pending = chars[i..].iter().collect();
+                        return;
+                    };
+                    i += used;
+                }
+                '\r' => {
+                    self.cursor.0 = 0;
+                    i += 1;
+                }
+                '\n' => {
+                    self.cursor = (0, self.cursor.1 + 1);
+                    i += 1;
+                }
+                '\x07' => i += 1,
+                ch => {
+                    self.put(ch);
+                    i += 1;
+                }
+            }
+        }
+    }
+
+    /// Apply the escape code at the start of some text, returning how long it was
+    /// (or None if it is cut off)
+    fn escape(&mut self, chars: &[char]) -> Option<usize> {
+        let used = match chars.get(1)? {
+            // An escape code cut short by the start of another is dropped
+            '\x1b' => return Some(1),
+            '[' => {
+                let end = chars.iter().skip(2).position(|c| ('@'..='~').contains(c))? + 2;
+                let params: String = chars[2..end].iter().collect();
+                if let Some(shape) = params.strip_suffix(' ').filter(|_| chars[end] == 'q') {
+                    self.cursor_shape = shape.parse().unwrap_or(0);
+                } else {
+                    self.control(params.trim_end_matches(' '), chars[end]);
+                }
+                end + 1
+            }
+            // Titles and the clipboard don't change what is on screen
+            ']' => {
+                let end = (2..chars.len()).find(|i| {
+                    chars[*i] == '\x07' || (chars[*i] == '\\' && chars[i - 1] == '\x1b')
+                })?;
+                end + 1
+            }
+            _ => 2,
+        };
+        self.escapes += 1;
+        Some(used)
+    }
+}
+
+/// The colour for one of the 16 standard colour codes
+fn ansi(code: usize) -> Color {
+    [
+        Color::Black,
+        Color::DarkRed,
+        Color::DarkGreen,
+        Color::DarkYellow,
+        Color::DarkBlue,
+        Color::DarkMagenta,
+        Color::DarkCyan,
+        Color::Grey,
+        Color::DarkGrey,
+        Color::Red,
+        Color::Green,
+        Color::Yellow,
+        Color::Blue,
+        Color::Magenta,
+        Color::Cyan,
+        Color::White,
+    ]
+    .get(code)
+    .copied()
+    .unwrap_or(Color::Reset)
+}
+
+/// Read the colour following a 38 or 48 styling code, along with how many numbers it took up
+#[allow(clippy::cast_possible_truncation)]
+fn extended(numbers: &[usize]) -> (Color, usize) {
+    match numbers {
+        [5, n, ..] if *n < 16 => (ansi(*n), 2),
+        [5, n, ..] => (Color::AnsiValue(*n as u8), 2),
+        [2, r, g, b, ..] => (
+            Color::Rgb {
+                r: *r as u8,
+                g: *g as u8,
+                b: *b as u8,
+            },
+            4,
+        ),
+        _ => (Color::Reset, numbers.len()),
+    }
+}
+
+/// A screen kept in memory, for testing what the editor draws without a terminal
+/// (clones share the same screen, so one can be kept to look at while the editor draws on another)
+#[derive(Debug, Clone)]
+pub struct MemoryBackend {
+    screen: Rc<RefCell<Screen>>,
+}
+
+impl MemoryBackend {
+    /// Create a screen `w` columns wide and `h` rows tall (including the feedback line)
+    #[must_use]
+    pub fn new(w: usize, h: usize) -> Self {
+        Self {
+            screen: Rc::new(RefCell::new(Screen::new(w, h))),
+        }
+    }
+
+    /// A copy of what is on screen right now
+    #[must_use]
+    pub fn screen(&self) -> Screen {
+        self.screen.borrow().clone()
+    }
+
+    /// Change the size of the screen, as if the terminal had been resized (this clears it)
+    pub fn resize(&self, w: usize, h: usize) {
+        *self.screen.borrow_mut() = Screen::new(w, h);
+    }
+}
+
+impl Backend for MemoryBackend {
+    fn goto(&mut self, x: usize, y: usize) -> Result<()> {
+        self.screen.borrow_mut().cursor = (x, y);
+        Ok(())
+    }
+
+    fn write(&mut self, text: &str) -> Result<()> {
+        self.screen.borrow_mut().feed(text);
+        Ok(())
+    }
+
+    fn clear(&mut self) -> Result<()> {
+        self.screen.borrow_mut().feed("\x1b[2J");
+        Ok(())
+    }
+
+    fn flush(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    fn size(&self) -> Result<Size> {
+        let screen = self.screen.borrow();
+        Ok(Size {
+            w: screen.w,
+            h: screen.h.saturating_sub(1),
+        })
+    }
+
+    fn show_cursor(&mut self) -> Result<()> {
+        self.screen.borrow_mut().cursor_visible = true;
+        Ok(())
+    }
+
+    fn hide_cursor(&mut self) -> Result<()> {
+        self.screen.borrow_mut().cursor_visible = false;
+        Ok(())
+    }
+
+    fn enter(&mut self, mouse: bool) -> Result<()> {
+        let mut screen = self.screen.borrow_mut();
+        screen.feed("\x1b[2J");
+        screen.entered = true;
+        screen.mouse_captured = mouse;
+        Ok(())
+    }
+
+    fn leave(&mut self, _mouse: bool) -> Result<()> {
+        let mut screen = self.screen.borrow_mut();
+        screen.entered = false;
+        screen.mouse_captured = false;
+        Ok(())
+    }
+}
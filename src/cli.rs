@@ -2,6 +2,7 @@
 use jargon_args::{Jargon, Key};
 use std::io;
 use std::io::BufRead;
+use std::path::Path;
 
 /// Holds the version number of the crate
 pub const VERSION: &str = env!("CARGO_PKG_VERSION");
@@ -10,7 +11,7 @@ pub const VERSION: &str = env!("CARGO_PKG_VERSION");
 pub const HELP: &str = "\
 Ox: A lightweight and flexible text editor
 
-USAGE: ox [options] [files]
+USAGE: ox [options] [files or a directory]
 
 OPTIONS:
   --help, -h                   : Show this help message
@@ -19,16 +20,34 @@ OPTIONS:
   --readonly, -r               : Prevent opened files from writing
   --filetype [name], -f [name] : Set the file type of files opened
   --stdin                      : Reads file from the stdin
+  --execute [cmds], -e [cmds]  : Run commands (separated by ;) once files are opened
+  --lua [code]                 : Run Lua on each file without opening the editor
+  --script [path]              : Run a Lua file on each file without opening the editor
+  --write                      : Save the files that --lua or --script changed
+  --remote [command] [args]    : Ask the instance already running to open files,
+                                 goto a line or eval Lua (starts ox if none is,
+                                 not available on windows)
+  --diff [file]                : Compare the file opened with this one side by side
   --config-assist              : Activate the configuration assistant
+  +[line]:[column]             : Open the next file at a position (the column is optional)
+
+Files can also be given as [file]:[line]:[column] to open them at a position,
+and giving a directory opens the file tree there
 
 EXAMPLES:
   ox
   ox test.txt
   ox test.txt test2.txt
+  ox src/main.rs:120 +40:5 src/cli.rs
+  ox src/
   ox /home/user/docs/test.txt
   ox -c config.lua test.txt
   ox -r -c ~/.config/.oxrc -f Lua my_file.lua
   tree | ox -r --stdin
+  ox -e \"macro_run cleanup; save; quit\" test.txt
+  ox --lua 'editor:replace_all(\"foo\", \"bar\")' --write test.txt test2.txt
+  ox --remote open src/main.rs:42
+  ox --diff old.txt new.txt
   ox --config-assist\
 ";
 
@@ -39,6 +58,77 @@ pub fn get_stdin() -> String {
     })
 }
 
+/// A line (and optionally a column) to open a file at, counting from 1
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Position {
+    pub line: usize,
+    pub column: Option<usize>,
+}
+
+/// A file (or directory) given on the command line, along with where to start in it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileToOpen {
+    pub name: String,
+    pub position: Option<Position>,
+}
+
+/// Read a position in the form `line` or `line:column`
+pub(crate) fn parse_position(text: &str) -> Option<Position> {
+    let (line, column) = match text.split_once(':') {
+        Some((line, column)) => (line, Some(column.parse().ok()?)),
+        None => (text, None),
+    };
+    Some(Position {
+        line: line.parse().ok()?,
+        column,
+    })
+}
+
+/// Split a position off the end of a file name (as in `main.rs:120` or `main.rs:120:5`),
+/// unless the whole argument names something that exists.
+/// Windows drive letters (as in `C:\main.rs`) are kept as part of the name
+fn split_position(arg: &str) -> (String, Option<Position>) {
+    let whole = (arg.to_string(), None);
+    if Path::new(arg).exists() {
+        return whole;
+    }
+    let is_number = |s: &str| !s.is_empty() && s.chars().all(|c| c.is_ascii_digit());
+    let Some((rest, last)) = arg.rsplit_once(':') else {
+        return whole;
+    };
+    if !is_number(last) {
+        return whole;
+    }
+    let (name, position) = match rest.rsplit_once(':') {
+        Some((name, line)) if is_number(line) => (name, format!("{line}:{last}")),
+        _ => (rest, last.to_string()),
+    };
+    let is_drive = name.len() == 1 && name.chars().all(|c| c.is_ascii_alphabetic());
+    if name.is_empty() || is_drive {
+        return whole;
+    }
+    (name.to_string(), parse_position(&position))
+}
+
+/// Work out which files to open and where, with positions given either after the name
+/// or as a `+line:column` argument before it (which only applies to the file that follows)
+pub(crate) fn files_to_open(args: Vec<String>) -> Vec<FileToOpen> {
+    let mut result = vec![];
+    let mut pending = None;
+    for arg in args.into_iter().filter(|arg| arg != "--") {
+        if let Some(position) = arg.strip_prefix('+').and_then(parse_position) {
+            pending = Some(position);
+            continue;
+        }
+        let (name, position) = split_position(&arg);
+        result.push(FileToOpen {
+            name,
+            position: pending.take().or(position),
+        });
+    }
+    result
+}
+
 /// Flags for command line interface
 #[allow(clippy::struct_excessive_bools)]
 pub struct CommandLineInterfaceFlags {
@@ -47,6 +137,7 @@ pub struct CommandLineInterfaceFlags {
     pub read_only: bool,
     pub stdin: bool,
     pub config_assist: bool,
+    pub write: bool,
 }
 
 /// Struct to help with starting ox
@@ -54,7 +145,14 @@ pub struct CommandLineInterface {
     pub flags: CommandLineInterfaceFlags,
     pub file_type: Option<String>,
     pub config_path: String,
-    pub to_open: Vec<String>,
+    pub execute: Vec<String>,
+    pub lua: Option<String>,
+    pub script: Option<String>,
+    /// A command for the instance already running, along with its arguments
+    pub remote: Option<(String, Vec<String>)>,
+    /// A file to compare the first file opened with
+    pub diff: Option<String>,
+    pub to_open: Vec<FileToOpen>,
 }
 
 impl CommandLineInterface {
@@ -66,21 +164,59 @@ impl CommandLineInterface {
         // Define keys
         let filetype: Key = ["-f", "--filetype"].into();
         let config: Key = ["-c", "--config"].into();
+        let execute: Key = ["-e", "--execute"].into();
+        let lua: Key = "--lua".into();
+        let script: Key = "--script".into();
+        let remote: Key = "--remote".into();
+        let diff: Key = "--diff".into();
 
-        Self {
+        let remote = j.option_arg::<String, Key>(remote);
+        let mut cli = Self {
             flags: CommandLineInterfaceFlags {
                 help: j.contains(["-h", "--help"]),
                 version: j.contains(["-v", "--version"]),
                 read_only: j.contains(["-r", "--readonly"]),
                 stdin: j.contains("--stdin"),
                 config_assist: j.contains("--config-assist"),
+                write: j.contains("--write"),
             },
             file_type: j.option_arg::<String, Key>(filetype.clone()),
             config_path: j
                 .option_arg::<String, Key>(config.clone())
                 .unwrap_or_else(|| "~/.oxrc".to_string()),
-            to_open: j.finish().into_iter().filter(|o| o != "--").collect(),
+            execute: j
+                .option_arg::<String, Key>(execute.clone())
+                .map(|cmds| {
+                    cmds.split(';')
+                        .map(|cmd| cmd.trim().to_string())
+                        .filter(|cmd| !cmd.is_empty())
+                        .collect()
+                })
+                .unwrap_or_default(),
+            lua: j.option_arg::<String, Key>(lua.clone()),
+            script: j.option_arg::<String, Key>(script.clone()),
+            remote: None,
+            diff: j.option_arg::<String, Key>(diff.clone()),
+            to_open: vec![],
+        };
+        // The arguments after a remote command belong to it, only files to open are opened
+        // (when no instance is running and ox starts as normal)
+        let rest = j.finish();
+        match remote {
+            Some(command) => {
+                if command == "open" {
+                    cli.to_open = files_to_open(rest.clone());
+                }
+                cli.remote = Some((command, rest));
+            }
+            None => cli.to_open = files_to_open(rest),
         }
+        cli
+    }
+
+    /// Whether to run Lua over the files instead of opening the editor
+    pub fn headless(&self) -> bool {
+        self.lua.is_some() || self.script.is_some()
     }
 
     /// Handle options that won't need to start the editor
@@ -87,7 +87,7 @@ Just a few things before you go:
 See the documentation here: https://github.com/curlpipe/ox/wiki
 Report any bugs or request new features here: https://github.com/curlpipe/ox/issues/new/choose
 
-Remember: You can press Ctrl + H when you are in the editor to reveal a help message to get started
+Remember: You can press Ctrl + H when you are in the editor to open a help document listing the key bindings
 
 I hope you enjoy your Ox experience
 
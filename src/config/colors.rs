@@ -1,10 +1,102 @@
 /// For dealing with colours in the configuration file
 use crate::error::{OxError, Result};
-use crate::ui::{rgb_to_xterm256, supports_true_color};
+use crate::ui::{detect_color_mode, rgb_to_ansi16, rgb_to_xterm256, xterm_to_rgb};
 use crossterm::style::Color as CColor;
 use mlua::prelude::*;
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::OnceLock;
 
-use super::issue_warning;
+use super::assistant::{DEFAULT16, GALAXY, OMNI, TRANSPARENT, TROPICAL};
+use super::{issue_warning, DAYLIGHT};
+
+/// The built-in themes, which can be picked with `colors.theme` (in the order they are cycled through)
+pub const THEMES: [(&str, &str); 8] = [
+    ("default", include_str!("../../plugins/themes/default.lua")),
+    ("default16", DEFAULT16),
+    ("galaxy", GALAXY),
+    ("gruvbox", include_str!("../../plugins/themes/gruvbox.lua")),
+    ("omni", OMNI),
+    ("tropical", TROPICAL),
+    ("transparent", TRANSPARENT),
+    ("daylight", DAYLIGHT),
+];
+
+/// Find the code of a built-in theme
+pub fn theme_code(name: &str) -> Option<&'static str> {
+    THEMES
+        .iter()
+        .find(|(n, _)| *n == name)
+        .map(|(_, code)| *code)
+}
+
+/// How many colours the terminal can show
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum ColorMode {
+    /// Work it out from the TERM and COLORTERM environment variables
+    Auto,
+    TrueColor,
+    Xterm256,
+    Ansi16,
+}
+
+impl ColorMode {
+    fn name(self) -> &'static str {
+        match self {
+            Self::Auto => "auto",
+            Self::TrueColor => "truecolor",
+            Self::Xterm256 => "256",
+            Self::Ansi16 => "16",
+        }
+    }
+}
+
+impl IntoLua for ColorMode {
+    fn into_lua(self, lua: &Lua) -> LuaResult<LuaValue> {
+        Ok(LuaValue::String(lua.create_string(self.name())?))
+    }
+}
+
+impl FromLua for ColorMode {
+    fn from_lua(val: LuaValue, _: &Lua) -> LuaResult<Self> {
+        let name = match &val {
+            LuaValue::String(s) => s.to_string_lossy(),
+            LuaValue::Integer(n) => n.to_string(),
+            _ => String::new(),
+        };
+        Ok(match name.as_str() {
+            "auto" => Self::Auto,
+            "truecolor" | "24bit" => Self::TrueColor,
+            "256" => Self::Xterm256,
+            "16" => Self::Ansi16,
+            _ => {
+                return Err(LuaError::runtime(format!(
+                    "invalid terminal.color_mode: '{name}' isn't one of truecolor, 256, 16 or auto"
+                )))
+            }
+        })
+    }
+}
+
+/// The colour mode in use, kept here so colours can be converted without the configuration
+static COLOR_MODE: AtomicU8 = AtomicU8::new(ColorMode::Auto as u8);
+/// The colour mode the terminal appears to support, worked out the first time it is needed
+static DETECTED: OnceLock<ColorMode> = OnceLock::new();
+
+/// Use a colour mode for every colour converted from now on
+pub fn set_color_mode(mode: ColorMode) {
+    COLOR_MODE.store(mode as u8, Ordering::Relaxed);
+}
+
+/// The colour mode in use, having looked at the terminal if it is left to automatic
+pub fn color_mode() -> ColorMode {
+    match COLOR_MODE.load(Ordering::Relaxed) {
+        1 => ColorMode::TrueColor,
+        2 => ColorMode::Xterm256,
+        3 => ColorMode::Ansi16,
+        _ => *DETECTED.get_or_init(detect_color_mode),
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct Colors {
@@ -52,6 +144,18 @@ pub struct Colors {
     pub file_tree_pink: Color,
     pub file_tree_brown: Color,
     pub file_tree_grey: Color,
+
+    pub line_added_fg: Color,
+    pub line_modified_fg: Color,
+    pub line_removed_fg: Color,
+
+    /// The backgrounds of lines that differ in diffs
+    pub diff_added_bg: Color,
+    pub diff_removed_bg: Color,
+    pub diff_changed_bg: Color,
+
+    /// The name of the built-in theme these colours were last set from
+    pub theme: String,
 }
 
 impl Default for Colors {
@@ -101,6 +205,16 @@ impl Default for Colors {
             file_tree_pink: Color::Rgb(206, 36, 240),
             file_tree_brown: Color::Rgb(158, 94, 94),
             file_tree_grey: Color::Rgb(150, 144, 201),
+
+            line_added_fg: Color::Rgb(89, 240, 169),
+            line_modified_fg: Color::Rgb(240, 237, 89),
+            line_removed_fg: Color::Rgb(240, 104, 89),
+
+            diff_added_bg: Color::Rgb(41, 74, 66),
+            diff_removed_bg: Color::Rgb(79, 41, 58),
+            diff_changed_bg: Color::Rgb(69, 66, 50),
+
+            theme: "default".to_string(),
         }
     }
 }
@@ -145,184 +259,241 @@ impl LuaUserData for Colors {
         fields.add_field_method_get("selection_bg", |env, this| {
             Ok(this.selection_bg.to_lua(env))
         });
+        fields.add_field_method_get("line_added_fg", |env, this| {
+            Ok(this.line_added_fg.to_lua(env))
+        });
+        fields.add_field_method_get("line_modified_fg", |env, this| {
+            Ok(this.line_modified_fg.to_lua(env))
+        });
+        fields.add_field_method_get("line_removed_fg", |env, this| {
+            Ok(this.line_removed_fg.to_lua(env))
+        });
+        fields.add_field_method_get("diff_added_bg", |env, this| {
+            Ok(this.diff_added_bg.to_lua(env))
+        });
+        fields.add_field_method_get("diff_removed_bg", |env, this| {
+            Ok(this.diff_removed_bg.to_lua(env))
+        });
+        fields.add_field_method_get("diff_changed_bg", |env, this| {
+            Ok(this.diff_changed_bg.to_lua(env))
+        });
+        fields.add_field_method_get("theme", |_, this| Ok(this.theme.clone()));
+        // Themes set the colours (and syntax highlighting colours) through lua,
+        // so the colours can't be borrowed while one is applied
+        fields.add_field_function_set("theme", |lua, this: LuaAnyUserData, name: String| {
+            let Some(code) = theme_code(&name) else {
+                let names: Vec<&str> = THEMES.iter().map(|(n, _)| *n).collect();
+                return Err(LuaError::runtime(format!(
+                    "invalid colors.theme: there is no theme called {name}, the themes are {}",
+                    names.join(", ")
+                )));
+            };
+            lua.load(code).exec()?;
+            this.borrow_mut::<Colors>()?.theme = name;
+            Ok(())
+        });
         fields.add_field_method_set("editor_bg", |_, this, value| {
-            this.editor_bg = Color::from_lua(value);
+            this.editor_bg = Color::checked("colors.editor_bg", value)?;
             Ok(())
         });
         fields.add_field_method_set("editor_fg", |_, this, value| {
-            this.editor_fg = Color::from_lua(value);
+            this.editor_fg = Color::checked("colors.editor_fg", value)?;
             Ok(())
         });
         fields.add_field_method_set("status_bg", |_, this, value| {
-            this.status_bg = Color::from_lua(value);
+            this.status_bg = Color::checked("colors.status_bg", value)?;
             Ok(())
         });
         fields.add_field_method_set("status_fg", |_, this, value| {
-            this.status_fg = Color::from_lua(value);
+            this.status_fg = Color::checked("colors.status_fg", value)?;
             Ok(())
         });
         fields.add_field_method_set("highlight", |_, this, value| {
-            this.highlight = Color::from_lua(value);
+            this.highlight = Color::checked("colors.highlight", value)?;
             Ok(())
         });
         fields.add_field_method_set("line_number_bg", |_, this, value| {
-            this.line_number_bg = Color::from_lua(value);
+            this.line_number_bg = Color::checked("colors.line_number_bg", value)?;
             Ok(())
         });
         fields.add_field_method_set("line_number_fg", |_, this, value| {
-            this.line_number_fg = Color::from_lua(value);
+            this.line_number_fg = Color::checked("colors.line_number_fg", value)?;
             Ok(())
         });
         fields.add_field_method_set("tab_active_fg", |_, this, value| {
-            this.tab_active_fg = Color::from_lua(value);
+            this.tab_active_fg = Color::checked("colors.tab_active_fg", value)?;
             Ok(())
         });
         fields.add_field_method_set("tab_active_bg", |_, this, value| {
-            this.tab_active_bg = Color::from_lua(value);
+            this.tab_active_bg = Color::checked("colors.tab_active_bg", value)?;
             Ok(())
         });
         fields.add_field_method_set("tab_inactive_fg", |_, this, value| {
-            this.tab_inactive_fg = Color::from_lua(value);
+            this.tab_inactive_fg = Color::checked("colors.tab_inactive_fg", value)?;
             Ok(())
         });
         fields.add_field_method_set("tab_inactive_bg", |_, this, value| {
-            this.tab_inactive_bg = Color::from_lua(value);
+            this.tab_inactive_bg = Color::checked("colors.tab_inactive_bg", value)?;
             Ok(())
         });
         fields.add_field_method_set("split_fg", |_, this, value| {
-            this.split_fg = Color::from_lua(value);
+            this.split_fg = Color::checked("colors.split_fg", value)?;
             Ok(())
         });
         fields.add_field_method_set("split_bg", |_, this, value| {
-            this.split_bg = Color::from_lua(value);
+            this.split_bg = Color::checked("colors.split_bg", value)?;
             Ok(())
         });
         fields.add_field_method_set("error_bg", |_, this, value| {
-            this.error_bg = Color::from_lua(value);
+            this.error_bg = Color::checked("colors.error_bg", value)?;
             Ok(())
         });
         fields.add_field_method_set("error_fg", |_, this, value| {
-            this.error_fg = Color::from_lua(value);
+            this.error_fg = Color::checked("colors.error_fg", value)?;
             Ok(())
         });
         fields.add_field_method_set("warning_bg", |_, this, value| {
-            this.warning_bg = Color::from_lua(value);
+            this.warning_bg = Color::checked("colors.warning_bg", value)?;
             Ok(())
         });
         fields.add_field_method_set("warning_fg", |_, this, value| {
-            this.warning_fg = Color::from_lua(value);
+            this.warning_fg = Color::checked("colors.warning_fg", value)?;
             Ok(())
         });
         fields.add_field_method_set("info_bg", |_, this, value| {
-            this.info_bg = Color::from_lua(value);
+            this.info_bg = Color::checked("colors.info_bg", value)?;
             Ok(())
         });
         fields.add_field_method_set("info_fg", |_, this, value| {
-            this.info_fg = Color::from_lua(value);
+            this.info_fg = Color::checked("colors.info_fg", value)?;
             Ok(())
         });
         fields.add_field_method_set("selection_fg", |_, this, value| {
-            this.selection_fg = Color::from_lua(value);
+            this.selection_fg = Color::checked("colors.selection_fg", value)?;
             Ok(())
         });
         fields.add_field_method_set("selection_bg", |_, this, value| {
-            this.selection_bg = Color::from_lua(value);
+            this.selection_bg = Color::checked("colors.selection_bg", value)?;
             Ok(())
         });
         fields.add_field_method_set("file_tree_bg", |_, this, value| {
-            this.file_tree_bg = Color::from_lua(value);
+            this.file_tree_bg = Color::checked("colors.file_tree_bg", value)?;
             Ok(())
         });
         fields.add_field_method_set("file_tree_fg", |_, this, value| {
-            this.file_tree_fg = Color::from_lua(value);
+            this.file_tree_fg = Color::checked("colors.file_tree_fg", value)?;
             Ok(())
         });
         fields.add_field_method_set("file_tree_selection_bg", |_, this, value| {
-            this.file_tree_selection_bg = Color::from_lua(value);
+            this.file_tree_selection_bg = Color::checked("colors.file_tree_selection_bg", value)?;
             Ok(())
         });
         fields.add_field_method_set("file_tree_selection_fg", |_, this, value| {
-            this.file_tree_selection_fg = Color::from_lua(value);
+            this.file_tree_selection_fg = Color::checked("colors.file_tree_selection_fg", value)?;
             Ok(())
         });
         fields.add_field_method_set("file_tree_red", |_, this, value| {
-            this.file_tree_red = Color::from_lua(value);
+            this.file_tree_red = Color::checked("colors.file_tree_red", value)?;
             Ok(())
         });
         fields.add_field_method_set("file_tree_red", |_, this, value| {
-            this.file_tree_red = Color::from_lua(value);
+            this.file_tree_red = Color::checked("colors.file_tree_red", value)?;
             Ok(())
         });
         fields.add_field_method_set("file_tree_orange", |_, this, value| {
-            this.file_tree_orange = Color::from_lua(value);
+            this.file_tree_orange = Color::checked("colors.file_tree_orange", value)?;
             Ok(())
         });
         fields.add_field_method_set("file_tree_orange", |_, this, value| {
-            this.file_tree_orange = Color::from_lua(value);
+            this.file_tree_orange = Color::checked("colors.file_tree_orange", value)?;
             Ok(())
         });
         fields.add_field_method_set("file_tree_yellow", |_, this, value| {
-            this.file_tree_yellow = Color::from_lua(value);
+            this.file_tree_yellow = Color::checked("colors.file_tree_yellow", value)?;
             Ok(())
         });
         fields.add_field_method_set("file_tree_yellow", |_, this, value| {
-            this.file_tree_yellow = Color::from_lua(value);
+            this.file_tree_yellow = Color::checked("colors.file_tree_yellow", value)?;
             Ok(())
         });
         fields.add_field_method_set("file_tree_green", |_, this, value| {
-            this.file_tree_green = Color::from_lua(value);
+            this.file_tree_green = Color::checked("colors.file_tree_green", value)?;
             Ok(())
         });
         fields.add_field_method_set("file_tree_green", |_, this, value| {
-            this.file_tree_green = Color::from_lua(value);
+            this.file_tree_green = Color::checked("colors.file_tree_green", value)?;
             Ok(())
         });
         fields.add_field_method_set("file_tree_lightblue", |_, this, value| {
-            this.file_tree_lightblue = Color::from_lua(value);
+            this.file_tree_lightblue = Color::checked("colors.file_tree_lightblue", value)?;
             Ok(())
         });
         fields.add_field_method_set("file_tree_lightblue", |_, this, value| {
-            this.file_tree_lightblue = Color::from_lua(value);
+            this.file_tree_lightblue = Color::checked("colors.file_tree_lightblue", value)?;
             Ok(())
         });
         fields.add_field_method_set("file_tree_darkblue", |_, this, value| {
-            this.file_tree_darkblue = Color::from_lua(value);
+            this.file_tree_darkblue = Color::checked("colors.file_tree_darkblue", value)?;
             Ok(())
         });
         fields.add_field_method_set("file_tree_darkblue", |_, this, value| {
-            this.file_tree_darkblue = Color::from_lua(value);
+            this.file_tree_darkblue = Color::checked("colors.file_tree_darkblue", value)?;
             Ok(())
         });
         fields.add_field_method_set("file_tree_purple", |_, this, value| {
-            this.file_tree_purple = Color::from_lua(value);
+            this.file_tree_purple = Color::checked("colors.file_tree_purple", value)?;
             Ok(())
         });
         fields.add_field_method_set("file_tree_purple", |_, this, value| {
-            this.file_tree_purple = Color::from_lua(value);
+            this.file_tree_purple = Color::checked("colors.file_tree_purple", value)?;
             Ok(())
         });
         fields.add_field_method_set("file_tree_pink", |_, this, value| {
-            this.file_tree_pink = Color::from_lua(value);
+            this.file_tree_pink = Color::checked("colors.file_tree_pink", value)?;
             Ok(())
         });
         fields.add_field_method_set("file_tree_pink", |_, this, value| {
-            this.file_tree_pink = Color::from_lua(value);
+            this.file_tree_pink = Color::checked("colors.file_tree_pink", value)?;
             Ok(())
         });
         fields.add_field_method_set("file_tree_brown", |_, this, value| {
-            this.file_tree_brown = Color::from_lua(value);
+            this.file_tree_brown = Color::checked("colors.file_tree_brown", value)?;
             Ok(())
         });
         fields.add_field_method_set("file_tree_brown", |_, this, value| {
-            this.file_tree_brown = Color::from_lua(value);
+            this.file_tree_brown = Color::checked("colors.file_tree_brown", value)?;
             Ok(())
         });
         fields.add_field_method_set("file_tree_grey", |_, this, value| {
-            this.file_tree_grey = Color::from_lua(value);
+            this.file_tree_grey = Color::checked("colors.file_tree_grey", value)?;
             Ok(())
         });
         fields.add_field_method_set("file_tree_grey", |_, this, value| {
-            this.file_tree_grey = Color::from_lua(value);
+            this.file_tree_grey = Color::checked("colors.file_tree_grey", value)?;
+            Ok(())
+        });
+        fields.add_field_method_set("line_added_fg", |_, this, value| {
+            this.line_added_fg = Color::checked("colors.line_added_fg", value)?;
+            Ok(())
+        });
+        fields.add_field_method_set("line_modified_fg", |_, this, value| {
+            this.line_modified_fg = Color::checked("colors.line_modified_fg", value)?;
+            Ok(())
+        });
+        fields.add_field_method_set("line_removed_fg", |_, this, value| {
+            this.line_removed_fg = Color::checked("colors.line_removed_fg", value)?;
+            Ok(())
+        });
+        fields.add_field_method_set("diff_added_bg", |_, this, value| {
+            this.diff_added_bg = Color::checked("colors.diff_added_bg", value)?;
+            Ok(())
+        });
+        fields.add_field_method_set("diff_removed_bg", |_, this, value| {
+            this.diff_removed_bg = Color::checked("colors.diff_removed_bg", value)?;
+            Ok(())
+        });
+        fields.add_field_method_set("diff_changed_bg", |_, this, value| {
+            this.diff_changed_bg = Color::checked("colors.diff_changed_bg", value)?;
             Ok(())
         });
     }
@@ -353,6 +524,41 @@ pub enum Color {
 }
 
 impl Color {
+    /// Converts from a lua value into a colour, failing with an error that names the setting
+    /// if it isn't a valid colour (so mistakes are caught when the configuration file is read)
+    pub fn checked(key: &str, value: LuaValue) -> LuaResult<Self> {
+        let in_range = |value: &LuaValue| match value {
+            LuaValue::Integer(n) => (0..=255).contains(n),
+            LuaValue::Number(n) => (0.0..=255.0).contains(n) && n.fract() == 0.0,
+            _ => false,
+        };
+        let problem = match &value {
+            LuaValue::String(string) => match Self::from_lua(value.clone()) {
+                Self::Hex(hex) if Self::hex_to_rgb(&hex).is_err() => Some(format!(
+                    "'{}' isn't a colour name or a hex code like '#1e1e2e'",
+                    string.to_string_lossy()
+                )),
+                _ => None,
+            },
+            LuaValue::Table(table) => {
+                let parts: Vec<LuaValue> = table.sequence_values().collect::<LuaResult<_>>()?;
+                (parts.len() != 3 || !parts.iter().all(in_range))
+                    .then(|| "RGB colours must be a list of 3 numbers from 0 to 255".to_string())
+            }
+            LuaValue::Integer(n) if (0..=255).contains(n) => None,
+            LuaValue::Integer(_) | LuaValue::Number(_) => {
+                Some("ANSI colour codes must be whole numbers from 0 to 255".to_string())
+            }
+            other => Some(format!("expected a colour, not a {}", other.type_name())),
+        };
+        match problem {
+            Some(problem) => Err(LuaError::runtime(format!(
+                "invalid colour for {key}: {problem}"
+            ))),
+            None => Ok(Self::from_lua(value)),
+        }
+    }
+
     /// Converts from a lua value into a colour
     pub fn from_lua(value: LuaValue) -> Self {
         match value {
@@ -448,28 +654,19 @@ impl Color {
     }
 
     /// Returns a colour as a crossterm colour, ready to turn into ANSI codes
+    /// (colours the terminal can't show are swapped for the closest one it can)
     pub fn to_color(&self) -> Result<CColor> {
-        let true_color = supports_true_color();
+        let mode = color_mode();
         // Perform conversion
         Ok(match self {
             Color::Hex(hex) => {
                 let (r, g, b) = Self::hex_to_rgb(hex)?;
-                if true_color {
-                    CColor::Rgb { r, g, b }
-                } else {
-                    CColor::AnsiValue(rgb_to_xterm256(r, g, b))
-                }
+                Self::rgb_in_mode(mode, r, g, b)
             }
-            Color::Rgb(r, g, b) => {
-                if true_color {
-                    CColor::Rgb {
-                        r: *r,
-                        g: *g,
-                        b: *b,
-                    }
-                } else {
-                    CColor::AnsiValue(rgb_to_xterm256(*r, *g, *b))
-                }
+            Color::Rgb(r, g, b) => Self::rgb_in_mode(mode, *r, *g, *b),
+            Color::Ansi(code) if mode == ColorMode::Ansi16 && *code >= 16 => {
+                let (r, g, b) = xterm_to_rgb(*code);
+                Self::rgb_in_mode(mode, r, g, b)
             }
             Color::Ansi(code) => CColor::AnsiValue(*code),
             Color::Black => CColor::Black,
@@ -492,6 +689,33 @@ impl Color {
         })
     }
 
+    /// Turn an rgb value into the closest colour the terminal can show
+    fn rgb_in_mode(mode: ColorMode, r: u8, g: u8, b: u8) -> CColor {
+        match mode {
+            ColorMode::Xterm256 => CColor::AnsiValue(rgb_to_xterm256(r, g, b)),
+            // The basic colours are used by name, as some terminals only understand them that way
+            ColorMode::Ansi16 => match rgb_to_ansi16(r, g, b) {
+                0 => CColor::Black,
+                1 => CColor::DarkRed,
+                2 => CColor::DarkGreen,
+                3 => CColor::DarkYellow,
+                4 => CColor::DarkBlue,
+                5 => CColor::DarkMagenta,
+                6 => CColor::DarkCyan,
+                7 => CColor::Grey,
+                8 => CColor::DarkGrey,
+                9 => CColor::Red,
+                10 => CColor::Green,
+                11 => CColor::Yellow,
+                12 => CColor::Blue,
+                13 => CColor::Magenta,
+                14 => CColor::Cyan,
+                _ => CColor::White,
+            },
+            _ => CColor::Rgb { r, g, b },
+        }
+    }
+
     /// Turn a hex value into an rgb value
     fn hex_to_rgb(hex: &str) -> Result<(u8, u8, u8)> {
         // Remove the leading '#' if present
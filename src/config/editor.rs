@@ -2,11 +2,14 @@
 use crate::cli::VERSION;
 #[cfg(not(target_os = "windows"))]
 use crate::config::runner::RunCommand;
-use crate::editor::{Editor, FileContainer, FileLayout};
+use crate::config::{load_plugins, plugin_failures};
+use crate::editor::{Action, Direction, Editor, FileContainer, FileLayout};
+use crate::error::OxError;
 #[cfg(not(target_os = "windows"))]
 use crate::pty::Pty;
 use crate::ui::Feedback;
 use crate::{config, fatal_error, PLUGIN_BOOTSTRAP, PLUGIN_MANAGER, PLUGIN_NETWORKING, PLUGIN_RUN};
+use kaolinite::document::{Decoding, LineEnding};
 use kaolinite::utils::{get_absolute_path, get_cwd, get_file_ext, get_file_name};
 use kaolinite::Loc;
 use mlua::prelude::*;
@@ -37,6 +40,9 @@ impl LuaUserData for Editor {
                 Ok(None)
             }
         });
+        fields.add_field_method_get("block_selection", |_, editor| {
+            Ok(editor.try_doc().map(|doc| doc.cursor.block.is_some()))
+        });
         fields.add_field_method_get("document_name", |_, editor| {
             if let Some(doc) = editor.try_doc() {
                 Ok(Some(doc.file_name.clone()))
@@ -94,15 +100,41 @@ impl LuaUserData for Editor {
                 Ok(None)
             }
         });
+        fields.add_field_method_get("line_ending", |_, editor| {
+            Ok(editor.try_doc().map(|doc| doc.info.line_ending.to_string()))
+        });
+        fields.add_field_method_get("encoding", |_, editor| {
+            Ok(editor
+                .try_doc()
+                .map(|doc| doc.info.encoding.name().to_string()))
+        });
         fields.add_field_method_get("cwd", |_, _| Ok(get_cwd()));
         fields.add_field_method_get("macro_recording", |_, editor| {
             Ok(editor.macro_man.recording)
         });
         fields.add_field_method_get("macro_playing", |_, editor| Ok(editor.macro_man.playing));
+        fields.add_field_method_get("overtype", |_, editor| Ok(editor.overtype));
+        fields.add_field_method_get("zen", |_, editor| Ok(editor.zen.is_some()));
+        fields.add_field_method_get("bookmarked_lines", |_, editor| {
+            Ok(editor
+                .try_doc()
+                .map(|doc| doc.bookmarks().map(|m| m.y + 1).collect::<Vec<_>>())
+                .unwrap_or_default())
+        });
+        fields.add_field_method_get("headless", |_, editor| Ok(editor.headless));
     }
 
     #[allow(clippy::too_many_lines)]
     fn add_methods<M: LuaUserDataMethods<Self>>(methods: &mut M) {
+        // Carrying out any action by name
+        methods.add_method_mut("perform", |lua, editor, action: String| {
+            match action.parse::<Action>() {
+                Ok(action) => editor.perform_reporting(lua, action),
+                Err(err) => editor.feedback = Feedback::Error(err.to_string()),
+            }
+            editor.update_highlighter();
+            Ok(())
+        });
         // Debugging methods
         methods.add_method_mut("panic", |_, _, msg: String| {
             fatal_error(&msg);
@@ -110,13 +142,97 @@ impl LuaUserData for Editor {
         });
         // Reload the configuration file
         methods.add_method_mut("reset_terminal", |_, editor, ()| {
-            let _ = editor.terminal.start();
+            if !editor.headless {
+                let _ = editor.terminal.start();
+            }
+            Ok(())
+        });
+        // Stepping out to the shell
+        methods.add_method_mut("suspend", |_, editor, ()| {
+            if let Err(err) = editor.suspend() {
+                editor.feedback = Feedback::Error(err.to_string());
+            }
+            Ok(())
+        });
+        methods.add_method_mut("shell", |_, editor, ()| {
+            if let Err(err) = editor.shell() {
+                editor.feedback = Feedback::Error(err.to_string());
+            }
             Ok(())
         });
         methods.add_method_mut("reload_config", |lua, editor, ()| {
-            let path = editor.config_path.clone();
-            if editor.load_config(&path, lua).is_some() {
-                editor.feedback = Feedback::Error("Failed to reload config".to_string());
+            if let Err(err) = editor.reload_config(lua) {
+                editor.report_config_error(err);
+            }
+            Ok(())
+        });
+        methods.add_method_mut("show_config_error", |_, editor, ()| {
+            if let Err(err) = editor.show_config_error() {
+                editor.feedback = Feedback::Error(err.to_string());
+            }
+            Ok(())
+        });
+        methods.add_method_mut("show_stats", |_, editor, ()| {
+            if let Err(err) = editor.show_stats() {
+                editor.feedback = Feedback::Error(err.to_string());
+            }
+            Ok(())
+        });
+        methods.add_method_mut("toggle_zen", |_, editor, ()| {
+            editor.toggle_zen();
+            Ok(())
+        });
+        methods.add_method_mut("toggle_preview", |_, editor, ()| {
+            editor.toggle_preview();
+            Ok(())
+        });
+        methods.add_method_mut("diff", |_, editor, against: Option<String>| {
+            if let Err(err) = editor.toggle_diff(against.as_deref()) {
+                editor.feedback = Feedback::Error(err.to_string());
+            }
+            Ok(())
+        });
+        methods.add_method_mut("next_hunk", |_, editor, ()| {
+            editor.next_hunk(true);
+            Ok(())
+        });
+        methods.add_method_mut("prev_hunk", |_, editor, ()| {
+            editor.next_hunk(false);
+            Ok(())
+        });
+        methods.add_method_mut("revert_hunk", |_, editor, ()| {
+            editor.revert_hunk();
+            Ok(())
+        });
+        methods.add_method_mut("start_count", |_, editor, ()| {
+            editor.start_count();
+            Ok(())
+        });
+        methods.add_method_mut("count_digit", |_, editor, digit: usize| {
+            editor.count_digit(digit);
+            Ok(())
+        });
+        methods.add_method_mut("cancel_count", |_, editor, ()| {
+            editor.cancel_count();
+            Ok(())
+        });
+        methods.add_method_mut("toggle_help", |lua, editor, ()| {
+            if let Err(err) = editor.toggle_help(lua) {
+                editor.feedback = Feedback::Error(err.to_string());
+            }
+            Ok(())
+        });
+        methods.add_method_mut("complete", |_, editor, ()| {
+            editor.complete();
+            Ok(())
+        });
+        methods.add_method_mut("ring_bell", |_, editor, ()| {
+            editor.ring_bell();
+            Ok(())
+        });
+        methods.add_method_mut("set_theme", |lua, editor, name: Option<String>| {
+            if let Err(err) = editor.set_theme(lua, name.as_deref()) {
+                editor.feedback = Feedback::Error(err.to_string());
             }
             Ok(())
         });
@@ -127,26 +243,30 @@ impl LuaUserData for Editor {
             let _ = lua.load(PLUGIN_NETWORKING).exec();
             // Reload the configuration file
             let path = editor.config_path.clone();
-            if editor.load_config(&path, lua).is_some() {
-                editor.feedback = Feedback::Error("Failed to reload config".to_string());
-            }
+            editor.load_config(&path, lua);
             // Run plug-ins
             let _ = lua.load(PLUGIN_RUN).exec();
+            // Run plug-ins that are installed with manifests
+            let plugins = load_plugins(lua);
+            if let Some(msg) = plugin_failures(&plugins) {
+                editor.feedback = Feedback::Error(msg);
+            }
+            lua.set_app_data(plugins);
             // Attach plugin manager
             let _ = lua.load(PLUGIN_MANAGER).exec();
             Ok(())
         });
         // Display messages
         methods.add_method_mut("display_error", |_, editor, message: String| {
-            editor.feedback = Feedback::Error(message);
+            editor.show_feedback(Feedback::Error(message));
             Ok(())
         });
         methods.add_method_mut("display_warning", |_, editor, message: String| {
-            editor.feedback = Feedback::Warning(message);
+            editor.show_feedback(Feedback::Warning(message));
             Ok(())
         });
         methods.add_method_mut("display_info", |_, editor, message: String| {
-            editor.feedback = Feedback::Info(message);
+            editor.show_feedback(Feedback::Info(message));
             Ok(())
         });
         // Prompt the user
@@ -167,20 +287,16 @@ impl LuaUserData for Editor {
             editor.plugin_active = false;
             Ok(())
         });
-        methods.add_method_mut("remove", |_, editor, ()| {
+        methods.add_method_mut("remove", |lua, editor, ()| {
             editor.plugin_active = true;
-            if let Err(err) = editor.backspace() {
-                editor.feedback = Feedback::Error(err.to_string());
-            }
+            editor.perform_reporting(lua, Action::Backspace);
             editor.update_highlighter();
             editor.plugin_active = false;
             Ok(())
         });
-        methods.add_method_mut("insert_line", |_, editor, ()| {
+        methods.add_method_mut("insert_line", |lua, editor, ()| {
             editor.plugin_active = true;
-            if let Err(err) = editor.enter() {
-                editor.feedback = Feedback::Error(err.to_string());
-            }
+            editor.perform_reporting(lua, Action::InsertLine);
             editor.update_highlighter();
             editor.plugin_active = false;
             Ok(())
@@ -194,13 +310,12 @@ impl LuaUserData for Editor {
             editor.plugin_active = false;
             Ok(())
         });
-        methods.add_method_mut("remove_word", |_, editor, ()| {
-            if let Some(doc) = editor.try_doc_mut() {
-                let _ = doc.delete_word();
-                let y = doc.loc().y;
-                editor.update_highlighter();
-                editor.hl_edit(y);
-            }
+        methods.add_method_mut("remove_word", |lua, editor, ()| {
+            editor.perform_reporting(lua, Action::DeleteWord);
+            Ok(())
+        });
+        methods.add_method_mut("remove_sub_word", |lua, editor, ()| {
+            editor.perform_reporting(lua, Action::DeleteSubWord);
             Ok(())
         });
         // Cursor moving
@@ -212,75 +327,73 @@ impl LuaUserData for Editor {
             }
             Ok(())
         });
-        methods.add_method_mut("move_up", |_, editor, ()| {
-            editor.up();
+        methods.add_method_mut("move_up", |lua, editor, ()| {
+            editor.perform_reporting(lua, Action::MoveCursor(Direction::Up));
             editor.update_highlighter();
             Ok(())
         });
-        methods.add_method_mut("move_down", |_, editor, ()| {
-            editor.down();
+        methods.add_method_mut("move_down", |lua, editor, ()| {
+            editor.perform_reporting(lua, Action::MoveCursor(Direction::Down));
             editor.update_highlighter();
             Ok(())
         });
-        methods.add_method_mut("move_left", |_, editor, ()| {
-            editor.left();
+        methods.add_method_mut("move_left", |lua, editor, ()| {
+            editor.perform_reporting(lua, Action::MoveCursor(Direction::Left));
             editor.update_highlighter();
             Ok(())
         });
-        methods.add_method_mut("move_right", |_, editor, ()| {
-            editor.right();
+        methods.add_method_mut("move_right", |lua, editor, ()| {
+            editor.perform_reporting(lua, Action::MoveCursor(Direction::Right));
             editor.update_highlighter();
             Ok(())
         });
-        methods.add_method_mut("move_home", |_, editor, ()| {
-            if let Some(doc) = editor.try_doc_mut() {
-                doc.move_home();
-                editor.update_highlighter();
-            }
+        methods.add_method_mut("move_home", |lua, editor, ()| {
+            editor.perform_reporting(lua, Action::MoveHome);
+            editor.update_highlighter();
             Ok(())
         });
-        methods.add_method_mut("move_end", |_, editor, ()| {
-            if let Some(doc) = editor.try_doc_mut() {
-                doc.move_end();
-                editor.update_highlighter();
-            }
+        methods.add_method_mut("move_end", |lua, editor, ()| {
+            editor.perform_reporting(lua, Action::MoveEnd);
+            editor.update_highlighter();
             Ok(())
         });
-        methods.add_method_mut("move_page_up", |_, editor, ()| {
-            if let Some(doc) = editor.try_doc_mut() {
-                doc.move_page_up();
-                editor.update_highlighter();
-            }
+        methods.add_method_mut("move_page_up", |lua, editor, ()| {
+            editor.perform_reporting(lua, Action::MovePageUp);
+            editor.update_highlighter();
             Ok(())
         });
-        methods.add_method_mut("move_page_down", |_, editor, ()| {
-            if let Some(doc) = editor.try_doc_mut() {
-                doc.move_page_down();
-                editor.update_highlighter();
-            }
+        methods.add_method_mut("move_page_down", |lua, editor, ()| {
+            editor.perform_reporting(lua, Action::MovePageDown);
+            editor.update_highlighter();
             Ok(())
         });
-        methods.add_method_mut("move_top", |_, editor, ()| {
-            if let Some(doc) = editor.try_doc_mut() {
-                doc.move_top();
-                editor.update_highlighter();
-            }
+        methods.add_method_mut("move_top", |lua, editor, ()| {
+            editor.perform_reporting(lua, Action::MoveTop);
+            editor.update_highlighter();
             Ok(())
         });
-        methods.add_method_mut("move_bottom", |_, editor, ()| {
-            if let Some(doc) = editor.try_doc_mut() {
-                doc.move_bottom();
-                editor.update_highlighter();
-            }
+        methods.add_method_mut("move_bottom", |lua, editor, ()| {
+            editor.perform_reporting(lua, Action::MoveBottom);
+            editor.update_highlighter();
+            Ok(())
+        });
+        methods.add_method_mut("move_previous_word", |lua, editor, ()| {
+            editor.perform_reporting(lua, Action::MovePreviousWord);
+            editor.update_highlighter();
+            Ok(())
+        });
+        methods.add_method_mut("move_next_word", |lua, editor, ()| {
+            editor.perform_reporting(lua, Action::MoveNextWord);
+            editor.update_highlighter();
             Ok(())
         });
-        methods.add_method_mut("move_previous_word", |_, editor, ()| {
-            editor.prev_word();
+        methods.add_method_mut("move_previous_sub_word", |lua, editor, ()| {
+            editor.perform_reporting(lua, Action::MovePreviousSubWord);
             editor.update_highlighter();
             Ok(())
         });
-        methods.add_method_mut("move_next_word", |_, editor, ()| {
-            editor.next_word();
+        methods.add_method_mut("move_next_sub_word", |lua, editor, ()| {
+            editor.perform_reporting(lua, Action::MoveNextSubWord);
             editor.update_highlighter();
             Ok(())
         });
@@ -290,6 +403,25 @@ impl LuaUserData for Editor {
             }
             Ok(())
         });
+        // Scroll the view sideways without moving the cursor (by the scroll amount by default)
+        methods.add_method_mut("scroll_left", |_, editor, columns: Option<usize>| {
+            let columns = columns.unwrap_or(config!(editor.config, terminal).scroll_amount);
+            if let Some(doc) = editor.try_doc_mut() {
+                for _ in 0..columns {
+                    doc.scroll_left();
+                }
+            }
+            Ok(())
+        });
+        methods.add_method_mut("scroll_right", |_, editor, columns: Option<usize>| {
+            let columns = columns.unwrap_or(config!(editor.config, terminal).scroll_amount);
+            if let Some(doc) = editor.try_doc_mut() {
+                for _ in 0..columns {
+                    doc.scroll_right();
+                }
+            }
+            Ok(())
+        });
         methods.add_method_mut("move_line_up", |_, editor, ()| {
             if let Some(doc) = editor.try_doc_mut() {
                 let _ = doc.swap_line_up();
@@ -310,29 +442,78 @@ impl LuaUserData for Editor {
             }
             Ok(())
         });
+        methods.add_method_mut("join_lines", |_, editor, ()| {
+            if let Err(err) = editor.join_lines() {
+                editor.feedback = Feedback::Error(err.to_string());
+            }
+            Ok(())
+        });
+        methods.add_method_mut(
+            "sort_lines",
+            |_, editor, (reverse, unique): (Option<bool>, Option<bool>)| {
+                let (reverse, unique) = (reverse.unwrap_or(false), unique.unwrap_or(false));
+                if let Err(err) = editor.sort_lines(reverse, unique) {
+                    editor.feedback = Feedback::Error(err.to_string());
+                }
+                Ok(())
+            },
+        );
+        methods.add_method_mut("change_case", |_, editor, case: String| {
+            if let Err(err) = editor.change_case(&case) {
+                editor.feedback = Feedback::Error(err.to_string());
+            }
+            Ok(())
+        });
+        methods.add_method_mut("toggle_overtype", |_, editor, ()| {
+            editor.toggle_overtype();
+            Ok(())
+        });
+        methods.add_method_mut(
+            "convert_indentation",
+            |_, editor, (kind, everywhere): (String, Option<bool>)| {
+                let result = match kind.as_str() {
+                    "spaces" => editor.convert_indentation(true, everywhere.unwrap_or(false)),
+                    "tabs" => editor.convert_indentation(false, false),
+                    _ => {
+                        let msg = format!("Can't convert indentation to '{kind}'");
+                        editor.feedback = Feedback::Error(msg);
+                        Ok(())
+                    }
+                };
+                if let Err(err) = result {
+                    editor.feedback = Feedback::Error(err.to_string());
+                }
+                Ok(())
+            },
+        );
         // Cursor selection and clipboard
-        methods.add_method_mut("select_up", |_, editor, ()| {
-            editor.select_up();
+        methods.add_method_mut("select_up", |lua, editor, ()| {
+            editor.perform_reporting(lua, Action::Select(Direction::Up));
+            editor.update_highlighter();
+            Ok(())
+        });
+        methods.add_method_mut("select_down", |lua, editor, ()| {
+            editor.perform_reporting(lua, Action::Select(Direction::Down));
             editor.update_highlighter();
             Ok(())
         });
-        methods.add_method_mut("select_down", |_, editor, ()| {
-            editor.select_down();
+        methods.add_method_mut("select_home", |_, editor, ()| {
+            editor.select_home();
             editor.update_highlighter();
             Ok(())
         });
-        methods.add_method_mut("select_left", |_, editor, ()| {
-            editor.select_left();
+        methods.add_method_mut("select_left", |lua, editor, ()| {
+            editor.perform_reporting(lua, Action::Select(Direction::Left));
             editor.update_highlighter();
             Ok(())
         });
-        methods.add_method_mut("select_right", |_, editor, ()| {
-            editor.select_right();
+        methods.add_method_mut("select_right", |lua, editor, ()| {
+            editor.perform_reporting(lua, Action::Select(Direction::Right));
             editor.update_highlighter();
             Ok(())
         });
-        methods.add_method_mut("select_all", |_, editor, ()| {
-            editor.select_all();
+        methods.add_method_mut("select_all", |lua, editor, ()| {
+            editor.perform_reporting(lua, Action::SelectAll);
             editor.update_highlighter();
             Ok(())
         });
@@ -344,9 +525,76 @@ impl LuaUserData for Editor {
             }
             Ok(())
         });
+        methods.add_method_mut(
+            "select",
+            |_, editor, (x1, y1, x2, y2): (usize, usize, usize, usize)| {
+                if let Some(doc) = editor.try_doc_mut() {
+                    doc.cancel_selection();
+                    doc.move_to(&Loc {
+                        x: x1,
+                        y: y1.saturating_sub(1),
+                    });
+                    doc.select_to(&Loc {
+                        x: x2,
+                        y: y2.saturating_sub(1),
+                    });
+                    editor.update_highlighter();
+                }
+                Ok(())
+            },
+        );
+        methods.add_method("selection_text", |_, editor, ()| {
+            Ok(editor.try_doc().map(kaolinite::Document::selection_text))
+        });
+        methods.add_method_mut("select_next_occurrence", |_, editor, ()| {
+            editor.select_next_occurrence();
+            editor.update_highlighter();
+            Ok(())
+        });
+        methods.add_method_mut("clear_cursors", |_, editor, ()| {
+            editor.clear_cursors();
+            Ok(())
+        });
         methods.add_method_mut("cancel_selection", |_, editor, ()| {
             if let Some(doc) = editor.try_doc_mut() {
-                doc.cancel_selection();
+                if doc.cursor.block.is_some() {
+                    doc.exit_block();
+                } else {
+                    doc.cancel_selection();
+                }
+            }
+            Ok(())
+        });
+        methods.add_method_mut("select_block_to", |_, editor, (x, y): (usize, usize)| {
+            if let Some(doc) = editor.try_doc_mut() {
+                doc.select_block_to(&Loc {
+                    x,
+                    y: y.saturating_sub(1),
+                });
+            }
+            Ok(())
+        });
+        methods.add_method_mut("select_block_up", |_, editor, ()| {
+            if let Some(doc) = editor.try_doc_mut() {
+                doc.block_up();
+            }
+            Ok(())
+        });
+        methods.add_method_mut("select_block_down", |_, editor, ()| {
+            if let Some(doc) = editor.try_doc_mut() {
+                doc.block_down();
+            }
+            Ok(())
+        });
+        methods.add_method_mut("select_block_left", |_, editor, ()| {
+            if let Some(doc) = editor.try_doc_mut() {
+                doc.block_left();
+            }
+            Ok(())
+        });
+        methods.add_method_mut("select_block_right", |_, editor, ()| {
+            if let Some(doc) = editor.try_doc_mut() {
+                doc.block_right();
             }
             Ok(())
         });
@@ -366,6 +614,26 @@ impl LuaUserData for Editor {
             editor.plugin_active = false;
             Ok(())
         });
+        methods.add_method_mut("paste_latest", |_, editor, ()| {
+            match editor.paste_latest() {
+                Ok(pasted) => Ok(pasted),
+                Err(err) => {
+                    editor.feedback = Feedback::Error(err.to_string());
+                    Ok(true)
+                }
+            }
+        });
+        methods.add_method_mut("paste_from_history", |lua, editor, ()| {
+            match editor.paste_from_history(lua) {
+                Ok(()) => (),
+                Err(OxError::Cancelled) if editor.clipboard.entries.is_empty() => {
+                    editor.feedback = Feedback::Info("Nothing has been copied yet".to_string());
+                }
+                Err(OxError::Cancelled) => (),
+                Err(err) => editor.feedback = Feedback::Error(err.to_string()),
+            }
+            Ok(())
+        });
         methods.add_method_mut("copy", |_, editor, ()| {
             if let Err(err) = editor.copy() {
                 editor.feedback = Feedback::Error(err.to_string());
@@ -525,75 +793,234 @@ impl LuaUserData for Editor {
                 Ok(None)
             }
         });
+        // Lines are read one at a time (so the editor can be used in between),
+        // stopping early if the function returns false
+        methods.add_function(
+            "each_line",
+            |_, (editor, function): (LuaAnyUserData, LuaFunction)| {
+                let mut y = 0;
+                loop {
+                    let line = {
+                        let mut editor = editor.borrow_mut::<Editor>()?;
+                        let Some(doc) = editor.try_doc_mut() else {
+                            break;
+                        };
+                        doc.load_to(y + 1);
+                        doc.line(y)
+                    };
+                    let Some(line) = line else {
+                        break;
+                    };
+                    y += 1;
+                    if function.call::<Option<bool>>((line, y))? == Some(false) {
+                        break;
+                    }
+                }
+                Ok(())
+            },
+        );
         // Document management
-        methods.add_method_mut("previous_tab", |_, editor, ()| {
-            editor.prev();
+        methods.add_method_mut("previous_tab", |lua, editor, ()| {
+            editor.perform_reporting(lua, Action::PreviousTab);
+            Ok(())
+        });
+        methods.add_method_mut("go_to_tab", |lua, editor, n: usize| {
+            editor.perform_reporting(lua, Action::GoToTab(n));
             Ok(())
         });
-        methods.add_method_mut("next_tab", |_, editor, ()| {
-            editor.next();
+        methods.add_method_mut("next_tab", |lua, editor, ()| {
+            editor.perform_reporting(lua, Action::NextTab);
             Ok(())
         });
         methods.add_method_mut("move_to_document", |_, editor, id: usize| {
-            editor.files.move_to(editor.ptr.clone(), id);
+            editor.switch_to(id);
+            Ok(())
+        });
+        methods.add_method_mut("new", |lua, editor, ()| {
+            editor.perform_reporting(lua, Action::NewDocument);
             Ok(())
         });
-        methods.add_method_mut("new", |_, editor, ()| {
-            if let Err(err) = editor.new_document() {
+        methods.add_method_mut("scratch", |_, editor, name: Option<String>| {
+            if let Err(err) = editor.scratch(&name.unwrap_or_default()) {
                 editor.feedback = Feedback::Error(err.to_string());
             }
             Ok(())
         });
-        methods.add_method_mut("open", |_, editor, ()| {
-            if let Err(err) = editor.open_document() {
+        methods.add_method_mut("notes", |_, editor, ()| {
+            if let Err(err) = editor.notes() {
                 editor.feedback = Feedback::Error(err.to_string());
             }
             Ok(())
         });
-        methods.add_method_mut("save", |_, editor, ()| {
-            if let Err(err) = editor.save() {
-                editor.feedback = Feedback::Error(err.to_string());
+        methods.add_method_mut("open", |lua, editor, ()| {
+            editor.perform_reporting(lua, Action::OpenDocument);
+            Ok(())
+        });
+        methods.add_method_mut("annotate", |_, editor, ()| {
+            match editor.annotate() {
+                Ok(()) | Err(OxError::Cancelled) => (),
+                Err(err) => editor.feedback = Feedback::Error(err.to_string()),
+            }
+            Ok(())
+        });
+        methods.add_method_mut("annotations", |lua, editor, ()| {
+            match editor.annotations(lua) {
+                Ok(()) | Err(OxError::Cancelled) => (),
+                Err(err) => editor.feedback = Feedback::Error(err.to_string()),
+            }
+            Ok(())
+        });
+        methods.add_method_mut("show_annotation", |_, editor, ()| {
+            editor.show_annotation();
+            Ok(())
+        });
+        methods.add_method_mut("toggle_bookmark", |_, editor, line: Option<usize>| {
+            match line {
+                Some(line) => editor.toggle_bookmark_at(line.saturating_sub(1)),
+                None => editor.toggle_bookmark(),
             }
             Ok(())
         });
-        methods.add_method_mut("save_as", |_, editor, ()| {
-            if let Err(err) = editor.save_as() {
+        methods.add_method("is_bookmarked", |_, editor, line: usize| {
+            let y = line.saturating_sub(1);
+            Ok(editor
+                .try_doc()
+                .is_some_and(|doc| doc.bookmark_at(y).is_some()))
+        });
+        methods.add_method_mut("next_bookmark", |_, editor, ()| {
+            editor.next_bookmark(true);
+            Ok(())
+        });
+        methods.add_method_mut("prev_bookmark", |_, editor, ()| {
+            editor.next_bookmark(false);
+            Ok(())
+        });
+        methods.add_method_mut("bookmarks", |lua, editor, ()| {
+            match editor.bookmarks(lua) {
+                Ok(()) | Err(OxError::Cancelled) => (),
+                Err(err) => editor.feedback = Feedback::Error(err.to_string()),
+            }
+            Ok(())
+        });
+        methods.add_method_mut("next_diagnostic", |_, editor, ()| {
+            editor.next_diagnostic(true);
+            Ok(())
+        });
+        methods.add_method_mut("prev_diagnostic", |_, editor, ()| {
+            editor.next_diagnostic(false);
+            Ok(())
+        });
+        methods.add_method_mut("toggle_spell_check", |_, editor, ()| {
+            editor.toggle_spell_check();
+            Ok(())
+        });
+        methods.add_method_mut("next_misspelling", |_, editor, ()| {
+            editor.next_misspelling(true);
+            Ok(())
+        });
+        methods.add_method_mut("prev_misspelling", |_, editor, ()| {
+            editor.next_misspelling(false);
+            Ok(())
+        });
+        methods.add_method_mut("spelling_suggestions", |_, editor, ()| {
+            if let Err(err) = editor.spelling_suggestions() {
                 editor.feedback = Feedback::Error(err.to_string());
             }
             Ok(())
         });
-        methods.add_method_mut("save_all", |_, editor, ()| {
-            if let Err(err) = editor.save_all() {
+        methods.add_method_mut("buffers", |lua, editor, ()| {
+            match editor.buffers(lua) {
+                Ok(()) | Err(OxError::Cancelled) => (),
+                Err(err) => editor.feedback = Feedback::Error(err.to_string()),
+            }
+            Ok(())
+        });
+        methods.add_method_mut("recent", |lua, editor, ()| {
+            match editor.recent(lua) {
+                Ok(()) | Err(OxError::Cancelled) => (),
+                Err(err) => editor.feedback = Feedback::Error(err.to_string()),
+            }
+            Ok(())
+        });
+        methods.add_method_mut("history", |lua, editor, path: Option<String>| {
+            match editor.history(lua, path.as_deref()) {
+                Ok(()) | Err(OxError::Cancelled) => (),
+                Err(err) => editor.feedback = Feedback::Error(err.to_string()),
+            }
+            Ok(())
+        });
+        methods.add_method_mut("reopen_closed", |_, editor, ()| {
+            if let Err(err) = editor.reopen_closed() {
                 editor.feedback = Feedback::Error(err.to_string());
             }
             Ok(())
         });
-        methods.add_method_mut("quit", |_, editor, ()| {
-            if let Err(err) = editor.quit() {
+        methods.add_method_mut("find_in_files", |lua, editor, pattern: Option<String>| {
+            match editor.find_in_files(lua, pattern.as_deref()) {
+                Ok(()) | Err(OxError::Cancelled) => (),
+                Err(err) => editor.feedback = Feedback::Error(err.to_string()),
+            }
+            Ok(())
+        });
+        methods.add_method_mut("build", |lua, editor, command: Option<String>| {
+            if let Err(err) = editor.build(lua, command.as_deref()) {
                 editor.feedback = Feedback::Error(err.to_string());
             }
             Ok(())
         });
-        methods.add_method_mut("undo", |_, editor, ()| {
-            if let Err(err) = editor.undo() {
+        methods.add_method_mut("next_build_location", |_, editor, ()| {
+            if let Err(err) = editor.next_build_location(true) {
                 editor.feedback = Feedback::Error(err.to_string());
             }
-            editor.update_highlighter();
             Ok(())
         });
-        methods.add_method_mut("redo", |_, editor, ()| {
-            if let Err(err) = editor.redo() {
+        methods.add_method_mut("prev_build_location", |_, editor, ()| {
+            if let Err(err) = editor.next_build_location(false) {
                 editor.feedback = Feedback::Error(err.to_string());
             }
-            editor.update_highlighter();
             Ok(())
         });
-        methods.add_method_mut("commit", |_, editor, ()| {
-            if let Some(doc) = editor.try_doc_mut() {
-                doc.commit();
+        methods.add_method_mut("find_file", |lua, editor, ()| {
+            match editor.find_file(lua) {
+                Ok(()) | Err(OxError::Cancelled) => (),
+                Err(err) => editor.feedback = Feedback::Error(err.to_string()),
             }
             Ok(())
         });
+        methods.add_method_mut("save", |lua, editor, ()| {
+            editor.perform_reporting(lua, Action::SaveFile);
+            Ok(())
+        });
+        methods.add_method_mut("save_as", |lua, editor, ()| {
+            editor.perform_reporting(lua, Action::SaveAs);
+            Ok(())
+        });
+        methods.add_method_mut("save_all", |lua, editor, ()| {
+            editor.perform_reporting(lua, Action::SaveAll);
+            Ok(())
+        });
+        methods.add_method_mut("quit", |lua, editor, ()| {
+            editor.perform_reporting(lua, Action::Quit);
+            Ok(())
+        });
+        methods.add_method_mut("quit_all", |lua, editor, ()| {
+            editor.perform_reporting(lua, Action::QuitAll);
+            Ok(())
+        });
+        methods.add_method_mut("undo", |lua, editor, ()| {
+            editor.perform_reporting(lua, Action::Undo);
+            editor.update_highlighter();
+            Ok(())
+        });
+        methods.add_method_mut("redo", |lua, editor, ()| {
+            editor.perform_reporting(lua, Action::Redo);
+            editor.update_highlighter();
+            Ok(())
+        });
+        methods.add_method_mut("commit", |lua, editor, ()| {
+            editor.perform_reporting(lua, Action::Commit);
+            Ok(())
+        });
         // Split management
         methods.add_method_mut("open_split_up", |_, editor, file: String| {
             if let Ok(fc) = editor.open_fc(&file) {
@@ -643,28 +1070,36 @@ impl LuaUserData for Editor {
                 Ok(false)
             }
         });
+        methods.add_method_mut("split_vertical", |_, editor, ()| {
+            if let Err(err) = editor.split_current(true) {
+                editor.feedback = Feedback::Error(err.to_string());
+            }
+            Ok(())
+        });
+        methods.add_method_mut("split_horizontal", |_, editor, ()| {
+            if let Err(err) = editor.split_current(false) {
+                editor.feedback = Feedback::Error(err.to_string());
+            }
+            Ok(())
+        });
         methods.add_method_mut(
             "grow_split",
-            |_, editor, (amount, direction): (f64, String)| {
-                match direction.as_str() {
-                    "width" => editor.files.grow_width(&editor.ptr, amount),
-                    "height" => editor.files.grow_height(&editor.ptr, amount),
-                    _ => (),
-                }
+            |_, editor, (amount, direction): (Option<f64>, String)| {
+                editor.resize_split(true, &direction, amount);
                 Ok(())
             },
         );
         methods.add_method_mut(
             "shrink_split",
-            |_, editor, (amount, direction): (f64, String)| {
-                match direction.as_str() {
-                    "width" => editor.files.shrink_width(&editor.ptr, amount),
-                    "height" => editor.files.shrink_height(&editor.ptr, amount),
-                    _ => (),
-                }
+            |_, editor, (amount, direction): (Option<f64>, String)| {
+                editor.resize_split(false, &direction, amount);
                 Ok(())
             },
         );
+        methods.add_method_mut("equalise_splits", |_, editor, ()| {
+            editor.equalise_splits();
+            Ok(())
+        });
         methods.add_method_mut("focus_split_up", |_, editor, ()| {
             editor.ptr = FileLayout::move_up(editor.ptr.clone(), &editor.render_cache.span);
             editor.cache_old_ptr(&editor.ptr.clone());
@@ -703,14 +1138,22 @@ impl LuaUserData for Editor {
             Ok(())
         });
         // Searching and replacing
-        methods.add_method_mut("search", |lua, editor, ()| {
+        methods.add_method_mut("search", |lua, editor, pattern: Option<String>| {
+            // Given a pattern, move to its next match straight away and give where it is
+            if let Some(pattern) = pattern {
+                let found = editor.find_next(&pattern);
+                return Ok(found.map(|loc| LuaLoc {
+                    x: loc.x,
+                    y: loc.y + 1,
+                }));
+            }
             if let Err(err) = editor.search(lua) {
                 editor.feedback = Feedback::Error(err.to_string());
             }
             editor.update_highlighter();
             editor.needs_rerender = true;
             let _ = editor.render(lua);
-            Ok(())
+            Ok(None)
         });
         methods.add_method_mut("replace", |lua, editor, ()| {
             if let Err(err) = editor.replace(lua) {
@@ -721,6 +1164,15 @@ impl LuaUserData for Editor {
             let _ = editor.render(lua);
             Ok(())
         });
+        methods.add_method_mut(
+            "replace_all",
+            |_, editor, (target, into): (String, String)| {
+                if editor.warn_if_binary() {
+                    return Ok(0);
+                }
+                Ok(editor.replace_all(&target, &into))
+            },
+        );
         methods.add_method_mut("move_next_match", |_, editor, query: String| {
             editor.next_match(&query);
             if let Some(doc) = editor.try_doc_mut() {
@@ -744,6 +1196,43 @@ impl LuaUserData for Editor {
             }
             Ok(())
         });
+        methods.add_method_mut("convert_line_endings", |_, editor, name: String| {
+            let ending = match name.to_lowercase().as_str() {
+                "lf" => LineEnding::Lf,
+                "crlf" => LineEnding::Crlf,
+                _ => {
+                    editor.feedback = Feedback::Error(format!("Invalid line ending: {name}"));
+                    return Ok(());
+                }
+            };
+            if let Some(doc) = editor.try_doc_mut() {
+                // Batch it up so that the conversion is undone in one go
+                doc.batch(|doc| doc.convert_line_endings(ending));
+            }
+            editor.reload_highlight();
+            Ok(())
+        });
+        methods.add_method_mut("set_encoding", |_, editor, name: String| {
+            let Some(doc) = editor.try_doc_mut() else {
+                return Ok(());
+            };
+            editor.feedback = match doc.reload_with_encoding(&name) {
+                Ok(()) if doc.info.decoding == Decoding::Lossy => Feedback::Warning(format!(
+                    "Reloaded document as {}, opened as read only due to invalid bytes",
+                    doc.info.encoding.name()
+                )),
+                Ok(()) => {
+                    Feedback::Info(format!("Reloaded document as {}", doc.info.encoding.name()))
+                }
+                Err(err) => Feedback::Error(OxError::from(err).to_string()),
+            };
+            editor.reload_highlight();
+            Ok(())
+        });
+        methods.add_method_mut("force_text", |_, editor, ()| {
+            editor.force_text();
+            Ok(())
+        });
         methods.add_method_mut("set_file_type", |_, editor, name: String| {
             if let Some(actual_doc) = editor.try_doc() {
                 let doc = config!(editor.config, document);
@@ -904,14 +1393,10 @@ impl LuaUserData for Editor {
             Ok(())
         });
         // Miscellaneous
-        methods.add_method_mut("open_command_line", |_, editor, ()| {
-            match editor.prompt("Command") {
-                Ok(command) => {
-                    editor.command = Some(command);
-                }
-                Err(err) => {
-                    editor.feedback = Feedback::Error(err.to_string());
-                }
+        methods.add_method_mut("open_command_line", |lua, editor, ()| {
+            match editor.command_prompt() {
+                Ok(command) => editor.perform_reporting(lua, Action::RunCommand(command)),
+                Err(err) => editor.feedback = Feedback::Error(err.to_string()),
             }
             Ok(())
         });
@@ -932,6 +1417,21 @@ impl LuaUserData for Editor {
             }
             Ok(())
         });
+        methods.add_method_mut("macro_save", |_, editor, name: String| {
+            editor.feedback = match editor.macro_man.save(&name) {
+                Ok(()) => Feedback::Info(format!("Macro saved as '{name}'")),
+                Err(err) => Feedback::Error(err.to_string()),
+            };
+            Ok(())
+        });
+        methods.add_method_mut("macro_run", |_, editor, name: String| {
+            if let Err(err) = editor.macro_man.play_saved(&name) {
+                editor.feedback = Feedback::Error(err.to_string());
+            } else if let Some(doc) = editor.try_doc_mut() {
+                doc.commit();
+            }
+            Ok(())
+        });
     }
 }
 
@@ -7,6 +7,7 @@ pub struct FileTree {
     pub move_focus_to_file: bool,
     pub icons: bool,
     pub language_icons: bool,
+    pub git_status: bool,
 }
 
 impl Default for FileTree {
@@ -16,6 +17,7 @@ impl Default for FileTree {
             move_focus_to_file: true,
             icons: false,
             language_icons: true,
+            git_status: true,
         }
     }
 }
@@ -42,5 +44,10 @@ impl LuaUserData for FileTree {
             this.language_icons = value;
             Ok(())
         });
+        fields.add_field_method_get("git_status", |_, this| Ok(this.git_status));
+        fields.add_field_method_set("git_status", |_, this, value| {
+            this.git_status = value;
+            Ok(())
+        });
     }
 }
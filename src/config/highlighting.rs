@@ -3,11 +3,12 @@ use crate::error::{OxError, Result};
 use crossterm::style::Color as CColor;
 use mlua::prelude::*;
 use std::collections::HashMap;
-use synoptic::Highlighter;
+use synoptic::{Highlighter, Regex};
 
 use super::Color;
 
 type BoundedInterpArgs = (String, String, String, String, String, bool);
+type NewLanguageArgs = (String, LuaTable, Option<Vec<String>>);
 
 /// For storing configuration information related to syntax highlighting
 #[derive(Debug)]
@@ -15,6 +16,12 @@ type BoundedInterpArgs = (String, String, String, String, String, bool);
 pub struct SyntaxHighlighting {
     pub theme: HashMap<String, Color>,
     pub user_rules: HashMap<String, Highlighter>,
+    /// Extra rules to add on top of a language (built in or user defined)
+    pub user_extensions: HashMap<String, Vec<SyntaxRule>>,
+    /// The keywords of each language declared in the configuration file (for completion)
+    pub keywords: HashMap<String, Vec<String>>,
+    /// Problems with the rules in the configuration file, to show once it has been read
+    pub warnings: Vec<String>,
 }
 
 impl Default for SyntaxHighlighting {
@@ -51,22 +58,213 @@ impl Default for SyntaxHighlighting {
         Self {
             theme,
             user_rules: HashMap::default(),
+            user_extensions: HashMap::default(),
+            keywords: HashMap::default(),
+            warnings: vec![],
+        }
+    }
+}
+
+/// A rule for highlighting part of a language, as declared in the configuration file
+#[derive(Debug, Clone)]
+pub enum SyntaxRule {
+    Keyword {
+        name: String,
+        pattern: String,
+    },
+    Bounded {
+        name: String,
+        start: String,
+        end: String,
+        escape: bool,
+    },
+    BoundedInterp {
+        name: String,
+        start: String,
+        end: String,
+        i_start: String,
+        i_end: String,
+        escape: bool,
+    },
+}
+
+impl SyntaxRule {
+    /// Read a rule from a table made by one of the rule functions (e.g. syntax:keyword)
+    fn from_table(rule: &HashMap<String, String>) -> std::result::Result<Self, String> {
+        let get = |key: &str| {
+            rule.get(key)
+                .cloned()
+                .ok_or_else(|| format!("is missing its {key}"))
+        };
+        let name = get("name")?;
+        Ok(match get("kind")?.as_str() {
+            "keyword" => Self::Keyword {
+                name,
+                pattern: get("pattern")?,
+            },
+            "bounded" => Self::Bounded {
+                name,
+                start: get("start")?,
+                end: get("end")?,
+                escape: get("escape")? == "true",
+            },
+            "bounded_interpolation" => Self::BoundedInterp {
+                name,
+                start: get("start")?,
+                end: get("end")?,
+                i_start: get("i_start")?,
+                i_end: get("i_end")?,
+                escape: get("escape")? == "true",
+            },
+            kind => return Err(format!("has an unknown kind '{kind}'")),
+        })
+    }
+
+    /// The words this rule highlights, if it is a keyword rule that is just a list of words
+    /// (like `\b(fn|let|match)\b`)
+    fn words(&self) -> Option<Vec<String>> {
+        let Self::Keyword { pattern, .. } = self else {
+            return None;
+        };
+        let pattern = pattern.replace(r"\b", "");
+        let list = pattern
+            .strip_prefix("(?:")
+            .or_else(|| pattern.strip_prefix('('))
+            .and_then(|p| p.strip_suffix(')'))
+            .unwrap_or(&pattern);
+        let words: Vec<String> = list.split('|').map(str::to_string).collect();
+        words
+            .iter()
+            .all(|w| !w.is_empty() && w.chars().all(|c| c.is_alphanumeric() || c == '_'))
+            .then_some(words)
+    }
+
+    /// Check that the patterns in this rule are valid regular expressions
+    fn validate(&self) -> std::result::Result<(), String> {
+        let patterns = match self {
+            Self::Keyword { pattern, .. } => vec![pattern],
+            Self::Bounded { start, end, .. } => vec![start, end],
+            Self::BoundedInterp {
+                start,
+                end,
+                i_start,
+                i_end,
+                ..
+            } => vec![start, end, i_start, i_end],
+        };
+        for pattern in patterns {
+            Regex::new(pattern).map_err(|_| format!("has an invalid pattern '{pattern}'"))?;
+        }
+        Ok(())
+    }
+
+    /// Add this rule to a highlighter
+    pub fn apply(&self, highlighter: &mut Highlighter) {
+        match self.clone() {
+            Self::Keyword { name, pattern } => highlighter.keyword(name, &pattern),
+            Self::Bounded {
+                name,
+                start,
+                end,
+                escape,
+            } => highlighter.bounded(name, start, end, escape),
+            Self::BoundedInterp {
+                name,
+                start,
+                end,
+                i_start,
+                i_end,
+                escape,
+            } => highlighter.bounded_interp(name, start, end, i_start, i_end, escape),
         }
     }
 }
 
 impl SyntaxHighlighting {
-    /// Get a colour from the theme
+    /// Get a colour from the theme, where a kind like `keyword.control` that hasn't been
+    /// given a colour of its own takes the colour of `keyword`
     pub fn get_theme(&self, name: &str) -> Result<CColor> {
-        if let Some(col) = self.theme.get(name) {
-            col.to_color()
-        } else {
-            let msg = format!("{name} has not been given a colour in the theme");
-            Err(OxError::Config { msg })
+        let mut kind = name;
+        loop {
+            if let Some(col) = self.theme.get(kind) {
+                return col.to_color();
+            }
+            match kind.rsplit_once('.') {
+                Some((parent, _)) => kind = parent,
+                None => break,
+            }
         }
+        let msg = format!("{name} has not been given a colour in the theme");
+        Err(OxError::Config { msg })
+    }
+
+    /// Read the rules for a language, leaving out (and warning about) any that are malformed
+    fn read_rules(&mut self, language: &str, rules: &LuaTable) -> LuaResult<Vec<SyntaxRule>> {
+        let mut result = vec![];
+        for rule_idx in 1..=(rules.len()?) {
+            let table = rules.get::<HashMap<String, String>>(rule_idx)?;
+            let rule = SyntaxRule::from_table(&table).and_then(|r| r.validate().map(|()| r));
+            match rule {
+                Ok(rule) => result.push(rule),
+                Err(problem) => {
+                    let name = table
+                        .get("name")
+                        .map_or(format!("#{rule_idx}"), |n| format!("'{n}'"));
+                    self.warnings
+                        .push(format!("Syntax rule {name} for {language} {problem}"));
+                }
+            }
+        }
+        Ok(result)
+    }
+
+    /// Remember the words of any keyword rules, so they can be offered as completions
+    fn note_keywords(&mut self, language: &str, rules: &[SyntaxRule]) {
+        let words = self.keywords.entry(language.to_string()).or_default();
+        for rule in rules {
+            words.extend(rule.words().into_iter().flatten());
+        }
+        words.sort();
+        words.dedup();
+    }
+
+    /// Take the warnings about malformed rules, joined up for the feedback line
+    pub fn take_warnings(&mut self) -> Option<String> {
+        let warnings = std::mem::take(&mut self.warnings);
+        (!warnings.is_empty()).then(|| warnings.join(", "))
     }
 }
 
+/// Make sure there is a file type for a language, covering the given extensions
+fn register_extensions(lua: &Lua, language: &str, extensions: Vec<String>) -> LuaResult<()> {
+    let file_types = match lua.globals().get::<LuaValue>("file_types")? {
+        LuaValue::Table(table) => table,
+        _ => {
+            let table = lua.create_table()?;
+            lua.globals().set("file_types", table.clone())?;
+            table
+        }
+    };
+    if let Some(info) = file_types.get::<Option<LuaTable>>(language)? {
+        let existing = info
+            .get::<Option<LuaTable>>("extensions")?
+            .unwrap_or(lua.create_table()?);
+        for ext in extensions {
+            existing.push(ext)?;
+        }
+        info.set("extensions", existing)?;
+    } else {
+        let info = lua.create_table()?;
+        info.set("icon", "󰈙 ")?;
+        info.set("extensions", extensions)?;
+        info.set("files", lua.create_table()?)?;
+        info.set("modelines", lua.create_table()?)?;
+        info.set("color", "grey")?;
+        file_types.set(language, info)?;
+    }
+    Ok(())
+}
+
 impl LuaUserData for SyntaxHighlighting {
     fn add_methods<M: LuaUserDataMethods<Self>>(methods: &mut M) {
         methods.add_method_mut(
@@ -114,44 +312,43 @@ impl LuaUserData for SyntaxHighlighting {
         );
         methods.add_method_mut(
             "new",
-            |_, syntax_highlighting, (name, rules): (String, LuaTable)| {
-                // Create highlighter
+            |lua, syntax_highlighting, (name, rules, extensions): NewLanguageArgs| {
+                // Create highlighter and add the rules one by one
                 let mut highlighter = Highlighter::new(4);
-                // Add rules one by one
-                for rule_idx in 1..=(rules.len()?) {
-                    // Get rule
-                    let rule = rules.get::<HashMap<String, String>>(rule_idx)?;
-                    // Find type of rule and attatch it to the highlighter
-                    match rule["kind"].as_str() {
-                        "keyword" => {
-                            highlighter.keyword(rule["name"].clone(), &rule["pattern"]);
-                        }
-                        "bounded" => highlighter.bounded(
-                            rule["name"].clone(),
-                            rule["start"].clone(),
-                            rule["end"].clone(),
-                            rule["escape"] == "true",
-                        ),
-                        "bounded_interpolation" => highlighter.bounded_interp(
-                            rule["name"].clone(),
-                            rule["start"].clone(),
-                            rule["end"].clone(),
-                            rule["i_start"].clone(),
-                            rule["i_end"].clone(),
-                            rule["escape"] == "true",
-                        ),
-                        _ => unreachable!(),
-                    }
+                let rules = syntax_highlighting.read_rules(&name, &rules)?;
+                for rule in &rules {
+                    rule.apply(&mut highlighter);
+                }
+                syntax_highlighting.keywords.remove(&name);
+                syntax_highlighting.note_keywords(&name, &rules);
+                // Files with the given extensions will be highlighted as this language
+                if let Some(extensions) = extensions {
+                    register_extensions(lua, &name, extensions)?;
                 }
                 syntax_highlighting.user_rules.insert(name, highlighter);
                 Ok(())
             },
         );
-        methods.add_method_mut("set", |_, syntax_highlighting, (name, value)| {
-            syntax_highlighting
-                .theme
-                .insert(name, Color::from_lua(value));
-            Ok(())
-        });
+        methods.add_method_mut(
+            "extend",
+            |_, syntax_highlighting, (name, rules): (String, LuaTable)| {
+                let rules = syntax_highlighting.read_rules(&name, &rules)?;
+                syntax_highlighting.note_keywords(&name, &rules);
+                syntax_highlighting
+                    .user_extensions
+                    .entry(name)
+                    .or_default()
+                    .extend(rules);
+                Ok(())
+            },
+        );
+        methods.add_method_mut(
+            "set",
+            |_, syntax_highlighting, (name, value): (String, LuaValue)| {
+                let color = Color::checked(&format!("syntax:set(\"{name}\")"), value)?;
+                syntax_highlighting.theme.insert(name, color);
+                Ok(())
+            },
+        );
     }
 }
@@ -1,15 +1,17 @@
 /// Utilities for configuring and rendering parts of the interface
 use crate::cli::VERSION;
-use crate::editor::{Editor, FileContainer};
+use crate::editor::{DiskState, Editor, FileContainer};
 #[cfg(not(target_os = "windows"))]
 use crate::pty::Shell;
 use crate::Feedback;
-use kaolinite::searching::Searcher;
-use kaolinite::utils::{get_absolute_path, get_file_ext, get_file_name};
+use crossterm::cursor::SetCursorStyle;
+use kaolinite::backups::format_size;
+use kaolinite::utils::{get_absolute_path, get_file_ext, get_file_name, trim, width};
 use mlua::prelude::*;
 use std::result::Result as RResult;
 
-use super::issue_warning;
+use super::placeholders::fill_placeholders;
+use super::ColorMode;
 
 type LuaRes<T> = RResult<T, LuaError>;
 
@@ -18,6 +20,30 @@ type LuaRes<T> = RResult<T, LuaError>;
 pub struct Terminal {
     pub mouse_enabled: bool,
     pub scroll_amount: usize,
+    /// Whether clicking outside an overlay (such as a picker) closes it
+    pub click_outside_dismisses: bool,
+    /// How many milliseconds a frame may take before unfocused panes are put off (0 for no limit)
+    pub frame_budget: u64,
+    /// How much of their parent split panes grow or shrink by when resized
+    pub split_step: f64,
+    /// Whether to read the configuration file again whenever it changes
+    pub watch_config: bool,
+    /// How many colours the terminal can show (colours it can't are swapped for the closest it can)
+    pub color_mode: ColorMode,
+    /// How to get the user's attention when something goes wrong
+    pub bell: Bell,
+    /// How many milliseconds without typing before the cursor blinks (0 to leave it alone)
+    pub cursor_blink_delay: u64,
+    /// How the cursor looks while editing as normal
+    pub cursor_style: CursorShape,
+    /// How the cursor looks while typing replaces text
+    pub cursor_style_overtype: CursorShape,
+    /// How the cursor looks while there is a selection
+    pub cursor_style_selection: CursorShape,
+    /// How many of the snippets most recently copied or cut are kept to paste again
+    pub clipboard_history: usize,
+    /// Whether other shells can control this instance (with `ox --remote`)
+    pub remote_control: bool,
     #[cfg(not(target_os = "windows"))]
     pub shell: Shell,
     #[cfg(target_os = "windows")]
@@ -30,6 +56,18 @@ impl Default for Terminal {
         Self {
             mouse_enabled: true,
             scroll_amount: 1,
+            click_outside_dismisses: true,
+            frame_budget: 16,
+            split_step: 0.15,
+            watch_config: true,
+            color_mode: ColorMode::Auto,
+            bell: Bell::None,
+            cursor_blink_delay: 0,
+            cursor_style: CursorShape::Default,
+            cursor_style_overtype: CursorShape::Underline,
+            cursor_style_selection: CursorShape::Default,
+            clipboard_history: 10,
+            remote_control: false,
             #[cfg(not(target_os = "windows"))]
             shell: Shell::Bash,
             #[cfg(target_os = "windows")]
@@ -50,6 +88,72 @@ impl LuaUserData for Terminal {
             this.scroll_amount = value;
             Ok(())
         });
+        fields.add_field_method_get("click_outside_dismisses", |_, this| {
+            Ok(this.click_outside_dismisses)
+        });
+        fields.add_field_method_set("click_outside_dismisses", |_, this, value| {
+            this.click_outside_dismisses = value;
+            Ok(())
+        });
+        fields.add_field_method_get("frame_budget", |_, this| Ok(this.frame_budget));
+        fields.add_field_method_set("frame_budget", |_, this, value| {
+            this.frame_budget = value;
+            Ok(())
+        });
+        fields.add_field_method_get("split_step", |_, this| Ok(this.split_step));
+        fields.add_field_method_set("split_step", |_, this, value| {
+            this.split_step = value;
+            Ok(())
+        });
+        fields.add_field_method_get("watch_config", |_, this| Ok(this.watch_config));
+        fields.add_field_method_set("watch_config", |_, this, value| {
+            this.watch_config = value;
+            Ok(())
+        });
+        fields.add_field_method_get("cursor_blink_delay", |_, this| Ok(this.cursor_blink_delay));
+        fields.add_field_method_set("cursor_blink_delay", |_, this, value| {
+            this.cursor_blink_delay = value;
+            Ok(())
+        });
+        fields.add_field_method_get("cursor_style", |_, this| Ok(this.cursor_style));
+        fields.add_field_method_set("cursor_style", |_, this, value| {
+            this.cursor_style = value;
+            Ok(())
+        });
+        fields.add_field_method_get("cursor_style_overtype", |_, this| {
+            Ok(this.cursor_style_overtype)
+        });
+        fields.add_field_method_set("cursor_style_overtype", |_, this, value| {
+            this.cursor_style_overtype = value;
+            Ok(())
+        });
+        fields.add_field_method_get("cursor_style_selection", |_, this| {
+            Ok(this.cursor_style_selection)
+        });
+        fields.add_field_method_set("cursor_style_selection", |_, this, value| {
+            this.cursor_style_selection = value;
+            Ok(())
+        });
+        fields.add_field_method_get("clipboard_history", |_, this| Ok(this.clipboard_history));
+        fields.add_field_method_set("clipboard_history", |_, this, value| {
+            this.clipboard_history = value;
+            Ok(())
+        });
+        fields.add_field_method_get("remote_control", |_, this| Ok(this.remote_control));
+        fields.add_field_method_set("remote_control", |_, this, value| {
+            this.remote_control = value;
+            Ok(())
+        });
+        fields.add_field_method_get("bell", |_, this| Ok(this.bell));
+        fields.add_field_method_set("bell", |_, this, value| {
+            this.bell = value;
+            Ok(())
+        });
+        fields.add_field_method_get("color_mode", |_, this| Ok(this.color_mode));
+        fields.add_field_method_set("color_mode", |_, this, value| {
+            this.color_mode = value;
+            Ok(())
+        });
         #[cfg(not(target_os = "windows"))]
         fields.add_field_method_get("shell", |_, this| Ok(this.shell));
         #[cfg(not(target_os = "windows"))]
@@ -64,12 +168,120 @@ impl LuaUserData for Terminal {
     }
 }
 
+/// How the user is told that something has gone wrong (an error, or nothing left to undo)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bell {
+    None,
+    /// Ring the terminal's bell
+    Audible,
+    /// Invert the status line for a moment
+    Visual,
+}
+
+impl IntoLua for Bell {
+    fn into_lua(self, lua: &Lua) -> LuaResult<LuaValue> {
+        let name = match self {
+            Self::None => "none",
+            Self::Audible => "audible",
+            Self::Visual => "visual",
+        };
+        Ok(LuaValue::String(lua.create_string(name)?))
+    }
+}
+
+impl FromLua for Bell {
+    fn from_lua(val: LuaValue, _: &Lua) -> LuaResult<Self> {
+        let name = match &val {
+            LuaValue::String(s) => s.to_string_lossy(),
+            _ => String::new(),
+        };
+        Ok(match name.as_str() {
+            "none" => Self::None,
+            "audible" => Self::Audible,
+            "visual" => Self::Visual,
+            _ => {
+                return Err(LuaError::runtime(format!(
+                    "invalid terminal.bell: '{name}' isn't one of none, audible or visual"
+                )))
+            }
+        })
+    }
+}
+
+/// How the cursor looks in one of the modes of editing
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CursorShape {
+    /// However the terminal shows it (nothing is sent to the terminal for this)
+    #[default]
+    Default,
+    Block,
+    Bar,
+    Underline,
+    BlinkingBlock,
+    BlinkingBar,
+    BlinkingUnderline,
+}
+
+impl CursorShape {
+    /// The names of each shape, as used in the configuration file
+    const NAMES: [(Self, &'static str); 7] = [
+        (Self::Default, "default"),
+        (Self::Block, "block"),
+        (Self::Bar, "bar"),
+        (Self::Underline, "underline"),
+        (Self::BlinkingBlock, "blinking_block"),
+        (Self::BlinkingBar, "blinking_bar"),
+        (Self::BlinkingUnderline, "blinking_underline"),
+    ];
+
+    /// The sequence that gives the cursor this shape
+    pub fn style(self) -> SetCursorStyle {
+        match self {
+            Self::Default => SetCursorStyle::DefaultUserShape,
+            Self::Block => SetCursorStyle::SteadyBlock,
+            Self::Bar => SetCursorStyle::SteadyBar,
+            Self::Underline => SetCursorStyle::SteadyUnderScore,
+            Self::BlinkingBlock => SetCursorStyle::BlinkingBlock,
+            Self::BlinkingBar => SetCursorStyle::BlinkingBar,
+            Self::BlinkingUnderline => SetCursorStyle::BlinkingUnderScore,
+        }
+    }
+}
+
+impl IntoLua for CursorShape {
+    fn into_lua(self, lua: &Lua) -> LuaResult<LuaValue> {
+        let name = Self::NAMES.iter().find(|(shape, _)| *shape == self);
+        Ok(LuaValue::String(
+            lua.create_string(name.map_or("default", |(_, n)| n))?,
+        ))
+    }
+}
+
+impl FromLua for CursorShape {
+    fn from_lua(val: LuaValue, _: &Lua) -> LuaResult<Self> {
+        let name = match &val {
+            LuaValue::String(s) => s.to_string_lossy(),
+            _ => String::new(),
+        };
+        let shape = Self::NAMES.iter().find(|(_, n)| *n == name);
+        shape.map(|(shape, _)| *shape).ok_or_else(|| {
+            let names: Vec<&str> = Self::NAMES.iter().map(|(_, n)| *n).collect();
+            LuaError::runtime(format!(
+                "invalid cursor style: '{name}' isn't one of {}",
+                names.join(", ")
+            ))
+        })
+    }
+}
+
 /// For storing configuration information related to line numbers
 #[derive(Debug)]
 pub struct LineNumbers {
     pub enabled: bool,
     pub padding_left: usize,
     pub padding_right: usize,
+    /// Whether to mark lines that have changed since the last save
+    pub change_markers: bool,
 }
 
 impl Default for LineNumbers {
@@ -78,6 +290,7 @@ impl Default for LineNumbers {
             enabled: true,
             padding_left: 1,
             padding_right: 1,
+            change_markers: true,
         }
     }
 }
@@ -99,6 +312,79 @@ impl LuaUserData for LineNumbers {
             this.padding_right = value;
             Ok(())
         });
+        fields.add_field_method_get("change_markers", |_, this| Ok(this.change_markers));
+        fields.add_field_method_set("change_markers", |_, this, value| {
+            this.change_markers = value;
+            Ok(())
+        });
+    }
+}
+
+/// The actions ox provides for greeting entries
+pub const GREETING_ACTIONS: [&str; 6] = [
+    "new",
+    "open",
+    "recent",
+    "recent_files",
+    "file_tree",
+    "config",
+];
+
+/// What choosing a greeting entry does
+#[derive(Debug, Clone)]
+pub enum GreetingAction {
+    /// One of the actions ox provides (see `GREETING_ACTIONS`)
+    Named(String),
+    /// A function from the configuration file or a plug-in
+    Lua(LuaFunction),
+}
+
+impl FromLua for GreetingAction {
+    fn from_lua(value: LuaValue, lua: &Lua) -> LuaRes<Self> {
+        match value {
+            LuaValue::Function(func) => Ok(Self::Lua(func)),
+            value => {
+                let name = String::from_lua(value, lua)?;
+                if GREETING_ACTIONS.contains(&name.as_str()) {
+                    Ok(Self::Named(name))
+                } else {
+                    Err(LuaError::runtime(format!(
+                        "'{name}' isn't a greeting action, use a function or one of {}",
+                        GREETING_ACTIONS.join(", ")
+                    )))
+                }
+            }
+        }
+    }
+}
+
+impl IntoLua for GreetingAction {
+    fn into_lua(self, lua: &Lua) -> LuaRes<LuaValue> {
+        match self {
+            Self::Named(name) => name.into_lua(lua),
+            Self::Lua(func) => Ok(LuaValue::Function(func)),
+        }
+    }
+}
+
+/// An entry on the greeting, chosen by pressing its key or clicking on it
+#[derive(Debug, Clone)]
+pub struct GreetingEntry {
+    pub key: char,
+    pub label: String,
+    pub action: GreetingAction,
+}
+
+impl GreetingEntry {
+    /// Read an entry from its parts (the key has to be a single character)
+    pub fn new(key: &str, label: String, action: GreetingAction) -> LuaRes<Self> {
+        let mut chars = key.chars();
+        match (chars.next(), chars.next()) {
+            (Some(key), None) => Ok(Self { key, label, action }),
+            _ => Err(LuaError::runtime(format!(
+                "greeting entry key '{key}' should be a single character"
+            ))),
+        }
     }
 }
 
@@ -107,6 +393,8 @@ impl LuaUserData for LineNumbers {
 pub struct GreetingMessage {
     pub enabled: bool,
     pub format: String,
+    /// Entries shown below the message, in the order they are listed
+    pub entries: Vec<GreetingEntry>,
 }
 
 impl Default for GreetingMessage {
@@ -114,6 +402,7 @@ impl Default for GreetingMessage {
         Self {
             enabled: true,
             format: String::new(),
+            entries: vec![],
         }
     }
 }
@@ -137,27 +426,26 @@ impl GreetingMessage {
         };
         result = result.replace("{highlight_start}", "").to_string();
         result = result.replace("{highlight_end}", "").to_string();
-        // Find functions to call and substitute in
-        let mut searcher = Searcher::new(r"\{[A-Za-z_][A-Za-z0-9_]*\}");
-        while let Some(m) = searcher.lfind(&result) {
-            let name = m
-                .text
-                .chars()
-                .skip(1)
-                .take(m.text.chars().count().saturating_sub(2))
-                .collect::<String>();
-            if let Ok(func) = lua.globals().get::<LuaFunction>(name) {
-                if let Ok(r) = func.call::<LuaString>(()) {
-                    result = result.replace(&m.text, r.to_string_lossy().as_str());
-                } else {
-                    break;
-                }
-            } else {
-                break;
-            }
-        }
+        // Find placeholders and functions to call and substitute in
+        result = fill_placeholders(&result, lua, |name| {
+            Ok(lua
+                .globals()
+                .get::<LuaFunction>(name)
+                .and_then(|func| func.call::<LuaString>(()))
+                .ok()
+                .map(|r| r.to_string_lossy()))
+        })
+        .unwrap_or(result);
         (result, highlighted)
     }
+
+    /// Add an entry, taking the place of any entry already using its key
+    pub fn set_entry(&mut self, entry: GreetingEntry) {
+        match self.entries.iter_mut().find(|e| e.key == entry.key) {
+            Some(existing) => *existing = entry,
+            None => self.entries.push(entry),
+        }
+    }
 }
 
 impl LuaUserData for GreetingMessage {
@@ -172,68 +460,59 @@ impl LuaUserData for GreetingMessage {
             this.format = value;
             Ok(())
         });
+        fields.add_field_method_get("entries", |lua, this| {
+            let entries = lua.create_table()?;
+            for (i, entry) in this.entries.iter().enumerate() {
+                let item = lua.create_table()?;
+                item.set("key", entry.key.to_string())?;
+                item.set("label", entry.label.clone())?;
+                item.set("action", entry.action.clone())?;
+                entries.set(i + 1, item)?;
+            }
+            Ok(entries)
+        });
+        fields.add_field_method_set("entries", |_, this, value: LuaTable| {
+            let mut entries = vec![];
+            for item in value.sequence_values::<LuaTable>() {
+                let item = item?;
+                let key: String = item.get("key")?;
+                entries.push(GreetingEntry::new(
+                    &key,
+                    item.get("label")?,
+                    item.get("action")?,
+                )?);
+            }
+            this.entries = entries;
+            Ok(())
+        });
+    }
+
+    fn add_methods<M: LuaUserDataMethods<Self>>(methods: &mut M) {
+        methods.add_method_mut(
+            "entry",
+            |_, this, (key, label, action): (String, String, GreetingAction)| {
+                this.set_entry(GreetingEntry::new(&key, label, action)?);
+                Ok(())
+            },
+        );
+        methods.add_method_mut("remove_entry", |_, this, key: String| {
+            this.entries.retain(|e| e.key.to_string() != key);
+            Ok(())
+        });
     }
 }
 
-/// For storing configuration information related to the help message
-#[derive(Debug)]
+/// For storing configuration information related to the help document
+#[derive(Debug, Default)]
 pub struct HelpMessage {
+    /// Left over from when help was shown beside the document (kept so older configuration
+    /// files still load, but no longer used)
     pub enabled: bool,
+    /// The text shown in the help document when the key bindings can't be listed
+    /// (empty for the built-in cheat sheet)
     pub format: String,
 }
 
-impl Default for HelpMessage {
-    fn default() -> Self {
-        Self {
-            enabled: true,
-            format: String::new(),
-        }
-    }
-}
-
-impl HelpMessage {
-    /// Take the configuration information and render the help message
-    pub fn render(&self, lua: &Lua) -> Vec<(bool, String)> {
-        let mut message = self.format.clone();
-        //result = result.replace("{highlight_start}", &highlight).to_string();
-        //result = result.replace("{highlight_end}", &editor_fg).to_string();
-        message = message.replace("{version}", VERSION).to_string();
-        // Find functions to call and substitute in
-        let mut searcher = Searcher::new(r"\{[A-Za-z_][A-Za-z0-9_]*\}");
-        while let Some(m) = searcher.lfind(&message) {
-            let name = m
-                .text
-                .chars()
-                .skip(1)
-                .take(m.text.chars().count().saturating_sub(2))
-                .collect::<String>();
-            if let Ok(func) = lua.globals().get::<LuaFunction>(name) {
-                if let Ok(r) = func.call::<LuaString>(()) {
-                    message = message.replace(&m.text, r.to_string_lossy().as_str());
-                } else {
-                    break;
-                }
-            } else {
-                break;
-            }
-        }
-        let mut highlighted = false;
-        let mut result = vec![];
-        for line in message.split('\n') {
-            // Process highlighter lines
-            if line.trim() == "{highlight_start}" {
-                result.push((true, String::new()));
-                highlighted = true;
-            } else if line.trim() == "{highlight_end}" {
-                highlighted = false;
-            } else {
-                result.push((highlighted, line.to_string()));
-            }
-        }
-        result
-    }
-}
-
 impl LuaUserData for HelpMessage {
     fn add_fields<F: LuaUserDataFields<Self>>(fields: &mut F) {
         fields.add_field_method_get("enabled", |_, this| Ok(this.enabled));
@@ -255,6 +534,18 @@ pub struct TabLine {
     pub enabled: bool,
     pub separators: bool,
     pub format: String,
+    /// Whether to draw a button on each tab that closes its document
+    pub close_button: bool,
+    /// Shown by {read_only} on documents that can't be edited
+    pub read_only_indicator: String,
+    /// Shown by {stdin} on documents read in from the standard input
+    pub stdin_indicator: String,
+    /// Shown by {scratch} on scratch buffers (the help document included)
+    pub scratch_indicator: String,
+    /// Shown by {deleted} on documents whose file another program has removed
+    pub deleted_indicator: String,
+    /// Shown by {disk_modified} on documents whose file another program has changed
+    pub disk_modified_indicator: String,
 }
 
 impl Default for TabLine {
@@ -263,6 +554,12 @@ impl Default for TabLine {
             enabled: true,
             separators: true,
             format: "  {file_name}{modified}  ".to_string(),
+            close_button: false,
+            read_only_indicator: " [RO]".to_string(),
+            stdin_indicator: " [stdin]".to_string(),
+            scratch_indicator: " [tmp]".to_string(),
+            deleted_indicator: " [deleted]".to_string(),
+            disk_modified_indicator: " [changed]".to_string(),
         }
     }
 }
@@ -274,16 +571,30 @@ impl TabLine {
             .doc
             .file_name
             .clone()
-            .unwrap_or_else(|| "[No Name]".to_string());
+            .unwrap_or_else(|| fc.placeholder_name());
         let file_extension = get_file_ext(&path).unwrap_or_else(|| "Unknown".to_string());
-        let absolute_path = get_absolute_path(&path).unwrap_or_else(|| "[No Name]".to_string());
-        let file_name = get_file_name(&path).unwrap_or_else(|| "[No Name]".to_string());
+        let absolute_path = get_absolute_path(&path).unwrap_or_else(|| fc.placeholder_name());
+        let file_name = get_file_name(&path).unwrap_or_else(|| fc.placeholder_name());
         let icon = fc.file_type.clone().map_or("󰈙 ".to_string(), |t| t.icon);
-        let modified = if fc.doc.event_mgmt.with_disk(&fc.doc.take_snapshot()) {
-            ""
-        } else {
-            "[+]"
+        let modified = if fc.unsaved_changes() { "[+]" } else { "" };
+        let indicator = |shown: bool, glyph: &str| {
+            if shown {
+                glyph.to_string()
+            } else {
+                String::new()
+            }
         };
+        let read_only = indicator(fc.doc.info.read_only, &self.read_only_indicator);
+        let stdin = indicator(fc.stdin, &self.stdin_indicator);
+        let scratch = indicator(fc.scratch.is_some(), &self.scratch_indicator);
+        let deleted = indicator(
+            fc.on_disk.state == DiskState::Deleted,
+            &self.deleted_indicator,
+        );
+        let disk_modified = indicator(
+            fc.on_disk.state == DiskState::Modified,
+            &self.disk_modified_indicator,
+        );
         let mut result = self.format.clone();
         result = result
             .replace("{file_extension}", &file_extension)
@@ -295,30 +606,28 @@ impl TabLine {
         result = result.replace("{path}", &path).to_string();
         result = result.replace("{modified}", modified).to_string();
         result = result.replace("{icon}", &icon).to_string();
-        // Find functions to call and substitute in
-        let mut searcher = Searcher::new(r"\{[A-Za-z_][A-Za-z0-9_]*\}");
-        while let Some(m) = searcher.lfind(&result) {
-            let name = m
-                .text
-                .chars()
-                .skip(1)
-                .take(m.text.chars().count().saturating_sub(2))
-                .collect::<String>();
-            if let Ok(func) = lua.globals().get::<LuaFunction>(name) {
-                match func.call::<LuaString>(absolute_path.clone()) {
-                    Ok(r) => {
-                        result = result.replace(&m.text, r.to_string_lossy().as_str());
-                    }
-                    Err(e) => {
-                        *fb = Feedback::Error(format!("Error occured in tab line: {e:?}"));
-                        break;
-                    }
+        result = result.replace("{read_only}", &read_only).to_string();
+        result = result.replace("{stdin}", &stdin).to_string();
+        result = result.replace("{scratch}", &scratch).to_string();
+        result = result.replace("{deleted}", &deleted).to_string();
+        result = result
+            .replace("{disk_modified}", &disk_modified)
+            .to_string();
+        // Find placeholders and functions to call and substitute in
+        // (ones that nothing provides are left empty)
+        fill_placeholders(&result, lua, |name| {
+            let Ok(func) = lua.globals().get::<LuaFunction>(name) else {
+                return Ok(Some(String::new()));
+            };
+            match func.call::<LuaString>(absolute_path.clone()) {
+                Ok(r) => Ok(Some(r.to_string_lossy())),
+                Err(e) => {
+                    *fb = Feedback::Error(format!("Error occured in tab line: {e:?}"));
+                    Ok(None)
                 }
-            } else {
-                break;
             }
-        }
-        result
+        })
+        .unwrap_or(result)
     }
 }
 
@@ -339,6 +648,46 @@ impl LuaUserData for TabLine {
             this.separators = value;
             Ok(())
         });
+        fields.add_field_method_get("close_button", |_, this| Ok(this.close_button));
+        fields.add_field_method_set("close_button", |_, this, value| {
+            this.close_button = value;
+            Ok(())
+        });
+        fields.add_field_method_get("read_only_indicator", |_, this| {
+            Ok(this.read_only_indicator.clone())
+        });
+        fields.add_field_method_set("read_only_indicator", |_, this, value| {
+            this.read_only_indicator = value;
+            Ok(())
+        });
+        fields.add_field_method_get("stdin_indicator", |_, this| {
+            Ok(this.stdin_indicator.clone())
+        });
+        fields.add_field_method_set("stdin_indicator", |_, this, value| {
+            this.stdin_indicator = value;
+            Ok(())
+        });
+        fields.add_field_method_get("scratch_indicator", |_, this| {
+            Ok(this.scratch_indicator.clone())
+        });
+        fields.add_field_method_set("scratch_indicator", |_, this, value| {
+            this.scratch_indicator = value;
+            Ok(())
+        });
+        fields.add_field_method_get("deleted_indicator", |_, this| {
+            Ok(this.deleted_indicator.clone())
+        });
+        fields.add_field_method_set("deleted_indicator", |_, this, value| {
+            this.deleted_indicator = value;
+            Ok(())
+        });
+        fields.add_field_method_get("disk_modified_indicator", |_, this| {
+            Ok(this.disk_modified_indicator.clone())
+        });
+        fields.add_field_method_set("disk_modified_indicator", |_, this, value| {
+            this.disk_modified_indicator = value;
+            Ok(())
+        });
     }
 }
 
@@ -347,6 +696,10 @@ impl LuaUserData for TabLine {
 pub struct StatusLine {
     pub parts: Vec<String>,
     pub alignment: StatusAlign,
+    /// The keys listed in place of the status line while stepping through search matches
+    pub search: String,
+    /// The keys listed in place of the status line while replacing
+    pub replace: String,
 }
 
 impl Default for StatusLine {
@@ -354,6 +707,11 @@ impl Default for StatusLine {
         Self {
             parts: vec![],
             alignment: StatusAlign::Between,
+            search: "[<-]: Search previous | [->]: Search next | [Enter] Finish | [Esc] Cancel{within}"
+                .to_string(),
+            replace:
+                "[<-] Previous | [->] Next | [Enter] Replace | [Tab] Replace All | [Esc] Exit{within}"
+                    .to_string(),
         }
     }
 }
@@ -362,28 +720,57 @@ impl StatusLine {
     /// Take the configuration information and render the status line
     pub fn render(&self, ptr: &[usize], editor: &Editor, lua: &Lua, w: usize) -> LuaRes<String> {
         let mut result = vec![];
-        let fc = editor.files.get(ptr.to_vec()).unwrap();
+        let Some(fc) = editor.files.get(ptr.to_vec()) else {
+            return Ok(" ".repeat(w));
+        };
         let doc = &fc.doc;
         let path = doc
             .file_name
             .clone()
-            .unwrap_or_else(|| "[No Name]".to_string());
+            .unwrap_or_else(|| fc.placeholder_name());
         let file_extension = get_file_ext(&path).unwrap_or_else(|| "Unknown".to_string());
-        let absolute_path = get_absolute_path(&path).unwrap_or_else(|| "[No Name]".to_string());
-        let file_name = get_file_name(&path).unwrap_or_else(|| "[No Name]".to_string());
+        let absolute_path = get_absolute_path(&path).unwrap_or_else(|| fc.placeholder_name());
+        let file_name = get_file_name(&path).unwrap_or_else(|| fc.placeholder_name());
         let file_type = fc
             .file_type
             .clone()
             .map_or("Unknown".to_string(), |ft| ft.name);
         let icon = fc.file_type.clone().map_or("󰈙 ".to_string(), |ft| ft.icon);
-        let modified = if doc.event_mgmt.with_disk(&doc.take_snapshot()) {
-            ""
-        } else {
-            "[+]"
-        };
+        let modified = if fc.unsaved_changes() { "[+]" } else { "" };
         let cursor_y = (doc.loc().y + 1).to_string();
         let cursor_x = doc.char_ptr.to_string();
         let line_count = doc.len_lines().to_string();
+        let line_ending = doc.info.line_ending.to_string();
+        let encoding = doc.info.encoding.name();
+        let mode = if editor.overtype { "OVR" } else { "INS" };
+        let zen = if editor.zen.is_some() { "ZEN" } else { "" };
+        let loading = doc
+            .file_name
+            .as_ref()
+            .and_then(|f| editor.loads.progress(f))
+            .map_or(String::new(), |p| format!("loading… {p}%"));
+        let block = doc
+            .block_size()
+            .map_or(String::new(), |(rows, cols)| format!("BLOCK {rows}×{cols}"));
+        // Anything that isn't known (yet) is left empty
+        let at = (
+            ptr.to_vec(),
+            editor.files.get_atom(ptr.to_vec()).map_or(0, |(_, i)| i),
+        );
+        let branch = editor.status_info.branch(doc);
+        let git_branch = branch.map_or(String::new(), |b| b.name.clone());
+        let git_dirty = if branch.is_some_and(|b| b.dirty) {
+            "*"
+        } else {
+            ""
+        };
+        let selection = doc.selection_size();
+        let selected_chars = selection.map_or(String::new(), |(chars, _)| chars.to_string());
+        let selected_lines = selection.map_or(String::new(), |(_, lines)| lines.to_string());
+        let stats = editor.status_info.stats(&at);
+        let word_count = stats.map_or(String::new(), |stats| stats.words.to_string());
+        let char_count = stats.map_or(String::new(), |stats| stats.chars.to_string());
+        let file_size = format_size(u64::try_from(doc.file.len_bytes()).unwrap_or(u64::MAX));
 
         for part in &self.parts {
             let mut part = part.clone();
@@ -399,22 +786,32 @@ impl StatusLine {
             part = part.replace("{cursor_y}", &cursor_y).to_string();
             part = part.replace("{cursor_x}", &cursor_x).to_string();
             part = part.replace("{line_count}", &line_count).to_string();
-            // Find functions to call and substitute in
-            let mut searcher = Searcher::new(r"\{[A-Za-z_][A-Za-z0-9_]*\}");
-            while let Some(m) = searcher.lfind(&part) {
-                let name = m
-                    .text
-                    .chars()
-                    .skip(1)
-                    .take(m.text.chars().count().saturating_sub(2))
-                    .collect::<String>();
+            part = part.replace("{line_ending}", &line_ending).to_string();
+            part = part.replace("{encoding}", encoding).to_string();
+            part = part.replace("{block}", &block).to_string();
+            part = part.replace("{mode}", mode).to_string();
+            part = part.replace("{zen}", zen).to_string();
+            part = part.replace("{loading}", &loading).to_string();
+            part = part.replace("{git_branch}", &git_branch).to_string();
+            part = part.replace("{git_dirty}", git_dirty).to_string();
+            part = part
+                .replace("{selected_chars}", &selected_chars)
+                .to_string();
+            part = part
+                .replace("{selected_lines}", &selected_lines)
+                .to_string();
+            part = part.replace("{word_count}", &word_count).to_string();
+            part = part.replace("{char_count}", &char_count).to_string();
+            part = part.replace("{file_size}", &file_size).to_string();
+            // Find placeholders and functions to call and substitute in
+            part = fill_placeholders(&part, lua, |name| {
                 if let Ok(func) = lua.globals().get::<LuaFunction>(name) {
                     let r = func.call::<LuaString>(absolute_path.clone())?;
-                    part = part.replace(&m.text, r.to_string_lossy().as_str());
+                    Ok(Some(r.to_string_lossy()))
                 } else {
-                    break;
+                    Ok(None)
                 }
-            }
+            })?;
             result.push(part);
         }
         let status: Vec<&str> = result.iter().map(String::as_str).collect();
@@ -422,7 +819,12 @@ impl StatusLine {
             StatusAlign::Between => alinio::align::between(status.as_slice(), w),
             StatusAlign::Around => alinio::align::around(status.as_slice(), w),
         }
-        .unwrap_or_else(String::new))
+        .unwrap_or_else(|| {
+            // Parts that can't be spaced out are run together and cut short
+            let joined = trim(&status.join(" "), 0, w, 4);
+            let padding = w.saturating_sub(width(&joined, 4));
+            format!("{joined}{}", " ".repeat(padding))
+        }))
     }
 }
 
@@ -449,7 +851,21 @@ impl LuaUserData for StatusLine {
             Ok(alignment)
         });
         fields.add_field_method_set("alignment", |_, this, value: String| {
-            this.alignment = StatusAlign::from_string(&value);
+            this.alignment = StatusAlign::from_string(&value).ok_or_else(|| {
+                LuaError::runtime(format!(
+                    "invalid status_line.alignment: '{value}' isn't one of around or between"
+                ))
+            })?;
+            Ok(())
+        });
+        fields.add_field_method_get("search", |_, this| Ok(this.search.clone()));
+        fields.add_field_method_set("search", |_, this, value| {
+            this.search = value;
+            Ok(())
+        });
+        fields.add_field_method_get("replace", |_, this| Ok(this.replace.clone()));
+        fields.add_field_method_set("replace", |_, this, value| {
+            this.replace = value;
             Ok(())
         });
     }
@@ -463,19 +879,11 @@ pub enum StatusAlign {
 
 impl StatusAlign {
     /// Converts a status line alignment value from string representation (in lua)
-    pub fn from_string(string: &str) -> Self {
+    pub fn from_string(string: &str) -> Option<Self> {
         match string {
-            "around" => Self::Around,
-            "between" => Self::Between,
-            // If the user has provided some random value, just default to between
-            _ => {
-                issue_warning(
-                    "\
-                    Invalid status line alignment used in configuration file - \
-                    make sure value is either 'around' or 'between' (defaulting to 'between')",
-                );
-                Self::Between
-            }
+            "around" => Some(Self::Around),
+            "between" => Some(Self::Between),
+            _ => None,
         }
     }
 }
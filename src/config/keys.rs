@@ -145,3 +145,52 @@ pub fn key_to_string(modifiers: KMod, key: KCode) -> String {
     key_normalise(&mut result);
     result
 }
+
+/// Converts a key in string format back into a key (the reverse of `key_to_string`)
+pub fn string_to_key(mut key: &str) -> Option<(KMod, KCode)> {
+    let mut modifiers = KMod::NONE;
+    // Deal with modifiers (a lone underscore is a key in its own right)
+    loop {
+        if let Some(rest) = key.strip_prefix("ctrl_").filter(|r| !r.is_empty()) {
+            modifiers |= KMod::CONTROL;
+            key = rest;
+        } else if let Some(rest) = key.strip_prefix("alt_").filter(|r| !r.is_empty()) {
+            modifiers |= KMod::ALT;
+            key = rest;
+        } else if let Some(rest) = key.strip_prefix("shift_").filter(|r| !r.is_empty()) {
+            modifiers |= KMod::SHIFT;
+            key = rest;
+        } else {
+            break;
+        }
+    }
+    let code = match key {
+        "\\\\" => KCode::Char('\\'),
+        "\\\"" => KCode::Char('"'),
+        "backspace" => KCode::Backspace,
+        "enter" => KCode::Enter,
+        "left" => KCode::Left,
+        "right" => KCode::Right,
+        "up" => KCode::Up,
+        "down" => KCode::Down,
+        "home" => KCode::Home,
+        "end" => KCode::End,
+        "pageup" => KCode::PageUp,
+        "pagedown" => KCode::PageDown,
+        "tab" => KCode::Tab,
+        "backtab" => KCode::BackTab,
+        "delete" => KCode::Delete,
+        "insert" => KCode::Insert,
+        "null" => KCode::Null,
+        "esc" => KCode::Esc,
+        _ => {
+            let mut chars = key.chars();
+            match (chars.next(), chars.next()) {
+                (Some(ch), None) => KCode::Char(ch),
+                (Some('f'), Some(_)) => KCode::F(key[1..].parse().ok()?),
+                _ => return None,
+            }
+        }
+    };
+    Some((modifiers, code))
+}
@@ -1,6 +1,8 @@
 /// For general configuration
 use crate::editor::{FileType, FileTypes};
 use crate::error::{OxError, Result};
+use crate::ui::TerminalBackground;
+use kaolinite::document::DEFAULT_WORD_SEPARATORS;
 use mlua::prelude::*;
 use std::fmt::{Display, Error, Formatter};
 use std::sync::{Arc, Mutex};
@@ -12,15 +14,23 @@ mod filetree;
 mod highlighting;
 mod interface;
 mod keys;
+mod placeholders;
+mod plugins;
 mod runner;
 mod tasks;
 
 pub use assistant::Assistant;
-pub use colors::{Color, Colors};
+pub use colors::{set_color_mode, Color, ColorMode, Colors, THEMES};
 pub use filetree::FileTree;
 pub use highlighting::SyntaxHighlighting;
-pub use interface::{GreetingMessage, HelpMessage, LineNumbers, StatusLine, TabLine, Terminal};
-pub use keys::{get_listeners, key_to_string, run_key, run_key_before};
+pub use interface::{
+    Bell, CursorShape, GreetingAction, GreetingEntry, GreetingMessage, HelpMessage, LineNumbers,
+    StatusLine, TabLine, Terminal, GREETING_ACTIONS,
+};
+pub use keys::{get_listeners, key_to_string, run_key, run_key_before, string_to_key};
+pub use placeholders::{placeholder_errors, placeholder_idle_tick};
+pub use plugins::{load_plugins, load_plugins_from, plugin_failures, PluginStatus};
+pub use runner::RunCommand;
 pub use tasks::TaskManager;
 
 /// Issue a warning to the user
@@ -28,13 +38,118 @@ fn issue_warning(msg: &str) {
     eprintln!("[WARNING] {msg}");
 }
 
+/// A readable account of an error that came up while reading the configuration file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConfigError {
+    /// One line saying what went wrong and where
+    pub summary: String,
+    /// The summary followed by the stack traceback, for showing in full
+    pub report: String,
+}
+
+/// Split a line of a lua error (like `~/.oxrc:12: attempt to call a nil value`)
+/// into the chunk it came from, the line number and the rest of it
+fn split_location(line: &str) -> Option<(&str, usize, &str)> {
+    let line = line.trim();
+    let mut at = 0;
+    while let Some(found) = line[at..].find(':') {
+        let start = at + found + 1;
+        let digits = line[start..]
+            .chars()
+            .take_while(char::is_ascii_digit)
+            .count();
+        if digits > 0 && line[start + digits..].starts_with(':') {
+            let number = line[start..start + digits].parse().ok()?;
+            return Some((
+                &line[..start - 1],
+                number,
+                line[start + digits + 1..].trim(),
+            ));
+        }
+        at = start;
+    }
+    None
+}
+
+impl ConfigError {
+    /// Describe an error from running the configuration file at `file`
+    pub fn new(err: &LuaError, file: &str) -> Self {
+        // Errors raised by ox (like invalid colours) come wrapped up with where they were raised
+        let mut err = err;
+        let mut traceback = vec![];
+        loop {
+            match err {
+                LuaError::CallbackError {
+                    traceback: trace,
+                    cause,
+                } => {
+                    traceback.extend(trace.lines().map(str::to_string));
+                    err = cause.as_ref();
+                }
+                LuaError::BadArgument { cause, .. } => err = cause.as_ref(),
+                _ => break,
+            }
+        }
+        let message = match err {
+            LuaError::SyntaxError { message, .. } | LuaError::RuntimeError(message) => {
+                message.clone()
+            }
+            err => err.to_string(),
+        };
+        let mut lines = message.lines();
+        let description = lines.next().unwrap_or("No message text");
+        let mut trace: Vec<String> = lines.map(str::to_string).collect();
+        trace.append(&mut traceback);
+        // Long chunk names are cut short at the start by lua
+        let ours = |chunk: &str| file.ends_with(chunk.trim_start_matches("..."));
+        // The line is either given in the message or found in the traceback
+        let (line, description) = match split_location(description) {
+            Some((chunk, line, rest)) if ours(chunk) => (Some(line), rest),
+            _ => {
+                let line = trace
+                    .iter()
+                    .filter_map(|l| split_location(l))
+                    .find(|(chunk, _, _)| ours(chunk))
+                    .map(|(_, line, _)| line);
+                (line, description)
+            }
+        };
+        let summary = match line {
+            Some(line) => format!("Error in {file} on line {line}: {description}"),
+            None => format!("Error in {file}: {description}"),
+        };
+        let trace: Vec<String> = trace
+            .iter()
+            .map(|l| l.trim())
+            .filter(|l| !l.is_empty() && *l != "stack traceback:")
+            .map(|l| format!("    {l}"))
+            .collect();
+        let report = if trace.is_empty() {
+            summary.clone()
+        } else {
+            format!("{summary}\n\nStack traceback:\n{}", trace.join("\n"))
+        };
+        Self { summary, report }
+    }
+}
+
 /// This contains the default configuration lua file
 const DEFAULT_CONFIG: &str = include_str!("../../config/.oxrc");
+/// The name the default configuration is loaded under (which errors in it are reported with)
+const DEFAULT_CONFIG_NAME: &str = "=default configuration";
+/// Colours to use by default on terminals with a light background
+const DAYLIGHT: &str = include_str!("../../plugins/themes/daylight.lua");
 
 /// Default plug-in code to use
 const PAIRS: &str = include_str!("../../plugins/pairs.lua");
 const AUTOINDENT: &str = include_str!("../../plugins/autoindent.lua");
 const QUICKCOMMENT: &str = include_str!("../../plugins/quickcomment.lua");
+/// The plug-ins that come with ox, by their names
+const BUILTINS: [(&str, &str); 3] = [
+    ("autoindent.lua", AUTOINDENT),
+    ("quickcomment.lua", QUICKCOMMENT),
+    ("pairs.lua", PAIRS),
+];
 
 /// This contains the code for setting up plug-in infrastructure
 pub const PLUGIN_BOOTSTRAP: &str = include_str!("../plugin/bootstrap.lua");
@@ -91,6 +206,9 @@ macro_rules! config {
     ($cfg:expr, terminal) => {
         $cfg.terminal.borrow::<$crate::config::Terminal>().unwrap()
     };
+    ($cfg:expr, replace) => {
+        $cfg.replace.borrow::<$crate::config::Replace>().unwrap()
+    };
 }
 
 /// The struct that holds all the configuration information
@@ -106,24 +224,13 @@ pub struct Config {
     pub file_tree: LuaAnyUserData,
     pub terminal: LuaAnyUserData,
     pub document: LuaAnyUserData,
+    pub replace: LuaAnyUserData,
     pub task_manager: Arc<Mutex<TaskManager>>,
 }
 
 impl Config {
     /// Take a lua instance, inject all the configuration tables and return a default config struct
     pub fn new(lua: &Lua) -> Result<Self> {
-        // Set up structs to populate (the default values will be thrown away)
-        let syntax_highlighting = lua.create_userdata(SyntaxHighlighting::default())?;
-        let line_numbers = lua.create_userdata(LineNumbers::default())?;
-        let greeting_message = lua.create_userdata(GreetingMessage::default())?;
-        let help_message = lua.create_userdata(HelpMessage::default())?;
-        let colors = lua.create_userdata(Colors::default())?;
-        let status_line = lua.create_userdata(StatusLine::default())?;
-        let tab_line = lua.create_userdata(TabLine::default())?;
-        let file_tree = lua.create_userdata(FileTree::default())?;
-        let terminal = lua.create_userdata(Terminal::default())?;
-        let document = lua.create_userdata(Document::default())?;
-
         // Set up the task manager
         let task_manager = Arc::new(Mutex::new(TaskManager::default()));
         let task_manager_clone = Arc::clone(&task_manager);
@@ -132,18 +239,9 @@ impl Config {
             std::thread::sleep(std::time::Duration::from_secs(1));
         });
 
-        // Push in configuration globals
-        lua.globals().set("syntax", syntax_highlighting.clone())?;
-        lua.globals().set("line_numbers", line_numbers.clone())?;
-        lua.globals()
-            .set("greeting_message", greeting_message.clone())?;
-        lua.globals().set("help_message", help_message.clone())?;
-        lua.globals().set("status_line", status_line.clone())?;
-        lua.globals().set("tab_line", tab_line.clone())?;
-        lua.globals().set("file_tree", file_tree.clone())?;
-        lua.globals().set("colors", colors.clone())?;
-        lua.globals().set("terminal", terminal.clone())?;
-        lua.globals().set("document", document.clone())?;
+        // Set up structs to populate and push in configuration globals
+        let config = Self::with_defaults(lua, Arc::clone(&task_manager))?;
+        config.expose(lua)?;
 
         // Define task list
         let task_manager_clone = Arc::clone(&task_manager);
@@ -179,52 +277,128 @@ impl Config {
         })?;
         lua.globals().set("every", every)?;
 
+        // Provide the "ox" table for editor wide APIs
+        let ox = lua.create_table()?;
+        placeholders::register_api(lua, &ox)?;
+        plugins::register_api(lua, &ox)?;
+        let terminal_background = lua.create_function(|lua, ()| {
+            Ok(lua.app_data_ref::<TerminalBackground>().map(|bg| {
+                if bg.is_light() {
+                    "light"
+                } else {
+                    "dark"
+                }
+            }))
+        })?;
+        ox.set("terminal_background", terminal_background)?;
+        lua.globals().set("ox", ox)?;
+
+        Ok(config)
+    }
+
+    /// Set up the configuration tables, holding their default values
+    fn with_defaults(lua: &Lua, task_manager: Arc<Mutex<TaskManager>>) -> Result<Self> {
         Ok(Config {
-            syntax_highlighting,
-            line_numbers,
-            colors,
-            status_line,
-            tab_line,
-            greeting_message,
-            help_message,
-            file_tree,
-            terminal,
-            document,
+            syntax_highlighting: lua.create_userdata(SyntaxHighlighting::default())?,
+            line_numbers: lua.create_userdata(LineNumbers::default())?,
+            colors: lua.create_userdata(Colors::default())?,
+            status_line: lua.create_userdata(StatusLine::default())?,
+            tab_line: lua.create_userdata(TabLine::default())?,
+            greeting_message: lua.create_userdata(GreetingMessage::default())?,
+            help_message: lua.create_userdata(HelpMessage::default())?,
+            file_tree: lua.create_userdata(FileTree::default())?,
+            terminal: lua.create_userdata(Terminal::default())?,
+            document: lua.create_userdata(Document::default())?,
+            replace: lua.create_userdata(Replace::default())?,
             task_manager,
         })
     }
 
-    /// Actually take the configuration file, open it and interpret it
+    /// A new set of configuration tables holding their default values, for reading the
+    /// configuration file into without disturbing this one (the task manager is shared)
+    pub fn fresh(&self, lua: &Lua) -> Result<Self> {
+        Self::with_defaults(lua, Arc::clone(&self.task_manager))
+    }
+
+    /// Take any warnings about the configuration file that came up while it was read
+    pub fn take_warnings(&self) -> Option<String> {
+        self.syntax_highlighting
+            .borrow_mut::<SyntaxHighlighting>()
+            .ok()?
+            .take_warnings()
+    }
+
+    /// Make the configuration tables available to lua as globals
+    pub fn expose(&self, lua: &Lua) -> Result<()> {
+        let globals = lua.globals();
+        globals.set("syntax", self.syntax_highlighting.clone())?;
+        globals.set("line_numbers", self.line_numbers.clone())?;
+        globals.set("greeting_message", self.greeting_message.clone())?;
+        globals.set("help_message", self.help_message.clone())?;
+        globals.set("status_line", self.status_line.clone())?;
+        globals.set("tab_line", self.tab_line.clone())?;
+        globals.set("file_tree", self.file_tree.clone())?;
+        globals.set("colors", self.colors.clone())?;
+        globals.set("terminal", self.terminal.clone())?;
+        globals.set("document", self.document.clone())?;
+        globals.set("replace", self.replace.clone())?;
+        Ok(())
+    }
+
+    /// Read the default configuration
+    fn read_defaults(lua: &Lua) -> Result<()> {
+        lua.load(DEFAULT_CONFIG)
+            .set_name(DEFAULT_CONFIG_NAME)
+            .exec()?;
+        // Swap to light colours if the terminal has a light background
+        let light = lua
+            .app_data_ref::<TerminalBackground>()
+            .is_some_and(|bg| bg.is_light());
+        if light {
+            lua.load(DAYLIGHT).exec()?;
+        }
+        Ok(())
+    }
+
+    /// Actually take the configuration file, open it and interpret it.
+    /// If the configuration file fails, the default configuration is used instead
+    /// (and the error that came up is returned once it is in place)
     pub fn read(path: &str, lua: &Lua) -> Result<()> {
         // Load the default config to start with
-        lua.load(DEFAULT_CONFIG).exec()?;
+        Self::read_defaults(lua)?;
 
         // Attempt to read config file from home directory
         let user_provided = Self::get_user_provided_config(path);
         let mut user_provided_config = false;
+        let mut failure = None;
         if let Some(config) = user_provided {
             // Reset plugin status based on built-in configuration file
             lua.load("plugins = {}").exec()?;
             lua.load("builtins = {}").exec()?;
-            // Load in user-defined configuration file
-            lua.load(config).exec()?;
-            user_provided_config = true;
+            // Load in user-defined configuration file (named so errors say where they happened)
+            match lua.load(config).set_name(format!("@{path}")).exec() {
+                Ok(()) => user_provided_config = true,
+                Err(err) => {
+                    // Start again from the defaults, rather than use half a configuration
+                    lua.load("plugins = {}").exec()?;
+                    lua.load("builtins = {}").exec()?;
+                    Self::read_defaults(lua)?;
+                    failure = Some(err);
+                }
+            }
         }
 
         // Determine whether or not to load built-in plugins
-        let builtins: Vec<(&str, &str)> = vec![
-            ("autoindent.lua", AUTOINDENT),
-            ("quickcomment.lua", QUICKCOMMENT),
-            ("pairs.lua", PAIRS),
-        ];
-        for (name, code) in &builtins {
+        for (name, code) in BUILTINS {
             if Self::load_bi(name, user_provided_config, lua) {
-                lua.load(*code).exec()?;
+                lua.load(code).set_name(format!("={name}")).exec()?;
             }
         }
 
         // Return result
-        if user_provided_config {
+        if let Some(err) = failure {
+            Err(OxError::Lua(err))
+        } else if user_provided_config {
             Ok(())
         } else {
             let msg = "Not Found".to_string();
@@ -232,6 +406,21 @@ impl Config {
         }
     }
 
+    /// The code behind a chunk of Lua, found from the name it was loaded under
+    /// (the default configuration and the built-in plug-ins are found without the disk)
+    pub fn chunk_source(name: &str) -> Option<String> {
+        if name == DEFAULT_CONFIG_NAME {
+            return Some(DEFAULT_CONFIG.to_string());
+        }
+        if let Some((_, code)) = BUILTINS
+            .iter()
+            .find(|(n, _)| name.strip_prefix('=') == Some(*n))
+        {
+            return Some((*code).to_string());
+        }
+        Self::get_user_provided_config(name.strip_prefix('@')?)
+    }
+
     /// Read the user-provided config
     pub fn get_user_provided_config(path: &str) -> Option<String> {
         if let Ok(path) = shellexpand::full(&path) {
@@ -309,8 +498,48 @@ pub struct Document {
     pub tab_width: usize,
     pub indentation: Indentation,
     pub undo_period: usize,
+    pub undo_limit: usize,
+    pub persistent_undo: bool,
+    pub persistent_bookmarks: bool,
     pub wrap_cursor: bool,
+    pub smart_home: bool,
+    pub block_padding: bool,
     pub file_types: FileTypes,
+    pub backups: bool,
+    pub backup_limit: usize,
+    pub backup_size_limit: usize,
+    pub backup_interval: usize,
+    pub swap_files: bool,
+    pub swap_interval: usize,
+    pub spell_check: bool,
+    pub dictionary: String,
+    pub signs: bool,
+    pub sign_files: Vec<String>,
+    /// The widest the text gets in distraction-free mode (it is centred in what is left)
+    pub zen_width: usize,
+    /// Whether the status line stays in distraction-free mode
+    pub zen_status_line: bool,
+    /// How many characters of a word have to be typed before completions pop up (0 for never)
+    pub autocomplete_after: usize,
+    /// Whether the keywords of the language are offered as completions too
+    pub autocomplete_keywords: bool,
+    /// Whether pasting the paths of existing files (like dropping them on the terminal)
+    /// offers to open them rather than typing them in
+    pub open_pasted_paths: bool,
+    /// Whether files are reopened with the cursor (and view) where they were left
+    pub remember_position: bool,
+    /// How many columns are kept between the cursor and the sides of the view when scrolling
+    pub horizontal_margin: usize,
+    /// Whether lines running off the sides of the view are marked at the edge they run past
+    pub overflow_markers: bool,
+    /// The characters (besides whitespace) that end a word when moving, deleting or selecting
+    pub word_separators: String,
+    /// Whether documents with unsaved changes are saved when the terminal loses focus
+    pub save_on_focus_lost: bool,
+    /// Whether colour swatches are drawn after hex codes and `rgb(...)` colours
+    pub color_preview: bool,
+    /// The file types colour swatches are drawn in (every type if this is empty)
+    pub color_preview_types: Vec<String>,
 }
 
 impl Default for Document {
@@ -319,8 +548,39 @@ impl Default for Document {
             tab_width: 4,
             indentation: Indentation::Tabs,
             undo_period: 10,
+            undo_limit: 1000,
+            persistent_undo: false,
+            persistent_bookmarks: false,
             wrap_cursor: true,
+            smart_home: true,
+            block_padding: false,
             file_types: FileTypes::default(),
+            backups: false,
+            backup_limit: 20,
+            backup_size_limit: 10 * 1024 * 1024,
+            backup_interval: 5,
+            swap_files: true,
+            swap_interval: 4,
+            spell_check: false,
+            dictionary: "en_US".to_string(),
+            signs: true,
+            sign_files: vec![],
+            zen_width: 80,
+            zen_status_line: false,
+            autocomplete_after: 0,
+            autocomplete_keywords: true,
+            open_pasted_paths: true,
+            remember_position: true,
+            horizontal_margin: 5,
+            overflow_markers: true,
+            word_separators: DEFAULT_WORD_SEPARATORS.to_string(),
+            save_on_focus_lost: false,
+            color_preview: true,
+            color_preview_types: [
+                "CSS", "SCSS", "Sass", "HTML", "Vue", "XML", "Lua", "TOML", "JSON",
+            ]
+            .map(str::to_string)
+            .to_vec(),
         }
     }
 }
@@ -339,16 +599,226 @@ impl LuaUserData for Document {
             this.indentation = value.into();
             Ok(())
         });
+        fields.add_field_method_get("indent_with_spaces", |_, document| {
+            Ok(document.indentation == Indentation::Spaces)
+        });
+        fields.add_field_method_set("indent_with_spaces", |_, this, value: bool| {
+            this.indentation = if value {
+                Indentation::Spaces
+            } else {
+                Indentation::Tabs
+            };
+            Ok(())
+        });
         fields.add_field_method_get("undo_period", |_, document| Ok(document.undo_period));
         fields.add_field_method_set("undo_period", |_, this, value| {
             this.undo_period = value;
             Ok(())
         });
+        fields.add_field_method_get("undo_limit", |_, document| Ok(document.undo_limit));
+        fields.add_field_method_set("undo_limit", |_, this, value| {
+            this.undo_limit = value;
+            Ok(())
+        });
+        fields.add_field_method_get(
+            "persistent_undo",
+            |_, document| Ok(document.persistent_undo),
+        );
+        fields.add_field_method_set("persistent_undo", |_, this, value| {
+            this.persistent_undo = value;
+            Ok(())
+        });
+        fields.add_field_method_get("persistent_bookmarks", |_, document| {
+            Ok(document.persistent_bookmarks)
+        });
+        fields.add_field_method_set("persistent_bookmarks", |_, this, value| {
+            this.persistent_bookmarks = value;
+            Ok(())
+        });
         fields.add_field_method_get("wrap_cursor", |_, document| Ok(document.wrap_cursor));
         fields.add_field_method_set("wrap_cursor", |_, this, value| {
             this.wrap_cursor = value;
             Ok(())
         });
+        fields.add_field_method_get("smart_home", |_, document| Ok(document.smart_home));
+        fields.add_field_method_set("smart_home", |_, this, value| {
+            this.smart_home = value;
+            Ok(())
+        });
+        fields.add_field_method_get("block_padding", |_, document| Ok(document.block_padding));
+        fields.add_field_method_set("block_padding", |_, this, value| {
+            this.block_padding = value;
+            Ok(())
+        });
+        fields.add_field_method_get("backups", |_, document| Ok(document.backups));
+        fields.add_field_method_set("backups", |_, this, value| {
+            this.backups = value;
+            Ok(())
+        });
+        fields.add_field_method_get("backup_limit", |_, document| Ok(document.backup_limit));
+        fields.add_field_method_set("backup_limit", |_, this, value| {
+            this.backup_limit = value;
+            Ok(())
+        });
+        fields.add_field_method_get("backup_size_limit", |_, document| {
+            Ok(document.backup_size_limit)
+        });
+        fields.add_field_method_set("backup_size_limit", |_, this, value| {
+            this.backup_size_limit = value;
+            Ok(())
+        });
+        fields.add_field_method_get(
+            "backup_interval",
+            |_, document| Ok(document.backup_interval),
+        );
+        fields.add_field_method_set("backup_interval", |_, this, value| {
+            this.backup_interval = value;
+            Ok(())
+        });
+        fields.add_field_method_get("swap_files", |_, document| Ok(document.swap_files));
+        fields.add_field_method_set("swap_files", |_, this, value| {
+            this.swap_files = value;
+            Ok(())
+        });
+        fields.add_field_method_get("swap_interval", |_, document| Ok(document.swap_interval));
+        fields.add_field_method_set("swap_interval", |_, this, value| {
+            this.swap_interval = value;
+            Ok(())
+        });
+        fields.add_field_method_get("spell_check", |_, document| Ok(document.spell_check));
+        fields.add_field_method_set("spell_check", |_, this, value| {
+            this.spell_check = value;
+            Ok(())
+        });
+        fields.add_field_method_get("dictionary", |_, document| Ok(document.dictionary.clone()));
+        fields.add_field_method_set("dictionary", |_, this, value| {
+            this.dictionary = value;
+            Ok(())
+        });
+        fields.add_field_method_get("signs", |_, document| Ok(document.signs));
+        fields.add_field_method_set("signs", |_, this, value| {
+            this.signs = value;
+            Ok(())
+        });
+        fields.add_field_method_get("sign_files", |_, document| Ok(document.sign_files.clone()));
+        fields.add_field_method_set("sign_files", |_, this, value| {
+            this.sign_files = value;
+            Ok(())
+        });
+        fields.add_field_method_get("zen_width", |_, document| Ok(document.zen_width));
+        fields.add_field_method_set("zen_width", |_, this, value| {
+            this.zen_width = value;
+            Ok(())
+        });
+        fields.add_field_method_get(
+            "zen_status_line",
+            |_, document| Ok(document.zen_status_line),
+        );
+        fields.add_field_method_set("zen_status_line", |_, this, value| {
+            this.zen_status_line = value;
+            Ok(())
+        });
+        fields.add_field_method_get("autocomplete_after", |_, document| {
+            Ok(document.autocomplete_after)
+        });
+        fields.add_field_method_set("autocomplete_after", |_, this, value| {
+            this.autocomplete_after = value;
+            Ok(())
+        });
+        fields.add_field_method_get("autocomplete_keywords", |_, document| {
+            Ok(document.autocomplete_keywords)
+        });
+        fields.add_field_method_set("autocomplete_keywords", |_, this, value| {
+            this.autocomplete_keywords = value;
+            Ok(())
+        });
+        fields.add_field_method_get("open_pasted_paths", |_, document| {
+            Ok(document.open_pasted_paths)
+        });
+        fields.add_field_method_set("open_pasted_paths", |_, this, value| {
+            this.open_pasted_paths = value;
+            Ok(())
+        });
+        fields.add_field_method_get("save_on_focus_lost", |_, document| {
+            Ok(document.save_on_focus_lost)
+        });
+        fields.add_field_method_set("save_on_focus_lost", |_, this, value| {
+            this.save_on_focus_lost = value;
+            Ok(())
+        });
+        fields.add_field_method_get("color_preview", |_, document| Ok(document.color_preview));
+        fields.add_field_method_set("color_preview", |_, this, value| {
+            this.color_preview = value;
+            Ok(())
+        });
+        fields.add_field_method_get("color_preview_types", |_, document| {
+            Ok(document.color_preview_types.clone())
+        });
+        fields.add_field_method_set("color_preview_types", |_, this, value| {
+            this.color_preview_types = value;
+            Ok(())
+        });
+        fields.add_field_method_get("remember_position", |_, document| {
+            Ok(document.remember_position)
+        });
+        fields.add_field_method_set("remember_position", |_, this, value| {
+            this.remember_position = value;
+            Ok(())
+        });
+        fields.add_field_method_get("horizontal_margin", |_, document| {
+            Ok(document.horizontal_margin)
+        });
+        fields.add_field_method_set("horizontal_margin", |_, this, value| {
+            this.horizontal_margin = value;
+            Ok(())
+        });
+        fields.add_field_method_get("overflow_markers", |_, document| {
+            Ok(document.overflow_markers)
+        });
+        fields.add_field_method_set("overflow_markers", |_, this, value| {
+            this.overflow_markers = value;
+            Ok(())
+        });
+        fields.add_field_method_get("word_separators", |_, document| {
+            Ok(document.word_separators.clone())
+        });
+        fields.add_field_method_set("word_separators", |_, this, value| {
+            this.word_separators = value;
+            Ok(())
+        });
+    }
+}
+
+/// For storing configuration for the replace menu
+#[derive(Debug)]
+pub struct Replace {
+    /// Whether to ask before replacing all occurances
+    pub confirm_all: bool,
+    /// Only ask when at least this many occurances would be replaced
+    pub confirm_threshold: usize,
+}
+
+impl Default for Replace {
+    fn default() -> Self {
+        Self {
+            confirm_all: true,
+            confirm_threshold: 10,
+        }
+    }
+}
+
+impl LuaUserData for Replace {
+    fn add_fields<F: LuaUserDataFields<Self>>(fields: &mut F) {
+        fields.add_field_method_get("confirm_all", |_, this| Ok(this.confirm_all));
+        fields.add_field_method_set("confirm_all", |_, this, value| {
+            this.confirm_all = value;
+            Ok(())
+        });
+        fields.add_field_method_get("confirm_threshold", |_, this| Ok(this.confirm_threshold));
+        fields.add_field_method_set("confirm_threshold", |_, this, value| {
+            this.confirm_threshold = value;
+            Ok(())
+        });
     }
 }
 
@@ -378,6 +848,12 @@ impl FromLua for FileTypes {
                     .filter_map(|val| if let Ok((_, v)) = val { Some(v) } else { None })
                     .collect::<Vec<String>>();
                 let color = info.get::<String>("color")?;
+                let rulers = info
+                    .get::<LuaTable>("rulers")
+                    .unwrap_or(lua.create_table()?)
+                    .pairs::<usize, usize>()
+                    .filter_map(|val| if let Ok((_, v)) = val { Some(v) } else { None })
+                    .collect::<Vec<usize>>();
                 result.push(FileType {
                     name,
                     icon,
@@ -385,6 +861,7 @@ impl FromLua for FileTypes {
                     extensions,
                     modelines,
                     color,
+                    rulers,
                 });
             }
         }
@@ -0,0 +1,192 @@
+/// For custom placeholders that can be used in the status line, tab line and greeting message
+use kaolinite::searching::Searcher;
+use mlua::prelude::*;
+use std::collections::{HashMap, HashSet};
+use std::time::{Duration, Instant};
+
+/// Placeholders that ox provides itself, these can't be registered
+pub const BUILT_IN_PLACEHOLDERS: [&str; 21] = [
+    "file_name",
+    "file_extension",
+    "icon",
+    "path",
+    "absolute_path",
+    "modified",
+    "read_only",
+    "stdin",
+    "scratch",
+    "deleted",
+    "disk_modified",
+    "file_type",
+    "cursor_y",
+    "cursor_x",
+    "line_count",
+    "line_ending",
+    "encoding",
+    "block",
+    "version",
+    "highlight_start",
+    "highlight_end",
+];
+
+/// How long a placeholder's value is kept while the editor is idle
+const IDLE_TICK: Duration = Duration::from_secs(1);
+
+/// Keeps track of registered placeholder providers and their latest values
+#[derive(Debug)]
+pub struct Placeholders {
+    providers: HashMap<String, LuaFunction>,
+    cache: HashMap<String, String>,
+    /// Placeholders whose providers failed, these render as nothing for the rest of the session
+    disabled: HashSet<String>,
+    errors: Vec<String>,
+    last_tick: Instant,
+}
+
+impl Default for Placeholders {
+    fn default() -> Self {
+        Self {
+            providers: HashMap::default(),
+            cache: HashMap::default(),
+            disabled: HashSet::default(),
+            errors: vec![],
+            last_tick: Instant::now(),
+        }
+    }
+}
+
+impl Placeholders {
+    /// Register a provider for a placeholder
+    pub fn register(&mut self, name: &str, provider: LuaFunction) -> Result<(), String> {
+        if BUILT_IN_PLACEHOLDERS.contains(&name) {
+            return Err(format!("'{name}' is a built-in placeholder"));
+        }
+        let mut chars = name.chars();
+        let valid_start = chars
+            .next()
+            .is_some_and(|c| c.is_ascii_alphabetic() || c == '_');
+        if !valid_start || !chars.all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            return Err(format!("'{name}' is not a valid placeholder name"));
+        }
+        self.providers.insert(name.to_string(), provider);
+        self.cache.remove(name);
+        self.disabled.remove(name);
+        Ok(())
+    }
+
+    /// Forget the value of a placeholder so its provider is called on the next render
+    pub fn invalidate(&mut self, name: &str) {
+        self.cache.remove(name);
+    }
+
+    /// Forget all placeholder values if an idle tick has passed, returns true if any were forgotten
+    pub fn idle_tick(&mut self) -> bool {
+        if self.last_tick.elapsed() < IDLE_TICK {
+            return false;
+        }
+        self.last_tick = Instant::now();
+        let had_values = !self.cache.is_empty();
+        self.cache.clear();
+        had_values
+    }
+
+    /// Take any errors that providers have produced
+    pub fn take_errors(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.errors)
+    }
+}
+
+/// Work out the value of a registered placeholder, or None if it isn't registered
+fn resolve(lua: &Lua, name: &str) -> Option<String> {
+    let provider = {
+        let placeholders = lua.app_data_ref::<Placeholders>()?;
+        if placeholders.disabled.contains(name) {
+            return Some(String::new());
+        }
+        if let Some(value) = placeholders.cache.get(name) {
+            return Some(value.clone());
+        }
+        placeholders.providers.get(name)?.clone()
+    };
+    // Providers may use the placeholder API themselves, so don't hold on to it while calling
+    let result = provider.call::<LuaString>(());
+    let mut placeholders = lua.app_data_mut::<Placeholders>()?;
+    match result {
+        Ok(value) => {
+            let value = value.to_string_lossy();
+            placeholders.cache.insert(name.to_string(), value.clone());
+            Some(value)
+        }
+        Err(err) => {
+            placeholders.disabled.insert(name.to_string());
+            placeholders
+                .errors
+                .push(format!("Placeholder '{name}' has been disabled: {err}"));
+            Some(String::new())
+        }
+    }
+}
+
+/// Substitute placeholders into a template (after built-ins have been substituted).
+/// Registered placeholders are tried first, then the fallback, otherwise the text is left as is
+pub fn fill_placeholders(
+    template: &str,
+    lua: &Lua,
+    mut fallback: impl FnMut(&str) -> LuaResult<Option<String>>,
+) -> LuaResult<String> {
+    let mut result = String::new();
+    let mut rest = template;
+    let mut searcher = Searcher::new(r"\{[A-Za-z_][A-Za-z0-9_]*\}");
+    while let Some(m) = searcher.lfind(rest) {
+        let start = Searcher::char_to_raw(m.loc.x, rest);
+        let end = start + m.text.len();
+        let name = &m.text[1..m.text.len() - 1];
+        result += &rest[..start];
+        if let Some(value) = resolve(lua, name) {
+            result += &value;
+        } else if let Some(value) = fallback(name)? {
+            result += &value;
+        } else {
+            result += &m.text;
+        }
+        rest = &rest[end..];
+    }
+    result += rest;
+    Ok(result)
+}
+
+/// Set up the placeholder API for lua
+pub fn register_api(lua: &Lua, ox: &LuaTable) -> LuaResult<()> {
+    lua.set_app_data(Placeholders::default());
+    let register = lua.create_function(|lua, (name, provider): (String, LuaFunction)| {
+        let Some(mut placeholders) = lua.app_data_mut::<Placeholders>() else {
+            return Ok(());
+        };
+        placeholders
+            .register(&name, provider)
+            .map_err(LuaError::RuntimeError)
+    })?;
+    ox.set("register_placeholder", register)?;
+    let invalidate = lua.create_function(|lua, name: String| {
+        if let Some(mut placeholders) = lua.app_data_mut::<Placeholders>() {
+            placeholders.invalidate(&name);
+        }
+        Ok(())
+    })?;
+    ox.set("invalidate_placeholder", invalidate)?;
+    Ok(())
+}
+
+/// Forget placeholder values once the editor has been idle for a tick, returns true if a
+/// rerender is needed to show the new values
+pub fn placeholder_idle_tick(lua: &Lua) -> bool {
+    lua.app_data_mut::<Placeholders>()
+        .is_some_and(|mut placeholders| placeholders.idle_tick())
+}
+
+/// Take any errors that placeholder providers have produced
+pub fn placeholder_errors(lua: &Lua) -> Vec<String> {
+    lua.app_data_mut::<Placeholders>()
+        .map(|mut placeholders| placeholders.take_errors())
+        .unwrap_or_default()
+}
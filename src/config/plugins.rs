@@ -0,0 +1,563 @@
+/// For plug-ins that are installed into their own directory, described by a manifest
+use crate::cli::VERSION;
+use crate::editor::data_dir;
+use mlua::prelude::*;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// The name of the file that describes a plug-in
+pub const MANIFEST_FILE: &str = "plugin.lua";
+/// The newest version of the manifest format that ox understands
+pub const MANIFEST_FORMAT: u64 = 1;
+
+/// Describes a plug-in, as read from its manifest
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Manifest {
+    /// The name of the plug-in (other plug-ins depend on it using this name)
+    pub name: String,
+    /// The version of the plug-in
+    pub version: String,
+    /// The oldest version of ox that the plug-in works with
+    pub min_ox: Option<String>,
+    /// The file to run (relative to the plug-in's directory)
+    pub entry: String,
+    /// The names of plug-ins that need to be loaded before this one
+    pub dependencies: Vec<String>,
+}
+
+/// How many Lua instructions a manifest can run before it is given up on
+const MANIFEST_BUDGET: u32 = 100_000;
+
+/// The first line of a Lua error (the rest is a traceback)
+fn first_line(err: &LuaError) -> String {
+    err.to_string()
+        .lines()
+        .next()
+        .unwrap_or_default()
+        .to_string()
+}
+
+/// Read a manifest. Manifests are Lua, run without access to any libraries or to the editor,
+/// and either set their fields as globals or return them in a table, for example
+/// name = "example", version = "1.0.0", ox = "0.7.0", entry = "init.lua",
+/// dependencies = { "other" } and format = 1 (fields that aren't known are skipped)
+pub fn parse_manifest(source: &str) -> Result<Manifest, String> {
+    let lua = Lua::new_with(LuaStdLib::NONE, LuaOptions::default()).map_err(|e| first_line(&e))?;
+    // Manifests that loop forever would stop ox from starting
+    lua.set_hook(
+        LuaHookTriggers::new().every_nth_instruction(MANIFEST_BUDGET),
+        |_, _| Err(LuaError::runtime("the manifest took too long to run")),
+    );
+    let env = lua.create_table().map_err(|e| first_line(&e))?;
+    let result: LuaValue = lua
+        .load(source)
+        .set_name(format!("={MANIFEST_FILE}"))
+        .set_environment(env.clone())
+        .call(())
+        .map_err(|e| first_line(&e))?;
+    let fields = match result {
+        LuaValue::Table(fields) => fields,
+        LuaValue::Nil => env,
+        _ => return Err("the manifest should return a table of fields".to_string()),
+    };
+    let field = |key: &str| fields.raw_get::<LuaValue>(key).unwrap_or(LuaValue::Nil);
+    // Refuse manifests written for a newer version of ox
+    match field("format") {
+        LuaValue::Nil => (),
+        LuaValue::Integer(format) if format >= 0 => {
+            if format.unsigned_abs() > MANIFEST_FORMAT {
+                return Err(format!(
+                    "manifest format {format} needs a newer version of ox"
+                ));
+            }
+        }
+        _ => return Err("'format' should be a number".to_string()),
+    }
+    let text = |key: &str| match field(key) {
+        LuaValue::String(text) => Ok(Some(text.to_string_lossy())),
+        LuaValue::Nil => Ok(None),
+        _ => Err(format!("'{key}' should be a string")),
+    };
+    let name = text("name")?.ok_or("the manifest has no name")?;
+    let version = text("version")?.ok_or("the manifest has no version")?;
+    let min_ox = text("ox")?;
+    let entry = text("entry")?.unwrap_or_else(|| "init.lua".to_string());
+    let dependencies = match field("dependencies") {
+        LuaValue::Table(items) => items
+            .sequence_values::<LuaValue>()
+            .map(|item| match item {
+                Ok(LuaValue::String(text)) => Ok(text.to_string_lossy()),
+                _ => Err("'dependencies' should be a list of strings".to_string()),
+            })
+            .collect::<Result<Vec<String>, String>>()?,
+        LuaValue::Nil => vec![],
+        _ => return Err("'dependencies' should be a list of strings".to_string()),
+    };
+    // Check the values make sense
+    if name.is_empty() || name.contains(['/', '\\']) || name.chars().any(char::is_whitespace) {
+        return Err(format!("'{name}' isn't a valid plug-in name"));
+    }
+    if version_parts(&version).is_none() {
+        return Err(format!("'{version}' isn't a valid version"));
+    }
+    if let Some(min_ox) = &min_ox {
+        if version_parts(min_ox).is_none() {
+            return Err(format!("'{min_ox}' isn't a valid version of ox"));
+        }
+    }
+    let entry_path = Path::new(&entry);
+    if entry_path.is_absolute() || entry_path.components().any(|c| c.as_os_str() == "..") {
+        return Err(format!(
+            "the entry point '{entry}' is outside of the plug-in"
+        ));
+    }
+    Ok(Manifest {
+        name,
+        version,
+        min_ox,
+        entry,
+        dependencies,
+    })
+}
+
+/// Split a version (e.g. 1.2.3, v0.7 or 2.0.0-beta) into its numbers
+fn version_parts(version: &str) -> Option<Vec<u64>> {
+    let version = version.trim().trim_start_matches('v');
+    let version = version.split(['-', '+']).next()?;
+    version.split('.').map(|part| part.parse().ok()).collect()
+}
+
+/// Compare two versions, missing numbers count as zero (so 1.2 is the same as 1.2.0).
+/// Returns None if either version isn't valid
+pub fn compare_versions(a: &str, b: &str) -> Option<Ordering> {
+    let (a, b) = (version_parts(a)?, version_parts(b)?);
+    let len = a.len().max(b.len());
+    let part = |v: &[u64], i: usize| v.get(i).copied().unwrap_or(0);
+    Some(
+        (0..len)
+            .map(|i| part(&a, i).cmp(&part(&b, i)))
+            .find(|o| o.is_ne())
+            .unwrap_or(Ordering::Equal),
+    )
+}
+
+/// Progress of a plug-in while working out the load order
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Visit {
+    New,
+    InProgress,
+    Done,
+}
+
+/// Work out the order to load plug-ins in, so each comes after the plug-ins it depends on.
+/// Returns the indices of the plug-ins that can be loaded (in order) along with the reasons
+/// the rest can't (missing dependencies, dependency cycles or names that are taken)
+pub fn load_order(manifests: &[Manifest]) -> (Vec<usize>, Vec<(usize, String)>) {
+    let mut by_name: HashMap<&str, usize> = HashMap::new();
+    let mut failed: HashMap<usize, String> = HashMap::new();
+    for (idx, manifest) in manifests.iter().enumerate() {
+        if by_name.contains_key(manifest.name.as_str()) {
+            let msg = format!("another plug-in is already called '{}'", manifest.name);
+            failed.insert(idx, msg);
+        } else {
+            by_name.insert(&manifest.name, idx);
+        }
+    }
+    let mut state = vec![Visit::New; manifests.len()];
+    let mut order = vec![];
+    let mut stack = vec![];
+    for idx in 0..manifests.len() {
+        visit(
+            idx,
+            manifests,
+            &by_name,
+            &mut state,
+            &mut stack,
+            &mut failed,
+            &mut order,
+        );
+    }
+    let mut failed: Vec<(usize, String)> = failed.into_iter().collect();
+    failed.sort();
+    (order, failed)
+}
+
+/// Visit a plug-in after visiting its dependencies, returns true if it can be loaded
+fn visit(
+    idx: usize,
+    manifests: &[Manifest],
+    by_name: &HashMap<&str, usize>,
+    state: &mut [Visit],
+    stack: &mut Vec<usize>,
+    failed: &mut HashMap<usize, String>,
+    order: &mut Vec<usize>,
+) -> bool {
+    match state[idx] {
+        Visit::Done => return !failed.contains_key(&idx),
+        Visit::InProgress => {
+            // This plug-in depends on itself somewhere along the line
+            let start = stack.iter().position(|i| *i == idx).unwrap_or(0);
+            let cycle: Vec<&str> = stack[start..]
+                .iter()
+                .chain([&idx])
+                .map(|i| manifests[*i].name.as_str())
+                .collect();
+            let msg = format!("dependency cycle {}", cycle.join(" -> "));
+            for i in &stack[start..] {
+                failed.insert(*i, msg.clone());
+            }
+            return false;
+        }
+        Visit::New => (),
+    }
+    state[idx] = Visit::InProgress;
+    stack.push(idx);
+    for dep in &manifests[idx].dependencies {
+        let reason = match by_name.get(dep.as_str()) {
+            None => Some(format!("depends on '{dep}', which isn't installed")),
+            Some(d) if !visit(*d, manifests, by_name, state, stack, failed, order) => {
+                Some(format!("depends on '{dep}', which couldn't be loaded"))
+            }
+            Some(_) => None,
+        };
+        if let Some(reason) = reason {
+            failed.entry(idx).or_insert(reason);
+        }
+    }
+    stack.pop();
+    state[idx] = Visit::Done;
+    let ok = !failed.contains_key(&idx);
+    if ok {
+        order.push(idx);
+    }
+    ok
+}
+
+/// How loading a plug-in went
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PluginStatus {
+    Loaded,
+    Failed(String),
+}
+
+/// A plug-in that was found in the plug-in directory
+#[derive(Debug, Clone)]
+pub struct Plugin {
+    pub manifest: Manifest,
+    /// The directory the plug-in lives in
+    pub dir: PathBuf,
+    pub status: PluginStatus,
+}
+
+/// Work out where plug-ins with manifests are installed
+pub fn plugins_dir() -> Option<PathBuf> {
+    Some(data_dir()?.join("plugins"))
+}
+
+/// Find every plug-in in a directory (in order of their directory names)
+fn discover(dir: &Path) -> Vec<Plugin> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return vec![];
+    };
+    let mut dirs: Vec<PathBuf> = entries
+        .filter_map(std::result::Result::ok)
+        .map(|e| e.path())
+        .filter(|p| p.join(MANIFEST_FILE).is_file())
+        .collect();
+    dirs.sort();
+    dirs.into_iter()
+        .map(|dir| {
+            let manifest = std::fs::read_to_string(dir.join(MANIFEST_FILE))
+                .map_err(|err| err.to_string())
+                .and_then(|source| parse_manifest(&source));
+            match manifest {
+                Ok(manifest) => Plugin {
+                    manifest,
+                    dir,
+                    status: PluginStatus::Loaded,
+                },
+                // Broken manifests are still listed, under the name of their directory
+                Err(err) => Plugin {
+                    manifest: Manifest {
+                        name: dir
+                            .file_name()
+                            .unwrap_or_default()
+                            .to_string_lossy()
+                            .to_string(),
+                        ..Manifest::default()
+                    },
+                    dir,
+                    status: PluginStatus::Failed(format!("invalid manifest: {err}")),
+                },
+            }
+        })
+        .collect()
+}
+
+/// Run a plug-in, taking on any key bindings it sets up
+fn run_plugin(lua: &Lua, plugin: &Plugin) -> Result<(), String> {
+    let path = plugin.dir.join(&plugin.manifest.entry);
+    let code = std::fs::read_to_string(&path)
+        .map_err(|err| format!("couldn't read {}: {err}", path.display()))?;
+    lua.load(code)
+        .set_name(format!("@{}", path.display()))
+        .exec()
+        .and_then(|()| {
+            let merge: LuaFunction = lua.globals().get("merge_plugin_mapping")?;
+            merge.call::<()>(plugin.manifest.name.as_str())
+        })
+        .map_err(|err| {
+            // Drop any bindings a failed plug-in set up before it stopped
+            if let Ok(table) = lua.create_table() {
+                let _ = lua.globals().set("event_mapping", table);
+            }
+            first_line(&err)
+        })
+}
+
+/// Find, order and run every plug-in in the plug-in directory, returning how each went
+pub fn load_plugins(lua: &Lua) -> Vec<Plugin> {
+    plugins_dir().map_or_else(Vec::new, |dir| load_plugins_from(lua, &dir))
+}
+
+/// Find, order and run every plug-in in a directory, returning how each went
+pub fn load_plugins_from(lua: &Lua, dir: &Path) -> Vec<Plugin> {
+    let mut plugins = discover(dir);
+    // Only plug-ins with working manifests that suit this version of ox are considered
+    for plugin in &mut plugins {
+        if let Some(min_ox) = &plugin.manifest.min_ox {
+            if compare_versions(VERSION, min_ox) == Some(Ordering::Less) {
+                let msg = format!("needs ox {min_ox} or newer");
+                plugin.status = PluginStatus::Failed(msg);
+            }
+        }
+    }
+    let candidates: Vec<usize> = (0..plugins.len())
+        .filter(|i| plugins[*i].status == PluginStatus::Loaded)
+        .collect();
+    let manifests: Vec<Manifest> = candidates
+        .iter()
+        .map(|i| plugins[*i].manifest.clone())
+        .collect();
+    let (order, failed) = load_order(&manifests);
+    for (idx, reason) in failed {
+        plugins[candidates[idx]].status = PluginStatus::Failed(reason);
+    }
+    for idx in order.into_iter().map(|i| candidates[i]) {
+        // A dependency may have failed while it was running
+        let broken = plugins[idx]
+            .manifest
+            .dependencies
+            .iter()
+            .find(|dep| {
+                plugins
+                    .iter()
+                    .any(|p| &p.manifest.name == *dep && p.status != PluginStatus::Loaded)
+            })
+            .cloned();
+        plugins[idx].status = match broken {
+            Some(dep) => PluginStatus::Failed(format!("depends on '{dep}', which failed")),
+            None => match run_plugin(lua, &plugins[idx]) {
+                Ok(()) => PluginStatus::Loaded,
+                Err(err) => PluginStatus::Failed(err),
+            },
+        };
+    }
+    plugins
+}
+
+/// Describe the plug-ins that were disabled, for reporting to the user
+pub fn plugin_failures(plugins: &[Plugin]) -> Option<String> {
+    let mut failures = plugins.iter().filter_map(|p| match &p.status {
+        PluginStatus::Failed(reason) => Some((&p.manifest.name, reason)),
+        PluginStatus::Loaded => None,
+    });
+    let (name, reason) = failures.next()?;
+    let more = failures.count();
+    let msg = format!("Plug-in '{name}' was disabled: {reason}");
+    Some(if more == 0 {
+        msg
+    } else {
+        format!("{msg} (and {more} more)")
+    })
+}
+
+/// Provide the plug-in APIs to lua
+pub fn register_api(lua: &Lua, ox: &LuaTable) -> LuaResult<()> {
+    // List the plug-ins that were found, along with how loading them went
+    let list = lua.create_function(|lua, ()| {
+        let result = lua.create_table()?;
+        let Some(plugins) = lua.app_data_ref::<Vec<Plugin>>() else {
+            return Ok(result);
+        };
+        for plugin in plugins.iter() {
+            let entry = lua.create_table()?;
+            entry.set("name", plugin.manifest.name.as_str())?;
+            entry.set("version", plugin.manifest.version.as_str())?;
+            entry.set("loaded", plugin.status == PluginStatus::Loaded)?;
+            if let PluginStatus::Failed(reason) = &plugin.status {
+                entry.set("error", reason.as_str())?;
+            }
+            result.push(entry)?;
+        }
+        Ok(result)
+    })?;
+    ox.set("plugins", list)?;
+    // Run a plug-in again (for when it is being worked on), returning an error if it failed
+    let reload = lua.create_function(|lua, name: String| {
+        let plugin = lua
+            .app_data_ref::<Vec<Plugin>>()
+            .and_then(|plugins| plugins.iter().find(|p| p.manifest.name == name).cloned());
+        let Some(mut plugin) = plugin else {
+            return Ok(Some(format!("There is no plug-in called '{name}'")));
+        };
+        // The manifest may have changed too
+        if let Ok(source) = std::fs::read_to_string(plugin.dir.join(MANIFEST_FILE)) {
+            match parse_manifest(&source) {
+                Ok(manifest) if manifest.name == name => plugin.manifest = manifest,
+                Ok(_) => return Ok(Some("A plug-in can't be renamed while reloading".into())),
+                Err(err) => return Ok(Some(format!("Invalid manifest: {err}"))),
+            }
+        }
+        let result = run_plugin(lua, &plugin);
+        plugin.status = match &result {
+            Ok(()) => PluginStatus::Loaded,
+            Err(err) => PluginStatus::Failed(err.clone()),
+        };
+        if let Some(mut plugins) = lua.app_data_mut::<Vec<Plugin>>() {
+            if let Some(entry) = plugins.iter_mut().find(|p| p.manifest.name == name) {
+                *entry = plugin;
+            }
+        }
+        Ok(result.err())
+    })?;
+    ox.set("reload_plugin", reload)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn manifest(name: &str, dependencies: &[&str]) -> Manifest {
+        Manifest {
+            name: name.to_string(),
+            version: "1.0.0".to_string(),
+            dependencies: dependencies.iter().map(ToString::to_string).collect(),
+            ..Manifest::default()
+        }
+    }
+
+    #[test]
+    fn manifests() {
+        // Fields can be set as globals...
+        let source = "-- An example\nname = 'example'\nversion = \"1.2.0\"\nox = '0.7.0'\n\
+                      dependencies = { 'base', 'other' }\nformat = 1\nhomepage = 'unknown'";
+        assert_eq!(
+            parse_manifest(source),
+            Ok(Manifest {
+                name: "example".to_string(),
+                version: "1.2.0".to_string(),
+                min_ox: Some("0.7.0".to_string()),
+                entry: "init.lua".to_string(),
+                dependencies: vec!["base".to_string(), "other".to_string()],
+            })
+        );
+        // ...or returned in a table
+        let source =
+            "local v = '0.' .. 1\nreturn { name = 'lib', version = v, entry = 'src/main.lua' }";
+        let lib = parse_manifest(source).unwrap();
+        assert_eq!(
+            (lib.version.as_str(), lib.entry.as_str()),
+            ("0.1", "src/main.lua")
+        );
+        assert_eq!(lib.min_ox, None);
+    }
+
+    #[test]
+    fn invalid_manifests() {
+        let fails = |source: &str, reason: &str| {
+            let err = parse_manifest(source).unwrap_err();
+            assert!(err.contains(reason), "{source:?} gave {err:?}");
+        };
+        fails("version = '1.0'", "no name");
+        fails("name = 'a'", "no version");
+        fails("name = 'a b'; version = '1'", "isn't a valid plug-in name");
+        fails("name = 'a'; version = 'one'", "isn't a valid version");
+        fails(
+            "name = 'a'; version = '1'; ox = 'new'",
+            "isn't a valid version of ox",
+        );
+        fails("name = 'a'; version = 1", "'version' should be a string");
+        fails(
+            "name = 'a'; version = '1'; dependencies = 'b'",
+            "list of strings",
+        );
+        fails(
+            "name = 'a'; version = '1'; dependencies = { 2 }",
+            "list of strings",
+        );
+        fails(
+            "name = 'a'; version = '1'; format = 2",
+            "needs a newer version",
+        );
+        fails(
+            "name = 'a'; version = '1'; format = '1'",
+            "should be a number",
+        );
+        fails(
+            "name = 'a'; version = '1'; entry = '../x.lua'",
+            "outside of the plug-in",
+        );
+        fails("return 'a'", "should return a table");
+        fails("name = ", "plugin.lua:1:");
+        // Manifests can't reach the outside world, or keep ox from starting
+        fails("os.remove('x')", "os");
+        fails("name = require('a')", "require");
+        fails("while true do end", "took too long");
+    }
+
+    #[test]
+    fn versions() {
+        let cmp = |a, b| compare_versions(a, b);
+        assert_eq!(cmp("1.2.3", "1.2.3"), Some(Ordering::Equal));
+        assert_eq!(cmp("1.2", "1.2.0"), Some(Ordering::Equal));
+        assert_eq!(cmp("v0.7.1", "0.7"), Some(Ordering::Greater));
+        assert_eq!(cmp("0.10.0", "0.9.9"), Some(Ordering::Greater));
+        assert_eq!(cmp("2.0.0-beta", "2.0.1"), Some(Ordering::Less));
+        assert_eq!(cmp("1.0+build", "1"), Some(Ordering::Equal));
+        assert_eq!(cmp("1.x", "1.0"), None);
+        assert_eq!(cmp("1.0", ""), None);
+    }
+
+    #[test]
+    fn loading_order() {
+        // Dependencies come first, otherwise the order they were found in is kept
+        let manifests = [
+            manifest("app", &["ui", "core"]),
+            manifest("ui", &["core"]),
+            manifest("core", &[]),
+        ];
+        assert_eq!(load_order(&manifests), (vec![2, 1, 0], vec![]));
+        // Anything that can't be loaded takes what depends on it down with it
+        let manifests = [
+            manifest("a", &["b"]),
+            manifest("b", &["c"]),
+            manifest("c", &["a"]),
+            manifest("d", &["missing"]),
+            manifest("e", &["d"]),
+            manifest("f", &[]),
+            manifest("f", &[]),
+        ];
+        let (order, failed) = load_order(&manifests);
+        assert_eq!(order, vec![5]);
+        let reason = |idx: usize| &failed.iter().find(|(i, _)| *i == idx).unwrap().1;
+        assert_eq!(reason(0), "dependency cycle a -> b -> c -> a");
+        assert_eq!(reason(2), "dependency cycle a -> b -> c -> a");
+        assert_eq!(reason(3), "depends on 'missing', which isn't installed");
+        assert_eq!(reason(4), "depends on 'd', which couldn't be loaded");
+        assert_eq!(reason(6), "another plug-in is already called 'f'");
+        assert_eq!(failed.len(), 6);
+    }
+}
@@ -0,0 +1,83 @@
+/// Abbreviations that are expanded as they are typed (the `abbreviations` table in the
+/// configuration file): a word followed by something that can't be part of a word is swapped
+/// for what it stands for
+use crate::error::Result;
+use kaolinite::event::Event;
+use kaolinite::utils::Loc;
+use mlua::{Lua, Table, Value};
+
+use super::completion::is_word_char;
+use super::Editor;
+
+/// Find the abbreviation that some text ends with (as a whole word), along with its expansion.
+/// Abbreviations for the file type (a table within the table named after it) come first,
+/// and the longest one that matches is taken
+fn find_abbreviation(lua: &Lua, file_type: Option<&str>, text: &str) -> Option<(String, String)> {
+    let table: Table = lua.globals().get("abbreviations").ok()?;
+    let scoped = file_type.and_then(|name| table.get::<Table>(name).ok());
+    for table in scoped.iter().chain([&table]) {
+        let mut found: Option<(String, String)> = None;
+        for pair in table.pairs::<String, Value>() {
+            let Ok((word, Value::String(expansion))) = pair else {
+                continue;
+            };
+            let Some(before) = text.strip_suffix(word.as_str()) else {
+                continue;
+            };
+            let whole = !before.chars().next_back().is_some_and(is_word_char);
+            let longer = !found.as_ref().is_some_and(|(w, _)| w.len() >= word.len());
+            if !word.is_empty() && whole && longer {
+                found = Some((word, expansion.to_string_lossy()));
+            }
+        }
+        if found.is_some() {
+            return found;
+        }
+    }
+    None
+}
+
+impl Editor {
+    /// Expand the abbreviation just before a character that has been typed (if there is one),
+    /// as a change of its own, so undoing it brings back what was typed
+    pub fn expand_abbreviation(&mut self, lua: &Lua, typed: char) -> Result<()> {
+        if is_word_char(typed) || self.in_block() {
+            return Ok(());
+        }
+        let Some(file) = self.files.get(self.ptr.clone()) else {
+            return Ok(());
+        };
+        let doc = &file.doc;
+        if doc.info.read_only || !doc.secondary_cursors.is_empty() {
+            return Ok(());
+        }
+        // The character typed should be just before the cursor
+        let here = doc.char_loc();
+        let line: Vec<char> = doc.line(here.y).unwrap_or_default().chars().collect();
+        let Some(at) = here
+            .x
+            .checked_sub(1)
+            .filter(|at| line.get(*at) == Some(&typed))
+        else {
+            return Ok(());
+        };
+        let before: String = line[..at].iter().collect();
+        let file_type = file.file_type.as_ref().map(|t| t.name.as_str());
+        let Some((word, expansion)) = find_abbreviation(lua, file_type, &before) else {
+            return Ok(());
+        };
+        let start = Loc::at(at - word.chars().count(), here.y);
+        let end = Loc::at(start.x + expansion.chars().count() + 1, here.y);
+        self.commit();
+        self.batch(|editor| {
+            editor.exe(Event::Delete(start, word))?;
+            editor.exe(Event::Insert(start, expansion))
+        })?;
+        self.commit();
+        if let Some(doc) = self.try_doc_mut() {
+            doc.move_to(&end);
+        }
+        self.highlight_edited(here.y);
+        Ok(())
+    }
+}
@@ -0,0 +1,233 @@
+/// Actions: everything the editor can be asked to do, whether by a key, the mouse,
+/// the command line or a plug-in, funnelled through a single place
+use crate::error::{OxError, Result};
+use crate::Feedback;
+use crossterm::event::{KeyCode as KCode, KeyModifiers as KMod};
+use mlua::Lua;
+use std::str::FromStr;
+
+use super::Editor;
+
+/// A direction the cursor can be moved (or a selection extended) in
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+/// Something the editor can be asked to do
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Action {
+    /// Type a character at the cursor (replacing any selection)
+    InsertChar(char),
+    /// Indent (or insert a tab character), depending on the configuration
+    InsertTab,
+    /// Split the line at the cursor
+    InsertLine,
+    /// Remove the character before the cursor
+    Backspace,
+    /// Remove the character under the cursor
+    Delete,
+    MoveCursor(Direction),
+    /// Extend the selection in a direction
+    Select(Direction),
+    SelectAll,
+    MoveHome,
+    MoveEnd,
+    MoveTop,
+    MoveBottom,
+    MovePageUp,
+    MovePageDown,
+    MovePreviousWord,
+    MoveNextWord,
+    /// Move by parts of identifiers (split at underscores and changes of case)
+    MovePreviousSubWord,
+    MoveNextSubWord,
+    /// Remove back to the start of the word (or part of an identifier) before the cursor
+    DeleteWord,
+    DeleteSubWord,
+    Undo,
+    Redo,
+    /// Record the current state of the document as an undo point
+    Commit,
+    SaveFile,
+    SaveAs,
+    SaveAll,
+    NewDocument,
+    OpenDocument,
+    NextTab,
+    PreviousTab,
+    /// Go to a tab (numbered from 1)
+    GoToTab(usize),
+    /// Close the current document (and the editor along with the last one)
+    Quit,
+    QuitAll,
+    /// Run a command, just as if it were typed into the command line
+    RunCommand(String),
+}
+
+impl Action {
+    /// The action a key performs regardless of the configuration file, if it has one
+    /// (these are the key bindings that can be added to but not changed)
+    #[must_use]
+    pub fn from_key(modifiers: KMod, code: KCode) -> Option<Self> {
+        Some(match (modifiers, code) {
+            (KMod::SHIFT | KMod::NONE, KCode::Char(ch)) => Self::InsertChar(ch),
+            (KMod::NONE, KCode::Tab) => Self::InsertTab,
+            (KMod::NONE, KCode::Backspace) => Self::Backspace,
+            (KMod::NONE, KCode::Delete) => Self::Delete,
+            (KMod::NONE, KCode::Enter) => Self::InsertLine,
+            _ => return None,
+        })
+    }
+}
+
+impl FromStr for Action {
+    type Err = OxError;
+
+    /// Read an action from its name (matching the name of the editor method in the
+    /// configuration file), followed by a space and an argument for actions that take one,
+    /// e.g. `move_up`, `save`, `go_to_tab 2` or `run_command theme dark`
+    fn from_str(text: &str) -> Result<Self> {
+        let (name, argument) = text.split_once(' ').unwrap_or((text, ""));
+        let unknown = || OxError::Config {
+            msg: format!("'{text}' is not an action"),
+        };
+        Ok(match name {
+            "insert_char" => Self::InsertChar(argument.chars().next().ok_or_else(unknown)?),
+            "insert_tab" => Self::InsertTab,
+            "insert_line" => Self::InsertLine,
+            "remove" | "backspace" => Self::Backspace,
+            "delete" => Self::Delete,
+            "move_up" => Self::MoveCursor(Direction::Up),
+            "move_down" => Self::MoveCursor(Direction::Down),
+            "move_left" => Self::MoveCursor(Direction::Left),
+            "move_right" => Self::MoveCursor(Direction::Right),
+            "select_up" => Self::Select(Direction::Up),
+            "select_down" => Self::Select(Direction::Down),
+            "select_left" => Self::Select(Direction::Left),
+            "select_right" => Self::Select(Direction::Right),
+            "select_all" => Self::SelectAll,
+            "move_home" => Self::MoveHome,
+            "move_end" => Self::MoveEnd,
+            "move_top" => Self::MoveTop,
+            "move_bottom" => Self::MoveBottom,
+            "move_page_up" => Self::MovePageUp,
+            "move_page_down" => Self::MovePageDown,
+            "move_previous_word" => Self::MovePreviousWord,
+            "move_next_word" => Self::MoveNextWord,
+            "move_previous_sub_word" | "previous_sub_word" => Self::MovePreviousSubWord,
+            "move_next_sub_word" | "next_sub_word" => Self::MoveNextSubWord,
+            "remove_word" | "delete_word" => Self::DeleteWord,
+            "remove_sub_word" | "delete_sub_word" => Self::DeleteSubWord,
+            "undo" => Self::Undo,
+            "redo" => Self::Redo,
+            "commit" => Self::Commit,
+            "save" => Self::SaveFile,
+            "save_as" => Self::SaveAs,
+            "save_all" => Self::SaveAll,
+            "new" => Self::NewDocument,
+            "open" => Self::OpenDocument,
+            "next_tab" => Self::NextTab,
+            "previous_tab" => Self::PreviousTab,
+            "go_to_tab" => Self::GoToTab(argument.trim().parse().map_err(|_| unknown())?),
+            "quit" => Self::Quit,
+            "quit_all" => Self::QuitAll,
+            "run_command" if !argument.trim().is_empty() => {
+                Self::RunCommand(argument.trim().to_string())
+            }
+            _ => return Err(unknown()),
+        })
+    }
+}
+
+impl Editor {
+    /// Carry out an action
+    pub fn perform(&mut self, lua: &Lua, action: Action) -> Result<()> {
+        // Moving past what has been read of a large file means reading the rest of it now
+        if matches!(action, Action::MoveBottom | Action::SelectAll) {
+            self.wait_for_document();
+        } else {
+            self.wait_if_near_end();
+        }
+        match action {
+            Action::InsertChar(ch) => self.character(ch)?,
+            Action::InsertTab => self.handle_tab()?,
+            Action::InsertLine => self.enter()?,
+            Action::Backspace => self.backspace()?,
+            Action::Delete => self.delete()?,
+            Action::MoveCursor(Direction::Up) => self.up(),
+            Action::MoveCursor(Direction::Down) => self.down(),
+            Action::MoveCursor(Direction::Left) => self.left(),
+            Action::MoveCursor(Direction::Right) => self.right(),
+            Action::Select(Direction::Up) => self.select_up(),
+            Action::Select(Direction::Down) => self.select_down(),
+            Action::Select(Direction::Left) => self.select_left(),
+            Action::Select(Direction::Right) => self.select_right(),
+            Action::SelectAll => self.select_all(),
+            Action::MoveHome => self.home(),
+            Action::MovePreviousWord => self.prev_word(),
+            Action::MoveNextWord => self.next_word(),
+            Action::MovePreviousSubWord => self.prev_sub_word(),
+            Action::MoveNextSubWord => self.next_sub_word(),
+            Action::DeleteWord => self.remove_word(false)?,
+            Action::DeleteSubWord => self.remove_word(true)?,
+            Action::MoveEnd => {
+                if let Some(doc) = self.try_doc_mut() {
+                    if doc.cursor.block.is_some() {
+                        doc.block_end();
+                    } else {
+                        doc.move_end();
+                    }
+                }
+            }
+            Action::MoveTop => {
+                if let Some(doc) = self.try_doc_mut() {
+                    doc.move_top();
+                }
+            }
+            Action::MoveBottom => {
+                if let Some(doc) = self.try_doc_mut() {
+                    doc.move_bottom();
+                }
+            }
+            Action::MovePageUp => {
+                if let Some(doc) = self.try_doc_mut() {
+                    doc.move_page_up();
+                }
+            }
+            Action::MovePageDown => {
+                if let Some(doc) = self.try_doc_mut() {
+                    doc.move_page_down();
+                }
+            }
+            Action::Undo => self.undo()?,
+            Action::Redo => self.redo()?,
+            Action::Commit => self.commit(),
+            Action::SaveFile => self.save()?,
+            Action::SaveAs => self.save_as()?,
+            Action::SaveAll => self.save_all()?,
+            Action::NewDocument => self.new_document()?,
+            Action::OpenDocument => self.open_document()?,
+            Action::NextTab => self.next(),
+            Action::PreviousTab => self.prev(),
+            Action::GoToTab(n) => self.go_to_tab(n),
+            Action::Quit => self.quit(lua)?,
+            Action::QuitAll => self.quit_all(lua)?,
+            // Commands are run by the main loop once the current event has been dealt with
+            Action::RunCommand(command) => self.command = Some(command),
+        }
+        Ok(())
+    }
+
+    /// Carry out an action, showing any error that comes of it to the user
+    /// (an action being cancelled isn't an error)
+    pub fn perform_reporting(&mut self, lua: &Lua, action: Action) {
+        match self.perform(lua, action) {
+            Ok(()) | Err(OxError::Cancelled) => (),
+            Err(err) => self.feedback = Feedback::Error(err.to_string()),
+        }
+    }
+}
@@ -0,0 +1,198 @@
+/// Annotating lines of files with short labels that are kept between sessions
+use crate::error::Result;
+use crate::Feedback;
+use crossterm::event::{KeyCode as KCode, KeyModifiers as KMod};
+use kaolinite::document::{load_marks, save_marks, LineMark};
+use kaolinite::event::stable_hash;
+use kaolinite::utils::{get_absolute_path, get_file_name, Loc};
+use kaolinite::Document;
+use mlua::Lua;
+use std::path::PathBuf;
+
+use super::picker::PickerKey;
+use super::Editor;
+
+/// Work out where ox keeps data that should last between sessions
+pub fn data_dir() -> Option<PathBuf> {
+    let data = std::env::var("XDG_DATA_HOME")
+        .ok()
+        .filter(|d| !d.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| {
+            let home = std::env::var("HOME").or_else(|_| std::env::var("USERPROFILE"));
+            home.ok()
+                .map(|h| PathBuf::from(h).join(".local").join("share"))
+        })?;
+    Some(data.join("ox"))
+}
+
+/// Work out where the annotations of a file are kept (based on its canonical path)
+pub fn annotations_path(file_name: &str) -> Option<PathBuf> {
+    let path = get_absolute_path(file_name)?;
+    let name = format!("{:016x}", stable_hash([path.as_str()]));
+    Some(data_dir()?.join("annotations").join(name))
+}
+
+/// Restore the annotations of a document, finding their lines again if the file has changed
+pub fn restore_annotations(doc: &mut Document) {
+    let Some(path) = doc.file_name.as_deref().and_then(annotations_path) else {
+        return;
+    };
+    if let Ok(data) = std::fs::read_to_string(path) {
+        doc.marks.retain(|m| m.bookmark);
+        doc.marks.extend(load_marks(&data));
+        doc.reanchor_marks(true);
+    }
+}
+
+/// An annotation in one of the open documents, as shown in the picker
+struct Listing {
+    ptr: Vec<usize>,
+    doc: usize,
+    y: usize,
+    text: String,
+}
+
+impl Editor {
+    /// Store the annotations of a document for future sessions
+    pub fn store_annotations_at(&mut self, ptr: &[usize], doc: usize) {
+        let Ok(file) = self.file_at(ptr, doc) else {
+            return;
+        };
+        let Some(path) = file.doc.file_name.as_deref().and_then(annotations_path) else {
+            return;
+        };
+        // Annotations are only a convenience, so failing to store them isn't worth reporting
+        let marks: Vec<LineMark> = file
+            .doc
+            .marks
+            .iter()
+            .filter(|m| !m.bookmark)
+            .cloned()
+            .collect();
+        if marks.is_empty() {
+            let _ = std::fs::remove_file(path);
+            return;
+        }
+        let data = save_marks(&marks);
+        if let Some(parent) = path.parent() {
+            let _ = std::fs::create_dir_all(parent);
+        }
+        let _ = std::fs::write(path, data);
+    }
+
+    /// Prompt for a label to annotate the current line with (an empty label removes it)
+    pub fn annotate(&mut self) -> Result<()> {
+        let Some((_, idx)) = self.files.get_atom(self.ptr.clone()) else {
+            return Ok(());
+        };
+        let label = self.prompt("Annotation")?;
+        let label = label.trim();
+        let Some(doc) = self.try_doc_mut() else {
+            return Ok(());
+        };
+        let y = doc.loc().y;
+        if label.is_empty() {
+            doc.remove_mark(y);
+        } else {
+            doc.add_mark(y, label);
+        }
+        self.store_annotations_at(&self.ptr.clone(), idx);
+        Ok(())
+    }
+
+    /// Show the annotation on the current line
+    pub fn show_annotation(&mut self) {
+        let Some(doc) = self.try_doc() else {
+            return;
+        };
+        self.feedback = match doc.mark_at(doc.loc().y) {
+            Some(mark) if mark.moved => {
+                Feedback::Warning(format!("{} (the line has changed since)", mark.label))
+            }
+            Some(mark) => Feedback::Info(mark.label.clone()),
+            None => Feedback::Warning("There is no annotation on this line".to_string()),
+        };
+    }
+
+    /// Gather up the annotations in every open document
+    fn list_annotations(&self) -> Vec<Listing> {
+        let mut result = vec![];
+        for (ptr, doc) in self.files.all_documents(vec![]) {
+            let Ok(file) = self.file_at(&ptr, doc) else {
+                continue;
+            };
+            let name = file
+                .doc
+                .file_name
+                .as_deref()
+                .and_then(get_file_name)
+                .unwrap_or_else(|| file.placeholder_name());
+            for mark in file.doc.marks.iter().filter(|m| !m.bookmark) {
+                let moved = if mark.moved { " (moved)" } else { "" };
+                let preview = file.doc.line(mark.y).unwrap_or_default();
+                let text = format!(
+                    "{name}:{}  {}{moved}  │ {}",
+                    mark.y + 1,
+                    mark.label,
+                    preview.trim()
+                );
+                result.push(Listing {
+                    ptr: ptr.clone(),
+                    doc,
+                    y: mark.y,
+                    text,
+                });
+            }
+        }
+        result
+    }
+
+    /// Open a picker listing the annotations in every open document,
+    /// where enter jumps to an annotation and d deletes it
+    pub fn annotations(&mut self, lua: &Lua) -> Result<()> {
+        let listings = self.list_annotations();
+        if listings.is_empty() {
+            self.feedback = Feedback::Warning("There are no annotations".to_string());
+            return Ok(());
+        }
+        let hint = "Annotations: enter to jump, d to delete, esc to cancel";
+        self.pick(
+            lua,
+            listings,
+            0,
+            |editor, listings, selected| {
+                let items: Vec<String> = listings.iter().map(|l| l.text.clone()).collect();
+                editor.render_list(&items, *selected, hint)?;
+                Ok(items.len())
+            },
+            |editor, lua, listings, key, selected| {
+                if key != (KMod::NONE, KCode::Char('d')) {
+                    return Ok(None);
+                }
+                // Delete the selected annotation
+                let Listing { ptr, doc, y, .. } = listings.remove(*selected);
+                if let Ok(file) = editor.file_at_mut(&ptr, doc) {
+                    file.doc.remove_mark(y);
+                }
+                editor.store_annotations_at(&ptr, doc);
+                // Redraw the rows that are no longer covered
+                editor.render_document(lua)?;
+                if listings.is_empty() {
+                    return Ok(Some(PickerKey::Done));
+                }
+                *selected = (*selected).min(listings.len() - 1);
+                Ok(Some(PickerKey::Stay))
+            },
+            |editor, mut listings, selected| {
+                let Listing { ptr, doc, y, .. } = listings.remove(selected);
+                editor.ptr.clone_from(&ptr);
+                editor.switch_to(doc);
+                if let Some(doc) = editor.try_doc_mut() {
+                    doc.move_to(&Loc { x: 0, y });
+                }
+                Ok(())
+            },
+        )
+    }
+}
@@ -0,0 +1,223 @@
+/// Keeping a history of timestamped copies of files as they are worked on
+use crate::config;
+use crate::error::Result;
+use crate::Feedback;
+use crossterm::event::{KeyCode as KCode, KeyModifiers as KMod};
+use kaolinite::backups::{
+    backup_due, backup_name, format_size, format_time, now, parse_backup_name, prune, Backup,
+};
+use kaolinite::event::stable_hash;
+use kaolinite::utils::{get_file_name, get_path_identity};
+use mlua::Lua;
+use std::path::{Path, PathBuf};
+
+use super::picker::PickerKey;
+use super::{data_dir, Editor, FileContainer};
+
+/// The file in each history directory that records which file the copies are of
+const ORIGIN_FILE: &str = "path";
+
+/// Work out where the copies of a file are kept (based on its canonical path).
+/// This works for files that no longer exist too, so the history of a file
+/// that has been renamed or removed can still be found from its old path
+pub fn backups_dir(file_name: &str) -> Option<PathBuf> {
+    let path = get_path_identity(file_name);
+    let name = format!("{:016x}", stable_hash([path.as_str()]));
+    Some(data_dir()?.join("history").join(name))
+}
+
+/// List the copies kept in a history directory (newest first)
+pub fn list_backups(dir: &Path) -> Vec<Backup> {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return vec![];
+    };
+    let mut result: Vec<Backup> = entries
+        .filter_map(std::result::Result::ok)
+        .filter_map(|entry| {
+            let name = entry.file_name().to_string_lossy().to_string();
+            let time = parse_backup_name(&name)?;
+            let size = entry.metadata().ok()?.len();
+            Some(Backup { name, time, size })
+        })
+        .collect();
+    result.sort_by_key(|b| std::cmp::Reverse(b.time));
+    result
+}
+
+/// Store a copy of a file's contents (unless it matches the newest copy),
+/// then remove the oldest copies that no longer fit within the limits
+fn write_backup(file_name: &str, contents: &str, limit: usize, size_limit: usize) {
+    let Some(dir) = backups_dir(file_name) else {
+        return;
+    };
+    let existing = list_backups(&dir);
+    if let Some(newest) = existing.first() {
+        if std::fs::read(dir.join(&newest.name)).is_ok_and(|c| c == contents.as_bytes()) {
+            return;
+        }
+    }
+    // The history is only a convenience, so failing to store it isn't worth reporting
+    if std::fs::create_dir_all(&dir).is_err() {
+        return;
+    }
+    let _ = std::fs::write(dir.join(ORIGIN_FILE), get_path_identity(file_name));
+    let _ = std::fs::write(dir.join(backup_name(now())), contents);
+    let size_limit = u64::try_from(size_limit).unwrap_or(u64::MAX);
+    for name in prune(&list_backups(&dir), limit, size_limit) {
+        let _ = std::fs::remove_file(dir.join(name));
+    }
+}
+
+impl Editor {
+    /// Store a copy of a document in its history (if backups are enabled)
+    pub fn backup_at(&mut self, ptr: &[usize], doc: usize) {
+        let document = config!(self.config, document);
+        let (enabled, limit) = (document.backups, document.backup_limit);
+        let size_limit = document.backup_size_limit;
+        drop(document);
+        if !enabled {
+            return;
+        }
+        let Ok(file) = self.file_at(ptr, doc) else {
+            return;
+        };
+        if !file.savable() {
+            return;
+        }
+        if let Some(file_name) = &file.doc.file_name {
+            write_backup(file_name, &file.doc.file.to_string(), limit, size_limit);
+        }
+    }
+
+    /// Store copies of documents with unsaved changes every so often (if backups are enabled)
+    pub fn backup_idle_tick(&mut self) {
+        let document = config!(self.config, document);
+        let interval = u64::try_from(document.backup_interval).unwrap_or(u64::MAX);
+        let enabled = document.backups;
+        drop(document);
+        if !enabled || !backup_due(self.last_backup, now(), interval.saturating_mul(60)) {
+            return;
+        }
+        self.last_backup = now();
+        for (ptr, doc) in self.files.all_documents(vec![]) {
+            if self
+                .file_at(&ptr, doc)
+                .is_ok_and(FileContainer::is_modified)
+            {
+                self.backup_at(&ptr, doc);
+            }
+        }
+    }
+
+    /// Open a picker listing the stored versions of a file (the current document by default),
+    /// where enter views a version, d compares it with the current document and r restores it
+    pub fn history(&mut self, lua: &Lua, path: Option<&str>) -> Result<()> {
+        let current = self.try_doc().and_then(|doc| doc.file_name.clone());
+        let Some(file_name) = path.map(str::to_string).or(current) else {
+            self.feedback = Feedback::Warning("This document has no history yet".to_string());
+            return Ok(());
+        };
+        let name = get_file_name(&file_name).unwrap_or_else(|| file_name.clone());
+        let Some(dir) = backups_dir(&file_name) else {
+            return Ok(());
+        };
+        let backups = list_backups(&dir);
+        if backups.is_empty() {
+            self.feedback = Feedback::Warning(if config!(self.config, document).backups {
+                format!("There are no stored versions of {name}")
+            } else {
+                "Backups are turned off, set document.backups = true to keep them".to_string()
+            });
+            return Ok(());
+        }
+        let items: Vec<String> = backups
+            .iter()
+            .map(|b| format!("{}  {}", format_time(b.time), format_size(b.size)))
+            .collect();
+        let hint =
+            format!("History of {name}: enter to view, d to compare, r to restore, esc to cancel");
+        // Enter views the selected version, and other keys can act on it instead
+        self.pick(
+            lua,
+            'v',
+            0,
+            |editor, _, selected| {
+                editor.render_list(&items, *selected, &hint)?;
+                Ok(items.len())
+            },
+            |_, _, action, key, _| {
+                Ok(match key {
+                    (KMod::NONE, KCode::Char(c @ ('d' | 'r'))) => {
+                        *action = c;
+                        Some(PickerKey::Pick)
+                    }
+                    _ => None,
+                })
+            },
+            |editor, action, selected| {
+                let backup = &backups[selected];
+                let bytes = std::fs::read(dir.join(&backup.name))?;
+                let contents = String::from_utf8_lossy(&bytes).to_string();
+                let title = format!("{name} {}", format_time(backup.time));
+                match action {
+                    'd' => editor.compare_version(&contents, &title),
+                    'r' => editor.restore_version(&contents, &title),
+                    _ => return editor.view_version(contents, &title),
+                }
+                Ok(())
+            },
+        )
+    }
+
+    /// Open a read only copy of an older version of a file
+    fn view_version(&mut self, contents: String, title: &str) -> Result<()> {
+        let file_type = self
+            .files
+            .get(self.ptr.clone())
+            .and_then(|f| f.file_type.as_ref())
+            .map(|t| t.name.clone());
+        self.open_from_string(contents, file_type)?;
+        self.next();
+        self.mark_version(title);
+        Ok(())
+    }
+
+    /// Show how the current document differs from an older version of it, in the diff view
+    fn compare_version(&mut self, contents: &str, title: &str) {
+        let Some(doc) = self.try_doc() else {
+            return;
+        };
+        let Some(file_name) = doc.file_name.clone() else {
+            self.feedback = Feedback::Error("Save this document before comparing it".to_string());
+            return;
+        };
+        if doc.file == contents {
+            self.feedback = Feedback::Info(format!("{title} is the same as the document"));
+            return;
+        }
+        self.show_diff(&file_name, contents, title.to_string(), false);
+    }
+
+    /// Make the document that was just opened a read only scratch buffer,
+    /// so that it is never prompted for saving
+    fn mark_version(&mut self, title: &str) {
+        if let Some(file) = self.files.get_mut(self.ptr.clone()) {
+            file.doc.info.read_only = true;
+            file.scratch = Some(title.to_string());
+        }
+    }
+
+    /// Replace the contents of the current document with an older version (in one undoable step)
+    fn restore_version(&mut self, contents: &str, title: &str) {
+        let Some(doc) = self.try_doc_mut() else {
+            return;
+        };
+        if doc.info.read_only {
+            self.feedback = Feedback::Error("This document is read only".to_string());
+            return;
+        }
+        doc.batch(|doc| doc.replace_contents(contents));
+        self.reload_highlight();
+        self.feedback = Feedback::Info(format!("Restored {title}, undo to go back"));
+    }
+}
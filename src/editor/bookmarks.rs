@@ -0,0 +1,187 @@
+/// Bookmarking lines to jump back to, within a document and across the open documents
+use crate::config;
+use crate::error::Result;
+use crate::Feedback;
+use crossterm::event::{KeyCode as KCode, KeyModifiers as KMod};
+use kaolinite::document::{load_marks, save_marks, LineMark};
+use kaolinite::event::stable_hash;
+use kaolinite::utils::{get_absolute_path, get_file_name, Loc};
+use kaolinite::Document;
+use mlua::Lua;
+use std::path::PathBuf;
+
+use super::annotations::data_dir;
+use super::picker::PickerKey;
+use super::Editor;
+
+/// Drawn before the line number of bookmarked lines
+pub const BOOKMARK_MARKER: char = '▸';
+
+/// Work out where the bookmarks of a file are kept (based on its canonical path)
+pub fn bookmarks_path(file_name: &str) -> Option<PathBuf> {
+    let path = get_absolute_path(file_name)?;
+    let name = format!("{:016x}", stable_hash([path.as_str()]));
+    Some(data_dir()?.join("bookmarks").join(name))
+}
+
+/// Restore the bookmarks of a document, finding their lines again if the file has changed
+pub fn restore_bookmarks(doc: &mut Document) {
+    let Some(path) = doc.file_name.as_deref().and_then(bookmarks_path) else {
+        return;
+    };
+    if let Ok(data) = std::fs::read_to_string(path) {
+        doc.marks.retain(|m| !m.bookmark);
+        doc.marks
+            .extend(load_marks(&data).into_iter().map(|m| LineMark {
+                bookmark: true,
+                ..m
+            }));
+        doc.reanchor_marks(true);
+    }
+}
+
+/// A bookmark in one of the open documents, as shown in the picker
+struct Listing {
+    ptr: Vec<usize>,
+    doc: usize,
+    y: usize,
+    text: String,
+}
+
+impl Editor {
+    /// Store the bookmarks of a document for future sessions (if enabled)
+    pub fn store_bookmarks_at(&mut self, ptr: &[usize], doc: usize) {
+        if !config!(self.config, document).persistent_bookmarks {
+            return;
+        }
+        let Ok(file) = self.file_at(ptr, doc) else {
+            return;
+        };
+        let Some(path) = file.doc.file_name.as_deref().and_then(bookmarks_path) else {
+            return;
+        };
+        // Bookmarks are only a convenience, so failing to store them isn't worth reporting
+        let bookmarks: Vec<LineMark> = file.doc.bookmarks().cloned().collect();
+        if bookmarks.is_empty() {
+            let _ = std::fs::remove_file(path);
+            return;
+        }
+        let data = save_marks(&bookmarks);
+        if let Some(parent) = path.parent() {
+            let _ = std::fs::create_dir_all(parent);
+        }
+        let _ = std::fs::write(path, data);
+    }
+
+    /// Bookmark the current line, or remove its bookmark if it has one
+    pub fn toggle_bookmark(&mut self) {
+        if let Some(doc) = self.try_doc() {
+            self.toggle_bookmark_at(doc.loc().y);
+        }
+    }
+
+    /// Bookmark a line of the current document, or remove its bookmark if it has one
+    pub fn toggle_bookmark_at(&mut self, y: usize) {
+        let Some((_, idx)) = self.files.get_atom(self.ptr.clone()) else {
+            return;
+        };
+        let Some(doc) = self.try_doc_mut() else {
+            return;
+        };
+        if y >= doc.len_lines() {
+            return;
+        }
+        self.feedback = if doc.toggle_bookmark(y) {
+            Feedback::Info(format!("Bookmarked line {}", y + 1))
+        } else {
+            Feedback::Info(format!("Removed the bookmark on line {}", y + 1))
+        };
+        self.store_bookmarks_at(&self.ptr.clone(), idx);
+    }
+
+    /// Move to the next (or previous) bookmark in the current document
+    pub fn next_bookmark(&mut self, forward: bool) {
+        let Some(doc) = self.try_doc_mut() else {
+            return;
+        };
+        let Some(y) = doc.next_bookmark(doc.loc().y, forward) else {
+            self.feedback = Feedback::Info("There are no bookmarks in this document".to_string());
+            return;
+        };
+        doc.move_to(&Loc { x: 0, y });
+    }
+
+    /// Gather up the bookmarks in every open document
+    fn list_bookmarks(&self) -> Vec<Listing> {
+        let mut result = vec![];
+        for (ptr, doc) in self.files.all_documents(vec![]) {
+            let Ok(file) = self.file_at(&ptr, doc) else {
+                continue;
+            };
+            let name = file
+                .doc
+                .file_name
+                .as_deref()
+                .and_then(get_file_name)
+                .unwrap_or_else(|| file.placeholder_name());
+            for mark in file.doc.bookmarks() {
+                let preview = file.doc.line(mark.y).unwrap_or_default();
+                let text = format!("{name}:{}  │ {}", mark.y + 1, preview.trim());
+                result.push(Listing {
+                    ptr: ptr.clone(),
+                    doc,
+                    y: mark.y,
+                    text,
+                });
+            }
+        }
+        result
+    }
+
+    /// Open a picker listing the bookmarks in every open document,
+    /// where enter jumps to a bookmark and d removes it
+    pub fn bookmarks(&mut self, lua: &Lua) -> Result<()> {
+        let listings = self.list_bookmarks();
+        if listings.is_empty() {
+            self.feedback = Feedback::Warning("There are no bookmarks".to_string());
+            return Ok(());
+        }
+        let hint = "Bookmarks: enter to jump, d to remove, esc to cancel";
+        self.pick(
+            lua,
+            listings,
+            0,
+            |editor, listings, selected| {
+                let items: Vec<String> = listings.iter().map(|l| l.text.clone()).collect();
+                editor.render_list(&items, *selected, hint)?;
+                Ok(items.len())
+            },
+            |editor, lua, listings, key, selected| {
+                if key != (KMod::NONE, KCode::Char('d')) {
+                    return Ok(None);
+                }
+                // Remove the selected bookmark
+                let Listing { ptr, doc, y, .. } = listings.remove(*selected);
+                if let Ok(file) = editor.file_at_mut(&ptr, doc) {
+                    file.doc.toggle_bookmark(y);
+                }
+                editor.store_bookmarks_at(&ptr, doc);
+                // Redraw the rows that are no longer covered
+                editor.render_document(lua)?;
+                if listings.is_empty() {
+                    return Ok(Some(PickerKey::Done));
+                }
+                *selected = (*selected).min(listings.len() - 1);
+                Ok(Some(PickerKey::Stay))
+            },
+            |editor, mut listings, selected| {
+                let Listing { ptr, doc, y, .. } = listings.remove(selected);
+                editor.focus_document(&ptr, doc);
+                if let Some(doc) = editor.try_doc_mut() {
+                    doc.move_to(&Loc { x: 0, y });
+                }
+                Ok(())
+            },
+        )
+    }
+}
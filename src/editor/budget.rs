@@ -0,0 +1,329 @@
+/// Budgeting rendering, so that panes which haven't changed aren't drawn again
+use crate::config;
+use crate::config::SyntaxHighlighting as SH;
+use crate::error::Result;
+use kaolinite::utils::{Loc, Size};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::ops::Range;
+use std::time::{Duration, Instant};
+
+use super::Editor;
+
+/// What to do with a pane in a frame
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PaneAction {
+    /// Its rows from the last frame are still correct
+    Skip,
+    Draw,
+    /// Show its rows from the last frame for now, drawing it again once idle
+    Defer,
+}
+
+/// Decide what to do with a pane, given whether it has changed since it was last drawn,
+/// how long the frame has taken so far and how long it is allowed to take
+pub fn plan(
+    dirty: bool,
+    focused: bool,
+    elapsed: Duration,
+    budget: Option<Duration>,
+    can_defer: bool,
+) -> PaneAction {
+    let over_budget = budget.is_some_and(|budget| elapsed >= budget);
+    match (dirty, focused) {
+        (false, _) => PaneAction::Skip,
+        // The pane being typed in is always drawn, so the cursor never lags behind
+        (true, true) => PaneAction::Draw,
+        (true, false) if over_budget && can_defer => PaneAction::Defer,
+        (true, false) => PaneAction::Draw,
+    }
+}
+
+/// What is known about a pane from the frames it has been drawn in
+#[derive(Debug, Default)]
+struct Pane {
+    /// A summary of everything the pane's contents depend on
+    fingerprint: u64,
+    /// Goes up whenever the fingerprint changes
+    generation: u64,
+    /// The generation that was last drawn
+    drawn: Option<u64>,
+    /// The rows that were last drawn (by screen row)
+    rows: HashMap<usize, String>,
+    /// Where on screen the rows were drawn (rows and columns)
+    area: Option<(Range<usize>, Range<usize>)>,
+}
+
+/// Keeps track of the content generation of each pane and the rows drawn for it
+#[derive(Debug, Default)]
+pub struct RenderBudget {
+    panes: HashMap<Vec<usize>, Pane>,
+    /// Whether any pane was put off, and needs drawing once the editor is idle
+    pub deferred: bool,
+    /// Set when idle, so the next frame draws everything that was put off
+    pub catch_up: bool,
+}
+
+impl RenderBudget {
+    /// Note the fingerprint of a pane, moving its content generation on if it has changed
+    pub fn observe(&mut self, ptr: &[usize], fingerprint: u64) {
+        let pane = self.panes.entry(ptr.to_vec()).or_default();
+        if pane.drawn.is_none() || pane.fingerprint != fingerprint {
+            pane.fingerprint = fingerprint;
+            pane.generation += 1;
+        }
+    }
+
+    /// Whether a pane has changed since it was last drawn
+    pub fn is_dirty(&self, ptr: &[usize]) -> bool {
+        self.panes
+            .get(ptr)
+            .map_or(true, |pane| pane.drawn != Some(pane.generation))
+    }
+
+    /// Whether a pane has rows from an earlier frame that can be shown in its place
+    /// (which they can't be if the pane has since moved or changed size)
+    pub fn fits(&self, ptr: &[usize], rows: &Range<usize>, cols: &Range<usize>) -> bool {
+        self.panes.get(ptr).is_some_and(|pane| {
+            pane.area
+                .as_ref()
+                .is_some_and(|(r, c)| r == rows && c == cols)
+        })
+    }
+
+    /// Note that a pane has been drawn at its current generation, in a certain area
+    pub fn mark_drawn(&mut self, ptr: &[usize], rows: Range<usize>, cols: Range<usize>) {
+        if let Some(pane) = self.panes.get_mut(ptr) {
+            pane.drawn = Some(pane.generation);
+            pane.area = Some((rows, cols));
+        }
+    }
+
+    /// Keep a row drawn for a pane
+    pub fn store(&mut self, ptr: &[usize], y: usize, row: String) {
+        self.panes
+            .entry(ptr.to_vec())
+            .or_default()
+            .rows
+            .insert(y, row);
+    }
+
+    /// Find a row drawn for a pane in an earlier frame
+    pub fn row(&self, ptr: &[usize], y: usize) -> Option<&String> {
+        self.panes.get(ptr)?.rows.get(&y)
+    }
+
+    /// Forget the rows drawn for a pane, so they are drawn afresh
+    pub fn clear_rows(&mut self, ptr: &[usize]) {
+        if let Some(pane) = self.panes.get_mut(ptr) {
+            pane.rows.clear();
+        }
+    }
+
+    /// Forget everything, so that every pane is drawn in the next frame
+    pub fn forget(&mut self) {
+        self.panes.clear();
+        self.deferred = false;
+    }
+
+    /// Forget about panes that are no longer on screen
+    pub fn retain(&mut self, ptrs: &[Vec<usize>]) {
+        self.panes.retain(|ptr, _| ptrs.contains(ptr));
+    }
+}
+
+impl Editor {
+    /// Work out which lines in view in a pane have changed since the last save
+    pub fn refresh_line_changes(&mut self, ptr: &[usize]) {
+        if !config!(self.config, line_numbers).change_markers {
+            return;
+        }
+        if let Some(fc) = self.files.get_mut(ptr.to_vec()) {
+            let doc = &mut fc.doc;
+            // One line further, to know whether lines were removed below the last one
+            let start = doc.offset.y;
+            doc.refresh_changes(start..start + doc.size.h + 1);
+        }
+    }
+
+    /// Summarise everything the rows of a document pane depend on
+    /// (returns None for panes that aren't documents, which are always drawn)
+    pub fn pane_fingerprint(
+        &self,
+        ptr: &[usize],
+        rows: &Range<usize>,
+        cols: &Range<usize>,
+    ) -> Option<u64> {
+        let fc = self.files.get(ptr.to_vec())?;
+        let doc = &fc.doc;
+        let mut hasher = DefaultHasher::new();
+        // Where the pane is and what else is drawn in it
+        (rows, cols, self.ptr == ptr, self.push_down).hash(&mut hasher);
+        (self.status_rows(), self.zen_margin(ptr), self.text_width()).hash(&mut hasher);
+        self.greet.hash(&mut hasher);
+        if self.greet {
+            let greeting = &self.render_cache;
+            (&greeting.greeting_message, &greeting.greeting_entries).hash(&mut hasher);
+        }
+        self.files
+            .get_atom(ptr.to_vec())
+            .map(|(_, idx)| idx)
+            .hash(&mut hasher);
+        // The contents of the document
+        (
+            &doc.file_name,
+            doc.generation,
+            doc.len_lines(),
+            doc.tab_width,
+        )
+            .hash(&mut hasher);
+        (
+            doc.binary.is_some(),
+            fc.file_type.as_ref().map(|ft| &ft.name),
+            self.color_preview_on(fc),
+        )
+            .hash(&mut hasher);
+        // Where the view is and what is selected
+        let loc = |loc: Loc| (loc.x, loc.y);
+        (
+            loc(doc.offset),
+            loc(doc.cursor.loc),
+            loc(doc.cursor.selection_end),
+        )
+            .hash(&mut hasher);
+        (doc.size.w, doc.size.h).hash(&mut hasher);
+        doc.block_bounds().hash(&mut hasher);
+        for cursor in &doc.secondary_cursors {
+            loc(*cursor).hash(&mut hasher);
+        }
+        // What is marked in the gutter
+        for mark in &doc.marks {
+            (mark.y, mark.moved, mark.bookmark).hash(&mut hasher);
+        }
+        for sign in &fc.signs {
+            (sign.line, sign.severity, sign.stale).hash(&mut hasher);
+        }
+        for y in doc.offset.y..doc.offset.y + doc.size.h {
+            doc.line_change(y).hash(&mut hasher);
+        }
+        Some(hasher.finish())
+    }
+
+    /// How long a frame may take before unfocused panes are put off (None means no limit)
+    pub fn frame_budget(&self) -> Option<Duration> {
+        let ms = config!(self.config, terminal).frame_budget;
+        (ms > 0).then(|| Duration::from_millis(ms))
+    }
+
+    /// Draw the document rows of the panes that have changed, the focused pane first,
+    /// keeping them for `render_line` to piece together.
+    /// Unfocused panes are put off once the frame budget is used up, if `can_defer` allows
+    pub fn render_panes(&mut self, sh: &SH, can_defer: bool) -> Result<()> {
+        let started = Instant::now();
+        let budget = self.frame_budget();
+        let tab_line = config!(self.config, tab_line).enabled;
+        let can_defer = can_defer && !std::mem::take(&mut self.render_budget.catch_up);
+        let mut panes = self.render_cache.span.clone();
+        // Sorting is stable, so the other panes stay in order
+        panes.sort_by_key(|(ptr, _, _)| *ptr != self.ptr);
+        let ptrs: Vec<Vec<usize>> = panes.iter().map(|(ptr, _, _)| ptr.clone()).collect();
+        self.render_budget.retain(&ptrs);
+        self.render_budget.deferred = false;
+        for (ptr, rows, cols) in panes {
+            self.refresh_line_changes(&ptr);
+            let Some(fingerprint) = self.pane_fingerprint(&ptr, &rows, &cols) else {
+                continue;
+            };
+            self.render_budget.observe(&ptr, fingerprint);
+            let dirty = self.render_budget.is_dirty(&ptr);
+            // A pane needs rows drawn for where it is now to show in the meantime
+            let can_defer = can_defer && self.render_budget.fits(&ptr, &rows, &cols);
+            match plan(dirty, ptr == self.ptr, started.elapsed(), budget, can_defer) {
+                PaneAction::Skip => (),
+                PaneAction::Defer => self.render_budget.deferred = true,
+                PaneAction::Draw => {
+                    self.render_budget.clear_rows(&ptr);
+                    let size = Size {
+                        w: cols.end.saturating_sub(cols.start),
+                        h: rows.end.saturating_sub(rows.start),
+                    };
+                    for y in rows.clone() {
+                        // The tab line and status line are drawn every frame
+                        let status_line = self.status_rows() > 0;
+                        if (y == rows.start && tab_line)
+                            || (y == rows.end.saturating_sub(1) && status_line)
+                        {
+                            continue;
+                        }
+                        let rel_y = y.saturating_sub(rows.start).saturating_sub(self.push_down);
+                        let row = self.render_file(&ptr, rel_y, size, sh)?;
+                        self.render_budget.store(&ptr, y, row);
+                    }
+                    self.render_budget.mark_drawn(&ptr, rows, cols);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BUDGET: Option<Duration> = Some(Duration::from_millis(16));
+
+    #[test]
+    fn planning_panes() {
+        let ms = Duration::from_millis;
+        // Panes that haven't changed are left as they are
+        assert_eq!(plan(false, true, ms(0), BUDGET, true), PaneAction::Skip);
+        assert_eq!(plan(false, false, ms(50), BUDGET, true), PaneAction::Skip);
+        // Changed panes are drawn while there is time left
+        assert_eq!(plan(true, false, ms(0), BUDGET, true), PaneAction::Draw);
+        assert_eq!(plan(true, false, ms(15), BUDGET, true), PaneAction::Draw);
+        // Once it runs out, unfocused panes are put off
+        assert_eq!(plan(true, false, ms(16), BUDGET, true), PaneAction::Defer);
+        assert_eq!(plan(true, false, ms(50), BUDGET, true), PaneAction::Defer);
+        // The focused pane never is, nor are panes with nothing to show in the meantime
+        assert_eq!(plan(true, true, ms(50), BUDGET, true), PaneAction::Draw);
+        assert_eq!(plan(true, false, ms(50), BUDGET, false), PaneAction::Draw);
+        // Without a budget, nothing is put off
+        assert_eq!(plan(true, false, ms(500), None, true), PaneAction::Draw);
+    }
+
+    #[test]
+    fn tracking_panes() {
+        let mut budget = RenderBudget::default();
+        let (left, right) = (vec![0], vec![1]);
+        // Panes that have never been drawn need drawing
+        assert!(budget.is_dirty(&left));
+        budget.observe(&left, 1);
+        assert!(budget.is_dirty(&left));
+        assert!(!budget.fits(&left, &(0..10), &(0..40)));
+        budget.store(&left, 3, "row".to_string());
+        budget.mark_drawn(&left, 0..10, 0..40);
+        assert!(!budget.is_dirty(&left));
+        assert_eq!(budget.row(&left, 3).map(String::as_str), Some("row"));
+        // Rows can only stand in for a pane that hasn't moved
+        assert!(budget.fits(&left, &(0..10), &(0..40)));
+        assert!(!budget.fits(&left, &(0..10), &(0..41)));
+        // Panes only need drawing again once what they depend on changes
+        budget.observe(&left, 1);
+        assert!(!budget.is_dirty(&left));
+        budget.observe(&left, 2);
+        assert!(budget.is_dirty(&left));
+        budget.mark_drawn(&left, 0..10, 0..40);
+        assert!(!budget.is_dirty(&left));
+        // Panes that are no longer on screen are forgotten
+        budget.observe(&right, 1);
+        budget.mark_drawn(&right, 0..10, 40..80);
+        budget.retain(&[right.clone()]);
+        assert!(budget.row(&left, 3).is_none());
+        assert!(budget.is_dirty(&left));
+        assert!(!budget.is_dirty(&right));
+        budget.forget();
+        assert!(budget.is_dirty(&right));
+    }
+}
@@ -0,0 +1,281 @@
+/// Switching between open documents by number or by picking them from a list,
+/// and closing them all at once
+use crate::config;
+use crate::error::{OxError, Result};
+use crate::ui::Feedback;
+use crossterm::event::{KeyCode as KCode, KeyModifiers as KMod};
+use mlua::Lua;
+
+use super::picker::PickerKey;
+use super::Editor;
+
+impl Editor {
+    /// Switch to the nth document (counting from 1) in the current split,
+    /// or the last one if there are fewer than that open
+    pub fn go_to_tab(&mut self, n: usize) {
+        self.switch_to(n.saturating_sub(1));
+    }
+
+    /// Switch to a document in the current split (counting from 0, or the last one if there
+    /// are fewer than that open), fitting its view to the screen as it is now
+    /// and making sure what comes into view is loaded and highlighted
+    pub fn switch_to(&mut self, idx: usize) {
+        let ptr = self.ptr.clone();
+        let Some((fcs, current)) = self.files.get_atom_mut(ptr.clone()) else {
+            return;
+        };
+        let idx = idx.min(fcs.len().saturating_sub(1));
+        *current = idx;
+        // The screen may have changed size since the document was last shown
+        let size = self
+            .files
+            .update_doc_sizes(&self.render_cache.span, self)
+            .into_iter()
+            .find(|(at, doc, _)| at == &ptr && *doc == idx)
+            .map(|(_, _, size)| size);
+        let margin = config!(self.config, document).horizontal_margin;
+        let separators = config!(self.config, document).word_separators.clone();
+        if let Ok(fc) = self.file_at_mut(&ptr, idx) {
+            if let Some(size) = size {
+                fc.doc.size = size;
+            }
+            fc.doc.horizontal_margin = margin;
+            fc.doc.word_separators = separators;
+            fc.doc.bring_cursor_in_viewport();
+        }
+        self.update_highlighter_for(&ptr, idx);
+        self.update_cwd();
+        self.needs_rerender = true;
+    }
+
+    /// Move focus to a document in any split
+    pub fn focus_document(&mut self, ptr: &[usize], doc: usize) {
+        self.cache_old_ptr(&ptr.to_vec());
+        self.ptr = ptr.to_vec();
+        self.switch_to(doc);
+    }
+
+    /// Describe each open document for the buffer list (modified marker and path)
+    fn buffer_items(&self, docs: &[(Vec<usize>, usize)]) -> Vec<String> {
+        docs.iter()
+            .filter_map(|(ptr, doc)| self.file_at(ptr, *doc).ok())
+            .map(|fc| {
+                let modified = if fc.unsaved_changes() { "[+]" } else { "   " };
+                let name = fc
+                    .doc
+                    .file_name
+                    .clone()
+                    .unwrap_or_else(|| fc.placeholder_name());
+                format!("{modified} {name}")
+            })
+            .collect()
+    }
+
+    /// Open a picker listing every open document, where enter switches to the selected one
+    /// and d closes it (asking first if it has unsaved changes)
+    pub fn buffers(&mut self, lua: &Lua) -> Result<()> {
+        let docs = self.files.all_documents(vec![]);
+        if docs.is_empty() {
+            self.needs_rerender = true;
+            return Ok(());
+        }
+        let selected = docs
+            .iter()
+            .position(|(ptr, doc)| *ptr == self.ptr && self.is_visible(ptr, *doc))
+            .unwrap_or(0);
+        let hint = "Open documents: enter to switch, d to close, esc to cancel";
+        self.pick(
+            lua,
+            docs,
+            selected,
+            |editor, docs, selected| {
+                let items = editor.buffer_items(docs);
+                editor.render_list(&items, *selected, hint)?;
+                Ok(items.len())
+            },
+            |editor, lua, docs, key, selected| {
+                if key != (KMod::NONE, KCode::Char('d')) {
+                    return Ok(None);
+                }
+                // Close the selected document, just as quitting it would
+                let (ptr, doc) = docs[*selected].clone();
+                editor.focus_document(&ptr, doc);
+                editor.quit(lua)?;
+                if !editor.active {
+                    return Ok(Some(PickerKey::Done));
+                }
+                // What was underneath the list may have changed
+                editor.terminal.invalidate();
+                editor.render_document(lua)?;
+                *docs = editor.files.all_documents(vec![]);
+                if docs.is_empty() {
+                    return Ok(Some(PickerKey::Done));
+                }
+                *selected = (*selected).min(docs.len() - 1);
+                Ok(Some(PickerKey::Stay))
+            },
+            |editor, docs, selected| {
+                // Switch to the selected document
+                let (ptr, doc) = docs[selected].clone();
+                editor.focus_document(&ptr, doc);
+                Ok(())
+            },
+        )
+    }
+
+    /// Every open document with unsaved changes (counting documents shown in several panes once)
+    fn unsaved_documents(&self) -> Vec<(Vec<usize>, usize)> {
+        let mut result: Vec<(Vec<usize>, usize)> = vec![];
+        for at in self.files.all_documents(vec![]) {
+            let unsaved = self
+                .file_at(&at.0, at.1)
+                .is_ok_and(super::FileContainer::unsaved_changes);
+            if unsaved && !self.views_of(&at).iter().any(|view| result.contains(view)) {
+                result.push(at);
+            }
+        }
+        result
+    }
+
+    /// Quit the editor, closing every document at once. If any have unsaved changes,
+    /// they are listed so they can all be saved, or all thrown away, before quitting
+    pub fn quit_all(&mut self, lua: &Lua) -> Result<()> {
+        let mut unsaved = self.unsaved_documents();
+        // Git waits on the messages it opens, so it needs telling whether to carry on
+        let mut aborted = false;
+        for at in unsaved.clone() {
+            if self.git_operation(&at.0, at.1).is_some() {
+                aborted |= self.settle_git_message(lua, &at)?;
+            }
+        }
+        unsaved.retain(|(ptr, doc)| self.git_operation(ptr, *doc).is_none());
+        let result = self.quit_unsaved(lua, &unsaved);
+        // Git is only told to abort if ox really does quit
+        if aborted && !self.active {
+            self.exit_code = 1;
+        }
+        result
+    }
+
+    /// Quit the editor, listing the documents that are unsaved (if there are any)
+    fn quit_unsaved(&mut self, lua: &Lua, unsaved: &[(Vec<usize>, usize)]) -> Result<()> {
+        if unsaved.is_empty() {
+            self.close_all();
+            return Ok(());
+        }
+        let items = self.buffer_items(unsaved);
+        let plural = if unsaved.len() == 1 { "" } else { "s" };
+        let hint = format!(
+            "{} unsaved document{plural}: s to save all and quit, q to quit without saving, enter to go to one, esc to cancel",
+            unsaved.len()
+        );
+        self.pick(
+            lua,
+            (),
+            0,
+            |editor, (), selected| {
+                editor.render_list(&items, *selected, &hint)?;
+                Ok(items.len())
+            },
+            |editor, lua, (), key, _| match key {
+                (KMod::NONE, KCode::Char('s')) => {
+                    editor.save_all_and_quit(lua, unsaved)?;
+                    Ok(Some(PickerKey::Done))
+                }
+                (KMod::NONE, KCode::Char('q')) => {
+                    // The changes are being thrown away, so there is nothing to recover
+                    for (ptr, doc) in unsaved {
+                        editor.remove_swap_at(ptr, *doc);
+                    }
+                    editor.close_all();
+                    Ok(Some(PickerKey::Done))
+                }
+                _ => Ok(None),
+            },
+            |editor, (), selected| {
+                // Go to the selected document instead of quitting
+                let (ptr, doc) = &unsaved[selected];
+                editor.focus_document(ptr, *doc);
+                Ok(())
+            },
+        )
+    }
+
+    /// Save each of the documents given before quitting. Documents that can't be saved
+    /// where they are (because they have no name, for instance) are saved as something else,
+    /// and quitting stops at the first document that still isn't saved
+    fn save_all_and_quit(&mut self, lua: &Lua, unsaved: &[(Vec<usize>, usize)]) -> Result<()> {
+        for (ptr, doc) in unsaved {
+            self.focus_document(ptr, *doc);
+            self.render(lua)?;
+            match self.save() {
+                Ok(()) => (),
+                Err(OxError::Cancelled) => return Err(OxError::Cancelled),
+                Err(_) => self.save_as()?,
+            }
+            let saved = self
+                .file_at(ptr, *doc)
+                .is_ok_and(|file| !file.unsaved_changes());
+            if !saved {
+                self.feedback = Feedback::Warning(format!(
+                    "{} wasn't saved, so ox is still open",
+                    self.describe_doc(&(ptr.clone(), *doc))
+                ));
+                return Ok(());
+            }
+        }
+        self.close_all();
+        Ok(())
+    }
+
+    /// The git operation waiting on a document, if it is a commit message or a rebase list
+    pub fn git_operation(&self, ptr: &[usize], doc: usize) -> Option<&'static str> {
+        let fc = self.file_at(ptr, doc).ok()?;
+        match fc.file_type.as_ref()?.name.as_str() {
+            "Git Commit" => Some("commit"),
+            "Git Rebase" => Some("rebase"),
+            _ => None,
+        }
+    }
+
+    /// Ask whether an unsaved commit message (or rebase list) should be saved so git carries on,
+    /// or thrown away to abort the commit (returning true if it is to be aborted)
+    pub fn settle_git_message(&mut self, lua: &Lua, at: &(Vec<usize>, usize)) -> Result<bool> {
+        let operation = self.git_operation(&at.0, at.1).unwrap_or("commit");
+        self.focus_document(&at.0, at.1);
+        self.render(lua)?;
+        let msg = format!("{} isn't saved:", self.describe_doc(at));
+        let abort = format!("abort the {operation}");
+        match self.choice(&msg, &[&abort, "save and continue"])? {
+            Some(0) => {
+                // The changes are being thrown away, so there is nothing to recover
+                self.remove_swap_at(&at.0, at.1);
+                Ok(true)
+            }
+            Some(_) => {
+                self.save()?;
+                Ok(false)
+            }
+            None => {
+                self.needs_rerender = true;
+                Err(OxError::Cancelled)
+            }
+        }
+    }
+
+    /// Close every document and quit the editor,
+    /// remembering what is needed to open the saved ones again later
+    fn close_all(&mut self) {
+        for (ptr, doc) in self.files.all_documents(vec![]) {
+            let saved = self
+                .file_at(&ptr, doc)
+                .is_ok_and(|file| !file.unsaved_changes());
+            if saved {
+                self.store_history_at(&ptr, doc);
+                self.remove_swap_at(&ptr, doc);
+            }
+            self.note_closed(&ptr, doc);
+        }
+        self.active = false;
+    }
+}
@@ -0,0 +1,379 @@
+/// Running a build command and listing its output, along with the places in files
+/// that the output points to (which can be opened, or stepped through one by one)
+use crate::config::RunCommand;
+use crate::error::{OxError, Result};
+use crate::events::get_event;
+use crate::ui::{key_event, Feedback};
+use crossterm::event::{KeyCode as KCode, KeyModifiers as KMod};
+use kaolinite::signs::{Severity, Sign};
+use kaolinite::utils::get_cwd;
+use mlua::Lua;
+#[cfg(not(target_os = "windows"))]
+use nix::errno::Errno;
+#[cfg(not(target_os = "windows"))]
+use nix::sys::signal::{killpg, Signal};
+#[cfg(not(target_os = "windows"))]
+use nix::unistd::Pid;
+use regex::Regex;
+use std::collections::HashMap;
+use std::io::{BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::mpsc::{channel, Receiver, Sender, TryRecvError};
+use std::sync::OnceLock;
+
+use super::Editor;
+
+/// The name of the scratch buffer the output of a build is shown in
+pub const BUILD_NAME: &str = "build";
+/// Shown in turn on the feedback line while a build is running
+const SPINNER: [char; 10] = ['⠋', '⠙', '⠹', '⠸', '⠼', '⠴', '⠦', '⠧', '⠇', '⠏'];
+
+/// Locations as given by python tracebacks (`File "app.py", line 12, in main`)
+static PYTHON_LOCATION: OnceLock<Regex> = OnceLock::new();
+/// Locations as given by most compilers (`src/main.rs:12:5` or `main.c:12: error`)
+static LOCATION: OnceLock<Regex> = OnceLock::new();
+
+/// A place in a file that a line of build output points to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Location {
+    pub path: String,
+    /// The line and column (counting from 1), where not every tool gives a column
+    pub line: usize,
+    pub column: Option<usize>,
+}
+
+/// Find the location a line of build output points to (if it points to one)
+pub fn parse_location(line: &str) -> Option<Location> {
+    let python = PYTHON_LOCATION
+        .get_or_init(|| Regex::new(r#"File "(?P<path>[^"]+)", line (?P<line>\d+)"#).unwrap());
+    let general = LOCATION.get_or_init(|| {
+        Regex::new(
+            r#"(?:^|[\s(\[])(?P<path>(?:[A-Za-z]:)?[^\s:"'()\[\]]*[./\\][^\s:"'()\[\]]*):(?P<line>\d+)(?::(?P<column>\d+))?"#,
+        )
+        .unwrap()
+    });
+    let caps = python.captures(line).or_else(|| general.captures(line))?;
+    Some(Location {
+        path: caps["path"].to_string(),
+        line: caps["line"].parse().ok()?,
+        column: caps.name("column").and_then(|c| c.as_str().parse().ok()),
+    })
+}
+
+/// Work out whether a line of build output reports an error or a warning
+fn severity_of(line: &str) -> Option<Severity> {
+    let line = line.to_lowercase();
+    if line.contains("error") {
+        Some(Severity::Error)
+    } else if line.contains("warning") {
+        Some(Severity::Warning)
+    } else {
+        None
+    }
+}
+
+/// The locations from the last build, which can be stepped through
+#[derive(Debug, Default)]
+pub struct BuildResults {
+    /// Each location, along with the message it was reported with
+    pub locations: Vec<(Location, Sign)>,
+    /// Which location was last moved to
+    pub current: Option<usize>,
+    /// The directory the build ran in (which relative paths are taken from)
+    pub root: PathBuf,
+}
+
+impl BuildResults {
+    /// Find the locations in the output of a build that point to files that exist,
+    /// where each is marked by a sign on the line of output it was found on
+    /// (with the message of the error or warning it belongs to)
+    fn find(root: PathBuf, output: &[String], first_line: usize) -> Self {
+        let mut locations = vec![];
+        // The error or warning being reported (tools leave a blank line after each one)
+        let mut reported: Option<(Severity, String)> = None;
+        for (y, line) in output.iter().enumerate() {
+            if line.trim().is_empty() {
+                reported = None;
+            } else if let Some(severity) = severity_of(line) {
+                reported = Some((severity, line.trim().to_string()));
+            }
+            let location = parse_location(line).filter(|l| root.join(&l.path).is_file());
+            let Some(location) = location else {
+                continue;
+            };
+            let (severity, message) = reported
+                .clone()
+                .unwrap_or((Severity::Info, line.trim().to_string()));
+            let sign = Sign {
+                file: String::new(),
+                line: first_line + y,
+                severity,
+                message,
+                stale: false,
+            };
+            locations.push((location, sign));
+        }
+        Self {
+            locations,
+            current: None,
+            root,
+        }
+    }
+}
+
+/// Set up a command to be run by the shell
+fn shell_command(command: &str) -> Command {
+    if cfg!(target_os = "windows") {
+        let mut cmd = Command::new("cmd");
+        cmd.arg("/C").arg(command);
+        cmd
+    } else {
+        let mut cmd = Command::new("sh");
+        cmd.arg("-c").arg(command);
+        cmd
+    }
+}
+
+/// Send each line read from a stream of output back as it is read
+fn read_lines(stream: impl Read + Send + 'static, tx: Sender<String>) {
+    std::thread::spawn(move || {
+        for line in BufReader::new(stream).lines() {
+            let Ok(line) = line else {
+                return;
+            };
+            if tx.send(line).is_err() {
+                return;
+            }
+        }
+    });
+}
+
+/// A build command running in the background, away from the terminal
+/// (its output is captured rather than drawn over the editor)
+struct BuildProcess {
+    child: Child,
+    rx: Receiver<String>,
+}
+
+impl BuildProcess {
+    /// Start running a command in a directory
+    fn start(command: &str, root: &Path) -> Result<Self> {
+        let mut cmd = shell_command(command);
+        cmd.current_dir(root)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped());
+        // Its own process group lets anything it starts be stopped along with it
+        #[cfg(not(target_os = "windows"))]
+        std::os::unix::process::CommandExt::process_group(&mut cmd, 0);
+        let mut child = cmd.spawn()?;
+        let (tx, rx) = channel();
+        if let Some(stdout) = child.stdout.take() {
+            read_lines(stdout, tx.clone());
+        }
+        if let Some(stderr) = child.stderr.take() {
+            read_lines(stderr, tx);
+        }
+        Ok(Self { child, rx })
+    }
+
+    /// Take the lines of output written since this was last called,
+    /// returning true once the command has finished writing them
+    fn poll(&mut self, output: &mut Vec<String>) -> bool {
+        loop {
+            match self.rx.try_recv() {
+                Ok(line) => output.push(line),
+                Err(TryRecvError::Empty) => return false,
+                Err(TryRecvError::Disconnected) => return true,
+            }
+        }
+    }
+
+    /// Stop the command, along with anything it started
+    fn stop(&mut self) -> Result<()> {
+        #[cfg(not(target_os = "windows"))]
+        if let Ok(pid) = i32::try_from(self.child.id()) {
+            // A group with nothing left in it has stopped already
+            match killpg(Pid::from_raw(pid), Signal::SIGTERM) {
+                Ok(()) | Err(Errno::ESRCH) => (),
+                Err(err) => return Err(std::io::Error::from(err).into()),
+            }
+        }
+        self.child.kill()?;
+        Ok(())
+    }
+}
+
+impl Editor {
+    /// The build command given for the file type of the document being edited
+    fn build_command(&self, lua: &Lua) -> Option<String> {
+        let kind = self.files.get(self.ptr.clone())?.file_type.clone()?.name;
+        let runner = lua
+            .globals()
+            .get::<HashMap<String, RunCommand>>("runner")
+            .ok()?;
+        let RunCommand { compile, run } = runner.get(&kind)?;
+        let command = compile.as_ref().or(run.as_ref())?;
+        let path = self.try_doc()?.file_name.clone().unwrap_or_default();
+        Some(command.replace("{file_path}", &path))
+    }
+
+    /// Run a build command (or the one for the file type of the document being edited),
+    /// then list its output in a read only buffer where the locations it mentions are marked.
+    /// Ctrl + C stops the command while it runs
+    pub fn build(&mut self, lua: &Lua, command: Option<&str>) -> Result<()> {
+        self.needs_terminal()?;
+        let command = match command.filter(|c| !c.trim().is_empty()) {
+            Some(command) => command.to_string(),
+            None => {
+                let Some(command) = self.build_command(lua) else {
+                    self.feedback = Feedback::Error(
+                        "There is no build command for this file type, give one to run it"
+                            .to_string(),
+                    );
+                    return Ok(());
+                };
+                command
+            }
+        };
+        let root = PathBuf::from(get_cwd().unwrap_or(".".to_string()));
+        let mut process = BuildProcess::start(&command, &root)?;
+        let mut output: Vec<String> = vec![];
+        let mut frame = 0;
+        let mut stopped = false;
+        let mut stop_failed = None;
+        self.terminal.hide_cursor();
+        while !process.poll(&mut output) {
+            let spinner = SPINNER[frame % SPINNER.len()];
+            frame += 1;
+            self.feedback = Feedback::Info(format!(
+                "{spinner} Running {command}: {} lines of output, ctrl + c to stop",
+                output.len()
+            ));
+            let size = self.terminal.size()?;
+            self.render_feedback_line(size.w, size.h)?;
+            self.terminal.flush()?;
+            let Some(event) = get_event(self) else {
+                continue;
+            };
+            self.redraw_after_resize(lua, &event)?;
+            if let Some((KMod::CONTROL, KCode::Char('c'))) = key_event(&event, &mut self.macro_man)
+            {
+                stop_failed = process.stop().err();
+                stopped = true;
+                break;
+            }
+        }
+        self.terminal.show_cursor();
+        self.needs_rerender = true;
+        // Anything started by the command may still be writing, so what it wrote so far is kept
+        process.poll(&mut output);
+        let status = process.child.wait().ok().filter(|_| !stopped);
+        self.show_build_output(&command, root, &output, status)?;
+        if let Some(err) = stop_failed {
+            self.feedback = Feedback::Error(format!("Failed to stop {command}: {err}"));
+        }
+        Ok(())
+    }
+
+    /// List the output of a build in a read only buffer
+    fn show_build_output(
+        &mut self,
+        command: &str,
+        root: PathBuf,
+        output: &[String],
+        status: Option<ExitStatus>,
+    ) -> Result<()> {
+        let outcome = match status {
+            None => "stopped".to_string(),
+            Some(status) if status.success() => "finished".to_string(),
+            Some(status) => match status.code() {
+                Some(code) => format!("failed with exit code {code}"),
+                None => "failed".to_string(),
+            },
+        };
+        let results = BuildResults::find(root, output, 1);
+        let found = results.locations.len();
+        let text = format!("$ {command}\n{}\n[{outcome}]\n", output.join("\n"));
+        let signs = results.locations.iter().map(|(_, s)| s.clone()).collect();
+        self.build = results;
+        self.open_from_string(text, None)?;
+        self.next();
+        if let Some(file) = self.files.get_mut(self.ptr.clone()) {
+            file.doc.info.read_only = true;
+            file.scratch = Some(BUILD_NAME.to_string());
+            file.signs = signs;
+        }
+        self.feedback = match found {
+            0 => Feedback::Info(format!("{command} {outcome}")),
+            1 => Feedback::Info(format!("{command} {outcome}, 1 location found")),
+            n => Feedback::Info(format!("{command} {outcome}, {n} locations found")),
+        };
+        Ok(())
+    }
+
+    /// Whether the document being edited is the output of a build
+    pub fn showing_build(&self) -> bool {
+        self.files
+            .get(self.ptr.clone())
+            .is_some_and(|file| file.scratch.as_deref() == Some(BUILD_NAME))
+    }
+
+    /// Open the location on the cursor's line of the build output
+    pub fn open_build_location(&mut self) -> Result<()> {
+        let Some(doc) = self.try_doc() else {
+            return Ok(());
+        };
+        let Some(location) = doc.line(doc.loc().y).and_then(|l| parse_location(&l)) else {
+            return Ok(());
+        };
+        self.build.current = self
+            .build
+            .locations
+            .iter()
+            .position(|(l, _)| *l == location);
+        self.go_to_location(&location)
+    }
+
+    /// Move to the next (or previous) location in the output of the last build, wrapping around
+    pub fn next_build_location(&mut self, forward: bool) -> Result<()> {
+        let total = self.build.locations.len();
+        if total == 0 {
+            self.feedback = Feedback::Info("There are no build locations to go to".to_string());
+            return Ok(());
+        }
+        let current = match (self.build.current, forward) {
+            (None, true) => 0,
+            (None, false) => total - 1,
+            (Some(n), true) => (n + 1) % total,
+            (Some(n), false) => (n + total - 1) % total,
+        };
+        self.build.current = Some(current);
+        let (location, sign) = self.build.locations[current].clone();
+        self.go_to_location(&location)?;
+        let msg = format!("{}/{total}: {}", current + 1, sign.message);
+        self.feedback = match sign.severity {
+            Severity::Error => Feedback::Error(msg),
+            Severity::Warning => Feedback::Warning(msg),
+            Severity::Info | Severity::Hint => Feedback::Info(msg),
+        };
+        Ok(())
+    }
+
+    /// Open a file from the build output, moving to the location in it
+    fn go_to_location(&mut self, location: &Location) -> Result<()> {
+        let path = self.build.root.join(&location.path);
+        // Files that are already open are moved to instead
+        match self.open(&path.to_string_lossy()) {
+            Ok(()) => self.next(),
+            Err(OxError::AlreadyOpen { .. }) => (),
+            Err(err) => return Err(err),
+        }
+        self.update_cwd();
+        if let Some((_, idx)) = self.files.get_atom(self.ptr.clone()) {
+            self.go_to_position(idx, location.line, location.column);
+        }
+        Ok(())
+    }
+}
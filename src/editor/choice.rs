@@ -0,0 +1,156 @@
+/// Menus that ask a question and offer a choice of answers,
+/// which every confirmation and "pick one of these" dialog is built on
+use crate::config;
+use crate::error::Result;
+use crate::events::wait_for_event_hog;
+use crate::ui::{key_event, Feedback};
+use crossterm::event::{KeyCode as KCode, KeyModifiers as KMod};
+use kaolinite::utils::width;
+
+use super::overlay::ListMouse;
+use super::Editor;
+
+/// Work out the letter that picks each option straight away:
+/// the first letter of the option that no earlier option has taken
+fn hotkeys(options: &[&str]) -> Vec<Option<char>> {
+    let mut taken: Vec<char> = vec![];
+    options
+        .iter()
+        .map(|option| {
+            let key = option
+                .chars()
+                .map(|c| c.to_ascii_lowercase())
+                .find(|c| c.is_ascii_alphabetic() && !taken.contains(c));
+            taken.extend(key);
+            key
+        })
+        .collect()
+}
+
+/// How an option is shown: its number, then its label with the hotkey in brackets
+fn label(n: usize, option: &str, hotkey: Option<char>) -> String {
+    let at = hotkey.and_then(|key| option.find(|c: char| c.to_ascii_lowercase() == key));
+    match at {
+        Some(at) => {
+            let (before, rest) = option.split_at(at);
+            let mut rest = rest.chars();
+            let key = rest.next().unwrap_or_default();
+            format!("{} {before}[{key}]{}", n + 1, rest.as_str())
+        }
+        None => format!("{} {option}", n + 1),
+    }
+}
+
+impl Editor {
+    /// Confirmation dialog
+    pub fn confirm(&mut self, msg: &str) -> Result<bool> {
+        self.confirm_with(msg, (KMod::CONTROL, KCode::Char('q')))
+    }
+
+    /// Confirmation dialog, where a specific key confirms and escape cancels
+    pub fn confirm_with(&mut self, msg: &str, key: (KMod, KCode)) -> Result<bool> {
+        Ok(self.choice_keyed(msg, &[], &[(key, 0)])?.is_some())
+    }
+
+    /// Choice dialog (returns the index of the option that was picked).
+    /// Options are picked with the arrow keys and enter, by their number or by their hotkey,
+    /// and escape cancels
+    pub fn choice(&mut self, msg: &str, options: &[&str]) -> Result<Option<usize>> {
+        self.choice_keyed(msg, options, &[])
+    }
+
+    /// Choice dialog, where certain keys pick an option (given by its index) straight away
+    fn choice_keyed(
+        &mut self,
+        msg: &str,
+        options: &[&str],
+        keys: &[((KMod, KCode), usize)],
+    ) -> Result<Option<usize>> {
+        self.needs_terminal()?;
+        let hotkeys = hotkeys(options);
+        let labels: Vec<String> = options
+            .iter()
+            .zip(&hotkeys)
+            .enumerate()
+            .map(|(n, (option, hotkey))| label(n, option, *hotkey))
+            .collect();
+        let tab_width = config!(self.config, document).tab_width;
+        let mut selected = 0;
+        let mut overlaid = false;
+        // Enter into the choice menu
+        self.terminal.hide_cursor();
+        let result = loop {
+            let size = self.terminal.size()?;
+            // Options go on the feedback line, unless there are too many to fit there
+            let listing = labels
+                .iter()
+                .enumerate()
+                .map(|(n, label)| {
+                    if n == selected {
+                        format!(">{label}<")
+                    } else {
+                        format!(" {label} ")
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join(" ");
+            let line = format!("{msg}  {listing}");
+            if width(&line, tab_width) <= size.w {
+                self.feedback = Feedback::Warning(line);
+                self.render_feedback_line(size.w, size.h)?;
+                self.terminal.flush()?;
+            } else {
+                overlaid = true;
+                self.render_list(&labels, selected, msg)?;
+            }
+            // Handle events (a resize is tidied up while waiting, and is redrawn next time round)
+            let event = wait_for_event_hog(self);
+            if overlaid {
+                match self.list_mouse(&event, selected, options.len()) {
+                    ListMouse::Activate(item) => break Some(item),
+                    ListMouse::Select(item) => selected = item,
+                    ListMouse::Dismiss => break None,
+                    ListMouse::Ignore => (),
+                }
+            }
+            let Some(pressed) = key_event(&event, &mut self.macro_man) else {
+                continue;
+            };
+            if let Some((_, picked)) = keys.iter().find(|(key, _)| *key == pressed) {
+                break Some(*picked);
+            }
+            match pressed {
+                // Cancel when escape key is pressed
+                (KMod::NONE, KCode::Esc) => break None,
+                (KMod::NONE, KCode::Enter) if !options.is_empty() => break Some(selected),
+                // Move between the options
+                (KMod::NONE, KCode::Up | KCode::Left) | (KMod::SHIFT, KCode::BackTab) => {
+                    selected = selected.saturating_sub(1);
+                }
+                (KMod::NONE, KCode::Down | KCode::Right | KCode::Tab) => {
+                    if selected + 1 < options.len() {
+                        selected += 1;
+                    }
+                }
+                // Pick an option by its number or its hotkey
+                (KMod::NONE | KMod::SHIFT, KCode::Char(c)) => {
+                    let numbered = c.to_digit(10).and_then(|n| usize::try_from(n).ok());
+                    if let Some(n) = numbered.filter(|n| (1..=options.len()).contains(n)) {
+                        break Some(n - 1);
+                    }
+                    let c = c.to_ascii_lowercase();
+                    if let Some(n) = hotkeys.iter().position(|key| *key == Some(c)) {
+                        break Some(n);
+                    }
+                }
+                _ => (),
+            }
+        };
+        self.feedback = Feedback::None;
+        if overlaid {
+            self.needs_rerender = true;
+        }
+        self.terminal.show_cursor();
+        Ok(result)
+    }
+}
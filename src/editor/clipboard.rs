@@ -0,0 +1,86 @@
+/// Clipboard history: the snippets most recently copied or cut, any of which can be pasted again
+use crate::config;
+use crate::error::{OxError, Result};
+use mlua::Lua;
+use std::collections::VecDeque;
+
+use super::Editor;
+
+/// The most characters of a snippet shown in the history picker
+const PREVIEW_LENGTH: usize = 60;
+
+/// Snippets that have been copied or cut, newest first
+#[derive(Debug, Default)]
+pub struct ClipboardHistory {
+    pub entries: VecDeque<String>,
+}
+
+impl ClipboardHistory {
+    /// Remember a snippet, keeping no more than `limit` of them
+    /// (copying a snippet that is already remembered moves it to the front)
+    pub fn push(&mut self, text: &str, limit: usize) {
+        if text.is_empty() {
+            return;
+        }
+        self.entries.retain(|entry| entry != text);
+        self.entries.push_front(text.to_string());
+        self.entries.truncate(limit);
+    }
+}
+
+/// How a snippet is shown in the history picker: its first line (cut short if it is long)
+/// and how many lines follow it
+fn preview(text: &str) -> String {
+    let mut lines = text.lines();
+    let first = lines.next().unwrap_or_default();
+    let mut result: String = first.chars().take(PREVIEW_LENGTH).collect();
+    if first.chars().count() > PREVIEW_LENGTH {
+        result.push('…');
+    }
+    match lines.count() {
+        0 => result,
+        1 => format!("{result}  (+1 line)"),
+        more => format!("{result}  (+{more} lines)"),
+    }
+}
+
+impl Editor {
+    /// Add a snippet to the clipboard history
+    pub fn remember_copy(&mut self, text: &str) {
+        let limit = config!(self.config, terminal).clipboard_history;
+        self.clipboard.push(text, limit);
+    }
+
+    /// Paste the snippet most recently copied or cut, returning false if there isn't one
+    pub fn paste_latest(&mut self) -> Result<bool> {
+        let Some(text) = self.clipboard.entries.front().cloned() else {
+            return Ok(false);
+        };
+        self.paste_text(&text)?;
+        Ok(true)
+    }
+
+    /// Open a picker listing the clipboard history, where enter pastes the selected snippet
+    pub fn paste_from_history(&mut self, lua: &Lua) -> Result<()> {
+        if self.clipboard.entries.is_empty() {
+            return Err(OxError::Cancelled);
+        }
+        let items: Vec<String> = self.clipboard.entries.iter().map(|s| preview(s)).collect();
+        let hint = "Clipboard history: enter to paste, esc to cancel";
+        self.pick(
+            lua,
+            (),
+            0,
+            |editor, (), selected| {
+                editor.render_list(&items, *selected, hint)?;
+                Ok(items.len())
+            },
+            // Copies are only pasted from here, so there are no keys of its own
+            |_, _, (), _, _| Ok(None),
+            |editor, (), selected| {
+                let text = editor.clipboard.entries[selected].clone();
+                editor.paste_text(&text)
+            },
+        )
+    }
+}
@@ -0,0 +1,300 @@
+/// Completing the word being typed from the words in the open documents
+use crate::config;
+use crate::error::Result;
+use crate::Feedback;
+use crossterm::event::{Event as CEvent, KeyCode as KCode, KeyModifiers as KMod};
+use crossterm::style::{SetBackgroundColor as Bg, SetForegroundColor as Fg};
+use kaolinite::event::Event;
+use kaolinite::utils::{width, Loc};
+use std::collections::HashMap;
+
+use super::Editor;
+
+/// The most completions shown at once (the rest are scrolled to)
+const MAX_SHOWN: usize = 8;
+/// The most completions offered for a word
+const MAX_CANDIDATES: usize = 100;
+/// The widest the popup gets
+const MAX_WIDTH: usize = 40;
+
+/// Whether a character can be part of a word that is completed
+pub fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// The completions being offered for the word before the cursor
+#[derive(Debug, Clone)]
+pub struct Completion {
+    /// The pane the word is being typed in
+    ptr: Vec<usize>,
+    /// Where the word being completed starts
+    start: Loc,
+    /// What has been typed of the word so far
+    prefix: String,
+    /// The words that start with the prefix, most used first
+    candidates: Vec<String>,
+    selected: usize,
+}
+
+impl Editor {
+    /// Find the word before the cursor, along with where it starts
+    /// (there is none to complete while there are several cursors)
+    fn word_before_cursor(&self) -> Option<(Loc, String)> {
+        let doc = self.try_doc()?;
+        if !doc.secondary_cursors.is_empty() {
+            return None;
+        }
+        let here = doc.char_loc();
+        let before: Vec<char> = doc.line(here.y)?.chars().take(here.x).collect();
+        let len = before
+            .iter()
+            .rev()
+            .take_while(|c| is_word_char(**c))
+            .count();
+        let prefix: String = before[before.len() - len..].iter().collect();
+        Some((Loc::at(here.x - len, here.y), prefix))
+    }
+
+    /// Find the words in the open documents (and the keywords of the language, if enabled)
+    /// that start with a prefix, leaving out the word being typed
+    fn completion_candidates(&self, start: Loc, prefix: &str) -> Vec<String> {
+        let mut counts: HashMap<String, usize> = HashMap::new();
+        let current = self.files.get_atom(self.ptr.clone()).map(|(_, doc)| doc);
+        for (ptr, doc) in self.files.all_documents(vec![]) {
+            let Some(file) = self
+                .files
+                .get_atom(ptr.clone())
+                .and_then(|(fcs, _)| fcs.get(doc))
+            else {
+                continue;
+            };
+            let typing_here = ptr == self.ptr && Some(doc) == current;
+            for (y, line) in file.doc.lines.iter().enumerate() {
+                let mut x = 0;
+                for word in line.split(|c: char| !is_word_char(c)) {
+                    let typing = typing_here && y == start.y && x == start.x;
+                    if !typing && word.len() > prefix.len() && word.starts_with(prefix) {
+                        *counts.entry(word.to_string()).or_default() += 1;
+                    }
+                    x += word.chars().count() + 1;
+                }
+            }
+        }
+        if config!(self.config, document).autocomplete_keywords {
+            let language = self
+                .files
+                .get(self.ptr.clone())
+                .and_then(|file| file.file_type.as_ref())
+                .map(|t| t.name.clone())
+                .unwrap_or_default();
+            let syntax = config!(self.config, syntax);
+            for word in syntax.keywords.get(&language).into_iter().flatten() {
+                if word.len() > prefix.len() && word.starts_with(prefix) {
+                    counts.entry(word.clone()).or_default();
+                }
+            }
+        }
+        let mut candidates: Vec<(String, usize)> = counts.into_iter().collect();
+        candidates.sort_by(|(a, a_count), (b, b_count)| {
+            b_count
+                .cmp(a_count)
+                .then(a.len().cmp(&b.len()))
+                .then(a.cmp(b))
+        });
+        candidates.truncate(MAX_CANDIDATES);
+        candidates.into_iter().map(|(word, _)| word).collect()
+    }
+
+    /// Offer the completions for a word, closing the popup if there are none
+    fn offer_completions(&mut self, start: Loc, prefix: String) {
+        let candidates = if prefix.is_empty() {
+            vec![]
+        } else {
+            self.completion_candidates(start, &prefix)
+        };
+        if candidates.is_empty() {
+            self.close_completion();
+            return;
+        }
+        // The word that was picked stays picked if it is still on offer
+        let selected = self
+            .completion
+            .as_ref()
+            .and_then(|c| c.candidates.get(c.selected))
+            .and_then(|picked| candidates.iter().position(|c| c == picked))
+            .unwrap_or(0);
+        self.completion = Some(Completion {
+            ptr: self.ptr.clone(),
+            start,
+            prefix,
+            candidates,
+            selected,
+        });
+        self.needs_rerender = true;
+    }
+
+    /// Show the completions for the word before the cursor
+    pub fn complete(&mut self) {
+        let Some((start, prefix)) = self.word_before_cursor() else {
+            return;
+        };
+        if prefix.is_empty() {
+            self.feedback = Feedback::Info("There is no word before the cursor".to_string());
+            return;
+        }
+        self.offer_completions(start, prefix.clone());
+        if self.completion.is_none() {
+            self.feedback = Feedback::Info(format!("No completions for '{prefix}'"));
+        }
+    }
+
+    /// Close the popup (the rows it covered are drawn again on the next render)
+    pub fn close_completion(&mut self) {
+        if self.completion.take().is_some() {
+            self.needs_rerender = true;
+        }
+    }
+
+    /// Keep the popup in step with the word being typed after an event, opening it once
+    /// `document.autocomplete_after` characters of a word have been typed,
+    /// and closing it when the cursor leaves the word
+    pub fn update_completion(&mut self, event: &CEvent) {
+        let Some((start, prefix)) = self.word_before_cursor() else {
+            self.close_completion();
+            return;
+        };
+        let (typed, erased) = match event {
+            CEvent::Key(key) => (
+                matches!(key.code, KCode::Char(c) if is_word_char(c)),
+                key.code == KCode::Backspace,
+            ),
+            _ => (false, false),
+        };
+        let after = config!(self.config, document).autocomplete_after;
+        match &self.completion {
+            // Nothing has changed (as when the popup has just been opened)
+            Some(c) if c.ptr == self.ptr && c.start == start && c.prefix == prefix => (),
+            // The word has grown or shrunk
+            Some(c) if c.ptr == self.ptr && c.start == start && (typed || erased) => {
+                self.offer_completions(start, prefix);
+            }
+            Some(_) => self.close_completion(),
+            None if typed && after > 0 && prefix.chars().count() >= after => {
+                self.offer_completions(start, prefix);
+            }
+            None => (),
+        }
+    }
+
+    /// Handle a key while the popup is open, returning true if the popup has used it
+    pub fn completion_key(&mut self, modifiers: KMod, code: KCode) -> bool {
+        let Some(completion) = &mut self.completion else {
+            return false;
+        };
+        let len = completion.candidates.len();
+        match (modifiers, code) {
+            (KMod::NONE, KCode::Up) => completion.selected = (completion.selected + len - 1) % len,
+            (KMod::NONE, KCode::Down) => completion.selected = (completion.selected + 1) % len,
+            (KMod::NONE, KCode::Tab | KCode::Enter) => {
+                if let Err(err) = self.accept_completion() {
+                    self.feedback = Feedback::Error(err.to_string());
+                }
+            }
+            (KMod::NONE, KCode::Esc) => self.close_completion(),
+            _ => return false,
+        }
+        self.needs_rerender = true;
+        true
+    }
+
+    /// Insert the rest of the picked word
+    fn accept_completion(&mut self) -> Result<()> {
+        let Some(completion) = self.completion.take() else {
+            return Ok(());
+        };
+        let typed = completion.prefix.chars().count();
+        let suffix: String = completion.candidates[completion.selected]
+            .chars()
+            .skip(typed)
+            .collect();
+        let at = Loc::at(completion.start.x + typed, completion.start.y);
+        self.exe(Event::Insert(at, suffix))
+    }
+
+    /// Draw the popup under the start of the word (or above it when there is no room below)
+    pub fn render_completion(&mut self) -> Result<()> {
+        let Some(completion) = &self.completion else {
+            return Ok(());
+        };
+        let (Some(cursor), Some(doc)) = (self.cursor_position(), self.try_doc()) else {
+            return Ok(());
+        };
+        let Some((_, rows, _)) = self
+            .render_cache
+            .span
+            .iter()
+            .find(|(p, _, _)| p == &self.ptr)
+        else {
+            return Ok(());
+        };
+        let tab_width = config!(self.config, document).tab_width;
+        let size = self.terminal.size()?;
+        // Work out which side of the cursor has room for the popup
+        let top = rows.start + self.push_down;
+        let bottom = top + doc.size.h;
+        let below = bottom.saturating_sub(cursor.y + 1);
+        let above = cursor.y.saturating_sub(top);
+        let wanted = MAX_SHOWN.min(completion.candidates.len());
+        let (shown, first_row) = if below >= wanted || below >= above {
+            (wanted.min(below), cursor.y + 1)
+        } else {
+            (wanted.min(above), cursor.y - wanted.min(above))
+        };
+        if shown == 0 {
+            return Ok(());
+        }
+        // Size the popup to fit the longest word, keeping it on screen
+        let longest = completion
+            .candidates
+            .iter()
+            .map(|c| width(c, tab_width))
+            .max()
+            .unwrap_or(0);
+        let w = (longest + 2).min(MAX_WIDTH).min(size.w);
+        let x = cursor
+            .x
+            .saturating_sub(width(&completion.prefix, tab_width))
+            .min(size.w - w);
+        let colors = config!(self.config, colors);
+        let normal = (
+            Bg(colors.file_tree_bg.to_color()?),
+            Fg(colors.file_tree_fg.to_color()?),
+        );
+        let highlighted = (
+            Bg(colors.file_tree_selection_bg.to_color()?),
+            Fg(colors.file_tree_selection_fg.to_color()?),
+        );
+        drop(colors);
+        // Scroll so that the selected completion is always in view
+        let first = (completion.selected + 1).saturating_sub(shown);
+        let mut lines = vec![];
+        for row in 0..shown {
+            let idx = first + row;
+            let (bg, fg) = if idx == completion.selected {
+                highlighted
+            } else {
+                normal
+            };
+            let word: String = completion.candidates[idx]
+                .chars()
+                .take(w.saturating_sub(2))
+                .collect();
+            let padding = " ".repeat(w.saturating_sub(width(&word, tab_width) + 1));
+            lines.push((first_row + row, format!("{bg}{fg} {word}{padding}")));
+        }
+        for (y, line) in lines {
+            self.terminal.draw_over(x, y, &line);
+        }
+        Ok(())
+    }
+}
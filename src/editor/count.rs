@@ -0,0 +1,97 @@
+/// Counts: typing a number before a key so that what the key does is carried out that many
+/// times, as a single change to undo (see `handle_event` for where the key is repeated)
+use crate::Feedback;
+use kaolinite::utils::Loc;
+
+use super::Editor;
+
+/// Counts up to this are always carried out in full, larger ones are cut down to the size of
+/// the document (a key can't usefully be repeated more often than there are characters in it)
+const SMALL_COUNT: usize = 999;
+
+/// A count being typed in, ready for the key after it
+#[derive(Debug, Default)]
+pub struct Count {
+    /// The number typed in so far (None when there isn't one)
+    pub pending: Option<usize>,
+    /// Whether the last key pressed added to the count (rather than using it up)
+    pub typed: bool,
+}
+
+/// Everything a repeated key can affect, to tell when repeating it has stopped doing anything
+#[derive(Debug, PartialEq, Eq)]
+pub struct RepeatState {
+    ptr: Vec<usize>,
+    doc: Option<usize>,
+    cursor: Option<(Loc, Loc)>,
+    size: Option<(usize, usize)>,
+    documents: usize,
+}
+
+impl Editor {
+    /// Start typing in a count, the digits typed next make up the number
+    pub fn start_count(&mut self) {
+        self.count.pending = Some(self.count.pending.unwrap_or(0));
+        self.count.typed = true;
+        self.feedback = Feedback::Info("Type a count, then the key to repeat".to_string());
+        self.needs_rerender = true;
+    }
+
+    /// Add a digit to the end of the count for the next key
+    pub fn count_digit(&mut self, digit: usize) {
+        let count = self.count.pending.unwrap_or(0);
+        self.count.pending = Some(count.saturating_mul(10).saturating_add(digit.min(9)));
+        self.count.typed = true;
+        self.needs_rerender = true;
+    }
+
+    /// Forget the count that was being typed in
+    pub fn cancel_count(&mut self) {
+        if self.count.pending.take().is_some() {
+            self.feedback = Feedback::Info("Count cancelled".to_string());
+            self.needs_rerender = true;
+        }
+    }
+
+    /// How many times a key will really be repeated for a count, keeping absurd counts
+    /// within the bounds of the document being edited
+    pub fn count_limit(&self, count: usize) -> usize {
+        let chars = self.try_doc().map_or(0, |doc| doc.file.len_chars());
+        count.min(chars.max(SMALL_COUNT)).max(1)
+    }
+
+    /// Note what a key can change, to stop repeating it once it doesn't change anything
+    pub fn repeat_state(&self) -> RepeatState {
+        let doc = self.try_doc();
+        RepeatState {
+            ptr: self.ptr.clone(),
+            doc: self.files.get_atom(self.ptr.clone()).map(|(_, idx)| idx),
+            cursor: doc.map(|doc| (doc.cursor.loc, doc.cursor.selection_end)),
+            size: doc.map(|doc| (doc.file.len_chars(), doc.len_lines())),
+            documents: self.files.len(),
+        }
+    }
+
+    /// Start repeating a key, keeping the edits it makes to the current document together,
+    /// giving back which document that is so the batch can be closed again afterwards
+    pub fn begin_repeat(&mut self) -> Option<(Vec<usize>, usize)> {
+        let ptr = self.ptr.clone();
+        let (_, idx) = self.files.get_atom(ptr.clone())?;
+        self.file_at_mut(&ptr, idx).ok()?.doc.begin_batch();
+        Some((ptr, idx))
+    }
+
+    /// Finish repeating a key, closing the batch of edits (even if that document is no longer
+    /// the one being edited)
+    pub fn end_repeat(&mut self, batch: Option<(Vec<usize>, usize)>) {
+        if !self.count.typed {
+            self.count.pending = None;
+        }
+        if let Some((ptr, idx)) = batch {
+            if let Ok(fc) = self.file_at_mut(&ptr, idx) {
+                fc.doc.end_batch();
+            }
+        }
+        self.needs_rerender = true;
+    }
+}
@@ -1,7 +1,10 @@
+use crate::ui::Feedback;
 /// Functions for moving the cursor around
-use crate::{config, ged, handle_event, CEvent, Loc, Result};
+use crate::{config, ged, handle_event, CEvent, Result};
 use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
 use kaolinite::event::Status;
+use kaolinite::Document;
+use kaolinite::Loc;
 use mlua::{AnyUserData, Lua};
 
 use super::Editor;
@@ -47,6 +50,19 @@ impl Editor {
         }
     }
 
+    /// Select to the start of the line (going to the first character that isn't whitespace
+    /// first if smart home is enabled)
+    pub fn select_home(&mut self) {
+        let smart = config!(self.config, document).smart_home;
+        if let Some(doc) = self.try_doc_mut() {
+            if smart {
+                doc.select_smart_home();
+            } else {
+                doc.select_home();
+            }
+        }
+    }
+
     /// Select the whole document
     pub fn select_all(&mut self) {
         if let Some(doc) = self.try_doc_mut() {
@@ -95,6 +111,48 @@ impl Editor {
         }
     }
 
+    /// Move to the start of the line (going to the first character that isn't whitespace
+    /// first if smart home is enabled)
+    pub fn home(&mut self) {
+        let smart = config!(self.config, document).smart_home;
+        if let Some(doc) = self.try_doc_mut() {
+            if doc.cursor.block.is_some() {
+                doc.block_home();
+            } else if smart {
+                doc.move_smart_home();
+            } else {
+                doc.move_home();
+            }
+        }
+    }
+
+    /// Move the cursor of an open document to a line and column (counting from 1) given on the
+    /// command line, going as near as possible (with a warning) if the document doesn't reach it
+    pub fn go_to_position(&mut self, idx: usize, line: usize, column: Option<usize>) {
+        let Some(doc) = self.get_doc(idx) else {
+            return;
+        };
+        let y = line.clamp(1, doc.len_lines().max(1)) - 1;
+        doc.load_to(y + doc.size.h);
+        let length = doc.line(y).map_or(0, |line| line.chars().count());
+        let x = column.unwrap_or(1).clamp(1, length + 1) - 1;
+        doc.move_to(&Loc { x, y });
+        if y + 1 != line || column.is_some_and(|column| column != x + 1) {
+            let name = doc.file_name.clone().unwrap_or_default();
+            let asked = match column {
+                Some(column) => format!("line {line}, column {column}"),
+                None => format!("line {line}"),
+            };
+            self.feedback = Feedback::Warning(format!(
+                "There is no {asked} in {name}, moved to {}:{}",
+                y + 1,
+                x + 1
+            ));
+        }
+        // The lines just loaded in need highlighting too
+        self.update_highlighter_for(&self.ptr.clone(), idx);
+    }
+
     /// Move the cursor to the previous word in the line
     pub fn prev_word(&mut self) {
         let wrapping = config!(self.config, document).wrap_cursor;
@@ -118,6 +176,102 @@ impl Editor {
             }
         }
     }
+
+    /// Move the cursor to the previous part of an identifier in the line
+    pub fn prev_sub_word(&mut self) {
+        let wrapping = config!(self.config, document).wrap_cursor;
+        if let Some(doc) = self.try_doc_mut() {
+            let status = doc.move_prev_subword();
+            if status == Status::StartOfLine && wrapping {
+                doc.move_up();
+                doc.move_end();
+            }
+        }
+    }
+
+    /// Move the cursor to the next part of an identifier in the line
+    pub fn next_sub_word(&mut self) {
+        let wrapping = config!(self.config, document).wrap_cursor;
+        if let Some(doc) = self.try_doc_mut() {
+            let status = doc.move_next_subword();
+            if status == Status::EndOfLine && wrapping {
+                doc.move_down();
+                doc.move_home();
+            }
+        }
+    }
+
+    /// Select the word under the cursor, or add a cursor at the next occurance of the selection
+    pub fn select_next_occurrence(&mut self) {
+        let Some(doc) = self.try_doc_mut() else {
+            return;
+        };
+        if doc.is_selection_empty()
+            && (doc.secondary_cursors.is_empty() || doc.occurrence.is_none())
+        {
+            // Start off by selecting the word under the cursor
+            let loc = doc.cursor.loc;
+            doc.select_word_at(&loc);
+            if !doc.is_selection_empty() {
+                doc.occurrence = Some(doc.selection_text());
+            }
+            return;
+        }
+        if !doc.is_selection_empty() {
+            // Turn the selection into a plain cursor at its end, like the ones that will follow
+            let text = doc.selection_text();
+            if text.contains('\n') {
+                return;
+            }
+            let end = doc.selection_loc_bound().1;
+            doc.move_to(&end);
+            doc.occurrence = Some(text);
+        }
+        let Some(text) = doc.occurrence.clone() else {
+            return;
+        };
+        let after = doc
+            .secondary_cursors
+            .last()
+            .copied()
+            .unwrap_or_else(|| doc.char_loc());
+        if let Some(loc) = doc.next_occurrence(&text, after) {
+            // Keep the edits made by all the cursors together in the undo history
+            doc.commit();
+            doc.new_cursor(loc);
+        } else {
+            self.feedback = Feedback::Info(format!("No more occurrences of '{text}'"));
+        }
+    }
+
+    /// Go back to a single cursor
+    pub fn clear_cursors(&mut self) {
+        if let Some(doc) = self.try_doc_mut() {
+            if !doc.secondary_cursors.is_empty() {
+                doc.commit();
+            }
+            doc.clear_cursors();
+        }
+    }
+}
+
+/// Where a cursor was and how big its document was, so the effect of an edit can be worked out
+#[derive(Debug, Default, Clone, Copy)]
+pub struct EditPoint {
+    loc: Loc,
+    lines: usize,
+    chars: usize,
+}
+
+impl EditPoint {
+    /// Take note of the primary cursor of a document
+    pub fn of(doc: &Document) -> Self {
+        Self {
+            loc: doc.char_loc(),
+            lines: doc.len_lines(),
+            chars: doc.file.len_chars(),
+        }
+    }
 }
 
 /// Handle multiple cursors (replay a key event for each of them)
@@ -125,24 +279,20 @@ pub fn handle_multiple_cursors(
     editor: &AnyUserData,
     event: &CEvent,
     lua: &Lua,
-    original_loc: &Loc,
+    before: &EditPoint,
 ) -> Result<()> {
     if ged!(&editor).try_doc().is_none() {
         return Ok(());
     }
-    let mut original_loc = *original_loc;
     // Cache the state of the document
-    let mut cursor = ged!(&editor).try_doc().unwrap().cursor;
+    let after = EditPoint::of(ged!(&editor).try_doc().unwrap());
+    let mut primary = after.loc;
     let mut secondary_cursors = ged!(&editor).try_doc().unwrap().secondary_cursors.clone();
     ged!(mut &editor).macro_man.playing = true;
-    // Prevent interference
-    adjust_other_cursors(
-        &mut secondary_cursors,
-        &original_loc.clone(),
-        &cursor.loc,
-        event,
-        &mut original_loc,
-    );
+    // Prevent interference from the edit made at the primary cursor
+    for c in &mut secondary_cursors {
+        adjust_cursor(c, before, &after);
+    }
     // Update each secondary cursor
     let mut ptr = 0;
     while ptr < secondary_cursors.len() {
@@ -153,92 +303,59 @@ pub fn handle_multiple_cursors(
             .unwrap()
             .move_to(&sec_cursor);
         // Replay the event
-        let old_loc = ged!(&editor).try_doc().unwrap().char_loc();
+        let before = EditPoint::of(ged!(&editor).try_doc().unwrap());
         handle_event(editor, event, lua)?;
-        // Prevent any interference
-        let char_loc = ged!(&editor).try_doc().unwrap().char_loc();
-        cursor.loc = adjust_other_cursors(
-            &mut secondary_cursors,
-            &old_loc,
-            &char_loc,
-            event,
-            &mut cursor.loc,
-        );
+        let after = EditPoint::of(ged!(&editor).try_doc().unwrap());
+        // Prevent any interference with the other cursors
+        for (idx, c) in secondary_cursors.iter_mut().enumerate() {
+            if idx != ptr {
+                adjust_cursor(c, &before, &after);
+            }
+        }
+        adjust_cursor(&mut primary, &before, &after);
         // Update the secondary cursor
-        *secondary_cursors.get_mut(ptr).unwrap() = char_loc;
+        secondary_cursors[ptr] = after.loc;
         // Move to the next secondary cursor
         ptr += 1;
     }
-    ged!(mut &editor).try_doc_mut().unwrap().secondary_cursors = secondary_cursors;
+    // Cursors that have run into each other become one
+    let mut seen = vec![primary];
+    secondary_cursors.retain(|c| {
+        let new = !seen.contains(c);
+        seen.push(*c);
+        new
+    });
     ged!(mut &editor).macro_man.playing = false;
-    // Restore back to the state of the document beforehand
-    // TODO: calculate char_ptr and old_cursor too
-    ged!(mut &editor).try_doc_mut().unwrap().cursor = cursor;
-    let char_ptr = ged!(&editor).try_doc().unwrap().character_idx(&cursor.loc);
-    ged!(mut &editor).try_doc_mut().unwrap().char_ptr = char_ptr;
-    ged!(mut &editor).try_doc_mut().unwrap().old_cursor = cursor.loc.x;
-    ged!(mut &editor).try_doc_mut().unwrap().cancel_selection();
+    // Restore back to the primary cursor
+    let mut editor = ged!(mut &editor);
+    let doc = editor.try_doc_mut().unwrap();
+    doc.secondary_cursors = secondary_cursors;
+    doc.move_to(&primary);
+    doc.old_cursor = doc.loc().x;
     Ok(())
 }
 
-/// Adjust other secondary cursors based of a change in one
-fn adjust_other_cursors(
-    cursors: &mut Vec<Loc>,
-    old_pos: &Loc,
-    new_pos: &Loc,
-    event: &CEvent,
-    primary: &mut Loc,
-) -> Loc {
-    cursors.push(*primary);
-    match event {
-        CEvent::Key(KeyEvent {
-            code: KeyCode::Enter,
-            ..
-        }) => {
-            // Enter key, push all cursors below this line downwards
-            for c in cursors.iter_mut() {
-                if c == old_pos {
-                    continue;
-                }
-                let mut new_loc = *c;
-                // Adjust x position
-                if old_pos.y == c.y && old_pos.x < c.x {
-                    new_loc.x -= old_pos.x;
-                }
-                // If this cursor is after the currently moved cursor, shift down
-                if c.y > old_pos.y || (c.y == old_pos.y && c.x > old_pos.x) {
-                    new_loc.y += 1;
-                }
-                // Update the secondary cursor
-                *c = new_loc;
-            }
+/// Adjust a cursor based on an edit made at another one
+fn adjust_cursor(c: &mut Loc, before: &EditPoint, after: &EditPoint) {
+    let (old, new) = (before.loc, after.loc);
+    if after.lines > before.lines {
+        // A line was split, push everything after the split downwards
+        if c.y == old.y && c.x > old.x {
+            *c = Loc::at(c.x - old.x + new.x, new.y);
+        } else if c.y > old.y {
+            c.y += 1;
         }
-        CEvent::Key(KeyEvent {
-            code: KeyCode::Backspace,
-            ..
-        }) => {
-            // Backspace key, push all cursors below this line upwards
-            for c in cursors.iter_mut() {
-                if c == old_pos {
-                    continue;
-                }
-                let mut new_loc = *c;
-                let at_line_start = old_pos.x == 0 && old_pos.y != 0;
-                // Adjust x position
-                if old_pos.y == c.y && old_pos.x < c.x && at_line_start {
-                    new_loc.x += new_pos.x;
-                }
-                // If this cursor is after the currently moved cursor, shift up
-                if (c.y > old_pos.y || (c.y == old_pos.y && c.x > old_pos.x)) && at_line_start {
-                    new_loc.y -= 1;
-                }
-                // Update the secondary cursor
-                *c = new_loc;
-            }
+    } else if after.lines < before.lines {
+        // Two lines were joined, pull everything after the join upwards
+        if c.y == new.y + 1 {
+            *c = Loc::at(c.x + new.x, new.y);
+        } else if c.y > new.y + 1 {
+            c.y -= 1;
         }
-        _ => (),
+    } else if c.y == old.y && c.x > old.x {
+        // Characters were inserted or removed, move along anything after them on the line
+        c.x = (c.x + after.chars).saturating_sub(before.chars);
     }
-    cursors.pop().unwrap()
 }
 
 // Determine whether an event should be acted on by the multi cursor
@@ -250,6 +367,7 @@ pub fn allowed_by_multi_cursor(event: &CEvent) -> bool {
             KeyEvent {
                 code: KeyCode::Tab
                     | KeyCode::Backspace
+                    | KeyCode::Delete
                     | KeyCode::Enter
                     | KeyCode::Up
                     | KeyCode::Down
@@ -0,0 +1,402 @@
+/// Comparing a document side by side with another version of it (what is saved, or another file),
+/// in a read-only pane beside it that is kept up to date as it changes
+use crate::config;
+use crate::error::Result;
+use crate::Feedback;
+use crossterm::style::{
+    Attribute, SetAttribute, SetBackgroundColor as Bg, SetForegroundColor as Fg,
+};
+use kaolinite::diff::{hunks, side_by_side, DiffRow, RowKind};
+use kaolinite::event::Event;
+use kaolinite::utils::{get_file_name, get_path_identity, width_char, Loc};
+use std::ops::Range;
+
+use super::{Editor, FileLayout};
+
+/// Shown in the cells opposite lines that are only on one side
+const FILLER: char = '╱';
+
+/// A document compared with another version of it, for a diff pane
+#[derive(Debug, Clone, Default)]
+pub struct DiffView {
+    /// The (canonical) path of the document being compared
+    pub source: String,
+    /// The name of the document, shown at the top of the pane
+    name: String,
+    /// The name of the version it is compared with
+    old_name: String,
+    /// The lines of the version it is compared with
+    old: Vec<String>,
+    /// Whether it is compared with what is saved (which follows the document as it is saved)
+    pub saved: bool,
+    /// The lines of the document when the diff was worked out
+    new: Vec<String>,
+    /// The rows of the diff, lining up the lines of both
+    rows: Vec<DiffRow>,
+    /// The rows of each run of differences
+    hunks: Vec<Range<usize>>,
+    /// The generation of the document the diff was worked out for (and whether it was modified)
+    computed: Option<(u64, bool)>,
+    /// The first row in view
+    offset: usize,
+}
+
+impl DiffView {
+    /// The line of the document a hunk starts at
+    /// (for lines that were only removed, the line that follows where they were)
+    fn hunk_line(&self, hunk: &Range<usize>) -> usize {
+        self.rows[hunk.start..]
+            .iter()
+            .find_map(|row| row.new)
+            .unwrap_or(self.new.len())
+    }
+
+    /// The lines of each version that a hunk covers
+    fn hunk_lines(&self, hunk: &Range<usize>) -> (Range<usize>, Range<usize>) {
+        let rows = &self.rows[hunk.clone()];
+        let start = self.hunk_line(hunk);
+        let added = rows.iter().filter(|row| row.new.is_some()).count();
+        let old_start = self.rows[hunk.start..]
+            .iter()
+            .find_map(|row| row.old)
+            .unwrap_or(self.old.len());
+        let removed = rows.iter().filter(|row| row.old.is_some()).count();
+        (old_start..old_start + removed, start..start + added)
+    }
+}
+
+/// Add as much of a line to a row as there is room for (tabs are shown as spaces)
+fn push_fit(row: &mut String, text: &str, room: &mut usize, tab_width: usize) {
+    for c in text.chars() {
+        let (c, n) = if c == '\t' { (' ', tab_width) } else { (c, 1) };
+        let w = width_char(&c, tab_width) * n;
+        if w > *room {
+            *room = 0;
+            return;
+        }
+        *room -= w;
+        for _ in 0..n {
+            row.push(c);
+        }
+    }
+}
+
+impl Editor {
+    /// Work out a diff again if its document has changed since it was last worked out
+    fn compute_diff(&mut self, at: &[usize]) {
+        let Some(FileLayout::Diff(diff)) = self.files.get_raw(at.to_vec()) else {
+            return;
+        };
+        // Another document could be saved to the same file, so prefer the one beside the diff
+        let mut beside = at.to_vec();
+        beside.pop();
+        beside.push(0);
+        let shown = self
+            .files
+            .get_atom(beside.clone())
+            .map(|(_, idx)| (beside, idx));
+        let found = self.files.find_all(vec![], &diff.source);
+        let Some((ptr, idx)) = shown
+            .filter(|shown| found.contains(shown))
+            .or_else(|| found.into_iter().next())
+        else {
+            return;
+        };
+        let Ok(fc) = self.file_at(&ptr, idx) else {
+            return;
+        };
+        let wanted = Some((fc.doc.generation, fc.unsaved_changes()));
+        if diff.computed == wanted {
+            return;
+        }
+        let new: Vec<String> = fc
+            .doc
+            .file
+            .to_string()
+            .lines()
+            .map(str::to_string)
+            .collect();
+        let Some(FileLayout::Diff(diff)) = self.files.get_raw_mut(at.to_vec()) else {
+            return;
+        };
+        // Once saved, what is saved is what is in the document
+        if diff.saved && wanted.is_some_and(|(_, modified)| !modified) {
+            diff.old.clone_from(&new);
+        }
+        diff.rows = side_by_side(&diff.old, &new);
+        diff.hunks = hunks(&diff.rows);
+        diff.new = new;
+        diff.computed = wanted;
+    }
+
+    /// Work out the diffs that are in view again if their documents have changed,
+    /// and scroll them to match where the cursor is in their documents
+    pub fn update_diffs(&mut self) {
+        let panes: Vec<(Vec<usize>, usize)> = self
+            .render_cache
+            .span
+            .iter()
+            .filter(|(ptr, _, _)| {
+                matches!(self.files.get_raw(ptr.clone()), Some(FileLayout::Diff(_)))
+            })
+            .map(|(ptr, rows, _)| (ptr.clone(), rows.len()))
+            .collect();
+        for (ptr, h) in panes {
+            self.compute_diff(&ptr);
+            let Some(FileLayout::Diff(diff)) = self.files.get_raw(ptr.clone()) else {
+                continue;
+            };
+            // Follow the pane being edited if it shows the document
+            let views = self.files.find_all(vec![], &diff.source);
+            let Some((at, idx)) = views
+                .iter()
+                .find(|(at, _)| at == &self.ptr)
+                .or(views.first())
+            else {
+                continue;
+            };
+            let Ok(fc) = self.file_at(at, *idx) else {
+                continue;
+            };
+            let cursor = fc.doc.loc().y;
+            let Some(FileLayout::Diff(diff)) = self.files.get_raw_mut(ptr) else {
+                continue;
+            };
+            // Keep the row the cursor is on a third of the way down
+            let at = diff
+                .rows
+                .iter()
+                .position(|row| row.new.is_some_and(|y| y >= cursor))
+                .unwrap_or(diff.rows.len());
+            diff.offset = at.saturating_sub(h.saturating_sub(1) / 3);
+        }
+    }
+
+    /// Render a row of a diff pane
+    #[allow(clippy::similar_names)]
+    pub fn render_diff(&self, ptr: &[usize], y: usize, w: usize) -> Result<String> {
+        let colors = config!(self.config, colors);
+        let editor_bg = Bg(colors.editor_bg.to_color()?);
+        let editor_fg = Fg(colors.editor_fg.to_color()?);
+        let line_number_fg = Fg(colors.line_number_fg.to_color()?);
+        let split_fg = Fg(colors.split_fg.to_color()?);
+        let title_bg = Bg(colors.tab_active_bg.to_color()?);
+        let title_fg = Fg(colors.tab_active_fg.to_color()?);
+        let added_bg = Bg(colors.diff_added_bg.to_color()?);
+        let removed_bg = Bg(colors.diff_removed_bg.to_color()?);
+        let changed_bg = Bg(colors.diff_changed_bg.to_color()?);
+        drop(colors);
+        let tab_width = config!(self.config, document).tab_width;
+        let reset = SetAttribute(Attribute::Reset);
+        let Some(FileLayout::Diff(diff)) = self.files.get_raw(ptr.to_vec()) else {
+            return Ok(format!("{editor_bg}{}", " ".repeat(w)));
+        };
+        // The top row says what is being compared
+        if y == 0 {
+            let mut row = format!("{title_bg}{title_fg}");
+            let mut room = w;
+            let title = format!(" {} → {}", diff.old_name, diff.name);
+            push_fit(&mut row, &title, &mut room, tab_width);
+            return Ok(format!("{row}{}{reset}", " ".repeat(room)));
+        }
+        let Some(row) = diff.rows.get(diff.offset + y - 1) else {
+            return Ok(format!("{editor_bg}{}", " ".repeat(w)));
+        };
+        let digits = diff.old.len().max(diff.new.len()).max(1).to_string().len();
+        let half = |lines: &[String], line: Option<usize>, bg: Bg, room: usize| -> String {
+            let Some(y) = line else {
+                let filler = FILLER.to_string().repeat(room);
+                return format!("{editor_bg}{line_number_fg}{filler}");
+            };
+            let mut result = format!("{bg}{line_number_fg}");
+            let mut room = room;
+            push_fit(
+                &mut result,
+                &format!("{:>digits$} ", y + 1),
+                &mut room,
+                tab_width,
+            );
+            result += &editor_fg.to_string();
+            push_fit(&mut result, &lines[y], &mut room, tab_width);
+            format!("{result}{}", " ".repeat(room))
+        };
+        let (old_bg, new_bg) = match row.kind {
+            RowKind::Same => (editor_bg, editor_bg),
+            RowKind::Added => (editor_bg, added_bg),
+            RowKind::Removed => (removed_bg, editor_bg),
+            RowKind::Changed => (changed_bg, changed_bg),
+        };
+        let left = w.saturating_sub(1) / 2;
+        let right = w.saturating_sub(left + 1);
+        Ok(format!(
+            "{reset}{}{editor_bg}{split_fg}│{}{reset}{editor_bg}",
+            half(&diff.old, row.old, old_bg, left),
+            half(&diff.new, row.new, new_bg, right),
+        ))
+    }
+
+    /// Where the diff of a document is (if it has one)
+    fn diff_of(&self, source: &str) -> Option<Vec<usize>> {
+        self.files
+            .all_diffs(vec![])
+            .into_iter()
+            .find(|(_, comparing)| comparing == source)
+            .map(|(at, _)| at)
+    }
+
+    /// Compare the document being edited with another file (or with what is saved if none is given)
+    /// in a pane beside it. Comparing with what is saved again closes the comparison
+    pub fn toggle_diff(&mut self, against: Option<&str>) -> Result<()> {
+        let Some(fc) = self.files.get(self.ptr.clone()) else {
+            return Ok(());
+        };
+        let Some(file_name) = fc.doc.file_name.clone() else {
+            self.feedback = Feedback::Error("Save this document before comparing it".to_string());
+            return Ok(());
+        };
+        let source = get_path_identity(&file_name);
+        if let Some(at) = self.diff_of(&source) {
+            self.close_pane(&at);
+            if against.is_none() {
+                return Ok(());
+            }
+        }
+        let (old, old_name) = match against {
+            Some(path) => (std::fs::read_to_string(path)?, path.to_string()),
+            None => match std::fs::read_to_string(&file_name) {
+                Ok(old) => (old, "saved".to_string()),
+                // A document that has never been saved is all new
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                    (String::new(), "saved".to_string())
+                }
+                Err(err) => return Err(err.into()),
+            },
+        };
+        let old_name = get_file_name(&old_name).unwrap_or(old_name);
+        self.show_diff(&file_name, &old, old_name, against.is_none());
+        Ok(())
+    }
+
+    /// Open a diff pane beside the document being edited, comparing it with other contents
+    /// (`saved` means they are what is saved, so they follow the document as it is saved)
+    pub fn show_diff(&mut self, file_name: &str, old: &str, old_name: String, saved: bool) {
+        let source = get_path_identity(file_name);
+        if let Some(at) = self.diff_of(&source) {
+            self.close_pane(&at);
+        }
+        let diff = DiffView {
+            source,
+            name: get_file_name(file_name).unwrap_or_else(|| file_name.to_string()),
+            old_name,
+            old: old.lines().map(str::to_string).collect(),
+            saved,
+            ..DiffView::default()
+        };
+        // The document being edited stays focused, on the left of the diff
+        let mut ptr = self
+            .files
+            .open_right(self.ptr.clone(), FileLayout::Diff(diff));
+        ptr.pop();
+        ptr.push(0);
+        self.ptr = ptr;
+        self.cache_old_ptr(&self.ptr.clone());
+    }
+
+    /// Find the diff of the document being edited, worked out for how it is now
+    fn current_diff(&mut self) -> Option<Vec<usize>> {
+        let file_name = self.try_doc()?.file_name.clone();
+        let at = file_name.and_then(|name| self.diff_of(&get_path_identity(&name)));
+        if at.is_none() {
+            self.feedback = Feedback::Error("This document isn't being compared".to_string());
+        }
+        let at = at?;
+        self.compute_diff(&at);
+        Some(at)
+    }
+
+    /// Move the cursor to the next (or previous) difference from the version it is compared with,
+    /// going round to the first (or last) one at the end
+    pub fn next_hunk(&mut self, forward: bool) {
+        let Some(at) = self.current_diff() else {
+            return;
+        };
+        let Some(FileLayout::Diff(diff)) = self.files.get_raw(at) else {
+            return;
+        };
+        let starts: Vec<usize> = diff.hunks.iter().map(|h| diff.hunk_line(h)).collect();
+        let Some(doc) = self.try_doc() else {
+            return;
+        };
+        let y = doc.loc().y;
+        let target = if forward {
+            starts.iter().find(|s| **s > y).or(starts.first())
+        } else {
+            starts.iter().rev().find(|s| **s < y).or(starts.last())
+        };
+        let Some(target) = target.copied() else {
+            self.feedback = Feedback::Info("There are no differences".to_string());
+            return;
+        };
+        if let Some(doc) = self.try_doc_mut() {
+            doc.cancel_selection();
+            doc.move_to(&Loc { x: 0, y: target });
+        }
+    }
+
+    /// Put back what is saved in place of the difference the cursor is on, as a single change
+    pub fn revert_hunk(&mut self) {
+        let Some(at) = self.current_diff() else {
+            return;
+        };
+        let Some(FileLayout::Diff(diff)) = self.files.get_raw(at) else {
+            return;
+        };
+        if !diff.saved {
+            self.feedback =
+                Feedback::Error("Only differences from what is saved can be reverted".to_string());
+            return;
+        }
+        let Some(y) = self.try_doc().map(|doc| doc.loc().y) else {
+            return;
+        };
+        let hunk = diff.hunks.iter().find(|hunk| {
+            let (_, new) = diff.hunk_lines(hunk);
+            new.contains(&y) || (new.is_empty() && new.start == y)
+        });
+        let Some((old, new)) = hunk.map(|hunk| diff.hunk_lines(hunk)) else {
+            self.feedback = Feedback::Error("The cursor isn't on a difference".to_string());
+            return;
+        };
+        let saved: Vec<String> = diff.old[old].to_vec();
+        let removed: Vec<String> = diff.new[new.clone()].to_vec();
+        let result = self.batch(|editor| {
+            for line in removed.into_iter().rev() {
+                editor.exe(Event::DeleteLine(new.start, line))?;
+            }
+            for (i, line) in saved.into_iter().enumerate() {
+                editor.exe(Event::InsertLine(new.start + i, line))?;
+            }
+            if let Some(doc) = editor.try_doc_mut() {
+                doc.cancel_selection();
+                doc.move_to(&Loc { x: 0, y: new.start });
+            }
+            Ok(())
+        });
+        self.reload_highlight();
+        if let Err(err) = result {
+            self.feedback = Feedback::Error(err.to_string());
+        }
+    }
+
+    /// Close the diffs of documents that are no longer open
+    pub fn close_orphaned_diffs(&mut self) {
+        while let Some((at, _)) = self
+            .files
+            .all_diffs(vec![])
+            .into_iter()
+            .find(|(_, source)| self.files.find_all(vec![], source).is_empty())
+        {
+            self.close_pane(&at);
+        }
+    }
+}
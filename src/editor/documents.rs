@@ -1,15 +1,20 @@
 /// Tools for placing all information about open files into one place
-use crate::editor::{get_absolute_path, Editor, FileType};
+use crate::editor::{get_absolute_path, get_path_identity, Editor, FileType};
 #[cfg(not(target_os = "windows"))]
 use crate::pty::Pty;
-use crate::Loc;
+use kaolinite::signs::Sign;
 use kaolinite::Document;
+use kaolinite::Loc;
 use kaolinite::Size;
 use std::ops::Range;
 #[cfg(not(target_os = "windows"))]
 use std::sync::{Arc, Mutex};
 use synoptic::Highlighter;
 
+use super::diffview::DiffView;
+use super::ondisk::OnDisk;
+use super::preview::Preview;
+
 pub type Span = Vec<(Vec<usize>, Range<usize>, Range<usize>)>;
 
 // File split structure
@@ -25,6 +30,10 @@ pub enum FileLayout {
     None,
     /// Representing a file tree
     FileTree,
+    /// Representing a read-only preview of a markdown document
+    Preview(Preview),
+    /// Representing a side-by-side diff of a document against another version of it
+    Diff(DiffView),
     /// Representing a terminal
     #[cfg(not(target_os = "windows"))]
     Terminal(Arc<Mutex<Pty>>),
@@ -51,7 +60,11 @@ impl FileLayout {
         match self {
             Self::None => vec![],
             // Atom file trees and terminals: stretch from starting position through to end of their containers
-            Self::Atom(_, _) | Self::FileTree | Self::Terminal(_) => {
+            Self::Atom(_, _)
+            | Self::FileTree
+            | Self::Terminal(_)
+            | Self::Preview(_)
+            | Self::Diff(_) => {
                 vec![(idx, at.y..at.y + size.h, at.x..at.x + size.w)]
             }
             // SideBySide: distributes available container space to each sub-layout
@@ -154,8 +167,13 @@ impl FileLayout {
                 for (doc, _) in fcs.iter().enumerate() {
                     // Work out correct new document width
                     let new_size = Size {
-                        h: rows.end.saturating_sub(rows.start + ed.push_down + 1),
-                        w: cols.end.saturating_sub(cols.start + ed.dent_for(idx, doc)),
+                        h: rows
+                            .end
+                            .saturating_sub(rows.start + ed.push_down + ed.status_rows()),
+                        w: cols
+                            .end
+                            .saturating_sub(cols.start + ed.dent_for(idx, doc))
+                            .min(ed.text_width()),
                     };
                     result.push((idx.clone(), doc, new_size));
                 }
@@ -167,7 +185,7 @@ impl FileLayout {
     /// Work out how many files are currently open
     pub fn len(&self) -> usize {
         match self {
-            Self::None | Self::FileTree | Self::Terminal(_) => 0,
+            Self::None | Self::FileTree | Self::Terminal(_) | Self::Preview(_) | Self::Diff(_) => 0,
             Self::Atom(containers, _) => containers.len(),
             Self::SideBySide(layouts) => layouts.iter().map(|(layout, _)| layout.len()).sum(),
             Self::TopToBottom(layouts) => layouts.iter().map(|(layout, _)| layout.len()).sum(),
@@ -177,7 +195,7 @@ impl FileLayout {
     /// Work out how many atoms are currently open
     pub fn n_atoms(&self) -> usize {
         match self {
-            Self::None | Self::FileTree | Self::Terminal(_) => 0,
+            Self::None | Self::FileTree | Self::Terminal(_) | Self::Preview(_) | Self::Diff(_) => 0,
             Self::Atom(_, _) => 1,
             Self::SideBySide(layouts) => layouts.iter().map(|(layout, _)| layout.n_atoms()).sum(),
             Self::TopToBottom(layouts) => layouts.iter().map(|(layout, _)| layout.n_atoms()).sum(),
@@ -187,7 +205,9 @@ impl FileLayout {
     /// Find a file container location from it's path
     pub fn find(&self, idx: Vec<usize>, path: &str) -> Option<(Vec<usize>, usize)> {
         match self {
-            Self::None | Self::FileTree | Self::Terminal(_) => None,
+            Self::None | Self::FileTree | Self::Terminal(_) | Self::Preview(_) | Self::Diff(_) => {
+                None
+            }
             Self::Atom(containers, _) => {
                 // Scan this atom for any documents
                 for (ptr, container) in containers.iter().enumerate() {
@@ -214,10 +234,99 @@ impl FileLayout {
         }
     }
 
+    /// Find every document that points to a certain file (identified by `get_path_identity`)
+    pub fn find_all(&self, idx: Vec<usize>, identity: &str) -> Vec<(Vec<usize>, usize)> {
+        match self {
+            Self::None | Self::FileTree | Self::Terminal(_) | Self::Preview(_) | Self::Diff(_) => {
+                vec![]
+            }
+            Self::Atom(containers, _) => containers
+                .iter()
+                .enumerate()
+                .filter(|(_, container)| {
+                    let file_path = container.doc.file_name.as_deref();
+                    file_path.map(get_path_identity).as_deref() == Some(identity)
+                })
+                .map(|(ptr, _)| (idx.clone(), ptr))
+                .collect(),
+            Self::SideBySide(layouts) | Self::TopToBottom(layouts) => {
+                // Recursively scan
+                let mut result = vec![];
+                for (nth, (layout, _)) in layouts.iter().enumerate() {
+                    let mut this_idx = idx.clone();
+                    this_idx.push(nth);
+                    result.append(&mut layout.find_all(this_idx, identity));
+                }
+                result
+            }
+        }
+    }
+
+    /// List the location of every document that is open
+    pub fn all_documents(&self, idx: Vec<usize>) -> Vec<(Vec<usize>, usize)> {
+        match self {
+            Self::None | Self::FileTree | Self::Terminal(_) | Self::Preview(_) | Self::Diff(_) => {
+                vec![]
+            }
+            Self::Atom(containers, _) => (0..containers.len())
+                .map(|ptr| (idx.clone(), ptr))
+                .collect(),
+            Self::SideBySide(layouts) | Self::TopToBottom(layouts) => {
+                // Recursively scan
+                let mut result = vec![];
+                for (nth, (layout, _)) in layouts.iter().enumerate() {
+                    let mut this_idx = idx.clone();
+                    this_idx.push(nth);
+                    result.append(&mut layout.all_documents(this_idx));
+                }
+                result
+            }
+        }
+    }
+
+    /// List the location of every markdown preview, along with the document it previews
+    pub fn all_previews(&self, idx: Vec<usize>) -> Vec<(Vec<usize>, String)> {
+        match self {
+            Self::Preview(preview) => vec![(idx, preview.source.clone())],
+            Self::SideBySide(layouts) | Self::TopToBottom(layouts) => {
+                let mut result = vec![];
+                for (nth, (layout, _)) in layouts.iter().enumerate() {
+                    let mut this_idx = idx.clone();
+                    this_idx.push(nth);
+                    result.append(&mut layout.all_previews(this_idx));
+                }
+                result
+            }
+            _ => vec![],
+        }
+    }
+
+    /// List the location of every diff, along with the document it compares
+    pub fn all_diffs(&self, idx: Vec<usize>) -> Vec<(Vec<usize>, String)> {
+        match self {
+            Self::Diff(diff) => vec![(idx, diff.source.clone())],
+            Self::SideBySide(layouts) | Self::TopToBottom(layouts) => {
+                let mut result = vec![];
+                for (nth, (layout, _)) in layouts.iter().enumerate() {
+                    let mut this_idx = idx.clone();
+                    this_idx.push(nth);
+                    result.append(&mut layout.all_diffs(this_idx));
+                }
+                result
+            }
+            _ => vec![],
+        }
+    }
+
     /// Get the `FileLayout` at a certain index
     pub fn get_raw(&self, mut idx: Vec<usize>) -> Option<&FileLayout> {
         match self {
-            Self::None | Self::Atom(_, _) | Self::FileTree | Self::Terminal(_) => Some(self),
+            Self::None
+            | Self::Atom(_, _)
+            | Self::FileTree
+            | Self::Terminal(_)
+            | Self::Preview(_)
+            | Self::Diff(_) => Some(self),
             Self::SideBySide(layouts) => {
                 if idx.is_empty() {
                     Some(self)
@@ -243,7 +352,12 @@ impl FileLayout {
             Some(self)
         } else {
             match self {
-                Self::None | Self::Atom(_, _) | Self::FileTree | Self::Terminal(_) => Some(self),
+                Self::None
+                | Self::Atom(_, _)
+                | Self::FileTree
+                | Self::Terminal(_)
+                | Self::Preview(_)
+                | Self::Diff(_) => Some(self),
                 Self::SideBySide(layouts) => {
                     let subidx = idx.remove(0);
                     layouts.get_mut(subidx)?.0.get_raw_mut(idx)
@@ -259,7 +373,12 @@ impl FileLayout {
     /// Get the `FileLayout` at a certain index
     pub fn set(&mut self, mut idx: Vec<usize>, fl: FileLayout) {
         match self {
-            Self::None | Self::Atom(_, _) | Self::FileTree | Self::Terminal(_) => *self = fl,
+            Self::None
+            | Self::Atom(_, _)
+            | Self::FileTree
+            | Self::Terminal(_)
+            | Self::Preview(_)
+            | Self::Diff(_) => *self = fl,
             Self::SideBySide(layouts) | Self::TopToBottom(layouts) => {
                 if idx.is_empty() {
                     *self = fl;
@@ -274,7 +393,9 @@ impl FileLayout {
     /// Given an index, find the file containers in the tree
     pub fn get_atom(&self, mut idx: Vec<usize>) -> Option<(&[FileContainer], usize)> {
         match self {
-            Self::None | Self::FileTree | Self::Terminal(_) => None,
+            Self::None | Self::FileTree | Self::Terminal(_) | Self::Preview(_) | Self::Diff(_) => {
+                None
+            }
             Self::Atom(containers, ptr) => Some((containers, *ptr)),
             Self::SideBySide(layouts) => {
                 let subidx = idx.remove(0);
@@ -293,7 +414,9 @@ impl FileLayout {
         mut idx: Vec<usize>,
     ) -> Option<(&mut Vec<FileContainer>, &mut usize)> {
         match self {
-            Self::None | Self::FileTree | Self::Terminal(_) => None,
+            Self::None | Self::FileTree | Self::Terminal(_) | Self::Preview(_) | Self::Diff(_) => {
+                None
+            }
             Self::Atom(ref mut containers, ref mut ptr) => Some((containers, ptr)),
             Self::SideBySide(layouts) => {
                 let subidx = idx.remove(0);
@@ -326,7 +449,9 @@ impl FileLayout {
     /// In the currently active atom, move to a different document
     pub fn move_to(&mut self, mut idx: Vec<usize>, ptr: usize) {
         match self {
-            Self::None | Self::FileTree | Self::Terminal(_) => (),
+            Self::None | Self::FileTree | Self::Terminal(_) | Self::Preview(_) | Self::Diff(_) => {
+                ()
+            }
             Self::Atom(_, ref mut old_ptr) => *old_ptr = ptr,
             Self::SideBySide(layouts) | Self::TopToBottom(layouts) => {
                 let subidx = idx.remove(0);
@@ -372,7 +497,12 @@ impl FileLayout {
             // Determine behaviour based on parent
             if let Some(parent) = self.get_raw_mut(at_parent) {
                 match parent {
-                    Self::None | Self::Atom(_, _) | Self::FileTree | Self::Terminal(_) => {
+                    Self::None
+                    | Self::Atom(_, _)
+                    | Self::FileTree
+                    | Self::Terminal(_)
+                    | Self::Preview(_)
+                    | Self::Diff(_) => {
                         unreachable!()
                     }
                     Self::SideBySide(layouts) | Self::TopToBottom(layouts) => {
@@ -398,7 +528,9 @@ impl FileLayout {
     /// Traverse the tree and return a list of indices to empty atoms
     pub fn empty_atoms(&self, at: Vec<usize>) -> Option<Vec<usize>> {
         match self {
-            Self::None | Self::FileTree | Self::Terminal(_) => None,
+            Self::None | Self::FileTree | Self::Terminal(_) | Self::Preview(_) | Self::Diff(_) => {
+                None
+            }
             Self::Atom(fcs, _) => {
                 if fcs.is_empty() {
                     Some(at)
@@ -426,7 +558,12 @@ impl FileLayout {
     /// Traverse the tree and return a list of indices to redundant sidebyside/toptobottom
     pub fn redundant_multis(&self, at: Vec<usize>) -> Option<Vec<usize>> {
         match self {
-            Self::None | Self::FileTree | Self::Atom(_, _) | Self::Terminal(_) => None,
+            Self::None
+            | Self::FileTree
+            | Self::Atom(_, _)
+            | Self::Terminal(_)
+            | Self::Preview(_)
+            | Self::Diff(_) => None,
             Self::SideBySide(layouts) | Self::TopToBottom(layouts) => {
                 if layouts.len() == 1 {
                     Some(at)
@@ -448,7 +585,9 @@ impl FileLayout {
     #[cfg(not(target_os = "windows"))]
     pub fn terminal_rerender(&mut self) -> bool {
         match self {
-            Self::None | Self::FileTree | Self::Atom(_, _) => false,
+            Self::None | Self::FileTree | Self::Atom(_, _) | Self::Preview(_) | Self::Diff(_) => {
+                false
+            }
             Self::Terminal(term) => {
                 let mut term = term.lock().unwrap();
                 if term.force_rerender {
@@ -497,7 +636,9 @@ impl FileLayout {
                 Self::Atom(_, _)
                 | Self::SideBySide(_)
                 | Self::TopToBottom(_)
-                | Self::Terminal(_) => {
+                | Self::Terminal(_)
+                | Self::Preview(_)
+                | Self::Diff(_) => {
                     new_ptr.push(0);
                     let old_fl = std::mem::replace(old_fl, FileLayout::None);
                     Self::TopToBottom(vec![(fl, 0.5), (old_fl, 0.5)])
@@ -518,7 +659,9 @@ impl FileLayout {
                 Self::Atom(_, _)
                 | Self::SideBySide(_)
                 | Self::TopToBottom(_)
-                | Self::Terminal(_) => {
+                | Self::Terminal(_)
+                | Self::Preview(_)
+                | Self::Diff(_) => {
                     new_ptr.push(1);
                     let old_fl = std::mem::replace(old_fl, FileLayout::None);
                     Self::TopToBottom(vec![(old_fl, 0.5), (fl, 0.5)])
@@ -539,7 +682,9 @@ impl FileLayout {
                 Self::Atom(_, _)
                 | Self::SideBySide(_)
                 | Self::TopToBottom(_)
-                | Self::Terminal(_) => {
+                | Self::Terminal(_)
+                | Self::Preview(_)
+                | Self::Diff(_) => {
                     new_ptr.push(0);
                     let old_fl = std::mem::replace(old_fl, FileLayout::None);
                     Self::SideBySide(vec![(fl, 0.5), (old_fl, 0.5)])
@@ -560,7 +705,9 @@ impl FileLayout {
                 Self::Atom(_, _)
                 | Self::SideBySide(_)
                 | Self::TopToBottom(_)
-                | Self::Terminal(_) => {
+                | Self::Terminal(_)
+                | Self::Preview(_)
+                | Self::Diff(_) => {
                     new_ptr.push(1);
                     let old_fl = std::mem::replace(old_fl, FileLayout::None);
                     Self::SideBySide(vec![(old_fl, 0.5), (fl, 0.5)])
@@ -587,79 +734,110 @@ impl FileLayout {
         }
     }
 
-    /// Get the proportion of a certain node in the tree
-    #[allow(clippy::cast_precision_loss)]
-    pub fn set_proportion(&mut self, mut at: Vec<usize>, amount: f64) {
+    /// Set the proportion of a certain node in the tree, taking the difference from its
+    /// siblings without letting any of them fall below `min`
+    pub fn set_proportion(&mut self, mut at: Vec<usize>, amount: f64, min: f64) {
         if let Some(last_idx) = at.pop() {
             if let Some(FileLayout::SideBySide(layouts) | FileLayout::TopToBottom(layouts)) =
                 self.get_raw_mut(at)
             {
-                layouts[last_idx].1 = amount;
-                // Potential problem with this algorithm - it could cause underflow (resulting in props not adding up to 1)
-                let reduce = layouts.iter().map(|(_, prop)| prop).sum::<f64>() - 1.0;
-                let reduce_each = reduce / layouts.len().saturating_sub(1) as f64;
-                layouts
-                    .iter_mut()
-                    .enumerate()
-                    .filter(|(c, _)| *c != last_idx)
-                    .for_each(|(_, (_, prop))| *prop -= reduce_each);
+                let mut props: Vec<f64> = layouts.iter().map(|(_, prop)| *prop).collect();
+                redistribute(&mut props, last_idx, amount, min);
+                for ((_, prop), new) in layouts.iter_mut().zip(props) {
+                    *prop = new;
+                }
+            }
+        }
+    }
+
+    /// Move the divider after a child of a split, so that it sits at `fraction` of the way
+    /// across the split, without letting the children either side fall below `min`
+    pub fn move_divider(&mut self, at: Vec<usize>, child: usize, fraction: f64, min: f64) {
+        if let Some(FileLayout::SideBySide(layouts) | FileLayout::TopToBottom(layouts)) =
+            self.get_raw_mut(at)
+        {
+            if child + 1 >= layouts.len() {
+                return;
+            }
+            let before: f64 = layouts[..child].iter().map(|(_, prop)| prop).sum();
+            let pair = layouts[child].1 + layouts[child + 1].1;
+            if pair < min * 2.0 {
+                return;
+            }
+            let new = (fraction - before).clamp(min, pair - min);
+            layouts[child].1 = new;
+            layouts[child + 1].1 = pair - new;
+        }
+    }
+
+    /// Give every split an equal share of its parent (the file tree keeps its width)
+    #[allow(clippy::cast_precision_loss)]
+    pub fn equalise(&mut self) {
+        if let Self::SideBySide(layouts) | Self::TopToBottom(layouts) = self {
+            let tree: f64 = layouts
+                .iter()
+                .filter(|(layout, _)| matches!(layout, FileLayout::FileTree))
+                .map(|(_, prop)| prop)
+                .sum();
+            let others = layouts
+                .iter()
+                .filter(|(layout, _)| !matches!(layout, FileLayout::FileTree))
+                .count();
+            let share = (1.0 - tree) / others.max(1) as f64;
+            for (layout, prop) in layouts.iter_mut() {
+                if !matches!(layout, FileLayout::FileTree) {
+                    *prop = share;
+                }
+                layout.equalise();
             }
         }
     }
 
     /// Shrink this split's width
-    pub fn shrink_width(&mut self, at: &[usize], amount: f64) {
+    pub fn shrink_width(&mut self, at: &[usize], amount: f64, min: f64) {
         // Find the parent
         if let Some((idx, one_down)) = self.get_sidebyside_parent(at.to_vec()) {
             // Got a side by side parent! Adjust the proportion
             let mut child = idx.clone();
             child.push(one_down);
             let current_prop = self.get_proportion(child.clone());
-            if current_prop > amount {
-                self.set_proportion(child, current_prop - amount);
-            }
+            self.set_proportion(child, current_prop - amount, min);
         }
     }
 
     /// Grow this split's width
-    pub fn grow_width(&mut self, at: &[usize], amount: f64) {
+    pub fn grow_width(&mut self, at: &[usize], amount: f64, min: f64) {
         // Find the parent
         if let Some((idx, one_down)) = self.get_sidebyside_parent(at.to_vec()) {
             // Got a side by side parent! Adjust the proportion
             let mut child = idx.clone();
             child.push(one_down);
             let current_prop = self.get_proportion(child.clone());
-            if current_prop + amount < 1.0 {
-                self.set_proportion(child, current_prop + amount);
-            }
+            self.set_proportion(child, current_prop + amount, min);
         }
     }
 
     /// Shrink this split's height
-    pub fn shrink_height(&mut self, at: &[usize], amount: f64) {
+    pub fn shrink_height(&mut self, at: &[usize], amount: f64, min: f64) {
         // Find the parent
         if let Some((idx, one_down)) = self.get_toptobottom_parent(at.to_vec()) {
             // Got a top to bottom parent! Adjust the proportion
             let mut child = idx.clone();
             child.push(one_down);
             let current_prop = self.get_proportion(child.clone());
-            if current_prop > amount {
-                self.set_proportion(child, current_prop - amount);
-            }
+            self.set_proportion(child, current_prop - amount, min);
         }
     }
 
     /// Grow this split's height
-    pub fn grow_height(&mut self, at: &[usize], amount: f64) {
+    pub fn grow_height(&mut self, at: &[usize], amount: f64, min: f64) {
         // Find the parent
         if let Some((idx, one_down)) = self.get_toptobottom_parent(at.to_vec()) {
             // Got a top to bottom parent! Adjust the proportion
             let mut child = idx.clone();
             child.push(one_down);
             let current_prop = self.get_proportion(child.clone());
-            if current_prop + amount < 1.0 {
-                self.set_proportion(child, current_prop + amount);
-            }
+            self.set_proportion(child, current_prop + amount, min);
         }
     }
 
@@ -843,6 +1021,35 @@ impl FileLayout {
     }
 }
 
+/// Set one of a split's proportions to `amount`, scaling the others so they still add up to 1.
+/// No proportion is left below `min` (or below an equal share, if there isn't room for `min`)
+#[allow(clippy::cast_precision_loss)]
+pub fn redistribute(props: &mut [f64], idx: usize, amount: f64, min: f64) {
+    let others = props.len().saturating_sub(1);
+    if idx >= props.len() || others == 0 {
+        return;
+    }
+    let min = min.clamp(0.0, 1.0 / props.len() as f64);
+    let target = amount.clamp(min, 1.0 - min * others as f64);
+    // Share out what is left above the minimum in the same ratio as before
+    let spare = 1.0 - target - min * others as f64;
+    let above: f64 = props
+        .iter()
+        .enumerate()
+        .filter(|(c, _)| *c != idx)
+        .map(|(_, prop)| (prop - min).max(0.0))
+        .sum();
+    for (c, prop) in props.iter_mut().enumerate() {
+        if c == idx {
+            *prop = target;
+        } else if above > 0.0 {
+            *prop = min + (*prop - min).max(0.0) * spare / above;
+        } else {
+            *prop = min + spare / others as f64;
+        }
+    }
+}
+
 /// Container for a file
 #[derive(Debug, Clone)]
 pub struct FileContainer {
@@ -852,6 +1059,45 @@ pub struct FileContainer {
     pub highlighter: Highlighter,
     /// File type (stores which file type this file is)
     pub file_type: Option<FileType>,
+    /// The name of the scratch buffer this is (an empty name for the default one)
+    pub scratch: Option<String>,
+    /// Whether this was read in from the standard input
+    pub stdin: bool,
+    /// The signs external tools have reported in this file
+    pub signs: Vec<Sign>,
+    /// Whether the file has been changed or removed by another program
+    pub on_disk: OnDisk,
+    /// Which group of panes showing the same document this pane belongs to (if any)
+    pub view: Option<usize>,
+}
+
+impl FileContainer {
+    /// Whether this file has been changed since it was last saved
+    pub fn is_modified(&self) -> bool {
+        !self.doc.event_mgmt.with_disk(&self.doc.take_snapshot())
+    }
+
+    /// Whether the user should be warned about losing changes to this file.
+    /// Scratch buffers are meant to be thrown away, so they never are
+    pub fn unsaved_changes(&self) -> bool {
+        self.scratch.is_none() && self.is_modified()
+    }
+
+    /// Whether this file should be written out when saving everything.
+    /// Scratch buffers and hex previews of binary files never are
+    pub fn savable(&self) -> bool {
+        self.scratch.is_none() && self.doc.binary.is_none()
+    }
+
+    /// The name to show for a file that isn't on the disk
+    pub fn placeholder_name(&self) -> String {
+        match self.scratch.as_deref() {
+            Some("") => "[scratch]".to_string(),
+            Some(super::HELP_NAME) => "[help]".to_string(),
+            Some(name) => format!("[scratch {name}]"),
+            None => "[No Name]".to_string(),
+        }
+    }
 }
 
 impl Default for FileContainer {
@@ -860,6 +1106,11 @@ impl Default for FileContainer {
             doc: Document::new(Size { w: 10, h: 10 }),
             highlighter: Highlighter::new(4),
             file_type: None,
+            scratch: None,
+            stdin: false,
+            signs: vec![],
+            on_disk: OnDisk::default(),
+            view: None,
         }
     }
 }
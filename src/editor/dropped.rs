@@ -0,0 +1,119 @@
+/// Dropped files: terminals paste the (quoted) paths of files dragged onto them,
+/// which can be opened as documents instead of being typed in
+use crate::config;
+use crate::error::{OxError, Result};
+use crate::Feedback;
+use kaolinite::utils::get_file_name;
+use std::path::Path;
+
+use super::Editor;
+
+/// Turn a `file://` address into a path (some terminals paste these for dropped files)
+fn from_uri(word: &str) -> String {
+    let Some(rest) = word.strip_prefix("file://") else {
+        return word.to_string();
+    };
+    // Leave out the host name, if there is one
+    let path = rest.find('/').map_or(rest, |at| &rest[at..]);
+    let bytes = path.as_bytes();
+    let mut decoded = vec![];
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = (bytes[i] == b'%')
+            .then(|| path.get(i + 1..i + 3))
+            .flatten()
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        if let Some(byte) = escaped {
+            decoded.push(byte);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8_lossy(&decoded).to_string()
+}
+
+/// Split text into words the way a shell would, undoing quotes and escaped spaces
+/// (giving back None if it can't be split, such as when a quote is never closed)
+fn shell_words(text: &str) -> Option<Vec<String>> {
+    let mut words = vec![];
+    let mut word: Option<String> = None;
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\'' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next()? {
+                        '\'' => break,
+                        c => word.push(c),
+                    }
+                }
+            }
+            '"' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next()? {
+                        '"' => break,
+                        // Only a few characters can be escaped inside double quotes
+                        '\\' => {
+                            let escaped = chars.next()?;
+                            if !matches!(escaped, '"' | '\\' | '$' | '`') {
+                                word.push('\\');
+                            }
+                            word.push(escaped);
+                        }
+                        c => word.push(c),
+                    }
+                }
+            }
+            '\\' => word.get_or_insert_with(String::new).push(chars.next()?),
+            c if c.is_whitespace() => words.extend(word.take()),
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    words.extend(word);
+    Some(words)
+}
+
+/// The files some pasted text names, if it names nothing but files that exist
+#[must_use]
+pub fn pasted_files(text: &str) -> Option<Vec<String>> {
+    let paths: Vec<String> = shell_words(text)?.iter().map(|w| from_uri(w)).collect();
+    let all_files = !paths.is_empty() && paths.iter().all(|p| Path::new(p).is_file());
+    all_files.then_some(paths)
+}
+
+impl Editor {
+    /// Offer to open the files named by some pasted text, giving back whether they were
+    /// opened (if they weren't, the text is to be typed in as usual)
+    pub fn open_pasted_files(&mut self, text: &str) -> Result<bool> {
+        if !config!(self.config, document).open_pasted_paths || self.macro_man.playing {
+            return Ok(false);
+        }
+        let Some(paths) = pasted_files(text) else {
+            return Ok(false);
+        };
+        let what = match paths.as_slice() {
+            [path] => format!("'{}'", get_file_name(path).unwrap_or_else(|| path.clone())),
+            _ => format!("{} files", paths.len()),
+        };
+        let msg = format!("Pasted the path of {what}:");
+        if self.choice(&msg, &["open", "paste as text"])? != Some(0) {
+            return Ok(false);
+        }
+        for path in paths {
+            // Files that are already open are moved to instead
+            match self.open(&path) {
+                Ok(()) => self.next(),
+                Err(OxError::AlreadyOpen { .. }) => (),
+                Err(err) => {
+                    self.feedback = Feedback::Error(format!("Couldn't open '{path}': {err}"));
+                }
+            }
+        }
+        self.update_cwd();
+        Ok(true)
+    }
+}
@@ -1,46 +1,115 @@
 /// General functions for editing a document
+use crate::config;
+use crate::config::Indentation;
 use crate::error::Result;
-use kaolinite::event::Event;
+use crate::ui::Feedback;
+use kaolinite::event::{Error as KError, Event};
 use kaolinite::utils::Loc;
+use kaolinite::Document;
 
 use super::Editor;
 
+/// Swap the case of every letter in some text
+fn toggle_case(text: &str) -> String {
+    text.chars()
+        .flat_map(|c| {
+            if c.is_uppercase() {
+                c.to_lowercase().collect::<Vec<char>>()
+            } else {
+                c.to_uppercase().collect()
+            }
+        })
+        .collect()
+}
+
 impl Editor {
     /// Execute an edit event
     pub fn exe(&mut self, ev: Event) -> Result<()> {
-        if self.try_doc().is_some() {
-            let multi_cursors = !self.try_doc().unwrap().secondary_cursors.is_empty();
-            if !(self.plugin_active || self.pasting || self.macro_man.playing || multi_cursors) {
-                let last_ev = self.try_doc().unwrap().event_mgmt.last_event.as_ref();
-                // If last event is present and the same as this one, commit
-                let event_type_differs = last_ev.map(|e1| e1.same_type(&ev)) != Some(true);
-                // If last event is present and on a different line from the previous, commit
-                let event_on_different_line =
-                    last_ev.map(|e| e.loc().y == ev.loc().y) != Some(true);
-                // Commit if necessary
-                if event_type_differs || event_on_different_line {
-                    self.try_doc_mut().unwrap().commit();
-                }
-            } else if self.try_doc().unwrap().event_mgmt.history.is_empty() {
-                // If there is no initial commit and a plug-in changes things without commiting
-                // It can cause the initial state of the document to be lost
-                // This condition makes sure there is a copy to go back to if this is the case
-                self.try_doc_mut().unwrap().commit();
+        match self.files.get_atom(self.ptr.clone()) {
+            Some((files, doc)) if doc < files.len() => self.exe_at(&self.ptr.clone(), doc, ev),
+            _ => Ok(()),
+        }
+    }
+
+    /// Execute an edit event on any open document, not just the one being edited
+    pub fn exe_at(&mut self, ptr: &[usize], doc: usize, ev: Event) -> Result<()> {
+        // Changes can only be made once the whole document is there
+        self.wait_for_document_at(ptr, doc);
+        if self.warn_if_binary_at(ptr, doc) {
+            return Ok(());
+        }
+        let visible = self.is_visible(ptr, doc);
+        let automated = self.plugin_active || self.pasting || self.macro_man.playing;
+        let file = self.file_at_mut(ptr, doc)?;
+        let multi_cursors = !file.doc.secondary_cursors.is_empty();
+        if !(automated || multi_cursors) {
+            let last_ev = file.doc.event_mgmt.last_event.as_ref();
+            // If last event is present and the same as this one, commit
+            let event_type_differs = last_ev.map(|e1| e1.same_type(&ev)) != Some(true);
+            // If last event is present and on a different line from the previous, commit
+            let event_on_different_line = last_ev.map(|e| e.loc().y == ev.loc().y) != Some(true);
+            // Commit if necessary
+            if event_type_differs || event_on_different_line {
+                file.doc.commit();
             }
-            self.try_doc_mut().unwrap().exe(ev)?;
+        } else if file.doc.event_mgmt.current.is_none() {
+            // If there is no initial commit and a plug-in changes things without commiting
+            // It can cause the initial state of the document to be lost
+            // This condition makes sure there is a copy to go back to if this is the case
+            file.doc.commit();
+        }
+        file.doc.exe(ev)?;
+        if visible {
+            self.needs_rerender = true;
         }
         Ok(())
     }
 
+    /// Commit the current document to the undo history
+    pub fn commit(&mut self) {
+        if let Some((_, doc)) = self.files.get_atom(self.ptr.clone()) {
+            let _ = self.commit_at(&self.ptr.clone(), doc);
+        }
+    }
+
+    /// Commit any open document to its undo history
+    pub fn commit_at(&mut self, ptr: &[usize], doc: usize) -> Result<()> {
+        self.file_at_mut(ptr, doc)?.doc.commit();
+        Ok(())
+    }
+
+    /// Make some edits to the current document as a single change in the undo history
+    /// (the batch is closed even if the edits fail part of the way through)
+    pub fn batch<T>(&mut self, edits: impl FnOnce(&mut Self) -> Result<T>) -> Result<T> {
+        if let Some(doc) = self.try_doc_mut() {
+            doc.begin_batch();
+        }
+        let result = edits(self);
+        if let Some(doc) = self.try_doc_mut() {
+            doc.end_batch();
+        }
+        result
+    }
+
     /// Insert a character into the document, creating a new row if editing
     /// on the last line of the document
     pub fn character(&mut self, ch: char) -> Result<()> {
+        if ch != '\n' && self.in_block() {
+            // Typing into a block selection types on every row of it
+            let text = self.typed_text(ch);
+            let pad = config!(self.config, document).block_padding;
+            return self.line_operation(|doc| doc.insert_block(&text, pad));
+        }
         if self.try_doc().is_some() {
+            self.load_around_cursor();
             let doc = self.try_doc().unwrap();
             let selection_overwrite = !doc.is_selection_empty() && !doc.info.read_only;
             if selection_overwrite {
-                self.try_doc_mut().unwrap().commit();
-                self.try_doc_mut().unwrap().remove_selection();
+                let doc = self.try_doc_mut().unwrap();
+                let old = doc.file.clone();
+                doc.batch(Document::remove_selection);
+                let region = doc.changed_region(&old);
+                self.highlight_region(region);
             }
             self.new_row()?;
             // Handle the character insertion
@@ -49,22 +118,68 @@ impl Editor {
             } else {
                 let doc = self.try_doc().unwrap();
                 let loc = doc.char_loc();
-                self.exe(Event::Insert(loc, ch.to_string()))?;
-                if let Some(file) = self.files.get_mut(self.ptr.clone()) {
-                    if !file.doc.info.read_only {
-                        file.highlighter.edit(loc.y, &file.doc.lines[loc.y]);
+                let text = self.typed_text(ch);
+                let under = doc.line(loc.y).and_then(|line| line.chars().nth(loc.x));
+                match under {
+                    Some(under) if self.overtype && !self.pasting => {
+                        self.overtype_char(loc, under, text)?;
                     }
+                    _ => self.exe(Event::Insert(loc, text))?,
                 }
-            }
-            if selection_overwrite {
-                self.reload_highlight();
+                self.highlight_edited(loc.y);
             }
         }
         Ok(())
     }
 
+    /// What typing a character inserts, which for a tab is a run of spaces when
+    /// indenting with spaces (tabs that are pasted in are kept as they are)
+    fn typed_text(&self, ch: char) -> String {
+        let document = config!(self.config, document);
+        if ch == '\t' && document.indentation == Indentation::Spaces && !self.pasting {
+            " ".repeat(document.tab_width)
+        } else {
+            ch.to_string()
+        }
+    }
+
+    /// Replace the character under the cursor with what was typed, as a deletion followed
+    /// by an insertion in the same place (so that undoing it brings the character back)
+    fn overtype_char(&mut self, loc: Loc, under: char, text: String) -> Result<()> {
+        if self.warn_if_binary() {
+            return Ok(());
+        }
+        self.exe(Event::Delete(loc, under.to_string()))?;
+        // Going straight to the document avoids committing between the two halves
+        if let Some(doc) = self.try_doc_mut() {
+            doc.exe(Event::Insert(loc, text))?;
+        }
+        Ok(())
+    }
+
+    /// Switch between inserting typed characters and replacing the ones under the cursor
+    pub fn toggle_overtype(&mut self) {
+        self.overtype = !self.overtype;
+        self.needs_rerender = true;
+    }
+
+    /// The number of spaces backspace should remove to take away a whole level of
+    /// indentation, if the cursor is just after one made of spaces (and indenting with spaces)
+    fn soft_tab_before(&self, doc: &Document) -> Option<usize> {
+        let document = config!(self.config, document);
+        let c = doc.char_ptr;
+        let tab_width = document.tab_width;
+        if document.indentation != Indentation::Spaces || tab_width < 2 || c == 0 {
+            return None;
+        }
+        let line = doc.line(doc.loc().y)?;
+        let all_spaces = line.chars().take(c).all(|ch| ch == ' ');
+        (all_spaces && c % tab_width == 0).then_some(tab_width)
+    }
+
     /// Handle the return key
     pub fn enter(&mut self) -> Result<()> {
+        self.load_around_cursor();
         if let Some(doc) = self.try_doc_mut() {
             // Perform the changes
             if doc.loc().y == doc.len_lines() {
@@ -75,13 +190,16 @@ impl Editor {
                 let loc = doc.char_loc();
                 self.exe(Event::SplitDown(loc))?;
                 if let Some(file) = self.files.get_mut(self.ptr.clone()) {
-                    if !file.doc.info.read_only {
-                        let line = &file.doc.lines[loc.y + 1];
-                        file.highlighter.insert_line(loc.y + 1, line);
-                        let line = &file.doc.lines[loc.y];
-                        file.highlighter.edit(loc.y, line);
+                    // Lines that aren't loaded yet are highlighted once they are
+                    if let Some(line) = file
+                        .doc
+                        .line(loc.y + 1)
+                        .filter(|_| !file.doc.info.read_only)
+                    {
+                        file.highlighter.insert_line(loc.y + 1, &line);
                     }
                 }
+                self.highlight_edited(loc.y);
             }
         }
         Ok(())
@@ -89,14 +207,19 @@ impl Editor {
 
     /// Handle the backspace key
     pub fn backspace(&mut self) -> Result<()> {
+        if self.in_block() {
+            return self.line_operation(Document::backspace_block);
+        }
         if self.try_doc().is_some() {
+            self.load_around_cursor();
             let doc = self.try_doc().unwrap();
             if !doc.is_selection_empty() && !doc.info.read_only {
                 // Removing a selection is significant and worth an undo commit
                 let doc = self.try_doc_mut().unwrap();
-                doc.commit();
-                doc.remove_selection();
-                self.reload_highlight();
+                let old = doc.file.clone();
+                doc.batch(Document::remove_selection);
+                let region = doc.changed_region(&old);
+                self.highlight_region(region);
                 return Ok(());
             }
             let doc = self.try_doc().unwrap();
@@ -109,17 +232,24 @@ impl Editor {
                 let mut loc = self.try_doc().unwrap().char_loc();
                 let file = self.files.get_mut(self.ptr.clone()).unwrap();
                 if !file.doc.info.read_only {
-                    self.highlighter().remove_line(loc.y);
+                    file.highlighter.remove_line(loc.y);
                 }
                 loc.y = loc.y.saturating_sub(1);
                 let file = self.files.get_mut(self.ptr.clone()).unwrap();
-                loc.x = file.doc.line(loc.y).unwrap().chars().count();
+                let Some(above) = file.doc.line(loc.y) else {
+                    return Ok(());
+                };
+                loc.x = above.chars().count();
                 self.exe(Event::SpliceUp(loc))?;
-                let file = self.files.get_mut(self.ptr.clone()).unwrap();
-                let line = &file.doc.lines[loc.y];
-                if !file.doc.info.read_only {
-                    file.highlighter.edit(loc.y, line);
-                }
+                self.highlight_edited(loc.y);
+            } else if let Some(width) = self.soft_tab_before(doc) {
+                // Backspace was pressed just after a level of indentation, remove all of it
+                let loc = Loc {
+                    x: c - width,
+                    y: doc.loc().y,
+                };
+                self.exe(Event::Delete(loc, " ".repeat(width)))?;
+                self.highlight_edited(loc.y);
             } else if !(c == 0 && on_first_line) {
                 // Backspace was pressed in the middle of the line, delete the character
                 c = c.saturating_sub(1);
@@ -130,10 +260,7 @@ impl Editor {
                             y: doc.loc().y,
                         };
                         self.exe(Event::Delete(loc, ch.to_string()))?;
-                        let file = self.files.get_mut(self.ptr.clone()).unwrap();
-                        if !file.doc.info.read_only {
-                            file.highlighter.edit(loc.y, &file.doc.lines[loc.y]);
-                        }
+                        self.highlight_edited(loc.y);
                     }
                 }
             }
@@ -143,6 +270,10 @@ impl Editor {
 
     /// Delete the character in place
     pub fn delete(&mut self) -> Result<()> {
+        if self.in_block() {
+            return self.line_operation(Document::delete_block);
+        }
+        self.load_around_cursor();
         if let Some(doc) = self.try_doc() {
             let c = doc.char_ptr;
             if let Some(line) = doc.line(doc.loc().y) {
@@ -152,17 +283,37 @@ impl Editor {
                         y: doc.loc().y,
                     };
                     self.exe(Event::Delete(loc, ch.to_string()))?;
-                    if let Some(file) = self.files.get_mut(self.ptr.clone()) {
-                        if !file.doc.info.read_only {
-                            file.highlighter.edit(loc.y, &file.doc.lines[loc.y]);
-                        }
-                    }
+                    self.highlight_edited(loc.y);
                 }
             }
         }
         Ok(())
     }
 
+    /// Make sure the lines around the cursor are loaded (and highlighted) before they are
+    /// edited, as the cursor can be put past what has been loaded by jumping into a large file
+    fn load_around_cursor(&mut self) {
+        if let Some(doc) = self.try_doc_mut() {
+            let y = doc.loc().y;
+            doc.load_to(y + 2);
+        }
+        self.update_highlighter();
+    }
+
+    /// Bring the highlighting of a line that has just been edited up to date
+    pub fn highlight_edited(&mut self, y: usize) {
+        let Some(file) = self.files.get_mut(self.ptr.clone()) else {
+            return;
+        };
+        if file.doc.info.read_only {
+            return;
+        }
+        // Lines that aren't loaded yet are highlighted once they are
+        if let Some(line) = file.doc.line(y) {
+            file.highlighter.edit(y, &line);
+        }
+    }
+
     /// Insert a new row at the end of the document if the cursor is on it
     fn new_row(&mut self) -> Result<()> {
         if self.try_doc().is_some() {
@@ -171,7 +322,9 @@ impl Editor {
                 self.exe(Event::InsertLine(doc.loc().y, String::new()))?;
                 let doc = self.try_doc().unwrap();
                 if !doc.info.read_only {
-                    self.highlighter().append("");
+                    if let Some(highlighter) = self.highlighter() {
+                        highlighter.append("");
+                    }
                 }
             }
         }
@@ -189,18 +342,44 @@ impl Editor {
                 self.exe(Event::DeleteLine(y, line))?;
                 let doc = self.try_doc().unwrap();
                 if !doc.info.read_only {
-                    self.highlighter().remove_line(y);
+                    if let Some(highlighter) = self.highlighter() {
+                        highlighter.remove_line(y);
+                    }
                 }
             }
         }
         Ok(())
     }
 
+    /// Delete back to the start of the word (or the part of an identifier) before the cursor
+    pub fn remove_word(&mut self, subwords: bool) -> Result<()> {
+        if let Some(doc) = self.try_doc_mut() {
+            if subwords {
+                doc.delete_subword()?;
+            } else {
+                doc.delete_word()?;
+            }
+            let y = doc.loc().y;
+            self.update_highlighter();
+            self.hl_edit(y);
+        }
+        Ok(())
+    }
+
     /// Perform redo action
     pub fn redo(&mut self) -> Result<()> {
         if let Some(doc) = self.try_doc_mut() {
-            doc.redo()?;
-            self.reload_highlight();
+            let old = doc.file.clone();
+            let change = doc.redo()?;
+            let region = doc.changed_region(&old);
+            self.highlight_region(region);
+            if change.is_none() {
+                self.ring_bell();
+            }
+            self.feedback = Feedback::Info(match change {
+                Some(change) => format!("Redid {}", change.describe()),
+                None => "Nothing to redo".to_string(),
+            });
         }
         Ok(())
     }
@@ -208,37 +387,142 @@ impl Editor {
     /// Perform undo action
     pub fn undo(&mut self) -> Result<()> {
         if let Some(doc) = self.try_doc_mut() {
-            doc.undo()?;
-            self.reload_highlight();
+            let old = doc.file.clone();
+            let change = doc.undo()?;
+            let region = doc.changed_region(&old);
+            self.highlight_region(region);
+            if change.is_none() {
+                self.ring_bell();
+            }
+            self.feedback = Feedback::Info(match change {
+                Some(change) => format!("Undid {}", change.describe()),
+                None => "Nothing to undo".to_string(),
+            });
         }
         Ok(())
     }
 
-    /// Copy the selected text
+    /// Copy the selected text (a block selection is copied with a line for each row)
     pub fn copy(&mut self) -> Result<()> {
+        let pad = config!(self.config, document).block_padding;
         if let Some(doc) = self.try_doc() {
-            let selected_text = doc.selection_text();
+            let selected_text = doc.block_text(pad).unwrap_or_else(|| doc.selection_text());
+            // The history is kept even when the terminal's clipboard can't be written to
+            self.remember_copy(&selected_text);
             self.terminal.copy(&selected_text)
         } else {
             Ok(())
         }
     }
 
-    /// Cut the selected text
+    /// Cut the selected text (it is always kept in the clipboard history, so it is still
+    /// cut if the terminal's clipboard can't be written to, the error is passed on afterwards)
     pub fn cut(&mut self) -> Result<()> {
+        if self.try_doc().is_some_and(|doc| doc.binary.is_some()) {
+            return Err(KError::BinaryPreview.into());
+        }
         if self.try_doc().is_some() {
-            self.copy()?;
-            self.try_doc_mut().unwrap().remove_selection();
-            self.reload_highlight();
+            let copied = self.copy();
+            if self.in_block() {
+                self.line_operation(Document::remove_block)?;
+                return copied;
+            }
+            let doc = self.try_doc_mut().unwrap();
+            let old = doc.file.clone();
+            doc.batch(Document::remove_selection);
+            let region = doc.changed_region(&old);
+            self.highlight_region(region);
+            return copied;
+        }
+        Ok(())
+    }
+
+    /// Perform an edit on the lines of the current document as a single undoable change,
+    /// rehighlighting only the lines it touched
+    fn line_operation(
+        &mut self,
+        op: impl FnOnce(&mut Document) -> kaolinite::event::Result<()>,
+    ) -> Result<()> {
+        if self.warn_if_binary() {
+            return Ok(());
         }
+        let Some(doc) = self.try_doc_mut() else {
+            return Ok(());
+        };
+        let old = doc.file.clone();
+        doc.batch(op)?;
+        let region = doc.changed_region(&old);
+        self.highlight_region(region);
         Ok(())
     }
 
+    /// Join the current line with the one below, or all the selected lines into one
+    pub fn join_lines(&mut self) -> Result<()> {
+        self.line_operation(Document::join_lines)
+    }
+
+    /// Sort the selected lines, alphabetically or in reverse, optionally removing duplicates
+    pub fn sort_lines(&mut self, reverse: bool, unique: bool) -> Result<()> {
+        if self.try_doc().is_some_and(Document::is_selection_empty) {
+            self.feedback = Feedback::Info("Select the lines to sort".to_string());
+            return Ok(());
+        }
+        self.line_operation(|doc| doc.sort_lines(reverse, unique))
+    }
+
+    /// Change the case of the selected text ("upper", "lower" or "toggle")
+    pub fn change_case(&mut self, case: &str) -> Result<()> {
+        if self.try_doc().is_some_and(Document::is_selection_empty) {
+            self.feedback = Feedback::Info("Select the text to change the case of".to_string());
+            return Ok(());
+        }
+        match case {
+            "upper" => self.line_operation(|doc| doc.transform_selection(str::to_uppercase)),
+            "lower" => self.line_operation(|doc| doc.transform_selection(str::to_lowercase)),
+            "toggle" => self.line_operation(|doc| doc.transform_selection(toggle_case)),
+            _ => {
+                self.feedback = Feedback::Error(format!("Unknown case '{case}'"));
+                Ok(())
+            }
+        }
+    }
+
+    /// Rewrite the indentation of every line in the current document with spaces or tabs
+    /// as a single undoable change (see `Document::convert_indentation`)
+    pub fn convert_indentation(&mut self, to_spaces: bool, everywhere: bool) -> Result<()> {
+        if self.warn_if_binary() {
+            return Ok(());
+        }
+        let Some(doc) = self.try_doc_mut() else {
+            return Ok(());
+        };
+        let changed = doc.batch(|doc| doc.convert_indentation(to_spaces, everywhere))?;
+        // Pick up the lines that had to be loaded, then only the lines that changed
+        self.update_highlighter();
+        for y in &changed {
+            self.hl_edit(*y);
+        }
+        let kind = if to_spaces { "spaces" } else { "tabs" };
+        self.feedback = Feedback::Info(match changed.len() {
+            0 => format!("The indentation already uses {kind}"),
+            1 => format!("Converted the indentation of 1 line to {kind}"),
+            n => format!("Converted the indentation of {n} lines to {kind}"),
+        });
+        Ok(())
+    }
+
+    /// Whether the current document has a block selection
+    pub fn in_block(&self) -> bool {
+        self.try_doc().is_some_and(|doc| doc.cursor.block.is_some())
+    }
+
     /// Shortcut to help rehighlight a line
     pub fn hl_edit(&mut self, y: usize) {
         if let Some(doc) = self.try_doc() {
             let line = doc.line(y).unwrap_or_default();
-            self.highlighter().edit(y, &line);
+            if let Some(highlighter) = self.highlighter() {
+                highlighter.edit(y, &line);
+            }
         }
     }
 }
@@ -1,14 +1,28 @@
 /// Utilities for handling the file tree
 use crate::config::FileTree as CfgFT;
+use crate::editor::gitstatus::{GitState, GitStatus};
+use crate::editor::resize::MIN_PANE_WIDTH;
 use crate::editor::FileLayout;
-use crate::ui::size;
 use crate::{config, Editor, Feedback, FileTypes, OxError, Result};
-use kaolinite::utils::{file_or_dir, get_cwd, get_file_name};
+use crossterm::event::{KeyCode as KCode, KeyModifiers as KMod};
+use kaolinite::fuzzy::fuzzy_match;
+use kaolinite::utils::{file_or_dir, get_cwd, get_file_name, get_path_identity};
 use std::path::{Path, PathBuf};
 
+/// How many levels down a filter looks into directories that haven't been expanded
+const FILTER_DEPTH: usize = 4;
+/// The most entries a filter reads in from directories that haven't been expanded
+const FILTER_ENTRIES: usize = 20_000;
+
 /// How parts of a file tree are stored
 /// (Padding, Icon, Icon Color, File Name)
-pub type FTParts = Vec<(usize, String, Option<String>, String)>;
+pub type FTParts = Vec<(
+    usize,
+    String,
+    Option<String>,
+    String,
+    Option<(GitState, &'static str)>,
+)>;
 
 /// The backend of a file tree - stores the structure of the files and directories
 #[derive(Debug, Clone)]
@@ -225,11 +239,17 @@ impl FileTree {
     }
 
     /// Display this file tree
-    pub fn display(&self, sel: &str, fts: &FileTypes, cfg: &CfgFT) -> (FTParts, Option<usize>) {
-        let mut result = self.display_recursive(sel, fts, cfg);
+    pub fn display(
+        &self,
+        sel: &str,
+        fts: &FileTypes,
+        cfg: &CfgFT,
+        git: &GitStatus,
+    ) -> (FTParts, Option<usize>) {
+        let mut result = self.display_recursive(sel, fts, cfg, git);
         result
             .0
-            .insert(0, (0, "󰉖  ".to_string(), None, "..".to_string()));
+            .insert(0, (0, "󰉖  ".to_string(), None, "..".to_string(), None));
         if sel == ".." {
             result.1 = Some(0);
         } else if let Some(ref mut at) = result.1 {
@@ -244,6 +264,7 @@ impl FileTree {
         sel: &str,
         fts: &FileTypes,
         cfg: &CfgFT,
+        git: &GitStatus,
     ) -> (FTParts, Option<usize>) {
         let icons = cfg.icons;
         match self {
@@ -254,8 +275,9 @@ impl FileTree {
                     (String::new(), None)
                 };
                 let file_name = get_file_name(path).unwrap_or(path.to_string());
+                let state = git.state_of(path, false).map(|s| (s, s.marker(false)));
                 (
-                    vec![(0, icon, icon_color, file_name)],
+                    vec![(0, icon, icon_color, file_name, state)],
                     if self.is_selected(sel) { Some(0) } else { None },
                 )
             }
@@ -269,14 +291,15 @@ impl FileTree {
                     (String::new(), None)
                 };
                 let file_name = get_file_name(path).unwrap_or(path.to_string());
-                result.push((0, icon, icon_color, file_name));
+                let state = git.state_of(path, true).map(|s| (s, s.marker(true)));
+                result.push((0, icon, icon_color, file_name, state));
                 if self.is_selected(sel) {
                     at = Some(result.len().saturating_sub(1));
                 }
                 // Write child nodes
                 if let Some(files) = files {
                     for file in files {
-                        let (sub_display, sub_at) = file.display_recursive(sel, fts, cfg);
+                        let (sub_display, sub_at) = file.display_recursive(sel, fts, cfg, git);
                         for (c, s) in sub_display.iter().enumerate() {
                             let mut s = s.clone();
                             s.0 += 1;
@@ -318,6 +341,27 @@ impl FileTree {
         }
     }
 
+    /// Find which row of the flattened tree a path is shown on
+    pub fn row_of(&self, needle: &str) -> Option<usize> {
+        self.flatten().iter().position(|path| path == needle)
+    }
+
+    /// Find the directory within this tree that a path sits in
+    pub fn parent_of(&self, needle: &str) -> Option<String> {
+        let Self::Dir {
+            path,
+            files: Some(files),
+        } = self
+        else {
+            return None;
+        };
+        if files.iter().any(|file| file.is_selected(needle)) {
+            Some(path.clone())
+        } else {
+            files.iter().find_map(|file| file.parent_of(needle))
+        }
+    }
+
     /// Expand this file tree upwards towards parent
     pub fn open_parent(&self) -> Result<Self> {
         if let Self::Dir { path, files } = self {
@@ -370,6 +414,116 @@ impl FileTree {
             }
         }
     }
+
+    /// Expand directories that haven't been expanded yet, down to a certain depth
+    /// (hidden directories are left alone, and reading stops once `budget` entries are in)
+    fn expand_within(&mut self, depth: usize, budget: &mut usize) {
+        if matches!(self, Self::Dir { files: None, .. }) {
+            if depth == 0 || *budget == 0 || self.is_hidden() {
+                return;
+            }
+            self.expand();
+        }
+        if let Self::Dir {
+            files: Some(files), ..
+        } = self
+        {
+            *budget = budget.saturating_sub(files.len());
+            for file in files {
+                file.expand_within(depth.saturating_sub(1), budget);
+            }
+        }
+    }
+
+    /// Copy this tree, keeping only entries whose names fuzzy match a query and the
+    /// directories leading to them (returns None if nothing in here matches)
+    fn filtered(&self, query: &str) -> Option<Self> {
+        let matches = |path: &str| {
+            get_file_name(path).is_some_and(|name| fuzzy_match(query, &name).is_some())
+        };
+        match self {
+            Self::File { path } => matches(path).then(|| self.clone()),
+            Self::Dir { path, files } => {
+                let kept: Vec<Self> = files
+                    .iter()
+                    .flatten()
+                    .filter_map(|file| file.filtered(query))
+                    .collect();
+                if kept.is_empty() && !matches(path) {
+                    None
+                } else {
+                    Some(Self::Dir {
+                        path: path.clone(),
+                        files: (!kept.is_empty()).then_some(kept),
+                    })
+                }
+            }
+        }
+    }
+
+    /// Find the first file in this tree
+    fn first_file(&self) -> Option<&str> {
+        match self {
+            Self::File { path } => Some(path),
+            Self::Dir { files, .. } => files.iter().flatten().find_map(Self::first_file),
+        }
+    }
+}
+
+/// A filter narrowing the file tree down to the entries whose names match a query.
+/// It works on copies, so the expansion of the tree itself is left as it was
+#[derive(Debug, Clone)]
+pub struct TreeFilter {
+    pub query: String,
+    /// A copy of the tree, with directories expanded (to a certain depth) to search within
+    expanded: FileTree,
+    /// The tree as shown, with only the matches and the directories leading to them
+    pub shown: FileTree,
+    /// What was selected before filtering, to go back to afterwards
+    selection: Option<String>,
+}
+
+impl TreeFilter {
+    /// Start filtering a tree
+    fn new(tree: &FileTree, selection: Option<String>) -> Self {
+        let mut expanded = tree.clone();
+        let mut budget = FILTER_ENTRIES;
+        expanded.expand_within(FILTER_DEPTH, &mut budget);
+        Self {
+            query: String::new(),
+            expanded,
+            shown: tree.clone(),
+            selection,
+        }
+    }
+
+    /// Narrow down the tree to what matches the query (showing it as it was without one)
+    fn update(&mut self, tree: &FileTree) {
+        self.shown = if self.query.is_empty() {
+            tree.clone()
+        } else if let Some(shown) = self.expanded.filtered(&self.query) {
+            shown
+        } else if let FileTree::Dir { path, .. } = &self.expanded {
+            FileTree::Dir {
+                path: path.clone(),
+                files: Some(vec![]),
+            }
+        } else {
+            self.expanded.clone()
+        };
+    }
+}
+
+/// Where a file tree of some number of rows should be scrolled to so the selected row
+/// is in view, moving it as little as possible from where it was
+#[must_use]
+pub fn tree_scroll(scroll: usize, selected: Option<usize>, height: usize, rows: usize) -> usize {
+    let scroll = match selected {
+        Some(sel) if sel < scroll => sel,
+        Some(sel) if sel >= scroll + height => sel + 1 - height,
+        _ => scroll,
+    };
+    scroll.min(rows.saturating_sub(height))
 }
 
 impl Editor {
@@ -378,9 +532,13 @@ impl Editor {
     pub fn open_file_tree(&mut self) {
         if !self.file_tree_is_open() {
             // Calculate display proportions
-            let total_width = size().map(|s| s.w as f64).unwrap_or(1.0);
+            let total_width = self.terminal.size().map(|s| s.w as f64).unwrap_or(1.0);
             let width = config!(self.config, file_tree).width as f64 / total_width;
-            let other = 1.0 - width as f64;
+            // Leave the documents room, even on narrow terminals
+            let width = width
+                .min(1.0 - MIN_PANE_WIDTH as f64 / total_width)
+                .max(0.0);
+            let other = 1.0 - width;
             // Set up file tree values
             self.old_ptr = self.ptr.clone();
             if let Some(cwd) = get_cwd() {
@@ -394,11 +552,25 @@ impl Editor {
             self.files =
                 FileLayout::SideBySide(vec![(FileLayout::FileTree, width), (files, other)]);
             self.ptr = vec![0];
+            self.file_tree_git_refresh();
+        }
+    }
+
+    /// Open the file tree at a directory given on the command line,
+    /// leaving the documents focused if any were opened too
+    pub fn open_file_tree_at(&mut self, dir: &str, focus_documents: bool) {
+        let _ = std::env::set_current_dir(dir);
+        self.open_file_tree();
+        if focus_documents {
+            let mut ptr = self.old_ptr.clone();
+            ptr.insert(0, 1);
+            self.ptr = ptr;
         }
     }
 
     /// Close the file tree
     pub fn close_file_tree(&mut self) {
+        self.file_tree_filter = None;
         if let Some(FileLayout::SideBySide(layouts)) = self.files.get_raw(vec![]) {
             let in_file_tree = matches!(
                 self.files.get_raw(self.ptr.clone()),
@@ -451,42 +623,83 @@ impl Editor {
         }
     }
 
+    /// Move the file tree selection up or down a row
+    fn file_tree_select_by(&mut self, up: bool) {
+        let Some(file_tree) = self.file_tree_shown() else {
+            return;
+        };
+        let flat = file_tree.flatten();
+        let row = self
+            .file_tree_selection
+            .as_ref()
+            .and_then(|sel| file_tree.row_of(sel))
+            .unwrap_or(0);
+        let row = if up {
+            row.saturating_sub(1)
+        } else {
+            (row + 1).min(flat.len().saturating_sub(1))
+        };
+        self.file_tree_selection = flat.get(row).cloned();
+        self.render_cache.file_tree_selection = Some(row);
+    }
+
     /// Move file tree selection upwards
     pub fn file_tree_select_up(&mut self) {
-        if let Some(ref mut fts) = self.render_cache.file_tree_selection {
-            // Move up a file (in the render cache)
-            *fts = fts.saturating_sub(1);
-            // Move up a file (in the backend)
-            let flat = self
-                .file_tree
-                .as_ref()
-                .map(FileTree::flatten)
-                .unwrap_or_default();
-            let new_path = flat.get(*fts);
-            self.file_tree_selection = new_path.cloned();
-        }
+        self.file_tree_select_by(true);
     }
 
-    /// Move file tree selection upwards
+    /// Move file tree selection downwards
     pub fn file_tree_select_down(&mut self) {
-        if let Some(ref mut fts) = self.render_cache.file_tree_selection {
-            let flat = self
-                .file_tree
-                .as_ref()
-                .map(FileTree::flatten)
-                .unwrap_or_default();
-            if *fts + 1 < flat.len() {
-                // Move up a file (in the render cache)
-                *fts += 1;
-                // Move up a file (in the backend)
-                let new_path = flat.get(*fts);
-                self.file_tree_selection = new_path.cloned();
+        self.file_tree_select_by(false);
+    }
+
+    /// Expand the selected directory, or move into it if it is already expanded
+    pub fn file_tree_expand(&mut self) {
+        let Some(file_tree) = &mut self.file_tree else {
+            return;
+        };
+        let Some(sel) = self.file_tree_selection.clone() else {
+            return;
+        };
+        match file_tree.get_mut(&sel) {
+            Some(node @ FileTree::Dir { files: None, .. }) => node.expand(),
+            Some(FileTree::Dir {
+                files: Some(files), ..
+            }) => {
+                if let Some(FileTree::File { path } | FileTree::Dir { path, .. }) = files.first() {
+                    self.file_tree_selection = Some(path.clone());
+                }
+            }
+            _ => (),
+        }
+    }
+
+    /// Collapse the selected directory, or move out to the directory the selection is in
+    pub fn file_tree_collapse(&mut self) {
+        let Some(file_tree) = &mut self.file_tree else {
+            return;
+        };
+        let Some(sel) = self.file_tree_selection.clone() else {
+            return;
+        };
+        let parent = file_tree.parent_of(&sel);
+        match file_tree.get_mut(&sel) {
+            Some(FileTree::Dir { files, .. }) if files.is_some() && parent.is_some() => {
+                *files = None;
+            }
+            _ => {
+                if let Some(parent) = parent {
+                    self.file_tree_selection = Some(parent);
+                }
             }
         }
     }
 
     /// Open a certain file / directory in a file tree
     pub fn file_tree_open_node(&mut self) -> Result<()> {
+        if self.file_tree_filter.is_some() {
+            return self.file_tree_filter_accept();
+        }
         if let Some(file_name) = &self.file_tree_selection.clone() {
             if file_name == ".." {
                 self.file_tree_open_parent()?;
@@ -512,7 +725,7 @@ impl Editor {
             temp.insert(0, 1);
             self.ptr = temp;
             // Perform open operation
-            self.open(file_name)?;
+            self.open_or_new(file_name.clone())?;
             self.next();
             self.update_cwd();
             // If we don't want to move focus, then move focus back to the file tree
@@ -587,39 +800,187 @@ impl Editor {
         }
     }
 
-    /// Create a new file / folder
+    /// Work out which directory new entries should go in, based on the selection
+    /// (the selected directory, or the directory the selected file is in)
+    fn file_tree_target_dir(&self) -> Option<String> {
+        let file_tree = self.file_tree.as_ref()?;
+        let root = file_tree.flatten_recursive().first().cloned();
+        match self.file_tree_selection.as_deref() {
+            None | Some("..") => root,
+            Some(sel) if file_or_dir(sel) == "directory" => Some(sel.to_string()),
+            Some(sel) => file_tree.parent_of(sel).or(root),
+        }
+    }
+
+    /// Rebuild a directory in the file tree after its contents have changed
+    /// (keeping any directories within it expanded)
+    fn file_tree_reload_dir(&mut self, dir: &str) {
+        if let Some(node) = self.file_tree.as_mut().and_then(|ft| ft.get_mut(dir)) {
+            if let FileTree::Dir { files: Some(_), .. } = node {
+                node.refresh();
+            } else {
+                node.expand();
+            }
+        }
+        self.file_tree_git_refresh();
+    }
+
+    /// Prompt for the name of a new entry, working out where it should go
+    fn file_tree_new_path(&mut self, kind: &str) -> Result<Option<(String, PathBuf)>> {
+        let Some(dir) = self.file_tree_target_dir() else {
+            return Ok(None);
+        };
+        let name = self.prompt(format!("New {kind} name"))?;
+        let name = name.trim();
+        if name.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some((dir.clone(), Path::new(&dir).join(name))))
+    }
+
+    /// Create a new file in the selected directory
     pub fn file_tree_new(&mut self) -> Result<()> {
-        let path = self.path_prompt()?;
-        if path.ends_with(std::path::MAIN_SEPARATOR) {
-            std::fs::create_dir_all(path)?;
-            self.file_tree_refresh();
-            self.feedback = Feedback::Info("Folder created".to_string());
+        let Some((dir, path)) = self.file_tree_new_path("file")? else {
+            return Ok(());
+        };
+        let result = path
+            .parent()
+            .map_or(Ok(()), std::fs::create_dir_all)
+            .and_then(|()| {
+                std::fs::OpenOptions::new()
+                    .write(true)
+                    .create_new(true)
+                    .open(&path)
+            });
+        self.file_tree_report(&dir, &path, result.map(|_| ()), "File created");
+        Ok(())
+    }
+
+    /// Create a new directory in the selected directory
+    pub fn file_tree_new_dir(&mut self) -> Result<()> {
+        let Some((dir, path)) = self.file_tree_new_path("folder")? else {
+            return Ok(());
+        };
+        let result = path
+            .parent()
+            .map_or(Ok(()), std::fs::create_dir_all)
+            .and_then(|()| std::fs::create_dir(&path));
+        self.file_tree_report(&dir, &path, result, "Folder created");
+        Ok(())
+    }
+
+    /// Show how a file tree operation went, selecting the entry it affected if it succeeded
+    fn file_tree_report(&mut self, dir: &str, path: &Path, result: std::io::Result<()>, msg: &str) {
+        let name = FileTree::path_to_string(path);
+        match result {
+            Ok(()) => {
+                self.file_tree_reload_dir(dir);
+                self.file_tree_selection = Some(name);
+                self.feedback = Feedback::Info(msg.to_string());
+            }
+            Err(err) => {
+                let name = get_file_name(&name).unwrap_or(name);
+                self.feedback = Feedback::Error(format!("Couldn't create {name}: {err}"));
+            }
+        }
+    }
+
+    /// Rename the selected file / folder (within the directory it is in)
+    pub fn file_tree_rename(&mut self) -> Result<()> {
+        let Some(old) = self.file_tree_selection.clone().filter(|s| s != "..") else {
+            return Ok(());
+        };
+        let Some(dir) = self.file_tree.as_ref().and_then(|ft| ft.parent_of(&old)) else {
+            self.feedback =
+                Feedback::Error("The top of the file tree can't be renamed".to_string());
+            return Ok(());
+        };
+        let old_name = get_file_name(&old).unwrap_or(old.clone());
+        let name = self.prompt(format!("Rename {old_name} to"))?;
+        let name = name.trim();
+        if name.is_empty() || name == old_name {
+            return Ok(());
+        }
+        let path = Path::new(&dir).join(name);
+        // Renaming over an existing entry would silently replace it
+        let result = if path.exists() {
+            Err(std::io::Error::new(
+                std::io::ErrorKind::AlreadyExists,
+                "an entry with that name already exists",
+            ))
         } else {
-            std::fs::File::create(path)?;
-            self.file_tree_refresh();
-            self.feedback = Feedback::Info("File created".to_string());
+            std::fs::rename(&old, &path)
+        };
+        if let Err(err) = result {
+            self.feedback = Feedback::Error(format!("Couldn't rename {old_name}: {err}"));
+            return Ok(());
         }
+        let new = FileTree::path_to_string(&path);
+        self.rename_open_documents(&old, &new);
+        self.file_tree_reload_dir(&dir);
+        self.file_tree_selection = Some(new);
+        self.feedback = Feedback::Info(format!("Renamed {old_name} to {name}"));
         Ok(())
     }
 
-    /// Delete a file
+    /// Point any open documents at or within a renamed path to where they now are
+    fn rename_open_documents(&mut self, old: &str, new: &str) {
+        for (ptr, idx) in self.files.all_documents(vec![]) {
+            let Ok(file) = self.file_at_mut(&ptr, idx) else {
+                continue;
+            };
+            let Some(file_name) = &file.doc.file_name else {
+                continue;
+            };
+            let path = get_path_identity(file_name);
+            let rest = if path == old {
+                Some("")
+            } else {
+                path.strip_prefix(old)
+                    .filter(|rest| rest.starts_with(std::path::MAIN_SEPARATOR))
+            };
+            if let Some(rest) = rest {
+                file.doc.file_name = Some(format!("{new}{rest}"));
+            }
+        }
+    }
+
+    /// Delete the selected file / folder (folders need confirming twice)
     pub fn file_tree_delete(&mut self) -> Result<()> {
-        if let Some(file_name) = &self.file_tree_selection.clone() {
-            let prompt =
-                self.prompt(format!("Are you sure you wish to delete {file_name} (y/n)"))?;
-            if prompt == "y" {
-                if file_or_dir(file_name) == "file" {
-                    std::fs::remove_file(file_name)?;
-                    self.file_tree_refresh();
-                    self.file_tree_select_up();
-                    self.feedback = Feedback::Info("File deleted".to_string());
-                } else {
-                    self.feedback = Feedback::Error(
-                        "Folders can't be deleted in Ox: too dangerous".to_string(),
-                    );
-                }
+        let Some(path) = self.file_tree_selection.clone().filter(|s| s != "..") else {
+            return Ok(());
+        };
+        let Some(dir) = self.file_tree.as_ref().and_then(|ft| ft.parent_of(&path)) else {
+            self.feedback =
+                Feedback::Error("The top of the file tree can't be deleted".to_string());
+            return Ok(());
+        };
+        let name = get_file_name(&path).unwrap_or(path.clone());
+        let is_dir = file_or_dir(&path) == "directory";
+        let confirm = (KMod::NONE, KCode::Char('y'));
+        let msg = format!("Delete {name}? Press y to confirm or esc to cancel");
+        if !self.confirm_with(&msg, confirm)? {
+            return Ok(());
+        }
+        let result = if is_dir {
+            let count = std::fs::read_dir(&path).map_or(0, Iterator::count);
+            let msg = format!(
+                "{name} is a folder with {count} entries, delete it and everything in it? Press y to confirm or esc to cancel"
+            );
+            if !self.confirm_with(&msg, confirm)? {
+                return Ok(());
             }
+            std::fs::remove_dir_all(&path)
+        } else {
+            std::fs::remove_file(&path)
+        };
+        if let Err(err) = result {
+            self.feedback = Feedback::Error(format!("Couldn't delete {name}: {err}"));
+            return Ok(());
         }
+        self.file_tree_select_up();
+        self.file_tree_reload_dir(&dir);
+        self.feedback = Feedback::Info(format!("Deleted {name}"));
         Ok(())
     }
 
@@ -660,5 +1021,196 @@ impl Editor {
         if let Some(ref mut file_tree) = self.file_tree {
             file_tree.refresh();
         }
+        self.file_tree_git_refresh();
+    }
+
+    /// Ask git for the status of the files in the file tree again (in the background)
+    pub fn file_tree_git_refresh(&mut self) {
+        if !self.file_tree_is_open() || !config!(self.config, file_tree).git_status {
+            return;
+        }
+        if let Some(root) = self
+            .file_tree
+            .as_ref()
+            .and_then(|ft| ft.flatten_recursive().first().cloned())
+        {
+            self.file_tree_git.refresh(&root);
+        }
+    }
+
+    /// The file tree as it is shown (narrowed down while it is being filtered)
+    pub fn file_tree_shown(&self) -> Option<&FileTree> {
+        match &self.file_tree_filter {
+            Some(filter) => Some(&filter.shown),
+            None => self.file_tree.as_ref(),
+        }
+    }
+
+    /// Start filtering the file tree
+    pub fn file_tree_filter_start(&mut self) {
+        if let Some(file_tree) = &self.file_tree {
+            let selection = self.file_tree_selection.clone();
+            self.file_tree_filter = Some(TreeFilter::new(file_tree, selection));
+        }
+    }
+
+    /// Handle a key press while the file tree is being filtered
+    pub fn file_tree_filter_key(&mut self, modifiers: KMod, code: KCode) -> Result<()> {
+        let Some(filter) = &mut self.file_tree_filter else {
+            return Ok(());
+        };
+        match (modifiers, code) {
+            (KMod::NONE | KMod::SHIFT, KCode::Char(c)) => {
+                filter.query.push(c);
+                self.file_tree_filter_update();
+            }
+            (KMod::NONE, KCode::Backspace) => {
+                filter.query.pop();
+                self.file_tree_filter_update();
+            }
+            (KMod::NONE, KCode::Up) => self.file_tree_select_up(),
+            (KMod::NONE, KCode::Down) => self.file_tree_select_down(),
+            (KMod::NONE, KCode::Enter) => self.file_tree_filter_accept()?,
+            (KMod::NONE, KCode::Esc) => self.file_tree_filter_clear(),
+            _ => (),
+        }
+        Ok(())
+    }
+
+    /// Narrow the file tree down to the query, keeping the selection on a match
+    fn file_tree_filter_update(&mut self) {
+        let (Some(filter), Some(file_tree)) = (&mut self.file_tree_filter, &self.file_tree) else {
+            return;
+        };
+        filter.update(file_tree);
+        let still_shown = self
+            .file_tree_selection
+            .as_ref()
+            .is_some_and(|sel| sel != ".." && filter.shown.row_of(sel).is_some());
+        if !still_shown {
+            self.file_tree_selection = filter
+                .shown
+                .first_file()
+                .map(ToString::to_string)
+                .or_else(|| filter.selection.clone());
+        }
+    }
+
+    /// Stop filtering, going back to what was selected beforehand
+    pub fn file_tree_filter_clear(&mut self) {
+        if let Some(filter) = self.file_tree_filter.take() {
+            self.file_tree_selection = filter.selection;
+        }
+    }
+
+    /// Open the selected match (or the first one if a file isn't selected) and stop filtering
+    fn file_tree_filter_accept(&mut self) -> Result<()> {
+        let Some(filter) = self.file_tree_filter.take() else {
+            return Ok(());
+        };
+        let target = self
+            .file_tree_selection
+            .clone()
+            .filter(|sel| file_or_dir(sel) == "file")
+            .or_else(|| filter.shown.first_file().map(ToString::to_string));
+        let Some(target) = target else {
+            self.file_tree_selection = filter.selection;
+            self.feedback = Feedback::Warning("No matching files".to_string());
+            return Ok(());
+        };
+        self.file_tree_selection = Some(target.clone());
+        self.file_tree_open_file()?;
+        // The match may be in a directory that isn't expanded in the tree itself
+        if self
+            .file_tree
+            .as_ref()
+            .and_then(|ft| ft.row_of(&target))
+            .is_none()
+        {
+            self.file_tree_selection = filter.selection;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A tree of `/r` with `a` expanded, `b` collapsed and a file `z` last
+    fn tree() -> FileTree {
+        let file = |path: &str| FileTree::File {
+            path: path.to_string(),
+        };
+        FileTree::Dir {
+            path: "/r".to_string(),
+            files: Some(vec![
+                FileTree::Dir {
+                    path: "/r/a".to_string(),
+                    files: Some(vec![file("/r/a/x"), file("/r/a/y")]),
+                },
+                FileTree::Dir {
+                    path: "/r/b".to_string(),
+                    files: None,
+                },
+                file("/r/z"),
+            ]),
+        }
+    }
+
+    #[test]
+    fn rows() {
+        let tree = tree();
+        let flat = tree.flatten();
+        // (path, row it is shown on, directory it sits in)
+        let cases = [
+            ("..", Some(0), None),
+            ("/r", Some(1), None),
+            ("/r/a", Some(2), Some("/r")),
+            ("/r/a/x", Some(3), Some("/r/a")),
+            ("/r/a/y", Some(4), Some("/r/a")),
+            ("/r/b", Some(5), Some("/r")),
+            ("/r/z", Some(6), Some("/r")),
+            // Inside a collapsed directory, and not in the tree at all
+            ("/r/b/hidden", None, None),
+            ("/elsewhere", None, None),
+        ];
+        for (path, row, parent) in cases {
+            assert_eq!(tree.row_of(path), row, "row of {path}");
+            assert_eq!(tree.parent_of(path).as_deref(), parent, "parent of {path}");
+            if let Some(row) = row {
+                assert_eq!(flat[row], path);
+            }
+        }
+        assert_eq!(flat.len(), 7);
+        assert_eq!(flat.get(7), None);
+    }
+
+    #[test]
+    fn scrolling() {
+        // (scroll, selected row, height, rows, scroll after)
+        let cases = [
+            // Already in view
+            (0, Some(3), 5, 20, 0),
+            (4, Some(8), 5, 20, 4),
+            // Above and below the view
+            (6, Some(2), 5, 20, 2),
+            (0, Some(7), 5, 20, 3),
+            // The last row sits at the bottom of the view
+            (0, Some(19), 5, 20, 15),
+            (15, Some(19), 5, 20, 15),
+            // Nothing selected, or a tree that fits, or has shrunk since
+            (3, None, 5, 20, 3),
+            (2, Some(1), 10, 4, 0),
+            (12, None, 5, 10, 5),
+            (0, Some(0), 5, 0, 0),
+        ];
+        for (scroll, selected, height, rows, after) in cases {
+            assert_eq!(
+                tree_scroll(scroll, selected, height, rows),
+                after,
+                "scroll {scroll}, row {selected:?}, height {height}, {rows} rows"
+            );
+        }
     }
 }
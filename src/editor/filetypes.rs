@@ -34,16 +34,15 @@ impl FileTypes {
     }
 
     pub fn identify_from_path(&self, path: &str) -> Option<FileType> {
-        if let Some(e) = Path::new(&path).extension() {
-            let file_name = get_file_name(path).unwrap_or_default();
-            let extension = e.to_str().unwrap_or_default().to_string();
-            for t in &self.types {
-                if t.fits(&extension, &file_name, "") {
-                    return Some(t.clone());
-                }
-            }
-        }
-        None
+        let file_name = get_file_name(path).unwrap_or_default();
+        let extension = Path::new(&path)
+            .extension()
+            .map(|e| e.to_str().unwrap_or_default().to_string())
+            .unwrap_or_default();
+        self.types
+            .iter()
+            .find(|t| t.fits(&extension, &file_name, ""))
+            .cloned()
     }
 
     pub fn get_name(&self, name: &str) -> Option<FileType> {
@@ -66,6 +65,8 @@ pub struct FileType {
     pub modelines: Vec<String>,
     /// The colour associated with this file type
     pub color: String,
+    /// The columns marked down documents of this type, to show how long lines should be
+    pub rulers: Vec<usize>,
 }
 
 impl Default for FileType {
@@ -77,6 +78,7 @@ impl Default for FileType {
             extensions: vec![],
             modelines: vec![],
             color: "grey".to_string(),
+            rulers: vec![],
         }
     }
 }
@@ -98,17 +100,41 @@ impl FileType {
 
     /// Identify the correct highlighter to use
     pub fn get_highlighter(&self, config: &Config, tab_width: usize) -> Highlighter {
-        if let Some(highlighter) = config!(config, syntax).user_rules.get(&self.name) {
+        let syntax = config!(config, syntax);
+        let mut highlighter = if let Some(highlighter) = syntax.user_rules.get(&self.name) {
             // The user has defined their own syntax highlighter for this file type
             highlighter.clone()
         } else {
             // The user hasn't defined their own syntax highlighter, use synoptic builtins
-            for ext in &self.extensions {
-                if let Some(h) = from_extension(ext, tab_width) {
-                    return h;
-                }
-            }
-            Highlighter::new(tab_width)
+            self.extensions
+                .iter()
+                .find_map(|ext| from_extension(ext, tab_width))
+                .or_else(|| builtin(&self.name, tab_width))
+                .unwrap_or_else(|| Highlighter::new(tab_width))
+        };
+        // Add any rules the user has added on top of this language
+        for rule in syntax.user_extensions.get(&self.name).into_iter().flatten() {
+            rule.apply(&mut highlighter);
+        }
+        highlighter
+    }
+}
+
+/// Highlighters for the file types that synoptic doesn't cover
+fn builtin(name: &str, tab_width: usize) -> Option<Highlighter> {
+    let mut highlighter = Highlighter::new(tab_width);
+    match name {
+        // Lines starting with a hash are left out of the message, so they are dimmed
+        "Git Commit" => highlighter.keyword("comment", "^#.*$"),
+        "Git Rebase" => {
+            highlighter.keyword("comment", "^#.*$");
+            highlighter.keyword(
+                "keyword",
+                r"^\s*(pick|p|reword|r|edit|e|squash|s|fixup|f|exec|x|break|b|drop|d|label|l|reset|t|merge|m|update-ref|u)\b",
+            );
+            highlighter.keyword("digit", r"\b[0-9a-f]{7,40}\b");
         }
+        _ => return None,
     }
+    Some(highlighter)
 }
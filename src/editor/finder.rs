@@ -0,0 +1,437 @@
+/// A project-wide fuzzy file finder, for opening files by typing part of their path
+use crate::error::{OxError, Result};
+use crate::{config, Feedback};
+use crossterm::{
+    event::{KeyCode as KCode, KeyModifiers as KMod},
+    style::{Attribute, SetAttribute, SetBackgroundColor as Bg, SetForegroundColor as Fg},
+};
+use kaolinite::fuzzy::{highlight_spans, FuzzyMatch, FuzzyRanker};
+use kaolinite::utils::{get_cwd, width, width_char, Loc};
+use mlua::Lua;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{channel, Receiver, TryRecvError};
+use std::time::{Duration, Instant};
+
+use super::overlay::OverlayRegion;
+use super::picker::PickerKey;
+use super::Editor;
+
+/// The most files that will be indexed in a project
+const MAX_INDEXED: usize = 100_000;
+/// The most results shown in the finder at once
+const MAX_RESULTS: usize = 15;
+/// How often the index is refreshed while the editor is idle (once the finder has been used)
+const REFRESH_PERIOD: Duration = Duration::from_secs(60);
+/// Directories that are never worth indexing (even when no ignore file mentions them)
+const SKIPPED_DIRS: [&str; 3] = [".git", "target", "node_modules"];
+
+/// A rule from an ignore file
+#[derive(Debug, Clone)]
+struct IgnoreRule {
+    /// The directory containing the ignore file (relative to the project root)
+    base: String,
+    pattern: String,
+    /// Anchored patterns match against the whole path, rather than just the file name
+    anchored: bool,
+    dir_only: bool,
+    negated: bool,
+}
+
+impl IgnoreRule {
+    /// Read a rule from a line of a .gitignore file
+    fn parse(base: &str, line: &str) -> Option<Self> {
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with('#') {
+            return None;
+        }
+        let (negated, line) = match line.strip_prefix('!') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        let (dir_only, line) = match line.strip_suffix('/') {
+            Some(rest) => (true, rest),
+            None => (false, line),
+        };
+        let anchored = line.contains('/');
+        let pattern = line.trim_start_matches('/').to_string();
+        if pattern.is_empty() {
+            return None;
+        }
+        Some(Self {
+            base: base.to_string(),
+            pattern,
+            anchored,
+            dir_only,
+            negated,
+        })
+    }
+
+    /// Check if this rule applies to a path (relative to the project root)
+    fn matches(&self, path: &str, is_dir: bool) -> bool {
+        if self.dir_only && !is_dir {
+            return false;
+        }
+        let path = if self.base.is_empty() {
+            Some(path)
+        } else {
+            path.strip_prefix(&self.base)
+                .and_then(|p| p.strip_prefix('/'))
+        };
+        let Some(path) = path else {
+            return false;
+        };
+        if self.anchored {
+            glob_match(&self.pattern, path)
+        } else {
+            let name = path.rsplit('/').next().unwrap_or(path);
+            glob_match(&self.pattern, name)
+        }
+    }
+}
+
+/// Match text against a glob pattern (* and ? don't cross a /, ** does)
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    glob_match_from(&pattern, &text)
+}
+
+/// Match characters against glob pattern characters
+fn glob_match_from(pattern: &[char], text: &[char]) -> bool {
+    match pattern {
+        [] => text.is_empty(),
+        ['*', '*', '/', rest @ ..] => (0..=text.len())
+            .any(|i| (i == 0 || text[i - 1] == '/') && glob_match_from(rest, &text[i..])),
+        ['*', '*', rest @ ..] => (0..=text.len()).any(|i| glob_match_from(rest, &text[i..])),
+        ['*', rest @ ..] => {
+            let reach = text.iter().position(|c| *c == '/').unwrap_or(text.len());
+            (0..=reach).any(|i| glob_match_from(rest, &text[i..]))
+        }
+        ['?', rest @ ..] => text
+            .split_first()
+            .is_some_and(|(c, text)| *c != '/' && glob_match_from(rest, text)),
+        [p, rest @ ..] => text
+            .split_first()
+            .is_some_and(|(c, text)| c == p && glob_match_from(rest, text)),
+    }
+}
+
+/// Check if a path should be left out of the index, the last rule to match wins
+fn is_ignored(rules: &[IgnoreRule], path: &str, is_dir: bool) -> bool {
+    rules
+        .iter()
+        .rev()
+        .find(|rule| rule.matches(path, is_dir))
+        .is_some_and(|rule| !rule.negated)
+}
+
+/// Walk a project, finding the paths of all files (relative to the root) that aren't ignored
+pub fn walk(root: &Path) -> Vec<String> {
+    let mut result = vec![];
+    let mut rules = vec![];
+    // Directories left to visit, along with how many ignore rules apply to them
+    let mut stack = vec![(String::new(), 0)];
+    while let Some((dir, n_rules)) = stack.pop() {
+        rules.truncate(n_rules);
+        let full = root.join(&dir);
+        if let Ok(ignore) = std::fs::read_to_string(full.join(".gitignore")) {
+            rules.extend(ignore.lines().filter_map(|l| IgnoreRule::parse(&dir, l)));
+        }
+        let Ok(entries) = std::fs::read_dir(&full) else {
+            continue;
+        };
+        let mut entries: Vec<(String, bool)> = entries
+            .filter_map(std::result::Result::ok)
+            .filter_map(|e| {
+                let is_dir = e.file_type().ok()?.is_dir();
+                Some((e.file_name().to_string_lossy().to_string(), is_dir))
+            })
+            .collect();
+        entries.sort();
+        for (name, is_dir) in entries.into_iter().rev() {
+            let path = if dir.is_empty() {
+                name.clone()
+            } else {
+                format!("{dir}/{name}")
+            };
+            if (is_dir && SKIPPED_DIRS.contains(&name.as_str()))
+                || is_ignored(&rules, &path, is_dir)
+            {
+                continue;
+            }
+            if is_dir {
+                stack.push((path, rules.len()));
+            } else if result.len() < MAX_INDEXED {
+                result.push(path);
+            }
+        }
+        if result.len() >= MAX_INDEXED {
+            break;
+        }
+    }
+    result.sort();
+    result
+}
+
+/// Work out the root of the project, this is the closest directory holding a repository
+/// (returns None if the directory isn't in a repository)
+pub fn project_root(dir: &str) -> Option<String> {
+    PathBuf::from(dir)
+        .ancestors()
+        .find(|d| d.join(".git").exists())
+        .map(|d| d.to_string_lossy().to_string())
+}
+
+/// Keeps a cached list of the files in a project, walking it in the background
+#[derive(Debug, Default)]
+pub struct FileIndex {
+    /// The root of the project being indexed
+    pub root: String,
+    /// Paths of files in the project, relative to the root
+    pub files: Vec<String>,
+    /// Will be some when a walk of the project is in progress
+    pending: Option<Receiver<Vec<String>>>,
+    /// When the project was last walked
+    walked: Option<Instant>,
+    /// Set once the finder has been opened, after which the index is kept fresh
+    used: bool,
+    /// Whether the root is a repository (other directories aren't walked until the finder is used)
+    repository: bool,
+    ranker: FuzzyRanker,
+}
+
+impl FileIndex {
+    /// Create an index for the project containing a directory
+    pub fn new(dir: &str) -> Self {
+        let root = project_root(dir);
+        Self {
+            repository: root.is_some(),
+            root: root.unwrap_or(dir.to_string()),
+            ..Self::default()
+        }
+    }
+
+    /// Returns true if the project is being walked for the first time
+    pub fn indexing(&self) -> bool {
+        self.pending.is_some() && self.walked.is_none()
+    }
+
+    /// Start walking the project in the background (unless a walk is already in progress)
+    pub fn refresh(&mut self) {
+        if self.pending.is_some() {
+            return;
+        }
+        let (tx, rx) = channel();
+        let root = PathBuf::from(&self.root);
+        std::thread::spawn(move || {
+            let _ = tx.send(walk(&root));
+        });
+        self.pending = Some(rx);
+    }
+
+    /// Pick up the results of a finished walk, returns true if the list of files changed
+    pub fn poll(&mut self) -> bool {
+        let Some(rx) = &self.pending else {
+            return false;
+        };
+        let files = match rx.try_recv() {
+            Ok(files) => files,
+            Err(TryRecvError::Empty) => return false,
+            Err(TryRecvError::Disconnected) => vec![],
+        };
+        self.pending = None;
+        self.walked = Some(Instant::now());
+        if files == self.files {
+            return false;
+        }
+        self.files = files;
+        self.ranker.reset();
+        true
+    }
+
+    /// Called while the editor is idle, walks the project when the index is missing or stale
+    pub fn idle_tick(&mut self) {
+        self.poll();
+        let stale = match self.walked {
+            None => self.used || self.repository,
+            Some(at) => self.used && at.elapsed() >= REFRESH_PERIOD,
+        };
+        if stale && !self.root.is_empty() {
+            self.refresh();
+        }
+    }
+
+    /// Find the best matching files for a pattern
+    pub fn search(&mut self, pattern: &str) -> Vec<(usize, FuzzyMatch)> {
+        self.ranker.rank(pattern, &self.files, MAX_RESULTS)
+    }
+}
+
+/// Split a line number off the end of the finder's input (e.g. src/main.rs:42)
+fn split_line(input: &str) -> (&str, Option<usize>) {
+    if let Some((path, line)) = input.rsplit_once(':') {
+        if let Ok(line) = line.parse::<usize>() {
+            return (path, Some(line));
+        }
+    }
+    (input, None)
+}
+
+impl Editor {
+    /// Open the fuzzy file finder and open the file that the user picks
+    pub fn find_file(&mut self, lua: &Lua) -> Result<()> {
+        self.needs_terminal()?;
+        if self.file_index.root.is_empty() {
+            self.file_index = FileIndex::new(&get_cwd().unwrap_or(".".to_string()));
+        }
+        self.file_index.used = true;
+        self.file_index.poll();
+        if self.file_index.walked.is_none() {
+            self.file_index.refresh();
+        }
+        // What has been typed, the files that match it and whether they need finding again
+        let state: (String, Vec<(usize, FuzzyMatch)>, bool) = (String::new(), vec![], true);
+        self.pick(
+            lua,
+            state,
+            0,
+            |editor, (input, results, stale), selected| {
+                // Pick up the index as soon as it is ready
+                if editor.file_index.poll() {
+                    *stale = true;
+                }
+                if *stale {
+                    *results = editor.file_index.search(split_line(input).0);
+                    *selected = (*selected).min(results.len().saturating_sub(1));
+                    *stale = false;
+                }
+                editor.render_finder(input, results, *selected)?;
+                Ok(results.len())
+            },
+            |_, _, (input, _, stale), key, _| {
+                match key {
+                    // Edit the input
+                    (KMod::NONE, KCode::Backspace) => {
+                        input.pop();
+                    }
+                    (KMod::NONE | KMod::SHIFT, KCode::Char(c)) => input.push(c),
+                    _ => return Ok(None),
+                }
+                *stale = true;
+                Ok(Some(PickerKey::Stay))
+            },
+            |editor, (input, results, _), selected| {
+                let line = split_line(&input).1;
+                let Some((idx, _)) = results.get(selected) else {
+                    editor.feedback = Feedback::Warning("No matching files".to_string());
+                    return Ok(());
+                };
+                let path = Path::new(&editor.file_index.root).join(&editor.file_index.files[*idx]);
+                let path = path.to_string_lossy().to_string();
+                // Files that are already open are moved to instead
+                match editor.open(&path) {
+                    Ok(()) => editor.next(),
+                    Err(OxError::AlreadyOpen { .. }) => (),
+                    Err(err) => return Err(err),
+                }
+                editor.update_cwd();
+                if let (Some(line), Some(doc)) = (line, editor.try_doc_mut()) {
+                    let y = line
+                        .saturating_sub(1)
+                        .min(doc.len_lines().saturating_sub(1));
+                    doc.move_to(&Loc { x: 0, y });
+                }
+                Ok(())
+            },
+        )
+    }
+
+    /// Draw the fuzzy file finder over the bottom of the document
+    #[allow(clippy::similar_names)]
+    fn render_finder(
+        &mut self,
+        input: &str,
+        results: &[(usize, FuzzyMatch)],
+        selected: usize,
+    ) -> Result<()> {
+        let size = self.terminal.size()?;
+        let colors = config!(self.config, colors);
+        let bg = Bg(colors.file_tree_bg.to_color()?);
+        let fg = Fg(colors.file_tree_fg.to_color()?);
+        let selection_bg = Bg(colors.file_tree_selection_bg.to_color()?);
+        let selection_fg = Fg(colors.file_tree_selection_fg.to_color()?);
+        let highlight = Fg(colors.highlight.to_color()?);
+        let editor_bg = Bg(colors.editor_bg.to_color()?);
+        let editor_fg = Fg(colors.editor_fg.to_color()?);
+        let tab_width = config!(self.config, document).tab_width;
+        // Render the results (best match at the bottom, next to the input)
+        let rows = MAX_RESULTS.min(size.h.saturating_sub(1));
+        let mut region = OverlayRegion {
+            rows: size.h - rows..size.h + 1,
+            cols: 0..size.w,
+            close: None,
+            items: vec![],
+        };
+        for row in 0..rows {
+            let y = size.h - rows + row;
+            if rows - row - 1 < results.len() {
+                region.items.push((y, rows - row - 1));
+            }
+            let line = match results.get(rows - row - 1) {
+                Some((idx, m)) => {
+                    let (bg, fg) = if rows - row - 1 == selected {
+                        (selection_bg, selection_fg)
+                    } else {
+                        (bg, fg)
+                    };
+                    let path = &self.file_index.files[*idx];
+                    let spans = highlight_spans(&m.indices);
+                    let mut line = format!("{bg}{fg} ");
+                    let mut total = 1;
+                    for (i, c) in path.chars().enumerate() {
+                        total += width_char(&c, tab_width);
+                        if total >= size.w {
+                            break;
+                        }
+                        if spans.iter().any(|s| s.contains(&i)) {
+                            let bold = SetAttribute(Attribute::Bold);
+                            let normal = SetAttribute(Attribute::NormalIntensity);
+                            line += &format!("{bold}{highlight}{c}{fg}{normal}");
+                        } else {
+                            line.push(c);
+                        }
+                    }
+                    line + &" ".repeat(size.w.saturating_sub(total))
+                }
+                None => {
+                    let msg = if row + 1 < rows {
+                        ""
+                    } else if self.file_index.indexing() {
+                        " indexing…"
+                    } else if !input.is_empty() {
+                        " no matching files"
+                    } else {
+                        ""
+                    };
+                    let padding = " ".repeat(size.w.saturating_sub(width(msg, tab_width)));
+                    format!("{bg}{fg}{msg}{padding}")
+                }
+            };
+            self.terminal.draw_line(y, line);
+        }
+        self.render_cache.overlay = Some(region);
+        // Render the input
+        let prompt = "Find file: ";
+        let padding = " ".repeat(
+            size.w
+                .saturating_sub(prompt.len() + width(input, tab_width)),
+        );
+        let line = format!("{editor_bg}{editor_fg}{prompt}{input}{padding}");
+        self.terminal.draw_line(size.h, line);
+        self.terminal
+            .goto(prompt.len() + width(input, tab_width), size.h);
+        self.terminal.show_cursor();
+        self.terminal.flush()?;
+        Ok(())
+    }
+}
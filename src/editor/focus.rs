@@ -0,0 +1,146 @@
+/// Noticing when the terminal gains and loses focus (for terminals that report it):
+/// periodic work stops while ox is in the background, and documents whose files other programs
+/// have changed are picked up when it comes back (noticed the same way the tab line notices them)
+use crate::config;
+use crate::error::Result;
+use crate::ui::Feedback;
+use kaolinite::document::unrepresentable;
+use kaolinite::utils::get_file_name;
+use kaolinite::Document;
+
+use super::{DiskState, Editor};
+
+/// Whether the terminal has focus
+#[derive(Debug)]
+pub struct Focus {
+    /// Terminals that don't report focus are always taken to have it
+    pub focused: bool,
+}
+
+impl Default for Focus {
+    fn default() -> Self {
+        Self { focused: true }
+    }
+}
+
+impl Editor {
+    /// Deal with the terminal gaining or losing focus
+    pub fn handle_focus(&mut self, gained: bool) {
+        if gained {
+            self.focus_gained();
+        } else {
+            self.focus_lost();
+        }
+    }
+
+    /// Save what can be saved (if asked to)
+    fn focus_lost(&mut self) {
+        self.focus.focused = false;
+        if config!(self.config, document).save_on_focus_lost {
+            self.save_quietly();
+        }
+    }
+
+    /// Pick up files that other programs have changed since they were opened or saved,
+    /// and draw everything again (the terminal may have been drawn over or resized)
+    fn focus_gained(&mut self) {
+        self.focus.focused = true;
+        self.check_on_disk();
+        let (mut reloaded, mut conflicts, mut failures) = (vec![], vec![], vec![]);
+        let mut done = vec![];
+        for at in self.files.all_documents(vec![]) {
+            if done.contains(&at) {
+                continue;
+            }
+            let Ok(file) = self.file_at(&at.0, at.1) else {
+                continue;
+            };
+            let Some(file_name) = file.doc.file_name.clone() else {
+                continue;
+            };
+            if file.on_disk.state != DiskState::Modified {
+                continue;
+            }
+            // Other panes showing the same file are dealt with along with this one
+            done.append(&mut self.views_of(&at));
+            let name = get_file_name(&file_name).unwrap_or(file_name);
+            // Changes made here are never thrown away
+            if file.unsaved_changes() {
+                conflicts.push(name);
+                continue;
+            }
+            if file.doc.binary.is_some() {
+                continue;
+            }
+            match self.reload_from_disk(&at.0, at.1) {
+                Ok(()) => reloaded.push(name),
+                Err(err) => failures.push(format!("{name} ({err})")),
+            }
+        }
+        if !failures.is_empty() {
+            self.feedback = Feedback::Error(format!("Failed to reload {}", failures.join(", ")));
+        } else if !conflicts.is_empty() {
+            self.feedback = Feedback::Warning(format!(
+                "Changed on the disk but not reloaded, as there are unsaved changes: {}",
+                conflicts.join(", ")
+            ));
+        } else if !reloaded.is_empty() {
+            self.feedback = Feedback::Info(format!(
+                "Reloaded what changed on the disk: {}",
+                reloaded.join(", ")
+            ));
+        }
+        self.terminal.invalidate();
+        self.needs_rerender = true;
+    }
+
+    /// Replace a document with what is on the disk now (which can be undone)
+    fn reload_from_disk(&mut self, ptr: &[usize], idx: usize) -> Result<()> {
+        self.wait_for_document_at(ptr, idx);
+        let size = self.document_size()?;
+        let file = self.file_at_mut(ptr, idx)?;
+        let Some(file_name) = file.doc.file_name.clone() else {
+            return Ok(());
+        };
+        let text = Document::open(size, &file_name)?.file.to_string();
+        let doc = &mut file.doc;
+        doc.batch(|doc| doc.replace_contents(&text));
+        doc.event_mgmt.disk_write(&doc.take_snapshot());
+        self.reload_highlight_at(ptr, idx)?;
+        self.share_changes(&(ptr.to_vec(), idx), true);
+        self.note_on_disk(ptr, idx);
+        Ok(())
+    }
+
+    /// Save the documents with unsaved changes that can be saved without asking anything
+    /// (ones that would overwrite other changes, or lose characters, wait to be saved by hand)
+    fn save_quietly(&mut self) {
+        for at in self.files.all_documents(vec![]) {
+            self.wait_for_document_at(&at.0, at.1);
+            let Ok(file) = self.file_at(&at.0, at.1) else {
+                continue;
+            };
+            let Some(file_name) = file.doc.file_name.clone() else {
+                continue;
+            };
+            let doc = &file.doc;
+            let lossy = !unrepresentable(&doc.file, doc.info.encoding, 1).is_empty();
+            if !file.unsaved_changes() || !file.savable() || doc.info.read_only || lossy {
+                continue;
+            }
+            if self.save_conflict(&at, &file_name).is_some() {
+                continue;
+            }
+            let Ok(file) = self.file_at_mut(&at.0, at.1) else {
+                continue;
+            };
+            if let Err(err) = file.doc.save() {
+                self.feedback = Feedback::Error(format!("Failed to save on losing focus: {err}"));
+                continue;
+            }
+            file.doc.commit();
+            self.saved_at(&at.0, at.1);
+        }
+        self.file_tree_git_refresh();
+    }
+}
@@ -0,0 +1,173 @@
+/// Working out the git status of files, for decorating the file tree
+use std::collections::HashMap;
+use std::path::Path;
+use std::process::Command;
+use std::sync::mpsc::{channel, Receiver, TryRecvError};
+
+/// The state of a file in a repository
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum GitState {
+    /// Changed in the working tree (and not yet staged)
+    Modified,
+    /// Changes are staged for the next commit
+    Staged,
+    Untracked,
+    Ignored,
+}
+
+impl GitState {
+    /// The marker shown after a file with this state in the file tree
+    /// (directories just show that something within them has changed)
+    pub fn marker(self, is_dir: bool) -> &'static str {
+        match (self, is_dir) {
+            (Self::Ignored, _) => "",
+            (_, true) => "•",
+            (Self::Modified, _) => "M",
+            (Self::Staged, _) => "S",
+            (Self::Untracked, _) => "U",
+        }
+    }
+
+    /// Work out the state from the two status letters of a `git status --porcelain` entry
+    fn from_letters(index: char, worktree: char) -> Option<Self> {
+        match (index, worktree) {
+            ('?', '?') => Some(Self::Untracked),
+            ('!', '!') => Some(Self::Ignored),
+            (_, ' ') if index != ' ' => Some(Self::Staged),
+            (_, ' ') => None,
+            _ => Some(Self::Modified),
+        }
+    }
+}
+
+/// The states of the files in a repository
+#[derive(Debug, Default, Clone)]
+struct Statuses {
+    /// The states of files and of untracked / ignored directories (by full path)
+    paths: HashMap<String, GitState>,
+    /// The most pressing state of anything within each directory that has changes
+    dirs: HashMap<String, GitState>,
+}
+
+/// Turn a path relative to the repository root (as git writes it) into a full path
+fn full_path(root: &Path, relative: &str) -> String {
+    let mut path = root.to_path_buf();
+    path.extend(relative.split('/').filter(|part| !part.is_empty()));
+    path.to_string_lossy().to_string()
+}
+
+/// Read the output of `git status --porcelain -z --ignored`
+fn parse_porcelain(root: &Path, output: &str) -> Statuses {
+    let mut result = Statuses::default();
+    let mut entries = output.split('\0');
+    while let Some(entry) = entries.next() {
+        let mut letters = entry.chars();
+        let (Some(index), Some(worktree), Some(' ')) =
+            (letters.next(), letters.next(), letters.next())
+        else {
+            continue;
+        };
+        // Renames and copies are followed by the path they came from
+        if matches!(index, 'R' | 'C') {
+            entries.next();
+        }
+        let Some(state) = GitState::from_letters(index, worktree) else {
+            continue;
+        };
+        let path = full_path(root, &entry[3..]);
+        if state != GitState::Ignored {
+            for dir in Path::new(&path).ancestors().skip(1) {
+                if !dir.starts_with(root) {
+                    break;
+                }
+                let dir = dir.to_string_lossy().to_string();
+                let aggregate = result.dirs.entry(dir).or_insert(state);
+                *aggregate = (*aggregate).min(state);
+            }
+        }
+        result.paths.insert(path, state);
+    }
+    result
+}
+
+/// Ask git for the states of the files in the repository containing a directory
+/// (returns None if the directory isn't in a repository or git isn't installed)
+fn gather(dir: &str) -> Option<Statuses> {
+    let run = |args: &[&str]| {
+        let output = Command::new("git")
+            .arg("-C")
+            .arg(dir)
+            .args(args)
+            .output()
+            .ok()?;
+        output
+            .status
+            .success()
+            .then(|| String::from_utf8_lossy(&output.stdout).to_string())
+    };
+    let root = run(&["rev-parse", "--show-toplevel"])?;
+    let root = std::fs::canonicalize(root.trim()).ok()?;
+    // Paths in the file tree don't have the prefix windows gives canonical paths
+    let root = root
+        .to_string_lossy()
+        .trim_start_matches("\\\\?\\")
+        .to_string();
+    let output = run(&["status", "--porcelain", "-z", "--ignored"])?;
+    Some(parse_porcelain(Path::new(&root), &output))
+}
+
+/// Keeps the git status of the files in the file tree, asking git in the background
+#[derive(Debug, Default)]
+pub struct GitStatus {
+    statuses: Statuses,
+    /// Will be some while git is being asked
+    pending: Option<Receiver<Option<Statuses>>>,
+}
+
+impl GitStatus {
+    /// Start finding the states of files afresh (the old states are kept until this finishes)
+    pub fn refresh(&mut self, dir: &str) {
+        let (tx, rx) = channel();
+        let dir = dir.to_string();
+        std::thread::spawn(move || {
+            let _ = tx.send(gather(&dir));
+        });
+        self.pending = Some(rx);
+    }
+
+    /// Pick up the result of a finished refresh, returns true if the states have changed
+    pub fn poll(&mut self) -> bool {
+        let Some(rx) = &self.pending else {
+            return false;
+        };
+        let statuses = match rx.try_recv() {
+            Ok(statuses) => statuses.unwrap_or_default(),
+            Err(TryRecvError::Empty) => return false,
+            Err(TryRecvError::Disconnected) => Statuses::default(),
+        };
+        self.pending = None;
+        let changed = statuses.paths != self.statuses.paths;
+        self.statuses = statuses;
+        changed
+    }
+
+    /// Find the state of a file, or the most pressing state of anything within a directory
+    pub fn state_of(&self, path: &str, is_dir: bool) -> Option<GitState> {
+        if let Some(state) = self.statuses.paths.get(path) {
+            return Some(*state);
+        }
+        // Everything within an untracked or ignored directory shares its state
+        let inherited = Path::new(path).ancestors().skip(1).find_map(|dir| {
+            let state = self.statuses.paths.get(dir.to_str()?)?;
+            matches!(state, GitState::Untracked | GitState::Ignored).then_some(*state)
+        });
+        if inherited.is_some() {
+            return inherited;
+        }
+        if is_dir {
+            self.statuses.dirs.get(path).copied()
+        } else {
+            None
+        }
+    }
+}
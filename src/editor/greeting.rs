@@ -0,0 +1,179 @@
+/// The greeting shown in empty panes: its message, with entries below it that are chosen
+/// by pressing their key or clicking on them
+use crate::config::GreetingAction;
+use crate::error::{OxError, Result};
+use crate::{config, Feedback};
+use crossterm::event::{
+    Event as CEvent, KeyCode as KCode, KeyEvent, KeyEventKind, KeyModifiers as KMod, MouseButton,
+    MouseEvent, MouseEventKind,
+};
+use kaolinite::utils::{trim, width};
+use kaolinite::Loc;
+use mlua::Lua;
+
+use super::{Action, Editor};
+
+/// Blank rows left between the message and the entries
+const ENTRY_GAP: usize = 1;
+
+/// A row of the greeting, as it is laid out in a pane
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct GreetingRow {
+    /// The column the row starts at
+    pub start: usize,
+    pub text: String,
+    pub highlighted: bool,
+    /// The key of the entry on this row (if there is one)
+    pub entry: Option<char>,
+}
+
+/// Lay out the greeting for a pane `w` wide, giving its rows and whether it is plain text.
+/// The message is centred line by line, and the entries are centred as one block below it,
+/// but if any of it doesn't fit, it all starts at the left edge (and is cut off at the right)
+pub fn layout_greeting(
+    message: &str,
+    highlights: &[usize],
+    entries: &[(char, String)],
+    w: usize,
+) -> (Vec<GreetingRow>, bool) {
+    let mut rows: Vec<GreetingRow> = message
+        .split('\n')
+        .enumerate()
+        .map(|(y, line)| GreetingRow {
+            text: line.to_string(),
+            highlighted: highlights.contains(&y),
+            ..GreetingRow::default()
+        })
+        .collect();
+    if !entries.is_empty() {
+        rows.extend((0..ENTRY_GAP).map(|_| GreetingRow::default()));
+    }
+    // Labels are padded out to the longest so the keys line up
+    let label_width = entries
+        .iter()
+        .map(|(_, label)| width(label, 4))
+        .max()
+        .unwrap_or(0);
+    let block_start = rows.len();
+    rows.extend(entries.iter().map(|(key, label)| GreetingRow {
+        text: format!(
+            "{key}  {label}{}",
+            " ".repeat(label_width - width(label, 4))
+        ),
+        entry: Some(*key),
+        ..GreetingRow::default()
+    }));
+    let plain = rows.iter().any(|row| width(&row.text, 4) > w);
+    if plain {
+        for row in &mut rows {
+            row.text = trim(&row.text, 0, w, 4);
+            row.highlighted = false;
+        }
+    } else {
+        let block_width = rows[block_start..]
+            .iter()
+            .map(|row| width(&row.text, 4))
+            .max()
+            .unwrap_or(0);
+        for row in &mut rows[..block_start] {
+            row.start = (w - width(&row.text, 4)) / 2;
+        }
+        for row in &mut rows[block_start..] {
+            row.start = (w - block_width) / 2;
+        }
+    }
+    (rows, plain)
+}
+
+/// The row a greeting of `rows` rows starts on in a pane `h` high with `room` rows for text:
+/// a quarter of the way down, or higher up if that would leave some of it off the bottom
+pub fn greeting_top(rows: usize, h: usize, room: usize) -> usize {
+    (h / 4).min(room.saturating_sub(rows))
+}
+
+impl Editor {
+    /// Whether the greeting is showing (in the panes that have nothing in them)
+    pub fn greeting_shown(&self) -> bool {
+        self.greet && config!(self.config, greeting_message).enabled
+    }
+
+    /// Lay out the greeting for a pane `w` wide
+    pub fn greeting_layout(&self, w: usize) -> (Vec<GreetingRow>, bool) {
+        let (message, highlights) = &self.render_cache.greeting_message;
+        layout_greeting(message, highlights, &self.render_cache.greeting_entries, w)
+    }
+
+    /// Find the greeting entry at a position in a pane `w` wide and `h` high,
+    /// with `room` rows for text
+    pub fn greeting_entry_at(&self, loc: Loc, w: usize, h: usize, room: usize) -> Option<char> {
+        let (rows, _) = self.greeting_layout(w);
+        let top = greeting_top(rows.len(), h, room);
+        let row = rows.get(loc.y.checked_sub(top)?)?;
+        let cols = row.start..row.start + width(&row.text, 4);
+        row.entry.filter(|_| cols.contains(&loc.x))
+    }
+
+    /// Work out which greeting entry an event chooses (while the greeting is showing)
+    pub fn greeting_choice(&mut self, lua: &Lua, event: &CEvent) -> Option<GreetingAction> {
+        if !self.greeting_shown() {
+            return None;
+        }
+        let key = match event {
+            CEvent::Key(KeyEvent {
+                modifiers: KMod::NONE | KMod::SHIFT,
+                code: KCode::Char(key),
+                kind,
+                ..
+            }) if *kind != KeyEventKind::Release => {
+                // Keys only choose entries when the greeting is in the pane being used
+                self.try_doc().filter(|doc| doc.file_name.is_none())?;
+                *key
+            }
+            CEvent::Mouse(
+                mouse @ MouseEvent {
+                    kind: MouseEventKind::Down(MouseButton::Left),
+                    modifiers: KMod::NONE,
+                    ..
+                },
+            ) => self.greeting_clicked(lua, *mouse)?,
+            _ => return None,
+        };
+        let greeting = config!(self.config, greeting_message);
+        let entry = greeting.entries.iter().find(|entry| entry.key == key)?;
+        Some(entry.action.clone())
+    }
+
+    /// Carry out one of the actions ox provides for greeting entries
+    pub fn greeting_action(&mut self, lua: &Lua, name: &str) {
+        let result = match name {
+            "new" => self.perform(lua, Action::NewDocument),
+            "open" => self.perform(lua, Action::OpenDocument),
+            "recent" => self.open_most_recent(),
+            "recent_files" => self.recent(lua),
+            "file_tree" => {
+                self.open_file_tree();
+                Ok(())
+            }
+            "config" => self.open_config(),
+            _ => Ok(()),
+        };
+        match result {
+            Ok(()) | Err(OxError::Cancelled) => (),
+            Err(err) => self.feedback = Feedback::Error(err.to_string()),
+        }
+        self.needs_rerender = true;
+    }
+
+    /// Open the configuration file (starting it if there isn't one yet)
+    fn open_config(&mut self) -> Result<()> {
+        let path = shellexpand::tilde(&self.config_path).to_string();
+        match self.open_or_new(path) {
+            Ok(()) => self.next(),
+            // The file is already open, so it has just been switched to
+            Err(OxError::AlreadyOpen { .. }) => return Ok(()),
+            Err(err) => return Err(err),
+        }
+        self.update_cwd();
+        Ok(())
+    }
+}
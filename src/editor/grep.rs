@@ -0,0 +1,196 @@
+/// Searching every file in the project for a pattern, listing the matches as they are found
+use crate::error::{OxError, Result};
+use crossterm::event::{KeyCode as KCode, KeyModifiers as KMod};
+use kaolinite::document::is_binary;
+use kaolinite::utils::get_cwd;
+use mlua::Lua;
+use regex::Regex;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender, TryRecvError};
+use std::sync::Arc;
+
+use super::finder::walk;
+use super::picker::PickerKey;
+use super::Editor;
+
+/// The most matches listed for a single search
+const MAX_MATCHES: usize = 10_000;
+/// The most characters of a matching line shown in the listing
+const MAX_PREVIEW: usize = 200;
+
+/// A line of a file that matched the pattern
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileMatch {
+    /// The path of the file, relative to where the search started
+    pub path: String,
+    /// The line and column the match starts at (counting from 1)
+    pub line: usize,
+    pub column: usize,
+    pub preview: String,
+}
+
+impl FileMatch {
+    /// Describe the match for the listing
+    fn describe(&self) -> String {
+        format!("{}:{}: {}", self.path, self.line, self.preview)
+    }
+}
+
+/// Look for a pattern in every file under a directory (skipping binary and ignored files),
+/// sending each match back as soon as it is found, until there are no files left or the
+/// search is cancelled
+fn search_files(root: &Path, pattern: &Regex, tx: &Sender<FileMatch>, cancelled: &AtomicBool) {
+    let mut found = 0;
+    for path in walk(root) {
+        if cancelled.load(Ordering::Relaxed) {
+            return;
+        }
+        let Ok(bytes) = std::fs::read(root.join(&path)) else {
+            continue;
+        };
+        if is_binary(&bytes) {
+            continue;
+        }
+        let text = String::from_utf8_lossy(&bytes);
+        for (y, line) in text.lines().enumerate() {
+            let Some(m) = pattern.find(line) else {
+                continue;
+            };
+            let result = FileMatch {
+                path: path.clone(),
+                line: y + 1,
+                column: line[..m.start()].chars().count() + 1,
+                preview: line.trim().chars().take(MAX_PREVIEW).collect(),
+            };
+            found += 1;
+            if tx.send(result).is_err() || found >= MAX_MATCHES {
+                return;
+            }
+        }
+    }
+}
+
+/// Read a pattern as a regular expression, or as plain text if it isn't a valid one
+fn to_regex(pattern: &str) -> Regex {
+    Regex::new(pattern).unwrap_or_else(|_| Regex::new(&regex::escape(pattern)).unwrap())
+}
+
+/// A search of the files in a project, running in the background
+struct FileSearch {
+    rx: Receiver<FileMatch>,
+    cancelled: Arc<AtomicBool>,
+    /// Whether the search has finished (or been stopped)
+    finished: bool,
+}
+
+impl FileSearch {
+    /// Start searching the files under a directory
+    fn start(root: PathBuf, pattern: Regex) -> Self {
+        let (tx, rx) = channel();
+        let cancelled = Arc::new(AtomicBool::new(false));
+        let flag = Arc::clone(&cancelled);
+        std::thread::spawn(move || search_files(&root, &pattern, &tx, &flag));
+        Self {
+            rx,
+            cancelled,
+            finished: false,
+        }
+    }
+
+    /// Take the matches that have been found since this was last called
+    fn poll(&mut self, matches: &mut Vec<FileMatch>) -> bool {
+        let before = matches.len();
+        loop {
+            match self.rx.try_recv() {
+                Ok(m) => matches.push(m),
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => {
+                    self.finished = true;
+                    break;
+                }
+            }
+        }
+        matches.len() != before
+    }
+
+    /// Stop the search, keeping what has been found so far
+    fn stop(&mut self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+        self.finished = true;
+    }
+}
+
+impl Drop for FileSearch {
+    fn drop(&mut self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+}
+
+impl Editor {
+    /// Search every file under the current directory for a pattern (asking for one if not given),
+    /// listing the matches as they come in, where enter opens the file at the selected match
+    pub fn find_in_files(&mut self, lua: &Lua, pattern: Option<&str>) -> Result<()> {
+        self.needs_terminal()?;
+        let pattern = match pattern {
+            Some(pattern) => pattern.to_string(),
+            None => self.prompt("Find in files")?,
+        };
+        if pattern.is_empty() {
+            return Ok(());
+        }
+        let root = PathBuf::from(get_cwd().unwrap_or(".".to_string()));
+        let search = FileSearch::start(root.clone(), to_regex(&pattern));
+        // The search, the matches found so far and their descriptions
+        let state: (FileSearch, Vec<FileMatch>, Vec<String>) = (search, vec![], vec![]);
+        self.pick(
+            lua,
+            state,
+            0,
+            |editor, (search, matches, items), selected| {
+                if search.poll(matches) {
+                    items.extend(matches[items.len()..].iter().map(FileMatch::describe));
+                }
+                let hint = match (search.finished, matches.len()) {
+                    (false, n) => format!("Searching for {pattern}: {n} found, esc to stop"),
+                    (true, 0) => format!("No matches for {pattern}, esc to close"),
+                    (true, n) if n >= MAX_MATCHES => {
+                        format!("The first {n} matches for {pattern}: enter to open, esc to close")
+                    }
+                    (true, n) => format!("{n} matches for {pattern}: enter to open, esc to close"),
+                };
+                editor.render_list(items, *selected, &hint)?;
+                Ok(items.len())
+            },
+            |_, _, (search, _, items), key, _| {
+                Ok(match key {
+                    // There is nothing to open until something is found
+                    (KMod::NONE, KCode::Enter) if items.is_empty() => Some(PickerKey::Stay),
+                    // Stop searching, or close the results once the search is over
+                    (KMod::NONE, KCode::Esc) if !search.finished => {
+                        search.stop();
+                        Some(PickerKey::Stay)
+                    }
+                    _ => None,
+                })
+            },
+            |editor, (search, matches, _), selected| {
+                drop(search);
+                // Open the file at the selected match
+                let m = &matches[selected];
+                let path = root.join(&m.path).to_string_lossy().to_string();
+                // Files that are already open are moved to instead
+                match editor.open(&path) {
+                    Ok(()) => editor.next(),
+                    Err(OxError::AlreadyOpen { .. }) => (),
+                    Err(err) => return Err(err),
+                }
+                editor.update_cwd();
+                if let Some((_, idx)) = editor.files.get_atom(editor.ptr.clone()) {
+                    editor.go_to_position(idx, m.line, Some(m.column));
+                }
+                Ok(())
+            },
+        )
+    }
+}
@@ -0,0 +1,44 @@
+/// Running the editor without a terminal, where messages go to the standard output instead
+use crate::error::{OxError, Result};
+use crate::ui::Feedback;
+
+use super::Editor;
+
+/// Write a message out for someone running the editor headlessly
+fn report(feedback: &Feedback) {
+    match feedback {
+        Feedback::Info(msg) => println!("{msg}"),
+        Feedback::Warning(msg) => eprintln!("Warning: {msg}"),
+        Feedback::Error(msg) => eprintln!("Error: {msg}"),
+        Feedback::None => (),
+    }
+}
+
+impl Editor {
+    /// Show a message below the status line, or straight away when running headlessly
+    pub fn show_feedback(&mut self, feedback: Feedback) {
+        if self.headless {
+            if let Feedback::Error(_) = feedback {
+                self.headless_errors += 1;
+            }
+            report(&feedback);
+        } else {
+            self.feedback = feedback;
+        }
+    }
+
+    /// Write out the message left below the status line (for when running headlessly)
+    pub fn flush_feedback(&mut self) {
+        let feedback = std::mem::replace(&mut self.feedback, Feedback::None);
+        self.show_feedback(feedback);
+    }
+
+    /// Fail if the user would need to be asked something, which can't be done headlessly
+    pub fn needs_terminal(&self) -> Result<()> {
+        if self.headless {
+            Err(OxError::Headless)
+        } else {
+            Ok(())
+        }
+    }
+}
@@ -0,0 +1,227 @@
+/// Help: a read only document listing the key bindings (including any the user has changed),
+/// which can be scrolled through and searched like any other document
+use crate::cli::VERSION;
+use crate::config;
+use crate::config::{string_to_key, Config};
+use crate::error::Result;
+use crossterm::event::{KeyCode as KCode, KeyModifiers as KMod};
+use mlua::prelude::*;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::path::Path;
+
+use super::Editor;
+
+/// The name of the scratch buffer the help document is kept in
+pub const HELP_NAME: &str = "help";
+
+/// Shown when the key bindings can't be worked out from the configuration
+pub const HELP_TEXT: &str = "\
+Key Binding Cheat Sheet
+
+Ctrl + H:   Help
+Ctrl + N:   New
+Ctrl + O:   Open
+Ctrl + P:   Find a file
+Ctrl + Q:   Quit
+Ctrl + S:   Save
+Alt  + S:   Save as
+Alt  + A:   Save all
+Ctrl + Z:   Undo
+Ctrl + Y:   Redo
+Ctrl + F:   Find
+Ctrl + R:   Replace
+Ctrl + W:   Delete Word
+Ctrl + D:   Add Cursor
+Alt  + D:   Delete Line
+Ctrl + G:   Go to a line
+Alt + Up:   Move line up
+Alt + Down: Move line down
+Ctrl + K:   Command Line
+Alt + ->:   Next Tab
+Alt + <-:   Previous Tab
+";
+
+/// How a key is written out for people to read, e.g. `Ctrl + Shift + Left`
+fn key_label(key: &str) -> Option<String> {
+    let (modifiers, code) = string_to_key(key)?;
+    let mut parts = vec![];
+    for (modifier, name) in [
+        (KMod::CONTROL, "Ctrl"),
+        (KMod::ALT, "Alt"),
+        (KMod::SHIFT, "Shift"),
+    ] {
+        if modifiers.contains(modifier) {
+            parts.push(name.to_string());
+        }
+    }
+    parts.push(match code {
+        KCode::Char(' ') => "Space".to_string(),
+        KCode::Char(ch) => ch.to_uppercase().to_string(),
+        KCode::F(n) => format!("F{n}"),
+        KCode::PageUp => "Page Up".to_string(),
+        KCode::PageDown => "Page Down".to_string(),
+        KCode::BackTab => "Back Tab".to_string(),
+        code => format!("{code:?}"),
+    });
+    Some(parts.join(" + "))
+}
+
+/// Describe what a function bound to a key does, from the code behind it:
+/// a comment at the start of it, else the first editor method it calls, else its first line
+fn describe(function: &LuaFunction) -> Option<String> {
+    let info = function.info();
+    let code = Config::chunk_source(info.source.as_deref()?)?;
+    let (start, end) = (info.line_defined?, info.last_line_defined?);
+    // Functions written on one line are looked at whole
+    let lines = if end > start + 1 {
+        start..end - 1
+    } else {
+        start.saturating_sub(1)..end
+    };
+    let body: Vec<&str> = code
+        .lines()
+        .skip(lines.start)
+        .take(lines.len())
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect();
+    if let Some(comment) = body.first().and_then(|line| line.strip_prefix("--")) {
+        return Some(comment.trim().to_string());
+    }
+    let method = body.iter().find_map(|line| {
+        let (_, after) = line.split_once("editor:")?;
+        let name: String = after
+            .chars()
+            .take_while(|c| c.is_alphanumeric() || *c == '_')
+            .collect();
+        (!name.is_empty()).then(|| name.replace('_', " "))
+    });
+    method.or_else(|| body.first().map(|line| (*line).to_string()))
+}
+
+/// Where a function bound to a key comes from, if it isn't the default configuration
+fn origin(function: &LuaFunction, config_path: &str) -> Option<String> {
+    let info = function.info();
+    let source = info.source?;
+    let line = info.line_defined.unwrap_or_default();
+    if source == format!("@{config_path}") {
+        Some(format!("your configuration, line {line}"))
+    } else if let Some(path) = source.strip_prefix('@').filter(|p| Path::new(p).is_file()) {
+        Some(format!("{path}, line {line}"))
+    } else {
+        source
+            .strip_suffix(".lua")
+            .and_then(|name| name.strip_prefix('='))
+            .map(|name| format!("the {name} plug-in"))
+    }
+}
+
+/// The functions bound to each key, from both the bindings that have been merged in from
+/// plug-ins and those that haven't been yet
+fn bindings(lua: &Lua) -> BTreeMap<String, Vec<LuaFunction>> {
+    let mut result: BTreeMap<String, Vec<LuaFunction>> = BTreeMap::new();
+    let globals = lua.globals();
+    if let Ok(merged) = globals.get::<LuaTable>("global_event_mapping") {
+        for (key, functions) in merged.pairs::<String, LuaTable>().flatten() {
+            let functions = functions.sequence_values::<LuaFunction>().flatten();
+            result.entry(key).or_default().extend(functions);
+        }
+    }
+    if let Ok(unmerged) = globals.get::<LuaTable>("event_mapping") {
+        for (key, function) in unmerged.pairs::<String, LuaFunction>().flatten() {
+            result.entry(key).or_default().push(function);
+        }
+    }
+    // Only key presses are of interest, not events like saving or the catch-all
+    result.retain(|key, _| key != "*" && string_to_key(key).is_some());
+    result
+}
+
+impl Editor {
+    /// The text of the help document, listing every key binding along with what it does
+    fn help_text(&self, lua: &Lua) -> String {
+        let bindings = bindings(lua);
+        if bindings.is_empty() {
+            let format = config!(self.config, help_message).format.clone();
+            if format.is_empty() {
+                return HELP_TEXT.to_string();
+            }
+            // Highlighting markers (from when help was drawn beside the document) are left out
+            let mut text = String::new();
+            for line in format.lines() {
+                if !matches!(line.trim(), "{highlight_start}" | "{highlight_end}") {
+                    let _ = writeln!(text, "{}", line.trim_end());
+                }
+            }
+            return text;
+        }
+        let mut rows: Vec<(String, String)> = bindings
+            .iter()
+            .filter_map(|(key, functions)| {
+                let label = key_label(key)?;
+                let mut description: Vec<String> = functions.iter().filter_map(describe).collect();
+                description.dedup();
+                let origins: Vec<String> = functions
+                    .iter()
+                    .filter_map(|f| origin(f, &self.config_path))
+                    .collect();
+                let mut description = description.join(", then ");
+                if description.is_empty() {
+                    description = "(runs some Lua)".to_string();
+                }
+                if !origins.is_empty() {
+                    let _ = write!(description, "  [{}]", origins.join("; "));
+                }
+                Some((label, description))
+            })
+            .collect();
+        rows.sort();
+        let width = rows.iter().map(|(label, _)| label.len()).max().unwrap_or(0);
+        let mut text = format!(
+            "Ox v{VERSION} Help\n\n\
+            Press q or Esc to close this, or Ctrl + F to search it.\n\
+            Typing, Tab, Backspace, Delete and Enter edit text as usual.\n\n\
+            Key Bindings\n\n"
+        );
+        for (label, description) in rows {
+            let _ = writeln!(text, "{label:<width$}   {description}");
+        }
+        text
+    }
+
+    /// Whether the document being edited is the help document
+    pub fn showing_help(&self) -> bool {
+        self.files
+            .get(self.ptr.clone())
+            .is_some_and(|file| file.scratch.as_deref() == Some(HELP_NAME))
+    }
+
+    /// Open the help document (or close it if it is the document being edited)
+    pub fn toggle_help(&mut self, lua: &Lua) -> Result<()> {
+        if self.showing_help() {
+            return self.quit(lua);
+        }
+        // Move to the help document if it is already open
+        let existing = self
+            .files
+            .get_atom(self.ptr.clone())
+            .and_then(|(files, _)| {
+                files
+                    .iter()
+                    .position(|f| f.scratch.as_deref() == Some(HELP_NAME))
+            });
+        if let Some(idx) = existing {
+            self.switch_to(idx);
+            return Ok(());
+        }
+        let text = self.help_text(lua);
+        self.open_from_string(text, None)?;
+        self.next();
+        if let Some(file) = self.files.get_mut(self.ptr.clone()) {
+            file.doc.info.read_only = true;
+            file.scratch = Some(HELP_NAME.to_string());
+        }
+        Ok(())
+    }
+}
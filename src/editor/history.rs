@@ -0,0 +1,58 @@
+/// Keeping the undo history of files between sessions
+use crate::config;
+use kaolinite::event::stable_hash;
+use kaolinite::utils::get_absolute_path;
+use kaolinite::Document;
+use std::path::PathBuf;
+
+use super::Editor;
+
+/// The most space the undo history of a single file can take up on disk
+const MAX_HISTORY_BYTES: usize = 1024 * 1024;
+
+/// Work out where the undo history of a file is kept (based on its canonical path)
+pub fn history_path(file_name: &str) -> Option<PathBuf> {
+    let path = get_absolute_path(file_name)?;
+    let cache = std::env::var("XDG_CACHE_HOME")
+        .ok()
+        .filter(|d| !d.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| {
+            let home = std::env::var("HOME").or_else(|_| std::env::var("USERPROFILE"));
+            home.ok().map(|h| PathBuf::from(h).join(".cache"))
+        })?;
+    let name = format!("{:016x}", stable_hash([path.as_str()]));
+    Some(cache.join("ox").join("undo").join(name))
+}
+
+/// Restore the undo history of a document from a previous session (if there is one that fits)
+pub fn restore_history(doc: &mut Document) {
+    let Some(path) = doc.file_name.as_deref().and_then(history_path) else {
+        return;
+    };
+    if let Ok(data) = std::fs::read(path) {
+        doc.load_history(&data);
+    }
+}
+
+impl Editor {
+    /// Store the undo history of a document for future sessions (if persistent undo is enabled)
+    pub fn store_history_at(&mut self, ptr: &[usize], doc: usize) {
+        if !config!(self.config, document).persistent_undo {
+            return;
+        }
+        let Ok(file) = self.file_at_mut(ptr, doc) else {
+            return;
+        };
+        file.doc.commit();
+        let Some(path) = file.doc.file_name.as_deref().and_then(history_path) else {
+            return;
+        };
+        let data = file.doc.save_history(MAX_HISTORY_BYTES);
+        // The history is only a convenience, so failing to store it isn't worth reporting
+        if let Some(parent) = path.parent() {
+            let _ = std::fs::create_dir_all(parent);
+        }
+        let _ = std::fs::write(path, data);
+    }
+}
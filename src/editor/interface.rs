@@ -1,9 +1,9 @@
 /// Functions for rendering the UI
-use crate::config::SyntaxHighlighting as SH;
+use crate::config::{placeholder_errors, set_color_mode, SyntaxHighlighting as SH};
 use crate::editor::{FTParts, FileLayout};
 use crate::error::{OxError, Result};
 use crate::events::wait_for_event_hog;
-use crate::ui::{key_event, size, Feedback};
+use crate::ui::{key_event, ColourTracker, Feedback};
 #[cfg(not(target_os = "windows"))]
 use crate::ui::{remove_ansi_codes, replace_reset, strip_escape_codes};
 use crate::{config, display, handle_lua_error};
@@ -11,60 +11,108 @@ use crossterm::{
     event::{KeyCode as KCode, KeyModifiers as KMod},
     style::{Attribute, Color, SetAttribute, SetBackgroundColor as Bg, SetForegroundColor as Fg},
 };
-use kaolinite::utils::{file_or_dir, get_cwd, get_parent, list_dir, width, width_char, Loc, Size};
+use kaolinite::diff::LineChange;
+use kaolinite::signs::{signs_on, Severity};
+use kaolinite::utils::{
+    file_or_dir, get_cwd, get_parent, list_dir, trim, width, width_char, Loc, Size,
+};
 use mlua::Lua;
 use std::ops::Range;
 use synoptic::{trim_fit, Highlighter, TokOpt};
 
+use super::bookmarks::BOOKMARK_MARKER;
+use super::filetree::tree_scroll;
+use super::gitstatus::GitState;
+use super::greeting::greeting_top;
+use super::overlay::{OverlayRegion, CLOSE_GLYPH};
+use super::signs::sign_marker;
+use super::swatches::color_swatches;
+use super::tabline::{SCROLL_LEFT, SCROLL_RIGHT, TAB_CLOSE};
 use super::Editor;
 
+/// How many rows above and below a block selection its column guides extend
+const BLOCK_GUIDE_REACH: usize = 2;
+/// Drawn over the text at the edge of the view that a line runs off
+const OVERFLOW_MARKER: char = '…';
+/// The most items shown in a list at once
+const MAX_LISTED: usize = 15;
+/// The smallest terminal the editor is drawn in (anything smaller shows a placeholder)
+const MIN_WIDTH: usize = 20;
+const MIN_HEIGHT: usize = 5;
+/// Shown in place of the editor when the terminal is too small to draw it in
+const TOO_SMALL: &str = "window too small";
+/// Shown at the start of the status line while the configuration file can't be used
+const SAFE_MODE_WARNING: &str = "config error — running with defaults";
+
+/// Whether the terminal is too small to draw the editor in
+fn too_small(size: Size) -> bool {
+    size.w < MIN_WIDTH || size.h < MIN_HEIGHT
+}
+
 /// Render cache to store the results of any calculations during rendering
 #[derive(Default)]
 pub struct RenderCache {
     pub greeting_message: (String, Vec<usize>),
+    /// The key and label of each greeting entry
+    pub greeting_entries: Vec<(char, String)>,
     pub span: Vec<(Vec<usize>, Range<usize>, Range<usize>)>,
-    pub help_message: Vec<(bool, String)>,
-    pub help_message_width: usize,
-    pub help_message_span: Range<usize>,
     pub file_tree: FTParts,
     pub file_tree_selection: Option<usize>,
+    /// The first row of the file tree in view
+    pub file_tree_scroll: usize,
     pub term_cursor: Option<Loc>,
+    /// Where the picker currently open was drawn (for the mouse to interact with)
+    pub overlay: Option<OverlayRegion>,
 }
 
 impl Editor {
     /// Update the render cache
-    #[allow(clippy::range_plus_one)]
     pub fn update_render_cache(&mut self, lua: &Lua, size: Size) {
         // Calculate greeting message
         if config!(self.config, tab_line).enabled && self.greet {
-            self.render_cache.greeting_message = config!(self.config, greeting_message).render(lua);
+            let greeting = config!(self.config, greeting_message);
+            self.render_cache.greeting_message = greeting.render(lua);
+            self.render_cache.greeting_entries = greeting
+                .entries
+                .iter()
+                .map(|entry| (entry.key, entry.label.clone()))
+                .collect();
         }
         // Calculate span
         self.render_cache.span = self.files.span(vec![], size, Loc::at(0, 0));
-        // Calculate help message information
-        let tab_width = config!(self.config, document).tab_width;
-        self.render_cache.help_message = config!(self.config, help_message).render(lua);
-        self.render_cache.help_message_width = self
-            .render_cache
-            .help_message
-            .iter()
-            .map(|(_, line)| width(line, tab_width))
-            .max()
-            .unwrap_or(0)
-            + 5;
-        let help_length = self.render_cache.help_message.len();
-        let help_start = (size.h / 2).saturating_sub(help_length / 2) + 1;
-        let help_end = help_start + help_length;
-        self.render_cache.help_message_span = help_start..help_end + 1;
         // Calculate file tree display representation
         let fts = &config!(self.config, document).file_types;
         let ft_config = &config!(self.config, file_tree);
-        if let Some(file_tree) = self.file_tree.as_ref() {
-            let (files, sel) = file_tree.display(
+        if let Some(file_tree) = self.file_tree_shown() {
+            let (mut files, sel) = file_tree.display(
                 self.file_tree_selection.as_ref().unwrap_or(&String::new()),
                 fts,
                 ft_config,
+                &self.file_tree_git,
             );
+            // While filtering, the top row shows what is being searched for
+            if let (Some(filter), Some(top)) = (&self.file_tree_filter, files.first_mut()) {
+                let icon = if ft_config.icons { "󰍉  " } else { "" };
+                *top = (
+                    0,
+                    icon.to_string(),
+                    None,
+                    format!("/{}│", filter.query),
+                    None,
+                );
+            }
+            // Scroll the file tree so the selection stays in view
+            let height = self
+                .render_cache
+                .span
+                .iter()
+                .find(|(ptr, _, _)| {
+                    matches!(self.files.get_raw(ptr.clone()), Some(FileLayout::FileTree))
+                })
+                .map_or(size.h, |(_, rows, _)| rows.len())
+                .max(1);
+            let scroll = self.render_cache.file_tree_scroll;
+            self.render_cache.file_tree_scroll = tree_scroll(scroll, sel, height, files.len());
             self.render_cache.file_tree = files;
             self.render_cache.file_tree_selection = sel;
         }
@@ -92,6 +140,11 @@ impl Editor {
                 self.files.get_raw(fc.to_owned()),
                 Some(FileLayout::Terminal(_))
             );
+            let in_preview = matches!(
+                self.files.get_raw(fc.to_owned()),
+                Some(FileLayout::Preview(_))
+            );
+            let in_diff = matches!(self.files.get_raw(fc.to_owned()), Some(FileLayout::Diff(_)));
             // Check if we have encountered an area of discontinuity in the line
             if range.start != accounted_for {
                 // Discontinuity detected, fill with vertical bar!
@@ -126,12 +179,21 @@ impl Editor {
             } else if in_terminal {
                 // Part of terminal!
                 result += &self.render_terminal(fc, rel_y, length, height)?;
+            } else if in_preview {
+                // Part of a markdown preview
+                result += &self.render_preview(fc, rel_y, length, sh)?;
+            } else if in_diff {
+                // Part of a diff
+                result += &self.render_diff(fc, rel_y, length)?;
             } else if y == rows.start && tab_line_enabled {
                 // Tab line
                 result += &self.render_tab_line(fc, lua, length)?;
-            } else if y == rows.end.saturating_sub(1) {
+            } else if y == rows.end.saturating_sub(1) && self.status_rows() > 0 {
                 // Status line
                 result += &self.render_status_line(fc, lua, length)?;
+            } else if let Some(row) = self.render_budget.row(fc, y) {
+                // Line of file (drawn ahead of time by render_panes)
+                result += &row.clone();
             } else {
                 // Line of file
                 result += &self.render_file(
@@ -193,42 +255,91 @@ impl Editor {
 
     /// Render a single frame of the editor in it's current state
     pub fn render(&mut self, lua: &Lua) -> Result<()> {
-        // Determine if re-rendering is needed
-        if !self.needs_rerender {
+        // Determine if re-rendering is needed (there is nothing to draw on when headless)
+        if !self.needs_rerender || self.headless {
             return Ok(());
         }
-        self.needs_rerender = false;
+        self.render_document(lua)?;
+        self.update_title();
         // Get size information
-        let size = size()?;
-        let Size { w, mut h } = size;
-        h = h.saturating_sub(1 + self.push_down);
+        let size = self.terminal.size()?;
+        if too_small(size) {
+            self.terminal.hide_cursor();
+            self.terminal.flush()?;
+            return Ok(());
+        }
+        // Draw any completions being offered over the document
+        self.render_completion()?;
+        // Render the feedback line
+        self.render_feedback_line(size.w, size.h)?;
+        // Move cursor to the correct location and perform render
+        if let Some(Loc { x, y }) = self.cursor_position() {
+            self.terminal.show_cursor();
+            self.terminal.set_cursor_shape(self.cursor_shape());
+            self.update_cursor_blink();
+            self.terminal.goto(x, y);
+        } else {
+            self.terminal.hide_cursor();
+        }
+        self.terminal.flush()?;
+        Ok(())
+    }
+
+    /// Draw the documents, tab lines and status line (without flushing),
+    /// so that overlays can be drawn on top before the frame is shown
+    pub fn render_document(&mut self, lua: &Lua) -> Result<()> {
+        self.needs_rerender = false;
+        set_color_mode(config!(self.config, terminal).color_mode);
+        // Any picker drawn over the document is drawn again afterwards if it is still open
+        let uncovered = self.render_cache.overlay.take().is_some();
+        let size = self.terminal.size()?;
+        if too_small(size) {
+            self.render_too_small(size)?;
+            return Ok(());
+        }
         // Update the cache before rendering
         self.update_render_cache(lua, size);
+        // Nothing drawn before can be relied on once the screen has been cleared
+        if self.terminal.frame.is_empty() {
+            self.render_budget.forget();
+        }
         // Update all document's size
         let updates = self.files.update_doc_sizes(&self.render_cache.span, self);
+        let margin = config!(self.config, document).horizontal_margin;
+        let separators = config!(self.config, document).word_separators.clone();
         for (ptr, doc_idx, new_size) in updates {
-            let doc = &mut self.files.get_atom_mut(ptr.clone()).unwrap().0[doc_idx].doc;
+            let Some(file) = self
+                .files
+                .get_atom_mut(ptr.clone())
+                .and_then(|(fcs, _)| fcs.get_mut(doc_idx))
+            else {
+                continue;
+            };
+            let doc = &mut file.doc;
             doc.size = new_size;
+            doc.horizontal_margin = margin;
+            doc.word_separators.clone_from(&separators);
             doc.load_to(doc.offset.y + doc.size.h + 1);
             self.update_highlighter_for(&ptr, doc_idx);
         }
-        // Hide the cursor before rendering
-        self.terminal.hide_cursor();
-        // Render each line of the document
+        // Bring any markdown previews and diffs up to date with their documents
+        self.update_previews();
+        self.update_diffs();
+        // Only the lines that have changed since they were last in view are checked again
+        self.check_spelling_in_view();
+        // Draw the panes that have changed, putting off unfocused ones if the frame runs long
+        // (but never when an overlay was just over them, as what's under it must be right)
         let syntax = config!(self.config, syntax);
+        self.render_panes(&syntax, !uncovered)?;
+        // Render each line of the document (only rows that have changed are written out)
         for y in 0..size.h {
             let line = self.render_line(y, size, lua, &syntax)?;
-            self.terminal.goto(0, y);
-            display!(self, line);
+            self.terminal.draw_line(y, line);
         }
-        // Render the feedback line
-        self.render_feedback_line(w, h)?;
-        // Move cursor to the correct location and perform render
-        if let Some(Loc { x, y }) = self.cursor_position() {
-            self.terminal.show_cursor();
-            self.terminal.goto(x, y);
+        // Report any custom placeholders that failed
+        if let Some(err) = placeholder_errors(lua).pop() {
+            self.feedback = Feedback::Error(err);
         }
-        self.terminal.flush()?;
         Ok(())
     }
 
@@ -245,7 +356,7 @@ impl Editor {
         match (in_file_tree, in_terminal) {
             // Move cursor to location within file
             (false, false) => {
-                let Loc { x, y } = self.try_doc().unwrap().cursor_loc_in_screen()?;
+                let Loc { x, y } = self.try_doc()?.cursor_loc_in_screen()?;
                 for (ptr, rows, cols) in &self.render_cache.span {
                     if ptr == &self.ptr {
                         return Some(Loc {
@@ -291,26 +402,76 @@ impl Editor {
         let line_numbers_enabled = config!(self.config, line_numbers).enabled;
         let ln_pad_left = config!(self.config, line_numbers).padding_left;
         let ln_pad_right = config!(self.config, line_numbers).padding_right;
-        let fc = self.files.get(ptr.to_owned()).unwrap();
+        let change_markers = config!(self.config, line_numbers).change_markers;
+        let overflow_markers = config!(self.config, document).overflow_markers;
+        let margin = self.zen_margin(ptr);
+        // A pane whose document has just closed is left blank until the layout catches up
+        let Some((fc, doc_idx)) = self
+            .files
+            .get_atom(ptr.to_owned())
+            .and_then(|(fcs, doc_idx)| Some((fcs.get(doc_idx)?, doc_idx)))
+        else {
+            return Ok(format!("{editor_bg}{}", " ".repeat(w)));
+        };
         let doc = &fc.doc;
         let selection = doc.selection_loc_bound_disp();
         let has_file = doc.file_name.is_none();
-        // Refuse to render help message on splits - awkward edge case
-        let help_message_here = config!(self.config, help_message).enabled
-            && self.render_cache.help_message_span.contains(&y)
-            && self.files.n_atoms() == 1;
-        // Render short of the help message
-        let mut total_width = if help_message_here {
-            self.render_cache.help_message_width
-        } else {
-            0
-        };
+        let mut total_width = 0;
+        // Distraction-free mode centres the text
+        if margin > 0 {
+            result += &format!("{editor_fg}{editor_bg}{}", " ".repeat(margin));
+            total_width += margin;
+        }
         // Render the line numbers if enabled
         if line_numbers_enabled {
             let num = doc.line_number(y + doc.offset.y);
-            let padding_left = " ".repeat(ln_pad_left);
+            // Signs and annotations are marked in the padding before the line number
+            let sign = signs_on(&fc.signs, y + doc.offset.y, doc.len_lines())
+                .into_iter()
+                .next();
+            let padding_left = match (sign, doc.mark_at(y + doc.offset.y)) {
+                (Some(sign), _) if ln_pad_left > 0 => {
+                    let colors = config!(self.config, colors);
+                    let color = match sign.severity {
+                        Severity::Error => &colors.error_fg,
+                        Severity::Warning => &colors.warning_fg,
+                        Severity::Info | Severity::Hint => &colors.info_fg,
+                    };
+                    let color = Fg(color.to_color()?);
+                    let spaces = " ".repeat(ln_pad_left - 1);
+                    format!("{spaces}{color}{}{line_number_fg}", sign_marker(&sign))
+                }
+                (_, Some(mark)) if ln_pad_left > 0 => {
+                    let marker = if mark.moved { '◇' } else { '◆' };
+                    let highlight = Fg(config!(self.config, colors).highlight.to_color()?);
+                    let spaces = " ".repeat(ln_pad_left - 1);
+                    format!("{spaces}{highlight}{marker}{line_number_fg}")
+                }
+                _ if ln_pad_left > 0 && doc.bookmark_at(y + doc.offset.y).is_some() => {
+                    let highlight = Fg(config!(self.config, colors).highlight.to_color()?);
+                    let spaces = " ".repeat(ln_pad_left - 1);
+                    format!("{spaces}{highlight}{BOOKMARK_MARKER}{line_number_fg}")
+                }
+                _ => " ".repeat(ln_pad_left),
+            };
             let padding_right = " ".repeat(ln_pad_right);
-            result += &format!("{line_number_bg}{line_number_fg}{padding_left}{num}{padding_right}│{editor_fg}{editor_bg}");
+            // Lines changed since the last save are marked on the divider
+            let change = doc
+                .line_change(y + doc.offset.y)
+                .filter(|_| change_markers && y + doc.offset.y < doc.len_lines());
+            let divider = if let Some(change) = change {
+                let colors = config!(self.config, colors);
+                let (color, marker) = match change {
+                    LineChange::Added => (&colors.line_added_fg, '┃'),
+                    LineChange::Modified => (&colors.line_modified_fg, '┃'),
+                    LineChange::RemovedAbove => (&colors.line_removed_fg, '▔'),
+                    LineChange::RemovedBelow => (&colors.line_removed_fg, '▁'),
+                };
+                format!("{}{marker}", Fg(color.to_color()?))
+            } else {
+                "│".to_string()
+            };
+            result += &format!("{line_number_bg}{line_number_fg}{padding_left}{num}{padding_right}{divider}{editor_fg}{editor_bg}");
             total_width += ln_pad_left + ln_pad_right + width(&num, tab_width) + 1;
         } else {
             result += &format!("{editor_fg}{editor_bg}");
@@ -320,16 +481,44 @@ impl Editor {
         let at_line = y + doc.offset.y;
         if let Some(line) = doc.line(at_line) {
             // Reset the cache
-            let mut cache_bg = editor_bg;
-            let mut cache_fg = editor_fg;
+            let mut colours = ColourTracker::new(editor_bg, editor_fg);
             // Gather the tokens
             let tokens = fc.highlighter.line(at_line, &line);
-            let tokens = trim_fit(&tokens, doc.offset.x, w, tab_width);
+            let view_w = w.min(doc.size.w);
+            let tokens = trim_fit(&tokens, doc.offset.x, view_w, tab_width);
+            // Lines running off either side of the view are marked there
+            let runs_left = overflow_markers && doc.offset.x > 0;
+            let runs_right = overflow_markers && width(&line, tab_width) > doc.offset.x + view_w;
             let mut x_disp = doc.offset.x;
             let mut x_char = doc.character_idx(&doc.offset);
             // Run some more calcs
             let is_focus = self.ptr == ptr;
             let has_selection_somewhere = doc.cursor.selection_end != doc.cursor.loc;
+            let block = doc.block_bounds().filter(|_| is_focus);
+            let misspelled = config!(self.config, document)
+                .spell_check
+                .then(|| self.spelling.line(&(ptr.to_vec(), doc_idx), at_line))
+                .flatten();
+            let mut underlined = false;
+            // Guides mark the edges of a block selection on the rows around it
+            let (guide_bg, line_len) = (Bg(line_number_fg.0), line.chars().count());
+            let guides = block.as_ref().and_then(|(rows, cols)| {
+                let reach =
+                    rows.start.saturating_sub(BLOCK_GUIDE_REACH)..rows.end + BLOCK_GUIDE_REACH;
+                (reach.contains(&at_line) && !rows.contains(&at_line))
+                    .then(|| [cols.start, cols.end.saturating_sub(1).max(cols.start)])
+            });
+            // Rulers mark how long lines of this type of document should be kept to
+            let rulers = fc
+                .file_type
+                .as_ref()
+                .map_or(&[][..], |t| t.rulers.as_slice());
+            // Colours written in the line have a swatch of them in the column just after
+            let swatches = if self.color_preview_on(fc) {
+                color_swatches(&line)
+            } else {
+                vec![]
+            };
             for token in tokens {
                 // Find out the text (and colour of that text)
                 let (text, colour, feedback) = self.breakdown_token(token, sh)?;
@@ -341,85 +530,141 @@ impl Editor {
                     let disp_loc = Loc::at(x_disp, at_line);
                     let char_loc = Loc::at(x_char, at_line);
                     // Work out selection
-                    let is_selected = is_focus
-                        && has_selection_somewhere
-                        && doc.is_this_loc_selected_disp(disp_loc, selection);
+                    let is_selected = match &block {
+                        Some((rows, cols)) => rows.contains(&at_line) && cols.contains(&x_disp),
+                        None => {
+                            is_focus
+                                && has_selection_somewhere
+                                && doc.is_this_loc_selected_disp(disp_loc, selection)
+                        }
+                    };
+                    let is_guide = guides.is_some_and(|g| g.contains(&x_disp));
                     // Render the correct colour
                     if is_selected {
-                        if cache_bg != selection_bg {
-                            result += &selection_bg.to_string();
-                            cache_bg = selection_bg;
-                        }
-                        if cache_fg != selection_fg {
-                            result += &selection_fg.to_string();
-                            cache_fg = selection_fg;
-                        }
+                        colours.bg(&mut result, selection_bg);
+                        colours.fg(&mut result, selection_fg);
                     } else {
-                        if cache_bg != editor_bg {
-                            result += &editor_bg.to_string();
-                            cache_bg = editor_bg;
-                        }
-                        if cache_fg != colour {
-                            result += &colour.to_string();
-                            cache_fg = colour;
-                        }
+                        let swatch = swatches.iter().find(|(at, _)| *at == x_char);
+                        let bg = if let Some((_, color)) = swatch {
+                            Bg(color.to_color()?)
+                        } else if is_guide && x_char < line_len {
+                            guide_bg
+                        } else if rulers.contains(&x_disp) {
+                            line_number_bg
+                        } else {
+                            editor_bg
+                        };
+                        colours.bg(&mut result, bg);
+                        colours.fg(&mut result, colour);
+                    }
+                    // Misspelled words are underlined
+                    let misspelled_here =
+                        misspelled.is_some_and(|m| m.iter().any(|r| r.contains(&x_char)));
+                    if misspelled_here != underlined {
+                        let attribute = if misspelled_here {
+                            underline
+                        } else {
+                            no_underline
+                        };
+                        result += &attribute.to_string();
+                        underlined = misspelled_here;
                     }
                     // Render multi-cursors
                     let multi_cursor_here = doc.has_cursor(char_loc).is_some();
                     if multi_cursor_here {
                         result += &format!("{underline}{}{}", Bg(Color::White), Fg(Color::Black));
                     }
-                    // Render the character
-                    result.push(c);
+                    // Render the character (or the marker over it at the edge of the view)
+                    let c_width = width_char(&c, tab_width);
+                    let column = x_disp - doc.offset.x;
+                    let at_left = runs_left && column == 0;
+                    let at_right = runs_right && column + c_width >= view_w;
+                    if at_left || at_right {
+                        // Wider characters are padded out so the rest of the row stays put
+                        let padding = " ".repeat(c_width.saturating_sub(1));
+                        colours.fg(&mut result, line_number_fg);
+                        if at_left {
+                            result += &format!("{OVERFLOW_MARKER}{padding}");
+                        } else {
+                            result += &format!("{padding}{OVERFLOW_MARKER}");
+                        }
+                    } else if is_guide && x_char >= line_len {
+                        // Past the end of shorter lines, guides are drawn as thin lines
+                        colours.fg(&mut result, line_number_fg);
+                        result.push('│');
+                    } else {
+                        result.push(c);
+                    }
                     // Reset any multi-cursor display
                     if multi_cursor_here {
-                        result += &format!("{no_underline}{cache_bg}{cache_fg}");
+                        result += &format!("{no_underline}{}{}", colours.bg, colours.fg);
+                        underlined = false;
                     }
                     x_char += 1;
-                    let c_width = width_char(&c, tab_width);
                     x_disp += c_width;
                     total_width += c_width;
                 }
             }
-            result += &format!("{editor_fg}{editor_bg}{cache_fg}");
-            result += &" ".repeat(w.saturating_sub(total_width));
+            if underlined {
+                result += &no_underline.to_string();
+            }
+            // Return to editor colours for the rest of the row
+            colours.bg(&mut result, editor_bg);
+            colours.fg(&mut result, editor_fg);
+            // Render multi-cursors that sit at the end of the line
+            let at_end = x_char == line.chars().count();
+            if at_end && total_width < w && doc.has_cursor(Loc::at(x_char, at_line)).is_some() {
+                let (bg, fg) = (Bg(Color::White), Fg(Color::Black));
+                result += &format!(
+                    "{underline}{bg}{fg} {no_underline}{}{}",
+                    colours.bg, colours.fg
+                );
+                total_width += 1;
+                x_disp += 1;
+            }
+            // A colour at the end of the line has its swatch drawn in the space after it
+            let end_swatch = swatches.iter().find(|(at, _)| *at == x_char);
+            if let Some((_, color)) = end_swatch.filter(|_| at_end && total_width < w) {
+                colours.bg(&mut result, Bg(color.to_color()?));
+                result.push(' ');
+                colours.bg(&mut result, editor_bg);
+                total_width += 1;
+                x_disp += 1;
+            }
+            let padding = w.saturating_sub(total_width);
+            if guides.is_some() || !rulers.is_empty() {
+                // Guides and rulers can fall past the end of shorter lines
+                for col in x_disp..x_disp + padding {
+                    let bg = if rulers.contains(&col) {
+                        line_number_bg
+                    } else {
+                        editor_bg
+                    };
+                    colours.bg(&mut result, bg);
+                    if guides.is_some_and(|g| g.contains(&col)) {
+                        colours.fg(&mut result, line_number_fg);
+                        result.push('│');
+                    } else {
+                        result.push(' ');
+                    }
+                }
+                colours.bg(&mut result, editor_bg);
+                colours.fg(&mut result, editor_fg);
+            } else {
+                result += &" ".repeat(padding);
+            }
         } else if config!(self.config, greeting_message).enabled && self.greet && has_file {
             // Render the greeting message (if enabled)
-            result += &self.render_greeting(y, w, h)?;
+            let room = doc.size.h;
+            result += &self.render_greeting(y, w, h, room)?;
         } else {
             // Empty line, just pad out with spaces to prevent artefacts
             result += &" ".repeat(w);
         }
-        // Add on help message if applicable
-        if help_message_here {
-            result += &self.render_help_message(y)?;
-        }
         // Send out the result
         Ok(result)
     }
 
-    /// Render help message
-    pub fn render_help_message(&self, y: usize) -> Result<String> {
-        let tab_width = config!(self.config, document).tab_width;
-        let colors = Fg(config!(self.config, colors).highlight.to_color()?);
-        let editor_fg = Fg(config!(self.config, colors).editor_fg.to_color()?);
-        let at = y.saturating_sub(self.render_cache.help_message_span.start);
-        let max_width = self.render_cache.help_message_width;
-        let (hl, msg) = self
-            .render_cache
-            .help_message
-            .get(at)
-            .map_or((false, " ".repeat(max_width)), |(hl, content)| {
-                (*hl, content.to_string())
-            });
-        let extra_padding = " ".repeat(max_width.saturating_sub(width(&msg, tab_width)));
-        if hl {
-            Ok(format!("{colors}{msg}{extra_padding}{editor_fg}"))
-        } else {
-            Ok(format!("{editor_fg}{msg}{extra_padding}"))
-        }
-    }
-
     /// Take a token and try to break it down into a colour and text
     pub fn breakdown_token(
         &self,
@@ -448,39 +693,6 @@ impl Editor {
         }
     }
 
-    /// Get list of tabs
-    pub fn get_tab_parts(
-        &mut self,
-        ptr: &[usize],
-        lua: &Lua,
-        w: usize,
-    ) -> (Vec<String>, usize, usize) {
-        let mut headers: Vec<String> = vec![];
-        let mut idx = 0;
-        let mut length = 0;
-        let mut offset = 0;
-        let tab_line = config!(self.config, tab_line);
-        let doc_idx = self
-            .files
-            .get_atom(ptr.to_owned())
-            .map_or(0, |(_, doc_idx)| doc_idx);
-        for (c, file) in self.files.get_all(ptr.to_vec()).iter().enumerate() {
-            let render = tab_line.render(lua, file, &mut self.feedback);
-            length += width(&render, 4) + 1;
-            headers.push(render);
-            if c == doc_idx {
-                idx = headers.len().saturating_sub(1);
-            }
-            while c == doc_idx && length >= w && headers.len() > 1 {
-                headers.remove(0);
-                length = length.saturating_sub(width(&headers[0], 4) + 1);
-                idx = headers.len().saturating_sub(1);
-                offset += 1;
-            }
-        }
-        (headers, idx, offset)
-    }
-
     /// Render the tab line at the top of the document
     #[allow(clippy::similar_names)]
     pub fn render_tab_line(&mut self, ptr: &[usize], lua: &Lua, w: usize) -> Result<String> {
@@ -490,34 +702,53 @@ impl Editor {
         let tab_active_fg = Fg(config!(self.config, colors).tab_active_fg.to_color()?);
         let tab_width = config!(self.config, document).tab_width;
         let separator_enabled = config!(self.config, tab_line).separators;
+        let layout = self.tab_layout(ptr, lua, w);
+        let overflow = layout.hidden_left || layout.hidden_right;
+        // Tabs stop short of the indicator on the right when they don't all fit
+        let limit = w.saturating_sub(usize::from(overflow));
         let mut current_width = 0;
-        let (tabs, idx, _) = self.get_tab_parts(ptr, lua, w);
         let mut result = format!("{tab_inactive_fg}{tab_inactive_bg}");
-        for (c, header) in tabs.iter().enumerate() {
-            // Work out what to render and what not to render based on situation
-            let pushes_over =
-                (current_width + width(header, tab_width) + usize::from(separator_enabled))
-                    .saturating_sub(w);
-            let render_sep = separator_enabled && pushes_over == 0;
+        if overflow {
+            result.push(if layout.hidden_left { SCROLL_LEFT } else { ' ' });
+            current_width += 1;
+        }
+        for (c, (header, placement)) in layout.headers.iter().zip(&layout.placements).enumerate() {
+            let close = if placement.close.is_some() {
+                format!("{TAB_CLOSE} ")
+            } else {
+                String::new()
+            };
+            // A tab too wide for the tab line by itself is cut short
+            let text = trim(
+                &format!("{header}{close}"),
+                0,
+                limit.saturating_sub(current_width),
+                tab_width,
+            );
+            let text_width = width(&text, tab_width);
+            let render_sep = separator_enabled && current_width + text_width < limit;
             // Calculate the string format
-            if c == idx {
+            if c == layout.active {
                 result += &format!(
-                    "{tab_active_bg}{tab_active_fg}{}{header}{}{tab_inactive_fg}{tab_inactive_bg}{}",
+                    "{tab_active_bg}{tab_active_fg}{}{text}{}{tab_inactive_fg}{tab_inactive_bg}{}",
                     SetAttribute(Attribute::Bold),
                     SetAttribute(Attribute::Reset),
                     if render_sep { "│" } else { "" },
                 );
             } else {
-                result += &format!("{header}{}", if render_sep { "│" } else { "" });
-            }
-            current_width += width(header, tab_width) + usize::from(render_sep);
-            // Don't bother continuing to render if we've gone over
-            if pushes_over > 0 {
-                break;
+                result += &format!("{text}{}", if render_sep { "│" } else { "" });
             }
+            current_width += text_width + usize::from(render_sep);
         }
         // Pad out
-        result += &" ".to_string().repeat(w.saturating_sub(current_width));
+        result += &" ".to_string().repeat(limit.saturating_sub(current_width));
+        if overflow {
+            result.push(if layout.hidden_right {
+                SCROLL_RIGHT
+            } else {
+                ' '
+            });
+        }
         Ok(result)
     }
 
@@ -526,10 +757,45 @@ impl Editor {
     pub fn render_status_line(&mut self, ptr: &[usize], lua: &Lua, w: usize) -> Result<String> {
         let editor_bg = Bg(config!(self.config, colors).editor_bg.to_color()?);
         let editor_fg = Fg(config!(self.config, colors).editor_fg.to_color()?);
-        let status_bg = Bg(config!(self.config, colors).status_bg.to_color()?);
-        let status_fg = Fg(config!(self.config, colors).status_fg.to_color()?);
+        let mut status_bg = Bg(config!(self.config, colors).status_bg.to_color()?);
+        let mut status_fg = Fg(config!(self.config, colors).status_fg.to_color()?);
+        // The visual bell inverts the status line for a moment
+        if self.flashing() {
+            status_bg = Bg(status_fg.0);
+            status_fg = Fg(config!(self.config, colors).status_bg.to_color()?);
+        }
         let mut result = String::new();
+        // Running without the configuration file is pointed out for as long as it lasts
+        let mut w = w;
+        if self.safe_mode {
+            let warning = format!(" {SAFE_MODE_WARNING} ");
+            let warning_bg = Bg(config!(self.config, colors).warning_bg.to_color()?);
+            let warning_fg = Fg(config!(self.config, colors).warning_fg.to_color()?);
+            result += &format!(
+                "{warning_bg}{warning_fg}{}{warning}{}",
+                SetAttribute(Attribute::Bold),
+                SetAttribute(Attribute::Reset)
+            );
+            w = w.saturating_sub(width(&warning, 4));
+        }
+        // A count waiting for the key to repeat is shown in the focused pane
+        if let Some(count) = self.count.pending.filter(|_| self.ptr == ptr) {
+            let count = if count == 0 {
+                " count: _ ".to_string()
+            } else {
+                format!(" count: {count} ")
+            };
+            let info_bg = Bg(config!(self.config, colors).info_bg.to_color()?);
+            let info_fg = Fg(config!(self.config, colors).info_fg.to_color()?);
+            result += &format!(
+                "{info_bg}{info_fg}{}{count}{}",
+                SetAttribute(Attribute::Bold),
+                SetAttribute(Attribute::Reset)
+            );
+            w = w.saturating_sub(width(&count, 4));
+        }
         result += &format!("{status_bg}{status_fg}");
+        self.refresh_status_info(ptr);
         match config!(self.config, status_line).render(ptr, self, lua, w) {
             Ok(content) => {
                 if content.is_empty() {
@@ -551,40 +817,101 @@ impl Editor {
         Ok(result)
     }
 
-    /// Render the feedback line
+    /// Render the keys a menu (such as search or replace) takes on the bottom row of the screen,
+    /// coloured like the status line and cut short to fit rather than wrapping
+    pub fn render_menu_status(&mut self, text: &str, w: usize, h: usize) -> Result<()> {
+        let editor_bg = Bg(config!(self.config, colors).editor_bg.to_color()?);
+        let editor_fg = Fg(config!(self.config, colors).editor_fg.to_color()?);
+        let status_bg = Bg(config!(self.config, colors).status_bg.to_color()?);
+        let status_fg = Fg(config!(self.config, colors).status_fg.to_color()?);
+        let tab_width = config!(self.config, document).tab_width;
+        let mut text = text.to_string();
+        if width(&text, tab_width) > w {
+            text = trim(&text, 0, w.saturating_sub(1), tab_width);
+            text.push(OVERFLOW_MARKER);
+        }
+        let padding = " ".repeat(w.saturating_sub(width(&text, tab_width)));
+        self.terminal.draw_line(
+            h,
+            format!(
+                "{status_bg}{status_fg}{}{text}{padding}{}{editor_fg}{editor_bg}",
+                SetAttribute(Attribute::Bold),
+                SetAttribute(Attribute::Reset),
+            ),
+        );
+        Ok(())
+    }
+
+    /// Render the feedback line on the bottom row of the screen (at `h`, as given by `Terminal::size`)
     pub fn render_feedback_line(&mut self, w: usize, h: usize) -> Result<()> {
-        self.terminal.goto(0, h + 2);
         let content = self.feedback.render(&config!(self.config, colors), w)?;
-        display!(self, content);
+        self.terminal.draw_line(h, content);
+        Ok(())
+    }
+
+    /// Fill the screen with a placeholder, for when it is too small to draw the editor in
+    fn render_too_small(&mut self, size: Size) -> Result<()> {
+        let editor_bg = Bg(config!(self.config, colors).editor_bg.to_color()?);
+        let editor_fg = Fg(config!(self.config, colors).editor_fg.to_color()?);
+        for y in 0..=size.h {
+            let text = if y == size.h / 2 { TOO_SMALL } else { "" };
+            let text: String = text.chars().take(size.w).collect();
+            let line = alinio::align::center(&text, size.w).unwrap_or(text);
+            self.terminal
+                .draw_line(y, format!("{editor_bg}{editor_fg}{line}"));
+        }
         Ok(())
     }
 
+    /// Tidy up the screen after the terminal is resized while a prompt or menu is open.
+    /// What was on screen is drawn again (cut to the new size) so no garbage is left behind,
+    /// and the editor is drawn properly at the new size as soon as it next renders
+    pub fn resized_under_menu(&mut self) {
+        self.terminal.repaint();
+        self.needs_rerender = true;
+    }
+
+    /// The size a document gets when it fills the screen,
+    /// leaving room for the tab line, status line and feedback line
+    pub fn document_size(&self) -> Result<Size> {
+        let mut size = self.terminal.size()?;
+        size.h = size.h.saturating_sub(self.status_rows() + self.push_down);
+        Ok(size)
+    }
+
     /// Render the greeting message
-    fn render_greeting(&mut self, y: usize, w: usize, h: usize) -> Result<String> {
+    fn render_greeting(&mut self, y: usize, w: usize, h: usize, room: usize) -> Result<String> {
         // Produce the greeting message
         let colors = config!(self.config, colors);
         let highlight = Fg(colors.highlight.to_color()?).to_string();
         let editor_fg = Fg(colors.editor_fg.to_color()?).to_string();
-        let (message, highlights) = &self.render_cache.greeting_message;
-        let message: Vec<&str> = message.split('\n').collect();
+        let (rows, plain) = self.greeting_layout(w);
         // Select the correct line
-        let greeting_span = (h / 4)..(h / 4 + message.len());
-        let line = if greeting_span.contains(&y) {
-            message[y.saturating_sub(h / 4)]
-        } else {
-            ""
+        let top = greeting_top(rows.len(), h, room);
+        let Some(row) = y.checked_sub(top).and_then(|y| rows.get(y)) else {
+            return Ok(" ".repeat(w));
         };
-        let mut content = alinio::align::center(line, w).unwrap_or_default();
-        if highlights.contains(&y.saturating_sub(h / 4)) {
+        let padding = w.saturating_sub(row.start + width(&row.text, 4));
+        let mut content = row.text.clone();
+        if row.highlighted {
             content = format!("{highlight}{content}{editor_fg}");
+        } else if let (Some(key), false) = (row.entry, plain) {
+            // The key to press stands out from the label
+            let label: String = content.chars().skip(1).collect();
+            content = format!("{highlight}{key}{editor_fg}{label}");
         }
         // Output
-        Ok(content)
+        Ok(format!(
+            "{}{content}{}",
+            " ".repeat(row.start),
+            " ".repeat(padding)
+        ))
     }
 
     /// Render a line in the file tree
     #[allow(clippy::similar_names)]
     fn render_file_tree(&mut self, y: usize, length: usize) -> Result<String> {
+        let y = y + self.render_cache.file_tree_scroll;
         let selected = self.render_cache.file_tree_selection == Some(y);
         let ft_bg = Bg(config!(self.config, colors).file_tree_bg.to_color()?);
         let ft_fg = Fg(config!(self.config, colors).file_tree_fg.to_color()?);
@@ -598,8 +925,27 @@ impl Editor {
         // Perform the rendering
         let mut total_length = 0;
         let line = self.render_cache.file_tree.get(y);
-        let mut line = if let Some((padding, icon, icon_colour, name)) = line {
-            total_length = padding * 2 + width(icon, 4) + width(name, 4);
+        let mut line = if let Some((padding, icon, icon_colour, name, git)) = line {
+            // Names are coloured by their git status and marked after
+            let (status, marker) = match git {
+                Some((state, marker)) if !selected => {
+                    let colour = match state {
+                        GitState::Modified => &ft_colors.file_tree_orange,
+                        GitState::Staged => &ft_colors.file_tree_green,
+                        GitState::Untracked => &ft_colors.file_tree_lightblue,
+                        GitState::Ignored => &ft_colors.file_tree_grey,
+                    };
+                    (Fg(colour.to_color()?).to_string(), *marker)
+                }
+                Some((_, marker)) => (String::new(), *marker),
+                None => (String::new(), ""),
+            };
+            let marker = if marker.is_empty() {
+                String::new()
+            } else {
+                format!(" {marker}")
+            };
+            total_length = padding * 2 + width(icon, 4) + width(name, 4) + width(&marker, 4);
             if let (Some(colour), false) = (icon_colour, selected) {
                 let colour = Fg(match colour.as_str() {
                     "red" => ft_colors.file_tree_red.to_color()?,
@@ -614,9 +960,12 @@ impl Editor {
                     "grey" => ft_colors.file_tree_grey.to_color()?,
                     _ => Color::White,
                 });
-                format!("{}{colour}{icon}{ft_fg}{name}", "  ".repeat(*padding))
+                format!(
+                    "{}{colour}{icon}{ft_fg}{status}{name}{marker}",
+                    "  ".repeat(*padding)
+                )
             } else {
-                format!("{}{icon}{name}", "  ".repeat(*padding))
+                format!("{}{icon}{status}{name}{marker}", "  ".repeat(*padding))
             }
         } else {
             String::new()
@@ -686,13 +1035,39 @@ impl Editor {
 
     /// Display a prompt in the document
     pub fn prompt<S: Into<String>>(&mut self, prompt: S) -> Result<String> {
+        self.prompt_completing(prompt, |_, _| None)
+    }
+
+    /// Display the command line prompt, where the tab key completes macro names
+    pub fn command_prompt(&mut self) -> Result<String> {
+        self.prompt_completing("Command", |editor, input| {
+            let prefix = input.strip_prefix("macro_run ")?;
+            let names = editor.macro_man.complete(prefix);
+            // Complete as far as all the possible names agree
+            let mut common = (*names.first()?).to_string();
+            for name in &names {
+                while !name.starts_with(&common) {
+                    common.pop();
+                }
+            }
+            Some(format!("macro_run {common}"))
+        })
+    }
+
+    /// Display a prompt, using a function to complete the input when tab is pressed
+    fn prompt_completing<S: Into<String>>(
+        &mut self,
+        prompt: S,
+        complete: fn(&Self, &str) -> Option<String>,
+    ) -> Result<String> {
+        self.needs_terminal()?;
         let prompt = prompt.into();
         let mut input = String::new();
         let mut done = false;
         // Enter into a menu that asks for a prompt
         while !done {
-            let h = size()?.h;
-            let w = size()?.w;
+            let h = self.terminal.size()?.h;
+            let w = self.terminal.size()?.w;
             // Render prompt message
             self.terminal.prepare_line(h);
             self.terminal.show_cursor();
@@ -722,6 +1097,12 @@ impl Editor {
                     }
                     // Add to the input string if the user presses a character
                     (KMod::NONE | KMod::SHIFT, KCode::Char(c)) => input.push(c),
+                    // Complete the input if possible
+                    (KMod::NONE, KCode::Tab) => {
+                        if let Some(completed) = complete(self, &input) {
+                            input = completed;
+                        }
+                    }
                     _ => (),
                 }
             }
@@ -733,6 +1114,7 @@ impl Editor {
     /// Prompt for selecting a file
     #[allow(clippy::similar_names)]
     pub fn path_prompt(&mut self) -> Result<String> {
+        self.needs_terminal()?;
         let mut input = get_cwd()
             .map(|p| {
                 if p.ends_with(std::path::MAIN_SEPARATOR) {
@@ -770,7 +1152,7 @@ impl Editor {
                 .map(std::string::ToString::to_string)
                 .unwrap_or(input.clone());
             // Render prompt message
-            let h = size()?.h;
+            let h = self.terminal.size()?.h;
             self.terminal.prepare_line(h);
             self.terminal.show_cursor();
             let suggestion_text = suggestion
@@ -781,7 +1163,7 @@ impl Editor {
             let editor_bg = Bg(config!(self.config, colors).editor_bg.to_color()?);
             let tab_width = config!(self.config, document).tab_width;
             let total_width = width(&input, tab_width) + width(&suggestion_text, tab_width);
-            let padding = " ".repeat(size()?.w.saturating_sub(total_width));
+            let padding = " ".repeat(self.terminal.size()?.w.saturating_sub(total_width));
             display!(
                 self,
                 editor_bg,
@@ -845,41 +1227,62 @@ impl Editor {
         Ok(input)
     }
 
-    /// Confirmation dialog
-    pub fn confirm(&mut self, msg: &str) -> Result<bool> {
-        let mut done = false;
-        let mut result = false;
-        // Enter into the confirmation menu
-        self.terminal.hide_cursor();
-        while !done {
-            let h = size()?.h;
-            let w = size()?.w;
-            // Render message
-            self.feedback = Feedback::Warning(msg.to_string());
-            self.render_feedback_line(w, h)?;
-            self.terminal.flush()?;
-            // Handle events
-            if let Some((modifiers, code)) =
-                key_event(&wait_for_event_hog(self), &mut self.macro_man)
-            {
-                match (modifiers, code) {
-                    // Exit the menu when the enter key is pressed
-                    (KMod::NONE, KCode::Esc) => {
-                        done = true;
-                        self.feedback = Feedback::None;
-                    }
-                    // Add to the input string if the user presses a character
-                    (KMod::CONTROL, KCode::Char('q')) => {
-                        done = true;
-                        result = true;
-                        self.feedback = Feedback::None;
-                    }
-                    _ => (),
+    /// Draw a list to pick from over the bottom of the document, with key hints below it
+    pub fn render_list(&mut self, items: &[String], selected: usize, hint: &str) -> Result<()> {
+        self.needs_terminal()?;
+        let size = self.terminal.size()?;
+        let colors = config!(self.config, colors);
+        let bg = Bg(colors.file_tree_bg.to_color()?);
+        let fg = Fg(colors.file_tree_fg.to_color()?);
+        let selection_bg = Bg(colors.file_tree_selection_bg.to_color()?);
+        let selection_fg = Fg(colors.file_tree_selection_fg.to_color()?);
+        let editor_bg = Bg(colors.editor_bg.to_color()?);
+        let editor_fg = Fg(colors.editor_fg.to_color()?);
+        let tab_width = config!(self.config, document).tab_width;
+        // Scroll so that the selected item is always in view
+        let rows = MAX_LISTED.min(items.len()).min(size.h.saturating_sub(1));
+        let first = (selected + 1).saturating_sub(rows);
+        let mut region = OverlayRegion {
+            rows: size.h - rows..size.h + 1,
+            cols: 0..size.w,
+            close: None,
+            items: vec![],
+        };
+        for row in 0..rows {
+            let y = size.h - rows + row;
+            let idx = first + row;
+            let (bg, fg) = if idx == selected {
+                (selection_bg, selection_fg)
+            } else {
+                (bg, fg)
+            };
+            // The top row leaves room for the close glyph
+            let reserved = if row == 0 { 3 } else { 0 };
+            let mut line = format!("{bg}{fg} ");
+            let mut total = 1;
+            for c in items[idx].chars() {
+                total += width_char(&c, tab_width);
+                if total + reserved >= size.w {
+                    break;
                 }
+                line.push(c);
+            }
+            line += &" ".repeat(size.w.saturating_sub(total + reserved));
+            if row == 0 {
+                line += &format!(" {CLOSE_GLYPH} ");
+                region.close = Some((size.w.saturating_sub(2), y));
             }
+            self.terminal.draw_line(y, line);
+            region.items.push((y, idx));
         }
-        self.terminal.show_cursor();
-        Ok(result)
+        self.render_cache.overlay = Some(region);
+        // Render the key hints
+        let padding = " ".repeat(size.w.saturating_sub(width(hint, tab_width)));
+        self.terminal
+            .draw_line(size.h, format!("{editor_bg}{editor_fg}{hint}{padding}"));
+        self.terminal.hide_cursor();
+        self.terminal.flush()?;
+        Ok(())
     }
 
     /// Append any missed lines to the syntax highlighter
@@ -891,7 +1294,12 @@ impl Editor {
 
     /// Update highlighter of a certain document
     pub fn update_highlighter_for(&mut self, ptr: &[usize], doc: usize) {
-        let percieved = self.highlighter_for(ptr.to_owned(), doc).line_ref.len();
+        let Some(percieved) = self
+            .highlighter_for(ptr.to_owned(), doc)
+            .map(|highlighter| highlighter.line_ref.len())
+        else {
+            return;
+        };
         if self.active {
             if let Some((ref mut fcs, _)) = self.files.get_atom_mut(ptr.to_owned()) {
                 let actual = fcs[doc].doc.info.loaded_to;
@@ -906,29 +1314,89 @@ impl Editor {
         }
     }
 
-    /// Returns a highlighter at a certain index
-    pub fn get_highlighter(&mut self, idx: usize) -> &mut Highlighter {
-        &mut self.files.get_atom_mut(self.ptr.clone()).unwrap().0[idx].highlighter
+    /// Returns a highlighter at a certain index (if there is a document there)
+    pub fn get_highlighter(&mut self, idx: usize) -> Option<&mut Highlighter> {
+        let (fcs, _) = self.files.get_atom_mut(self.ptr.clone())?;
+        Some(&mut fcs.get_mut(idx)?.highlighter)
     }
 
-    /// Gets a mutable reference to the current document
-    pub fn highlighter(&mut self) -> &mut Highlighter {
-        &mut self.files.get_mut(self.ptr.clone()).unwrap().highlighter
+    /// Gets a mutable reference to the highlighter of the current document (if there is one)
+    pub fn highlighter(&mut self) -> Option<&mut Highlighter> {
+        Some(&mut self.files.get_mut(self.ptr.clone())?.highlighter)
     }
 
-    /// Gets a mutable reference to the current document
-    pub fn highlighter_for(&self, ptr: Vec<usize>, doc: usize) -> &Highlighter {
-        &self.files.get_atom(ptr).unwrap().0[doc].highlighter
+    /// Gets a reference to the highlighter of a document (if it is still open)
+    pub fn highlighter_for(&self, ptr: Vec<usize>, doc: usize) -> Option<&Highlighter> {
+        Some(&self.files.get_atom(ptr)?.0.get(doc)?.highlighter)
     }
 
     /// Reload the whole document in the highlighter
     pub fn reload_highlight(&mut self) {
-        if let Some(file) = self.files.get_mut(self.ptr.clone()) {
-            file.highlighter.run(&file.doc.lines);
+        if let Some((_, doc)) = self.files.get_atom(self.ptr.clone()) {
+            let _ = self.reload_highlight_at(&self.ptr.clone(), doc);
         }
     }
 
-    /// Work out how much to push the document to the right (to make way for line numbers)
+    /// Reload the whole of any open document in its highlighter
+    pub fn reload_highlight_at(&mut self, ptr: &[usize], doc: usize) -> Result<()> {
+        let file = self.file_at_mut(ptr, doc)?;
+        file.highlighter.run(&file.doc.lines);
+        if self.is_visible(ptr, doc) {
+            self.needs_rerender = true;
+        }
+        Ok(())
+    }
+
+    /// Update the highlighter for a region of lines that changed (see `Document::changed_region`)
+    /// as well as the lines currently in view, leaving the rest of the document alone
+    pub fn highlight_region(&mut self, region: (usize, usize, usize)) {
+        if let Some((_, doc)) = self.files.get_atom(self.ptr.clone()) {
+            let _ = self.highlight_region_at(&self.ptr.clone(), doc, region);
+        }
+    }
+
+    /// Update the highlighter of any open document for a region of lines that changed
+    pub fn highlight_region_at(
+        &mut self,
+        ptr: &[usize],
+        doc: usize,
+        (start, old_end, new_end): (usize, usize, usize),
+    ) -> Result<()> {
+        {
+            let file = self.file_at_mut(ptr, doc)?;
+            let (doc, hl) = (&file.doc, &mut file.highlighter);
+            // Swap out the lines that changed (only those that are loaded matter)
+            for _ in start..old_end {
+                if start >= hl.line_ref.len() {
+                    break;
+                }
+                hl.remove_line(start);
+            }
+            for y in start..new_end.min(doc.lines.len()) {
+                if y > hl.line_ref.len() {
+                    break;
+                }
+                hl.insert_line(y, &doc.lines[y]);
+            }
+            // Keep the highlighter in line with the number of loaded lines
+            while hl.line_ref.len() > doc.lines.len() {
+                hl.remove_line(hl.line_ref.len() - 1);
+            }
+            // Refresh what is on screen
+            let viewport = doc.offset.y..(doc.offset.y + doc.size.h).min(hl.line_ref.len());
+            for y in viewport {
+                hl.edit(y, &doc.lines[y]);
+            }
+        }
+        self.update_highlighter_for(ptr, doc);
+        if self.is_visible(ptr, doc) {
+            self.needs_rerender = true;
+        }
+        Ok(())
+    }
+
+    /// Work out how much to push the document to the right
+    /// (to make way for line numbers, and to centre the text in distraction-free mode)
     pub fn dent(&self) -> usize {
         if let Some((_, doc)) = self.files.get_atom(self.ptr.clone()) {
             self.dent_for(&self.ptr, doc)
@@ -937,16 +1405,24 @@ impl Editor {
         }
     }
 
-    /// Work out how much to push the document to the right (to make way for line numbers)
+    /// Work out how much to push the document to the right
+    /// (to make way for line numbers, and to centre the text in distraction-free mode)
     pub fn dent_for(&self, at: &[usize], doc: usize) -> usize {
+        self.zen_margin(at) + self.line_number_width(at, doc)
+    }
+
+    /// Work out how wide the line numbers of a document are
+    fn line_number_width(&self, at: &[usize], doc: usize) -> usize {
         if config!(self.config, line_numbers).enabled {
             let padding_left = config!(self.config, line_numbers).padding_left;
             let padding_right = config!(self.config, line_numbers).padding_right;
-            if let Some((fcs, _)) = self.files.get_atom(at.to_owned()) {
-                fcs[doc].doc.len_lines().to_string().len() + 1 + padding_left + padding_right
-            } else {
-                0
-            }
+            // A pane whose document has just closed has no line numbers to make room for
+            self.files
+                .get_atom(at.to_owned())
+                .and_then(|(fcs, _)| fcs.get(doc))
+                .map_or(0, |fc| {
+                    fc.doc.len_lines().to_string().len() + 1 + padding_left + padding_right
+                })
         } else {
             0
         }
@@ -0,0 +1,333 @@
+/// Loading: reading large files in the background so the editor stays responsive,
+/// showing the start of a file straight away while the rest of it is read in
+use crate::config;
+use crate::error::OxError;
+use crate::Feedback;
+use kaolinite::document::{decode, detect_encoding, is_binary, read_rope, Decoding};
+use kaolinite::event::Result as KResult;
+use kaolinite::utils::{get_file_name, get_path_identity};
+use kaolinite::{Document, Size};
+use ropey::Rope;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError, TryRecvError};
+use std::sync::Arc;
+use std::time::Duration;
+use synoptic::Highlighter;
+
+use super::Editor;
+
+/// Files bigger than this (in bytes) are read in the background
+const BACKGROUND_SIZE: u64 = 8 * 1024 * 1024;
+/// How much of a file (in bytes) is shown while the rest of it is read in
+const PREVIEW_BYTES: u64 = 64 * 1024;
+/// How often progress is shown while waiting for a file to be read
+const WAIT_PERIOD: Duration = Duration::from_millis(20);
+
+/// What reading a file in the background comes back with
+type Outcome = KResult<Document>;
+
+/// A file being read in the background
+#[derive(Debug)]
+struct Load {
+    /// How many bytes have been read so far
+    read: Arc<AtomicU64>,
+    /// How many bytes there are to read
+    total: u64,
+    /// How many bytes from the start of the file its previews hold
+    shown: u64,
+    result: Receiver<Outcome>,
+}
+
+/// The files being read in the background (by the identity of their paths)
+#[derive(Debug, Default)]
+pub struct Loads {
+    files: HashMap<String, Load>,
+}
+
+impl Loads {
+    /// How much of a file has been read, as a percentage (if it is still being read)
+    pub fn progress(&self, file_name: &str) -> Option<u64> {
+        let load = self.files.get(&get_path_identity(file_name))?;
+        let read = load.read.load(Ordering::Relaxed);
+        Some((read * 100 / load.total.max(1)).min(100))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+}
+
+/// Read the start of a file, cut off at the end of the last whole line, into a document
+/// (alongside how many bytes of the file it holds)
+fn preview(size: Size, file_name: &str) -> Option<(Document, u64)> {
+    let mut sample = vec![];
+    File::open(file_name)
+        .ok()?
+        .take(PREVIEW_BYTES)
+        .read_to_end(&mut sample)
+        .ok()?;
+    // Binary files are shown as a hex preview instead
+    if is_binary(&sample) {
+        return None;
+    }
+    // Leave out the last line, which could be cut off part of the way through a character
+    let whole = sample
+        .iter()
+        .rposition(|b| *b == b'\n')
+        .map_or(0, |n| n + 1);
+    let (encoding, bom) = detect_encoding(&sample[..whole]);
+    let (mut text, _) = decode(&sample[bom..], encoding);
+    text.truncate(text.rfind('\n').map_or(0, |n| n + 1));
+    let file = Rope::from_str(&text);
+    let mut doc = Document::from_rope(size, file_name, file, encoding, bom != 0, false);
+    // Nothing can be changed until the whole file is there
+    doc.info.read_only = true;
+    Some((doc, whole as u64))
+}
+
+/// Read at least a certain number of lines of a file, starting a number of bytes in
+/// and cut off at the end of the last whole line (fewer lines are read at the end of the file)
+fn read_lines(file_name: &str, from: u64, lines: usize) -> Option<Vec<u8>> {
+    let mut file = File::open(file_name).ok()?;
+    file.seek(SeekFrom::Start(from)).ok()?;
+    let mut reader = BufReader::new(file);
+    let mut bytes = vec![];
+    for _ in 0..lines {
+        if reader.read_until(b'\n', &mut bytes).ok()? == 0 {
+            break;
+        }
+    }
+    let whole = bytes.iter().rposition(|b| *b == b'\n').map_or(0, |n| n + 1);
+    bytes.truncate(whole);
+    Some(bytes)
+}
+
+impl Editor {
+    /// Start reading a large file in the background, giving back a read only document
+    /// holding the start of it to show in the meantime (or None if the file is small enough
+    /// to be read in straight away)
+    pub fn start_loading(&mut self, size: Size, file_name: &str) -> Option<Document> {
+        let total = std::fs::metadata(file_name).ok()?.len();
+        if total <= BACKGROUND_SIZE || self.headless {
+            return None;
+        }
+        let (doc, shown) = preview(size, file_name)?;
+        let read = Arc::new(AtomicU64::new(0));
+        let (sender, result) = channel();
+        let (path, progress) = (file_name.to_string(), read.clone());
+        std::thread::spawn(move || {
+            let read = read_rope(&path, |n| progress.store(n, Ordering::Relaxed));
+            let doc = read.map(|(file, encoding, bom, undecodable)| {
+                Document::from_rope(size, &path, file, encoding, bom != 0, undecodable)
+            });
+            // The editor may have quit (or the document closed) by now
+            let _ = sender.send(doc);
+        });
+        let load = Load {
+            read,
+            total,
+            shown,
+            result,
+        };
+        self.loads.files.insert(get_path_identity(file_name), load);
+        Some(doc)
+    }
+
+    /// Whether a document is only partly there because it is still being read in
+    pub fn is_loading(&self, doc: &Document) -> bool {
+        !self.loads.is_empty()
+            && doc
+                .file_name
+                .as_ref()
+                .is_some_and(|f| self.loads.progress(f).is_some())
+    }
+
+    /// Put any files that have finished being read in place of their previews,
+    /// returning whether anything needs redrawing (which is the case while files are being read)
+    pub fn loading_idle_tick(&mut self) -> bool {
+        if self.loads.is_empty() {
+            return false;
+        }
+        let finished: Vec<(String, Outcome)> = self
+            .loads
+            .files
+            .iter()
+            .filter_map(|(identity, load)| match load.result.try_recv() {
+                Ok(read) => Some((identity.clone(), read)),
+                Err(TryRecvError::Disconnected) => Some((
+                    identity.clone(),
+                    Err(std::io::Error::other("the file stopped being read").into()),
+                )),
+                Err(TryRecvError::Empty) => None,
+            })
+            .collect();
+        for (identity, read) in finished {
+            self.loads.files.remove(&identity);
+            self.install(&identity, read);
+        }
+        self.needs_rerender = true;
+        true
+    }
+
+    /// Wait for a file to finish being read (showing how far through it is),
+    /// then put it in place of its preview. If the wait is stopped, the preview is left as it is
+    #[allow(clippy::cast_precision_loss)]
+    fn finish_loading(&mut self, identity: &str) {
+        let Some(load) = self.loads.files.remove(identity) else {
+            return;
+        };
+        let file = get_file_name(identity).unwrap_or_default();
+        self.start_progress(&format!("Reading '{file}'"));
+        let read = loop {
+            match load.result.recv_timeout(WAIT_PERIOD) {
+                Ok(read) => break Some(read),
+                Err(RecvTimeoutError::Disconnected) => {
+                    break Some(Err(
+                        std::io::Error::other("the file stopped being read").into()
+                    ))
+                }
+                Err(RecvTimeoutError::Timeout) => {
+                    let read = load.read.load(Ordering::Relaxed);
+                    if !self.tick(read as f64 / load.total.max(1) as f64) {
+                        break None;
+                    }
+                }
+            }
+        };
+        self.finish();
+        match read {
+            Some(read) => self.install(identity, read),
+            None => {
+                self.feedback = Feedback::Warning(format!(
+                    "Stopped reading '{file}', only the start of it is open (as read only)"
+                ));
+            }
+        }
+        self.needs_rerender = true;
+    }
+
+    /// Make sure the whole of a document is there (waiting for it to be read if it isn't yet)
+    pub fn wait_for_document_at(&mut self, ptr: &[usize], doc: usize) {
+        if self.loads.is_empty() {
+            return;
+        }
+        let Some(file_name) = self
+            .file_at(ptr, doc)
+            .ok()
+            .and_then(|fc| fc.doc.file_name.clone())
+        else {
+            return;
+        };
+        self.finish_loading(&get_path_identity(&file_name));
+    }
+
+    /// Make sure the whole of the document being edited is there
+    pub fn wait_for_document(&mut self) {
+        if let Some((_, doc)) = self.files.get_atom(self.ptr.clone()) {
+            self.wait_for_document_at(&self.ptr.clone(), doc);
+        }
+    }
+
+    /// Make sure every document is all there
+    pub fn wait_for_all_documents(&mut self) {
+        let loading: Vec<String> = self.loads.files.keys().cloned().collect();
+        for identity in loading {
+            self.finish_loading(&identity);
+        }
+    }
+
+    /// Read another screen of the document being edited straight away if the cursor
+    /// (or the view) is getting close to the end of what has been read so far
+    pub fn wait_if_near_end(&mut self) {
+        let Some(doc) = self.try_doc() else {
+            return;
+        };
+        let furthest = doc.loc().y.max(doc.offset.y);
+        if self.is_loading(doc) && furthest + doc.size.h >= doc.len_lines() {
+            let needed = furthest + doc.size.h * 2;
+            if let Some(file_name) = doc.file_name.clone() {
+                self.extend_previews(&get_path_identity(&file_name), needed);
+            }
+        }
+    }
+
+    /// Read more of a file onto the end of its previews, so they hold at least a number of lines
+    fn extend_previews(&mut self, identity: &str, needed: usize) {
+        let previews = self.files.find_all(vec![], identity);
+        let Some(preview) = previews
+            .first()
+            .and_then(|(ptr, idx)| self.file_at(ptr, *idx).ok())
+        else {
+            return;
+        };
+        let (have, encoding) = (preview.doc.len_lines(), preview.doc.info.encoding);
+        let Some(load) = self.loads.files.get_mut(identity) else {
+            return;
+        };
+        let lines = needed.saturating_sub(have);
+        let Some(bytes) = read_lines(identity, load.shown, lines).filter(|b| !b.is_empty()) else {
+            return;
+        };
+        load.shown += bytes.len() as u64;
+        let (text, _) = decode(&bytes, encoding);
+        for (ptr, idx) in previews {
+            if let Ok(fc) = self.file_at_mut(&ptr, idx) {
+                let end = fc.doc.file.len_chars();
+                fc.doc.file.insert(end, &text);
+                fc.doc.changes.reset(&fc.doc.file);
+            }
+        }
+        self.needs_rerender = true;
+    }
+
+    /// Put a file that has been read in place of its preview, wherever it is open
+    fn install(&mut self, identity: &str, read: Outcome) {
+        let file = get_file_name(identity).unwrap_or_default();
+        let whole = match read {
+            Ok(doc) => doc,
+            Err(err) => {
+                let err = OxError::from(err);
+                self.feedback = Feedback::Error(format!("Couldn't finish reading '{file}': {err}"));
+                return;
+            }
+        };
+        let tab_width = config!(self.config, document).tab_width;
+        for (ptr, idx) in self.files.find_all(vec![], identity) {
+            let Ok(fc) = self.file_at(&ptr, idx) else {
+                continue;
+            };
+            let preview = &fc.doc;
+            let mut doc = whole.clone();
+            // Carry on from wherever the user had got to in the preview
+            doc.size = preview.size;
+            doc.cursor = preview.cursor;
+            doc.offset = preview.offset;
+            doc.char_ptr = preview.char_ptr;
+            doc.old_cursor = preview.old_cursor;
+            self.restore_document_state(&mut doc);
+            doc.set_tab_width(tab_width);
+            doc.load_to(doc.offset.y + doc.size.h);
+            let mut highlighter = fc
+                .file_type
+                .as_ref()
+                .map_or(Highlighter::new(tab_width), |t| {
+                    t.get_highlighter(&self.config, tab_width)
+                });
+            let loaded: Vec<String> = doc.lines.iter().take(doc.info.loaded_to).cloned().collect();
+            highlighter.run(&loaded);
+            if let Ok(fc) = self.file_at_mut(&ptr, idx) {
+                fc.doc = doc;
+                fc.highlighter = highlighter;
+            }
+        }
+        if whole.info.decoding == Decoding::Lossy {
+            let encoding = whole.info.encoding.name();
+            self.feedback = Feedback::Warning(format!(
+                "'{file}' contains bytes that aren't valid {encoding}, opened as read only"
+            ));
+        }
+    }
+}
@@ -1,5 +1,10 @@
 /// Tools for recording and playing back macros for bulk editing
+use crate::config::{key_to_string, string_to_key};
+use crate::error::{OxError, Result};
 use crossterm::event::{Event as CEvent, KeyCode, KeyEvent, KeyModifiers};
+use mlua::{Lua, Table};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
 
 /// Macro manager struct
 #[derive(Debug, Default, Clone, PartialEq, Eq)]
@@ -10,6 +15,8 @@ pub struct MacroMan {
     pub ptr: usize,
     pub just_completed: bool,
     pub reps: usize,
+    /// Named macros, stored as lists of actions
+    pub saved: BTreeMap<String, Vec<String>>,
 }
 
 impl MacroMan {
@@ -45,7 +52,7 @@ impl MacroMan {
     }
 
     /// Get next event from macro man
-    pub fn next(&mut self) -> Option<CEvent> {
+    pub fn next_event(&mut self) -> Option<CEvent> {
         if self.playing {
             let result = self.sequence.get(self.ptr).cloned();
             self.ptr += 1;
@@ -73,4 +80,234 @@ impl MacroMan {
             self.sequence.pop();
         }
     }
+
+    /// Store the last recorded macro under a name and write all named macros to disk
+    pub fn save(&mut self, name: &str) -> Result<()> {
+        if self.sequence.is_empty() {
+            return Err(OxError::Macro {
+                msg: "There is no recorded macro to save".to_string(),
+            });
+        }
+        self.saved
+            .insert(name.to_string(), to_actions(&self.sequence));
+        let path = macro_path().ok_or(OxError::InvalidPath)?;
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, serialize(&self.saved))?;
+        Ok(())
+    }
+
+    /// Play back a named macro
+    pub fn play_saved(&mut self, name: &str) -> Result<()> {
+        let actions = self.saved.get(name).ok_or_else(|| OxError::Macro {
+            msg: format!("There is no macro called '{name}'"),
+        })?;
+        self.sequence = from_actions(actions).map_err(|action| OxError::Macro {
+            msg: format!("Macro '{name}' uses an unknown action '{action}'"),
+        })?;
+        self.finish();
+        self.play(1);
+        Ok(())
+    }
+
+    /// Load named macros from the disk, returns a list of any problems found in them.
+    /// Macros with problems are still kept, so they aren't lost when the file is rewritten
+    pub fn load(&mut self, lua: &Lua) -> Result<Vec<String>> {
+        let Some(path) = macro_path().filter(|p| p.exists()) else {
+            return Ok(vec![]);
+        };
+        let code = std::fs::read_to_string(path)?;
+        let table: Table = lua.load(code).eval()?;
+        let mappings: Option<Table> = lua.globals().get("global_event_mapping").ok();
+        let mut problems = vec![];
+        for pair in table.pairs::<String, Vec<String>>() {
+            let (name, actions) = match pair {
+                Ok(pair) => pair,
+                Err(err) => {
+                    problems.push(format!("Invalid macro entry: {err}"));
+                    continue;
+                }
+            };
+            for action in &actions {
+                if !action_exists(action, mappings.as_ref()) {
+                    problems.push(format!("Macro '{name}' uses an unknown action '{action}'"));
+                }
+            }
+            self.saved.insert(name, actions);
+        }
+        Ok(problems)
+    }
+
+    /// Find the names of saved macros that start with a certain prefix
+    pub fn complete(&self, prefix: &str) -> Vec<&str> {
+        self.saved
+            .keys()
+            .filter(|name| name.starts_with(prefix))
+            .map(String::as_str)
+            .collect()
+    }
+}
+
+/// Work out where named macros are stored
+pub fn macro_path() -> Option<PathBuf> {
+    let data = std::env::var("XDG_DATA_HOME")
+        .ok()
+        .filter(|d| !d.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| {
+            let home = std::env::var("HOME").or_else(|_| std::env::var("USERPROFILE"));
+            home.ok()
+                .map(|h| PathBuf::from(h).join(".local").join("share"))
+        })?;
+    Some(data.join("ox").join("macros.lua"))
+}
+
+/// Convert recorded events into actions (typed text, pastes and key bindings).
+/// Mouse events depend on the layout of the screen, so they aren't kept
+pub fn to_actions(events: &[CEvent]) -> Vec<String> {
+    let mut result: Vec<String> = vec![];
+    for event in events {
+        match event {
+            CEvent::Key(KeyEvent {
+                modifiers: KeyModifiers::NONE | KeyModifiers::SHIFT,
+                code: KeyCode::Char(ch),
+                ..
+            }) => {
+                // Group typed characters together to keep things readable
+                if let Some(text) = result.last_mut().filter(|a| a.starts_with("type:")) {
+                    text.push(*ch);
+                } else {
+                    result.push(format!("type:{ch}"));
+                }
+            }
+            CEvent::Key(KeyEvent {
+                modifiers, code, ..
+            }) => result.push(key_to_string(*modifiers, *code)),
+            CEvent::Paste(text) => result.push(format!("paste:{text}")),
+            _ => (),
+        }
+    }
+    result
+}
+
+/// Convert actions back into events, returns the first action that isn't recognised on failure
+pub fn from_actions(actions: &[String]) -> std::result::Result<Vec<CEvent>, String> {
+    let mut result = vec![];
+    for action in actions {
+        if let Some(text) = action.strip_prefix("type:") {
+            for ch in text.chars() {
+                let modifiers = if ch.is_uppercase() {
+                    KeyModifiers::SHIFT
+                } else {
+                    KeyModifiers::NONE
+                };
+                result.push(CEvent::Key(KeyEvent::new(KeyCode::Char(ch), modifiers)));
+            }
+        } else if let Some(text) = action.strip_prefix("paste:") {
+            result.push(CEvent::Paste(text.to_string()));
+        } else if let Some((modifiers, code)) = string_to_key(action) {
+            result.push(CEvent::Key(KeyEvent::new(code, modifiers)));
+        } else {
+            return Err(action.to_string());
+        }
+    }
+    Ok(result)
+}
+
+/// Check that an action can still be carried out (key bindings with modifiers must be bound)
+fn action_exists(action: &str, mappings: Option<&Table>) -> bool {
+    if action.starts_with("type:") || action.starts_with("paste:") {
+        return true;
+    }
+    match string_to_key(action) {
+        Some((modifiers, _)) if modifiers.intersects(KeyModifiers::CONTROL | KeyModifiers::ALT) => {
+            mappings.is_some_and(|m| m.contains_key(action).unwrap_or(false))
+        }
+        Some(_) => true,
+        None => false,
+    }
+}
+
+/// Write out named macros as a human editable lua file
+pub fn serialize(saved: &BTreeMap<String, Vec<String>>) -> String {
+    let mut result = "-- Macros saved by ox, each one is a list of actions:\n".to_string();
+    result += "-- 'type:<text>' types text, 'paste:<text>' pastes text, anything else is a key\n";
+    result += "return {\n";
+    for (name, actions) in saved {
+        let actions: Vec<String> = actions.iter().map(|a| lua_string(a)).collect();
+        result += &format!(
+            "    [{}] = {{ {} }},\n",
+            lua_string(name),
+            actions.join(", ")
+        );
+    }
+    result += "}\n";
+    result
+}
+
+/// Quote a string so that it can be read back in by lua
+fn lua_string(st: &str) -> String {
+    let mut result = "\"".to_string();
+    for ch in st.chars() {
+        match ch {
+            '"' => result += "\\\"",
+            '\\' => result += "\\\\",
+            '\n' => result += "\\n",
+            '\r' => result += "\\r",
+            '\t' => result += "\\t",
+            ch if ch.is_control() => result += &format!("\\u{{{:X}}}", u32::from(ch)),
+            ch => result.push(ch),
+        }
+    }
+    result.push('"');
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(code: KeyCode, modifiers: KeyModifiers) -> CEvent {
+        CEvent::Key(KeyEvent::new(code, modifiers))
+    }
+
+    #[test]
+    fn round_trip() {
+        let (none, shift) = (KeyModifiers::NONE, KeyModifiers::SHIFT);
+        let (ctrl, alt) = (KeyModifiers::CONTROL, KeyModifiers::ALT);
+        let events = vec![
+            key(KeyCode::Char('a'), none),
+            key(KeyCode::Char('B'), shift),
+            key(KeyCode::Char('"'), none),
+            key(KeyCode::Char('\\'), none),
+            key(KeyCode::Enter, none),
+            key(KeyCode::Char('s'), ctrl),
+            key(KeyCode::Left, alt | shift),
+            key(KeyCode::Char('\\'), ctrl),
+            key(KeyCode::Char('"'), alt),
+            key(KeyCode::Char('_'), ctrl),
+            key(KeyCode::F(12), ctrl | alt),
+            key(KeyCode::BackTab, shift),
+            CEvent::Paste("say \"hi\"\n\tC:\\path\r\u{7}é🦀 ]] --".to_string()),
+            key(KeyCode::Esc, none),
+        ];
+        let actions = to_actions(&events);
+        assert_eq!(actions[0], "type:aB\"\\");
+        assert_eq!(actions[3], "alt_shift_left");
+        assert_eq!(from_actions(&actions), Ok(events));
+        // Written out and read back in, the macros are just as they were
+        let mut saved = BTreeMap::new();
+        saved.insert("edit".to_string(), actions);
+        let name = "say \"hi\" \\ and\nleave";
+        saved.insert(name.to_string(), vec!["esc".to_string()]);
+        saved.insert("nothing".to_string(), vec![]);
+        let lua = Lua::new();
+        let table: Table = lua.load(serialize(&saved)).eval().unwrap();
+        let loaded: BTreeMap<String, Vec<String>> = table
+            .pairs::<String, Vec<String>>()
+            .collect::<mlua::Result<_>>()
+            .unwrap();
+        assert_eq!(loaded, saved);
+    }
 }
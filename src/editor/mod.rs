@@ -1,37 +1,95 @@
 /// Main functionality of the editor
 use crate::config;
-use crate::config::{Config, Indentation};
+use crate::config::Config;
 use crate::error::{OxError, Result};
-use crate::ui::{size, Feedback, Terminal};
+use crate::ui::{Feedback, Terminal};
 use crossterm::event::{
     Event as CEvent, KeyCode as KCode, KeyModifiers as KMod, MouseEvent, MouseEventKind,
 };
+use kaolinite::document::{unrepresentable, Decoding};
 use kaolinite::event::Error as KError;
-use kaolinite::utils::{file_or_dir, get_absolute_path, get_file_name};
+use kaolinite::searching::describe_char;
+use kaolinite::utils::{file_or_dir, get_absolute_path, get_file_name, get_path_identity};
 use kaolinite::{Document, Loc};
 use mlua::{Error as LuaError, Lua};
+use std::collections::VecDeque;
 use std::env;
 use std::io::ErrorKind;
 use std::path::Path;
-use std::time::Instant;
+use std::time::{Instant, SystemTime};
 use synoptic::Highlighter;
 
+mod abbreviations;
+mod actions;
+mod annotations;
+mod backups;
+mod bookmarks;
+mod budget;
+mod buffers;
+mod build;
+mod choice;
+mod clipboard;
+mod completion;
+mod count;
 mod cursor;
+mod diffview;
 mod documents;
+mod dropped;
 mod editing;
 mod filetree;
 mod filetypes;
+mod finder;
+mod focus;
+mod gitstatus;
+mod greeting;
+mod grep;
+mod headless;
+mod help;
+mod history;
 mod interface;
+mod loading;
 mod macros;
 mod mouse;
+mod notes;
+mod notify;
+mod ondisk;
+mod overlay;
+mod picker;
+mod positions;
+mod preview;
+mod progress;
+mod recent;
+mod reload;
+mod remote;
+mod resize;
 mod scanning;
+mod signs;
+mod spelling;
+mod status;
+mod suspend;
+mod swap;
+mod swatches;
+mod tabline;
+mod views;
+mod zen;
 
-pub use cursor::{allowed_by_multi_cursor, handle_multiple_cursors};
+pub use actions::{Action, Direction};
+pub use annotations::data_dir;
+pub use build::{parse_location, Location, BUILD_NAME};
+pub use cursor::{allowed_by_multi_cursor, handle_multiple_cursors, EditPoint};
 pub use documents::{FileContainer, FileLayout};
+pub use dropped::pasted_files;
 pub use filetree::{FTParts, FileTree};
 pub use filetypes::{FileType, FileTypes};
+pub use finder::FileIndex;
+pub use help::HELP_NAME;
 pub use interface::RenderCache;
 pub use macros::MacroMan;
+pub use ondisk::{DiskState, OnDisk};
+pub use swap::write_pending_swaps;
+
+/// How many characters to list when the file's encoding can't represent some of them
+const MAX_OFFENDERS: usize = 3;
 
 /// For managing all editing and rendering of cactus
 #[allow(clippy::struct_excessive_bools)]
@@ -60,24 +118,91 @@ pub struct Editor {
     pub push_down: usize,
     /// Used to cache the location of the configuration file
     pub config_path: String,
+    /// When the configuration file was last changed, as of when it was last read
+    pub config_modified: Option<SystemTime>,
+    /// When the configuration file was last checked for changes
+    pub config_checked: Instant,
     /// Flag to determine whether or not the editor is under control by a plug-in
     pub plugin_active: bool,
     /// Flag to determine whether or not the editor is pasting
     pub pasting: bool,
+    /// Whether typed characters replace the ones under the cursor (toggled with insert)
+    pub overtype: bool,
+    /// Whether the editor is running Lua over files without a terminal to draw on
+    pub headless: bool,
+    /// How many errors have been reported while running headlessly
+    pub headless_errors: usize,
+    /// The code ox exits with (which tells git to abort when a commit message is thrown away)
+    pub exit_code: i32,
     /// Stores the last click the user made (in order to detect double-click)
     pub last_click: Option<(Instant, MouseEvent)>,
     /// Stores whether or not we're in a double click
     pub alt_click_state: Option<(Loc, Loc)>,
+    /// The start and end of the line a drag in the line number gutter started on
+    pub gutter_drag: Option<(Loc, Loc)>,
+    /// The split whose divider is being dragged, along with which child the divider comes after
+    pub split_drag: Option<(Vec<usize>, usize)>,
     /// Macro manager
     pub macro_man: MacroMan,
+    /// Events that were read ahead of time (while checking for a burst of typing
+    /// or for keys that stop a long operation)
+    pub pending_events: VecDeque<CEvent>,
+    /// The long operations under way
+    pub progress: progress::Progress,
+    /// The count typed in for the next key to be repeated by
+    pub count: count::Count,
+    /// The snippets most recently copied or cut
+    pub clipboard: clipboard::ClipboardHistory,
+    /// The locations found in the output of the last build
+    pub build: build::BuildResults,
+    /// Listens for requests from other shells (when remote control is on)
+    pub remote: Option<crate::remote::RemoteControl>,
     /// Render cache
     pub render_cache: RenderCache,
     /// For storing the current file tree value
     pub file_tree: Option<FileTree>,
     /// The selected file in the file tree
     pub file_tree_selection: Option<String>,
+    /// The git status of the files in the file tree
+    pub file_tree_git: gitstatus::GitStatus,
+    /// Information for the status line that is kept between frames (git branch, word counts)
+    pub status_info: status::StatusInfo,
+    /// The filter narrowing down the file tree, while one is being typed in
+    pub file_tree_filter: Option<filetree::TreeFilter>,
     /// For caching a pointer to go back to when in a file tree
     pub old_ptr: Vec<usize>,
+    /// The list of files in the project, for the fuzzy file finder
+    pub file_index: FileIndex,
+    /// Files that were opened recently (most recent first)
+    pub recent: Vec<recent::RecentFile>,
+    /// Documents closed this session, so they can be reopened (most recent last)
+    pub closed: Vec<recent::RecentFile>,
+    /// When copies of documents with unsaved changes were last stored (in seconds since the epoch)
+    pub last_backup: u64,
+    /// Which documents need their swap files writing
+    pub swaps: swap::Swaps,
+    /// When the files behind documents were last checked for changes by other programs
+    pub disk_checked: Option<Instant>,
+    /// The spelling dictionary and the misspellings found in each document
+    pub spelling: spelling::Spelling,
+    /// The signs reported by external tools, kept up to date as they change
+    pub signs: signs::SignWatcher,
+    /// Which panes have changed since they were last drawn
+    pub render_budget: budget::RenderBudget,
+    /// When the visual bell stops inverting the status line
+    pub flash_until: Option<Instant>,
+    /// What distraction-free mode switched off, while it is on
+    pub zen: Option<zen::Zen>,
+    /// The completions being offered for the word before the cursor
+    pub completion: Option<completion::Completion>,
+    /// The large files being read in the background
+    pub loads: loading::Loads,
+    /// Whether the terminal has focus (for terminals that report it)
+    pub focus: focus::Focus,
+    /// The full report of the last error in the configuration file (if there was one)
+    pub config_error: Option<String>,
+    /// Whether the default configuration is in use because the configuration file failed
+    pub safe_mode: bool,
 }
 
 impl Editor {
@@ -97,15 +222,48 @@ impl Editor {
             last_active: Instant::now(),
             push_down: 1,
             config_path: "~/.oxrc".to_string(),
+            config_modified: None,
+            config_checked: Instant::now(),
             plugin_active: false,
             pasting: false,
+            overtype: false,
+            headless: false,
+            headless_errors: 0,
+            exit_code: 0,
             last_click: None,
             alt_click_state: None,
+            gutter_drag: None,
+            split_drag: None,
             macro_man: MacroMan::default(),
+            pending_events: VecDeque::new(),
+            progress: progress::Progress::default(),
+            count: count::Count::default(),
+            clipboard: clipboard::ClipboardHistory::default(),
+            build: build::BuildResults::default(),
+            remote: None,
             render_cache: RenderCache::default(),
             file_tree: None,
             file_tree_selection: None,
+            file_tree_git: gitstatus::GitStatus::default(),
+            status_info: status::StatusInfo::default(),
+            file_tree_filter: None,
             old_ptr: vec![],
+            file_index: FileIndex::default(),
+            recent: recent::load_recent(),
+            closed: vec![],
+            last_backup: kaolinite::backups::now(),
+            swaps: swap::Swaps::default(),
+            disk_checked: None,
+            spelling: spelling::Spelling::default(),
+            signs: signs::SignWatcher::default(),
+            render_budget: budget::RenderBudget::default(),
+            flash_until: None,
+            zen: None,
+            completion: None,
+            loads: loading::Loads::default(),
+            focus: focus::Focus::default(),
+            config_error: None,
+            safe_mode: false,
         })
     }
 
@@ -117,10 +275,10 @@ impl Editor {
 
     /// Function to create a new document (without moving to it)
     pub fn blank(&mut self) -> Result<()> {
-        let mut size = size()?;
-        size.h = size.h.saturating_sub(1 + self.push_down);
+        let size = self.document_size()?;
         let mut doc = Document::new(size);
         doc.set_tab_width(config!(self.config, document).tab_width);
+        doc.event_mgmt.limit = config!(self.config, document).undo_limit;
         doc.event_mgmt.force_not_with_disk = true;
         // Load all the lines within viewport into the document
         doc.load_to(size.h);
@@ -132,6 +290,7 @@ impl Editor {
             highlighter,
             file_type: Some(FileType::default()),
             doc,
+            ..Default::default()
         };
         if let Some((files, ptr)) = self.files.get_atom_mut(self.ptr.clone()) {
             if *ptr + 1 >= files.len() {
@@ -150,6 +309,29 @@ impl Editor {
         Ok(())
     }
 
+    /// Open a scratch buffer (or move to it if it is already open), these are never saved
+    pub fn scratch(&mut self, name: &str) -> Result<()> {
+        let name = name.trim();
+        let existing = self
+            .files
+            .get_atom(self.ptr.clone())
+            .and_then(|(files, _)| {
+                files
+                    .iter()
+                    .position(|f| f.scratch.as_deref() == Some(name))
+            });
+        if let Some(idx) = existing {
+            self.switch_to(idx);
+            return Ok(());
+        }
+        self.blank()?;
+        self.next();
+        if let Some((files, ptr)) = self.files.get_atom_mut(self.ptr.clone()) {
+            files[*ptr].scratch = Some(name.to_string());
+        }
+        Ok(())
+    }
+
     /// Create a blank document if none are already opened
     pub fn new_if_empty(&mut self) -> Result<()> {
         let cache = self.ptr.clone();
@@ -165,6 +347,53 @@ impl Editor {
         Ok(())
     }
 
+    /// Function to create a document from a string (without moving to it)
+    /// The document has no file name, so saving it will prompt for a location
+    pub fn open_from_string(&mut self, contents: String, name: Option<String>) -> Result<()> {
+        let size = self.document_size()?;
+        let tab_width = config!(self.config, document).tab_width;
+        let mut doc = Document::new(size);
+        doc.set_tab_width(tab_width);
+        doc.event_mgmt.limit = config!(self.config, document).undo_limit;
+        // Swap in the contents, leaving lines to be loaded in as and when they are needed
+        doc.file = contents.into();
+        doc.lines.clear();
+        doc.info.loaded_to = 0;
+        doc.info.eol = !doc
+            .file
+            .line(doc.file.len_lines().saturating_sub(1))
+            .to_string()
+            .is_empty();
+        doc.load_to(size.h);
+        // Work out the file type (the name provided takes precedence over any modelines)
+        let file_type = if let Some(name) = name {
+            config!(self.config, document).file_types.get_name(&name)
+        } else {
+            config!(self.config, document).file_types.identify(&mut doc)
+        };
+        // Update in the syntax highlighter (only for the lines currently loaded)
+        let mut highlighter = file_type.as_ref().map_or(Highlighter::new(tab_width), |t| {
+            t.get_highlighter(&self.config, tab_width)
+        });
+        let loaded: Vec<String> = doc.lines.iter().take(doc.info.loaded_to).cloned().collect();
+        highlighter.run(&loaded);
+        // Add document to documents
+        let file = FileContainer {
+            doc,
+            highlighter,
+            file_type: Some(file_type.unwrap_or_default()),
+            ..Default::default()
+        };
+        if let Some((files, ptr)) = self.files.get_atom_mut(self.ptr.clone()) {
+            if *ptr + 1 >= files.len() {
+                files.push(file);
+            } else {
+                files.insert(*ptr + 1, file);
+            }
+        }
+        Ok(())
+    }
+
     /// Function to open a document into the editor
     pub fn open(&mut self, file_name: &str) -> Result<()> {
         let file = self.open_fc(file_name)?;
@@ -197,34 +426,128 @@ impl Editor {
         {
             // Move to existing file
             self.ptr.clone_from(&idx);
-            self.files.move_to(idx, ptr);
+            self.switch_to(ptr);
             // Send out error message
             let file = get_file_name(file_name).unwrap_or_default();
             return Err(OxError::AlreadyOpen { file });
         }
-        let mut size = size()?;
-        size.h = size.h.saturating_sub(1 + self.push_down);
-        let mut doc = Document::open(size, file_name)?;
-        // Collect various data from the document
+        let size = self.document_size()?;
+        // Large files show their start straight away while the rest is read in the background
+        let mut doc = match self.start_loading(size, file_name) {
+            Some(doc) => doc,
+            None => {
+                let mut doc = Document::open(size, file_name)?;
+                self.restore_document_state(&mut doc);
+                doc
+            }
+        };
+        self.note_opened(file_name);
+        // Warn if the file couldn't be decoded properly
+        if doc.info.decoding == Decoding::Lossy {
+            let file = get_file_name(file_name).unwrap_or_default();
+            let encoding = doc.info.encoding.name();
+            self.feedback = Feedback::Warning(format!(
+                "'{file}' contains bytes that aren't valid {encoding}, opened as read only"
+            ));
+        }
+        // Binary files are shown as a hex preview, which doesn't need highlighting
         let tab_width = config!(self.config, document).tab_width;
+        if doc.binary.is_some() {
+            let file = get_file_name(file_name).unwrap_or_default();
+            self.feedback = Feedback::Warning(format!(
+                "'{file}' looks like a binary file, showing a read only hex preview (run 'force text' to open it as text)"
+            ));
+            doc.set_tab_width(tab_width);
+            doc.load_to(size.h);
+            return Ok(FileContainer {
+                doc,
+                highlighter: Highlighter::new(tab_width),
+                on_disk: OnDisk::of(Some(file_name)),
+                ..Default::default()
+            });
+        }
+        // Collect various data from the document
         let file_type = config!(self.config, document).file_types.identify(&mut doc);
         // Set up the document
         doc.set_tab_width(tab_width);
         doc.load_to(size.h);
-        // Update in the syntax highlighter
+        self.restore_position_of(&mut doc);
+        // Update in the syntax highlighter (only for the lines currently loaded)
         let mut highlighter = file_type.as_ref().map_or(Highlighter::new(tab_width), |t| {
             t.get_highlighter(&self.config, tab_width)
         });
-        highlighter.run(&doc.lines);
+        let loaded: Vec<String> = doc.lines.iter().take(doc.info.loaded_to).cloned().collect();
+        highlighter.run(&loaded);
         // Add in the file
         let file = FileContainer {
             doc,
             highlighter,
             file_type,
+            signs: self.signs.index.for_file(file_name),
+            on_disk: OnDisk::of(Some(file_name)),
+            ..Default::default()
         };
         Ok(file)
     }
 
+    /// Bring back what was kept of a document from when it was last open
+    /// (its undo history, annotations and bookmarks)
+    pub fn restore_document_state(&self, doc: &mut Document) {
+        doc.event_mgmt.limit = config!(self.config, document).undo_limit;
+        if config!(self.config, document).persistent_undo {
+            history::restore_history(doc);
+        }
+        annotations::restore_annotations(doc);
+        if config!(self.config, document).persistent_bookmarks {
+            bookmarks::restore_bookmarks(doc);
+        }
+    }
+
+    /// Open the hex preview of a binary file as text instead
+    pub fn force_text(&mut self) {
+        let tab_width = config!(self.config, document).tab_width;
+        let Some(file) = self.files.get_mut(self.ptr.clone()) else {
+            return;
+        };
+        if file.doc.binary.is_none() {
+            self.feedback = Feedback::Info("This document is already open as text".to_string());
+            return;
+        }
+        file.doc.force_text();
+        let file_type = config!(self.config, document)
+            .file_types
+            .identify(&mut file.doc);
+        file.highlighter = file_type.as_ref().map_or(Highlighter::new(tab_width), |t| {
+            t.get_highlighter(&self.config, tab_width)
+        });
+        file.highlighter.run(&file.doc.lines);
+        file.file_type = file_type;
+        self.feedback = if file.doc.info.read_only {
+            Feedback::Warning("Opened as read only text due to invalid bytes".to_string())
+        } else {
+            Feedback::Info("Opened as text".to_string())
+        };
+    }
+
+    /// Warn the user if they try to change the hex preview of a binary file
+    pub fn warn_if_binary(&mut self) -> bool {
+        match self.files.get_atom(self.ptr.clone()) {
+            Some((_, doc)) => self.warn_if_binary_at(&self.ptr.clone(), doc),
+            None => false,
+        }
+    }
+
+    /// Check if a document is the hex preview of a binary file (warning the user if they can see it)
+    pub fn warn_if_binary_at(&mut self, ptr: &[usize], doc: usize) -> bool {
+        let binary = self
+            .file_at(ptr, doc)
+            .is_ok_and(|file| file.doc.binary.is_some());
+        if binary && self.is_visible(ptr, doc) {
+            self.feedback = Feedback::Warning(OxError::from(KError::BinaryPreview).to_string());
+        }
+        binary
+    }
+
     /// Function to ask the user for a file to open
     pub fn open_document(&mut self) -> Result<()> {
         let path = self.path_prompt()?;
@@ -241,8 +564,10 @@ impl Editor {
             if os.kind() == ErrorKind::NotFound || os.kind() == ErrorKind::IsADirectory {
                 // Create a new document if not found
                 self.blank()?;
-                if let Some((files, _)) = self.files.get_atom_mut(self.ptr.clone()) {
-                    let file = files.last_mut().unwrap();
+                if let Some((files, ptr)) = self.files.get_atom_mut(self.ptr.clone()) {
+                    // The new document goes just after the current one
+                    let idx = (*ptr + 1).min(files.len() - 1);
+                    let file = &mut files[idx];
                     file.doc.file_name = Some(file_name);
                     // Work out information for the document
                     let tab_width = config!(self.config, document).tab_width;
@@ -273,24 +598,220 @@ impl Editor {
         self.files.find(vec![], abs_path)
     }
 
+    /// Find another open document with unsaved changes that a save to `target` would overwrite
+    pub fn save_conflict(
+        &self,
+        at: &(Vec<usize>, usize),
+        target: &str,
+    ) -> Option<(Vec<usize>, usize)> {
+        let identity = get_path_identity(target);
+        self.files
+            .find_all(vec![], &identity)
+            .into_iter()
+            .find(|other| {
+                other != at
+                    && !self.views_of(at).contains(other)
+                    && self
+                        .files
+                        .get_atom(other.0.clone())
+                        .is_some_and(|(fcs, _)| {
+                            let doc = &fcs[other.1].doc;
+                            !doc.event_mgmt.with_disk(&doc.take_snapshot())
+                        })
+            })
+    }
+
+    /// Describe a document by its file name and tab number (for use in messages)
+    fn describe_doc(&self, at: &(Vec<usize>, usize)) -> String {
+        let name = self
+            .files
+            .get_atom(at.0.clone())
+            .and_then(|(fcs, _)| fcs.get(at.1))
+            .map_or_else(
+                || "[No Name]".to_string(),
+                |fc| {
+                    let name = fc.doc.file_name.as_deref().and_then(get_file_name);
+                    name.unwrap_or_else(|| fc.placeholder_name())
+                },
+            );
+        format!("'{name}' (tab {})", at.1 + 1)
+    }
+
+    /// Ask the user how to resolve two documents that would be saved to the same file.
+    /// Returns true if the document at `at` should go ahead and be saved.
+    pub fn resolve_save_conflict(
+        &mut self,
+        at: &(Vec<usize>, usize),
+        other: &(Vec<usize>, usize),
+    ) -> Result<bool> {
+        let msg = format!(
+            "Saving {} would overwrite unsaved changes in {}",
+            self.describe_doc(at),
+            self.describe_doc(other),
+        );
+        let options = ["save this", "save other", "merge manually", "cancel"];
+        match self.choice(&msg, &options)? {
+            Some(0) => Ok(true),
+            Some(1) => {
+                if let Some((fcs, _)) = self.files.get_atom_mut(other.0.clone()) {
+                    let doc = &mut fcs[other.1].doc;
+                    doc.save()?;
+                    doc.commit();
+                }
+                self.note_on_disk(&other.0, other.1);
+                let other = self.describe_doc(other);
+                self.feedback = Feedback::Info(format!("Saved {other} instead"));
+                Ok(false)
+            }
+            Some(2) => {
+                // Compare this document with the other one, side by side
+                let theirs = self.file_at(&other.0, other.1)?.doc.file.to_string();
+                let other = self.describe_doc(other);
+                self.ptr.clone_from(&at.0);
+                self.switch_to(at.1);
+                if let Some(file_name) = self.try_doc().and_then(|doc| doc.file_name.clone()) {
+                    self.show_diff(&file_name, &theirs, other.clone(), false);
+                }
+                self.feedback = Feedback::Info(format!(
+                    "Comparing with {other}, bring its changes over by hand before saving"
+                ));
+                Ok(false)
+            }
+            _ => Err(OxError::Cancelled),
+        }
+    }
+
     /// save the document to the disk
     pub fn save(&mut self) -> Result<()> {
+        self.wait_for_document();
+        if self.warn_if_binary() {
+            return Ok(());
+        }
+        // Make sure another open document won't have its changes overwritten
+        if let Some((_, ptr)) = self.files.get_atom(self.ptr.clone()) {
+            let at = (self.ptr.clone(), ptr);
+            let file_name = self.try_doc().and_then(|doc| doc.file_name.clone());
+            if let Some(other) = file_name.and_then(|f| self.save_conflict(&at, &f)) {
+                if !self.resolve_save_conflict(&at, &other)? {
+                    return Ok(());
+                }
+            }
+        }
+        self.check_encoding()?;
         if let Some(doc) = self.try_doc_mut() {
             // Perform the save
             doc.save()?;
             // All done
             self.feedback = Feedback::Info("Document saved successfully".to_string());
         }
+        if let Some((_, doc)) = self.files.get_atom(self.ptr.clone()) {
+            self.saved_at(&self.ptr.clone(), doc);
+        }
+        self.file_tree_git_refresh();
+        Ok(())
+    }
+
+    /// Keep what goes along with a document once it has been saved,
+    /// and bring the other panes showing it up to date
+    fn saved_at(&mut self, ptr: &[usize], doc: usize) {
+        self.store_history_at(ptr, doc);
+        self.store_annotations_at(ptr, doc);
+        self.store_bookmarks_at(ptr, doc);
+        self.backup_at(ptr, doc);
+        self.remove_swap_at(ptr, doc);
+        self.share_changes(&(ptr.to_vec(), doc), true);
+        self.note_on_disk(ptr, doc);
+    }
+
+    /// Make sure the document can be represented in its encoding before it is saved,
+    /// letting the user decide what to do with any characters that can't be
+    pub fn check_encoding(&mut self) -> Result<()> {
+        match self.files.get_atom(self.ptr.clone()) {
+            Some((_, idx)) => self.check_encoding_at(&(self.ptr.clone(), idx)),
+            None => Ok(()),
+        }
+    }
+
+    /// Make sure the document at `at` can be represented in its encoding before it is saved
+    pub fn check_encoding_at(&mut self, at: &(Vec<usize>, usize)) -> Result<()> {
+        let Ok(doc) = self.file_at(&at.0, at.1).map(|fc| &fc.doc) else {
+            return Ok(());
+        };
+        let offenders = unrepresentable(&doc.file, doc.info.encoding, MAX_OFFENDERS + 1);
+        if offenders.is_empty() {
+            return Ok(());
+        }
+        // List the first few characters that are a problem
+        let mut listing: Vec<String> = offenders
+            .iter()
+            .take(MAX_OFFENDERS)
+            .map(|(loc, c)| format!("'{c}' ({}) on line {}", describe_char(*c), loc.y + 1))
+            .collect();
+        if offenders.len() > MAX_OFFENDERS {
+            listing.push("...".to_string());
+        }
+        let msg = format!(
+            "{} can't represent {}",
+            doc.info.encoding.name(),
+            listing.join(", ")
+        );
+        let options = ["save as UTF-8", "replace them", "go to first", "cancel"];
+        match self.choice(&msg, &options)? {
+            Some(0) => self.file_at_mut(&at.0, at.1)?.doc.convert_to_utf8(),
+            Some(1) => {
+                let doc = &mut self.file_at_mut(&at.0, at.1)?.doc;
+                doc.batch(|doc| doc.replace_unrepresentable('?'));
+                self.reload_highlight_at(&at.0, at.1)?;
+            }
+            Some(2) => {
+                self.ptr.clone_from(&at.0);
+                self.switch_to(at.1);
+                self.file_at_mut(&at.0, at.1)?.doc.move_to(&offenders[0].0);
+                return Err(OxError::Cancelled);
+            }
+            _ => return Err(OxError::Cancelled),
+        }
         Ok(())
     }
 
     /// save the document to the disk at a specified path
     pub fn save_as(&mut self) -> Result<()> {
+        if self.warn_if_binary() {
+            return Ok(());
+        }
         if self.try_doc().is_some() {
             let file_name = self.prompt("Save as")?;
+            // Warn if this path belongs to another open document
+            if let Some((_, ptr)) = self.files.get_atom(self.ptr.clone()) {
+                let at = (self.ptr.clone(), ptr);
+                let identity = get_path_identity(&file_name);
+                let owners = self.files.find_all(vec![], &identity);
+                if let Some(other) = owners.iter().find(|other| **other != at) {
+                    let msg = format!("{} is already open", self.describe_doc(other));
+                    if self.choice(&msg, &["save anyway", "cancel"])? != Some(0) {
+                        return Err(OxError::Cancelled);
+                    }
+                }
+            }
+            if !self.check_save_as_path(&file_name)? {
+                return Ok(());
+            }
+            self.check_encoding()?;
             self.try_doc_mut().unwrap().save_as(&file_name)?;
-            // If this file is currently unnamed, give it a name, syntax highlighting and a type
-            if self.try_doc().unwrap().file_name.is_none() {
+            // If this file is unnamed or has been saved somewhere else, give it the new name,
+            // along with the syntax highlighting and type that go with it
+            let identity = get_path_identity(&file_name);
+            let current = self
+                .try_doc()
+                .unwrap()
+                .file_name
+                .as_deref()
+                .map(get_path_identity);
+            if current.as_ref() != Some(&identity) {
+                // Unsaved changes to the old file are now saved in the new one
+                if let Some((_, doc)) = self.files.get_atom(self.ptr.clone()) {
+                    self.remove_swap_at(&self.ptr.clone(), doc);
+                }
                 let tab_width = config!(self.config, document).tab_width;
                 if let Some((files, ptr)) = self.files.get_atom_mut(self.ptr.clone()) {
                     let file = files.get_mut(*ptr).unwrap();
@@ -309,57 +830,120 @@ impl Editor {
                         });
                     file.highlighter = highlighter;
                     file.highlighter.run(&file.doc.lines);
-                    // Set up to date with disk
-                    file.doc.event_mgmt.force_not_with_disk = false;
-                    file.doc.event_mgmt.disk_write(&file.doc.take_snapshot());
+                    // Scratch buffers become normal documents once they have a home
+                    file.scratch = None;
                 }
             }
+            // Set up to date with disk
+            if let Some(doc) = self.try_doc_mut() {
+                doc.event_mgmt.force_not_with_disk = false;
+                doc.event_mgmt.disk_write(&doc.take_snapshot());
+                doc.changes.reset(&doc.file);
+            }
             // Commit events to event manager (for undo / redo)
             self.try_doc_mut().unwrap().commit();
+            if let Some((_, doc)) = self.files.get_atom(self.ptr.clone()) {
+                self.saved_at(&self.ptr.clone(), doc);
+            }
+            self.file_tree_git_refresh();
             // All done
             self.feedback = Feedback::Info(format!("Document saved as {file_name} successfully"));
         }
         Ok(())
     }
 
+    /// Make sure a document can be saved at a path, asking before overwriting a file
+    /// that belongs to something else and offering to create any missing folders.
+    /// Returns false if saving there shouldn't go ahead
+    fn check_save_as_path(&mut self, file_name: &str) -> Result<bool> {
+        let path = Path::new(file_name);
+        let name = get_file_name(file_name).unwrap_or(file_name.to_string());
+        let confirm = (KMod::NONE, KCode::Char('y'));
+        let current = self.try_doc().and_then(|doc| doc.file_name.clone());
+        let is_current =
+            current.is_some_and(|f| get_path_identity(&f) == get_path_identity(file_name));
+        if path.exists() && !is_current {
+            let msg = format!("File exists, overwrite {name}? Press y to confirm or esc to cancel");
+            if !self.confirm_with(&msg, confirm)? {
+                return Ok(false);
+            }
+        }
+        let missing = path
+            .parent()
+            .filter(|dir| !dir.as_os_str().is_empty() && !dir.exists());
+        if let Some(dir) = missing {
+            let dir = dir.display().to_string();
+            let msg =
+                format!("{dir} doesn't exist, create it? Press y to confirm or esc to cancel");
+            if !self.confirm_with(&msg, confirm)? {
+                return Ok(false);
+            }
+            if let Err(err) = std::fs::create_dir_all(&dir) {
+                self.feedback = Feedback::Error(format!("Couldn't create {dir}: {err}"));
+                return Ok(false);
+            }
+        }
+        Ok(true)
+    }
+
     /// Save all the open documents to the disk
     pub fn save_all(&mut self) -> Result<()> {
-        if let Some((files, _)) = self.files.get_atom_mut(self.ptr.clone()) {
-            for file in files {
-                file.doc.save()?;
-                // Commit events to event manager (for undo / redo)
-                file.doc.commit();
+        self.wait_for_all_documents();
+        let ptr = self.ptr.clone();
+        let len = self
+            .files
+            .get_atom(ptr.clone())
+            .map_or(0, |(fcs, _)| fcs.len());
+        // Documents that have been dealt with while resolving a conflict
+        let mut resolved = vec![];
+        for idx in 0..len {
+            let at = (ptr.clone(), idx);
+            if resolved.contains(&at) {
+                continue;
             }
+            // Make sure another open document won't have its changes overwritten
+            let file_name = self.files.get_atom(ptr.clone()).unwrap().0[idx]
+                .doc
+                .file_name
+                .clone();
+            if let Some(other) = file_name.and_then(|f| self.save_conflict(&at, &f)) {
+                resolved.push(other.clone());
+                if !self.resolve_save_conflict(&at, &other)? {
+                    // Merging by hand opens a diff view, which moves this split
+                    if self.ptr != ptr {
+                        self.file_tree_git_refresh();
+                        return Ok(());
+                    }
+                    continue;
+                }
+            }
+            if !self.file_at(&ptr, idx)?.savable() {
+                continue;
+            }
+            self.check_encoding_at(&at)?;
+            let file = &mut self.files.get_atom_mut(ptr.clone()).unwrap().0[idx];
+            let doc = &mut file.doc;
+            doc.save()?;
+            // Commit events to event manager (for undo / redo)
+            doc.commit();
+            self.saved_at(&ptr, idx);
         }
+        self.file_tree_git_refresh();
         self.feedback = Feedback::Info("Saved all documents".to_string());
         Ok(())
     }
 
-    /// Quit the editor
-    pub fn quit(&mut self) -> Result<()> {
+    /// Close what is being edited, then quit the editor if there is nothing left to edit
+    pub fn quit(&mut self, lua: &Lua) -> Result<()> {
         match self.files.get_raw(self.ptr.clone()) {
-            Some(FileLayout::Atom(fcs, ptr)) => {
-                let last_file = fcs.len() == 1;
-                // Remove the file that is currently open and selected
-                let msg =
-                    "This document isn't saved, press Ctrl + Q to force quit or Esc to cancel";
-                let doc = &fcs[*ptr].doc;
-                if doc.event_mgmt.with_disk(&doc.take_snapshot()) || self.confirm(msg)? {
-                    let (fcs, ptr) = self.files.get_atom_mut(self.ptr.clone()).unwrap();
-                    fcs.remove(*ptr);
-                    self.prev();
-                }
-                // Perform cleanup / pointer reassignment if this atom is now empty
-                if last_file {
-                    // Clean up the file structure
-                    self.files.clean_up();
-                    // Find a new pointer position
-                    self.ptr = self.files.new_pointer_position(&self.ptr);
-                    // Clean up the redundant sidebyside/toptobottom
-                    self.ptr = self.files.clean_up_multis(self.ptr.clone());
+            Some(FileLayout::Atom(_, idx)) => {
+                let idx = *idx;
+                self.close_document(lua, idx)?;
+                if !self.active {
+                    return Ok(());
                 }
             }
-            Some(FileLayout::Terminal(_)) => {
+            Some(FileLayout::Terminal(_) | FileLayout::Preview(_) | FileLayout::Diff(_)) => {
                 self.files.remove(self.ptr.clone());
                 // Find a new pointer position
                 self.ptr = self.files.new_pointer_position(&self.ptr);
@@ -368,31 +952,102 @@ impl Editor {
             }
             _ => (),
         }
-        // If there are no longer any active atoms, quit the entire editor
-        self.active = !matches!(
+        // Previews and diffs go with the documents they show
+        self.close_orphaned_previews();
+        self.close_orphaned_diffs();
+        self.try_exit(lua)
+    }
+
+    /// Close a document in the current split. Unsaved changes are only thrown away
+    /// once that has been confirmed, and forcing it only ever applies to this document
+    pub fn close_document(&mut self, lua: &Lua, idx: usize) -> Result<()> {
+        let ptr = self.ptr.clone();
+        let at = (ptr.clone(), idx);
+        let unsaved = self.file_at(&ptr, idx)?.unsaved_changes();
+        let msg = "This document isn't saved, press Ctrl + Q to force quit or Esc to cancel";
+        if !self.views_of(&at).is_empty() {
+            // The document is still shown in another pane, so just close this one
+        } else if !unsaved {
+            // Keep the undo history of saved documents for next time
+            self.store_history_at(&ptr, idx);
+            self.remove_swap_at(&ptr, idx);
+            self.note_closed(&ptr, idx);
+        } else if self.files.all_documents(vec![]).len() == 1 {
+            // Quitting the last document quits the editor, which lists what is unsaved
+            return self.quit_all(lua);
+        } else if self.git_operation(&ptr, idx).is_some() {
+            // Closing it without saving means git aborts once ox quits
+            if self.settle_git_message(lua, &at)? {
+                self.exit_code = 1;
+            }
+            self.note_closed(&ptr, idx);
+        } else if self.confirm(msg)? {
+            // The changes are being thrown away, so there is nothing to recover
+            self.remove_swap_at(&ptr, idx);
+            self.note_closed(&ptr, idx);
+        } else {
+            return Ok(());
+        }
+        self.remove_document(idx);
+        Ok(())
+    }
+
+    /// Take a document out of the current split, settling on the one before it
+    /// if it was being edited (and removing the split if it is now empty)
+    fn remove_document(&mut self, idx: usize) {
+        let Some((fcs, current)) = self.files.get_atom_mut(self.ptr.clone()) else {
+            return;
+        };
+        fcs.remove(idx);
+        if fcs.is_empty() {
+            // Clean up the file structure
+            self.files.clean_up();
+            // Find a new pointer position
+            self.ptr = self.files.new_pointer_position(&self.ptr);
+            // Clean up the redundant sidebyside/toptobottom
+            self.ptr = self.files.clean_up_multis(self.ptr.clone());
+            return;
+        }
+        // The documents after the one closed move back a place
+        let current = if *current >= idx {
+            current.saturating_sub(1)
+        } else {
+            *current
+        };
+        self.switch_to(current);
+    }
+
+    /// Quit the editor once there is nothing left to edit,
+    /// listing any documents that still have unsaved changes first
+    pub fn try_exit(&mut self, lua: &Lua) -> Result<()> {
+        let editing = !matches!(
             self.files,
-            FileLayout::None | FileLayout::FileTree | FileLayout::Terminal(_)
+            FileLayout::None
+                | FileLayout::FileTree
+                | FileLayout::Terminal(_)
+                | FileLayout::Preview(_)
+                | FileLayout::Diff(_)
         );
-        Ok(())
+        if editing {
+            return Ok(());
+        }
+        self.quit_all(lua)
     }
 
     /// Move to the next document opened in the editor
     pub fn next(&mut self) {
-        if let Some((files, ptr)) = self.files.get_atom_mut(self.ptr.clone()) {
-            if *ptr + 1 < files.len() {
-                *ptr += 1;
-                self.update_cwd();
+        if let Some((files, ptr)) = self.files.get_atom(self.ptr.clone()) {
+            if ptr + 1 < files.len() {
+                self.switch_to(ptr + 1);
             }
         }
     }
 
     /// Move to the previous document opened in the editor
+    /// (after a document is closed this settles on the one that takes its place)
     pub fn prev(&mut self) {
-        if let Some((_, ptr)) = self.files.get_atom_mut(self.ptr.clone()) {
-            if *ptr != 0 {
-                *ptr = ptr.saturating_sub(1);
-                self.update_cwd();
-            }
+        if let Some((_, ptr)) = self.files.get_atom(self.ptr.clone()) {
+            self.switch_to(ptr.saturating_sub(1));
         }
     }
 
@@ -408,6 +1063,29 @@ impl Editor {
         }
     }
 
+    /// Get the file container of any open document, checking that it is still open
+    pub fn file_at(&self, ptr: &[usize], doc: usize) -> Result<&FileContainer> {
+        self.files
+            .get_atom(ptr.to_vec())
+            .and_then(|(files, _)| files.get(doc))
+            .ok_or(OxError::DocumentClosed)
+    }
+
+    /// Get the file container of any open document mutably, checking that it is still open
+    pub fn file_at_mut(&mut self, ptr: &[usize], doc: usize) -> Result<&mut FileContainer> {
+        self.files
+            .get_atom_mut(ptr.to_vec())
+            .and_then(|(files, _)| files.get_mut(doc))
+            .ok_or(OxError::DocumentClosed)
+    }
+
+    /// Determine if a document is on screen (it is the current tab of its split)
+    pub fn is_visible(&self, ptr: &[usize], doc: usize) -> bool {
+        self.files
+            .get_atom(ptr.to_vec())
+            .is_some_and(|(files, current)| current == doc && doc < files.len())
+    }
+
     /// Try to get a document
     pub fn try_doc(&self) -> Option<&Document> {
         self.files.get(self.ptr.clone()).map(|file| &file.doc)
@@ -420,20 +1098,27 @@ impl Editor {
             .map(|file| &mut file.doc)
     }
 
-    /// Returns a document at a certain index
-    pub fn get_doc(&mut self, idx: usize) -> &mut Document {
-        &mut self.files.get_atom_mut(self.ptr.clone()).unwrap().0[idx].doc
+    /// Returns a document at a certain index (if there is one there)
+    pub fn get_doc(&mut self, idx: usize) -> Option<&mut Document> {
+        let (fcs, _) = self.files.get_atom_mut(self.ptr.clone())?;
+        Some(&mut fcs.get_mut(idx)?.doc)
     }
 
     /// Gets the number of documents currently open
     pub fn doc_len(&mut self) -> usize {
-        self.files.get_atom(self.ptr.clone()).unwrap().0.len()
+        self.files
+            .get_atom(self.ptr.clone())
+            .map_or(0, |(fcs, _)| fcs.len())
     }
 
-    /// Load the configuration values
+    /// Load the configuration values, giving back the error the configuration file failed with
+    /// (if it did, in which case it has been reported and the defaults are used instead)
     pub fn load_config(&mut self, path: &str, lua: &Lua) -> Option<LuaError> {
         self.config_path = path.to_string();
+        self.config_modified = reload::config_modified(path);
+        self.safe_mode = false;
         let result = Config::read(path, lua);
+        let mut failure = None;
         // Display any warnings if the user configuration couldn't be found
         match result {
             Ok(()) => (),
@@ -444,12 +1129,22 @@ impl Editor {
                     self.feedback = Feedback::Warning(warn);
                 }
             }
-            Err(OxError::Lua(err)) => return Some(err),
+            // The defaults are in place of a configuration file that failed
+            Err(OxError::Lua(err)) => {
+                self.safe_mode = true;
+                self.report_config_error(OxError::Lua(err.clone()));
+                failure = Some(err);
+            }
             _ => unreachable!(),
         }
+        if let Some(warnings) = self.config.take_warnings() {
+            if !self.safe_mode {
+                self.feedback = Feedback::Warning(warnings);
+            }
+        }
         // Calculate the correct push down based on config
         self.push_down = usize::from(config!(self.config, tab_line).enabled);
-        None
+        failure
     }
 
     /// Handle event
@@ -463,7 +1158,7 @@ impl Editor {
         };
         // Pass event down to special handlers
         match event {
-            CEvent::Key(key) => self.handle_key_event(key.modifiers, key.code)?,
+            CEvent::Key(key) => self.handle_key_event(lua, key.modifiers, key.code)?,
             CEvent::Resize(_, _) => self.handle_resize(lua)?,
             CEvent::Mouse(mouse_event) => self.handle_mouse_event(lua, mouse_event)?,
             CEvent::Paste(text) => self.handle_paste(&text)?,
@@ -473,22 +1168,32 @@ impl Editor {
     }
 
     /// Handle key event
-    pub fn handle_key_event(&mut self, modifiers: KMod, code: KCode) -> Result<()> {
+    pub fn handle_key_event(&mut self, lua: &Lua, modifiers: KMod, code: KCode) -> Result<()> {
         match self.files.get_raw_mut(self.ptr.clone()) {
             // File tree key behaviour
+            Some(FileLayout::FileTree) if self.file_tree_filter.is_some() => {
+                self.file_tree_filter_key(modifiers, code)?;
+            }
             Some(FileLayout::FileTree) => match (modifiers, code) {
                 (KMod::NONE, KCode::Up) => self.file_tree_select_up(),
                 (KMod::NONE, KCode::Down) => self.file_tree_select_down(),
                 (KMod::NONE, KCode::Enter) => self.file_tree_open_node()?,
+                (KMod::NONE, KCode::Right) => self.file_tree_expand(),
+                (KMod::NONE, KCode::Left) => self.file_tree_collapse(),
                 (KMod::CONTROL, KCode::Up) => self.file_tree_move_to_top(),
                 (KMod::CONTROL, KCode::Down) => self.file_tree_move_to_bottom(),
                 (KMod::CONTROL, KCode::Enter) => self.file_tree_move_into(),
                 (KMod::NONE, KCode::Char('n')) => self.file_tree_new()?,
+                (KMod::SHIFT, KCode::Char('N')) => self.file_tree_new_dir()?,
+                (KMod::NONE, KCode::Char('r')) => self.file_tree_rename()?,
                 (KMod::NONE, KCode::Char('d')) => self.file_tree_delete()?,
                 (KMod::NONE, KCode::Char('m')) => self.file_tree_move()?,
                 (KMod::NONE, KCode::Char('c')) => self.file_tree_copy()?,
+                (KMod::NONE, KCode::Char('/')) => self.file_tree_filter_start(),
                 _ => (),
             },
+            // Previews and diffs can't be edited
+            Some(FileLayout::Preview(_) | FileLayout::Diff(_)) => (),
             // Terminal behaviour
             #[cfg(not(target_os = "windows"))]
             Some(FileLayout::Terminal(term)) => match (modifiers, code) {
@@ -511,15 +1216,22 @@ impl Editor {
                 }
                 // Register this activity
                 self.last_active = Instant::now();
+                // The help document closes like a pager would
+                let closing = matches!(code, KCode::Char('q') | KCode::Esc);
+                if self.showing_help() && modifiers == KMod::NONE && closing {
+                    return self.quit(lua);
+                }
+                // Enter on a line of build output opens the location it points to
+                if self.showing_build() && (modifiers, code) == (KMod::NONE, KCode::Enter) {
+                    return self.open_build_location();
+                }
                 // Editing - these key bindings can't be modified (only added to)!
-                match (modifiers, code) {
-                    // Core key bindings (non-configurable behaviour)
-                    (KMod::SHIFT | KMod::NONE, KCode::Char(ch)) => self.character(ch)?,
-                    (KMod::NONE, KCode::Tab) => self.handle_tab()?,
-                    (KMod::NONE, KCode::Backspace) => self.backspace()?,
-                    (KMod::NONE, KCode::Delete) => self.delete()?,
-                    (KMod::NONE, KCode::Enter) => self.enter()?,
-                    _ => (),
+                if let Some(action) = Action::from_key(modifiers, code) {
+                    self.perform(lua, action.clone())?;
+                    // Abbreviations are expanded once something that ends a word is typed
+                    if let Action::InsertChar(ch) = action {
+                        self.expand_abbreviation(lua, ch)?;
+                    }
                 }
             }
         }
@@ -529,6 +1241,7 @@ impl Editor {
     /// Handle resize
     pub fn handle_resize(&mut self, lua: &Lua) -> Result<()> {
         // Rerender the editor (that'll handle everything with the new size)
+        self.terminal.invalidate();
         self.needs_rerender = true;
         self.render(lua)
     }
@@ -537,6 +1250,10 @@ impl Editor {
     pub fn handle_paste(&mut self, text: &str) -> Result<()> {
         // Take the text and ensure there are no nasty surprises when it comes to newlines
         let text = text.replace("\r\n", "\n").replace('\r', "\n");
+        // Files dropped on the terminal have their paths pasted, which can be opened instead
+        if self.try_doc().is_some() && self.open_pasted_files(&text)? {
+            return Ok(());
+        }
         if self.try_doc().is_some() {
             // If we're playing back a macro, use the last text the user copied
             // (to prevent hard-coded pasting)
@@ -545,30 +1262,21 @@ impl Editor {
             } else {
                 text.to_string()
             };
-            // Save state before paste
-            self.try_doc_mut().unwrap().commit();
-            // Apply paste
-            self.pasting = true;
-            for ch in text.chars() {
-                self.character(ch)?;
-            }
-            self.pasting = false;
-            // Save state after paste
-            self.try_doc_mut().unwrap().commit();
+            self.paste_text(&text)?;
         }
         Ok(())
     }
 
+    /// Paste text in (replacing any selection) as a single undoable change
+    pub fn paste_text(&mut self, text: &str) -> Result<()> {
+        self.pasting = true;
+        let pasted = self.batch(|editor| text.chars().try_for_each(|ch| editor.character(ch)));
+        self.pasting = false;
+        pasted
+    }
+
     /// Handle tab character being inserted
     pub fn handle_tab(&mut self) -> Result<()> {
-        if config!(self.config, document).indentation == Indentation::Tabs {
-            self.character('\t')?;
-        } else {
-            let tab_width = config!(self.config, document).tab_width;
-            for _ in 0..tab_width {
-                self.character(' ')?;
-            }
-        }
-        Ok(())
+        self.character('\t')
     }
 }
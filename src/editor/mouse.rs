@@ -2,18 +2,22 @@ use crate::editor::FileLayout;
 /// For handling mouse events
 use crate::{config, Result};
 use crossterm::event::{KeyModifiers, MouseButton, MouseEvent, MouseEventKind};
-use kaolinite::{utils::width, Loc};
+use kaolinite::Loc;
 use mlua::Lua;
 use std::time::{Duration, Instant};
 
-use super::Editor;
+use super::{Action, Editor};
 
 /// Represents where the mouse has clicked / been dragged
 enum MouseLocation {
     /// Where the mouse has clicked within a file
     File(Vec<usize>, Loc),
+    /// Where the mouse has clicked on a line number (giving the line)
+    Gutter(Vec<usize>, usize),
     /// Where the mouse has clicked on a tab
     Tabs(Vec<usize>, usize),
+    /// Where the mouse has clicked on the close button of a tab
+    TabClose(Vec<usize>, usize),
     /// Where the mouse has clicked in the file tree
     FileTree(usize),
     /// Where the mouse has clicked in the terminal
@@ -50,29 +54,52 @@ impl Editor {
                     // Work out where the user clicked
                     if clicked.y == 0 && tab_enabled {
                         // Clicked on tab line
-                        let (tabs, _, offset) =
-                            self.get_tab_parts(&idx, lua, cols.end.saturating_sub(cols.start));
+                        let w = cols.end.saturating_sub(cols.start);
+                        let layout = self.tab_layout(&idx, lua, w);
+                        let first = layout.placements.first().map_or(0, |p| p.doc);
+                        let last = layout.placements.last().map_or(0, |p| p.doc);
+                        // The scroll indicators move to the tab just out of view
+                        if clicked.x == 0 && layout.hidden_left {
+                            return MouseLocation::Tabs(idx, first.saturating_sub(1));
+                        }
+                        if clicked.x + 1 == w && layout.hidden_right {
+                            return MouseLocation::Tabs(idx, last + 1);
+                        }
                         // Try to work out which tab we clicked on
-                        let mut c = u16::try_from(clicked.x).unwrap_or(u16::MAX) + 2;
-                        for (i, header) in tabs.iter().enumerate() {
-                            let header_len = width(header, 4) + 1;
-                            c = c.saturating_sub(u16::try_from(header_len).unwrap_or(u16::MAX));
-                            if c == 0 {
-                                // This tab was clicked on
-                                return MouseLocation::Tabs(idx.clone(), i + offset);
+                        match layout
+                            .placements
+                            .iter()
+                            .find(|p| p.cols.contains(&clicked.x))
+                        {
+                            Some(p) if p.close == Some(clicked.x) => {
+                                MouseLocation::TabClose(idx, p.doc)
                             }
+                            Some(p) => MouseLocation::Tabs(idx, p.doc),
+                            // Did not click on a tab
+                            None => MouseLocation::Out,
                         }
-                        // Did not click on a tab
-                        MouseLocation::Out
-                    } else if clicked.y == rows.end.saturating_sub(1) {
+                    } else if clicked.y == rows.end.saturating_sub(1) && self.status_rows() > 0 {
                         // Clicked on status line
                         MouseLocation::Out
                     } else if clicked.x < dent {
-                        // Clicked on line numbers
-                        MouseLocation::Out
-                    } else if let Some((fcs, ptr)) = self.files.get_atom(idx.clone()) {
+                        // Clicked on line numbers (rather than the margin of distraction-free mode)
+                        let on_numbers = clicked.x >= self.zen_margin(&idx)
+                            && config!(self.config, line_numbers).enabled;
+                        match self.files.get(idx.clone()) {
+                            Some(fc) if on_numbers => {
+                                let doc = &fc.doc;
+                                let y = clicked.y.saturating_sub(tab) + doc.offset.y;
+                                if y < doc.len_lines() {
+                                    MouseLocation::Gutter(idx.clone(), y)
+                                } else {
+                                    MouseLocation::Out
+                                }
+                            }
+                            _ => MouseLocation::Out,
+                        }
+                    } else if let Some(fc) = self.files.get(idx.clone()) {
                         // Clicked on document
-                        let offset = fcs[ptr].doc.offset;
+                        let offset = fc.doc.offset;
                         MouseLocation::File(
                             idx.clone(),
                             Loc {
@@ -85,7 +112,10 @@ impl Editor {
                         MouseLocation::Out
                     }
                 }
-                Some(FileLayout::FileTree) => MouseLocation::FileTree(row),
+                Some(FileLayout::FileTree) => {
+                    let row = row.saturating_sub(rows.start);
+                    MouseLocation::FileTree(row + self.render_cache.file_tree_scroll)
+                }
                 Some(FileLayout::Terminal(_)) => MouseLocation::Terminal(idx),
                 _ => MouseLocation::Out,
             }
@@ -94,9 +124,32 @@ impl Editor {
         }
     }
 
+    /// Find the greeting entry a click landed on (if the greeting is in the pane clicked on)
+    pub fn greeting_clicked(&mut self, lua: &Lua, event: MouseEvent) -> Option<char> {
+        let MouseLocation::File(idx, loc) = self.find_mouse_location(lua, event) else {
+            return None;
+        };
+        let (fcs, doc) = self.files.get_atom(idx.clone())?;
+        let room = fcs.get(doc)?.doc.size.h;
+        if fcs.get(doc)?.doc.file_name.is_some() {
+            return None;
+        }
+        let (_, rows, cols) = self
+            .render_cache
+            .span
+            .iter()
+            .find(|(at, _, _)| *at == idx)?;
+        let w = cols.len().saturating_sub(self.dent_for(&idx, doc));
+        self.greeting_entry_at(loc, w, rows.len(), room)
+    }
+
     /// Handles a mouse event (dragging / clicking)
     #[allow(clippy::too_many_lines)]
     pub fn handle_mouse_event(&mut self, lua: &Lua, event: MouseEvent) -> Result<()> {
+        // Dragging the dividers between splits resizes them
+        if self.split_drag_mouse(event) {
+            return Ok(());
+        }
         match event.modifiers {
             KeyModifiers::NONE => match event.kind {
                 // Single click
@@ -127,15 +180,31 @@ impl Editor {
                                 doc.old_cursor = doc.loc().x;
                             }
                         }
-                        MouseLocation::Tabs(idx, i) => {
-                            self.files.move_to(idx.clone(), i);
+                        MouseLocation::Gutter(idx, y) => {
                             self.cache_old_ptr(&idx);
                             self.ptr.clone_from(&idx);
                             self.update_cwd();
+                            if let Some(doc) = self.try_doc_mut() {
+                                doc.clear_cursors();
+                                doc.select_line_at(y);
+                                let len = doc.line(y).unwrap_or_default().chars().count();
+                                self.gutter_drag = Some((Loc { x: 0, y }, Loc { x: len, y }));
+                            }
+                        }
+                        MouseLocation::Tabs(idx, i) => {
+                            self.cache_old_ptr(&idx);
+                            self.ptr.clone_from(&idx);
+                            self.switch_to(i);
+                        }
+                        MouseLocation::TabClose(idx, i) => {
+                            self.cache_old_ptr(&idx);
+                            self.ptr.clone_from(&idx);
+                            self.switch_to(i);
+                            self.perform(lua, Action::Quit)?;
                         }
                         MouseLocation::FileTree(y) => {
                             // Handle the click
-                            if let Some(ft) = &self.file_tree {
+                            if let Some(ft) = self.file_tree_shown() {
                                 // Move selection to where we clicked
                                 if let Some(item) = ft.flatten().get(y) {
                                     self.file_tree_selection = Some(item.to_string());
@@ -180,15 +249,30 @@ impl Editor {
                 // Double click detection
                 MouseEventKind::Up(MouseButton::Left) => {
                     self.alt_click_state = None;
+                    self.gutter_drag = None;
                     let now = Instant::now();
                     // Register this click as having happened
                     self.last_click = Some((now, event));
                 }
+                // Dragging from the line numbers selects whole lines
+                MouseEventKind::Drag(MouseButton::Left) if self.gutter_drag.is_some() => {
+                    let (idx, y) = match self.find_mouse_location(lua, event) {
+                        MouseLocation::File(idx, loc) => (idx, loc.y),
+                        MouseLocation::Gutter(idx, y) => (idx, y),
+                        _ => return Ok(()),
+                    };
+                    if idx == self.ptr {
+                        if let Some(lines) = self.gutter_drag {
+                            self.select_lines_to(y, lines);
+                        }
+                    }
+                }
                 // Mouse drag
                 MouseEventKind::Drag(MouseButton::Left) => {
                     match self.find_mouse_location(lua, event) {
                         MouseLocation::File(idx, mut loc) => {
-                            if self.try_doc().is_some() {
+                            // The pane may not have a document in it any more
+                            if self.files.get(idx.clone()).is_some() {
                                 self.cache_old_ptr(&idx);
                                 self.ptr.clone_from(&idx);
                                 self.update_cwd();
@@ -216,6 +300,8 @@ impl Editor {
                             }
                         }
                         MouseLocation::Tabs(_, _)
+                        | MouseLocation::TabClose(_, _)
+                        | MouseLocation::Gutter(_, _)
                         | MouseLocation::Out
                         | MouseLocation::FileTree(_)
                         | MouseLocation::Terminal(_) => (),
@@ -224,87 +310,141 @@ impl Editor {
                 MouseEventKind::Drag(MouseButton::Right) => {
                     match self.find_mouse_location(lua, event) {
                         MouseLocation::File(idx, mut loc) => {
-                            if self.try_doc().is_some() {
+                            // The pane may not have a document in it any more
+                            if self.files.get(idx.clone()).is_some() {
                                 self.cache_old_ptr(&idx);
                                 self.ptr.clone_from(&idx);
                                 self.update_cwd();
                                 let doc = self.try_doc_mut().unwrap();
                                 loc.x = doc.character_idx(&loc);
-                                if let Some((line_start, line_end)) = self.alt_click_state {
-                                    let doc = self.try_doc().unwrap();
-                                    if loc.y > doc.cursor.selection_end.y {
-                                        let line = doc.line(loc.y).unwrap_or_default();
-                                        let doc = self.try_doc_mut().unwrap();
-                                        doc.move_to(&line_start);
-                                        doc.select_to(&Loc {
-                                            x: line.chars().count(),
-                                            y: loc.y,
-                                        });
-                                    } else {
-                                        let doc = self.try_doc_mut().unwrap();
-                                        doc.move_to(&line_end);
-                                        doc.select_to(&Loc { x: 0, y: loc.y });
-                                    }
+                                if let Some(lines) = self.alt_click_state {
+                                    self.select_lines_to(loc.y, lines);
                                 } else {
                                     self.try_doc_mut().unwrap().select_to(&loc);
                                 }
                             }
                         }
                         MouseLocation::Tabs(_, _)
+                        | MouseLocation::TabClose(_, _)
+                        | MouseLocation::Gutter(_, _)
                         | MouseLocation::Out
                         | MouseLocation::FileTree(_)
                         | MouseLocation::Terminal(_) => (),
                     }
                 }
                 // Mouse scroll behaviour
-                MouseEventKind::ScrollDown | MouseEventKind::ScrollUp => {
-                    let scroll_amount = config!(self.config, terminal).scroll_amount;
-                    if let MouseLocation::File(idx, _) = self.find_mouse_location(lua, event) {
-                        self.cache_old_ptr(&idx);
-                        self.ptr.clone_from(&idx);
-                        self.update_cwd();
-                        if let Some(doc) = self.try_doc_mut() {
-                            for _ in 0..scroll_amount {
-                                if event.kind == MouseEventKind::ScrollDown {
-                                    doc.scroll_down();
-                                } else {
-                                    doc.scroll_up();
-                                }
-                            }
-                        }
-                    }
-                }
-                MouseEventKind::ScrollLeft => {
-                    if let Some(doc) = self.try_doc_mut() {
-                        doc.move_left();
-                    }
+                MouseEventKind::ScrollDown
+                | MouseEventKind::ScrollUp
+                | MouseEventKind::ScrollLeft
+                | MouseEventKind::ScrollRight => self.scroll_with_wheel(lua, event, event.kind),
+                _ => (),
+            },
+            // Scrolling with shift held moves sideways (for mice without a horizontal wheel)
+            KeyModifiers::SHIFT => match event.kind {
+                MouseEventKind::ScrollUp => {
+                    self.scroll_with_wheel(lua, event, MouseEventKind::ScrollLeft);
                 }
-                MouseEventKind::ScrollRight => {
-                    if let Some(doc) = self.try_doc_mut() {
-                        doc.move_right();
-                    }
+                MouseEventKind::ScrollDown => {
+                    self.scroll_with_wheel(lua, event, MouseEventKind::ScrollRight);
                 }
                 _ => (),
             },
             // Multi cursor behaviour
             KeyModifiers::CONTROL => {
                 if let MouseEventKind::Down(MouseButton::Left) = event.kind {
-                    if let MouseLocation::File(idx, loc) = self.find_mouse_location(lua, event) {
+                    match self.find_mouse_location(lua, event) {
+                        MouseLocation::File(idx, loc) => {
+                            self.cache_old_ptr(&idx);
+                            self.ptr.clone_from(&idx);
+                            self.update_cwd();
+                            if let Some(doc) = self.try_doc_mut() {
+                                doc.new_cursor(loc);
+                                doc.commit();
+                            }
+                        }
+                        // Ctrl clicking a line number bookmarks the line
+                        MouseLocation::Gutter(idx, y) => {
+                            self.cache_old_ptr(&idx);
+                            self.ptr.clone_from(&idx);
+                            self.update_cwd();
+                            self.toggle_bookmark_at(y);
+                        }
+                        _ => (),
+                    }
+                }
+            }
+            // Block selection behaviour
+            KeyModifiers::ALT => match event.kind {
+                MouseEventKind::Down(MouseButton::Left) => {
+                    if let MouseLocation::File(idx, mut loc) = self.find_mouse_location(lua, event)
+                    {
                         self.cache_old_ptr(&idx);
                         self.ptr.clone_from(&idx);
                         self.update_cwd();
                         if let Some(doc) = self.try_doc_mut() {
-                            doc.new_cursor(loc);
-                            doc.commit();
+                            doc.clear_cursors();
+                            loc.x = doc.character_idx(&loc);
+                            doc.move_to(&loc);
                         }
                     }
                 }
-            }
+                MouseEventKind::Drag(MouseButton::Left) => {
+                    // The block reaches the column dragged to, even past the end of the line
+                    if let MouseLocation::File(idx, loc) = self.find_mouse_location(lua, event) {
+                        if idx == self.ptr {
+                            if let Some(doc) = self.try_doc_mut() {
+                                doc.select_block_to(&loc);
+                            }
+                        }
+                    }
+                }
+                _ => (),
+            },
             _ => (),
         }
         Ok(())
     }
 
+    /// Scroll the document under the mouse, leaving the cursor where it is
+    fn scroll_with_wheel(&mut self, lua: &Lua, event: MouseEvent, direction: MouseEventKind) {
+        let scroll_amount = config!(self.config, terminal).scroll_amount;
+        if let MouseLocation::File(idx, _) = self.find_mouse_location(lua, event) {
+            self.cache_old_ptr(&idx);
+            self.ptr.clone_from(&idx);
+            self.update_cwd();
+            self.wait_if_near_end();
+            if let Some(doc) = self.try_doc_mut() {
+                for _ in 0..scroll_amount {
+                    match direction {
+                        MouseEventKind::ScrollDown => doc.scroll_down(),
+                        MouseEventKind::ScrollUp => doc.scroll_up(),
+                        MouseEventKind::ScrollLeft => doc.scroll_left(),
+                        MouseEventKind::ScrollRight => doc.scroll_right(),
+                        _ => (),
+                    }
+                }
+            }
+        }
+    }
+
+    /// Extend a selection by whole lines, from the line it started on to another line
+    fn select_lines_to(&mut self, y: usize, (line_start, line_end): (Loc, Loc)) {
+        let Some(doc) = self.try_doc_mut() else {
+            return;
+        };
+        if y > line_start.y {
+            let line = doc.line(y).unwrap_or_default();
+            doc.move_to(&line_start);
+            doc.select_to(&Loc {
+                x: line.chars().count(),
+                y,
+            });
+        } else {
+            doc.move_to(&line_end);
+            doc.select_to(&Loc { x: 0, y });
+        }
+    }
+
     /// Handle a double-click event
     pub fn handle_double_click(&mut self, lua: &Lua, event: MouseEvent) {
         // Select the current word
@@ -0,0 +1,43 @@
+/// The notes file: a single file kept with the configuration, for jotting things down in
+/// whatever is being worked on
+use crate::error::{OxError, Result};
+use std::path::PathBuf;
+
+use super::Editor;
+
+/// Work out where ox keeps its configuration (plug-ins are kept here too)
+fn config_dir() -> Option<PathBuf> {
+    let home = std::env::var("HOME").or_else(|_| std::env::var("USERPROFILE"));
+    let home = PathBuf::from(home.ok()?);
+    if cfg!(target_os = "windows") {
+        Some(home.join("ox"))
+    } else {
+        Some(home.join(".config").join("ox"))
+    }
+}
+
+/// Work out where the notes file is kept
+fn notes_path() -> Option<PathBuf> {
+    Some(config_dir()?.join("notes.md"))
+}
+
+impl Editor {
+    /// Open the notes file (creating it the first time), or move to it if it is already open
+    pub fn notes(&mut self) -> Result<()> {
+        let path = notes_path().ok_or(OxError::InvalidPath)?;
+        if !path.exists() {
+            if let Some(parent) = path.parent() {
+                std::fs::create_dir_all(parent)?;
+            }
+            std::fs::write(&path, "")?;
+        }
+        match self.open(&path.to_string_lossy()) {
+            Ok(()) => self.next(),
+            // The notes are already open, so they have just been switched to
+            Err(OxError::AlreadyOpen { .. }) => return Ok(()),
+            Err(err) => return Err(err),
+        }
+        self.update_cwd();
+        Ok(())
+    }
+}
@@ -0,0 +1,76 @@
+/// Keeping the terminal title up to date, and ringing the bell when something goes wrong
+use crate::config;
+use crate::config::Bell;
+use crate::Feedback;
+use kaolinite::utils::get_file_name;
+use std::time::{Duration, Instant};
+
+use super::Editor;
+
+/// How long the status line stays inverted for the visual bell
+const FLASH_LENGTH: Duration = Duration::from_millis(150);
+
+impl Editor {
+    /// Work out the terminal title from the document being edited
+    fn title(&self) -> String {
+        let Some(file) = self.files.get(self.ptr.clone()) else {
+            return "ox".to_string();
+        };
+        let name = file
+            .doc
+            .file_name
+            .as_deref()
+            .and_then(get_file_name)
+            .unwrap_or_else(|| file.placeholder_name());
+        let modified = if file.unsaved_changes() {
+            " (modified)"
+        } else {
+            ""
+        };
+        format!("{name}{modified} — ox")
+    }
+
+    /// Show the document being edited in the terminal title
+    pub fn update_title(&mut self) {
+        let title = self.title();
+        self.terminal.set_title(&title);
+    }
+
+    /// Get the user's attention in the way `terminal.bell` asks for
+    pub fn ring_bell(&mut self) {
+        let bell = config!(self.config, terminal).bell;
+        match bell {
+            Bell::None => (),
+            // Failing to ring the bell isn't worth reporting
+            Bell::Audible => {
+                let _ = self.terminal.bell();
+            }
+            Bell::Visual => {
+                self.flash_until = Some(Instant::now() + FLASH_LENGTH);
+                self.needs_rerender = true;
+            }
+        }
+    }
+
+    /// Ring the bell if an error has been reported (the feedback is cleared before each event)
+    pub fn bell_on_error(&mut self) {
+        if matches!(self.feedback, Feedback::Error(_)) {
+            self.ring_bell();
+        }
+    }
+
+    /// Whether the status line should be inverted for the visual bell
+    pub fn flashing(&self) -> bool {
+        self.flash_until.is_some_and(|until| Instant::now() < until)
+    }
+
+    /// Put the status line back once the visual bell is over, returning true if it needs redrawing
+    pub fn bell_idle_tick(&mut self) -> bool {
+        if self.flash_until.is_none() || self.flashing() {
+            return false;
+        }
+        self.flash_until = None;
+        self.needs_rerender = true;
+        true
+    }
+}
@@ -0,0 +1,107 @@
+/// Keeping track of whether the files behind documents have been changed or removed
+/// by other programs since they were opened or last saved (which the tab line can show,
+/// and which are picked up when the terminal regains focus)
+use std::time::{Duration, Instant, SystemTime};
+
+use super::Editor;
+
+/// How often the files behind documents are checked
+const CHECK_PERIOD: Duration = Duration::from_secs(2);
+
+/// When a file was last changed on the disk
+fn disk_time(file_name: &str) -> Option<SystemTime> {
+    std::fs::metadata(file_name).and_then(|m| m.modified()).ok()
+}
+
+/// How the file behind a document stands, as of the last check
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum DiskState {
+    /// As it was when opened or last saved (or there is no file to speak of)
+    #[default]
+    Unchanged,
+    /// Another program has written to it since
+    Modified,
+    /// It has been removed since
+    Deleted,
+}
+
+/// What a document knows of its file on the disk
+#[derive(Debug, Default, Clone)]
+pub struct OnDisk {
+    /// The file this is about (documents get a new one when saved elsewhere)
+    name: Option<String>,
+    /// When the file was last changed, as of opening or saving it
+    time: Option<SystemTime>,
+    pub state: DiskState,
+}
+
+impl OnDisk {
+    /// Note how a file is on the disk now
+    pub fn of(file_name: Option<&str>) -> Self {
+        Self {
+            name: file_name.map(str::to_string),
+            time: file_name.and_then(disk_time),
+            state: DiskState::Unchanged,
+        }
+    }
+
+    /// Work out how the file stands now, returning whether that is different from before
+    fn check(&mut self, file_name: Option<&str>) -> bool {
+        let before = self.state;
+        if self.name.as_deref() != file_name {
+            *self = Self::of(file_name);
+        }
+        // Files that didn't exist yet when opened are left alone until they are saved
+        if let (Some(name), Some(time)) = (file_name, self.time) {
+            self.state = match disk_time(name) {
+                None => DiskState::Deleted,
+                Some(now) if now != time => DiskState::Modified,
+                Some(_) => DiskState::Unchanged,
+            };
+        }
+        self.state != before
+    }
+}
+
+impl Editor {
+    /// Note how a document's file is on the disk now, for it and any other views of it
+    /// (after it has been saved or reloaded)
+    pub fn note_on_disk(&mut self, ptr: &[usize], idx: usize) {
+        let at = (ptr.to_vec(), idx);
+        let mut views = self.views_of(&at);
+        views.push(at);
+        for (ptr, idx) in views {
+            if let Ok(file) = self.file_at_mut(&ptr, idx) {
+                file.on_disk = OnDisk::of(file.doc.file_name.as_deref());
+            }
+        }
+    }
+
+    /// Check the files behind documents every so often, returning whether the tabs need redrawing
+    pub fn on_disk_idle_tick(&mut self) -> bool {
+        if self
+            .disk_checked
+            .is_some_and(|at| at.elapsed() < CHECK_PERIOD)
+        {
+            return false;
+        }
+        self.check_on_disk()
+    }
+
+    /// See whether the files behind documents have been changed or removed,
+    /// returning whether any of them are different from the last check
+    pub fn check_on_disk(&mut self) -> bool {
+        self.disk_checked = Some(Instant::now());
+        let mut changed = false;
+        for (ptr, idx) in self.files.all_documents(vec![]) {
+            if let Ok(file) = self.file_at_mut(&ptr, idx) {
+                let file_name = file.doc.file_name.clone();
+                changed |= file.on_disk.check(file_name.as_deref());
+            }
+        }
+        if changed {
+            self.needs_rerender = true;
+        }
+        changed
+    }
+}
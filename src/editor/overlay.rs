@@ -0,0 +1,207 @@
+/// Letting the mouse interact with overlays (like pickers) drawn over documents
+use crate::config;
+use crossterm::event::{Event as CEvent, MouseButton, MouseEventKind};
+use std::ops::Range;
+
+use super::Editor;
+
+/// The glyph drawn in the corner of an overlay, which closes it when clicked
+pub const CLOSE_GLYPH: char = '×';
+
+/// Where an overlay was drawn on screen in the last frame
+#[derive(Debug, Default, Clone)]
+pub struct OverlayRegion {
+    pub rows: Range<usize>,
+    pub cols: Range<usize>,
+    /// Where the close glyph was drawn (column, row)
+    pub close: Option<(usize, usize)>,
+    /// The screen row of each item shown, along with which item it is
+    pub items: Vec<(usize, usize)>,
+}
+
+/// What part of an overlay a point on screen is over
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverlayHit {
+    Close,
+    Item(usize),
+    Inside,
+    Outside,
+}
+
+impl OverlayRegion {
+    /// Work out what part of the overlay a point on screen is over
+    pub fn hit(&self, col: usize, row: usize) -> OverlayHit {
+        if self.close == Some((col, row)) {
+            OverlayHit::Close
+        } else if !self.rows.contains(&row) || !self.cols.contains(&col) {
+            OverlayHit::Outside
+        } else if let Some((_, item)) = self.items.iter().find(|(r, _)| *r == row) {
+            OverlayHit::Item(*item)
+        } else {
+            OverlayHit::Inside
+        }
+    }
+}
+
+/// What a mouse event means for a picker
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ListMouse {
+    /// Move the selection to an item (hovering or scrolling)
+    Select(usize),
+    /// Pick an item (clicking on it)
+    Activate(usize),
+    /// Close the picker (clicking the close glyph, or outside when configured to)
+    Dismiss,
+    Ignore,
+}
+
+impl Editor {
+    /// Work out what a mouse event means for the picker currently drawn,
+    /// given which item is selected out of how many
+    pub fn list_mouse(&self, event: &CEvent, selected: usize, len: usize) -> ListMouse {
+        let (CEvent::Mouse(event), Some(region)) = (event, &self.render_cache.overlay) else {
+            return ListMouse::Ignore;
+        };
+        let hit = region.hit(event.column as usize, event.row as usize);
+        let scroll = config!(self.config, terminal).scroll_amount.max(1);
+        match (event.kind, hit) {
+            (MouseEventKind::Down(MouseButton::Left), OverlayHit::Close) => ListMouse::Dismiss,
+            (MouseEventKind::Down(MouseButton::Left), OverlayHit::Item(item)) => {
+                ListMouse::Activate(item)
+            }
+            (MouseEventKind::Down(_), OverlayHit::Outside)
+                if config!(self.config, terminal).click_outside_dismisses =>
+            {
+                ListMouse::Dismiss
+            }
+            (MouseEventKind::Moved, OverlayHit::Item(item)) if item != selected => {
+                ListMouse::Select(item)
+            }
+            // Scrolling over the picker moves through it rather than the document underneath
+            (MouseEventKind::ScrollUp, _) => ListMouse::Select(selected.saturating_sub(scroll)),
+            (MouseEventKind::ScrollDown, _) => {
+                ListMouse::Select((selected + scroll).min(len.saturating_sub(1)))
+            }
+            _ => ListMouse::Ignore,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crossterm::event::{KeyModifiers, MouseEvent};
+    use mlua::Lua;
+
+    /// A picker over rows 10 to 13 and columns 5 to 24, with its close glyph in the corner
+    /// and items 3 to 5 shown on rows 11 to 13
+    fn region() -> OverlayRegion {
+        OverlayRegion {
+            rows: 10..14,
+            cols: 5..25,
+            close: Some((24, 10)),
+            items: vec![(11, 3), (12, 4), (13, 5)],
+        }
+    }
+
+    fn mouse(kind: MouseEventKind, column: u16, row: u16) -> CEvent {
+        CEvent::Mouse(MouseEvent {
+            kind,
+            column,
+            row,
+            modifiers: KeyModifiers::NONE,
+        })
+    }
+
+    #[test]
+    fn hits() {
+        let region = region();
+        assert_eq!(region.hit(24, 10), OverlayHit::Close);
+        // The header row isn't an item, but is still part of the overlay
+        assert_eq!(region.hit(5, 10), OverlayHit::Inside);
+        assert_eq!(region.hit(23, 10), OverlayHit::Inside);
+        // Items cover their whole row, from the first column to the last
+        assert_eq!(region.hit(5, 11), OverlayHit::Item(3));
+        assert_eq!(region.hit(24, 11), OverlayHit::Item(3));
+        assert_eq!(region.hit(14, 13), OverlayHit::Item(5));
+        // Just past each edge is outside
+        assert_eq!(region.hit(4, 11), OverlayHit::Outside);
+        assert_eq!(region.hit(25, 11), OverlayHit::Outside);
+        assert_eq!(region.hit(10, 9), OverlayHit::Outside);
+        assert_eq!(region.hit(10, 14), OverlayHit::Outside);
+        assert_eq!(region.hit(0, 0), OverlayHit::Outside);
+        // Nothing can be hit on an overlay that isn't anywhere
+        let empty = OverlayRegion::default();
+        assert_eq!(empty.hit(0, 0), OverlayHit::Outside);
+    }
+
+    #[test]
+    fn picker_mouse() {
+        let lua = Lua::new();
+        let mut editor = Editor::new(&lua).unwrap();
+        let left = MouseEventKind::Down(MouseButton::Left);
+        // Without a picker on screen, the mouse is left to the document
+        assert_eq!(
+            editor.list_mouse(&mouse(left, 10, 11), 0, 6),
+            ListMouse::Ignore
+        );
+        editor.render_cache.overlay = Some(region());
+        // Clicking an item picks it, hovering over one selects it
+        assert_eq!(
+            editor.list_mouse(&mouse(left, 10, 12), 0, 6),
+            ListMouse::Activate(4)
+        );
+        let moved = mouse(MouseEventKind::Moved, 10, 13);
+        assert_eq!(editor.list_mouse(&moved, 0, 6), ListMouse::Select(5));
+        assert_eq!(editor.list_mouse(&moved, 5, 6), ListMouse::Ignore);
+        // Scrolling moves through the list, stopping at either end
+        let up = mouse(MouseEventKind::ScrollUp, 10, 12);
+        let down = mouse(MouseEventKind::ScrollDown, 0, 0);
+        assert_eq!(editor.list_mouse(&up, 3, 6), ListMouse::Select(2));
+        assert_eq!(editor.list_mouse(&up, 0, 6), ListMouse::Select(0));
+        assert_eq!(editor.list_mouse(&down, 3, 6), ListMouse::Select(4));
+        assert_eq!(editor.list_mouse(&down, 5, 6), ListMouse::Select(5));
+        editor
+            .config
+            .terminal
+            .borrow_mut::<config::Terminal>()
+            .unwrap()
+            .scroll_amount = 3;
+        assert_eq!(editor.list_mouse(&down, 1, 6), ListMouse::Select(4));
+        assert_eq!(editor.list_mouse(&up, 1, 6), ListMouse::Select(0));
+        // Clicking the close glyph, or outside the list, closes it
+        assert_eq!(
+            editor.list_mouse(&mouse(left, 24, 10), 0, 6),
+            ListMouse::Dismiss
+        );
+        assert_eq!(
+            editor.list_mouse(&mouse(left, 30, 11), 0, 6),
+            ListMouse::Dismiss
+        );
+        let right = MouseEventKind::Down(MouseButton::Right);
+        assert_eq!(
+            editor.list_mouse(&mouse(right, 10, 2), 0, 6),
+            ListMouse::Dismiss
+        );
+        // ...unless clicking outside is set to do nothing
+        editor
+            .config
+            .terminal
+            .borrow_mut::<config::Terminal>()
+            .unwrap()
+            .click_outside_dismisses = false;
+        assert_eq!(
+            editor.list_mouse(&mouse(left, 30, 11), 0, 6),
+            ListMouse::Ignore
+        );
+        assert_eq!(
+            editor.list_mouse(&mouse(left, 24, 10), 0, 6),
+            ListMouse::Dismiss
+        );
+        // Clicking inside the list, but not on an item, does nothing
+        assert_eq!(
+            editor.list_mouse(&mouse(left, 6, 10), 0, 6),
+            ListMouse::Ignore
+        );
+    }
+}
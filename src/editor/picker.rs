@@ -0,0 +1,113 @@
+/// Lists drawn over the bottom of the document to pick an item from, which all share
+/// the way they are moved through (by keyboard and mouse) and picked from
+use crate::error::{OxError, Result};
+use crate::events::get_event;
+use crate::ui::key_event;
+use crossterm::event::{Event as CEvent, KeyCode as KCode, KeyModifiers as KMod};
+use mlua::Lua;
+
+use super::overlay::ListMouse;
+use super::Editor;
+
+/// What happens after a key a picker deals with itself
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PickerKey {
+    /// Carry on showing the list
+    Stay,
+    /// Pick the selected item
+    Pick,
+    /// Close the list, as if it was cancelled
+    Cancel,
+    /// Close the list, with nothing left to do
+    Done,
+}
+
+impl Editor {
+    /// Redraw the document underneath whatever is being shown, to fit the new size
+    pub fn redraw_after_resize(&mut self, lua: &Lua, event: &CEvent) -> Result<()> {
+        if let CEvent::Resize(..) = event {
+            self.terminal.invalidate();
+            self.render_document(lua)?;
+        }
+        Ok(())
+    }
+
+    /// Show a list, starting with an item selected, until an item is picked (with enter
+    /// or a click), then hand it to `on_select`. `draw` draws the list, returning how many
+    /// items are in it, and `on_key` is offered every key first, returning None for keys
+    /// it leaves to the list (enter, escape and moving through it).
+    /// `state` is handed to each of them in turn.
+    /// Returns `Cancelled` if the list is closed without anything being picked
+    pub fn pick<S>(
+        &mut self,
+        lua: &Lua,
+        mut state: S,
+        mut selected: usize,
+        mut draw: impl FnMut(&mut Self, &mut S, &mut usize) -> Result<usize>,
+        mut on_key: impl FnMut(
+            &mut Self,
+            &Lua,
+            &mut S,
+            (KMod, KCode),
+            &mut usize,
+        ) -> Result<Option<PickerKey>>,
+        on_select: impl FnOnce(&mut Self, S, usize) -> Result<()>,
+    ) -> Result<()> {
+        loop {
+            let len = draw(self, &mut state, &mut selected)?;
+            let Some(event) = get_event(self) else {
+                continue;
+            };
+            self.redraw_after_resize(lua, &event)?;
+            let outcome = match self.list_mouse(&event, selected, len) {
+                ListMouse::Activate(item) => {
+                    selected = item;
+                    PickerKey::Pick
+                }
+                ListMouse::Select(item) => {
+                    selected = item;
+                    PickerKey::Stay
+                }
+                ListMouse::Dismiss => PickerKey::Cancel,
+                ListMouse::Ignore => {
+                    let Some(key) = key_event(&event, &mut self.macro_man) else {
+                        continue;
+                    };
+                    match on_key(self, lua, &mut state, key, &mut selected)? {
+                        Some(outcome) => outcome,
+                        None => match key {
+                            (KMod::NONE, KCode::Enter) => PickerKey::Pick,
+                            (KMod::NONE, KCode::Esc) => PickerKey::Cancel,
+                            // Move through the list
+                            (KMod::NONE, KCode::Up) | (KMod::SHIFT, KCode::BackTab) => {
+                                selected = selected.saturating_sub(1);
+                                PickerKey::Stay
+                            }
+                            (KMod::NONE, KCode::Down | KCode::Tab) => {
+                                if selected + 1 < len {
+                                    selected += 1;
+                                }
+                                PickerKey::Stay
+                            }
+                            _ => PickerKey::Stay,
+                        },
+                    }
+                }
+            };
+            match outcome {
+                PickerKey::Stay => (),
+                PickerKey::Pick => break,
+                PickerKey::Cancel => {
+                    self.needs_rerender = true;
+                    return Err(OxError::Cancelled);
+                }
+                PickerKey::Done => {
+                    self.needs_rerender = true;
+                    return Ok(());
+                }
+            }
+        }
+        self.needs_rerender = true;
+        on_select(self, state, selected)
+    }
+}
@@ -0,0 +1,136 @@
+/// Positions: where the cursor and view were left in each file, so that reopening a file
+/// (even in a later session) carries on from the same place
+use crate::config;
+use kaolinite::utils::{get_absolute_path, Loc};
+use kaolinite::Document;
+use std::fmt::Write;
+use std::path::PathBuf;
+
+use super::swap::cache_dir;
+use super::Editor;
+
+/// The most files positions are remembered for (the least recently closed are forgotten)
+const MAX_POSITIONS: usize = 500;
+
+/// Where the cursor and view were left in a file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Position {
+    /// The absolute path of the file
+    pub path: String,
+    /// Where the cursor was (as a character index into the line)
+    pub loc: Loc,
+    /// Where the view was scrolled to
+    pub offset: Loc,
+}
+
+/// Work out where the positions are kept
+fn positions_path() -> Option<PathBuf> {
+    Some(cache_dir()?.join("positions"))
+}
+
+/// Read in the remembered positions (most recently closed first)
+fn load_positions() -> Vec<Position> {
+    let Some(data) = positions_path().and_then(|p| std::fs::read_to_string(p).ok()) else {
+        return vec![];
+    };
+    data.lines()
+        .filter_map(|line| {
+            let mut fields = line.splitn(5, '\t');
+            let mut number = || fields.next()?.parse::<usize>().ok();
+            let (y, x, offset_y, offset_x) = (number()?, number()?, number()?, number()?);
+            Some(Position {
+                loc: Loc { x, y },
+                offset: Loc {
+                    x: offset_x,
+                    y: offset_y,
+                },
+                path: fields.next()?.to_string(),
+            })
+        })
+        .take(MAX_POSITIONS)
+        .collect()
+}
+
+/// Write out the remembered positions, one per line
+fn save_positions(positions: &[Position]) {
+    let Some(path) = positions_path() else {
+        return;
+    };
+    let mut data = String::new();
+    for Position { path, loc, offset } in positions {
+        let _ = writeln!(
+            data,
+            "{}\t{}\t{}\t{}\t{path}",
+            loc.y, loc.x, offset.y, offset.x
+        );
+    }
+    // Positions are only a convenience, so failing to store them isn't worth reporting
+    if let Some(parent) = path.parent() {
+        let _ = std::fs::create_dir_all(parent);
+    }
+    let _ = std::fs::write(path, data);
+}
+
+/// Find where a file was left, if it is remembered
+pub fn remembered_position(file_name: &str) -> Option<Position> {
+    let path = get_absolute_path(file_name)?;
+    load_positions().into_iter().find(|p| p.path == path)
+}
+
+/// Put the cursor and view of a document back where they were left
+/// (keeping them within the document, in case the file has got shorter since)
+pub fn restore_position(doc: &mut Document, position: &Position) {
+    let last = doc.len_lines().saturating_sub(1);
+    let y = position.loc.y.min(last);
+    // The view stays where it was as long as the cursor is still in it
+    // (and it isn't scrolled past the end of the document)
+    let lowest = (y + 1).saturating_sub(doc.size.h.max(1));
+    let highest = y.min(doc.len_lines().saturating_sub(doc.size.h));
+    doc.offset.y = position.offset.y.min(highest).max(lowest);
+    doc.offset.x = position.offset.x;
+    doc.load_to(doc.offset.y + doc.size.h + 1);
+    doc.move_to(&Loc {
+        x: position.loc.x,
+        y,
+    });
+    doc.old_cursor = doc.loc().x;
+}
+
+impl Editor {
+    /// Remember where the cursor and view are in a document, for when it is next opened
+    pub fn store_position_at(&mut self, ptr: &[usize], doc: usize) {
+        if !config!(self.config, document).remember_position || self.headless {
+            return;
+        }
+        let Ok(file) = self.file_at(ptr, doc) else {
+            return;
+        };
+        if file.scratch.is_some() || file.doc.binary.is_some() {
+            return;
+        }
+        let Some(path) = file.doc.file_name.as_deref().and_then(get_absolute_path) else {
+            return;
+        };
+        let position = Position {
+            path,
+            loc: file.doc.char_loc(),
+            offset: file.doc.offset,
+        };
+        let mut positions = load_positions();
+        positions.retain(|p| p.path != position.path);
+        positions.insert(0, position);
+        positions.truncate(MAX_POSITIONS);
+        save_positions(&positions);
+    }
+
+    /// Put a newly opened document back where it was left, if that is remembered
+    pub fn restore_position_of(&self, doc: &mut Document) {
+        if !config!(self.config, document).remember_position || self.headless {
+            return;
+        }
+        let position = doc.file_name.as_deref().and_then(remembered_position);
+        if let Some(position) = position {
+            restore_position(doc, &position);
+        }
+    }
+}
@@ -0,0 +1,285 @@
+/// Previewing markdown documents in a read-only pane beside them, kept up to date as they change
+use crate::config;
+use crate::config::SyntaxHighlighting as SH;
+use crate::error::Result;
+use crate::Feedback;
+use crossterm::style::{
+    Attribute, SetAttribute, SetBackgroundColor as Bg, SetForegroundColor as Fg,
+};
+use kaolinite::markdown::{render, Line, LineKind, Style};
+use kaolinite::utils::{get_file_name, get_path_identity, width_char};
+use synoptic::{Highlighter, TokOpt};
+
+use super::{Editor, FileLayout};
+
+/// The highlighted pieces of a line of code, along with the kind of token each is
+type Tokens = Vec<(String, Option<String>)>;
+
+/// A markdown document rendered for a preview pane
+#[derive(Debug, Clone, Default)]
+pub struct Preview {
+    /// The (canonical) path of the document being previewed
+    pub source: String,
+    /// The name of the document, shown at the top of the pane
+    name: String,
+    /// The rendered lines, along with the highlighted pieces of any lines of code
+    lines: Vec<(Line, Option<Tokens>)>,
+    /// The generation of the document and the width the lines were rendered for
+    rendered: Option<(u64, usize)>,
+    /// The first line in view
+    offset: usize,
+}
+
+/// Add as much of some text to a row as there is room for
+fn push_fit(row: &mut String, text: &str, room: &mut usize) {
+    for c in text.chars() {
+        let w = width_char(&c, 4);
+        if w > *room {
+            *room = 0;
+            return;
+        }
+        *room -= w;
+        row.push(c);
+    }
+}
+
+impl Editor {
+    /// Find a highlighter for the language given to a code block (by its name or extension)
+    fn code_highlighter(&self, language: &str) -> Option<Highlighter> {
+        let file_type = config!(self.config, document)
+            .file_types
+            .types
+            .iter()
+            .find(|t| {
+                t.name.eq_ignore_ascii_case(language) || t.extensions.iter().any(|e| e == language)
+            })
+            .cloned()?;
+        Some(file_type.get_highlighter(&self.config, 4))
+    }
+
+    /// Render markdown into lines no wider than `w`, highlighting the code blocks
+    fn render_markdown(&self, markdown: &str, w: usize) -> Vec<(Line, Option<Tokens>)> {
+        let mut result: Vec<(Line, Option<Tokens>)> = render(markdown, w)
+            .into_iter()
+            .map(|line| (line, None))
+            .collect();
+        // Each code block is highlighted as a whole, so things that span lines come out right
+        let mut y = 0;
+        while y < result.len() {
+            let LineKind::Code(language) = result[y].0.kind.clone() else {
+                y += 1;
+                continue;
+            };
+            let kind = LineKind::Code(language.clone());
+            let end = (y..result.len())
+                .find(|i| result[*i].0.kind != kind)
+                .unwrap_or(result.len());
+            if let Some(mut highlighter) = language.and_then(|l| self.code_highlighter(&l)) {
+                let code: Vec<String> = result[y..end].iter().map(|(l, _)| l.text()).collect();
+                highlighter.run(&code);
+                for (i, text) in code.iter().enumerate() {
+                    let tokens = highlighter
+                        .line(i, text)
+                        .into_iter()
+                        .map(|token| match token {
+                            TokOpt::Some(text, kind) => (text, Some(kind)),
+                            TokOpt::None(text) => (text, None),
+                        })
+                        .collect();
+                    result[y + i].1 = Some(tokens);
+                }
+            }
+            y = end;
+        }
+        result
+    }
+
+    /// Render the previews that are in view again if their documents have changed,
+    /// and scroll them to match where the cursor is in their documents
+    pub fn update_previews(&mut self) {
+        let panes: Vec<(Vec<usize>, usize, usize)> = self
+            .render_cache
+            .span
+            .iter()
+            .filter(|(ptr, _, _)| {
+                matches!(
+                    self.files.get_raw(ptr.clone()),
+                    Some(FileLayout::Preview(_))
+                )
+            })
+            .map(|(ptr, rows, cols)| (ptr.clone(), rows.len(), cols.len()))
+            .collect();
+        for (ptr, h, w) in panes {
+            let Some(FileLayout::Preview(preview)) = self.files.get_raw(ptr.clone()) else {
+                continue;
+            };
+            // Follow the pane being edited if it shows the document
+            let views = self.files.find_all(vec![], &preview.source);
+            let Some((at, idx)) = views
+                .iter()
+                .find(|(at, _)| at == &self.ptr)
+                .or(views.first())
+            else {
+                continue;
+            };
+            let Ok(fc) = self.file_at(at, *idx) else {
+                continue;
+            };
+            let (generation, cursor) = (fc.doc.generation, fc.doc.loc().y);
+            let wanted = Some((generation, w));
+            let lines = (preview.rendered != wanted)
+                .then(|| self.render_markdown(&fc.doc.file.to_string(), w.saturating_sub(2)));
+            let Some(FileLayout::Preview(preview)) = self.files.get_raw_mut(ptr) else {
+                continue;
+            };
+            if let Some(lines) = lines {
+                preview.lines = lines;
+                preview.rendered = wanted;
+            }
+            // Keep the part of the preview the cursor is at a third of the way down
+            let at = preview
+                .lines
+                .iter()
+                .position(|(line, _)| line.source >= cursor)
+                .unwrap_or(preview.lines.len());
+            preview.offset = at.saturating_sub(h.saturating_sub(1) / 3);
+        }
+    }
+
+    /// Render a row of a preview pane
+    #[allow(clippy::similar_names)]
+    pub fn render_preview(&self, ptr: &[usize], y: usize, w: usize, sh: &SH) -> Result<String> {
+        let colors = config!(self.config, colors);
+        let editor_bg = Bg(colors.editor_bg.to_color()?);
+        let editor_fg = Fg(colors.editor_fg.to_color()?);
+        let title_bg = Bg(colors.tab_active_bg.to_color()?);
+        let title_fg = Fg(colors.tab_active_fg.to_color()?);
+        drop(colors);
+        let reset = SetAttribute(Attribute::Reset);
+        let Some(FileLayout::Preview(preview)) = self.files.get_raw(ptr.to_vec()) else {
+            return Ok(format!("{editor_bg}{}", " ".repeat(w)));
+        };
+        let mut room = w;
+        // The top row says what is being previewed
+        if y == 0 {
+            let mut row = format!("{title_bg}{title_fg}");
+            push_fit(
+                &mut row,
+                &format!(" Preview of {}", preview.name),
+                &mut room,
+            );
+            return Ok(format!("{row}{}{reset}", " ".repeat(room)));
+        }
+        let Some((line, tokens)) = preview.lines.get(preview.offset + y - 1) else {
+            return Ok(format!("{editor_bg}{}", " ".repeat(w)));
+        };
+        let theme = |kind: &str| sh.get_theme(kind).map_or(editor_fg, Fg);
+        let mut row = format!("{reset}{editor_bg} ");
+        room = room.saturating_sub(1);
+        if let Some(tokens) = tokens {
+            for (text, kind) in tokens {
+                let fg = kind.as_deref().map_or(editor_fg, |kind| theme(kind));
+                row += &fg.to_string();
+                push_fit(&mut row, text, &mut room);
+            }
+        } else {
+            for (text, style) in &line.spans {
+                row += &format!("{reset}{editor_bg}");
+                let fg = match (&line.kind, style) {
+                    (LineKind::Heading(_), _) => {
+                        row += &SetAttribute(Attribute::Bold).to_string();
+                        theme("heading")
+                    }
+                    (LineKind::Quote | LineKind::Rule, _) => theme("quote"),
+                    (LineKind::Code(_), _) | (_, Style { code: true, .. }) => theme("block"),
+                    (_, Style { link: true, .. }) => {
+                        row += &SetAttribute(Attribute::Underlined).to_string();
+                        theme("link")
+                    }
+                    _ => editor_fg,
+                };
+                if style.strong {
+                    row += &SetAttribute(Attribute::Bold).to_string();
+                }
+                if style.emphasis {
+                    row += &SetAttribute(Attribute::Italic).to_string();
+                }
+                row += &fg.to_string();
+                push_fit(&mut row, text, &mut room);
+            }
+        }
+        Ok(format!("{row}{reset}{editor_bg}{}", " ".repeat(room)))
+    }
+
+    /// Where the preview of a document is (if it has one)
+    fn preview_of(&self, source: &str) -> Option<Vec<usize>> {
+        self.files
+            .all_previews(vec![])
+            .into_iter()
+            .find(|(_, previewing)| previewing == source)
+            .map(|(at, _)| at)
+    }
+
+    /// Show a preview of the markdown document being edited beside it,
+    /// or close its preview if it already has one
+    pub fn toggle_preview(&mut self) {
+        let Some(fc) = self.files.get(self.ptr.clone()) else {
+            return;
+        };
+        if fc.file_type.as_ref().map(|t| t.name.as_str()) != Some("Markdown") {
+            self.feedback = Feedback::Error("Only markdown documents can be previewed".to_string());
+            return;
+        }
+        let Some(file_name) = fc.doc.file_name.clone() else {
+            self.feedback = Feedback::Error("Save this document before previewing it".to_string());
+            return;
+        };
+        let source = get_path_identity(&file_name);
+        if let Some(at) = self.preview_of(&source) {
+            self.close_pane(&at);
+            return;
+        }
+        let preview = Preview {
+            source,
+            name: get_file_name(&file_name).unwrap_or(file_name),
+            ..Preview::default()
+        };
+        // The document being edited stays focused, on the left of the preview
+        let mut ptr = self
+            .files
+            .open_right(self.ptr.clone(), FileLayout::Preview(preview));
+        ptr.pop();
+        ptr.push(0);
+        self.ptr = ptr;
+        self.cache_old_ptr(&self.ptr.clone());
+    }
+
+    /// Close a pane that doesn't hold documents (like a preview), wherever it is
+    pub fn close_pane(&mut self, at: &[usize]) {
+        let focused = self.ptr == at;
+        self.files.remove(at.to_vec());
+        if focused {
+            self.ptr = self.files.new_pointer_position(&self.ptr);
+        } else if let Some((last, parent)) = at.split_last() {
+            // The panes after the one removed move back a place
+            let depth = parent.len();
+            if self.ptr.starts_with(parent) && self.ptr.get(depth).is_some_and(|p| p > last) {
+                self.ptr[depth] -= 1;
+            }
+        }
+        self.ptr = self.files.clean_up_multis(self.ptr.clone());
+        self.cache_old_ptr(&self.ptr.clone());
+    }
+
+    /// Close the previews of documents that are no longer open
+    pub fn close_orphaned_previews(&mut self) {
+        while let Some((at, _)) = self
+            .files
+            .all_previews(vec![])
+            .into_iter()
+            .find(|(_, source)| self.files.find_all(vec![], source).is_empty())
+        {
+            self.close_pane(&at);
+        }
+    }
+}
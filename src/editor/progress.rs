@@ -0,0 +1,162 @@
+/// Progress of long operations (like replacing everything in a huge file or waiting for one
+/// to be read in): shown in the feedback line as they go, and stopped by escape or ctrl + c
+use crate::config;
+use crate::config::Config;
+use crate::ui::{Feedback, Terminal};
+use crossterm::event::{
+    poll, read, Event as CEvent, KeyCode as KCode, KeyEvent, KeyEventKind, KeyModifiers as KMod,
+};
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use super::Editor;
+
+/// How often the terminal is checked for keys (and the progress drawn again)
+const PUMP_PERIOD: Duration = Duration::from_millis(50);
+/// Operations quicker than this finish without their progress ever being shown
+const SHOW_AFTER: Duration = Duration::from_millis(100);
+/// The frames of the spinner shown next to the percentage
+const SPINNER: [char; 10] = ['⠋', '⠙', '⠹', '⠸', '⠼', '⠴', '⠦', '⠧', '⠇', '⠏'];
+
+/// The long operations under way. Operations can start others as part of them,
+/// in which case the innermost one is shown, and stopping one stops them all
+#[derive(Debug, Default)]
+pub struct Progress {
+    /// What each operation is doing, with how far through it is (from 0 to 1)
+    steps: Vec<(String, f64)>,
+    /// When the outermost operation started
+    started: Option<Instant>,
+    /// When the terminal was last checked for keys
+    pumped: Option<Instant>,
+    frame: usize,
+    /// Whether the operations have been asked to stop (until the outermost one finishes)
+    pub cancelled: bool,
+}
+
+/// Whether an event asks for the operation under way to stop
+fn is_cancel(event: &CEvent) -> bool {
+    matches!(
+        event,
+        CEvent::Key(
+            KeyEvent {
+                code: KCode::Esc,
+                modifiers: KMod::NONE,
+                kind: KeyEventKind::Press,
+                ..
+            } | KeyEvent {
+                code: KCode::Char('c'),
+                modifiers: KMod::CONTROL,
+                kind: KeyEventKind::Press,
+                ..
+            }
+        )
+    )
+}
+
+impl Progress {
+    /// Note that an operation has started
+    pub fn start(&mut self, label: &str) {
+        if self.steps.is_empty() {
+            self.started = Some(Instant::now());
+            self.cancelled = false;
+        }
+        self.steps.push((label.to_string(), 0.0));
+    }
+
+    /// Note that the innermost operation has finished (or stopped)
+    pub fn finish(&mut self) {
+        self.steps.pop();
+        if self.steps.is_empty() {
+            self.started = None;
+            self.pumped = None;
+            self.cancelled = false;
+        }
+    }
+
+    /// Note how far through the innermost operation is, drawing it and checking for keys
+    /// every so often. Keys other than the ones that stop it are kept to be dealt with after.
+    /// Returns whether the operation should carry on
+    pub fn tick(
+        &mut self,
+        fraction: f64,
+        terminal: &mut Terminal,
+        pending: &mut VecDeque<CEvent>,
+        config: &Config,
+        headless: bool,
+    ) -> bool {
+        if let Some(step) = self.steps.last_mut() {
+            step.1 = fraction.clamp(0.0, 1.0);
+        }
+        // Keys read ahead of time count too
+        if let Some(at) = pending.iter().position(is_cancel) {
+            pending.remove(at);
+            self.cancelled = true;
+        }
+        if self.cancelled || headless {
+            return !self.cancelled;
+        }
+        if self.pumped.is_some_and(|at| at.elapsed() < PUMP_PERIOD) {
+            return true;
+        }
+        self.pumped = Some(Instant::now());
+        while let Ok(true) = poll(Duration::ZERO) {
+            let Ok(event) = read() else {
+                break;
+            };
+            if is_cancel(&event) {
+                self.cancelled = true;
+            } else {
+                pending.push_back(event);
+            }
+        }
+        if self.started.is_some_and(|at| at.elapsed() >= SHOW_AFTER) {
+            self.draw(terminal, config);
+        }
+        !self.cancelled
+    }
+
+    /// Show the innermost operation in the feedback line
+    #[allow(clippy::cast_possible_truncation, clippy::cast_sign_loss)]
+    fn draw(&mut self, terminal: &mut Terminal, config: &Config) {
+        let Some((label, fraction)) = self.steps.last() else {
+            return;
+        };
+        let Ok(size) = terminal.size() else {
+            return;
+        };
+        self.frame = (self.frame + 1) % SPINNER.len();
+        let percent = (fraction * 100.0) as usize;
+        let spinner = SPINNER[self.frame];
+        let feedback = Feedback::Info(format!("{spinner} {label}: {percent}% (esc to stop)"));
+        if let Ok(line) = feedback.render(&config!(config, colors), size.w) {
+            terminal.draw_line(size.h, line);
+            let _ = terminal.flush();
+        }
+    }
+}
+
+impl Editor {
+    /// Start a long operation, which shows its progress as it goes and can be stopped.
+    /// Each call needs a matching `finish` (operations can be nested)
+    pub fn start_progress(&mut self, label: &str) {
+        self.progress.start(label);
+    }
+
+    /// Note how far through the innermost operation is (from 0 to 1),
+    /// returning false if it has been asked to stop
+    pub fn tick(&mut self, fraction: f64) -> bool {
+        self.progress.tick(
+            fraction,
+            &mut self.terminal,
+            &mut self.pending_events,
+            &self.config,
+            self.headless,
+        )
+    }
+
+    /// Finish the innermost long operation
+    pub fn finish(&mut self) {
+        self.progress.finish();
+        self.needs_rerender = true;
+    }
+}
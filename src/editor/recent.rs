@@ -0,0 +1,170 @@
+/// Keeping track of recently opened files between sessions
+use crate::error::{OxError, Result};
+use crate::Feedback;
+use kaolinite::utils::{get_absolute_path, Loc};
+use mlua::Lua;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+use super::{data_dir, Editor};
+
+/// The most files remembered as recently opened
+const MAX_RECENT: usize = 50;
+
+/// A file that was opened recently, along with where the cursor was left in it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecentFile {
+    /// The absolute path of the file
+    pub path: String,
+    /// Where the cursor was when the file was last closed
+    pub loc: Loc,
+}
+
+/// Work out where the list of recently opened files is kept
+pub fn recent_path() -> Option<PathBuf> {
+    Some(data_dir()?.join("recent"))
+}
+
+/// Read in the recently opened files (most recent first), leaving out any that no longer exist
+pub fn load_recent() -> Vec<RecentFile> {
+    let Some(data) = recent_path().and_then(|p| std::fs::read_to_string(p).ok()) else {
+        return vec![];
+    };
+    data.lines()
+        .filter_map(|line| {
+            let mut fields = line.splitn(3, '\t');
+            let y = fields.next()?.parse().ok()?;
+            let x = fields.next()?.parse().ok()?;
+            let path = fields.next()?.to_string();
+            Some(RecentFile {
+                path,
+                loc: Loc { x, y },
+            })
+        })
+        .filter(|entry| Path::new(&entry.path).is_file())
+        .take(MAX_RECENT)
+        .collect()
+}
+
+/// Write out the recently opened files, one per line
+fn save_recent(recent: &[RecentFile]) {
+    let Some(path) = recent_path() else {
+        return;
+    };
+    let mut data = String::new();
+    for RecentFile { path, loc } in recent {
+        let _ = writeln!(data, "{}\t{}\t{path}", loc.y, loc.x);
+    }
+    // The list is only a convenience, so failing to store it isn't worth reporting
+    if let Some(parent) = path.parent() {
+        let _ = std::fs::create_dir_all(parent);
+    }
+    let _ = std::fs::write(path, data);
+}
+
+impl Editor {
+    /// Move a file to the top of the recently opened files and store the list
+    /// (the cursor position it was last left at is kept if `loc` isn't given)
+    fn remember_recent(&mut self, path: String, loc: Option<Loc>) {
+        let old = self.recent.iter().position(|e| e.path == path);
+        let old = old.map(|idx| self.recent.remove(idx));
+        let loc = loc.or(old.map(|e| e.loc)).unwrap_or_default();
+        self.recent.insert(0, RecentFile { path, loc });
+        self.recent.truncate(MAX_RECENT);
+        save_recent(&self.recent);
+    }
+
+    /// Record that a file has just been opened
+    pub fn note_opened(&mut self, file_name: &str) {
+        if let Some(path) = get_absolute_path(file_name) {
+            self.remember_recent(path, None);
+        }
+    }
+
+    /// Record that a document is about to be closed, so it can be reopened later
+    /// (where it was left)
+    pub fn note_closed(&mut self, ptr: &[usize], doc: usize) {
+        self.store_position_at(ptr, doc);
+        let Ok(file) = self.file_at(ptr, doc) else {
+            return;
+        };
+        if file.scratch.is_some() {
+            return;
+        }
+        let loc = file.doc.char_loc();
+        let Some(path) = file.doc.file_name.as_deref().and_then(get_absolute_path) else {
+            return;
+        };
+        self.closed.push(RecentFile {
+            path: path.clone(),
+            loc,
+        });
+        self.remember_recent(path, Some(loc));
+    }
+
+    /// Open a recently opened file, putting the cursor back where it was left
+    fn open_recent(&mut self, entry: &RecentFile) -> Result<()> {
+        match self.open(&entry.path) {
+            Ok(()) => self.next(),
+            // The file is already open, so it has just been switched to
+            Err(OxError::AlreadyOpen { .. }) => return Ok(()),
+            Err(err) => return Err(err),
+        }
+        self.update_cwd();
+        if let Some(doc) = self.try_doc_mut() {
+            doc.move_to(&entry.loc);
+        }
+        Ok(())
+    }
+
+    /// Open the file that was most recently opened
+    pub fn open_most_recent(&mut self) -> Result<()> {
+        // Files may have been removed since the list was loaded
+        self.recent.retain(|e| Path::new(&e.path).is_file());
+        let Some(entry) = self.recent.first().cloned() else {
+            self.feedback = Feedback::Warning("There are no recently opened files".to_string());
+            return Ok(());
+        };
+        self.open_recent(&entry)
+    }
+
+    /// Reopen the document that was most recently closed
+    pub fn reopen_closed(&mut self) -> Result<()> {
+        // Skip over files that have since been reopened or removed
+        while let Some(entry) = self.closed.pop() {
+            let open = self.already_open(&entry.path).is_some();
+            if !open && Path::new(&entry.path).is_file() {
+                return self.open_recent(&entry);
+            }
+        }
+        self.feedback = Feedback::Warning("There are no closed documents to reopen".to_string());
+        Ok(())
+    }
+
+    /// Open a picker listing the recently opened files, where enter opens the selected file
+    pub fn recent(&mut self, lua: &Lua) -> Result<()> {
+        // Files may have been removed since the list was loaded
+        self.recent.retain(|e| Path::new(&e.path).is_file());
+        if self.recent.is_empty() {
+            self.feedback = Feedback::Warning("There are no recently opened files".to_string());
+            return Ok(());
+        }
+        let items: Vec<String> = self.recent.iter().map(|e| e.path.clone()).collect();
+        let hint = "Recent files: enter to open, esc to cancel";
+        self.pick(
+            lua,
+            (),
+            0,
+            |editor, (), selected| {
+                editor.render_list(&items, *selected, hint)?;
+                Ok(items.len())
+            },
+            // Just the keys every list has
+            |_, _, (), _, _| Ok(None),
+            |editor, (), selected| {
+                let entry = editor.recent[selected].clone();
+                editor.open_recent(&entry)
+            },
+        )
+    }
+}
@@ -0,0 +1,203 @@
+/// Reading the configuration file again, and switching themes, while the editor is running
+use crate::config::{Config, ConfigError, THEMES};
+use crate::error::{OxError, Result};
+use crate::{config, Feedback};
+use mlua::{FromLua, Function, Lua, Result as LuaResult, Value};
+use std::time::{Duration, Instant, SystemTime};
+use synoptic::Highlighter;
+
+use super::{Editor, FileTypes};
+
+/// How often the configuration file is checked for changes
+const WATCH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// When the configuration file at a path was last changed
+pub fn config_modified(path: &str) -> Option<SystemTime> {
+    let path = shellexpand::full(path).ok()?;
+    std::fs::metadata(path.as_ref()).ok()?.modified().ok()
+}
+
+/// Take a copy of every global variable, so they can be put back if reading the configuration fails
+fn save_globals(lua: &Lua) -> LuaResult<Vec<(Value, Value)>> {
+    lua.globals().pairs().collect()
+}
+
+/// Put the global variables back to how they were when they were saved
+fn restore_globals(lua: &Lua, saved: Vec<(Value, Value)>) -> LuaResult<()> {
+    let globals = lua.globals();
+    let keys: Vec<Value> = globals
+        .pairs::<Value, Value>()
+        .map(|pair| pair.map(|(key, _)| key))
+        .collect::<LuaResult<_>>()?;
+    for key in keys {
+        globals.raw_set(key, Value::Nil)?;
+    }
+    for (key, value) in saved {
+        globals.raw_set(key, value)?;
+    }
+    Ok(())
+}
+
+impl Editor {
+    /// Read the file types defined in the configuration file
+    pub fn load_file_types(&mut self, lua: &Lua) -> Result<()> {
+        let file_types = lua
+            .globals()
+            .get("file_types")
+            .unwrap_or(Value::Table(lua.create_table()?));
+        let file_types = FileTypes::from_lua(file_types, lua).unwrap_or_default();
+        self.config
+            .document
+            .borrow_mut::<config::Document>()
+            .unwrap()
+            .file_types = file_types;
+        Ok(())
+    }
+
+    /// Read the configuration file again, bringing the open documents and the display up to date.
+    /// It is read into a fresh configuration, which only takes the place of the current one
+    /// if it runs without errors, so a mistake in the file leaves the editor as it was
+    pub fn reload_config(&mut self, lua: &Lua) -> Result<()> {
+        self.config_modified = config_modified(&self.config_path);
+        let saved = save_globals(lua)?;
+        let fresh = self.config.fresh(lua)?;
+        fresh.expose(lua)?;
+        match Config::read(&self.config_path, lua) {
+            // Without a configuration file, the defaults are used
+            Ok(()) | Err(OxError::Config { .. }) => (),
+            Err(err) => {
+                restore_globals(lua, saved)?;
+                return Err(err);
+            }
+        }
+        self.terminal.config = fresh.terminal.clone();
+        self.config = fresh;
+        self.safe_mode = false;
+        self.config_error = None;
+        self.load_file_types(lua)?;
+        self.keep_zen();
+        self.push_down = usize::from(config!(self.config, tab_line).enabled);
+        self.rebuild_highlighters();
+        // Anything on screen could look different now
+        self.render_budget.forget();
+        self.terminal.invalidate();
+        self.needs_rerender = true;
+        self.feedback = match self.config.take_warnings() {
+            Some(warnings) => Feedback::Warning(warnings),
+            None => Feedback::Info("Configuration reloaded".to_string()),
+        };
+        Ok(())
+    }
+
+    /// Set up the highlighter of every open document again, for after the syntax rules change
+    fn rebuild_highlighters(&mut self) {
+        let tab_width = config!(self.config, document).tab_width;
+        for (ptr, idx) in self.files.all_documents(vec![]) {
+            let Some(file) = self
+                .files
+                .get_atom_mut(ptr)
+                .and_then(|(fcs, _)| fcs.get_mut(idx))
+            else {
+                continue;
+            };
+            // Binary files are shown as a hex preview, which isn't highlighted
+            if file.doc.binary.is_some() {
+                continue;
+            }
+            // Documents without a file name keep the file type they were given
+            let document = config!(self.config, document);
+            let file_types = &document.file_types;
+            let file_type = file_types.identify(&mut file.doc).or_else(|| {
+                let name = &file.file_type.as_ref()?.name;
+                file_types.get_name(name)
+            });
+            file.highlighter = file_type.as_ref().map_or(Highlighter::new(tab_width), |t| {
+                t.get_highlighter(&self.config, tab_width)
+            });
+            file.highlighter.run(&file.doc.lines);
+            file.file_type = file_type;
+        }
+    }
+
+    /// Switch to one of the built-in themes, or to the one after the current theme if none is given
+    pub fn set_theme(&mut self, lua: &Lua, name: Option<&str>) -> Result<()> {
+        let name = match name {
+            Some(name) => name.to_string(),
+            None => {
+                let current = config!(self.config, colors).theme.clone();
+                let at = THEMES.iter().position(|(n, _)| *n == current);
+                let next = at.map_or(0, |at| (at + 1) % THEMES.len());
+                THEMES[next].0.to_string()
+            }
+        };
+        if !THEMES.iter().any(|(n, _)| *n == name) {
+            let names: Vec<&str> = THEMES.iter().map(|(n, _)| *n).collect();
+            self.feedback = Feedback::Error(format!(
+                "There is no theme called {name}, the themes are {}",
+                names.join(", ")
+            ));
+            return Ok(());
+        }
+        let apply: Function = lua
+            .load("return function(colors, name) colors.theme = name end")
+            .eval()?;
+        apply.call::<()>((self.config.colors.clone(), name.clone()))?;
+        // Every colour on screen could be different now
+        self.render_budget.forget();
+        self.terminal.invalidate();
+        self.needs_rerender = true;
+        self.feedback = Feedback::Info(format!("Switched to the {name} theme"));
+        Ok(())
+    }
+
+    /// Report a problem reading the configuration file, keeping the full details
+    /// (with the stack traceback) to show with the `config_error` command
+    pub fn report_config_error(&mut self, err: OxError) {
+        match err {
+            OxError::Lua(err) => {
+                let error = ConfigError::new(&err, &self.config_path);
+                self.feedback = Feedback::Error(format!(
+                    "{} (run 'config_error' for details)",
+                    error.summary
+                ));
+                self.config_error = Some(error.report);
+            }
+            err => self.feedback = Feedback::Error(err.to_string()),
+        }
+    }
+
+    /// Show the full report of the last error in the configuration file in a read only buffer
+    pub fn show_config_error(&mut self) -> Result<()> {
+        let Some(report) = self.config_error.clone() else {
+            self.feedback =
+                Feedback::Info("There are no errors in the configuration file".to_string());
+            return Ok(());
+        };
+        self.open_from_string(report, None)?;
+        self.next();
+        if let Some(file) = self.files.get_mut(self.ptr.clone()) {
+            file.doc.info.read_only = true;
+            file.scratch = Some("config error".to_string());
+        }
+        Ok(())
+    }
+
+    /// Read the configuration file again if it has been changed since it was last read
+    /// (and watching it is enabled), returning true if it was read
+    pub fn config_idle_tick(&mut self, lua: &Lua) -> bool {
+        if !config!(self.config, terminal).watch_config
+            || self.config_checked.elapsed() < WATCH_INTERVAL
+        {
+            return false;
+        }
+        self.config_checked = Instant::now();
+        let modified = config_modified(&self.config_path);
+        if modified.is_none() || modified == self.config_modified {
+            return false;
+        }
+        if let Err(err) = self.reload_config(lua) {
+            self.report_config_error(err);
+        }
+        true
+    }
+}
@@ -0,0 +1,67 @@
+/// Carrying out the requests other shells send to this instance with `ox --remote`
+use crate::cli::{FileToOpen, Position};
+use crate::config;
+use crate::error::{OxError, Result};
+use crate::remote::{socket_path, Connection, RemoteControl};
+use crate::ui::Feedback;
+use kaolinite::utils::file_or_dir;
+use std::path::Path;
+
+use super::Editor;
+
+impl Editor {
+    /// Start listening for requests from other shells, if `terminal.remote_control` is on
+    /// (another instance already listening in this session keeps them)
+    pub fn start_remote(&mut self) {
+        if !config!(self.config, terminal).remote_control {
+            return;
+        }
+        match socket_path().and_then(|path| RemoteControl::start_at(&path)) {
+            Ok(remote) => self.remote = remote,
+            Err(err) => {
+                self.feedback = Feedback::Warning(format!("Remote control failed to start: {err}"));
+            }
+        }
+    }
+
+    /// Take a request sent by another shell, if one has come in
+    pub fn poll_remote(&mut self) -> Option<Connection> {
+        self.remote.as_ref()?.poll()
+    }
+
+    /// Open files asked for by another shell, moving to the last of them
+    /// (names are taken from the directory the request was made in)
+    pub fn remote_open(&mut self, cwd: &Path, files: &[FileToOpen]) -> Result<String> {
+        for file in files {
+            let name = cwd.join(&file.name).to_string_lossy().to_string();
+            // Directories are opened in the file tree
+            if file_or_dir(&name) == "directory" {
+                self.open_file_tree_at(&name, false);
+                continue;
+            }
+            match self.open_or_new(name) {
+                Ok(()) => self.next(),
+                // Files that are already open are moved to instead
+                Err(OxError::AlreadyOpen { .. }) => (),
+                Err(err) => return Err(err),
+            }
+            self.update_cwd();
+            if let Some(Position { line, column }) = file.position {
+                self.remote_goto(line, column);
+            }
+        }
+        self.needs_rerender = true;
+        Ok(match files {
+            [file] => format!("Opened {}", file.name),
+            files => format!("Opened {} files", files.len()),
+        })
+    }
+
+    /// Move to a line (and column) of the document being edited, as asked by another shell
+    pub fn remote_goto(&mut self, line: usize, column: Option<usize>) {
+        if let Some((_, idx)) = self.files.get_atom(self.ptr.clone()) {
+            self.go_to_position(idx, line, column);
+        }
+        self.needs_rerender = true;
+    }
+}
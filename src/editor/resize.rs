@@ -0,0 +1,129 @@
+/// Resizing splits with the keyboard and by dragging the dividers between them
+use crate::config;
+use crossterm::event::{KeyModifiers, MouseButton, MouseEvent, MouseEventKind};
+use std::ops::Range;
+
+use super::{Editor, FileLayout};
+
+/// The fewest columns a pane can be resized down to
+pub const MIN_PANE_WIDTH: usize = 10;
+/// The fewest rows a pane can be resized down to
+pub const MIN_PANE_HEIGHT: usize = 3;
+
+impl Editor {
+    /// Find the columns (or rows) the split at `at` took up in the last frame
+    fn split_extent(&self, at: &[usize], side_by_side: bool) -> Option<Range<usize>> {
+        let ranges = self
+            .render_cache
+            .span
+            .iter()
+            .filter(|(ptr, _, _)| ptr.starts_with(at))
+            .map(|(_, rows, cols)| if side_by_side { cols } else { rows });
+        let start = ranges.clone().map(|r| r.start).min()?;
+        let end = ranges.map(|r| r.end).max()?;
+        Some(start..end)
+    }
+
+    /// The smallest proportion a child of the split at `at` may have
+    #[allow(clippy::cast_precision_loss)]
+    fn min_proportion(&self, at: &[usize], side_by_side: bool) -> f64 {
+        let min = if side_by_side {
+            MIN_PANE_WIDTH
+        } else {
+            MIN_PANE_HEIGHT
+        };
+        self.split_extent(at, side_by_side)
+            .map_or(0.0, |extent| min as f64 / extent.len().max(1) as f64)
+    }
+
+    /// Grow or shrink the focused pane's "width" or "height",
+    /// by `amount` or by the configured step
+    pub fn resize_split(&mut self, grow: bool, direction: &str, amount: Option<f64>) {
+        let amount = amount.unwrap_or(config!(self.config, terminal).split_step);
+        let ptr = self.ptr.clone();
+        match direction {
+            "width" => {
+                let Some((parent, _)) = self.files.get_sidebyside_parent(ptr.clone()) else {
+                    return;
+                };
+                let min = self.min_proportion(&parent, true);
+                if grow {
+                    self.files.grow_width(&ptr, amount, min);
+                } else {
+                    self.files.shrink_width(&ptr, amount, min);
+                }
+            }
+            "height" => {
+                let Some((parent, _)) = self.files.get_toptobottom_parent(ptr.clone()) else {
+                    return;
+                };
+                let min = self.min_proportion(&parent, false);
+                if grow {
+                    self.files.grow_height(&ptr, amount, min);
+                } else {
+                    self.files.shrink_height(&ptr, amount, min);
+                }
+            }
+            _ => (),
+        }
+    }
+
+    /// Give every split an equal share of the screen
+    pub fn equalise_splits(&mut self) {
+        self.files.equalise();
+    }
+
+    /// Find the side by side split whose divider is at a point on screen,
+    /// along with which child the divider comes after
+    fn divider_at(&self, col: usize, row: usize) -> Option<(Vec<usize>, usize)> {
+        let span = &self.render_cache.span;
+        if !FileLayout::is_empty_at(row, col, span) {
+            return None;
+        }
+        let (left, _, _) = span
+            .iter()
+            .find(|(_, rows, cols)| rows.contains(&row) && cols.end == col)?;
+        // The divider belongs to the innermost split where the pane isn't the last child
+        (0..left.len()).rev().find_map(|depth| {
+            let parent = left[..depth].to_vec();
+            let child = left[depth];
+            match self.files.get_raw(parent.clone()) {
+                Some(FileLayout::SideBySide(layouts)) if child + 1 < layouts.len() => {
+                    Some((parent, child))
+                }
+                _ => None,
+            }
+        })
+    }
+
+    /// Let the mouse drag the dividers between side by side panes,
+    /// returning true if the event was used up
+    #[allow(clippy::cast_precision_loss)]
+    pub fn split_drag_mouse(&mut self, event: MouseEvent) -> bool {
+        if event.modifiers != KeyModifiers::NONE {
+            return false;
+        }
+        let (col, row) = (event.column as usize, event.row as usize);
+        match event.kind {
+            MouseEventKind::Down(MouseButton::Left) => {
+                self.split_drag = self.divider_at(col, row);
+                self.split_drag.is_some()
+            }
+            MouseEventKind::Drag(MouseButton::Left) => {
+                let Some((parent, child)) = self.split_drag.clone() else {
+                    return false;
+                };
+                if let Some(extent) = self.split_extent(&parent, true) {
+                    let width = extent.len().max(1) as f64;
+                    // The divider sits just after the last column of the child before it
+                    let fraction = (col + 1).saturating_sub(extent.start) as f64 / width;
+                    let min = self.min_proportion(&parent, true);
+                    self.files.move_divider(parent, child, fraction, min);
+                }
+                true
+            }
+            MouseEventKind::Up(MouseButton::Left) => self.split_drag.take().is_some(),
+            _ => false,
+        }
+    }
+}
@@ -1,17 +1,68 @@
 /// Functions for searching and replacing
 use crate::error::{OxError, Result};
 use crate::events::wait_for_event_hog;
-use crate::ui::{key_event, size};
+use crate::ui::{key_event, Feedback};
 use crate::{config, display};
 use crossterm::{
     event::{KeyCode as KCode, KeyModifiers as KMod},
-    style::{Attribute, Print, SetAttribute, SetBackgroundColor as Bg},
+    style::{Attribute, SetAttribute, SetBackgroundColor as Bg},
 };
+use kaolinite::searching::{describe_char, loose_differences, Match};
 use kaolinite::utils::{Loc, Size};
+use kaolinite::Document;
 use mlua::Lua;
 
 use super::Editor;
 
+/// The most matches that are counted before asking to replace them all
+const MAX_COUNTED: usize = 100_000;
+
+/// The selection a search or replacement is confined to
+#[derive(Debug, Clone, Copy)]
+struct Scope {
+    /// Where the selection starts and ends (where x is a character index)
+    start: Loc,
+    end: Loc,
+    /// Whether the cursor was at the start of the selection, rather than the end
+    reversed: bool,
+}
+
+impl Scope {
+    /// Take the selection of a document (if there is one)
+    fn of(doc: &Document) -> Option<Self> {
+        if doc.is_selection_empty() {
+            return None;
+        }
+        let (start, end) = doc.selection_loc_bound();
+        let reversed = doc.char_loc() == start;
+        Some(Self {
+            start,
+            end,
+            reversed,
+        })
+    }
+
+    /// Select the text in the scope again, the same way round as it was
+    fn restore(&self, doc: &mut Document) {
+        let (anchor, head) = if self.reversed {
+            (self.end, self.start)
+        } else {
+            (self.start, self.end)
+        };
+        doc.move_to(&anchor);
+        doc.select_to(&head);
+    }
+
+    /// The words shown in the status line to make clear the search is confined to the selection
+    fn describe(scope: Option<Self>) -> &'static str {
+        if scope.is_some() {
+            " (in selection)"
+        } else {
+            ""
+        }
+    }
+}
+
 impl Editor {
     /// Use search feature
     pub fn search(&mut self, lua: &Lua) -> Result<()> {
@@ -20,13 +71,103 @@ impl Editor {
             return Ok(());
         }
         // Gather data
-        let editor_bg = Bg(config!(self.config, colors).editor_bg.to_color()?);
         let cache = self.try_doc().unwrap().char_loc();
+        // With text selected, only the selection is searched
+        let scope = Scope::of(self.try_doc().unwrap());
+        let within = Scope::describe(scope);
+        let target = self.search_target(lua, within, &cache, scope)?;
+
+        // If no target is given, do nothing
+        if target.is_empty() {
+            return Ok(());
+        }
+
+        // If there are no exact matches, check for ones that only look identical
+        // (only for plain text, a pattern can't be loosened)
+        let plain = regex::escape(&target) == target;
+        if plain && !self.has_exact_match(&target, scope) {
+            let mut loose = self.try_doc().unwrap().loose_matches(&target);
+            if let Some(Scope { start, end, .. }) = scope {
+                loose.retain(|m| {
+                    let finish = Loc::at(m.loc.x + m.text.chars().count(), m.loc.y);
+                    m.loc >= start && finish <= end
+                });
+            }
+            if !loose.is_empty() {
+                return self.loose_search(lua, &target, &loose, &cache, scope);
+            }
+            self.ring_bell();
+        }
+
+        // Main body of the search feature
+        let mut done = false;
+        let mut cancelled = false;
+        let status = config!(self.config, status_line)
+            .search
+            .replace("{within}", within);
+        // Enter into search menu
+        while !done {
+            let Size { w, h } = self.terminal.size()?;
+            // Rerender the editor
+            self.needs_rerender = true;
+            self.render(lua)?;
+            // Render custom status line with mode information
+            self.render_menu_status(&status, w, h)?;
+            // Move back to correct cursor position
+            if let Some(Loc { x, y }) = self.cursor_position() {
+                self.terminal.goto(x, y);
+                self.terminal.show_cursor();
+            } else {
+                self.terminal.hide_cursor();
+            }
+            self.terminal.flush()?;
+            // Handle events
+            if let Some((modifiers, code)) =
+                key_event(&wait_for_event_hog(self), &mut self.macro_man)
+            {
+                match (modifiers, code) {
+                    // On return or escape key, exit menu
+                    (KMod::NONE, KCode::Enter) => done = true,
+                    (KMod::NONE, KCode::Esc) => (done, cancelled) = (true, true),
+                    // On left key, move to the previous match in the document
+                    (KMod::NONE, KCode::Left) => {
+                        if self.jump_to_match(&target, false, scope).is_none() {
+                            self.ring_bell();
+                        }
+                    }
+                    // On right key, move to the next match in the document
+                    (KMod::NONE, KCode::Right) => {
+                        if self.jump_to_match(&target, true, scope).is_none() {
+                            self.ring_bell();
+                        }
+                    }
+                    _ => (),
+                }
+            }
+            self.update_highlighter();
+        }
+        if cancelled {
+            self.leave_search(&cache, scope);
+        } else {
+            self.try_doc_mut().unwrap().cancel_selection();
+        }
+        Ok(())
+    }
+
+    /// Prompt for a search term, moving to the first match as it is typed
+    fn search_target(
+        &mut self,
+        lua: &Lua,
+        within: &str,
+        cache: &Loc,
+        scope: Option<Scope>,
+    ) -> Result<String> {
+        let editor_bg = Bg(config!(self.config, colors).editor_bg.to_color()?);
         // Prompt for a search term
         let mut target = String::new();
         let mut done = false;
         while !done {
-            let Size { w, h } = size()?;
+            let Size { w, h } = self.terminal.size()?;
             // Rerender the editor
             self.needs_rerender = true;
             self.render(lua)?;
@@ -35,7 +176,7 @@ impl Editor {
             display!(
                 self,
                 editor_bg,
-                "Search: ",
+                format!("Search{within}: "),
                 target.clone(),
                 "│",
                 " ".to_string().repeat(w)
@@ -56,48 +197,90 @@ impl Editor {
                     (KMod::NONE, KCode::Enter) => done = true,
                     // Cancel operation
                     (KMod::NONE, KCode::Esc) => {
-                        self.try_doc_mut().unwrap().move_to(&cache);
-                        self.try_doc_mut().unwrap().cancel_selection();
+                        self.leave_search(cache, scope);
                         return Err(OxError::Cancelled);
                     }
                     // Remove from the input string if the user presses backspace
                     (KMod::NONE, KCode::Backspace) => {
                         target.pop();
-                        self.try_doc_mut().unwrap().move_to(&cache);
-                        self.next_match(&target);
+                        self.restart_search(&target, cache, scope);
                     }
                     // Add to the input string if the user presses a character
                     (KMod::NONE | KMod::SHIFT, KCode::Char(c)) => {
                         target.push(c);
-                        self.try_doc_mut().unwrap().move_to(&cache);
-                        self.next_match(&target);
+                        self.restart_search(&target, cache, scope);
                     }
                     _ => (),
                 }
             }
         }
+        Ok(target)
+    }
 
-        // If no target is given, do nothing
-        if target.is_empty() {
-            return Ok(());
+    /// Go back to where a search started, selecting the text it was confined to again
+    fn leave_search(&mut self, cache: &Loc, scope: Option<Scope>) {
+        let Some(doc) = self.try_doc_mut() else {
+            return;
+        };
+        if let Some(scope) = scope {
+            scope.restore(doc);
+        } else {
+            doc.move_to(cache);
         }
+        self.update_highlighter();
+    }
 
-        // Main body of the search feature
-        let mut done = false;
-        let Size { w, h } = size()?;
-        // Enter into search menu
-        while !done {
+    /// Look for the first match of a search target again, after it has been changed
+    fn restart_search(&mut self, target: &str, cache: &Loc, scope: Option<Scope>) {
+        let Some(doc) = self.try_doc_mut() else {
+            return;
+        };
+        doc.move_to(&scope.map_or(*cache, |s| s.start));
+        self.jump_to_match(target, true, scope);
+    }
+
+    /// Determine whether a search target matches anywhere in the current document
+    /// (or in the selection it is confined to)
+    fn has_exact_match(&mut self, target: &str, scope: Option<Scope>) -> bool {
+        if let Some(Scope { start, end, .. }) = scope {
+            return self
+                .try_doc_mut()
+                .is_some_and(|doc| !doc.matches_within(target, start, end).is_empty());
+        }
+        if let Some(doc) = self.try_doc_mut() {
+            let loc = doc.char_loc();
+            doc.move_to(&Loc::at(0, 0));
+            let found = doc.next_match(target, 0).is_some();
+            doc.move_to(&loc);
+            found
+        } else {
+            false
+        }
+    }
+
+    /// Let the user jump through matches that differ only in normalisation or invisible characters
+    fn loose_search(
+        &mut self,
+        lua: &Lua,
+        target: &str,
+        matches: &[Match],
+        cache: &Loc,
+        scope: Option<Scope>,
+    ) -> Result<()> {
+        let within = Scope::describe(scope);
+        let mut status = format!(
+            "No exact matches{within}, but {} match{} differ only in Unicode normalization or invisible characters — press N to jump to them",
+            matches.len(),
+            if matches.len() == 1 { "" } else { "es" },
+        );
+        let mut current = 0;
+        loop {
+            let Size { w, h } = self.terminal.size()?;
             // Rerender the editor
             self.needs_rerender = true;
             self.render(lua)?;
-            // Render custom status line with mode information
-            self.terminal.prepare_line(h);
-            display!(
-                self,
-                editor_bg,
-                Print("[<-]: Search previous | [->]: Search next | [Enter] Finish | [Esc] Cancel"),
-                Print(" ".repeat(w.saturating_sub(73)))
-            );
+            // Render the hint or details about the current match
+            self.render_menu_status(&status, w, h)?;
             // Move back to correct cursor position
             if let Some(Loc { x, y }) = self.cursor_position() {
                 self.terminal.goto(x, y);
@@ -111,16 +294,38 @@ impl Editor {
                 key_event(&wait_for_event_hog(self), &mut self.macro_man)
             {
                 match (modifiers, code) {
-                    // On return or escape key, exit menu
-                    (KMod::NONE, KCode::Enter) => done = true,
+                    (KMod::NONE, KCode::Enter) => break,
                     (KMod::NONE, KCode::Esc) => {
-                        self.try_doc_mut().unwrap().move_to(&cache);
-                        done = true;
+                        self.leave_search(cache, scope);
+                        return Ok(());
+                    }
+                    // Jump to the next loose match and show how it differs
+                    (KMod::NONE | KMod::SHIFT, KCode::Char('n' | 'N')) => {
+                        let mtch = &matches[current];
+                        let doc = self.try_doc_mut().unwrap();
+                        doc.cancel_selection();
+                        let mut move_to = mtch.loc;
+                        move_to.x += mtch.text.chars().count();
+                        doc.move_to(&move_to);
+                        doc.select_to(&mtch.loc);
+                        let (found, wanted) = loose_differences(target, &mtch.text);
+                        let describe = |chars: Vec<char>| {
+                            let codes: Vec<String> = chars.into_iter().map(describe_char).collect();
+                            if codes.is_empty() {
+                                "nothing".to_string()
+                            } else {
+                                codes.join(" ")
+                            }
+                        };
+                        status = format!(
+                            "Match {}/{}: document has {}, search has {} | [N] Next | [Enter] Finish | [Esc] Cancel",
+                            current + 1,
+                            matches.len(),
+                            describe(found),
+                            describe(wanted),
+                        );
+                        current = (current + 1) % matches.len();
                     }
-                    // On left key, move to the previous match in the document
-                    (KMod::NONE, KCode::Left) => std::mem::drop(self.prev_match(&target)),
-                    // On right key, move to the next match in the document
-                    (KMod::NONE, KCode::Right) => std::mem::drop(self.next_match(&target)),
                     _ => (),
                 }
             }
@@ -152,6 +357,53 @@ impl Editor {
         }
     }
 
+    /// Move to the next match after the cursor and select it, just as searching does,
+    /// giving where it starts (for scripts, which search without the prompt)
+    pub fn find_next(&mut self, target: &str) -> Option<Loc> {
+        self.next_match(target)?;
+        self.try_doc().map(|doc| doc.selection_loc_bound().0)
+    }
+
+    /// Move to the next (or previous) match, only looking in the scope if there is one.
+    /// Within a scope, a match at the cursor counts as the next one unless it is already selected
+    fn jump_to_match(
+        &mut self,
+        target: &str,
+        forward: bool,
+        scope: Option<Scope>,
+    ) -> Option<String> {
+        let Some(Scope { start, end, .. }) = scope else {
+            return if forward {
+                self.next_match(target)
+            } else {
+                self.prev_match(target)
+            };
+        };
+        if target.is_empty() {
+            return None;
+        }
+        let doc = self.try_doc_mut()?;
+        let here = doc.char_loc();
+        let fresh = doc.is_selection_empty();
+        let matches = doc.matches_within(target, start, end);
+        let mtch = if forward {
+            matches
+                .into_iter()
+                .find(|m| m.loc > here || (fresh && m.loc == here))?
+        } else {
+            matches.into_iter().rev().find(|m| m.loc < here)?
+        };
+        // Select match
+        doc.cancel_selection();
+        let mut move_to = mtch.loc;
+        move_to.x += mtch.text.chars().count();
+        doc.move_to(&move_to);
+        doc.select_to(&mtch.loc);
+        // Update highlighting
+        self.update_highlighter();
+        Some(mtch.text)
+    }
+
     /// Move to the previous match
     pub fn prev_match(&mut self, target: &str) -> Option<String> {
         if target.is_empty() {
@@ -181,8 +433,6 @@ impl Editor {
         if self.try_doc().is_none() {
             return Ok(());
         }
-        // Gather data
-        let editor_bg = Bg(config!(self.config, colors).editor_bg.to_color()?);
         // Request replace information
         let target = self.prompt("Replace")?;
         // If no target is given, do nothing
@@ -191,36 +441,42 @@ impl Editor {
         }
         let into = self.prompt("With")?;
         let mut done = false;
-        let Size { w, h } = size()?;
+        // With text selected, only the selection is replaced in, starting from the top of it
+        let mut scope = Scope::of(self.try_doc().unwrap());
+        let within = Scope::describe(scope);
+        if let Some(Scope { start, .. }) = scope {
+            self.try_doc_mut().unwrap().move_to(&start);
+        }
         // Jump to match
         let mut mtch;
-        if let Some(m) = self.next_match(&target) {
+        if let Some(m) = self.jump_to_match(&target, true, scope) {
             // Automatically move to next match, keeping note of what that match is
             mtch = m;
-        } else if let Some(m) = self.prev_match(&target) {
+        } else if let Some(m) = self.jump_to_match(&target, false, scope) {
             // Automatically move to previous match, keeping not of what that match is
             // This happens if there are no matches further down the document, only above
             mtch = m;
         } else {
-            // Exit if there are no matches in the document
+            // Exit if there are no matches in the document (or selection)
+            self.ring_bell();
+            if let Some(scope) = scope {
+                scope.restore(self.try_doc_mut().unwrap());
+                self.feedback = Feedback::Info("No matches in the selection".to_string());
+            }
             return Ok(());
         }
+        let status = config!(self.config, status_line)
+            .replace
+            .replace("{within}", within);
         self.update_highlighter();
         // Enter into the replace menu
         while !done {
+            let Size { w, h } = self.terminal.size()?;
             // Rerender
             self.needs_rerender = true;
             self.render(lua)?;
             // Write custom status line for the replace mode
-            self.terminal.prepare_line(h);
-            display!(
-                self,
-                editor_bg,
-                Print(
-                    "[<-] Previous | [->] Next | [Enter] Replace | [Tab] Replace All | [Esc] Exit"
-                ),
-                Print(" ".repeat(w.saturating_sub(76)))
-            );
+            self.render_menu_status(&status, w, h)?;
             // Move back to correct cursor location
             if let Some(Loc { x, y }) = self.cursor_position() {
                 self.terminal.goto(x, y);
@@ -237,20 +493,37 @@ impl Editor {
                     // On escape key, exit
                     (KMod::NONE, KCode::Esc) => done = true,
                     // On right key, move to the previous match, keeping note of what that match is
-                    (KMod::NONE, KCode::Left) => mtch = self.prev_match(&target).unwrap_or(mtch),
+                    (KMod::NONE, KCode::Left) => {
+                        mtch = self.jump_to_match(&target, false, scope).unwrap_or(mtch);
+                    }
                     // On left key, move to the next match, keeping note of what that match is
-                    (KMod::NONE, KCode::Right) => mtch = self.next_match(&target).unwrap_or(mtch),
+                    (KMod::NONE, KCode::Right) => {
+                        mtch = self.jump_to_match(&target, true, scope).unwrap_or(mtch);
+                    }
                     // On return key, perform replacement
-                    (KMod::NONE, KCode::Enter) => self.do_replace(&into, &mtch)?,
-                    // On tab key, replace all instances within the document
-                    (KMod::NONE, KCode::Tab) => self.do_replace_all(&target, &into),
+                    (KMod::NONE, KCode::Enter) => {
+                        let at = self.try_doc().unwrap().char_loc();
+                        self.do_replace(&into, &mtch)?;
+                        // The selection ends in a different place if the line it ends on changed
+                        if let Some(scope) = scope.as_mut().filter(|s| s.end.y == at.y) {
+                            scope.end.x = (scope.end.x + into.chars().count())
+                                .saturating_sub(mtch.chars().count());
+                        }
+                    }
+                    // On tab key, replace all instances within the document (or selection)
+                    (KMod::NONE, KCode::Tab) => self.do_replace_all(&target, &into, &mut scope)?,
                     _ => (),
                 }
             }
             // Update syntax highlighter if necessary
             self.update_highlighter();
         }
-        self.try_doc_mut().unwrap().cancel_selection();
+        // The selection is kept (taking in any replacements) so it can be worked on further
+        if let Some(scope) = scope {
+            scope.restore(self.try_doc_mut().unwrap());
+        } else {
+            self.try_doc_mut().unwrap().cancel_selection();
+        }
         Ok(())
     }
 
@@ -272,25 +545,125 @@ impl Editor {
         Ok(())
     }
 
-    /// Replace all instances in a document
-    fn do_replace_all(&mut self, target: &str, into: &str) {
-        if self.try_doc().is_some() {
-            // Commit events to event manager (for undo / redo)
-            self.try_doc_mut().unwrap().commit();
-            // Replace everything top to bottom
-            self.try_doc_mut().unwrap().move_to(&Loc::at(0, 0));
-            while let Some(mtch) = self.try_doc_mut().unwrap().next_match(target, 1) {
-                drop(
-                    self.try_doc_mut()
-                        .unwrap()
-                        .replace(mtch.loc, &mtch.text, into),
-                );
-                self.update_highlighter();
-                if let Some(file) = self.files.get_mut(self.ptr.clone()) {
-                    file.highlighter
-                        .edit(mtch.loc.y, &file.doc.lines[mtch.loc.y]);
-                }
+    /// Replace all instances in a document, or in the scope if there is one
+    /// (asking first if there are a lot of them)
+    fn do_replace_all(
+        &mut self,
+        target: &str,
+        into: &str,
+        scope: &mut Option<Scope>,
+    ) -> Result<()> {
+        let Some(doc) = self.try_doc_mut() else {
+            return Ok(());
+        };
+        // Work out how many replacements are about to be made
+        let count = match scope {
+            Some(Scope { start, end, .. }) => doc.matches_within(target, *start, *end).len(),
+            None => doc.count_matches(target, MAX_COUNTED),
+        };
+        let confirm = {
+            let replace = config!(self.config, replace);
+            replace.confirm_all && count >= replace.confirm_threshold
+        };
+        if confirm {
+            let count = if count >= MAX_COUNTED {
+                format!("{MAX_COUNTED}+")
+            } else {
+                count.to_string()
+            };
+            let msg = format!(
+                "Replace {count} occurrences of '{target}' with '{into}'? [Enter] confirm / [Esc] cancel"
+            );
+            if !self.confirm_with(&msg, (KMod::NONE, KCode::Enter))? {
+                return Ok(());
             }
         }
+        match scope {
+            Some(scope) => self.replace_all_within(target, into, scope),
+            None => {
+                self.replace_all(target, into);
+            }
+        }
+        Ok(())
+    }
+
+    /// Replace all instances in the current document as a single undoable change,
+    /// returning how many were replaced
+    pub fn replace_all(&mut self, target: &str, into: &str) -> usize {
+        let Some(doc) = self.try_doc_mut() else {
+            return 0;
+        };
+        doc.load_to(doc.len_lines());
+        let end = Loc::at(usize::MAX, doc.len_lines());
+        // Replace everything top to bottom, leaving the cursor at the last replacement
+        let Some((replaced, _)) = self.replace_with_progress(target, into, Loc::at(0, 0), end)
+        else {
+            return 0;
+        };
+        let plural = if replaced == 1 { "" } else { "s" };
+        self.feedback = Feedback::Info(format!("Replaced {replaced} occurrence{plural}"));
+        replaced
+    }
+
+    /// Replace all instances in a selection as a single undoable change,
+    /// moving the end of the selection to take in the changes
+    fn replace_all_within(&mut self, target: &str, into: &str, scope: &mut Scope) {
+        let Some((replaced, end)) =
+            self.replace_with_progress(target, into, scope.start, scope.end)
+        else {
+            return;
+        };
+        scope.end = end;
+        let plural = if replaced == 1 { "" } else { "s" };
+        self.feedback = Feedback::Info(format!(
+            "Replaced {replaced} occurrence{plural} in the selection"
+        ));
+    }
+
+    /// Replace the instances between two locations as a single undoable change, showing how far
+    /// through it is. This gives how many were replaced and where the end location moved to,
+    /// or None if it was stopped (in which case the document is put back as it was)
+    #[allow(clippy::cast_precision_loss)]
+    fn replace_with_progress(
+        &mut self,
+        target: &str,
+        into: &str,
+        start: Loc,
+        end: Loc,
+    ) -> Option<(usize, Loc)> {
+        let file = self.files.get_mut(self.ptr.clone())?;
+        let (progress, terminal, pending) = (
+            &mut self.progress,
+            &mut self.terminal,
+            &mut self.pending_events,
+        );
+        progress.start(&format!("Replacing '{target}'"));
+        let mut stopped = false;
+        let (replaced, end) = file.doc.batch(|doc| {
+            let keep_going = |done: usize, total: usize| {
+                let fraction = done as f64 / total.max(1) as f64;
+                let carry_on =
+                    progress.tick(fraction, terminal, pending, &self.config, self.headless);
+                stopped |= !carry_on;
+                carry_on
+            };
+            let (replaced, last, end) =
+                doc.replace_within_until(target, into, start, end, keep_going);
+            if stopped {
+                doc.rollback();
+            } else if let Some(loc) = last {
+                doc.cancel_selection();
+                doc.move_to(&loc);
+            }
+            (replaced, end)
+        });
+        self.finish();
+        // Update syntax highlighter
+        self.reload_highlight();
+        if stopped {
+            self.feedback = Feedback::Warning("Stopped replacing, nothing was changed".to_string());
+            return None;
+        }
+        Some((replaced, end))
     }
 }
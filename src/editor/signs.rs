@@ -0,0 +1,177 @@
+/// Showing what external tools (linters, test runners) report about lines in the gutter
+use crate::config;
+use crate::Feedback;
+use kaolinite::signs::{clamp_signs, signs_on, Severity, Sign, SignIndex};
+use kaolinite::utils::{get_file_name, Loc};
+use std::collections::HashMap;
+use std::time::{Duration, Instant, SystemTime};
+
+use super::Editor;
+
+/// What is added to the name of a file to find the sign file beside it
+pub const SIGN_SUFFIX: &str = ".ox-signs.json";
+/// How often sign files are checked for changes
+const CHECK_PERIOD: Duration = Duration::from_secs(1);
+
+/// Keeps the signs from sign files up to date as tools rewrite them
+#[derive(Debug, Default)]
+pub struct SignWatcher {
+    pub index: SignIndex,
+    /// When each loaded sign file was last changed
+    modified: HashMap<String, SystemTime>,
+    checked: Option<Instant>,
+}
+
+/// Work out which marker to show in the gutter for a sign
+pub fn sign_marker(sign: &Sign) -> char {
+    match (sign.severity, sign.stale) {
+        (_, true) => '○',
+        (Severity::Error, _) => '●',
+        (Severity::Warning, _) => '▲',
+        (Severity::Info | Severity::Hint, _) => '•',
+    }
+}
+
+impl Editor {
+    /// The sign files to watch, along with the file their entries default to
+    fn sign_files(&self) -> Vec<(String, Option<String>)> {
+        let mut result: Vec<(String, Option<String>)> = config!(self.config, document)
+            .sign_files
+            .iter()
+            .map(|path| (path.clone(), None))
+            .collect();
+        for (ptr, doc) in self.files.all_documents(vec![]) {
+            if let Ok(file) = self.file_at(&ptr, doc) {
+                if let Some(file_name) = &file.doc.file_name {
+                    result.push((format!("{file_name}{SIGN_SUFFIX}"), Some(file_name.clone())));
+                }
+            }
+        }
+        result
+    }
+
+    /// Load any sign files that have changed since they were last checked,
+    /// returning whether the documents need redrawing
+    pub fn signs_idle_tick(&mut self) -> bool {
+        if !config!(self.config, document).signs {
+            return false;
+        }
+        if self
+            .signs
+            .checked
+            .is_some_and(|at| at.elapsed() < CHECK_PERIOD)
+        {
+            return false;
+        }
+        self.signs.checked = Some(Instant::now());
+        let mut changed = false;
+        let mut skipped = vec![];
+        for (source, default_file) in self.sign_files() {
+            let modified = std::fs::metadata(&source).and_then(|m| m.modified()).ok();
+            if modified.is_some() && modified == self.signs.modified.get(&source).copied() {
+                continue;
+            }
+            // Sign files are often rewritten while being read, so only note them once read
+            let text = modified.and_then(|_| std::fs::read_to_string(&source).ok());
+            match (modified, text) {
+                (Some(modified), Some(text)) => {
+                    let count = self
+                        .signs
+                        .index
+                        .load(&source, &text, default_file.as_deref());
+                    if count > 0 {
+                        let name = get_file_name(&source).unwrap_or_else(|| source.clone());
+                        skipped.push(format!("{count} malformed entries in {name}"));
+                    }
+                    self.signs.modified.insert(source, modified);
+                    changed = true;
+                }
+                _ => {
+                    if self.signs.modified.remove(&source).is_some() {
+                        changed |= self.signs.index.remove(&source);
+                    }
+                }
+            }
+        }
+        if changed {
+            self.distribute_signs();
+            self.needs_rerender = true;
+        }
+        if !skipped.is_empty() && matches!(self.feedback, Feedback::None) {
+            self.feedback = Feedback::Warning(format!("Skipped {}", skipped.join(", ")));
+            self.needs_rerender = true;
+        }
+        self.needs_rerender
+    }
+
+    /// Give each open document the signs that are in it
+    pub fn distribute_signs(&mut self) {
+        for (ptr, doc) in self.files.all_documents(vec![]) {
+            let Ok(file) = self.file_at(&ptr, doc) else {
+                continue;
+            };
+            // Documents without files (like build output) keep the signs they were given
+            let Some(file_name) = file.doc.file_name.as_deref() else {
+                continue;
+            };
+            let signs = self.signs.index.for_file(file_name);
+            if let Ok(file) = self.file_at_mut(&ptr, doc) {
+                file.signs = signs;
+            }
+        }
+    }
+
+    /// Show the message of a sign on the cursor's line (unless there is already feedback)
+    pub fn show_sign(&mut self) {
+        if !matches!(self.feedback, Feedback::None) {
+            return;
+        }
+        let Some(file) = self.files.get(self.ptr.clone()) else {
+            return;
+        };
+        let doc = &file.doc;
+        let Some(sign) = signs_on(&file.signs, doc.loc().y, doc.len_lines())
+            .into_iter()
+            .next()
+        else {
+            return;
+        };
+        let msg = if sign.stale {
+            format!("{} (the line no longer exists)", sign.message)
+        } else {
+            sign.message
+        };
+        self.feedback = match sign.severity {
+            Severity::Error => Feedback::Error(msg),
+            Severity::Warning => Feedback::Warning(msg),
+            Severity::Info | Severity::Hint => Feedback::Info(msg),
+        };
+    }
+
+    /// Move the cursor to the next (or previous) line with a sign on it, wrapping around
+    pub fn next_diagnostic(&mut self, forward: bool) {
+        let Some(file) = self.files.get(self.ptr.clone()) else {
+            return;
+        };
+        let mut lines: Vec<usize> = clamp_signs(&file.signs, file.doc.len_lines())
+            .iter()
+            .map(|sign| sign.line)
+            .collect();
+        lines.sort_unstable();
+        lines.dedup();
+        let y = file.doc.loc().y;
+        let target = if forward {
+            lines.iter().find(|l| **l > y).or(lines.first())
+        } else {
+            lines.iter().rev().find(|l| **l < y).or(lines.last())
+        };
+        let Some(&target) = target else {
+            self.feedback = Feedback::Info("There are no diagnostics in this document".to_string());
+            return;
+        };
+        if let Some(doc) = self.try_doc_mut() {
+            doc.move_to(&Loc { x: 0, y: target });
+        }
+        self.show_sign();
+    }
+}
@@ -0,0 +1,354 @@
+/// Checking the spelling of prose, and of the comments and strings in code
+use crate::config;
+use crate::error::Result;
+use crate::Feedback;
+use kaolinite::event::Event;
+use kaolinite::spelling::{misspellings, Dictionary};
+use kaolinite::utils::Loc;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::io::Write;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use synoptic::TokOpt;
+
+use super::{data_dir, Editor};
+
+/// File types whose whole text is checked, rather than just comments and strings
+const PROSE: [&str; 4] = ["Markdown", "Plain Text", "reStructuredText", "Unknown"];
+/// The most corrections offered for a misspelled word
+const MAX_SUGGESTIONS: usize = 5;
+/// Where Hunspell dictionaries are usually installed
+const DICTIONARY_DIRS: [&str; 5] = [
+    "/usr/share/hunspell",
+    "/usr/share/myspell",
+    "/usr/share/myspell/dicts",
+    "/usr/local/share/hunspell",
+    "/Library/Spelling",
+];
+/// Used when no Hunspell dictionary can be found
+const SYSTEM_WORDS: &str = "/usr/share/dict/words";
+
+/// Where the words the user has added to the dictionary are kept
+pub fn words_path() -> Option<PathBuf> {
+    Some(data_dir()?.join("words"))
+}
+
+/// Read a dictionary from a file, either a Hunspell .dic (with its .aff next to it)
+/// or a plain list of words
+fn read_dictionary(path: &Path) -> Option<Dictionary> {
+    let read = |path: &Path| {
+        let bytes = std::fs::read(path).ok()?;
+        Some(String::from_utf8_lossy(&bytes).to_string())
+    };
+    let contents = read(path)?;
+    if path.extension().is_some_and(|ext| ext == "dic") {
+        let aff = read(&path.with_extension("aff")).unwrap_or_default();
+        Some(Dictionary::from_hunspell(&contents, &aff))
+    } else {
+        Some(Dictionary::from_word_list(&contents))
+    }
+}
+
+/// Find and read a dictionary, given either its path or the name of a Hunspell dictionary
+/// (like `en_US`), along with the words the user has added
+fn load_dictionary(name: &str) -> Option<Dictionary> {
+    let expanded = shellexpand::full(name).map_or(name.to_string(), |n| n.to_string());
+    let mut candidates = vec![PathBuf::from(&expanded)];
+    let dirs = std::env::var("DICPATH").unwrap_or_default();
+    for dir in dirs
+        .split(':')
+        .filter(|d| !d.is_empty())
+        .chain(DICTIONARY_DIRS)
+    {
+        candidates.push(PathBuf::from(dir).join(format!("{name}.dic")));
+    }
+    candidates.push(PathBuf::from(SYSTEM_WORDS));
+    let mut dictionary = candidates
+        .iter()
+        .filter(|path| path.is_file())
+        .map(PathBuf::as_path)
+        .find_map(read_dictionary)?;
+    if let Some(words) = words_path().and_then(|path| std::fs::read_to_string(path).ok()) {
+        for word in words.lines().filter(|w| !w.trim().is_empty()) {
+            dictionary.insert(word.trim());
+        }
+    }
+    Some(dictionary)
+}
+
+/// Work out which parts of a line (as character ranges) are comments or strings
+fn checked_regions(tokens: &[TokOpt]) -> Vec<Range<usize>> {
+    let mut result = vec![];
+    let mut x = 0;
+    for token in tokens {
+        let (text, kind) = match token {
+            TokOpt::Some(text, kind) => (text, Some(kind)),
+            TokOpt::None(text) => (text, None),
+        };
+        let len = text.chars().count();
+        if kind.is_some_and(|k| k.contains("comment") || k.contains("string")) {
+            result.push(x..x + len);
+        }
+        x += len;
+    }
+    result
+}
+
+/// The misspellings on each line of a document (by line index),
+/// along with a hash of the text that was checked, so only lines that change are checked again
+type CheckedLines = HashMap<usize, (u64, Vec<Range<usize>>)>;
+
+/// Keeps the dictionary, and the misspellings found in each document
+#[derive(Debug, Default)]
+pub struct Spelling {
+    /// The dictionary, which is only read when spell checking is first needed
+    dictionary: Option<Dictionary>,
+    /// The name of the dictionary that was read (so a change to it can be noticed)
+    loaded: Option<String>,
+    /// The misspellings found in each document
+    lines: HashMap<(Vec<usize>, usize), CheckedLines>,
+}
+
+impl Spelling {
+    /// The misspellings last found on a line of a document
+    pub fn line(&self, at: &(Vec<usize>, usize), y: usize) -> Option<&Vec<Range<usize>>> {
+        self.lines.get(at)?.get(&y).map(|(_, found)| found)
+    }
+
+    /// Forget every misspelling found, for when the dictionary changes
+    fn forget(&mut self) {
+        self.lines.clear();
+    }
+}
+
+impl Editor {
+    /// Make sure the dictionary has been read (returns false if it couldn't be found)
+    fn ensure_dictionary(&mut self) -> bool {
+        let name = config!(self.config, document).dictionary.clone();
+        if self.spelling.loaded.as_ref() != Some(&name) {
+            self.spelling.forget();
+            self.spelling.dictionary = load_dictionary(&name);
+            self.spelling.loaded = Some(name.clone());
+            if self.spelling.dictionary.is_none() {
+                self.feedback = Feedback::Error(format!(
+                    "No dictionary called {name} was found, set document.dictionary to the path of one"
+                ));
+            }
+        }
+        self.spelling.dictionary.is_some()
+    }
+
+    /// Find the misspellings on a line of a document, checking it again only if it has changed
+    fn check_line(&mut self, ptr: &[usize], doc: usize, y: usize) -> Vec<Range<usize>> {
+        let Some(file) = self
+            .files
+            .get_atom(ptr.to_vec())
+            .and_then(|(fcs, _)| fcs.get(doc))
+        else {
+            return vec![];
+        };
+        let Some(dictionary) = &self.spelling.dictionary else {
+            return vec![];
+        };
+        let Some(line) = file.doc.line(y) else {
+            return vec![];
+        };
+        let prose = file
+            .file_type
+            .as_ref()
+            .is_none_or(|t| PROSE.contains(&t.name.as_str()));
+        let regions = if prose {
+            vec![0..line.chars().count()]
+        } else if y < file.highlighter.line_ref.len() {
+            checked_regions(&file.highlighter.line(y, &line))
+        } else {
+            vec![]
+        };
+        let mut hasher = DefaultHasher::new();
+        (&line, &regions).hash(&mut hasher);
+        let version = hasher.finish();
+        let lines = self.spelling.lines.entry((ptr.to_vec(), doc)).or_default();
+        match lines.get(&y) {
+            Some((checked, found)) if *checked == version => found.clone(),
+            _ => {
+                let found = misspellings(&line, &regions, dictionary);
+                lines.insert(y, (version, found.clone()));
+                found
+            }
+        }
+    }
+
+    /// Check the spelling of the lines in view in every split (if spell checking is enabled)
+    pub fn check_spelling_in_view(&mut self) {
+        if !config!(self.config, document).spell_check || !self.ensure_dictionary() {
+            return;
+        }
+        let panes: Vec<Vec<usize>> = self
+            .render_cache
+            .span
+            .iter()
+            .map(|(ptr, _, _)| ptr.clone())
+            .collect();
+        for ptr in panes {
+            let Some((fcs, doc)) = self.files.get_atom(ptr.clone()) else {
+                continue;
+            };
+            let Some(file) = fcs.get(doc) else {
+                continue;
+            };
+            let start = file.doc.offset.y;
+            let end = (start + file.doc.size.h).min(file.doc.len_lines());
+            for y in start..end {
+                self.check_line(&ptr, doc, y);
+            }
+        }
+    }
+
+    /// Turn spell checking on or off
+    pub fn toggle_spell_check(&mut self) {
+        let mut document = self
+            .config
+            .document
+            .borrow_mut::<config::Document>()
+            .unwrap();
+        document.spell_check = !document.spell_check;
+        let on = document.spell_check;
+        drop(document);
+        self.feedback = Feedback::Info(format!(
+            "Spell checking turned {}",
+            if on { "on" } else { "off" }
+        ));
+        self.needs_rerender = true;
+    }
+
+    /// Move the cursor to the next (or previous) misspelled word, wrapping around
+    pub fn next_misspelling(&mut self, forward: bool) {
+        if !config!(self.config, document).spell_check {
+            self.feedback = Feedback::Info("Spell checking is turned off".to_string());
+            return;
+        }
+        if !self.ensure_dictionary() {
+            return;
+        }
+        let Some((_, doc)) = self.files.get_atom(self.ptr.clone()) else {
+            return;
+        };
+        // The whole document needs to be highlighted to know where the comments are
+        let Some(document) = self.try_doc_mut() else {
+            return;
+        };
+        document.load_to(document.len_lines());
+        let (len, here) = (document.len_lines(), document.char_loc());
+        self.update_highlighter();
+        let ptr = self.ptr.clone();
+        let mut order: Vec<usize> = (here.y..len).chain(0..=here.y).collect();
+        if !forward {
+            order = (0..=here.y).rev().chain((here.y..len).rev()).collect();
+        }
+        let mut first_line = true;
+        for y in order {
+            let found = self.check_line(&ptr, doc, y);
+            let target = if first_line && forward {
+                found.iter().find(|r| r.start > here.x)
+            } else if first_line {
+                found.iter().rev().find(|r| r.start < here.x)
+            } else if forward {
+                found.first()
+            } else {
+                found.last()
+            };
+            first_line = false;
+            if let Some(target) = target {
+                if let Some(document) = self.try_doc_mut() {
+                    document.move_to(&Loc::at(target.start, y));
+                }
+                return;
+            }
+        }
+        self.feedback =
+            Feedback::Info("There are no misspelled words in this document".to_string());
+    }
+
+    /// Offer corrections for the misspelled word under the cursor, replacing it with the one
+    /// picked, or add it to the dictionary (for this session or for good)
+    pub fn spelling_suggestions(&mut self) -> Result<()> {
+        if !self.ensure_dictionary() {
+            return Ok(());
+        }
+        let Some((_, doc)) = self.files.get_atom(self.ptr.clone()) else {
+            return Ok(());
+        };
+        let Some(here) = self.try_doc().map(kaolinite::Document::char_loc) else {
+            return Ok(());
+        };
+        let ptr = self.ptr.clone();
+        let found = self.check_line(&ptr, doc, here.y);
+        let Some(range) = found
+            .into_iter()
+            .find(|r| r.start <= here.x && here.x <= r.end)
+        else {
+            self.feedback =
+                Feedback::Info("There is no misspelled word under the cursor".to_string());
+            return Ok(());
+        };
+        let line = self
+            .try_doc()
+            .and_then(|d| d.line(here.y))
+            .unwrap_or_default();
+        let word: String = line.chars().skip(range.start).take(range.len()).collect();
+        let suggestions = self
+            .spelling
+            .dictionary
+            .as_ref()
+            .map(|d| d.suggest(&word, MAX_SUGGESTIONS))
+            .unwrap_or_default();
+        let mut options: Vec<&str> = suggestions.iter().map(String::as_str).collect();
+        options.push("ignore");
+        options.push("add to dictionary");
+        let msg = if suggestions.is_empty() {
+            format!("No suggestions for '{word}'")
+        } else {
+            format!("Suggestions for '{word}'")
+        };
+        let Some(picked) = self.choice(&msg, &options)? else {
+            return Ok(());
+        };
+        if let Some(replacement) = suggestions.get(picked) {
+            let loc = Loc::at(range.start, here.y);
+            return self.batch(|editor| {
+                editor.exe(Event::Delete(loc, word))?;
+                editor.exe(Event::Insert(loc, replacement.clone()))
+            });
+        }
+        if let Some(dictionary) = &mut self.spelling.dictionary {
+            dictionary.insert(&word);
+        }
+        self.spelling.forget();
+        if picked == suggestions.len() + 1 {
+            self.add_to_word_list(&word);
+        }
+        Ok(())
+    }
+
+    /// Add a word to the user's own word list, so it is spelled correctly in future sessions too
+    fn add_to_word_list(&mut self, word: &str) {
+        let Some(path) = words_path() else {
+            return;
+        };
+        if let Some(parent) = path.parent() {
+            let _ = std::fs::create_dir_all(parent);
+        }
+        let file = std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path);
+        match file.and_then(|mut f| writeln!(f, "{word}")) {
+            Ok(()) => self.feedback = Feedback::Info(format!("Added '{word}' to the dictionary")),
+            Err(err) => {
+                self.feedback = Feedback::Error(format!("Failed to add '{word}': {err}"));
+            }
+        }
+    }
+}
@@ -0,0 +1,213 @@
+/// Gathering the information shown in the status line that is too slow to work out every frame
+use crate::config;
+use crate::error::Result;
+use crate::ui::Feedback;
+use kaolinite::document::Stats;
+use kaolinite::utils::get_cwd;
+use kaolinite::Document;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::path::Path;
+use std::process::Command;
+use std::sync::mpsc::{channel, Receiver, TryRecvError};
+use std::time::{Duration, Instant};
+
+use super::Editor;
+
+/// How long the branch of a repository is remembered before git is asked again
+const BRANCH_TTL: Duration = Duration::from_secs(3);
+
+/// The branch a repository is on
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Branch {
+    pub name: String,
+    /// Whether there are changes to tracked files that haven't been committed
+    pub dirty: bool,
+}
+
+/// Ask git which branch the repository containing a directory is on
+/// (returns None if the directory isn't in a repository or git isn't installed)
+fn branch_of(dir: &str) -> Option<Branch> {
+    let run = |args: &[&str]| {
+        let output = Command::new("git")
+            .arg("-C")
+            .arg(dir)
+            .args(args)
+            .output()
+            .ok()?;
+        output
+            .status
+            .success()
+            .then(|| String::from_utf8_lossy(&output.stdout).to_string())
+    };
+    let name = run(&["rev-parse", "--abbrev-ref", "HEAD"])?
+        .trim()
+        .to_string();
+    let dirty = run(&["status", "--porcelain", "--untracked-files=no"])
+        .is_some_and(|status| !status.trim().is_empty());
+    Some(Branch { name, dirty })
+}
+
+/// The directory a document is in, used for finding the repository it belongs to
+fn status_dir(doc: &Document) -> Option<String> {
+    let parent = doc
+        .file_name
+        .as_deref()
+        .and_then(|name| Path::new(name).parent())
+        .map(|dir| dir.to_string_lossy().to_string());
+    match parent {
+        Some(dir) if !dir.is_empty() => Some(dir),
+        _ => get_cwd(),
+    }
+}
+
+/// Lay out how much text is in a document (and in the selection, when there is one)
+fn stats_report(name: &str, doc: Stats, selection: Option<Stats>) -> String {
+    let rows: [(&str, fn(Stats) -> usize); 5] = [
+        ("Lines", |s| s.lines),
+        ("Words", |s| s.words),
+        ("Characters", |s| s.chars),
+        ("  without whitespace", |s| s.chars_without_whitespace),
+        ("Bytes", |s| s.bytes),
+    ];
+    let mut report = format!("Statistics for {name}\n\n{:<20}{:>12}", "", "Document");
+    if selection.is_some() {
+        report += &format!("{:>12}", "Selection");
+    }
+    report.push('\n');
+    for (label, figure) in rows {
+        report += &format!("{label:<20}{:>12}", figure(doc));
+        if let Some(selection) = selection {
+            report += &format!("{:>12}", figure(selection));
+        }
+        report.push('\n');
+    }
+    report
+}
+
+/// Keeps the slower parts of the status line, working them out again only when needed
+#[derive(Debug, Default)]
+pub struct StatusInfo {
+    /// The branch each directory is on, and when git was last asked
+    branches: HashMap<String, (Instant, Option<Branch>)>,
+    /// Will be some while git is being asked about a directory
+    pending: Option<(String, Receiver<Option<Branch>>)>,
+    /// How much text is in each document, along with the version it was counted at
+    stats: HashMap<(Vec<usize>, usize), (u64, Stats)>,
+}
+
+impl StatusInfo {
+    /// Ask git about a directory in the background, if what is known about it is out of date
+    pub fn refresh_branch(&mut self, dir: &str) {
+        let fresh = self
+            .branches
+            .get(dir)
+            .is_some_and(|(at, _)| at.elapsed() < BRANCH_TTL);
+        if fresh || self.pending.is_some() {
+            return;
+        }
+        let (tx, rx) = channel();
+        let target = dir.to_string();
+        std::thread::spawn(move || {
+            let _ = tx.send(branch_of(&target));
+        });
+        self.pending = Some((dir.to_string(), rx));
+    }
+
+    /// Pick up the result of a finished refresh, returns true if the branch has changed
+    pub fn poll(&mut self) -> bool {
+        let Some((dir, rx)) = &self.pending else {
+            return false;
+        };
+        let branch = match rx.try_recv() {
+            Ok(branch) => branch,
+            Err(TryRecvError::Empty) => return false,
+            Err(TryRecvError::Disconnected) => None,
+        };
+        let dir = dir.clone();
+        self.pending = None;
+        let old = self.branches.insert(dir, (Instant::now(), branch.clone()));
+        old.map(|(_, old)| old) != Some(branch)
+    }
+
+    /// The branch the repository a document is in was last known to be on
+    pub fn branch(&self, doc: &Document) -> Option<&Branch> {
+        self.branches.get(&status_dir(doc)?)?.1.as_ref()
+    }
+
+    /// Count up the text in a document, unless it was counted since it last changed
+    pub fn count_stats(&mut self, at: (Vec<usize>, usize), doc: &Document) -> Stats {
+        // Another document could be opened in the same place, so the name is noted too
+        let mut hasher = DefaultHasher::new();
+        (&doc.file_name, doc.generation, doc.file.len_chars()).hash(&mut hasher);
+        let version = hasher.finish();
+        match self.stats.get(&at) {
+            Some((counted, stats)) if *counted == version => *stats,
+            _ => {
+                let stats = doc.stats();
+                self.stats.insert(at, (version, stats));
+                stats
+            }
+        }
+    }
+
+    /// The last count of the text in a document
+    pub fn stats(&self, at: &(Vec<usize>, usize)) -> Option<Stats> {
+        self.stats.get(at).map(|(_, stats)| *stats)
+    }
+}
+
+impl Editor {
+    /// Bring the status line information for the document shown at `ptr` up to date
+    pub fn refresh_status_info(&mut self, ptr: &[usize]) {
+        let Some((fcs, idx)) = self.files.get_atom(ptr.to_vec()) else {
+            return;
+        };
+        let Some(fc) = fcs.get(idx) else {
+            return;
+        };
+        // Counting means reading the whole document, so only do it if a count is shown
+        let shown = config!(self.config, status_line)
+            .parts
+            .iter()
+            .any(|part| part.contains("{word_count}") || part.contains("{char_count}"));
+        if shown {
+            self.status_info.count_stats((ptr.to_vec(), idx), &fc.doc);
+        }
+        if let Some(dir) = status_dir(&fc.doc) {
+            self.status_info.refresh_branch(&dir);
+        }
+    }
+
+    /// Open a read only document listing how much text is in the current document
+    /// (counted again only if it has changed since the status line last counted it)
+    pub fn show_stats(&mut self) -> Result<()> {
+        let Some((fcs, idx)) = self.files.get_atom(self.ptr.clone()) else {
+            self.feedback = Feedback::Error("There is no document to count".to_string());
+            return Ok(());
+        };
+        let Some(fc) = fcs.get(idx) else {
+            return Ok(());
+        };
+        let name = fc.doc.file_name.as_deref().map_or_else(
+            || fc.placeholder_name(),
+            |name| {
+                Path::new(name)
+                    .file_name()
+                    .map_or(name.to_string(), |n| n.to_string_lossy().to_string())
+            },
+        );
+        let selection = fc.doc.selection_stats();
+        let stats = self
+            .status_info
+            .count_stats((self.ptr.clone(), idx), &fc.doc);
+        self.open_from_string(stats_report(&name, stats, selection), None)?;
+        self.next();
+        if let Some(file) = self.files.get_mut(self.ptr.clone()) {
+            file.doc.info.read_only = true;
+            file.scratch = Some("stats".to_string());
+        }
+        Ok(())
+    }
+}
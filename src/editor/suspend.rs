@@ -0,0 +1,112 @@
+/// Stepping out of the editor for a while, either by suspending it (as ctrl + z does elsewhere)
+/// or by dropping to a shell, with the terminal handed back as it was until ox returns
+use crate::error::Result;
+#[cfg(target_os = "windows")]
+use crate::ui::Feedback;
+use std::process::Command;
+
+use super::Editor;
+
+impl Editor {
+    /// Suspend ox, handing the terminal back to the shell it was started from.
+    /// Everything is set up again and drawn afresh once the shell resumes it
+    #[cfg(not(target_os = "windows"))]
+    pub fn suspend(&mut self) -> Result<()> {
+        use nix::sys::signal::{raise, Signal};
+        self.step_out(|| {
+            // This only returns once ox is resumed (or straight away if nothing can resume it)
+            raise(Signal::SIGTSTP).map_err(std::io::Error::from)?;
+            Ok(())
+        })
+    }
+
+    /// Windows has no job control, so there is nothing to suspend to
+    #[cfg(target_os = "windows")]
+    pub fn suspend(&mut self) -> Result<()> {
+        self.feedback = Feedback::Info("Suspending isn't supported on Windows".to_string());
+        Ok(())
+    }
+
+    /// Run an interactive shell (the one in `$SHELL`), returning to ox once it exits
+    pub fn shell(&mut self) -> Result<()> {
+        let shell = if cfg!(target_os = "windows") {
+            std::env::var("COMSPEC").unwrap_or_else(|_| "cmd".to_string())
+        } else {
+            std::env::var("SHELL").unwrap_or_else(|_| "sh".to_string())
+        };
+        // However the shell exits (even with an error from the last thing run in it), ox carries on
+        self.step_out(|| {
+            Command::new(&shell).status()?;
+            Ok(())
+        })
+    }
+
+    /// Put the terminal back as it was before ox started, do something,
+    /// then set it up for ox again (documents changed in the meantime are picked up,
+    /// just as they are when the terminal loses focus and gets it back)
+    fn step_out(&mut self, away: impl FnOnce() -> Result<()>) -> Result<()> {
+        self.needs_terminal()?;
+        self.handle_focus(false);
+        self.terminal.end()?;
+        let result = away();
+        self.terminal.start()?;
+        // Whatever ran in the meantime has drawn over the screen
+        self.needs_rerender = true;
+        self.handle_focus(true);
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::MemoryBackend;
+    use crate::config::{CursorShape, Terminal as TerminalConfig};
+    use crate::error::OxError;
+    use crate::ui::Terminal;
+    use mlua::Lua;
+
+    #[test]
+    fn stepping_out() {
+        let lua = Lua::new();
+        let mut editor = Editor::new(&lua).unwrap();
+        let backend = MemoryBackend::new(40, 10);
+        editor.terminal =
+            Terminal::with_backend(editor.config.terminal.clone(), Box::new(backend.clone()));
+        let terminal = editor.config.terminal.clone();
+        terminal
+            .borrow_mut::<TerminalConfig>()
+            .unwrap()
+            .cursor_style = CursorShape::Bar;
+        editor.init().unwrap();
+        let mouse = crate::config!(editor.config, terminal).mouse_enabled;
+        assert!(backend.screen().entered);
+        assert_eq!(backend.screen().cursor_shape, 6);
+        // The terminal is handed back as it was while away
+        editor.terminal.hide_cursor();
+        editor.needs_rerender = false;
+        let away = backend.clone();
+        editor
+            .step_out(move || {
+                let screen = away.screen();
+                assert!(!screen.entered && !screen.mouse_captured);
+                assert!(screen.cursor_visible);
+                assert_eq!(screen.cursor_shape, 0);
+                Ok(())
+            })
+            .unwrap();
+        // Then set up again, to be drawn from scratch
+        let screen = backend.screen();
+        assert!(screen.entered);
+        assert_eq!(screen.mouse_captured, mouse);
+        assert_eq!(screen.cursor_shape, 6);
+        assert!(editor.needs_rerender);
+        assert!(editor.terminal.frame.is_empty());
+        // Even when what was done while away went wrong
+        editor.needs_rerender = false;
+        let result = editor.step_out(|| Err(OxError::Cancelled));
+        assert!(matches!(result, Err(OxError::Cancelled)));
+        assert!(backend.screen().entered);
+        assert!(editor.needs_rerender);
+    }
+}
@@ -0,0 +1,283 @@
+/// Keeping swap files of documents with unsaved changes, so they can be recovered after a crash
+use crate::config;
+use crate::error::Result;
+use crate::Feedback;
+use kaolinite::diff::unified_diff;
+use kaolinite::event::stable_hash;
+use kaolinite::utils::{get_file_name, get_path_identity};
+use mlua::Lua;
+use ropey::Rope;
+use std::collections::HashMap;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use super::Editor;
+
+/// How many unchanged lines are shown around each change when comparing with a swap file
+const DIFF_CONTEXT: usize = 3;
+
+/// The contents of each document with unsaved changes as of the last idle moment,
+/// kept here so the panic hook can write them out without borrowing the editor
+static PENDING: Mutex<Vec<(PathBuf, Rope)>> = Mutex::new(Vec::new());
+
+/// Work out where temporary files are kept
+pub fn cache_dir() -> Option<PathBuf> {
+    let cache = std::env::var("XDG_CACHE_HOME")
+        .ok()
+        .filter(|d| !d.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| {
+            let home = std::env::var("HOME").or_else(|_| std::env::var("USERPROFILE"));
+            home.ok().map(|h| PathBuf::from(h).join(".cache"))
+        })?;
+    Some(cache.join("ox"))
+}
+
+/// Work out where the swap file of a file is kept (based on its canonical path)
+pub fn swap_path(file_name: &str) -> Option<PathBuf> {
+    let path = get_path_identity(file_name);
+    let name = format!("{:016x}.swap", stable_hash([path.as_str()]));
+    Some(cache_dir()?.join("swap").join(name))
+}
+
+/// Write a swap file, going through a temporary file so that a crash part way through
+/// never leaves a swap file with only some of the document in it
+fn write_swap(path: &Path, contents: &Rope) -> std::io::Result<()> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let temp = path.with_extension("tmp");
+    let mut writer = BufWriter::new(std::fs::File::create(&temp)?);
+    contents.write_to(&mut writer)?;
+    writer
+        .into_inner()
+        .map_err(|e| e.into_error())?
+        .sync_all()?;
+    std::fs::rename(temp, path)
+}
+
+/// Read the swap file left behind for a file, if there is one
+pub fn read_swap(file_name: &str) -> Option<String> {
+    std::fs::read_to_string(swap_path(file_name)?).ok()
+}
+
+/// Write out the swap files of every document that had unsaved changes at the last idle moment.
+/// This is called from the panic hook, so it must never block or panic itself
+pub fn write_pending_swaps() {
+    let Ok(pending) = PENDING.try_lock() else {
+        return;
+    };
+    for (path, contents) in pending.iter() {
+        let _ = write_swap(path, contents);
+    }
+}
+
+/// Keep track of the document in a swap file
+fn set_pending(path: &Path, contents: Option<Rope>) {
+    let Ok(mut pending) = PENDING.lock() else {
+        return;
+    };
+    pending.retain(|(p, _)| p != path);
+    if let Some(contents) = contents {
+        pending.push((path.to_path_buf(), contents));
+    }
+}
+
+/// Where the swap file of a document is up to
+#[derive(Debug, Clone, Copy)]
+struct SwapState {
+    /// The version of the document that was last looked at
+    generation: u64,
+    /// Whether the swap file has caught up with that version
+    written: bool,
+}
+
+/// Keeps track of which documents need their swap files writing
+#[derive(Debug)]
+pub struct Swaps {
+    /// When swap files were last written
+    last_write: Instant,
+    /// The state of the swap file at each path
+    states: HashMap<PathBuf, SwapState>,
+}
+
+impl Default for Swaps {
+    fn default() -> Self {
+        Self {
+            last_write: Instant::now(),
+            states: HashMap::default(),
+        }
+    }
+}
+
+impl Editor {
+    /// Remove the swap file of a document, for when its changes have been saved or thrown away
+    pub fn remove_swap_at(&mut self, ptr: &[usize], doc: usize) {
+        let Some(file_name) = self
+            .file_at(ptr, doc)
+            .ok()
+            .and_then(|file| file.doc.file_name.clone())
+        else {
+            return;
+        };
+        let Some(path) = swap_path(&file_name) else {
+            return;
+        };
+        self.swaps.states.remove(&path);
+        set_pending(&path, None);
+        let _ = std::fs::remove_file(path);
+    }
+
+    /// Keep the swap files of documents with unsaved changes up to date (if they are enabled).
+    /// Documents are noted as soon as they change, in case of a panic,
+    /// but only written out every `document.swap_interval` seconds
+    pub fn swap_idle_tick(&mut self) {
+        let document = config!(self.config, document);
+        let (enabled, interval) = (document.swap_files, document.swap_interval);
+        drop(document);
+        if !enabled {
+            return;
+        }
+        for (ptr, doc) in self.files.all_documents(vec![]) {
+            let Ok(file) = self.file_at(&ptr, doc) else {
+                continue;
+            };
+            if !file.savable() || file.doc.info.read_only {
+                continue;
+            }
+            let Some(path) = file.doc.file_name.as_deref().and_then(swap_path) else {
+                continue;
+            };
+            let generation = file.doc.generation;
+            let state = self.swaps.states.get(&path).copied();
+            if state.is_some_and(|s| s.generation == generation) {
+                continue;
+            }
+            // Documents that have gone back to how they are on the disk don't need one
+            let modified = file.is_modified();
+            let contents = modified.then(|| file.doc.file.clone());
+            set_pending(&path, contents);
+            if !modified {
+                let _ = std::fs::remove_file(&path);
+            }
+            let state = SwapState {
+                generation,
+                written: !modified,
+            };
+            self.swaps.states.insert(path, state);
+        }
+        let interval = Duration::from_secs(u64::try_from(interval).unwrap_or(u64::MAX));
+        if self.swaps.last_write.elapsed() < interval {
+            return;
+        }
+        let Ok(pending) = PENDING.lock() else {
+            return;
+        };
+        let mut wrote = false;
+        for (path, contents) in pending.iter() {
+            let Some(state) = self.swaps.states.get_mut(path) else {
+                continue;
+            };
+            // Swap files are only a safety net, so failing to write one isn't worth reporting
+            if !state.written && write_swap(path, contents).is_ok() {
+                state.written = true;
+                wrote = true;
+            }
+        }
+        if wrote {
+            self.swaps.last_write = Instant::now();
+        }
+    }
+
+    /// Look for swap files left behind for the open documents, asking what to do with each
+    pub fn recover_swaps(&mut self, lua: &Lua) -> Result<()> {
+        if !config!(self.config, document).swap_files {
+            return Ok(());
+        }
+        let mut seen = vec![];
+        // Diffs open just after the document, so going backwards keeps the rest where they are
+        for (ptr, doc) in self.files.all_documents(vec![]).into_iter().rev() {
+            let Ok(file) = self.file_at(&ptr, doc) else {
+                continue;
+            };
+            let Some(file_name) = file.doc.file_name.clone() else {
+                continue;
+            };
+            let Some(path) = swap_path(&file_name) else {
+                continue;
+            };
+            if seen.contains(&path) {
+                continue;
+            }
+            seen.push(path.clone());
+            let Some(swap) = read_swap(&file_name) else {
+                continue;
+            };
+            // A swap file that matches the file was left by a crash after saving
+            if file.doc.file.to_string() == swap {
+                let _ = std::fs::remove_file(path);
+                continue;
+            }
+            self.focus_document(&ptr, doc);
+            self.recover_swap(lua, doc, &file_name, &swap, &path)?;
+        }
+        Ok(())
+    }
+
+    /// Ask what to do with the swap file of the current document (the `doc`th in its split)
+    fn recover_swap(
+        &mut self,
+        lua: &Lua,
+        doc: usize,
+        file_name: &str,
+        swap: &str,
+        path: &Path,
+    ) -> Result<()> {
+        let name = get_file_name(file_name).unwrap_or_else(|| file_name.to_string());
+        let ptr = self.ptr.clone();
+        loop {
+            self.needs_rerender = true;
+            self.render(lua)?;
+            let msg =
+                format!("Recovered unsaved changes found for {name} — recover, diff, or discard?");
+            match self.choice(&msg, &["recover", "diff", "discard"])? {
+                Some(0) => {
+                    // Go back to the document in case a diff is being shown
+                    self.files.move_to(ptr.clone(), doc);
+                    if let Some(doc) = self.try_doc_mut() {
+                        // The document stays modified, so the recovered changes still need saving
+                        doc.batch(|doc| doc.replace_contents(swap));
+                    }
+                    self.reload_highlight();
+                    self.feedback = Feedback::Info(format!("Recovered unsaved changes to {name}"));
+                    return Ok(());
+                }
+                Some(1) => {
+                    self.files.move_to(ptr.clone(), doc);
+                    let current = self.try_doc().map(|doc| doc.file.to_string());
+                    let diff = unified_diff(
+                        &current.unwrap_or_default(),
+                        swap,
+                        "file",
+                        "swap",
+                        DIFF_CONTEXT,
+                    );
+                    self.open_from_string(diff, Some("Diff".to_string()))?;
+                    self.next();
+                    if let Some(file) = self.files.get_mut(self.ptr.clone()) {
+                        file.doc.info.read_only = true;
+                        file.scratch = Some(format!("{name} swap diff"));
+                    }
+                }
+                Some(2) => {
+                    let _ = std::fs::remove_file(path);
+                    return Ok(());
+                }
+                // The swap file is kept for next time
+                _ => return Ok(()),
+            }
+        }
+    }
+}
@@ -0,0 +1,63 @@
+/// Colour swatches drawn just after the hex codes and `rgb(...)` colours in a line,
+/// so colours can be seen while they are being tweaked
+use crate::config;
+use crate::config::Color;
+use regex::Regex;
+use std::sync::OnceLock;
+
+use super::{Editor, FileContainer};
+
+/// Finds `#rrggbb` and `#rgb` codes
+static HEX: OnceLock<Regex> = OnceLock::new();
+/// Finds `rgb(r, g, b)` colours
+static RGB: OnceLock<Regex> = OnceLock::new();
+
+/// Find the colours written in a line, giving the character index just after each one
+/// (where its swatch goes) and the colour itself
+pub fn color_swatches(line: &str) -> Vec<(usize, Color)> {
+    let hex = HEX.get_or_init(|| Regex::new(r"#([0-9a-fA-F]{6}|[0-9a-fA-F]{3})\b").unwrap());
+    let rgb = RGB.get_or_init(|| {
+        Regex::new(r"rgb\(\s*(\d{1,3})\s*,\s*(\d{1,3})\s*,\s*(\d{1,3})\s*\)").unwrap()
+    });
+    let mut found = vec![];
+    for caps in hex.captures_iter(line) {
+        let digits = &caps[1];
+        // Short codes have each digit doubled (#f80 is #ff8800)
+        let channel = |i: usize| {
+            let part = if digits.len() == 3 {
+                digits[i..=i].repeat(2)
+            } else {
+                digits[i * 2..i * 2 + 2].to_string()
+            };
+            u8::from_str_radix(&part, 16).ok()
+        };
+        if let (Some(r), Some(g), Some(b)) = (channel(0), channel(1), channel(2)) {
+            found.push((caps.get(0).unwrap().end(), Color::Rgb(r, g, b)));
+        }
+    }
+    for caps in rgb.captures_iter(line) {
+        let channel = |i: usize| caps[i].parse::<u8>().ok();
+        if let (Some(r), Some(g), Some(b)) = (channel(1), channel(2), channel(3)) {
+            found.push((caps.get(0).unwrap().end(), Color::Rgb(r, g, b)));
+        }
+    }
+    found.sort_by_key(|(end, _)| *end);
+    // Byte offsets are turned into character indices, which is what the document uses
+    found
+        .into_iter()
+        .map(|(end, color)| (line[..end].chars().count(), color))
+        .collect()
+}
+
+impl Editor {
+    /// Whether colour swatches are drawn in a document
+    /// (which is only for the file types they are turned on for, or every type if none are given)
+    pub fn color_preview_on(&self, fc: &FileContainer) -> bool {
+        let document = config!(self.config, document);
+        let type_name = fc.file_type.as_ref().map(|t| t.name.as_str());
+        document.color_preview
+            && (document.color_preview_types.is_empty()
+                || type_name
+                    .is_some_and(|name| document.color_preview_types.iter().any(|t| t == name)))
+    }
+}
@@ -0,0 +1,103 @@
+/// Laying out the tab line, scrolling it so the active tab is always visible
+use crate::config;
+use kaolinite::utils::width;
+use mlua::Lua;
+use std::ops::Range;
+
+use super::Editor;
+
+/// Drawn at the edges of the tab line when there are tabs hidden in that direction
+pub const SCROLL_LEFT: char = '«';
+pub const SCROLL_RIGHT: char = '»';
+/// Drawn at the end of each tab when close buttons are enabled
+pub const TAB_CLOSE: char = '✕';
+
+/// Where a tab is drawn on the tab line
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TabPlacement {
+    /// Which document in the split this is the tab of
+    pub doc: usize,
+    /// The columns the header (and close button) take up
+    pub cols: Range<usize>,
+    /// The column of the close button
+    pub close: Option<usize>,
+}
+
+/// How the tab line is laid out in a split
+#[derive(Debug, Clone, Default)]
+pub struct TabLayout {
+    /// The rendered header of each tab that is shown
+    pub headers: Vec<String>,
+    pub placements: Vec<TabPlacement>,
+    /// The tab the active document is on (an index into `placements`)
+    pub active: usize,
+    /// Whether there are tabs hidden off the left and right edges
+    pub hidden_left: bool,
+    pub hidden_right: bool,
+}
+
+/// Work out which tabs fit on a tab line `w` columns wide, given the width of each
+/// (including any separator), so that the active tab is visible.
+/// Returns the range of tabs shown, leaving a column at each edge for the scroll
+/// indicators when they don't all fit
+pub fn visible_tabs(widths: &[usize], active: usize, w: usize) -> Range<usize> {
+    if widths.iter().sum::<usize>() <= w || widths.is_empty() {
+        return 0..widths.len();
+    }
+    let room = w.saturating_sub(2);
+    let active = active.min(widths.len() - 1);
+    // Show as many tabs before the active one as fit, then fill up with those after it
+    let mut start = active;
+    let mut used = widths[active];
+    while start > 0 && used + widths[start - 1] <= room {
+        start -= 1;
+        used += widths[start];
+    }
+    let mut end = active + 1;
+    while end < widths.len() && used + widths[end] <= room {
+        used += widths[end];
+        end += 1;
+    }
+    start..end
+}
+
+impl Editor {
+    /// Lay out the tab line of a split `w` columns wide
+    pub fn tab_layout(&mut self, ptr: &[usize], lua: &Lua, w: usize) -> TabLayout {
+        let tab_line = config!(self.config, tab_line);
+        let extra = usize::from(tab_line.separators) + 2 * usize::from(tab_line.close_button);
+        let active = self
+            .files
+            .get_atom(ptr.to_owned())
+            .map_or(0, |(_, doc_idx)| doc_idx);
+        let headers: Vec<String> = self
+            .files
+            .get_all(ptr.to_vec())
+            .iter()
+            .map(|file| tab_line.render(lua, file, &mut self.feedback))
+            .collect();
+        let widths: Vec<usize> = headers.iter().map(|h| width(h, 4) + extra).collect();
+        let shown = visible_tabs(&widths, active, w);
+        let overflow = shown.len() < headers.len();
+        let mut layout = TabLayout {
+            hidden_left: shown.start > 0,
+            hidden_right: shown.end < headers.len(),
+            active: active.saturating_sub(shown.start),
+            ..TabLayout::default()
+        };
+        let mut x = usize::from(overflow);
+        for doc in shown {
+            let header_width = width(&headers[doc], 4);
+            let close = tab_line.close_button.then_some(x + header_width);
+            let end = x + header_width + 2 * usize::from(tab_line.close_button);
+            layout.placements.push(TabPlacement {
+                doc,
+                cols: x..end,
+                close,
+            });
+            layout.headers.push(headers[doc].clone());
+            x = end + usize::from(tab_line.separators);
+        }
+        layout
+    }
+}
@@ -0,0 +1,152 @@
+/// Showing a document in more than one pane, each with its own cursor and scroll position
+use crate::error::Result;
+use crate::Feedback;
+use kaolinite::{Document, Loc};
+
+use super::{Editor, FileLayout};
+
+/// Where a pane is in the document it shows, which stays put when the document is copied
+/// in from another pane (the cursor and selection are kept as character indices)
+#[derive(Debug, Clone, Copy)]
+struct View {
+    loc: Loc,
+    selection_end: Loc,
+    block: Option<usize>,
+    offset: Loc,
+    old_cursor: usize,
+}
+
+impl View {
+    /// Take note of where a pane is in its document
+    fn of(doc: &Document) -> Self {
+        let char_loc = |loc: Loc| Loc::at(doc.character_idx(&loc), loc.y);
+        Self {
+            loc: char_loc(doc.cursor.loc),
+            selection_end: char_loc(doc.cursor.selection_end),
+            block: doc.cursor.block,
+            offset: doc.offset,
+            old_cursor: doc.old_cursor,
+        }
+    }
+
+    /// Put a pane back where it was, as near as the document's new contents allow.
+    /// The scroll position is kept as it was wherever it is still inside the document
+    fn restore(self, doc: &mut Document) {
+        let last = doc.file.len_lines().saturating_sub(1);
+        let clamp = |loc: Loc| Loc::at(loc.x, loc.y.min(last));
+        let furthest = self
+            .loc
+            .y
+            .max(self.selection_end.y)
+            .max(self.offset.y + doc.size.h);
+        doc.load_to(furthest + 1);
+        doc.move_to(&clamp(self.selection_end));
+        doc.select_to(&clamp(self.loc));
+        doc.cursor.block = self.block;
+        doc.old_cursor = self.old_cursor;
+        doc.offset = clamp(self.offset);
+    }
+}
+
+impl Editor {
+    /// Show the current document in a new pane, either beside it or below it
+    pub fn split_current(&mut self, side_by_side: bool) -> Result<()> {
+        let Some(mut fc) = self.files.get(self.ptr.clone()).cloned() else {
+            return Ok(());
+        };
+        // Unnamed documents can't be told apart from other unnamed documents
+        if fc.doc.file_name.is_none() && fc.scratch.is_none() {
+            self.feedback =
+                Feedback::Error("Save this document before showing it in two panes".to_string());
+            return Ok(());
+        }
+        // Both panes join the group of views the current one is part of
+        let view = fc.view.unwrap_or_else(|| self.new_view());
+        if let Some(current) = self.files.get_mut(self.ptr.clone()) {
+            current.view = Some(view);
+        }
+        fc.view = Some(view);
+        let layout = FileLayout::Atom(vec![fc], 0);
+        self.ptr = if side_by_side {
+            self.files.open_right(self.ptr.clone(), layout)
+        } else {
+            self.files.open_down(self.ptr.clone(), layout)
+        };
+        self.cache_old_ptr(&self.ptr.clone());
+        self.update_cwd();
+        Ok(())
+    }
+
+    /// Pick a number for a new group of views that no open document is using
+    fn new_view(&self) -> usize {
+        self.files
+            .all_documents(vec![])
+            .into_iter()
+            .filter_map(|(ptr, idx)| self.file_at(&ptr, idx).ok()?.view)
+            .max()
+            .map_or(0, |view| view + 1)
+    }
+
+    /// Find the other panes showing the same document as the one at `at`.
+    /// Documents that were opened separately never count, even if they share a path
+    pub fn views_of(&self, at: &(Vec<usize>, usize)) -> Vec<(Vec<usize>, usize)> {
+        let Some(view) = self.file_at(&at.0, at.1).ok().and_then(|fc| fc.view) else {
+            return vec![];
+        };
+        self.files
+            .all_documents(vec![])
+            .into_iter()
+            .filter(|other| {
+                other != at
+                    && self
+                        .file_at(&other.0, other.1)
+                        .is_ok_and(|fc| fc.view == Some(view))
+            })
+            .collect()
+    }
+
+    /// Copy the contents of the document at `at` into the other panes showing it,
+    /// leaving each pane's cursor and scroll position where they were.
+    /// Panes already up to date are left alone, unless `force` is set
+    pub fn share_changes(&mut self, at: &(Vec<usize>, usize), force: bool) {
+        let Ok(source) = self.file_at(&at.0, at.1) else {
+            return;
+        };
+        let generation = source.doc.generation;
+        // Only copy the document when something in it has changed
+        let stale: Vec<_> = self
+            .views_of(at)
+            .into_iter()
+            .filter(|(ptr, idx)| {
+                force
+                    || self
+                        .file_at(ptr, *idx)
+                        .is_ok_and(|fc| fc.doc.generation != generation)
+            })
+            .collect();
+        if stale.is_empty() {
+            return;
+        }
+        let (doc, highlighter) = (source.doc.clone(), source.highlighter.clone());
+        for (ptr, idx) in stale {
+            let Ok(fc) = self.file_at_mut(&ptr, idx) else {
+                continue;
+            };
+            let (view, size) = (View::of(&fc.doc), fc.doc.size);
+            let secondary_cursors = std::mem::take(&mut fc.doc.secondary_cursors);
+            fc.doc = doc.clone();
+            fc.doc.size = size;
+            view.restore(&mut fc.doc);
+            fc.doc.secondary_cursors = secondary_cursors;
+            fc.highlighter = highlighter.clone();
+            self.update_highlighter_for(&ptr, idx);
+        }
+    }
+
+    /// Copy any changes made in the focused pane into the other panes showing its document
+    pub fn share_focused_changes(&mut self) {
+        if let Some((_, idx)) = self.files.get_atom(self.ptr.clone()) {
+            self.share_changes(&(self.ptr.clone(), idx), false);
+        }
+    }
+}
@@ -0,0 +1,129 @@
+/// Distraction-free mode, and how the cursor looks (in each mode of editing, and blinking
+/// only once typing has stopped)
+use crate::config;
+use crate::config::CursorShape;
+use crate::Feedback;
+use std::time::Duration;
+
+use super::Editor;
+
+/// The settings distraction-free mode switched off, so they can be put back exactly as they were
+#[derive(Debug, Clone, Copy)]
+pub struct Zen {
+    tab_line: bool,
+    line_numbers: bool,
+}
+
+impl Editor {
+    /// Hide the tab line and line numbers, returning what they were set to
+    fn hide_chrome(&mut self) -> Zen {
+        let mut tab_line = self
+            .config
+            .tab_line
+            .borrow_mut::<config::TabLine>()
+            .unwrap();
+        let mut line_numbers = self
+            .config
+            .line_numbers
+            .borrow_mut::<config::LineNumbers>()
+            .unwrap();
+        let saved = Zen {
+            tab_line: tab_line.enabled,
+            line_numbers: line_numbers.enabled,
+        };
+        tab_line.enabled = false;
+        line_numbers.enabled = false;
+        saved
+    }
+
+    /// Put the tab line and line numbers back to how they were before distraction-free mode
+    fn restore_chrome(&mut self, saved: Zen) {
+        self.config
+            .tab_line
+            .borrow_mut::<config::TabLine>()
+            .unwrap()
+            .enabled = saved.tab_line;
+        self.config
+            .line_numbers
+            .borrow_mut::<config::LineNumbers>()
+            .unwrap()
+            .enabled = saved.line_numbers;
+    }
+
+    /// Turn distraction-free mode on or off, which hides the tab line, line numbers and
+    /// status line and keeps the text to a column down the middle of each document
+    pub fn toggle_zen(&mut self) {
+        if let Some(saved) = self.zen.take() {
+            self.restore_chrome(saved);
+        } else {
+            self.zen = Some(self.hide_chrome());
+        }
+        self.push_down = usize::from(config!(self.config, tab_line).enabled);
+        // What was drawn around the documents has to go, so everything is drawn from scratch
+        self.render_budget.forget();
+        self.terminal.clear();
+        self.needs_rerender = true;
+        self.feedback = Feedback::Info(format!(
+            "Distraction-free mode turned {}",
+            if self.zen.is_some() { "on" } else { "off" }
+        ));
+    }
+
+    /// Switch the tab line and line numbers off again after the configuration has been read,
+    /// remembering what the configuration set them to for when distraction-free mode ends
+    pub fn keep_zen(&mut self) {
+        if self.zen.is_some() {
+            self.zen = Some(self.hide_chrome());
+        }
+    }
+
+    /// How many rows are kept under each document for its status line
+    pub fn status_rows(&self) -> usize {
+        usize::from(self.zen.is_none() || config!(self.config, document).zen_status_line)
+    }
+
+    /// The widest the text of a document can be (only limited in distraction-free mode)
+    pub fn text_width(&self) -> usize {
+        if self.zen.is_some() {
+            config!(self.config, document).zen_width
+        } else {
+            usize::MAX
+        }
+    }
+
+    /// How far the text of a pane is pushed in to centre it in distraction-free mode
+    pub fn zen_margin(&self, at: &[usize]) -> usize {
+        if self.zen.is_none() {
+            return 0;
+        }
+        let Some((_, _, cols)) = self.render_cache.span.iter().find(|(ptr, _, _)| ptr == at) else {
+            return 0;
+        };
+        cols.len().saturating_sub(self.text_width()) / 2
+    }
+
+    /// How the cursor should look for what is being done:
+    /// typing over text, selecting, or editing as normal
+    pub fn cursor_shape(&self) -> CursorShape {
+        let terminal = config!(self.config, terminal);
+        let selecting = self.try_doc().is_some_and(|doc| !doc.is_selection_empty());
+        if self.overtype {
+            terminal.cursor_style_overtype
+        } else if selecting {
+            terminal.cursor_style_selection
+        } else {
+            terminal.cursor_style
+        }
+    }
+
+    /// Keep the cursor still while typing, letting it blink once there has been no typing for
+    /// `terminal.cursor_blink_delay` milliseconds, returning true if the cursor has changed
+    pub fn update_cursor_blink(&mut self) -> bool {
+        let delay = config!(self.config, terminal).cursor_blink_delay;
+        if delay == 0 {
+            return false;
+        }
+        let idle = self.last_active.elapsed() >= Duration::from_millis(delay);
+        self.terminal.blink_cursor(idle)
+    }
+}
@@ -11,6 +11,9 @@ error_set! {
                 KError::NoFileName => "This document has no file name, please use 'save as' instead".to_string(),
                 KError::OutOfRange => "Requested operation is out of range".to_string(),
                 KError::ReadOnlyFile => "This file is read only and can't be saved or edited".to_string(),
+                KError::Unrepresentable => "This document contains characters that can't be saved in its encoding".to_string(),
+                KError::UnknownEncoding => "That encoding isn't recognised".to_string(),
+                KError::BinaryPreview => "This is a preview of a binary file, run 'force text' to open it as text".to_string(),
                 KError::Rope(rerr) => format!("Backend had an issue processing text: {rerr}"),
                 KError::Io(ioerr) => format!("I/O Error: {ioerr}"),
             }
@@ -28,7 +31,15 @@ error_set! {
         AlreadyOpen {
             file: String,
         },
+        #[display("{}", msg)]
+        Macro {
+            msg: String,
+        },
         InvalidPath,
+        #[display("That document is no longer open")]
+        DocumentClosed,
+        #[display("This needs an answer from the user, which can't be given without the editor open")]
+        Headless,
         // None, <--- Needed???
     };
 }
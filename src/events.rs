@@ -1,8 +1,18 @@
-use crate::{ged, handle_lua_error, CEvent, Editor, Feedback, KeyEvent, KeyEventKind, Result};
-use crossterm::event::{poll, read};
+use crate::config::placeholder_idle_tick;
+use crate::editor::pasted_files;
+use crate::remote::answer;
+use crate::{
+    config, ged, handle_lua_error, CEvent, Editor, Feedback, KeyEvent, KeyEventKind, Result,
+};
+use crossterm::event::{poll, read, KeyCode as KCode, KeyModifiers as KMod};
 use mlua::{AnyUserData, Lua};
 use std::time::Duration;
 
+/// Key presses that come closer together than this (in milliseconds) are part of a burst
+const BURST_GAP: u64 = 2;
+/// The fewest key presses in a burst that could be pasted text
+const BURST_LENGTH: usize = 3;
+
 #[allow(unused_variables)]
 pub fn term_force(editor: &AnyUserData) -> bool {
     #[cfg(not(target_os = "windows"))]
@@ -48,6 +58,68 @@ pub fn wait_for_event(editor: &AnyUserData, lua: &Lua) -> Result<CEvent> {
                             Feedback::Warning(format!("Function '{task}' was not found"));
                     }
                 }
+                // Carry out requests sent from other shells (with ox --remote)
+                let connection = ged!(mut &editor).poll_remote();
+                if let Some(connection) = connection {
+                    answer(editor, lua, connection);
+                    ged!(mut &editor).needs_rerender = true;
+                    ged!(mut &editor).render(lua)?;
+                }
+                // Keep copies of documents with unsaved changes every so often
+                ged!(mut &editor).backup_idle_tick();
+                // Keep swap files of documents with unsaved changes, in case of a crash
+                ged!(mut &editor).swap_idle_tick();
+                // The rest waits until the terminal has focus again (all is redrawn then)
+                if !ged!(&editor).focus.focused {
+                    continue;
+                }
+                // Keep the list of files in the project up to date
+                ged!(mut &editor).file_index.idle_tick();
+                // Show the git status of files in the file tree once it is known
+                if ged!(mut &editor).file_tree_git.poll() {
+                    ged!(mut &editor).needs_rerender = true;
+                    ged!(mut &editor).render(lua)?;
+                }
+                // Show the branch in the status line once git has been asked
+                if ged!(mut &editor).status_info.poll() {
+                    ged!(mut &editor).needs_rerender = true;
+                    ged!(mut &editor).render(lua)?;
+                }
+                // Show how far along large files are, and swap them in once they are read
+                if ged!(mut &editor).loading_idle_tick() {
+                    ged!(mut &editor).render(lua)?;
+                }
+                // Notice files that other programs have changed or removed
+                if ged!(mut &editor).on_disk_idle_tick() {
+                    ged!(mut &editor).render(lua)?;
+                }
+                // Pick up any changes to the signs reported by external tools
+                if ged!(mut &editor).signs_idle_tick() {
+                    ged!(mut &editor).render(lua)?;
+                }
+                // Read the configuration file again if it has been changed
+                if ged!(mut &editor).config_idle_tick(lua) {
+                    ged!(mut &editor).render(lua)?;
+                }
+                // Let the cursor blink once typing has stopped
+                if ged!(mut &editor).update_cursor_blink() {
+                    ged!(mut &editor).terminal.flush()?;
+                }
+                // Put the status line back once the visual bell is over
+                if ged!(mut &editor).bell_idle_tick() {
+                    ged!(mut &editor).render(lua)?;
+                }
+                // Draw any panes that were put off to keep typing responsive
+                if ged!(mut &editor).render_budget.deferred {
+                    ged!(mut &editor).render_budget.catch_up = true;
+                    ged!(mut &editor).needs_rerender = true;
+                    ged!(mut &editor).render(lua)?;
+                }
+                // Refresh custom placeholders every so often
+                if placeholder_idle_tick(lua) {
+                    ged!(mut &editor).needs_rerender = true;
+                    ged!(mut &editor).render(lua)?;
+                }
                 // If a terminal dictates, force a rerender
                 #[cfg(not(target_os = "windows"))]
                 if was_term {
@@ -71,12 +143,66 @@ pub fn wait_for_event(editor: &AnyUserData, lua: &Lua) -> Result<CEvent> {
                 ..
             })
         ) {
-            return Ok(event);
+            return Ok(burst_paste(&mut ged!(mut &editor), event));
+        }
+    }
+}
+
+/// The character a key press types, if it is a plain one
+fn typed_char(event: &CEvent) -> Option<char> {
+    match event {
+        CEvent::Key(KeyEvent {
+            code: KCode::Char(ch),
+            modifiers: KMod::NONE | KMod::SHIFT,
+            kind: KeyEventKind::Press | KeyEventKind::Repeat,
+            ..
+        }) => Some(*ch),
+        _ => None,
+    }
+}
+
+/// Terminals without bracketed paste type pasted text in as a burst of key presses,
+/// so a burst that names existing files is turned back into a paste (to offer to open them)
+fn burst_paste(editor: &mut Editor, event: CEvent) -> CEvent {
+    let Some(ch) = typed_char(&event) else {
+        return event;
+    };
+    let enabled = config!(editor.config, document).open_pasted_paths;
+    let pending = !editor.pending_events.is_empty() || editor.macro_man.playing;
+    if !enabled || pending || editor.try_doc().is_none() {
+        return event;
+    }
+    let mut text = ch.to_string();
+    // Nobody types fast enough for their key presses to count as a burst
+    while let Ok(true) = poll(Duration::from_millis(BURST_GAP)) {
+        let Ok(next) = read() else {
+            break;
+        };
+        if let CEvent::Key(KeyEvent {
+            kind: KeyEventKind::Release,
+            ..
+        }) = next
+        {
+            continue;
+        }
+        let ch = typed_char(&next);
+        editor.pending_events.push_back(next);
+        match ch {
+            Some(ch) => text.push(ch),
+            None => break,
         }
     }
+    let typed = editor.pending_events.len() + 1;
+    if typed >= BURST_LENGTH && typed == text.chars().count() && pasted_files(&text).is_some() {
+        editor.pending_events.clear();
+        CEvent::Paste(text)
+    } else {
+        event
+    }
 }
 
-/// Wait for event, but without the task manager (and it hogs editor)
+/// Wait for event, but without the task manager (and it hogs editor).
+/// This is used by prompts and menus, so resizes are dealt with here on their behalf
 pub fn wait_for_event_hog(editor: &mut Editor) -> CEvent {
     loop {
         // Attempt to get an event
@@ -85,6 +211,11 @@ pub fn wait_for_event_hog(editor: &mut Editor) -> CEvent {
             continue;
         };
 
+        // Don't leave what was drawn at the old size on screen
+        if let CEvent::Resize(..) = event {
+            editor.resized_under_menu();
+        }
+
         // Block certain events from passing through
         if !matches!(
             event,
@@ -100,7 +231,10 @@ pub fn wait_for_event_hog(editor: &mut Editor) -> CEvent {
 
 // Find out where to source an event from and source it
 pub fn get_event(editor: &mut Editor) -> Option<CEvent> {
-    if let Some(ev) = editor.macro_man.next() {
+    if let Some(ev) = editor.pending_events.pop_front() {
+        // Take from the events that were read ahead of time
+        Some(ev)
+    } else if let Some(ev) = editor.macro_man.next_event() {
         // Take from macro man
         Some(ev)
     } else if let Ok(true) = poll(Duration::from_millis(50)) {
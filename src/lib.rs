@@ -0,0 +1,660 @@
+//! The ox editor, which the binary runs (and tests drive through a backend kept in memory)
+#![warn(clippy::all, clippy::pedantic)]
+#![allow(
+    clippy::missing_errors_doc,
+    clippy::missing_panics_doc,
+    clippy::must_use_candidate
+)]
+
+pub mod backend;
+pub mod cli;
+pub mod config;
+pub mod editor;
+pub mod error;
+pub mod events;
+#[cfg(not(target_os = "windows"))]
+pub mod pty;
+pub mod remote;
+pub mod ui;
+
+use cli::{CommandLineInterface, FileToOpen, Position};
+use config::{
+    get_listeners, key_to_string, load_plugins, plugin_failures, run_key, run_key_before,
+    GreetingAction, PLUGIN_BOOTSTRAP, PLUGIN_MANAGER, PLUGIN_NETWORKING, PLUGIN_RUN,
+};
+use crossterm::event::{
+    Event as CEvent, KeyCode as KCode, KeyEvent, KeyEventKind, KeyModifiers as KMod,
+};
+use editor::{
+    allowed_by_multi_cursor, handle_multiple_cursors, EditPoint, Editor, FileContainer, FileIndex,
+    FileTypes,
+};
+use error::{OxError, Result};
+use events::wait_for_event;
+use kaolinite::event::Error as KError;
+use kaolinite::searching::Searcher;
+use kaolinite::utils::{file_or_dir, get_absolute_path, get_cwd};
+use mlua::Error::{RuntimeError, SyntaxError};
+use mlua::{AnyUserData, Lua};
+use std::collections::VecDeque;
+use std::io::ErrorKind;
+use std::result::Result as RResult;
+use ui::{fatal_error, Feedback};
+
+/// Get editor helper macro
+#[macro_export]
+macro_rules! ged {
+    ($editor:expr) => {
+        $editor.borrow::<Editor>().unwrap()
+    };
+    (mut $editor:expr) => {
+        $editor.borrow_mut::<Editor>().unwrap()
+    };
+}
+
+/// Set up the editor and its configuration, ready for files to be opened
+/// (plug-ins aren't run when headless, so scripts behave the same on every machine)
+pub fn prepare(cli: &CommandLineInterface, lua: &Lua, headless: bool) -> Result<AnyUserData> {
+    // Find out the terminal's background colour before anything is drawn
+    if !headless {
+        if let Some(background) = ui::query_background() {
+            lua.set_app_data(background);
+        }
+    }
+
+    // Create editor
+    let mut editor = match Editor::new(lua) {
+        Ok(editor) => editor,
+        Err(error) => panic!("Editor failed to start: {error:?}"),
+    };
+    editor.headless = headless;
+
+    // Push editor into lua
+    let editor = lua.create_userdata(editor)?;
+    lua.globals().set("editor", editor.clone())?;
+
+    // Inject the networking library for plug-ins to use
+    handle_lua_error(
+        "",
+        lua.load(PLUGIN_NETWORKING).exec(),
+        &mut ged!(mut &editor).feedback,
+    );
+
+    // Load config and initialise (errors in the config file are reported, and the defaults used)
+    lua.load(PLUGIN_BOOTSTRAP).exec()?;
+    ged!(mut &editor).load_config(&cli.config_path, lua);
+    if headless {
+        ged!(mut &editor).load_file_types(lua)?;
+        return Ok(editor);
+    }
+
+    // Run plug-ins
+    handle_lua_error(
+        "",
+        lua.load(PLUGIN_RUN).exec(),
+        &mut ged!(mut &editor).feedback,
+    );
+
+    // Run plug-ins that are installed with manifests
+    let plugins = load_plugins(lua);
+    if let Some(msg) = plugin_failures(&plugins) {
+        ged!(mut &editor).feedback = Feedback::Error(msg);
+    }
+    lua.set_app_data(plugins);
+
+    // Load in the file types
+    ged!(mut &editor).load_file_types(lua)?;
+    Ok(editor)
+}
+
+/// Run Lua over each file given on the command line (saving them after if asked to),
+/// without a terminal, returning the exit code
+pub fn run_headless(cli: &CommandLineInterface) -> i32 {
+    match headless(cli) {
+        Ok(true) => 0,
+        Ok(false) => 1,
+        Err(err) => {
+            eprintln!("Error: {err}");
+            1
+        }
+    }
+}
+
+/// Run Lua over the files headlessly, returning false if anything went wrong
+fn headless(cli: &CommandLineInterface) -> Result<bool> {
+    let mut code = vec![];
+    if let Some(path) = &cli.script {
+        code.push((path.clone(), std::fs::read_to_string(path)?));
+    }
+    if let Some(snippet) = &cli.lua {
+        code.push(("--lua".to_string(), snippet.clone()));
+    }
+    let lua = Lua::new();
+    let editor = prepare(cli, &lua, true)?;
+    ged!(mut &editor).flush_feedback();
+    let files: Vec<&FileToOpen> = cli
+        .to_open
+        .iter()
+        .filter(|file| file_or_dir(&file.name) != "directory")
+        .collect();
+    // Without any files, the Lua runs once on an empty document
+    if files.is_empty() {
+        ged!(mut &editor).blank()?;
+    }
+    for file in &files {
+        let result = ged!(mut &editor).open_or_new(file.name.clone());
+        if let Err(err) = result {
+            eprintln!("Error: Couldn't open '{}': {err}", file.name);
+            ged!(mut &editor).headless_errors += 1;
+            continue;
+        }
+        ged!(mut &editor).next();
+        let ptr = ged!(&editor).ptr.clone();
+        let idx = ged!(&editor)
+            .files
+            .get_atom(ptr.clone())
+            .map_or(0, |(_, idx)| idx);
+        if let Some(Position { line, column }) = file.position {
+            ged!(mut &editor).go_to_position(idx, line, column);
+        }
+        if cli.flags.read_only {
+            if let Some(doc) = ged!(mut &editor).get_doc(idx) {
+                doc.info.read_only = true;
+            }
+        }
+        ged!(mut &editor).flush_feedback();
+        run_headless_code(&editor, &lua, &code);
+        // Only documents that were changed are saved
+        let changed = ged!(&editor)
+            .file_at(&ptr, idx)
+            .is_ok_and(FileContainer::unsaved_changes);
+        if cli.flags.write && changed {
+            let result = ged!(mut &editor).save();
+            if let Err(err) = result {
+                ged!(mut &editor).feedback =
+                    Feedback::Error(format!("Couldn't save '{}': {err}", file.name));
+            }
+            ged!(mut &editor).flush_feedback();
+        }
+    }
+    if files.is_empty() {
+        run_headless_code(&editor, &lua, &code);
+    }
+    let errors = ged!(&editor).headless_errors;
+    Ok(errors == 0)
+}
+
+/// Run each piece of Lua on the current document, reporting any errors
+fn run_headless_code(editor: &AnyUserData, lua: &Lua, code: &[(String, String)]) {
+    for (name, code) in code {
+        let result = lua.load(code.as_str()).set_name(name.as_str()).exec();
+        handle_lua_error("", result, &mut ged!(mut &editor).feedback);
+        ged!(mut &editor).flush_feedback();
+    }
+}
+
+/// Run the editor, returning the exit code
+#[allow(clippy::too_many_lines)]
+pub fn run(cli: &CommandLineInterface) -> Result<i32> {
+    // Create lua interpreter
+    let lua = Lua::new();
+    let editor = prepare(cli, &lua, false)?;
+
+    // Ensure focus is on the initial atom
+    let init_atom = ged!(&editor).files.empty_atoms(vec![]);
+    if let Some(init_atom) = init_atom {
+        ged!(mut &editor).ptr = init_atom;
+    }
+
+    // Open files user has asked to open
+    let cwd = get_cwd().unwrap_or(".".to_string());
+    ged!(mut &editor).file_index = FileIndex::new(&cwd);
+    let mut tree_dir: Option<String> = None;
+    let mut c = 0;
+    for request in &cli.to_open {
+        let file = &request.name;
+        // Reset cwd
+        let _ = std::env::set_current_dir(&cwd);
+        // Directories are opened in the file tree (only one fits)
+        if file_or_dir(file) == "directory" {
+            if tree_dir.is_some() {
+                ged!(mut &editor).feedback = Feedback::Warning(format!(
+                    "Only one directory can be opened, skipped '{file}'"
+                ));
+            } else {
+                tree_dir = Some(get_absolute_path(file).unwrap_or_else(|| file.clone()));
+            }
+            continue;
+        }
+        // Open the file
+        let result = ged!(mut &editor).open_or_new(file.to_string());
+        handle_file_opening(&editor, result, file);
+        // Move to where the user asked to start
+        if let Some(Position { line, column }) = request.position {
+            ged!(mut &editor).go_to_position(c, line, column);
+        }
+        // Set read only if applicable
+        if cli.flags.read_only {
+            if let Some(doc) = ged!(mut &editor).get_doc(c) {
+                doc.info.read_only = true;
+            }
+        }
+        // Set highlighter if applicable
+        if let Some(ref file_type) = cli.file_type {
+            let tab_width = config!(ged!(&editor).config, document).tab_width;
+            let file_type = config!(ged!(mut &editor).config, document)
+                .file_types
+                .get_name(file_type)
+                .unwrap_or_default();
+            let mut highlighter = file_type.get_highlighter(&ged!(&editor).config, tab_width);
+            let mut editor = ged!(mut &editor);
+            let current_ptr = editor.ptr.clone();
+            if let Some(file) = editor
+                .files
+                .get_atom_mut(current_ptr)
+                .and_then(|(fcs, _)| fcs.get_mut(c))
+            {
+                highlighter.run(&file.doc.lines);
+                file.highlighter = highlighter;
+                file.file_type = Some(file_type);
+            }
+        }
+        // Move the pointer to the file we just created
+        ged!(mut &editor).next();
+        c += 1;
+    }
+    // Reset the pointer back to the first document
+    let current_ptr = ged!(mut &editor).ptr.clone();
+    ged!(mut &editor).files.move_to(current_ptr, 0);
+
+    // Compare the first file with the one the user asked to compare it with
+    if let Some(against) = &cli.diff {
+        let mut holder = ged!(mut &editor);
+        if let Err(err) = holder.toggle_diff(Some(against)) {
+            holder.feedback = Feedback::Error(err.to_string());
+        }
+    }
+
+    // Handle stdin if applicable
+    if cli.flags.stdin {
+        let stdin = cli::get_stdin();
+        let mut holder = ged!(mut &editor);
+        holder.open_from_string(stdin, cli.file_type.clone())?;
+        holder.next();
+        let ptr = holder.ptr.clone();
+        if let Some((files, ptr)) = holder.files.get_atom_mut(ptr) {
+            files[*ptr].stdin = true;
+        }
+        if cli.flags.read_only {
+            if let Some(doc) = holder.try_doc_mut() {
+                doc.info.read_only = true;
+            }
+        }
+    }
+
+    // Create a blank document if none are opened
+    ged!(mut &editor).new_if_empty()?;
+
+    // Open the file tree at the directory the user asked for
+    if let Some(dir) = tree_dir {
+        let focus_documents = c > 0 || cli.flags.stdin;
+        ged!(mut &editor).open_file_tree_at(&dir, focus_documents);
+    }
+
+    // Add in the plugin manager
+    handle_lua_error(
+        "",
+        lua.load(PLUGIN_MANAGER).exec(),
+        &mut ged!(mut &editor).feedback,
+    );
+
+    // Load in named macros
+    let problems = ged!(mut &editor).macro_man.load(&lua);
+    match problems {
+        Ok(problems) if problems.len() > 1 => {
+            let more = problems.len() - 1;
+            ged!(mut &editor).feedback =
+                Feedback::Warning(format!("{} (and {more} more problems)", problems[0]));
+        }
+        Ok(problems) if problems.len() == 1 => {
+            ged!(mut &editor).feedback = Feedback::Warning(problems[0].clone());
+        }
+        Ok(_) => (),
+        Err(err) => {
+            ged!(mut &editor).feedback = Feedback::Error(format!("Failed to load macros: {err}"));
+        }
+    }
+
+    // Run the editor and handle errors if applicable
+    ged!(&editor).update_cwd();
+    ged!(mut &editor).init()?;
+    // Listen for requests from other shells (sent with ox --remote)
+    ged!(mut &editor).start_remote();
+    // Offer to bring back changes that were lost when ox last crashed
+    if let Err(err) = ged!(mut &editor).recover_swaps(&lua) {
+        ged!(mut &editor).feedback = Feedback::Error(err.to_string());
+    }
+    let mut execute: VecDeque<String> = cli.execute.clone().into();
+    while ged!(&editor).active {
+        // Run commands from the command line (waiting for any macros they play to finish)
+        if !ged!(&editor).macro_man.playing {
+            if let Some(command) = execute.pop_front() {
+                run_editor_command(&editor, &command, &lua);
+                continue;
+            }
+        }
+
+        // Render (unless a macro is being played, in which case, don't bother)
+        if !ged!(&editor).macro_man.playing || ged!(&editor).macro_man.just_completed {
+            ged!(mut &editor).render(&lua)?;
+        }
+
+        // Wait for an event
+        let event = wait_for_event(&editor, &lua)?;
+
+        // Handle the event
+        let before = ged!(&editor)
+            .try_doc()
+            .map(EditPoint::of)
+            .unwrap_or_default();
+        handle_event(&editor, &event, &lua)?;
+
+        // Handle multi cursors
+        if let CEvent::Key(_) = event {
+            let has_multicursors = !ged!(&editor)
+                .try_doc()
+                .map_or(true, |doc| doc.secondary_cursors.is_empty());
+            if ged!(&editor).active && allowed_by_multi_cursor(&event) && has_multicursors {
+                handle_multiple_cursors(&editor, &event, &lua, &before)?;
+            }
+        }
+
+        ged!(mut &editor).update_highlighter();
+
+        // Keep any completions on offer in step with what was typed
+        ged!(mut &editor).update_completion(&event);
+
+        // Check for any commands to run
+        let command = ged!(&editor).command.clone();
+        if let Some(command) = command {
+            run_editor_command(&editor, &command, &lua);
+        }
+        ged!(mut &editor).command = None;
+
+        // Let the user know if anything went wrong (resizes leave the feedback as it was)
+        if !matches!(event, CEvent::Resize(..)) {
+            ged!(mut &editor).bell_on_error();
+        }
+    }
+
+    // Run any plugin cleanup operations
+    let result = lua.load(run_key("exit")).exec();
+    handle_lua_error("exit", result, &mut ged!(mut &editor).feedback);
+
+    ged!(mut &editor).terminal.end()?;
+    let code = ged!(&editor).exit_code;
+    Ok(code)
+}
+
+/// Handle an event (a macro that finishes playing back is committed as a single change)
+pub fn handle_event(editor: &AnyUserData, event: &CEvent, lua: &Lua) -> Result<()> {
+    let macro_done = {
+        let macro_man = &ged!(&editor).macro_man;
+        macro_man.just_completed && !macro_man.playing
+    };
+    let result = handle_counted_event(editor, event, lua);
+    if macro_done {
+        ged!(mut &editor).commit();
+    }
+    result
+}
+
+/// Handle an event (keys pressed after a count are repeated that many times)
+fn handle_counted_event(editor: &AnyUserData, event: &CEvent, lua: &Lua) -> Result<()> {
+    // Focus changes aren't something the user did, so they leave everything else as it was
+    if let CEvent::FocusGained | CEvent::FocusLost = event {
+        ged!(mut &editor).handle_focus(matches!(event, CEvent::FocusGained));
+        return Ok(());
+    }
+    let count = ged!(&editor).count.pending;
+    match (event, count) {
+        (CEvent::Key(key), Some(count)) if key.kind != KeyEventKind::Release => {
+            match (key.modifiers, key.code) {
+                (KMod::NONE, KCode::Esc) => ged!(mut &editor).cancel_count(),
+                // Digits go on the end of the count rather than into the document
+                (KMod::NONE, KCode::Char(ch @ '0'..='9')) => {
+                    ged!(mut &editor).count_digit(ch as usize - '0' as usize);
+                }
+                _ => return repeat_event(editor, event, lua, count),
+            }
+            Ok(())
+        }
+        _ => dispatch_event(editor, event, lua),
+    }
+}
+
+/// Handle a key pressed after a count, as a single change to undo, stopping early once
+/// the key stops having any effect (a digit added to the count is handled just once)
+fn repeat_event(editor: &AnyUserData, event: &CEvent, lua: &Lua, count: usize) -> Result<()> {
+    ged!(mut &editor).count.typed = false;
+    let limit = ged!(&editor).count_limit(count);
+    let batch = ged!(mut &editor).begin_repeat();
+    let mut result = Ok(());
+    for _ in 0..limit {
+        let before = ged!(&editor).repeat_state();
+        result = dispatch_event(editor, event, lua);
+        let editor = ged!(&editor);
+        let failed = result.is_err() || matches!(editor.feedback, Feedback::Error(_));
+        let stuck = editor.repeat_state() == before;
+        if editor.count.typed || failed || stuck || !editor.active {
+            break;
+        }
+    }
+    ged!(mut &editor).end_repeat(batch);
+    result
+}
+
+/// Handle an event, running any plug-ins that listen out for it
+fn dispatch_event(editor: &AnyUserData, event: &CEvent, lua: &Lua) -> Result<()> {
+    // While the greeting is showing, its entries are chosen by their keys or by clicking them
+    let choice = ged!(mut &editor).greeting_choice(lua, event);
+    if let Some(action) = choice {
+        run_greeting_action(editor, lua, action);
+        return Ok(());
+    }
+
+    // Clear screen of temporary items (expect on resize event)
+    if !matches!(event, CEvent::Resize(_, _)) {
+        ged!(mut &editor).greet = false;
+        ged!(mut &editor).feedback = Feedback::None;
+    }
+
+    // While completions are offered, the keys that pick one go to the popup and nowhere else
+    if let CEvent::Key(key) = event {
+        if ged!(mut &editor).completion_key(key.modifiers, key.code) {
+            return Ok(());
+        }
+    }
+
+    // Handle plug-in before key press mappings
+    if let CEvent::Key(key) = event {
+        let key_str = key_to_string(key.modifiers, key.code);
+        let code = run_key_before(&key_str);
+        let result = lua.load(&code).exec();
+        handle_lua_error(&key_str, result, &mut ged!(mut &editor).feedback);
+    }
+
+    // Handle paste event (before event)
+    if let CEvent::Paste(ref paste_text) = event {
+        let listeners = get_listeners("before:paste", lua)?;
+        for listener in listeners {
+            handle_lua_error(
+                "paste",
+                listener.call(paste_text.clone()),
+                &mut ged!(mut &editor).feedback,
+            );
+        }
+    }
+
+    // Actually handle editor event (errors included)
+    let event_result = ged!(mut &editor).handle_event(lua, event.clone());
+    if let Err(err) = event_result {
+        // Nicely display error to user
+        match err {
+            OxError::Lua(err) => {
+                handle_lua_error("event", Err(err), &mut ged!(mut &editor).feedback);
+            }
+            OxError::AlreadyOpen { file } => {
+                ged!(mut &editor).feedback =
+                    Feedback::Error(format!("File '{file}' is already open"));
+            }
+            _ => ged!(mut &editor).feedback = Feedback::Error(format!("{err:?}")),
+        }
+    }
+
+    // Handle paste event (after event)
+    if let CEvent::Paste(ref paste_text) = event {
+        let listeners = get_listeners("paste", lua)?;
+        for listener in listeners {
+            handle_lua_error(
+                "paste",
+                listener.call(paste_text.clone()),
+                &mut ged!(mut &editor).feedback,
+            );
+        }
+    }
+
+    // Handle plug-in after key press mappings (if no errors occured)
+    if let CEvent::Key(key) = event {
+        let key_str = key_to_string(key.modifiers, key.code);
+        let code = run_key(&key_str);
+        let result = lua.load(&code).exec();
+        handle_lua_error(&key_str, result, &mut ged!(mut &editor).feedback);
+    }
+
+    // Keep other panes showing the same document up to date
+    ged!(mut &editor).share_focused_changes();
+
+    // Explain any sign the cursor has landed on
+    ged!(mut &editor).show_sign();
+
+    Ok(())
+}
+
+/// Carry out what a greeting entry does, which dismisses the greeting
+fn run_greeting_action(editor: &AnyUserData, lua: &Lua, action: GreetingAction) {
+    ged!(mut &editor).greet = false;
+    ged!(mut &editor).feedback = Feedback::None;
+    match action {
+        GreetingAction::Named(name) => ged!(mut &editor).greeting_action(lua, &name),
+        // The editor isn't borrowed while the function runs, as it is likely to use it
+        GreetingAction::Lua(func) => {
+            let result = func.call::<()>(());
+            handle_lua_error("greeting", result, &mut ged!(mut &editor).feedback);
+            ged!(mut &editor).needs_rerender = true;
+        }
+    }
+}
+
+/// Handle a lua error, showing the user an informative error
+pub fn handle_lua_error(key_str: &str, error: RResult<(), mlua::Error>, feedback: &mut Feedback) {
+    match error {
+        // All good
+        Ok(()) => (),
+        // Handle a runtime error
+        Err(RuntimeError(msg)) => {
+            let msg = msg.split('\n').collect::<Vec<&str>>();
+            // Extract description
+            let description = msg.first().unwrap_or(&"No Message Text");
+            // See if there is any additional error location information
+            let mut error_line_finder = Searcher::new(r"^\s*(.+:\d+):.*$");
+            let mut location_line = msg
+                .iter()
+                .skip(1)
+                .position(|line| error_line_finder.lfind(line).is_some());
+            // Don't attach additional location if description already includes it
+            if error_line_finder.lfind(description).is_some() {
+                location_line = None;
+            }
+            // Put together the message (attaching location if not already provided)
+            let msg = if let Some(trace) = location_line {
+                // There is additional line info, attach it
+                let location = msg[trace + 1]
+                    .to_string()
+                    .trim()
+                    .split(':')
+                    .take(2)
+                    .collect::<Vec<_>>()
+                    .join(" on line ");
+                format!("{location}: {description}")
+            } else {
+                (*description).to_string()
+            };
+            if msg.ends_with("key not bound") {
+                // Key was not bound, issue a warning would be helpful
+                let key_str = key_str.replace(' ', "space");
+                if key_str.contains('_') && key_str != "_" && !key_str.starts_with("shift") {
+                    *feedback = Feedback::Warning(format!("The key {key_str} is not bound"));
+                }
+            } else if msg.ends_with("command not found") {
+                // Command was not found, issue an error
+                *feedback = Feedback::Error(format!("The command '{key_str}' is not defined"));
+            } else {
+                // Some other runtime error
+                *feedback = Feedback::Error(msg.to_string());
+            }
+        }
+        // Handle a syntax error
+        Err(SyntaxError { message, .. }) => {
+            *feedback = Feedback::Error(format!("Syntax Error: {message:?}"));
+        }
+        // Other miscellaneous error
+        Err(err) => {
+            *feedback = Feedback::Error(format!("Failed to run Lua code: {err:?}"));
+        }
+    }
+}
+
+/// Handle opening files
+fn handle_file_opening(editor: &AnyUserData, result: Result<()>, name: &str) {
+    match result {
+        Ok(()) => (),
+        Err(OxError::AlreadyOpen { .. }) => {
+            let len = ged!(&editor).files.len().saturating_sub(1);
+            let current_ptr = ged!(&editor).ptr.clone();
+            ged!(mut &editor).files.move_to(current_ptr, len);
+        }
+        Err(OxError::Kaolinite(kerr)) => match kerr {
+            KError::Io(ioerr) => match ioerr.kind() {
+                ErrorKind::NotFound => fatal_error(&format!("File '{name}' not found")),
+                ErrorKind::PermissionDenied => {
+                    fatal_error(&format!("Permission to read file '{name}' denied"));
+                }
+                ErrorKind::IsADirectory => {
+                    fatal_error(&format!("'{name}' is a directory, not a file"));
+                }
+                ErrorKind::ReadOnlyFilesystem => fatal_error("You are on a read only file system"),
+                ErrorKind::ResourceBusy => fatal_error(&format!("The resource '{name}' is busy")),
+                ErrorKind::OutOfMemory => fatal_error("You are out of memory"),
+                kind => fatal_error(&format!("I/O error occured: {kind:?}")),
+            },
+            _ => fatal_error(&format!("Backend error opening '{name}': {kerr:?}")),
+        },
+        result => fatal_error(&format!("Error opening file '{name}': {result:?}")),
+    }
+}
+
+/// Run a command in the editor
+fn run_editor_command(editor: &AnyUserData, cmd: &str, lua: &Lua) {
+    let cmd = cmd.replace('\'', "\\'").to_string();
+    if let [subcmd, arguments @ ..] = cmd.split(' ').collect::<Vec<&str>>().as_slice() {
+        let arguments = arguments.join("', '");
+        let code =
+            format!("(commands['{subcmd}'] or error('command not found'))({{'{arguments}'}})");
+        handle_lua_error(
+            subcmd,
+            lua.load(code).exec(),
+            &mut ged!(mut &editor).feedback,
+        );
+    }
+}
@@ -450,6 +450,21 @@ file_types = {
         modelines = {},
         color = "darkblue",
     },
+    ["Git Commit"] = {
+        icon = "󰊢 ",
+        files = {"COMMIT_EDITMSG", "MERGE_MSG"},
+        extensions = {},
+        modelines = {},
+        color = "orange",
+        rulers = {50, 72},
+    },
+    ["Git Rebase"] = {
+        icon = "󰊢 ",
+        files = {"git-rebase-todo"},
+        extensions = {},
+        modelines = {},
+        color = "orange",
+    },
     ["GLSL"] = {
         icon = " ",
         files = {},
@@ -38,17 +38,50 @@ function remap_keys(from, to)
     end
 end
 
+-- Names of keys that are handled under a different name
+key_aliases = {
+    ["space"] = " ",
+    ["ctrl_space"] = "ctrl_ ",
+    ["alt_space"] = "alt_ ",
+    ["ctrl_alt_space"] = "ctrl_alt_ ",
+    ["shift_tab"] = "shift_backtab",
+    ["before:space"] = "before: ",
+    ["before:ctrl_space"] = "before:ctrl_ ",
+    ["before:alt_space"] = "before:alt_ ",
+    ["before:ctrl_alt_space"] = "before:ctrl_alt_ ",
+    ["before:shift_tab"] = "before:shift_backtab",
+}
+
 -- Remap space keys
-remap_keys("space", " ")
-remap_keys("ctrl_space", "ctrl_ ")
-remap_keys("alt_space", "alt_ ")
-remap_keys("ctrl_alt_space", "ctrl_alt_ ")
-remap_keys("shift_tab", "shift_backtab")
-remap_keys("before:space", "before: ")
-remap_keys("before:ctrl_space", "before:ctrl_ ")
-remap_keys("before:alt_space", "before:alt_ ")
-remap_keys("before:ctrl_alt_space", "before:ctrl_alt_ ")
-remap_keys("before:shift_tab", "before:shift_backtab")
+for from, to in pairs(key_aliases) do
+    remap_keys(from, to)
+end
+
+-- The bindings each manifest plug-in has registered, so they can be replaced on reload
+plugin_bindings = {}
+
+-- Merge the bindings of a manifest plug-in, replacing any it registered before
+function merge_plugin_mapping(name)
+    for key, f in pairs(plugin_bindings[name] or {}) do
+        local listeners = global_event_mapping[key] or {}
+        for i = #listeners, 1, -1 do
+            if listeners[i] == f then
+                table.remove(listeners, i)
+            end
+        end
+    end
+    plugin_bindings[name] = {}
+    for key, f in pairs(event_mapping) do
+        key = key_aliases[key] or key
+        if global_event_mapping[key] ~= nil then
+            table.insert(global_event_mapping[key], f)
+        else
+            global_event_mapping[key] = {f,}
+        end
+        plugin_bindings[name][key] = f
+    end
+    event_mapping = {}
+end
 
 -- Show warning if any plugins weren't able to be loaded
 if plugin_issues then
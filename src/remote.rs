@@ -0,0 +1,340 @@
+/// Remote control: a running instance listens on a local socket, so that `ox --remote`
+/// in another shell can ask it to open files, move the cursor or run Lua
+/// (instead of starting a second editor).
+/// This needs unix sockets, so it isn't available on windows
+use crate::cli::{files_to_open, parse_position, FileToOpen, Position};
+use crate::{ged, Editor, Feedback};
+use mlua::{AnyUserData, Lua, MultiValue};
+#[cfg(not(target_os = "windows"))]
+use nix::sys::stat::{umask, Mode};
+#[cfg(not(target_os = "windows"))]
+use nix::unistd::getuid;
+use std::io::ErrorKind;
+#[cfg(not(target_os = "windows"))]
+use std::io::{Read, Write};
+#[cfg(not(target_os = "windows"))]
+use std::os::unix::fs::{DirBuilderExt, FileTypeExt, MetadataExt};
+#[cfg(not(target_os = "windows"))]
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+#[cfg(not(target_os = "windows"))]
+use std::time::Duration;
+
+/// Parts of a request are separated by this (it can't appear in paths or arguments)
+const SEPARATOR: char = '\0';
+/// How long a request has to arrive once connected, so a stuck client can't hang the editor
+#[cfg(not(target_os = "windows"))]
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Something another shell has asked the running instance to do
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Request {
+    /// Open files (relative names are taken from the directory the request came from)
+    Open(Vec<FileToOpen>),
+    /// Move the cursor in the document being edited
+    Goto(Position),
+    /// Run Lua, replying with what it returns
+    Eval(String),
+}
+
+impl Request {
+    /// Read a request from the command and its arguments (as given after `--remote`)
+    pub fn parse(command: &str, args: &[String]) -> Result<Self, String> {
+        match command {
+            "open" if args.is_empty() => Err("open needs files to open".to_string()),
+            "open" => Ok(Self::Open(files_to_open(args.to_vec()))),
+            "goto" => {
+                let position = args.first().and_then(|arg| parse_position(arg));
+                position
+                    .map(Self::Goto)
+                    .ok_or_else(|| "goto needs a line (and optionally a column)".to_string())
+            }
+            "eval" if args.is_empty() => Err("eval needs Lua to run".to_string()),
+            "eval" => Ok(Self::Eval(args.join(" "))),
+            _ => Err(format!(
+                "'{command}' isn't a remote command, use open, goto or eval"
+            )),
+        }
+    }
+}
+
+/// Work out where the socket for this session lives.
+/// Sessions are named by `$OX_SESSION` (so separate sets of instances can be controlled),
+/// and are kept in a directory only the current user can get into
+#[cfg(not(target_os = "windows"))]
+pub fn socket_path() -> std::io::Result<PathBuf> {
+    let session: String = std::env::var("OX_SESSION")
+        .unwrap_or_else(|_| "default".to_string())
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    let uid = getuid().as_raw();
+    let dir = std::env::var_os("XDG_RUNTIME_DIR").map_or_else(
+        || std::env::temp_dir().join(format!("ox-{uid}")),
+        |dir| PathBuf::from(dir).join("ox"),
+    );
+    private_dir(&dir, uid)?;
+    Ok(dir.join(format!("{session}.sock")))
+}
+
+/// Remote control needs unix sockets, which windows doesn't have
+#[cfg(target_os = "windows")]
+pub fn socket_path() -> std::io::Result<PathBuf> {
+    Err(std::io::Error::new(
+        ErrorKind::Unsupported,
+        "remote control isn't available on windows",
+    ))
+}
+
+/// Make sure a directory exists that only a user can get into, creating it if needed.
+/// One made by anyone else (who could listen in on requests, or answer them) is refused
+#[cfg(not(target_os = "windows"))]
+fn private_dir(dir: &Path, uid: u32) -> std::io::Result<()> {
+    match std::fs::DirBuilder::new().mode(0o700).create(dir) {
+        Err(err) if err.kind() != ErrorKind::AlreadyExists => return Err(err),
+        _ => (),
+    }
+    let meta = std::fs::symlink_metadata(dir)?;
+    if !meta.is_dir() || meta.uid() != uid || meta.mode() & 0o077 != 0 {
+        return Err(std::io::Error::new(
+            ErrorKind::PermissionDenied,
+            format!(
+                "{} isn't a directory that only you can get into",
+                dir.display()
+            ),
+        ));
+    }
+    Ok(())
+}
+
+/// Ask the instance running in this session to carry out a request, then report its reply.
+/// Returns the exit code, or None when no instance is running (so ox should start as normal)
+pub fn request(command: &str, args: &[String]) -> Option<i32> {
+    let path = match socket_path() {
+        Ok(path) => path,
+        Err(err) => {
+            eprintln!("Error: {err}");
+            return Some(1);
+        }
+    };
+    let reply = send_to(&path, command, args)?;
+    match reply {
+        Ok(reply) => match reply.split_once('\n') {
+            Some(("ok", message)) => {
+                if !message.is_empty() {
+                    println!("{message}");
+                }
+                Some(0)
+            }
+            Some((_, message)) => {
+                eprintln!("Error: {message}");
+                Some(1)
+            }
+            None => {
+                eprintln!("Error: the running instance gave an unexpected reply");
+                Some(1)
+            }
+        },
+        Err(err) => {
+            eprintln!("Error: failed to talk to the running instance: {err}");
+            Some(1)
+        }
+    }
+}
+
+/// Send a request to the instance listening on a socket, returning its reply
+/// (or None if nothing is listening there)
+#[cfg(not(target_os = "windows"))]
+pub fn send_to(path: &Path, command: &str, args: &[String]) -> Option<std::io::Result<String>> {
+    let mut stream = UnixStream::connect(path).ok()?;
+    let cwd = std::env::current_dir().unwrap_or_default();
+    let mut parts = vec![cwd.to_string_lossy().to_string(), command.to_string()];
+    parts.extend(args.iter().cloned());
+    let mut exchange = || {
+        stream.write_all(parts.join(&SEPARATOR.to_string()).as_bytes())?;
+        stream.shutdown(std::net::Shutdown::Write)?;
+        let mut reply = String::new();
+        stream.read_to_string(&mut reply)?;
+        Ok(reply)
+    };
+    Some(exchange())
+}
+
+/// Remote control needs unix sockets, so on windows ox always starts as normal
+#[cfg(target_os = "windows")]
+pub fn send_to(_path: &Path, _command: &str, _args: &[String]) -> Option<std::io::Result<String>> {
+    None
+}
+
+/// A request that has come in, waiting to be answered
+pub struct Connection {
+    #[cfg(not(target_os = "windows"))]
+    stream: UnixStream,
+    /// The directory the request was made from
+    pub cwd: PathBuf,
+    pub request: Result<Request, String>,
+}
+
+impl Connection {
+    /// Reply to the request, telling the shell it came from whether it worked
+    pub fn reply(self, result: Result<String, String>) {
+        let reply = match result {
+            Ok(message) => format!("ok\n{message}"),
+            Err(message) => format!("error\n{message}"),
+        };
+        #[cfg(not(target_os = "windows"))]
+        {
+            let mut stream = self.stream;
+            let _ = stream.write_all(reply.as_bytes());
+        }
+        #[cfg(target_os = "windows")]
+        let _ = reply;
+    }
+}
+
+/// The socket a running instance listens on for requests
+pub struct RemoteControl {
+    #[cfg(not(target_os = "windows"))]
+    listener: UnixListener,
+    pub path: PathBuf,
+}
+
+impl RemoteControl {
+    /// Start listening on a socket, returning None if another instance is already listening there.
+    /// A socket left behind by an instance of ours that crashed is replaced,
+    /// but anything else in the way is left alone
+    #[cfg(not(target_os = "windows"))]
+    pub fn start_at(path: &Path) -> std::io::Result<Option<Self>> {
+        if let Ok(meta) = std::fs::symlink_metadata(path) {
+            if !meta.file_type().is_socket() || meta.uid() != getuid().as_raw() {
+                return Err(std::io::Error::new(
+                    ErrorKind::AlreadyExists,
+                    format!("{} is in the way of the socket", path.display()),
+                ));
+            }
+            if UnixStream::connect(path).is_ok() {
+                return Ok(None);
+            }
+            std::fs::remove_file(path)?;
+        }
+        // Requests can run Lua, so only the user that started ox may send them
+        // (the socket is made that way, so there is no moment anyone else could connect)
+        let mask = umask(Mode::from_bits_truncate(0o177));
+        let listener = UnixListener::bind(path);
+        umask(mask);
+        let listener = listener?;
+        listener.set_nonblocking(true)?;
+        Ok(Some(Self {
+            listener,
+            path: path.to_path_buf(),
+        }))
+    }
+
+    /// Remote control needs unix sockets, so there is nothing to listen on with windows
+    #[cfg(target_os = "windows")]
+    pub fn start_at(_path: &Path) -> std::io::Result<Option<Self>> {
+        Ok(None)
+    }
+
+    /// Take a request that has come in (if there is one), without waiting for one
+    #[cfg(not(target_os = "windows"))]
+    pub fn poll(&self) -> Option<Connection> {
+        let (mut stream, _) = self.listener.accept().ok()?;
+        stream.set_nonblocking(false).ok()?;
+        stream.set_read_timeout(Some(REQUEST_TIMEOUT)).ok()?;
+        let mut text = String::new();
+        stream.read_to_string(&mut text).ok()?;
+        // Another instance checking whether this one is listening sends nothing
+        if text.is_empty() {
+            return None;
+        }
+        let mut parts = text.split(SEPARATOR).map(str::to_string);
+        let cwd = PathBuf::from(parts.next().unwrap_or_default());
+        let command = parts.next().unwrap_or_default();
+        let args: Vec<String> = parts.collect();
+        Some(Connection {
+            stream,
+            cwd,
+            request: Request::parse(&command, &args),
+        })
+    }
+
+    /// There is nothing to listen on with windows
+    #[cfg(target_os = "windows")]
+    pub fn poll(&self) -> Option<Connection> {
+        None
+    }
+}
+
+impl Drop for RemoteControl {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+/// Carry out a request from another shell and reply to it
+pub fn answer(editor: &AnyUserData, lua: &Lua, connection: Connection) {
+    let result = match &connection.request {
+        Ok(Request::Open(files)) => ged!(mut &editor)
+            .remote_open(&connection.cwd, files)
+            .map_err(|err| err.to_string()),
+        Ok(Request::Goto(Position { line, column })) => {
+            ged!(mut &editor).remote_goto(*line, *column);
+            Ok(String::new())
+        }
+        // The editor isn't borrowed while the Lua runs, as the Lua is likely to use it
+        Ok(Request::Eval(code)) => eval(lua, code),
+        Err(err) => Err(err.clone()),
+    };
+    if let Err(err) = &result {
+        ged!(mut &editor).feedback = Feedback::Error(format!("Remote request failed: {err}"));
+    }
+    connection.reply(result);
+}
+
+/// Run Lua, giving back what it returned (with each value on its own line)
+fn eval(lua: &Lua, code: &str) -> Result<String, String> {
+    let values = lua
+        .load(code)
+        .eval::<MultiValue>()
+        .map_err(|err| err.to_string())?;
+    let values: Vec<String> = values
+        .iter()
+        .map(|value| value.to_string().unwrap_or_else(|err| err.to_string()))
+        .collect();
+    Ok(values.join("\n"))
+}
+
+#[cfg(all(test, not(target_os = "windows")))]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::PermissionsExt;
+
+    #[test]
+    fn private_dirs() {
+        let uid = getuid().as_raw();
+        let base = std::env::temp_dir().join(format!("ox-private-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&base);
+        std::fs::create_dir_all(&base).unwrap();
+        // Made only the user can get into, and taken as it is after that
+        let dir = base.join("made");
+        private_dir(&dir, uid).unwrap();
+        let mode = std::fs::metadata(&dir).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o700);
+        private_dir(&dir, uid).unwrap();
+        // Ones others can get into, or that belong to someone else, are refused
+        let open = base.join("open");
+        std::fs::create_dir(&open).unwrap();
+        std::fs::set_permissions(&open, std::fs::Permissions::from_mode(0o755)).unwrap();
+        assert!(private_dir(&open, uid).is_err());
+        assert!(private_dir(&dir, uid + 1).is_err());
+        // As are files, and links to a private directory
+        let file = base.join("file");
+        std::fs::write(&file, "").unwrap();
+        assert!(private_dir(&file, uid).is_err());
+        let link = base.join("link");
+        std::os::unix::fs::symlink(&dir, &link).unwrap();
+        assert!(private_dir(&link, uid).is_err());
+        std::fs::remove_dir_all(&base).unwrap();
+    }
+}
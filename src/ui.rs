@@ -1,27 +1,28 @@
 /// Utilities for rendering the user interface
-use crate::config::{Colors, Terminal as TerminalConfig};
+use crate::backend::{Backend, CrosstermBackend};
+use crate::config::{ColorMode, Colors, CursorShape, Terminal as TerminalConfig};
 use crate::editor::MacroMan;
 use crate::error::Result;
 use base64::prelude::*;
 use crossterm::{
-    cursor::{Hide, MoveTo, Show},
+    cursor::{DisableBlinking, EnableBlinking, Hide, MoveTo, SetCursorStyle, Show},
     event::{
-        DisableBracketedPaste, DisableMouseCapture, EnableBracketedPaste, EnableMouseCapture,
-        Event as CEvent, KeyCode as KCode, KeyEvent, KeyEventKind, KeyModifiers as KMod,
-        KeyboardEnhancementFlags, PushKeyboardEnhancementFlags,
+        DisableBracketedPaste, DisableFocusChange, DisableMouseCapture, Event as CEvent,
+        KeyCode as KCode, KeyEvent, KeyEventKind, KeyModifiers as KMod,
     },
-    execute, queue,
+    execute,
     style::{Attribute, Color, SetAttribute, SetBackgroundColor as Bg, SetForegroundColor as Fg},
-    terminal::{
-        self, Clear, ClearType as ClType, DisableLineWrap, EnableLineWrap, EnterAlternateScreen,
-        LeaveAlternateScreen,
-    },
+    terminal::{self, Clear, ClearType as ClType, LeaveAlternateScreen, SetTitle},
+    Command,
 };
 use kaolinite::utils::{width, Size};
 use mlua::AnyUserData;
 use std::collections::HashMap;
 use std::env;
-use std::io::{stdout, Stdout, Write};
+use std::io::{stdout, IsTerminal, Write};
+use std::ops::RangeInclusive;
+#[cfg(not(target_os = "windows"))]
+use std::time::{Duration, Instant};
 #[cfg(not(target_os = "windows"))]
 use synoptic::Regex;
 
@@ -36,9 +37,16 @@ macro_rules! display {
     };
 }
 
+/// The size assumed when there is no terminal to measure
+const FALLBACK_SIZE: (u16, u16) = (80, 24);
+
 /// Gets the size of the terminal
 pub fn size() -> Result<Size> {
-    let (w, h) = terminal::size()?;
+    // Without a terminal (as when running headlessly in a pipeline), use a standard size
+    let (w, h) = match terminal::size() {
+        Err(_) if !stdout().is_terminal() => FALLBACK_SIZE,
+        size => size?,
+    };
     Ok(Size {
         w: w as usize,
         h: (h as usize).saturating_sub(1),
@@ -47,16 +55,19 @@ pub fn size() -> Result<Size> {
 
 /// Fatal Error
 pub fn fatal_error(msg: &str) {
-    // Prevent upset terminal state
-    terminal::disable_raw_mode().unwrap();
-    execute!(
-        stdout(),
-        LeaveAlternateScreen,
-        Show,
-        DisableMouseCapture,
-        DisableBracketedPaste,
-    )
-    .unwrap();
+    // Prevent upset terminal state (unless it was never set up, as when running headlessly)
+    if terminal::is_raw_mode_enabled().unwrap_or(false) {
+        terminal::disable_raw_mode().unwrap();
+        execute!(
+            stdout(),
+            LeaveAlternateScreen,
+            Show,
+            DisableMouseCapture,
+            DisableBracketedPaste,
+            DisableFocusChange,
+        )
+        .unwrap();
+    }
     // Display the error information
     eprintln!(
         "{}{}[Error]{}{} {msg}",
@@ -158,55 +169,83 @@ impl Feedback {
     }
 }
 
+/// Keeps track of the colours in use while writing out a row, so that escape sequences
+/// are only written when a colour actually changes
+pub struct ColourTracker {
+    pub bg: Bg,
+    pub fg: Fg,
+}
+
+impl ColourTracker {
+    /// Start tracking from colours that have just been written out
+    pub fn new(bg: Bg, fg: Fg) -> Self {
+        Self { bg, fg }
+    }
+
+    /// Switch to a background colour
+    pub fn bg(&mut self, out: &mut String, bg: Bg) {
+        if self.bg != bg {
+            *out += &bg.to_string();
+            self.bg = bg;
+        }
+    }
+
+    /// Switch to a foreground colour
+    pub fn fg(&mut self, out: &mut String, fg: Fg) {
+        if self.fg != fg {
+            *out += &fg.to_string();
+            self.fg = fg;
+        }
+    }
+}
+
 pub struct Terminal {
-    pub stdout: Stdout,
+    /// Where the editor is drawn
+    pub backend: Box<dyn Backend>,
     pub cache: String,
     pub config: AnyUserData,
     pub last_copy: String,
+    /// The rows currently on screen, so that only rows that change are written out
+    pub frame: Vec<Option<String>>,
+    pub cursor_visible: bool,
+    /// How the cursor was last made to look
+    pub cursor_shape: CursorShape,
+    /// The title last given to the terminal window
+    pub title: Option<String>,
+    /// Whether the cursor was last set to blink (None if it has been left as the terminal had it)
+    pub cursor_blinking: Option<bool>,
 }
 
 impl Terminal {
     pub fn new(config: AnyUserData) -> Self {
+        Self::with_backend(config, Box::new(CrosstermBackend::default()))
+    }
+
+    /// Create a terminal that draws somewhere other than the real terminal (like a test screen)
+    pub fn with_backend(config: AnyUserData, backend: Box<dyn Backend>) -> Self {
         Terminal {
-            stdout: stdout(),
-            cache: String::with_capacity(size().map(|s| s.w * s.h).unwrap_or(1000)),
+            backend,
+            cache: String::with_capacity(1000),
             config,
             last_copy: String::new(),
+            frame: vec![],
+            cursor_visible: true,
+            cursor_shape: CursorShape::Default,
+            title: None,
+            cursor_blinking: None,
         }
     }
 
     /// Set up the terminal so that it is clean and doesn't affect existing terminal text
     pub fn start(&mut self) -> Result<()> {
-        std::panic::set_hook(Box::new(|e| {
-            terminal::disable_raw_mode().unwrap();
-            execute!(
-                stdout(),
-                LeaveAlternateScreen,
-                Show,
-                DisableMouseCapture,
-                DisableBracketedPaste,
-            )
-            .unwrap();
-            eprintln!("{e}");
-        }));
-        execute!(
-            self.stdout,
-            EnterAlternateScreen,
-            Clear(ClType::All),
-            DisableLineWrap,
-        )?;
         let cfg = self.config.borrow::<TerminalConfig>().unwrap();
-        if cfg.mouse_enabled {
-            execute!(self.stdout, EnableMouseCapture)?;
-        }
-        terminal::enable_raw_mode()?;
-        if cfg!(not(target_os = "windows")) {
-            execute!(
-                self.stdout,
-                PushKeyboardEnhancementFlags(KeyboardEnhancementFlags::DISAMBIGUATE_ESCAPE_CODES),
-                EnableBracketedPaste,
-            )?;
-        }
+        let (mouse, shape) = (cfg.mouse_enabled, cfg.cursor_style);
+        drop(cfg);
+        self.backend.enter(mouse)?;
+        self.cursor_shape = CursorShape::Default;
+        self.set_cursor_shape(shape);
+        self.title = None;
+        self.invalidate();
         self.flush()?;
         Ok(())
     }
@@ -214,27 +253,130 @@ impl Terminal {
     /// Restore terminal back to state before the editor was started
     pub fn end(&mut self) -> Result<()> {
         self.show_cursor();
-        terminal::disable_raw_mode()?;
-        execute!(self.stdout, LeaveAlternateScreen, EnableLineWrap,)?;
-        if cfg!(not(target_os = "windows")) {
-            execute!(self.stdout, DisableBracketedPaste,)?;
-        }
-        let cfg = self.config.borrow::<TerminalConfig>().unwrap();
-        if cfg.mouse_enabled {
-            execute!(self.stdout, DisableMouseCapture)?;
+        // Put the cursor back to how the user has it if it was made to look different
+        let blinked = self.cursor_blinking.take().is_some();
+        if blinked || self.cursor_shape != CursorShape::Default {
+            let _ = SetCursorStyle::DefaultUserShape.write_ansi(&mut self.cache);
+            self.cursor_shape = CursorShape::Default;
         }
+        let mouse = self
+            .config
+            .borrow::<TerminalConfig>()
+            .unwrap()
+            .mouse_enabled;
+        self.backend.leave(mouse)?;
         self.flush()?;
         Ok(())
     }
 
     /// Shows the cursor on the screen
     pub fn show_cursor(&mut self) {
-        self.cache += &Show.to_string();
+        if !self.cursor_visible {
+            self.cache += &Show.to_string();
+            self.cursor_visible = true;
+        }
+    }
+
+    /// Changes how the cursor looks (this does nothing if it already looks that way,
+    /// so terminals that don't know the sequence are sent as little of it as possible)
+    pub fn set_cursor_shape(&mut self, shape: CursorShape) {
+        if self.cursor_shape != shape {
+            let _ = shape.style().write_ansi(&mut self.cache);
+            self.cursor_shape = shape;
+            // The shape decides whether the cursor blinks too, so that is set again after
+            self.cursor_blinking = None;
+        }
+    }
+
+    /// Sets the title of the terminal window (this does nothing if it already has that title)
+    pub fn set_title(&mut self, title: &str) {
+        if self.title.as_deref() != Some(title) {
+            let _ = SetTitle(title).write_ansi(&mut self.cache);
+            self.title = Some(title.to_string());
+        }
+    }
+
+    /// Rings the terminal's bell
+    pub fn bell(&mut self) -> Result<()> {
+        self.backend.write("\x07")?;
+        self.backend.flush()
+    }
+
+    /// Makes the cursor blink or keeps it still, returning true if this changed it
+    pub fn blink_cursor(&mut self, blink: bool) -> bool {
+        if self.cursor_blinking == Some(blink) {
+            return false;
+        }
+        if blink {
+            self.cache += &EnableBlinking.to_string();
+        } else {
+            self.cache += &DisableBlinking.to_string();
+        }
+        self.cursor_blinking = Some(blink);
+        true
     }
 
     /// Hides the cursor on the screen
     pub fn hide_cursor(&mut self) {
-        self.cache += &Hide.to_string();
+        if self.cursor_visible {
+            self.cache += &Hide.to_string();
+            self.cursor_visible = false;
+        }
+    }
+
+    /// Draw a row of the screen, this does nothing if the row is already on screen
+    pub fn draw_line(&mut self, y: usize, line: String) {
+        if self
+            .frame
+            .get(y)
+            .is_some_and(|old| old.as_ref() == Some(&line))
+        {
+            return;
+        }
+        // Hide the cursor while rows are being redrawn
+        self.hide_cursor();
+        self.goto(0, y);
+        self.cache += &SetAttribute(Attribute::NormalIntensity).to_string();
+        self.cache += &line;
+        if self.frame.len() <= y {
+            self.frame.resize(y + 1, None);
+        }
+        self.frame[y] = Some(line);
+    }
+
+    /// Draw over part of a row (for popups), the whole row is then drawn again on the next render
+    pub fn draw_over(&mut self, x: usize, y: usize, text: &str) {
+        if let Some(row) = self.frame.get_mut(y) {
+            *row = None;
+        }
+        self.hide_cursor();
+        self.goto(x, y);
+        self.cache += &SetAttribute(Attribute::NormalIntensity).to_string();
+        self.cache += text;
+    }
+
+    /// Forget what is on screen, so that everything is redrawn on the next render
+    pub fn invalidate(&mut self) {
+        self.frame.clear();
+    }
+
+    /// Clear the screen, so that everything is drawn from scratch on the next render
+    pub fn clear(&mut self) {
+        self.cache += &Clear(ClType::All).to_string();
+        self.invalidate();
+    }
+
+    /// Clear the screen and draw the rows that were on it again,
+    /// for when the screen has been resized but can't be rendered from scratch
+    pub fn repaint(&mut self) {
+        let rows = self.size().map_or(usize::MAX, |s| s.h + 1);
+        let frame = std::mem::take(&mut self.frame);
+        self.cache += &Clear(ClType::All).to_string();
+        for (y, row) in frame.into_iter().enumerate().take(rows) {
+            if let Some(row) = row {
+                self.draw_line(y, row);
+            }
+        }
     }
 
     /// Moves the cursor to a specific position on screen
@@ -255,30 +397,34 @@ impl Terminal {
 
     /// Moves to a line and makes sure it is cleared
     pub fn prepare_line(&mut self, y: usize) {
+        // This row is being drawn over, so it'll need redrawing later
+        if let Some(row) = self.frame.get_mut(y) {
+            *row = None;
+        }
         self.goto(0, y);
         self.clear_current_line();
     }
 
-    /// Flush the stdout (push the queued events to the screen)
+    /// Flush the backend (push the queued events to the screen)
     pub fn flush(&mut self) -> Result<()> {
         let mut queue = String::new();
         std::mem::swap(&mut queue, &mut self.cache);
-        queue!(self.stdout, crossterm::style::Print(&queue))?;
+        self.backend.write(&queue)?;
         queue.clear();
         std::mem::swap(&mut queue, &mut self.cache);
-        self.stdout.flush()?;
-        Ok(())
+        self.backend.flush()
+    }
+
+    /// Gets the size of the screen being drawn on
+    pub fn size(&self) -> Result<Size> {
+        self.backend.size()
     }
 
     /// Put text into the clipboard
     pub fn copy(&mut self, text: &str) -> Result<()> {
         self.last_copy = text.to_string();
-        write!(
-            self.stdout,
-            "\x1b]52;c;{}\x1b\\",
-            BASE64_STANDARD.encode(text)
-        )?;
-        Ok(())
+        self.backend
+            .write(&format!("\x1b]52;c;{}\x1b\\", BASE64_STANDARD.encode(text)))
     }
 }
 
@@ -298,12 +444,47 @@ pub fn supports_true_color() -> bool {
     false
 }
 
+/// Terminals known to only show the 16 basic colours
+const BASIC_TERMINALS: [&str; 7] = ["linux", "vt100", "vt102", "vt220", "ansi", "cons25", "dumb"];
+
+/// Work out how many colours this terminal can show
+pub fn detect_color_mode() -> ColorMode {
+    if supports_true_color() {
+        return ColorMode::TrueColor;
+    }
+    // Consoles like the linux one only have the basic colours
+    let term = env::var("TERM").unwrap_or_default();
+    if BASIC_TERMINALS.contains(&term.as_str()) {
+        ColorMode::Ansi16
+    } else {
+        ColorMode::Xterm256
+    }
+}
+
 /// Converts rgb to the closest xterm equivalent
 pub fn rgb_to_xterm256(r: u8, g: u8, b: u8) -> u8 {
+    closest_xterm(r, g, b, 0..=255)
+}
+
+/// Converts rgb to the closest of the 16 basic colours
+pub fn rgb_to_ansi16(r: u8, g: u8, b: u8) -> u8 {
+    closest_xterm(r, g, b, 0..=15)
+}
+
+/// Converts an xterm colour to its rgb value
+pub fn xterm_to_rgb(code: u8) -> (u8, u8, u8) {
+    get_xterm_lookup().get(&code).copied().unwrap_or((0, 0, 0))
+}
+
+/// Find the xterm colour (out of those given) closest to an rgb value
+fn closest_xterm(r: u8, g: u8, b: u8, candidates: RangeInclusive<u8>) -> u8 {
     let lookup = get_xterm_lookup();
     let mut min_distance = f64::INFINITY;
     let mut closest_index = 0;
-    for (index, &(xr, xg, xb)) in &lookup {
+    for index in candidates {
+        let Some(&(xr, xg, xb)) = lookup.get(&index) else {
+            continue;
+        };
         // Calculate the Euclidean distance in RGB space
         let distance = ((f64::from(r) - f64::from(xr)).powi(2)
             + (f64::from(g) - f64::from(xg)).powi(2)
@@ -311,13 +492,141 @@ pub fn rgb_to_xterm256(r: u8, g: u8, b: u8) -> u8 {
         .sqrt();
         if distance < min_distance {
             min_distance = distance;
-            closest_index = *index;
+            closest_index = index;
         }
     }
 
     closest_index
 }
 
+/// How long to wait for the terminal to report its background colour
+#[cfg(not(target_os = "windows"))]
+const BACKGROUND_TIMEOUT: Duration = Duration::from_millis(500);
+
+/// The background colour the terminal reported at start up
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TerminalBackground(pub u8, pub u8, pub u8);
+
+impl TerminalBackground {
+    /// A background is light if dark text would stand out more on it than light text
+    pub fn is_light(self) -> bool {
+        let lum = luminance(self.0, self.1, self.2);
+        (lum + 0.05) / 0.05 > 1.05 / (lum + 0.05)
+    }
+}
+
+/// Work out the relative luminance of a colour (0 for black through to 1 for white)
+pub fn luminance(r: u8, g: u8, b: u8) -> f64 {
+    let linear = |c: u8| {
+        let c = f64::from(c) / 255.0;
+        if c <= 0.040_45 {
+            c / 12.92
+        } else {
+            ((c + 0.055) / 1.055).powf(2.4)
+        }
+    };
+    0.2126 * linear(r) + 0.7152 * linear(g) + 0.0722 * linear(b)
+}
+
+/// Read the colour out of a terminal's reply to a background colour query (OSC 11).
+/// Replies come as `rgb:r/g/b` or `rgba:r/g/b/a` with 1 to 4 hex digits per channel,
+/// or as `#rgb` style hex, ended by either BEL or ST
+pub fn parse_background_reply(reply: &[u8]) -> Option<TerminalBackground> {
+    let reply = String::from_utf8_lossy(reply);
+    let start = reply.find("\x1b]11;")? + 5;
+    let rest = &reply[start..];
+    let end = rest.find(['\x07', '\x1b']).unwrap_or(rest.len());
+    let colour = rest[..end].trim();
+    // Scale a channel of any number of hex digits to fit in a byte
+    let channel = |hex: &str| -> Option<u8> {
+        if hex.is_empty() || hex.len() > 4 {
+            return None;
+        }
+        let value = u32::from_str_radix(hex, 16).ok()?;
+        let max = (1_u32 << (4 * hex.len())) - 1;
+        u8::try_from((value * 255 + max / 2) / max).ok()
+    };
+    let channels: Vec<u8> = if let Some(hex) = colour.strip_prefix('#') {
+        if hex.is_empty() || hex.len() % 3 != 0 || !hex.is_ascii() {
+            return None;
+        }
+        let n = hex.len() / 3;
+        (0..3)
+            .map(|i| channel(&hex[i * n..(i + 1) * n]))
+            .collect::<Option<_>>()?
+    } else {
+        let (kind, values) = colour.split_once(':')?;
+        let count = match kind {
+            "rgb" => 3,
+            "rgba" => 4,
+            _ => return None,
+        };
+        let values: Vec<&str> = values.split('/').collect();
+        if values.len() != count {
+            return None;
+        }
+        values[..3]
+            .iter()
+            .map(|v| channel(v))
+            .collect::<Option<_>>()?
+    };
+    Some(TerminalBackground(channels[0], channels[1], channels[2]))
+}
+
+/// Check if a reply contains the answer to a primary device attributes query (ESC [ ? ... c)
+fn has_device_attributes(reply: &[u8]) -> bool {
+    reply.windows(3).enumerate().any(|(i, w)| {
+        w == b"\x1b[?"
+            && reply[i + 3..]
+                .iter()
+                .find(|b| !(b.is_ascii_digit() || **b == b';'))
+                .is_some_and(|b| *b == b'c')
+    })
+}
+
+/// Ask the terminal for its background colour, giving up if it doesn't answer in time.
+/// Every terminal answers a device attributes query, so one is sent afterwards to mark
+/// the end of the reply, making sure none of it is left over to be read as key presses
+#[cfg(not(target_os = "windows"))]
+pub fn query_background() -> Option<TerminalBackground> {
+    use nix::fcntl::OFlag;
+    use std::io::{IsTerminal, Read};
+    use std::os::unix::fs::OpenOptionsExt;
+    if !stdout().is_terminal() {
+        return None;
+    }
+    let mut tty = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .custom_flags(OFlag::O_NONBLOCK.bits())
+        .open("/dev/tty")
+        .ok()?;
+    let was_raw = terminal::is_raw_mode_enabled().unwrap_or(false);
+    terminal::enable_raw_mode().ok()?;
+    let mut reply = vec![];
+    if tty.write_all(b"\x1b]11;?\x1b\\\x1b[c").is_ok() && tty.flush().is_ok() {
+        let start = Instant::now();
+        let mut buffer = [0; 256];
+        while !has_device_attributes(&reply) && start.elapsed() < BACKGROUND_TIMEOUT {
+            match tty.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(n) => reply.extend_from_slice(&buffer[..n]),
+                Err(_) => std::thread::sleep(Duration::from_millis(5)),
+            }
+        }
+    }
+    if !was_raw {
+        let _ = terminal::disable_raw_mode();
+    }
+    parse_background_reply(&reply)
+}
+
+/// Windows consoles have no way to report their background colour
+#[cfg(target_os = "windows")]
+pub fn query_background() -> Option<TerminalBackground> {
+    None
+}
+
 /// Data representing xterm colours and their equivalent RGB values
 pub const XTERMLOOKUP: &str = "0:0,0,0|1:128,0,0|2:0,128,0|3:128,128,0|4:0,0,128|5:128,0,128|6:0,128,128|7:192,192,192|8:128,128,128|9:255,0,0|10:0,255,0|11:255,255,0|12:0,0,255|13:255,0,255|14:0,255,255|15:255,255,255|16:0,0,0|17:0,0,95|18:0,0,135|19:0,0,175|20:0,0,215|21:0,0,255|22:0,95,0|23:0,95,95|24:0,95,135|25:0,95,175|26:0,95,215|27:0,95,255|28:0,135,0|29:0,135,95|30:0,135,135|31:0,135,175|32:0,135,215|33:0,135,255|34:0,175,0|35:0,175,95|36:0,175,135|37:0,175,175|38:0,175,215|39:0,175,255|40:0,215,0|41:0,215,95|42:0,215,135|43:0,215,175|44:0,215,215|45:0,215,255|46:0,255,0|47:0,255,95|48:0,255,135|49:0,255,175|50:0,255,215|51:0,255,255|52:95,0,0|53:95,0,95|54:95,0,135|55:95,0,175|56:95,0,215|57:95,0,255|58:95,95,0|59:95,95,95|60:95,95,135|61:95,95,175|62:95,95,215|63:95,95,255|64:95,135,0|65:95,135,95|66:95,135,135|67:95,135,175|68:95,135,215|69:95,135,255|70:95,175,0|71:95,175,95|72:95,175,135|73:95,175,175|74:95,175,215|75:95,175,255|76:95,215,0|77:95,215,95|78:95,215,135|79:95,215,175|80:95,215,215|81:95,215,255|82:95,255,0|83:95,255,95|84:95,255,135|85:95,255,175|86:95,255,215|87:95,255,255|88:135,0,0|89:135,0,95|90:135,0,135|91:135,0,175|92:135,0,215|93:135,0,255|94:135,95,0|95:135,95,95|96:135,95,135|97:135,95,175|98:135,95,215|99:135,95,255|100:135,135,0|101:135,135,95|102:135,135,135|103:135,135,175|104:135,135,215|105:135,135,255|106:135,175,0|107:135,175,95|108:135,175,135|109:135,175,175|110:135,175,215|111:135,175,255|112:135,215,0|113:135,215,95|114:135,215,135|115:135,215,175|116:135,215,215|117:135,215,255|118:135,255,0|119:135,255,95|120:135,255,135|121:135,255,175|122:135,255,215|123:135,255,255|124:175,0,0|125:175,0,95|126:175,0,135|127:175,0,175|128:175,0,215|129:175,0,255|130:175,95,0|131:175,95,95|132:175,95,135|133:175,95,175|134:175,95,215|135:175,95,255|136:175,135,0|137:175,135,95|138:175,135,135|139:175,135,175|140:175,135,215|141:175,135,255|142:175,175,0|143:175,175,95|144:175,175,135|145:175,175,175|146:175,175,215|147:175,175,255|148:175,215,0|149:175,215,95|150:175,215,135|151:175,215,175|152:175,215,215|153:175,215,255|154:175,255,0|155:175,255,95|156:175,255,135|157:175,255,175|158:175,255,215|159:175,255,255|160:215,0,0|161:215,0,95|162:215,0,135|163:215,0,175|164:215,0,215|165:215,0,255|166:215,95,0|167:215,95,95|168:215,95,135|169:215,95,175|170:215,95,215|171:215,95,255|172:215,135,0|173:215,135,95|174:215,135,135|175:215,135,175|176:215,135,215|177:215,135,255|178:215,175,0|179:215,175,95|180:215,175,135|181:215,175,175|182:215,175,215|183:215,175,255|184:215,215,0|185:215,215,95|186:215,215,135|187:215,215,175|188:215,215,215|189:215,215,255|190:215,255,0|191:215,255,95|192:215,255,135|193:215,255,175|194:215,255,215|195:215,255,255|196:255,0,0|197:255,0,95|198:255,0,135|199:255,0,175|200:255,0,215|201:255,0,255|202:255,95,0|203:255,95,95|204:255,95,135|205:255,95,175|206:255,95,215|207:255,95,255|208:255,135,0|209:255,135,95|210:255,135,135|211:255,135,175|212:255,135,215|213:255,135,255|214:255,175,0|215:255,175,95|216:255,175,135|217:255,175,175|218:255,175,215|219:255,175,255|220:255,215,0|221:255,215,95|222:255,215,135|223:255,215,175|224:255,215,215|225:255,215,255|226:255,255,0|227:255,255,95|228:255,255,135|229:255,255,175|230:255,255,215|231:255,255,255|232:8,8,8|233:18,18,18|234:28,28,28|235:38,38,38|236:48,48,48|237:58,58,58|238:68,68,68|239:78,78,78|240:88,88,88|241:98,98,98|242:108,108,108|243:118,118,118|244:128,128,128|245:138,138,138|246:148,148,148|247:158,158,158|248:168,168,168|249:178,178,178|250:188,188,188|251:198,198,198|252:208,208,208|253:218,218,218|254:228,228,228|255:238,238,238";
 
@@ -423,3 +732,70 @@ pub fn replace_reset(input: &str, custom_bg: &str, custom_fg: &str) -> String {
     let input = reset_fg_regex.replace_all(&input, custom_fg).to_string();
     input
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn luminances() {
+        assert!(luminance(0, 0, 0).abs() < f64::EPSILON);
+        assert!((luminance(255, 255, 255) - 1.0).abs() < 1e-9);
+        // Green looks far brighter than blue at the same strength
+        assert!(luminance(0, 255, 0) > luminance(255, 0, 0));
+        assert!(luminance(255, 0, 0) > luminance(0, 0, 255));
+        assert!(TerminalBackground(250, 250, 240).is_light());
+        assert!(!TerminalBackground(40, 42, 54).is_light());
+    }
+
+    #[test]
+    fn background_replies() {
+        let parse = |reply: &[u8]| parse_background_reply(reply);
+        let white = Some(TerminalBackground(255, 255, 255));
+        // Replies can end with BEL or ST
+        assert_eq!(parse(b"\x1b]11;rgb:ffff/ffff/ffff\x07"), white);
+        assert_eq!(parse(b"\x1b]11;rgb:ffff/ffff/ffff\x1b\\"), white);
+        // Channels can have any number of hex digits up to 4
+        let dark = Some(TerminalBackground(0x28, 0x2a, 0x36));
+        assert_eq!(parse(b"\x1b]11;rgb:2828/2a2a/3636\x07"), dark);
+        assert_eq!(parse(b"\x1b]11;rgb:28/2a/36\x1b\\"), dark);
+        assert_eq!(
+            parse(b"\x1b]11;rgb:f/8/0\x07"),
+            Some(TerminalBackground(255, 136, 0))
+        );
+        assert_eq!(parse(b"\x1b]11;rgba:2828/2a2a/3636/ffff\x07"), dark);
+        assert_eq!(parse(b"\x1b]11;#282a36\x07"), dark);
+        // Anything around the reply (like the device attributes answer) is ignored
+        assert_eq!(parse(b"junk\x1b]11;rgb:28/2a/36\x07\x1b[?62;22c"), dark);
+        // Without an answer to the query, there is no colour
+        assert_eq!(parse(b"\x1b[?62;22c"), None);
+        assert_eq!(parse(b""), None);
+        // Garbled or cut off replies are rejected
+        assert_eq!(parse(b"\x1b]11;rgb:28/2a\x07"), None);
+        assert_eq!(parse(b"\x1b]11;rgb:28/2a/"), None);
+        assert_eq!(parse(b"\x1b]11;rgb:zz/2a/36\x07"), None);
+        assert_eq!(parse(b"\x1b]11;rgb:28282/2a/36\x07"), None);
+        assert_eq!(parse(b"\x1b]11;cmyk:1/2/3\x07"), None);
+        assert_eq!(parse(b"\x1b]11;#28a3\x07"), None);
+        assert_eq!(parse(b"\x1b]11;\x07"), None);
+        assert_eq!(parse(b"\x1b]11"), None);
+        assert_eq!(
+            parse(b"\xff\xfe\x1b]11;#\xc3\xa9\xc3\xa9\xc3\xa9\x07"),
+            None
+        );
+    }
+
+    #[test]
+    fn device_attributes() {
+        assert!(has_device_attributes(b"\x1b[?62;22c"));
+        assert!(has_device_attributes(b"\x1b[?1;2c"));
+        assert!(has_device_attributes(b"\x1b]11;rgb:0/0/0\x07\x1b[?6c"));
+        // Replies still on their way, or that are something else
+        assert!(!has_device_attributes(b""));
+        assert!(!has_device_attributes(b"\x1b[?62;22"));
+        assert!(!has_device_attributes(b"\x1b[?"));
+        assert!(!has_device_attributes(b"\x1b]11;rgb:0/0/0\x07"));
+        assert!(!has_device_attributes(b"\x1b[62;22c"));
+        assert!(!has_device_attributes(b"\x1b[?62;2xc"));
+    }
+}
@@ -0,0 +1 @@
+greeted = base_loaded
@@ -0,0 +1,4 @@
+-- Loaded after base, despite being found first
+name = "greeter"
+version = "1.0.0"
+dependencies = { "base" }
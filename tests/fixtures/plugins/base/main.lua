@@ -0,0 +1 @@
+base_loaded = true
@@ -0,0 +1,5 @@
+return {
+    name = "base",
+    version = "0.1",
+    entry = "main.lua",
+}
@@ -0,0 +1 @@
+future_loaded = true
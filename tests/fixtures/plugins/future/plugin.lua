@@ -0,0 +1,3 @@
+name = "future"
+version = "1.0.0"
+ox = "999.0.0"
@@ -0,0 +1 @@
+lonely_loaded = true
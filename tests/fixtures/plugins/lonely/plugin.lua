@@ -0,0 +1,3 @@
+name = "lonely"
+version = "1.0.0"
+dependencies = { "missing" }
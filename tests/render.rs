@@ -0,0 +1,2184 @@
+use crossterm::event::{
+    Event, KeyCode, KeyEvent, KeyModifiers, MouseButton, MouseEvent, MouseEventKind,
+};
+use kaolinite::Loc;
+use mlua::{AnyUserData, Lua};
+use ox::backend::{MemoryBackend, Screen};
+use ox::config;
+use ox::config::{PLUGIN_BOOTSTRAP, PLUGIN_RUN};
+use ox::editor::{Action, Direction, Editor, MacroMan};
+use ox::ged;
+use ox::ui::{Feedback, Terminal};
+use std::path::PathBuf;
+use std::sync::Once;
+
+/// The width and height of the screen the editor is drawn on
+const W: usize = 80;
+const H: usize = 24;
+
+/// Keep what the editor remembers between sessions (positions, recent files, history...)
+/// in a directory of its own, rather than in the real cache and data directories,
+/// and draw in full colour whatever terminal the tests happen to be run from
+fn isolate() {
+    static ISOLATE: Once = Once::new();
+    ISOLATE.call_once(|| {
+        let mut root = PathBuf::from(env!("CARGO_TARGET_TMPDIR"));
+        root.push(format!("xdg-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&root);
+        std::env::set_var("XDG_CACHE_HOME", root.join("cache"));
+        std::env::set_var("XDG_DATA_HOME", root.join("data"));
+        std::env::set_var("COLORTERM", "truecolor");
+    });
+}
+
+/// Set up an editor with the default configuration, drawing onto a screen kept in memory
+fn editor(lua: &Lua) -> (AnyUserData, MemoryBackend) {
+    isolate();
+    let backend = MemoryBackend::new(W, H);
+    let mut editor = Editor::new(lua).unwrap();
+    editor.terminal =
+        Terminal::with_backend(editor.config.terminal.clone(), Box::new(backend.clone()));
+    let editor = lua.create_userdata(editor).unwrap();
+    lua.globals().set("editor", editor.clone()).unwrap();
+    lua.load(PLUGIN_BOOTSTRAP).exec().unwrap();
+    let path = concat!(env!("CARGO_MANIFEST_DIR"), "/config/.oxrc");
+    assert!(ged!(mut &editor).load_config(path, lua).is_none());
+    // Run the plug-ins from the configuration, as happens at start up
+    lua.load(PLUGIN_RUN).exec().unwrap();
+    ged!(mut &editor).load_file_types(lua).unwrap();
+    (editor, backend)
+}
+
+/// Write a file for the editor to open
+fn file(name: &str, contents: &str) -> String {
+    let mut path = PathBuf::from(env!("CARGO_TARGET_TMPDIR"));
+    path.push(name);
+    std::fs::write(&path, contents).unwrap();
+    path.to_string_lossy().to_string()
+}
+
+/// Open a file in the editor and focus it (it goes just after the document being edited)
+fn open(editor: &AnyUserData, path: &str) {
+    let ptr = ged!(&editor).ptr.clone();
+    let opened = ged!(&editor)
+        .files
+        .get_atom(ptr)
+        .map_or(0, |(_, idx)| idx + 1);
+    ged!(mut &editor).open_or_new(path.to_string()).unwrap();
+    ged!(mut &editor).switch_to(opened);
+}
+
+/// Carry out some actions, then draw the editor
+fn act(editor: &AnyUserData, lua: &Lua, backend: &MemoryBackend, actions: &[Action]) -> Screen {
+    for action in actions {
+        ged!(mut &editor).perform(lua, action.clone()).unwrap();
+    }
+    ged!(mut &editor).needs_rerender = true;
+    ged!(mut &editor).render(lua).unwrap();
+    backend.screen()
+}
+
+/// The column a piece of text starts at on a row
+fn column(screen: &Screen, y: usize, text: &str) -> usize {
+    let row = screen.row(y);
+    let at = row.find(text).unwrap();
+    row[..at].chars().count()
+}
+
+#[test]
+fn line_numbers_and_tab_line() {
+    let lua = Lua::new();
+    let (editor, backend) = editor(&lua);
+    let path = file("numbers.txt", "first line\nsecond line\nthird line\n");
+    open(&editor, &path);
+    let screen = act(&editor, &lua, &backend, &[]);
+    // The tab line is on top, with the name of the document
+    assert!(screen.row(0).contains("numbers.txt"));
+    // Each line of the document follows, with its number before it
+    for (y, text) in ["first line", "second line", "third line"]
+        .iter()
+        .enumerate()
+    {
+        let row = screen.row(y + 1);
+        assert!(row.trim_start().starts_with(&(y + 1).to_string()));
+        assert!(row.contains(text));
+    }
+    // The rows after the document are empty
+    assert!(!screen.row(5).contains("line"));
+}
+
+#[test]
+fn moving_the_cursor() {
+    let lua = Lua::new();
+    let (editor, backend) = editor(&lua);
+    let path = file("cursor.txt", "first line\nsecond line\nthird line\n");
+    open(&editor, &path);
+    let screen = act(&editor, &lua, &backend, &[]);
+    assert_eq!(screen.cursor, (column(&screen, 1, "first"), 1));
+    assert!(screen.cursor_visible);
+    let screen = act(
+        &editor,
+        &lua,
+        &backend,
+        &[
+            Action::MoveCursor(Direction::Down),
+            Action::MoveCursor(Direction::Down),
+            Action::MoveCursor(Direction::Right),
+        ],
+    );
+    assert_eq!(screen.cursor, (column(&screen, 3, "third") + 1, 3));
+    let screen = act(&editor, &lua, &backend, &[Action::MoveEnd]);
+    assert_eq!(screen.cursor, (column(&screen, 3, "third") + 10, 3));
+}
+
+#[test]
+fn selecting_text() {
+    let lua = Lua::new();
+    let (editor, backend) = editor(&lua);
+    let path = file("select.txt", "first line\nsecond line\n");
+    open(&editor, &path);
+    let screen = act(
+        &editor,
+        &lua,
+        &backend,
+        &[
+            Action::Select(Direction::Right),
+            Action::Select(Direction::Right),
+            Action::Select(Direction::Right),
+        ],
+    );
+    let selection = config!(ged!(&editor).config, colors)
+        .selection_bg
+        .to_color()
+        .unwrap();
+    let start = column(&screen, 1, "first");
+    // "fir" is selected, the rest of the line isn't
+    for x in start..start + 3 {
+        assert_eq!(screen.cell(x, 1).unwrap().bg, selection);
+    }
+    for x in start + 3..start + 10 {
+        assert_ne!(screen.cell(x, 1).unwrap().bg, selection);
+    }
+    // Nothing on the next line is selected
+    let next = column(&screen, 2, "second");
+    assert_ne!(screen.cell(next, 2).unwrap().bg, selection);
+}
+
+#[test]
+fn status_line() {
+    let lua = Lua::new();
+    let (editor, backend) = editor(&lua);
+    let path = file("status.txt", "first line\nsecond line\nthird line\n");
+    open(&editor, &path);
+    let screen = act(
+        &editor,
+        &lua,
+        &backend,
+        &[Action::MoveCursor(Direction::Down)],
+    );
+    // The status line is just above the feedback line at the bottom
+    let status = screen.row(H - 2);
+    assert!(status.contains("status.txt"));
+    assert!(status.contains("2 / "));
+}
+
+#[test]
+fn typing_is_drawn() {
+    let lua = Lua::new();
+    let (editor, backend) = editor(&lua);
+    let path = file("typing.txt", "world\n");
+    open(&editor, &path);
+    let typed: Vec<Action> = "hello ".chars().map(Action::InsertChar).collect();
+    let screen = act(&editor, &lua, &backend, &typed);
+    assert!(screen.row(1).contains("hello world"));
+    // The tab line shows that the document has changed
+    assert!(screen.row(0).contains("typing.txt[+]"));
+}
+
+#[test]
+fn switching_tabs_after_resizing() {
+    let lua = Lua::new();
+    let (editor, backend) = editor(&lua);
+    let long = file("long.txt", &"line\n".repeat(100));
+    open(&editor, &long);
+    ged!(mut &editor)
+        .open_or_new(file("short.txt", "short\n"))
+        .unwrap();
+    // Leave the cursor near the bottom of the screen in the long document
+    let down = vec![Action::MoveCursor(Direction::Down); 60];
+    act(&editor, &lua, &backend, &down);
+    act(&editor, &lua, &backend, &[Action::NextTab]);
+    // The screen shrinks while the long document isn't being shown
+    backend.resize(60, 12);
+    let screen = act(&editor, &lua, &backend, &[]);
+    assert!(screen.row(1).contains("short"));
+    let switches = [
+        (Action::PreviousTab, "line"),
+        (Action::GoToTab(2), "short"),
+        (Action::GoToTab(1), "line"),
+    ];
+    for (action, text) in switches {
+        let screen = act(&editor, &lua, &backend, &[action]);
+        // The view has moved to keep the cursor on screen, where it is drawn
+        let loc = ged!(&editor).try_doc().unwrap().cursor_loc_in_screen();
+        assert!(loc.is_some());
+        let at = ged!(&editor).cursor_position().unwrap();
+        assert_eq!(screen.cursor, (at.x, at.y));
+        assert!(screen.row(at.y).contains(text));
+    }
+    assert_eq!(ged!(&editor).try_doc().unwrap().loc().y, 60);
+    // Growing again leaves everything where it was
+    backend.resize(W, H);
+    let screen = act(
+        &editor,
+        &lua,
+        &backend,
+        &[Action::NextTab, Action::PreviousTab],
+    );
+    let at = ged!(&editor).cursor_position().unwrap();
+    assert_eq!(screen.cursor, (at.x, at.y));
+}
+
+#[test]
+fn reopening_where_left() {
+    let lua = Lua::new();
+    let (editor, backend) = editor(&lua);
+    let lines: Vec<String> = (1..=50).map(|n| format!("line {n}\n")).collect();
+    let path = file("left.txt", &lines.concat());
+    open(&editor, &path);
+    let down = vec![Action::MoveCursor(Direction::Down); 40];
+    act(&editor, &lua, &backend, &down);
+    act(&editor, &lua, &backend, &[Action::MoveEnd, Action::Quit]);
+    // Quitting the last document ends the session, so each reopening starts another
+    let reopen = || {
+        ged!(mut &editor).active = true;
+        open(&editor, &path);
+    };
+    // Opening the file again puts the cursor back, in view
+    reopen();
+    let screen = act(&editor, &lua, &backend, &[]);
+    let doc = ged!(&editor).try_doc().unwrap().char_loc();
+    assert_eq!((doc.x, doc.y), (7, 40));
+    let at = ged!(&editor).cursor_position().unwrap();
+    assert!(screen.row(at.y).contains("line 41"));
+    // A position given on the command line comes first
+    act(&editor, &lua, &backend, &[Action::Quit]);
+    reopen();
+    ged!(mut &editor).go_to_position(0, 3, None);
+    assert_eq!(ged!(&editor).try_doc().unwrap().char_loc().y, 2);
+    // Files that have got shorter since have the cursor kept within them
+    act(&editor, &lua, &backend, &down);
+    act(&editor, &lua, &backend, &[Action::Quit]);
+    std::fs::write(&path, lines[..10].concat()).unwrap();
+    reopen();
+    let screen = act(&editor, &lua, &backend, &[]);
+    let ed = ged!(&editor);
+    let doc = ed.try_doc().unwrap();
+    assert!(doc.char_loc().y <= doc.len_lines());
+    assert!(doc.cursor_loc_in_screen().is_some());
+    assert!(screen.row(1).contains("line 1"));
+}
+
+#[test]
+fn long_lines() {
+    let lua = Lua::new();
+    let (editor, backend) = editor(&lua);
+    let path = file(
+        "long_lines.txt",
+        &format!("{}\nshort\n", "0123456789".repeat(20)),
+    );
+    open(&editor, &path);
+    // Lines running off the right of the view are marked at the edge
+    let screen = act(&editor, &lua, &backend, &[]);
+    assert!(screen.row(1).ends_with('…'));
+    assert!(!screen.row(2).contains('…'));
+    // The view scrolls before the cursor reaches the edge, marking the text off to the left
+    let screen = act(&editor, &lua, &backend, &[Action::MoveEnd]);
+    let at = ged!(&editor).cursor_position().unwrap();
+    assert!(at.x + 5 < W);
+    assert!(screen.row(1).contains("│…"));
+    assert!(!screen.row(1).ends_with('…'));
+    // The view can be scrolled without moving the cursor
+    let offset = ged!(&editor).try_doc().unwrap().offset.x;
+    lua.load("editor:scroll_left(3)").exec().unwrap();
+    let scrolled = ged!(&editor)
+        .try_doc()
+        .map(|doc| (doc.offset.x, doc.char_loc().x));
+    assert_eq!(scrolled, Some((offset - 3, 200)));
+    // Clicking on a marker goes to the text underneath it
+    let screen = act(&editor, &lua, &backend, &[]);
+    let click = MouseEvent {
+        kind: MouseEventKind::Down(MouseButton::Left),
+        column: u16::try_from(column(&screen, 1, "…")).unwrap(),
+        row: 1,
+        modifiers: KeyModifiers::NONE,
+    };
+    ged!(mut &editor).handle_mouse_event(&lua, click).unwrap();
+    assert_eq!(ged!(&editor).try_doc().unwrap().char_loc().x, offset - 3);
+}
+
+/// Click (or drag) with the mouse at a place on the screen
+fn mouse(editor: &AnyUserData, lua: &Lua, kind: MouseEventKind, at: (usize, usize), ctrl: bool) {
+    let event = MouseEvent {
+        kind,
+        column: u16::try_from(at.0).unwrap(),
+        row: u16::try_from(at.1).unwrap(),
+        modifiers: if ctrl {
+            KeyModifiers::CONTROL
+        } else {
+            KeyModifiers::NONE
+        },
+    };
+    ged!(mut &editor).handle_mouse_event(lua, event).unwrap();
+}
+
+#[test]
+fn gutter_clicks() {
+    let lua = Lua::new();
+    let (editor, backend) = editor(&lua);
+    let text: String = (1..=9).map(|n| format!("line {n}\n")).collect();
+    let path = file("gutter.txt", &text);
+    open(&editor, &path);
+    act(&editor, &lua, &backend, &[]);
+    let down = MouseEventKind::Down(MouseButton::Left);
+    let drag = MouseEventKind::Drag(MouseButton::Left);
+    let up = MouseEventKind::Up(MouseButton::Left);
+    let selection = |editor: &AnyUserData| {
+        let ed = ged!(&editor);
+        ed.try_doc().unwrap().selection_loc_bound()
+    };
+    // Clicking a line number selects the line, and dragging selects whole lines
+    let dent = ged!(&editor).dent();
+    mouse(&editor, &lua, down, (dent - 1, 3), false);
+    assert_eq!(selection(&editor), (Loc::at(0, 2), Loc::at(6, 2)));
+    mouse(&editor, &lua, drag, (dent + 3, 5), false);
+    assert_eq!(selection(&editor), (Loc::at(0, 2), Loc::at(6, 4)));
+    mouse(&editor, &lua, drag, (0, 1), false);
+    assert_eq!(selection(&editor), (Loc::at(0, 0), Loc::at(6, 2)));
+    mouse(&editor, &lua, up, (0, 1), false);
+    // Ctrl clicking a line number bookmarks it
+    mouse(&editor, &lua, down, (dent - 1, 4), true);
+    assert!(ged!(&editor).try_doc().unwrap().bookmark_at(3).is_some());
+    let bookmarked: Vec<usize> = lua.load("editor.bookmarked_lines").eval().unwrap();
+    assert_eq!(bookmarked, [4]);
+    // Clicking just past the line numbers moves into the text as usual
+    mouse(&editor, &lua, down, (dent, 2), false);
+    let ed = ged!(&editor);
+    let doc = ed.try_doc().unwrap();
+    assert_eq!(doc.loc(), Loc::at(0, 1));
+    assert!(doc.is_selection_empty());
+}
+
+#[test]
+fn text_stats() {
+    let lua = Lua::new();
+    let (editor, backend) = editor(&lua);
+    let path = file("stats.txt", "one two three\nfour five\n");
+    open(&editor, &path);
+    // Word counts in the status line keep up with edits
+    lua.load("status_line.parts = { 'words: {word_count}' }")
+        .exec()
+        .unwrap();
+    let screen = act(&editor, &lua, &backend, &[]);
+    assert!(screen.text().contains("words: 5"));
+    let mut typed = vec![Action::MoveEnd];
+    typed.extend(" six".chars().map(Action::InsertChar));
+    let screen = act(&editor, &lua, &backend, &typed);
+    assert!(screen.text().contains("words: 6"));
+    // The figures for the selection are listed beside those for the whole document
+    let mut actions = vec![Action::MoveHome];
+    actions.extend((0..3).map(|_| Action::Select(Direction::Right)));
+    act(&editor, &lua, &backend, &actions);
+    lua.load("commands['stats']({})").exec().unwrap();
+    let screen = act(&editor, &lua, &backend, &[]);
+    assert!(screen.text().contains("Statistics for stats.txt"));
+    assert!(screen
+        .text()
+        .contains(&format!("{:<20}{:>12}{:>12}", "Words", 6, 1)));
+    assert!(screen
+        .text()
+        .contains(&format!("{:<20}{:>12}{:>12}", "Lines", 2, 1)));
+    assert!(ged!(&editor).try_doc().unwrap().info.read_only);
+}
+
+#[test]
+fn clipboard_history() {
+    let lua = Lua::new();
+    let (editor, backend) = editor(&lua);
+    let path = file("clipboard.txt", "alpha\nbeta\n");
+    open(&editor, &path);
+    let select = |n| {
+        (0..n)
+            .map(|_| Action::Select(Direction::Right))
+            .collect::<Vec<_>>()
+    };
+    // Copying and cutting both add to the history, newest first
+    act(&editor, &lua, &backend, &select(5));
+    ged!(mut &editor).copy().unwrap();
+    let down = [Action::MoveCursor(Direction::Down), Action::MoveHome];
+    act(&editor, &lua, &backend, &down);
+    act(&editor, &lua, &backend, &select(4));
+    ged!(mut &editor).cut().unwrap();
+    assert_eq!(ged!(&editor).clipboard.entries, ["beta", "alpha"]);
+    // The newest is pasted straight away, older ones are picked from a list
+    assert!(ged!(mut &editor).paste_latest().unwrap());
+    for code in [KeyCode::Down, KeyCode::Enter] {
+        let event = Event::Key(KeyEvent::new(code, KeyModifiers::NONE));
+        ged!(mut &editor).pending_events.push_back(event);
+    }
+    ged!(mut &editor).paste_from_history(&lua).unwrap();
+    let text = ged!(&editor).try_doc().unwrap().file.to_string();
+    assert_eq!(text, "alpha\nbetaalpha\n");
+    // Each paste is undone in one go
+    act(&editor, &lua, &backend, &[Action::Undo]);
+    let text = ged!(&editor).try_doc().unwrap().file.to_string();
+    assert_eq!(text, "alpha\nbeta\n");
+    // Only so many snippets are kept
+    lua.load("terminal.clipboard_history = 1").exec().unwrap();
+    ged!(mut &editor).remember_copy("gamma");
+    assert_eq!(ged!(&editor).clipboard.entries, ["gamma"]);
+}
+
+/// Put the cursor on the first line of the document that hasn't been loaded yet
+/// (as a plug-in moving it straight there would), returning which line that is
+fn jump_past_loaded(editor: &AnyUserData) -> usize {
+    let mut ed = ged!(mut &editor);
+    let doc = ed.try_doc_mut().unwrap();
+    let y = doc.info.loaded_to;
+    doc.cursor.loc = Loc::at(0, y);
+    doc.cursor.selection_end = doc.cursor.loc;
+    doc.char_ptr = 0;
+    y
+}
+
+#[test]
+fn editing_unloaded_lines() {
+    let lua = Lua::new();
+    let (editor, backend) = editor(&lua);
+    let lines: Vec<String> = (0..5000).map(|n| format!("line {n}\n")).collect();
+    let path = file("unloaded.txt", &lines.concat());
+    open(&editor, &path);
+    act(&editor, &lua, &backend, &[]);
+    ged!(mut &editor).go_to_position(0, 3000, None);
+    let line = |y| ged!(&editor).try_doc().unwrap().line(y).unwrap();
+    // Joining a line that wasn't loaded onto the one above it
+    let y = jump_past_loaded(&editor);
+    act(&editor, &lua, &backend, &[Action::Backspace]);
+    assert_eq!(line(y - 1), format!("line {}line {y}", y - 1));
+    // Removing the first character of a line that wasn't loaded
+    let y = jump_past_loaded(&editor);
+    act(&editor, &lua, &backend, &[Action::Delete]);
+    assert_eq!(line(y), format!("ine {}", y + 1));
+    // Splitting a line that wasn't loaded
+    let y = jump_past_loaded(&editor);
+    act(&editor, &lua, &backend, &[Action::InsertLine]);
+    assert_eq!(
+        (line(y), line(y + 1)),
+        (String::new(), format!("line {}", y + 1))
+    );
+}
+
+/// Queue up keys for a menu to read
+fn queue(editor: &AnyUserData, keys: &[(KeyModifiers, KeyCode)]) {
+    for (modifiers, code) in keys {
+        let event = Event::Key(KeyEvent::new(*code, *modifiers));
+        ged!(mut &editor).pending_events.push_back(event);
+    }
+}
+
+#[test]
+fn choice_menus() {
+    let lua = Lua::new();
+    let (editor, backend) = editor(&lua);
+    let options = ["recover", "diff", "discard"];
+    let none = KeyModifiers::NONE;
+    let pick = |keys: &[(KeyModifiers, KeyCode)]| {
+        queue(&editor, keys);
+        ged!(mut &editor)
+            .choice("Swap file found", &options)
+            .unwrap()
+    };
+    // Options are picked by number, by hotkey, or by moving to them
+    assert_eq!(pick(&[(none, KeyCode::Char('2'))]), Some(1));
+    assert_eq!(pick(&[(none, KeyCode::Char('i'))]), Some(2));
+    assert_eq!(pick(&[(KeyModifiers::SHIFT, KeyCode::Char('R'))]), Some(0));
+    let moves = [KeyCode::Down, KeyCode::Down, KeyCode::Up, KeyCode::Enter];
+    assert_eq!(pick(&moves.map(|code| (none, code))), Some(1));
+    assert_eq!(
+        pick(&[(none, KeyCode::Char('9')), (none, KeyCode::Esc)]),
+        None
+    );
+    // The options are listed on the feedback line, with their hotkeys marked
+    let screen = backend.screen();
+    assert!((0..H).any(|y| screen.row(y).contains("1 [r]ecover")));
+    assert!((0..H).any(|y| screen.row(y).contains("3 d[i]scard")));
+    assert!(matches!(ged!(&editor).feedback, Feedback::None));
+    // Confirmations are menus that only their confirming key picks from
+    queue(
+        &editor,
+        &[
+            (none, KeyCode::Enter),
+            (KeyModifiers::CONTROL, KeyCode::Char('q')),
+        ],
+    );
+    assert!(ged!(mut &editor).confirm("Really quit?").unwrap());
+    queue(&editor, &[(none, KeyCode::Char('y')), (none, KeyCode::Esc)]);
+    assert!(!ged!(mut &editor).confirm("Really quit?").unwrap());
+}
+
+#[test]
+fn build_locations() {
+    use ox::editor::{parse_location, Location};
+    let at = |path: &str, line, column| Location {
+        path: path.to_string(),
+        line,
+        column,
+    };
+    // Rust, C and python all point to places in files in their own ways
+    assert_eq!(
+        parse_location("   --> src/main.rs:12:5"),
+        Some(at("src/main.rs", 12, Some(5)))
+    );
+    assert_eq!(
+        parse_location("main.c:3: error: expected ';'"),
+        Some(at("main.c", 3, None))
+    );
+    assert_eq!(
+        parse_location("  File \"app/run.py\", line 40, in main"),
+        Some(at("app/run.py", 40, None))
+    );
+    assert_eq!(parse_location("Finished in 12:30"), None);
+}
+
+#[cfg(not(target_os = "windows"))]
+#[test]
+fn build_output() {
+    let lua = Lua::new();
+    let (editor, backend) = editor(&lua);
+    let path = file("built.c", "int main() {\n    return x;\n}\n");
+    let command = format!("echo 'compiling'; echo '{path}:2:12: error: x is undefined' >&2");
+    ged!(mut &editor).build(&lua, Some(&command)).unwrap();
+    act(&editor, &lua, &backend, &[]);
+    // The output is listed, with the location in it marked
+    {
+        let ed = ged!(&editor);
+        let file = ed.files.get(ed.ptr.clone()).unwrap();
+        assert_eq!(file.scratch.as_deref(), Some(ox::editor::BUILD_NAME));
+        assert_eq!(file.signs.len(), 1);
+        assert_eq!(
+            file.signs[0].message,
+            format!("{path}:2:12: error: x is undefined")
+        );
+    }
+    // Stepping through the locations opens the file at each one
+    ged!(mut &editor).next_build_location(true).unwrap();
+    let ed = ged!(&editor);
+    let doc = ed.try_doc().unwrap();
+    assert!(doc
+        .file_name
+        .as_ref()
+        .is_some_and(|f| f.ends_with("built.c")));
+    assert_eq!(doc.loc(), Loc::at(11, 1));
+}
+
+#[test]
+fn search_status() {
+    let lua = Lua::new();
+    let (editor, backend) = editor(&lua);
+    let path = file("search_status.txt", "find me\nand find me again\n");
+    open(&editor, &path);
+    let none = KeyModifiers::NONE;
+    let search = || {
+        let mut keys: Vec<(KeyModifiers, KeyCode)> =
+            "find".chars().map(|c| (none, KeyCode::Char(c))).collect();
+        keys.extend([(none, KeyCode::Enter), (none, KeyCode::Esc)]);
+        queue(&editor, &keys);
+        ged!(mut &editor).search(&lua).unwrap();
+        backend.screen().row(H - 1)
+    };
+    // The keys are listed in the colours of the status line
+    let row = search();
+    assert!(row.starts_with("[<-]: Search previous | [->]: Search next"));
+    let status_bg = config!(ged!(&editor).config, colors)
+        .status_bg
+        .to_color()
+        .unwrap();
+    assert_eq!(backend.screen().cell(0, H - 1).unwrap().bg, status_bg);
+    // They can be put in other words, and are cut short when they don't fit
+    lua.load("status_line.search = 'Suchen: [<-] zurück | [->] weiter' .. string.rep('!', 100)")
+        .exec()
+        .unwrap();
+    let row = search();
+    assert!(row.starts_with("Suchen: [<-] zurück | [->] weiter!"));
+    assert_eq!(row.chars().count(), W);
+    assert!(row.ends_with('…'));
+}
+
+#[cfg(not(target_os = "windows"))]
+#[test]
+fn remote_control() {
+    use ox::remote::{answer, send_to, RemoteControl};
+    use std::os::unix::fs::PermissionsExt;
+    use std::time::{Duration, Instant};
+    let lua = Lua::new();
+    let (editor, _) = editor(&lua);
+    // A socket left behind by an instance that crashed is taken over
+    let socket = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("remote.sock");
+    let _ = std::fs::remove_file(&socket);
+    drop(std::os::unix::net::UnixListener::bind(&socket).unwrap());
+    let remote = RemoteControl::start_at(&socket).unwrap().unwrap();
+    let mode = std::fs::metadata(&socket).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o600);
+    // One that is still being listened on isn't
+    assert!(RemoteControl::start_at(&socket).unwrap().is_none());
+    // Nor is anything other than a socket
+    let in_the_way = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("remote_in_the_way");
+    std::fs::write(&in_the_way, "mine").unwrap();
+    assert!(RemoteControl::start_at(&in_the_way).is_err());
+    assert_eq!(std::fs::read_to_string(&in_the_way).unwrap(), "mine");
+    ged!(mut &editor).remote = Some(remote);
+    let ask = |command: &str, args: &[&str]| {
+        let socket = socket.clone();
+        let (asked, args): (String, Vec<String>) = (
+            command.to_string(),
+            args.iter().map(ToString::to_string).collect(),
+        );
+        let client = std::thread::spawn(move || send_to(&socket, &asked, &args));
+        // Wait for the request to come in, giving up rather than hanging if it never does
+        let deadline = Instant::now() + Duration::from_secs(5);
+        loop {
+            let connection = ged!(mut &editor).poll_remote();
+            if let Some(connection) = connection {
+                answer(&editor, &lua, connection);
+                break;
+            }
+            assert!(Instant::now() < deadline, "no {command} request arrived");
+            std::thread::sleep(Duration::from_millis(5));
+        }
+        client.join().unwrap().unwrap().unwrap()
+    };
+    let y = |editor: &AnyUserData| ged!(&editor).try_doc().unwrap().loc().y;
+    // Files are opened at the position asked for, and moved to
+    let path = file("remote.txt", "one\ntwo\nthree\n");
+    assert_eq!(
+        ask("open", &[&format!("{path}:3")]),
+        format!("ok\nOpened {path}")
+    );
+    let name = ged!(&editor).try_doc().unwrap().file_name.clone().unwrap();
+    assert!(name.ends_with("remote.txt"));
+    assert_eq!(y(&editor), 2);
+    assert_eq!(ask("goto", &["2"]), "ok\n");
+    assert_eq!(y(&editor), 1);
+    // Lua is run, replying with what it gives back
+    assert_eq!(ask("eval", &["return", "1 + 1"]), "ok\n2");
+    assert!(ask("eval", &["error('no')"]).starts_with("error\n"));
+    assert!(ask("fly", &[]).starts_with("error\n"));
+    // Requests go nowhere once the editor stops listening
+    ged!(mut &editor).remote = None;
+    assert!(!socket.exists());
+    assert!(send_to(&socket, "goto", &["1".to_string()]).is_none());
+}
+
+#[test]
+fn diff_view() {
+    let lua = Lua::new();
+    let (editor, backend) = editor(&lua);
+    let path = file("diff_view.txt", "one\ntwo\nthree\nfour\n");
+    open(&editor, &path);
+    let down = Action::MoveCursor(Direction::Down);
+    act(
+        &editor,
+        &lua,
+        &backend,
+        &[
+            down.clone(),
+            Action::MoveEnd,
+            Action::InsertChar('!'),
+            down,
+            Action::MoveEnd,
+            Action::InsertLine,
+            Action::InsertChar('x'),
+        ],
+    );
+    // What is saved is shown beside what has changed, lined up and coloured by how it differs
+    ged!(mut &editor).toggle_diff(None).unwrap();
+    let screen = act(&editor, &lua, &backend, &[]);
+    assert!(screen.row(0).contains("saved → diff_view.txt"));
+    assert!(screen.row(2).contains("2 two"));
+    assert!(screen.row(2).contains("2 two!"));
+    assert!(screen.row(4).contains('╱'));
+    assert!(screen.row(4).contains("4 x"));
+    assert!(screen.row(5).contains("4 four"));
+    assert!(screen.row(5).contains("5 four"));
+    let colors = config!(ged!(&editor).config, colors).clone();
+    let has_bg = |y: usize, bg: &ox::config::Color| {
+        let bg = bg.to_color().unwrap();
+        (0..W).any(|x| screen.cell(x, y).unwrap().bg == bg)
+    };
+    assert!(has_bg(2, &colors.diff_changed_bg));
+    assert!(has_bg(4, &colors.diff_added_bg));
+    assert!(!has_bg(5, &colors.diff_added_bg));
+    // The differences are stepped through, going round at the end
+    ged!(mut &editor).next_hunk(true);
+    assert_eq!(ged!(&editor).try_doc().unwrap().loc().y, 1);
+    ged!(mut &editor).next_hunk(false);
+    assert_eq!(ged!(&editor).try_doc().unwrap().loc().y, 3);
+    // Reverting one puts back what is saved as a single change
+    ged!(mut &editor).next_hunk(true);
+    act(&editor, &lua, &backend, &[Action::Commit]);
+    let depth = undo_depth(&editor);
+    ged!(mut &editor).revert_hunk();
+    assert_eq!(undo_depth(&editor), depth + 1);
+    let screen = act(&editor, &lua, &backend, &[]);
+    assert!(!screen.row(2).contains("two!"));
+    assert_eq!(ged!(&editor).try_doc().unwrap().line(1).unwrap(), "two");
+    assert_eq!(ged!(&editor).try_doc().unwrap().line(3).unwrap(), "x");
+    let screen = act(&editor, &lua, &backend, &[Action::Undo]);
+    assert!(screen.row(2).contains("2 two!"));
+    // Comparing again closes the diff
+    ged!(mut &editor).toggle_diff(None).unwrap();
+    assert!(ged!(&editor).files.all_diffs(vec![]).is_empty());
+}
+
+#[test]
+fn scripted_cleanup() {
+    let lua = Lua::new();
+    let (editor, _) = editor(&lua);
+    open(
+        &editor,
+        &file("cleanup_a.txt", "let x = 1;  \nTODO: tidy\nfoo  bar\n"),
+    );
+    ged!(mut &editor)
+        .open_or_new(file("cleanup_b.txt", "foo bar\t\n\tTODO later\n"))
+        .unwrap();
+    // The sort of thing a configuration file might do to tidy up every open file
+    let (todos, removed, found): (usize, usize, mlua::Table) = lua
+        .load(
+            r#"
+            local todos, removed, found = 0, 0, {}
+            for id = 0, editor.document_count - 1 do
+                editor:move_to_document(id)
+                editor:commit()
+                removed = removed + editor:replace_all("[ \t]+$", "")
+                editor:each_line(function(line, y)
+                    if line:find("TODO") then
+                        todos = todos + 1
+                    end
+                end)
+                editor:move_to(0, 1)
+                found[id + 1] = editor:search("bar")
+            end
+            return todos, removed, found
+            "#,
+        )
+        .eval()
+        .unwrap();
+    assert_eq!((todos, removed), (2, 2));
+    // Searching selects the match and gives where it starts
+    let first: mlua::Table = found.get(1).unwrap();
+    assert_eq!(first.get::<usize>("x").unwrap(), 5);
+    assert_eq!(first.get::<usize>("y").unwrap(), 3);
+    let selected: String = lua.load("editor:selection_text()").eval().unwrap();
+    assert_eq!(selected, "bar");
+    // Each file was cleaned up as a single change
+    ged!(mut &editor).switch_to(0);
+    assert_eq!(
+        ged!(&editor).try_doc().unwrap().line(0).unwrap(),
+        "let x = 1;"
+    );
+    lua.load("editor:undo()").exec().unwrap();
+    assert_eq!(
+        ged!(&editor).try_doc().unwrap().line(0).unwrap(),
+        "let x = 1;  "
+    );
+    // Lines are only read as far as asked for
+    let first_line: String = lua
+        .load(
+            r#"
+            local first
+            editor:each_line(function(line)
+                first = line
+                return false
+            end)
+            editor:select(0, 1, 3, 1)
+            return first .. "/" .. editor:selection_text()
+            "#,
+        )
+        .eval()
+        .unwrap();
+    assert_eq!(first_line, "let x = 1;  /let");
+}
+
+#[test]
+fn focus_changes() {
+    use std::time::{Duration, SystemTime};
+    let lua = Lua::new();
+    let (editor, backend) = editor(&lua);
+    let path = file("focus.txt", "first\n");
+    open(&editor, &path);
+    let focus = |gained: bool| {
+        let event = if gained {
+            Event::FocusGained
+        } else {
+            Event::FocusLost
+        };
+        ox::handle_event(&editor, &event, &lua).unwrap();
+    };
+    // Another program changing the file (well after it was last changed)
+    let rewrite = |text: &str, later: u64| {
+        std::fs::write(&path, text).unwrap();
+        let file = std::fs::File::options().write(true).open(&path).unwrap();
+        let time = SystemTime::now() + Duration::from_secs(later);
+        file.set_modified(time).unwrap();
+    };
+    // Changes can be saved on switching away
+    lua.load("document.save_on_focus_lost = true")
+        .exec()
+        .unwrap();
+    act(&editor, &lua, &backend, &[Action::InsertChar('!')]);
+    focus(false);
+    assert!(!ged!(&editor).focus.focused);
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "!first\n");
+    // Files changed in the meantime are reloaded on coming back
+    rewrite("second\n", 10);
+    focus(true);
+    assert!(ged!(&editor).focus.focused);
+    assert!(matches!(ged!(&editor).feedback, Feedback::Info(_)));
+    let screen = act(&editor, &lua, &backend, &[]);
+    assert!(screen.row(1).contains("second"));
+    assert!(!ged!(&editor)
+        .files
+        .get(ged!(&editor).ptr.clone())
+        .unwrap()
+        .unsaved_changes());
+    // Unsaved changes are never thrown away
+    lua.load("document.save_on_focus_lost = false")
+        .exec()
+        .unwrap();
+    act(
+        &editor,
+        &lua,
+        &backend,
+        &[Action::MoveHome, Action::InsertChar('?')],
+    );
+    focus(false);
+    rewrite("third\n", 20);
+    focus(true);
+    assert!(matches!(ged!(&editor).feedback, Feedback::Warning(_)));
+    assert_eq!(ged!(&editor).try_doc().unwrap().line(0).unwrap(), "?second");
+    // Nothing happens when nothing has changed
+    focus(false);
+    focus(true);
+    assert_eq!(ged!(&editor).try_doc().unwrap().line(0).unwrap(), "?second");
+    // Changes the tab line has already noticed are picked up on coming back too
+    act(&editor, &lua, &backend, &[Action::Undo]);
+    rewrite("fourth\n", 30);
+    ged!(mut &editor).check_on_disk();
+    let state = |editor: &AnyUserData| {
+        let ptr = ged!(&editor).ptr.clone();
+        ged!(&editor).files.get(ptr).unwrap().on_disk.state
+    };
+    assert_eq!(state(&editor), ox::editor::DiskState::Modified);
+    focus(true);
+    assert_eq!(ged!(&editor).try_doc().unwrap().line(0).unwrap(), "fourth");
+    assert_eq!(state(&editor), ox::editor::DiskState::Unchanged);
+}
+
+#[test]
+fn greeting_entries() {
+    let lua = Lua::new();
+    let (editor, backend) = editor(&lua);
+    let path = file("greeting.txt", "recent\n");
+    ged!(mut &editor).note_opened(&path);
+    ged!(mut &editor).new_if_empty().unwrap();
+    let screen = act(&editor, &lua, &backend, &[]);
+    // The entries are listed below the message, with their keys standing out
+    let y = (0..H)
+        .find(|y| screen.row(*y).contains("Open the most recent file"))
+        .unwrap();
+    let key = column(&screen, y, "r  Open");
+    let highlight = config!(ged!(&editor).config, colors)
+        .highlight
+        .to_color()
+        .unwrap();
+    assert_eq!(screen.cell(key, y).unwrap().fg, highlight);
+    assert_ne!(screen.cell(key + 3, y).unwrap().fg, highlight);
+    // Pressing an entry's key carries it out (and dismisses the greeting)
+    press(&editor, &lua, KeyModifiers::NONE, KeyCode::Char('r'));
+    assert!(!ged!(&editor).greet);
+    let doc = ged!(&editor).try_doc().unwrap().file_name.clone();
+    assert!(doc.is_some_and(|name| name.ends_with("greeting.txt")));
+    // Entries can be replaced and added from lua, running functions when clicked
+    lua.load(
+        r#"
+        greeting_message:entry("r", "Latest file", "recent")
+        greeting_message:entry("x", "Say hello", function() editor:display_info("hello") end)
+        "#,
+    )
+    .exec()
+    .unwrap();
+    assert!(lua
+        .load("greeting_message:entry('z', 'Nothing', 'nothing')")
+        .exec()
+        .is_err());
+    ged!(mut &editor).go_to_tab(1);
+    ged!(mut &editor).greet = true;
+    let screen = act(&editor, &lua, &backend, &[]);
+    assert!(!screen.text().contains("Open the most recent file"));
+    assert!(screen.text().contains("Latest file"));
+    let y = (0..H)
+        .find(|y| screen.row(*y).contains("Say hello"))
+        .unwrap();
+    let x = column(&screen, y, "Say hello");
+    let click = MouseEvent {
+        kind: MouseEventKind::Down(MouseButton::Left),
+        column: u16::try_from(x).unwrap(),
+        row: u16::try_from(y).unwrap(),
+        modifiers: KeyModifiers::NONE,
+    };
+    ox::handle_event(&editor, &Event::Mouse(click), &lua).unwrap();
+    assert!(matches!(&ged!(&editor).feedback, Feedback::Info(m) if m == "hello"));
+    assert!(!ged!(&editor).greet);
+    // Where the menu can't be centred, it is shown as plain text from the left
+    ged!(mut &editor).greet = true;
+    backend.resize(30, H);
+    let screen = act(&editor, &lua, &backend, &[]);
+    let y = (0..H).find(|y| screen.row(*y).contains("x  Say")).unwrap();
+    let title = (0..H)
+        .find(|y| screen.row(*y).contains("Ox Editor"))
+        .unwrap();
+    let start = column(&screen, title, "Ox Editor");
+    assert_eq!(column(&screen, y, "x  Say"), start);
+    assert_ne!(screen.cell(start, y).unwrap().fg, highlight);
+    // Anything else is typed as normal, dismissing the greeting
+    press(&editor, &lua, KeyModifiers::NONE, KeyCode::Char('h'));
+    assert!(!ged!(&editor).greet);
+    assert_eq!(ged!(&editor).try_doc().unwrap().line(0).unwrap(), "h");
+}
+
+#[test]
+fn tab_line_indicators() {
+    use std::time::{Duration, SystemTime};
+    let lua = Lua::new();
+    let (editor, _) = editor(&lua);
+    lua.load("tab_line.format = '{file_name}{modified}{read_only}{stdin}{scratch}{deleted}{disk_modified}{unknown}'")
+        .exec()
+        .unwrap();
+    let header = || {
+        let editor = ged!(&editor);
+        let fc = editor.files.get(editor.ptr.clone()).unwrap();
+        let mut feedback = Feedback::None;
+        let header = config!(editor.config, tab_line).render(&lua, fc, &mut feedback);
+        assert!(matches!(feedback, Feedback::None));
+        header
+    };
+    let path = file("indicators.txt", "text\n");
+    open(&editor, &path);
+    // Placeholders that nothing provides are left empty
+    assert_eq!(header(), "indicators.txt");
+    ged!(mut &editor).try_doc_mut().unwrap().info.read_only = true;
+    assert_eq!(header(), "indicators.txt [RO]");
+    ged!(mut &editor).try_doc_mut().unwrap().info.read_only = false;
+    // Another program changing the file, then removing it
+    let file = std::fs::File::options().write(true).open(&path).unwrap();
+    file.set_modified(SystemTime::now() + Duration::from_secs(10))
+        .unwrap();
+    assert!(ged!(mut &editor).check_on_disk());
+    assert_eq!(header(), "indicators.txt [changed]");
+    std::fs::remove_file(&path).unwrap();
+    assert!(ged!(mut &editor).check_on_disk());
+    assert_eq!(header(), "indicators.txt [deleted]");
+    assert!(!ged!(mut &editor).check_on_disk());
+    // Saving puts the file back as it is in the editor
+    ged!(mut &editor).save().unwrap();
+    assert_eq!(header(), "indicators.txt");
+    // Documents read in from the standard input, and scratch buffers
+    ged!(mut &editor)
+        .open_from_string("piped\n".to_string(), None)
+        .unwrap();
+    ged!(mut &editor).next();
+    let ptr = ged!(&editor).ptr.clone();
+    ged!(mut &editor).files.get_mut(ptr).unwrap().stdin = true;
+    assert_eq!(header(), "[No Name] [stdin]");
+    ged!(mut &editor).scratch("").unwrap();
+    assert_eq!(header(), "[scratch] [tmp]");
+}
+
+#[test]
+fn abbreviations() {
+    let lua = Lua::new();
+    let (editor, backend) = editor(&lua);
+    lua.load(r#"abbreviations = { teh = "the", ["(c)"] = "©", Rust = { pfn = "pub fn" } }"#)
+        .exec()
+        .unwrap();
+    let type_text = |text: &str| {
+        for ch in text.chars() {
+            press(&editor, &lua, KeyModifiers::NONE, KeyCode::Char(ch));
+        }
+    };
+    let line = || ged!(&editor).try_doc().unwrap().line(0).unwrap();
+    let path = file("abbreviations.txt", "");
+    open(&editor, &path);
+    // Words are expanded once they end, leaving the cursor after what was typed
+    type_text("teh,");
+    assert_eq!(line(), "the,");
+    assert_eq!(ged!(&editor).try_doc().unwrap().char_loc(), Loc::at(4, 0));
+    // Undoing straight away brings back what was typed
+    act(&editor, &lua, &backend, &[Action::Undo]);
+    assert_eq!(line(), "teh,");
+    // Undoing lands on the change, so carry on typing from the end of the line
+    act(&editor, &lua, &backend, &[Action::MoveEnd]);
+    // Only whole words are expanded, and only those for this type of file
+    // (the pairs plug-in closes the bracket, so step over it rather than typing it)
+    type_text(" xteh tehx pfn (c");
+    press(&editor, &lua, KeyModifiers::NONE, KeyCode::Right);
+    type_text(" ");
+    assert_eq!(line(), "teh, xteh tehx pfn © ");
+    let path = file("abbreviations.rs", "");
+    open(&editor, &path);
+    ged!(mut &editor).go_to_tab(2);
+    type_text("pfn main");
+    assert_eq!(line(), "pub fn main");
+}
+
+#[test]
+fn cursor_styles() {
+    let lua = Lua::new();
+    let (editor, backend) = editor(&lua);
+    // Left as it is, the terminal is never asked to change the cursor
+    open(&editor, &file("cursor_styles.txt", "some text\n"));
+    assert_eq!(act(&editor, &lua, &backend, &[]).cursor_shape, 0);
+    lua.load("terminal.cursor_style = 'bar'; terminal.cursor_style_selection = 'blinking_block'")
+        .exec()
+        .unwrap();
+    assert_eq!(act(&editor, &lua, &backend, &[]).cursor_shape, 6);
+    let select = Action::Select(Direction::Right);
+    assert_eq!(act(&editor, &lua, &backend, &[select]).cursor_shape, 1);
+    // Overtype mode wins over a selection
+    ged!(mut &editor).toggle_overtype();
+    assert_eq!(act(&editor, &lua, &backend, &[]).cursor_shape, 4);
+    ged!(mut &editor).toggle_overtype();
+    let right = Action::MoveCursor(Direction::Right);
+    assert_eq!(act(&editor, &lua, &backend, &[right]).cursor_shape, 6);
+    assert!(lua.load("terminal.cursor_style = 'beam'").exec().is_err());
+}
+
+#[test]
+fn closing_documents() {
+    let lua = Lua::new();
+    let (editor, backend) = editor(&lua);
+    for name in [
+        "close_1.rs",
+        "close_2.py",
+        "close_3.rs",
+        "close_4.py",
+        "close_5.rs",
+    ] {
+        // Each document opens after the current one, so move on to it as at start up
+        ged!(mut &editor).open_or_new(file(name, "text\n")).unwrap();
+        ged!(mut &editor).next();
+    }
+    // The documents left and the one being edited,
+    // checking each kept the highlighting for its own file type
+    let left = || {
+        let ed = ged!(&editor);
+        let (fcs, current) = ed.files.get_atom(ed.ptr.clone()).unwrap();
+        let mut names = String::new();
+        for fc in fcs {
+            let name = fc.doc.file_name.clone().unwrap();
+            let kind = if name.ends_with(".rs") {
+                "Rust"
+            } else {
+                "Python"
+            };
+            assert_eq!(fc.file_type.as_ref().unwrap().name, kind);
+            names.push(name.split("close_").nth(1).unwrap().chars().next().unwrap());
+        }
+        (names, current)
+    };
+    // Closing from the middle, the start and the end settles on the document before
+    ged!(mut &editor).switch_to(2);
+    act(&editor, &lua, &backend, &[Action::Quit]);
+    assert_eq!(left(), ("1245".to_string(), 1));
+    ged!(mut &editor).switch_to(0);
+    act(&editor, &lua, &backend, &[Action::Quit]);
+    assert_eq!(left(), ("245".to_string(), 0));
+    ged!(mut &editor).switch_to(2);
+    act(&editor, &lua, &backend, &[Action::Quit]);
+    assert_eq!(left(), ("24".to_string(), 1));
+    // Forcing a document closed only throws away its own changes
+    ged!(mut &editor).switch_to(0);
+    act(&editor, &lua, &backend, &[Action::InsertChar('x')]);
+    ged!(mut &editor).switch_to(1);
+    act(&editor, &lua, &backend, &[Action::InsertChar('x')]);
+    queue(&editor, &[(KeyModifiers::CONTROL, KeyCode::Char('q'))]);
+    act(&editor, &lua, &backend, &[Action::Quit]);
+    assert_eq!(left(), ("2".to_string(), 0));
+    assert!(ged!(&editor).active);
+    // Closing the last document lists what is still unsaved before quitting
+    {
+        let ed = ged!(&editor);
+        assert!(ed.files.get(ed.ptr.clone()).unwrap().unsaved_changes());
+    }
+    queue(&editor, &[(KeyModifiers::NONE, KeyCode::Esc)]);
+    assert!(ged!(mut &editor).quit(&lua).is_err());
+    assert!(ged!(&editor).active);
+    queue(&editor, &[(KeyModifiers::NONE, KeyCode::Char('q'))]);
+    ged!(mut &editor).quit(&lua).unwrap();
+    assert!(!ged!(&editor).active);
+}
+
+#[test]
+fn color_swatches() {
+    let lua = Lua::new();
+    let (editor, backend) = editor(&lua);
+    let text = "a { color: #ff0000; }\nb { color: rgb(0, 0, 255) }\nc { color: #0f0";
+    open(&editor, &file("swatches.css", text));
+    let rgb = |r, g, b| ox::config::Color::Rgb(r, g, b).to_color().unwrap();
+    // The background of the column just after a colour (found by the text before it)
+    let swatch = |y: usize, before: &str| {
+        let screen = act(&editor, &lua, &backend, &[]);
+        let row = screen.row(y);
+        let x = row[..row.find(before).unwrap()].chars().count() + before.chars().count();
+        screen.cell(x, y).unwrap().bg
+    };
+    assert_eq!(swatch(1, "#ff0000"), rgb(255, 0, 0));
+    assert_eq!(swatch(2, "rgb(0, 0, 255)"), rgb(0, 0, 255));
+    // Colours at the end of a line have their swatch past the end of it
+    assert_eq!(swatch(3, "#0f0"), rgb(0, 255, 0));
+    // The text itself doesn't move, so neither does the cursor
+    let screen = act(&editor, &lua, &backend, &[Action::MoveEnd]);
+    assert!(screen.row(1).contains("#ff0000; }"));
+    let cursor = ged!(&editor).cursor_position().unwrap();
+    assert_eq!(screen.row(1).chars().nth(cursor.x - 1), Some('}'));
+    // Swatches can be turned off, and are only drawn in certain types of file
+    let editor_bg = config!(ged!(&editor).config, colors)
+        .editor_bg
+        .to_color()
+        .unwrap();
+    lua.load("document.color_preview = false").exec().unwrap();
+    assert_eq!(swatch(1, "#ff0000"), editor_bg);
+    lua.load("document.color_preview = true").exec().unwrap();
+    open(&editor, &file("swatches.txt", text));
+    ged!(mut &editor).switch_to(1);
+    assert_eq!(swatch(1, "#ff0000"), editor_bg);
+}
+
+#[test]
+fn stopping_long_operations() {
+    let lua = Lua::new();
+    let (editor, backend) = editor(&lua);
+    let text = "a b a\n".repeat(200);
+    open(&editor, &file("stopping.txt", &text));
+    act(&editor, &lua, &backend, &[]);
+    let depth = undo_depth(&editor);
+    // Escape stops replacing, leaving the document as it was
+    let esc = (KeyModifiers::NONE, KeyCode::Esc);
+    queue(&editor, &[esc, (KeyModifiers::NONE, KeyCode::Char('x'))]);
+    assert_eq!(ged!(mut &editor).replace_all("a", "c"), 0);
+    assert_eq!(ged!(&editor).try_doc().unwrap().line(0).unwrap(), "a b a");
+    assert_eq!(undo_depth(&editor), depth);
+    assert!(matches!(ged!(&editor).feedback, Feedback::Warning(_)));
+    // Other keys are kept to be dealt with afterwards
+    assert_eq!(ged!(&editor).pending_events.len(), 1);
+    ged!(mut &editor).pending_events.clear();
+    assert_eq!(ged!(mut &editor).replace_all("a", "c"), 400);
+    assert_eq!(undo_depth(&editor), depth + 1);
+    // Stopping an operation inside another stops both, but not the next one
+    let mut ed = ged!(mut &editor);
+    ed.start_progress("outer");
+    assert!(ed.tick(0.5));
+    ed.start_progress("inner");
+    ed.pending_events
+        .push_back(Event::Key(KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE)));
+    assert!(!ed.tick(0.1));
+    ed.finish();
+    assert!(!ed.tick(0.6));
+    ed.finish();
+    ed.start_progress("next");
+    assert!(ed.tick(0.0));
+    ed.finish();
+}
+
+#[test]
+fn stepping_out_needs_a_terminal() {
+    let lua = Lua::new();
+    let (editor, _) = editor(&lua);
+    // Without a terminal there is nothing to hand back, so nothing is touched
+    ged!(mut &editor).headless = true;
+    #[cfg(not(target_os = "windows"))]
+    assert!(matches!(
+        ged!(mut &editor).suspend(),
+        Err(ox::error::OxError::Headless)
+    ));
+    assert!(matches!(
+        ged!(mut &editor).shell(),
+        Err(ox::error::OxError::Headless)
+    ));
+    assert!(ged!(&editor).focus.focused);
+}
+
+#[test]
+fn save_conflicts() {
+    let lua = Lua::new();
+    let (editor, backend) = editor(&lua);
+    let path = file("conflict.txt", "first\n");
+    open(&editor, &path);
+    act(&editor, &lua, &backend, &[Action::InsertChar('a')]);
+    // A second document pointed at the same file, with changes of its own
+    ged!(mut &editor).new_document().unwrap();
+    act(&editor, &lua, &backend, &[Action::InsertChar('b')]);
+    ged!(mut &editor).try_doc_mut().unwrap().file_name = Some(path.clone());
+    let none = KeyModifiers::NONE;
+    let unsaved = |tab: usize| {
+        let editor = ged!(&editor);
+        let (fcs, _) = editor.files.get_atom(editor.ptr.clone()).unwrap();
+        !fcs[tab]
+            .doc
+            .event_mgmt
+            .with_disk(&fcs[tab].doc.take_snapshot())
+    };
+    // Backing out leaves the file and both documents alone
+    queue(&editor, &[(none, KeyCode::Esc)]);
+    assert!(ged!(mut &editor).save().is_err());
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "first\n");
+    assert!(unsaved(0) && unsaved(1));
+    queue(&editor, &[(none, KeyCode::Char('4'))]);
+    assert!(ged!(mut &editor).save().is_err());
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "first\n");
+    // Merging by hand compares the two documents in the diff view and saves nothing
+    queue(&editor, &[(none, KeyCode::Char('3'))]);
+    ged!(mut &editor).save().unwrap();
+    let screen = act(&editor, &lua, &backend, &[]);
+    assert!(screen.row(1).contains("1 afirst") && screen.row(1).contains("1 b"));
+    assert_eq!(ged!(&editor).try_doc().unwrap().line(0).unwrap(), "b");
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "first\n");
+    ged!(mut &editor).toggle_diff(None).unwrap();
+    // Saving this document overwrites the file, the other keeps its changes
+    queue(&editor, &[(none, KeyCode::Char('1'))]);
+    ged!(mut &editor).save().unwrap();
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "b\n");
+    assert!(unsaved(0) && !unsaved(1));
+    // Saving the other document instead leaves this one unsaved
+    act(&editor, &lua, &backend, &[Action::InsertChar('c')]);
+    queue(&editor, &[(none, KeyCode::Char('2'))]);
+    ged!(mut &editor).save().unwrap();
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "afirst\n");
+    assert!(!unsaved(0) && unsaved(1));
+}
+
+#[test]
+fn plugin_directories() {
+    let lua = Lua::new();
+    let _ = editor(&lua);
+    let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/plugins");
+    let plugins = config::load_plugins_from(&lua, std::path::Path::new(dir));
+    let status = |name: &str| {
+        let plugin = plugins.iter().find(|p| p.manifest.name == name).unwrap();
+        plugin.status.clone()
+    };
+    let global = |name: &str| lua.globals().get::<Option<bool>>(name).unwrap();
+    // Plug-ins are run after the ones they depend on
+    assert_eq!(status("greeter"), config::PluginStatus::Loaded);
+    assert_eq!(status("base"), config::PluginStatus::Loaded);
+    assert_eq!(global("greeted"), Some(true));
+    // The rest are listed along with why they were disabled, without being run
+    let failed = |name: &str, reason: &str| {
+        let config::PluginStatus::Failed(why) = status(name) else {
+            panic!("{name} shouldn't have loaded");
+        };
+        assert!(why.contains(reason), "{name} failed with {why:?}");
+    };
+    failed("broken", "invalid manifest");
+    failed("future", "needs ox 999.0.0 or newer");
+    failed("lonely", "depends on 'missing'");
+    assert_eq!(global("future_loaded"), None);
+    assert_eq!(global("lonely_loaded"), None);
+    assert_eq!(plugins.len(), 5);
+}
+
+#[test]
+fn three_panes() {
+    let lua = Lua::new();
+    let (editor, backend) = editor(&lua);
+    let paths = [
+        file("pane_a.txt", "alpha\n"),
+        file("pane_b.txt", "bravo\n"),
+        file("pane_c.txt", "charlie\n"),
+    ];
+    open(&editor, &paths[0]);
+    let split = |side: &str, path: &str| {
+        let code = format!("editor:open_split_{side}({path:?})");
+        assert!(lua.load(code).eval::<bool>().unwrap());
+    };
+    split("right", &paths[1]);
+    split("down", &paths[2]);
+    // Splitting moves panes further down the tree, so find where each one ended up
+    let pane = |path: &str| {
+        let editor = ged!(&editor);
+        let found = editor.render_cache.span.iter().find(|(ptr, _, _)| {
+            let doc = &editor.files.get(ptr.clone()).unwrap().doc;
+            doc.file_name.as_deref() == Some(path)
+        });
+        found.unwrap().0.clone()
+    };
+    let shown =
+        |screen: &Screen, text: &str| (0..H).filter(|y| screen.row(*y).contains(text)).count();
+    let screen = act(&editor, &lua, &backend, &[]);
+    for text in ["alpha", "bravo", "charlie"] {
+        assert_eq!(shown(&screen, text), 1, "{text} should be on screen once");
+    }
+    let (left, right, below) = (pane(&paths[0]), pane(&paths[1]), pane(&paths[2]));
+    let dirty = |ptr: &Vec<usize>| ged!(&editor).render_budget.is_dirty(ptr);
+    assert!(!dirty(&left) && !dirty(&right) && !dirty(&below));
+    // Typing only changes the focused pane, the others keep the rows drawn for them
+    let screen = act(&editor, &lua, &backend, &[Action::InsertChar('!')]);
+    assert_eq!(shown(&screen, "!charlie"), 1);
+    assert_eq!(shown(&screen, "alpha"), 1);
+    assert_eq!(shown(&screen, "bravo"), 1);
+    assert!(ged!(&editor).render_budget.row(&left, 1).is_some());
+    // Unfocused panes whose documents change are drawn again
+    ged!(mut &editor)
+        .files
+        .get_mut(left.clone())
+        .unwrap()
+        .doc
+        .insert(&Loc { x: 0, y: 0 }, "?")
+        .unwrap();
+    let screen = act(&editor, &lua, &backend, &[]);
+    assert_eq!(shown(&screen, "?alpha"), 1);
+    assert_eq!(shown(&screen, "!charlie"), 1);
+    // Resizing moves every pane, so they are all drawn afresh
+    backend.resize(W - 10, H);
+    let screen = act(&editor, &lua, &backend, &[]);
+    for text in ["?alpha", "bravo", "!charlie"] {
+        assert_eq!(shown(&screen, text), 1, "{text} should be on screen once");
+    }
+    assert!(!dirty(&left) && !dirty(&right) && !dirty(&below));
+}
+
+#[test]
+fn placeholder_precedence() {
+    let lua = Lua::new();
+    let (editor, backend) = editor(&lua);
+    let path = file("precedence.txt", "text\n");
+    open(&editor, &path);
+    // The same names are given by built-ins, registered placeholders and global functions
+    lua.load(
+        r#"
+        function file_name() return "global" end
+        function project() return "global" end
+        function other() return "global" end
+        ox.register_placeholder("project", function() return "registered" end)
+        ox.register_placeholder("broken", function() error("oops") end)
+        function broken() return "global" end
+        status_line.parts = { "[{file_name}] [{project}] [{other}] [{broken}] [{unknown}]" }
+        "#,
+    )
+    .exec()
+    .unwrap();
+    let registered = lua
+        .load(r#"return pcall(ox.register_placeholder, "file_name", function() return "x" end)"#)
+        .eval::<bool>()
+        .unwrap();
+    assert!(!registered);
+    let screen = act(&editor, &lua, &backend, &[]);
+    let status = (0..H)
+        .map(|y| screen.row(y))
+        .find(|row| row.contains("[precedence.txt]"))
+        .expect("built-ins come before anything else");
+    // Registered placeholders come next, then global functions, otherwise the text is kept
+    assert!(status.contains("[registered] [global]"));
+    assert!(status.contains("[{unknown}]"));
+    // Placeholders whose providers fail are left empty, rather than falling through
+    assert!(status.contains("[global] [] [{unknown}]"));
+}
+
+#[test]
+fn escape_codes() {
+    use crossterm::style::Color;
+    use ox::backend::{Backend, Cell};
+    let mut backend = MemoryBackend::new(10, 3);
+    // An escape code cut short by another is dropped, the one after it still applies
+    backend.write("\x1b\x1b[1mA").unwrap();
+    // Codes with several parts, titles and repeated codes each count once
+    backend
+        .write("\x1b[38;2;10;20;30;48;5;4mB\x1b]0;title\x07\x1b[0m\x1b[0mC")
+        .unwrap();
+    // So do codes split across writes
+    backend.write("\x1b[3").unwrap();
+    backend.write("mD\x1b").unwrap();
+    backend.write("[23mE").unwrap();
+    let screen = backend.screen();
+    assert_eq!(screen.row(0), "ABCDE     ");
+    let cell = |x: usize| *screen.cell(x, 0).unwrap();
+    assert!(cell(0).bold);
+    assert_eq!(
+        cell(1).fg,
+        Color::Rgb {
+            r: 10,
+            g: 20,
+            b: 30
+        }
+    );
+    assert_eq!(cell(1).bg, Color::DarkBlue);
+    assert!(cell(1).bold);
+    let plain = Cell {
+        ch: 'C',
+        ..Cell::default()
+    };
+    assert_eq!(cell(2), plain);
+    assert!(cell(3).italic);
+    assert!(!cell(4).italic);
+    assert_eq!(screen.escapes, 7);
+}
+
+#[test]
+fn escape_codes_per_row() {
+    use ox::backend::Backend;
+    let lua = Lua::new();
+    let (editor, backend) = editor(&lua);
+    let words = "word ".repeat(14);
+    let path = file(
+        "escapes.rs",
+        &format!("{words}\nlet value = \"text\"; // a comment\n"),
+    );
+    open(&editor, &path);
+    act(&editor, &lua, &backend, &[]);
+    let syntax = ged!(&editor).config.syntax_highlighting.clone();
+    let sh = syntax.borrow::<config::SyntaxHighlighting>().unwrap();
+    // Draw a row of the document on its own, then read it back
+    let row = |y: usize| {
+        let ptr = ged!(&editor).ptr.clone();
+        let size = kaolinite::utils::Size { w: W, h: H };
+        let text = ged!(mut &editor).render_file(&ptr, y, size, &sh).unwrap();
+        let mut row = MemoryBackend::new(W, 2);
+        row.write(&text).unwrap();
+        row.screen()
+    };
+    // Colours are only written where they change, rather than for every character
+    let plain = row(0);
+    assert!(plain.row(0).contains(words.trim_end()));
+    assert!(plain.escapes <= 8, "{} escape codes", plain.escapes);
+    // Selections and highlighted tokens add a code at each of their edges
+    let highlighted = row(1);
+    assert!(highlighted
+        .row(0)
+        .contains("let value = \"text\"; // a comment"));
+    assert!(
+        highlighted.escapes <= 16,
+        "{} escape codes",
+        highlighted.escapes
+    );
+    if let Some(doc) = ged!(mut &editor).try_doc_mut() {
+        doc.move_to(&Loc { x: 5, y: 0 });
+        doc.select_to(&Loc { x: 15, y: 0 });
+    }
+    let selected = row(0);
+    assert!(selected.escapes <= plain.escapes + 4);
+    let start = column(&selected, 0, "word");
+    let (inside, outside) = (selected.cell(start + 5, 0), selected.cell(start + 16, 0));
+    assert_ne!(inside.unwrap().bg, outside.unwrap().bg);
+}
+
+#[test]
+fn background_edits() {
+    use kaolinite::event::Event as Edit;
+    use synoptic::TokOpt;
+    let lua = Lua::new();
+    let (editor, backend) = editor(&lua);
+    open(&editor, &file("background.rs", "fn main() {}\n"));
+    open(&editor, &file("foreground.txt", "in front\n"));
+    let ptr = ged!(&editor).ptr.clone();
+    let before = act(&editor, &lua, &backend, &[]);
+    ged!(mut &editor).needs_rerender = false;
+    // Edit the document that isn't focused
+    let edit = Edit::Insert(Loc { x: 0, y: 0 }, "// ".to_string());
+    ged!(mut &editor).exe_at(&ptr, 0, edit).unwrap();
+    ged!(mut &editor).commit_at(&ptr, 0).unwrap();
+    ged!(mut &editor).reload_highlight_at(&ptr, 0).unwrap();
+    // It lands in that document, leaving the focused one (and the screen) alone
+    let line = |doc: usize| {
+        ged!(&editor)
+            .file_at(&ptr, doc)
+            .unwrap()
+            .doc
+            .line(0)
+            .unwrap()
+    };
+    assert_eq!(line(0), "// fn main() {}");
+    assert_eq!(line(1), "in front");
+    assert_eq!(ged!(&editor).files.get_atom(ptr.clone()).unwrap().1, 1);
+    assert!(!ged!(&editor).needs_rerender);
+    assert_eq!(backend.screen().text(), before.text());
+    // Its highlighting has caught up with the edit
+    let comment = ged!(&editor)
+        .file_at(&ptr, 0)
+        .unwrap()
+        .highlighter
+        .line(0, &line(0))
+        .iter()
+        .any(|token| matches!(token, TokOpt::Some(_, kind) if kind == "comment"));
+    assert!(comment);
+    // The edit can be undone and redone there
+    ged!(mut &editor)
+        .file_at_mut(&ptr, 0)
+        .unwrap()
+        .doc
+        .undo()
+        .unwrap();
+    assert_eq!(line(0), "fn main() {}");
+    ged!(mut &editor)
+        .file_at_mut(&ptr, 0)
+        .unwrap()
+        .doc
+        .redo()
+        .unwrap();
+    assert_eq!(line(0), "// fn main() {}");
+    // Switching over shows the edit straight away
+    ged!(mut &editor).prev();
+    let screen = act(&editor, &lua, &backend, &[]);
+    assert!(screen.text().contains("// fn main() {}"));
+    // Documents that have gone are turned away
+    let edit = Edit::Insert(Loc { x: 0, y: 0 }, "gone".to_string());
+    assert!(ged!(mut &editor).exe_at(&ptr, 5, edit).is_err());
+    assert!(ged!(mut &editor).commit_at(&ptr, 5).is_err());
+}
+
+#[test]
+fn quitting_with_scratch_buffers() {
+    let lua = Lua::new();
+    let (editor, backend) = editor(&lua);
+    let path = file("quit_all.txt", "kept\n");
+    open(&editor, &path);
+    ged!(mut &editor).character('x').unwrap();
+    ged!(mut &editor).scratch("notes").unwrap();
+    for ch in "jotted".chars() {
+        ged!(mut &editor).character(ch).unwrap();
+    }
+    act(&editor, &lua, &backend, &[]);
+    // The first line of the scratch buffer with a name, or of the real file
+    let contents = |scratch: Option<&str>| {
+        let editor = ged!(&editor);
+        let (files, _) = editor.files.get_atom(editor.ptr.clone()).unwrap();
+        let file = files.iter().find(|f| f.scratch.as_deref() == scratch);
+        file.and_then(|f| f.doc.line(0))
+    };
+    // Only the real file is asked about, and backing out keeps both as they were
+    queue(&editor, &[(KeyModifiers::NONE, KeyCode::Esc)]);
+    let result = ged!(mut &editor).quit_all(&lua);
+    assert!(matches!(result, Err(ox::error::OxError::Cancelled)));
+    let screen = backend.screen();
+    assert!(screen
+        .text()
+        .contains("1 unsaved document: s to save all and quit"));
+    assert!(screen.text().contains("quit_all.txt"));
+    assert!(ged!(&editor).active);
+    assert_eq!(contents(None).as_deref(), Some("xkept"));
+    assert_eq!(contents(Some("notes")).as_deref(), Some("jotted"));
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "kept\n");
+    // Saving everything saves the real file, without asking where to put the scratch buffer
+    queue(&editor, &[(KeyModifiers::NONE, KeyCode::Char('s'))]);
+    ged!(mut &editor).quit_all(&lua).unwrap();
+    assert!(!ged!(&editor).active);
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "xkept\n");
+}
+
+/// Feed the mouse and resize events that can arrive at any moment, drawing after each
+fn poke(editor: &AnyUserData, lua: &Lua, backend: &MemoryBackend) {
+    let spots = [
+        (0, 0),
+        (1, 5),
+        (10, 5),
+        (W - 1, 0),
+        (10, H - 2),
+        (W - 1, H - 1),
+    ];
+    let kinds = [
+        MouseEventKind::Down(MouseButton::Left),
+        MouseEventKind::Drag(MouseButton::Left),
+        MouseEventKind::Up(MouseButton::Left),
+        MouseEventKind::Down(MouseButton::Right),
+        MouseEventKind::ScrollDown,
+        MouseEventKind::ScrollUp,
+        MouseEventKind::Moved,
+    ];
+    for (column, row) in spots {
+        for kind in kinds {
+            let event = Event::Mouse(MouseEvent {
+                kind,
+                column: u16::try_from(column).unwrap(),
+                row: u16::try_from(row).unwrap(),
+                modifiers: KeyModifiers::NONE,
+            });
+            // Events may be turned away, but shouldn't bring the editor down
+            let _ = ox::handle_event(editor, &event, lua);
+            assert!(ged!(mut &editor).render(lua).is_ok());
+        }
+    }
+    for (w, h) in [(W - 30, H / 2), (5, 3), (W, H)] {
+        backend.resize(w, h);
+        let event = Event::Resize(u16::try_from(w).unwrap(), u16::try_from(h).unwrap());
+        let _ = ox::handle_event(editor, &event, lua);
+        assert!(ged!(mut &editor).render(lua).is_ok());
+    }
+}
+
+#[test]
+fn events_after_closing() {
+    let lua = Lua::new();
+    let (editor, backend) = editor(&lua);
+    let one = file("closing_one.txt", "one\n");
+    open(&editor, &one);
+    open(&editor, &file("closing_two.txt", "two\n"));
+    let code = format!("editor:open_split_right({one:?})");
+    lua.load(code).exec().unwrap();
+    act(&editor, &lua, &backend, &[]);
+    // A pane whose documents have all gone before the layout catches up
+    let ptr = ged!(&editor).ptr.clone();
+    ged!(mut &editor).files.get_atom_mut(ptr).unwrap().0.clear();
+    poke(&editor, &lua, &backend);
+    // Closing every document there is, one after another (from whichever pane is focused)
+    for _ in 0..10 {
+        let _ = ged!(mut &editor).perform(&lua, Action::Quit);
+        if !ged!(&editor).active {
+            break;
+        }
+    }
+    poke(&editor, &lua, &backend);
+}
+
+#[test]
+fn comparing_history_versions() {
+    let lua = Lua::new();
+    let (editor, backend) = editor(&lua);
+    lua.load("document.backups = true").exec().unwrap();
+    let path = file("versions.txt", "first\nsecond\n");
+    open(&editor, &path);
+    // Store the version as it is now, then change the document
+    let ptr = ged!(&editor).ptr.clone();
+    ged!(mut &editor).backup_at(&ptr, 0);
+    act(
+        &editor,
+        &lua,
+        &backend,
+        &[Action::MoveEnd, Action::InsertChar('!')],
+    );
+    // Comparing a stored version shows it beside the document in the diff view
+    queue(&editor, &[(KeyModifiers::NONE, KeyCode::Char('d'))]);
+    ged!(mut &editor).history(&lua, None).unwrap();
+    let screen = act(&editor, &lua, &backend, &[]);
+    let top = screen.row(0);
+    assert!(top.contains("versions.txt 20") && top.contains('→'));
+    assert!(screen.row(1).contains("1 first") && screen.row(1).contains("1 first!"));
+    // The document being edited keeps the focus
+    let doc = ged!(&editor).try_doc().unwrap().file.to_string();
+    assert_eq!(doc, "first!\nsecond\n");
+}
+
+#[test]
+fn saving_all_encodings() {
+    let lua = Lua::new();
+    let (editor, backend) = editor(&lua);
+    let path = file("latin.txt", "");
+    std::fs::write(&path, b"caf\xe9\n").unwrap();
+    open(&editor, &path);
+    act(&editor, &lua, &backend, &[Action::InsertChar('你')]);
+    // Saving everything still asks about characters the encoding can't hold
+    queue(&editor, &[(KeyModifiers::NONE, KeyCode::Esc)]);
+    let result = ged!(mut &editor).save_all();
+    assert!(matches!(result, Err(ox::error::OxError::Cancelled)));
+    assert_eq!(std::fs::read(&path).unwrap(), b"caf\xe9\n");
+}
+
+#[test]
+fn views() {
+    let lua = Lua::new();
+    let (editor, backend) = editor(&lua);
+    let lines: Vec<String> = (0..100).map(|n| format!("line {n}\n")).collect();
+    let path = file("views.txt", &lines.concat());
+    open(&editor, &path);
+    act(&editor, &lua, &backend, &[]);
+    ged!(mut &editor).split_current(true).unwrap();
+    let right = ged!(&editor).ptr.clone();
+    let mut left = right.clone();
+    left.pop();
+    left.push(0);
+    let doc = |ptr: &Vec<usize>| {
+        let editor = ged!(&editor);
+        let doc = &editor.files.get(ptr.clone()).unwrap().doc;
+        let line = |y| doc.file.line(y).to_string().trim_end().to_string();
+        (line(1), line(55), doc.loc(), doc.offset)
+    };
+    // The left pane is scrolled further down the document
+    {
+        let mut editor = ged!(mut &editor);
+        let doc = &mut editor.files.get_mut(left.clone()).unwrap().doc;
+        doc.move_to(&Loc::at(3, 55));
+        doc.offset.y = 50;
+    }
+    // Editing and undoing on the right is shown on the left, which stays where it was
+    // (the change is shared once the key press is done)
+    let edit = |actions: &[Action]| {
+        act(&editor, &lua, &backend, actions);
+        ged!(mut &editor).share_focused_changes();
+    };
+    edit(&[Action::MoveCursor(Direction::Down), Action::InsertChar('!')]);
+    let (line, _, loc, offset) = doc(&left);
+    assert_eq!(line, "!line 1");
+    assert_eq!((loc, offset), (Loc::at(3, 55), Loc::at(0, 50)));
+    edit(&[Action::Undo]);
+    assert_eq!(doc(&left), ("line 1".into(), "line 55".into(), loc, offset));
+    // And the same from the other side
+    let (_, _, loc, offset) = doc(&right);
+    ged!(mut &editor).ptr.clone_from(&left);
+    edit(&[Action::InsertChar('?')]);
+    assert_eq!(
+        doc(&right),
+        ("line 1".into(), "lin?e 55".into(), loc, offset)
+    );
+    edit(&[Action::Undo]);
+    assert_eq!(
+        doc(&right),
+        ("line 1".into(), "line 55".into(), loc, offset)
+    );
+    // Places that no longer exist are moved to the end of the document
+    edit(&[Action::SelectAll, Action::Backspace]);
+    ged!(mut &editor).ptr.clone_from(&right);
+    let editor_ref = ged!(&editor);
+    let other = &editor_ref.files.get(right.clone()).unwrap().doc;
+    assert_eq!((other.loc(), other.offset), (Loc::at(0, 0), Loc::at(0, 0)));
+    drop(editor_ref);
+    // A document opened separately at the same path is left alone
+    ged!(mut &editor).new_document().unwrap();
+    ged!(mut &editor).try_doc_mut().unwrap().file_name = Some(path.clone());
+    let at = (ged!(&editor).ptr.clone(), 1);
+    assert!(ged!(&editor).views_of(&at).is_empty());
+}
+
+#[test]
+fn named_macros() {
+    let lua = Lua::new();
+    let (editor, backend) = editor(&lua);
+    let path = file("macros.txt", "one\ntwo\nthree\n");
+    open(&editor, &path);
+    let key = |code: KeyCode| press(&editor, &lua, KeyModifiers::NONE, code);
+    // Record adding to the end of a line, then moving down to the next one
+    ged!(mut &editor).macro_man.record();
+    for code in [
+        KeyCode::End,
+        KeyCode::Char('!'),
+        KeyCode::Char('?'),
+        KeyCode::Down,
+    ] {
+        key(code);
+    }
+    ged!(mut &editor).macro_man.finish();
+    lua.load("commands['macro_save']({'shout'})")
+        .exec()
+        .unwrap();
+    // Named macros are read back from the disk in the next session
+    ged!(mut &editor).macro_man = MacroMan::default();
+    assert!(ged!(mut &editor).macro_man.load(&lua).unwrap().is_empty());
+    lua.load("commands['macro_run']({'shout'})").exec().unwrap();
+    while ged!(&editor).macro_man.playing {
+        let event = ged!(mut &editor).macro_man.next_event().unwrap();
+        ox::handle_event(&editor, &event, &lua).unwrap();
+    }
+    let screen = act(&editor, &lua, &backend, &[]);
+    assert!(screen.row(1).contains("one!?"));
+    assert!(screen.row(2).contains("two!?"));
+    assert_eq!(ged!(&editor).try_doc().unwrap().loc().y, 2);
+    // Typing afterwards is a change of its own, and the whole of the playback is undone at once
+    key(KeyCode::Up);
+    key(KeyCode::Char('.'));
+    let screen = act(&editor, &lua, &backend, &[Action::Undo]);
+    assert!(screen.row(2).contains("two!?") && !screen.row(2).contains("two!?."));
+    let screen = act(&editor, &lua, &backend, &[Action::Undo]);
+    assert!(screen.row(1).contains("one!?"));
+    assert!(screen.row(2).contains("two") && !screen.row(2).contains("two!"));
+}
+
+#[test]
+fn reading_large_files() {
+    let lua = Lua::new();
+    let (editor, backend) = editor(&lua);
+    let filler = "-".repeat(200);
+    let lines: Vec<String> = (0..45_000)
+        .map(|n| format!("line {n} {filler}\n"))
+        .collect();
+    let path = file("large.txt", &lines.concat());
+    open(&editor, &path);
+    act(&editor, &lua, &backend, &[]);
+    let (shown, h) = {
+        let editor = ged!(&editor);
+        let doc = editor.try_doc().unwrap();
+        assert!(editor.is_loading(doc) && doc.info.read_only);
+        (doc.len_lines(), doc.size.h)
+    };
+    // Getting close to the end of the start of the file that is shown reads just a bit more
+    ged!(mut &editor).try_doc_mut().unwrap().cursor.loc = Loc::at(0, shown - 2);
+    act(
+        &editor,
+        &lua,
+        &backend,
+        &[Action::MoveCursor(Direction::Down)],
+    );
+    {
+        let editor = ged!(&editor);
+        let doc = editor.try_doc().unwrap();
+        assert!(editor.is_loading(doc));
+        assert!(doc.len_lines() >= shown + h && doc.len_lines() < lines.len());
+        assert_eq!(doc.line(shown), Some(format!("line {shown} {filler}")));
+    }
+    // Jumping to the bottom needs all of it
+    act(&editor, &lua, &backend, &[Action::MoveBottom]);
+    let editor = ged!(&editor);
+    let doc = editor.try_doc().unwrap();
+    assert!(!editor.is_loading(doc) && !doc.info.read_only);
+    assert_eq!(doc.len_lines(), lines.len());
+}
+
+#[test]
+fn replacing_all() {
+    let lua = Lua::new();
+    let (editor, backend) = editor(&lua);
+    let path = file("replace_all.txt", &"a b a\n".repeat(30));
+    open(&editor, &path);
+    act(&editor, &lua, &backend, &[]);
+    // Replace one thing with another from part of the way down, pressing some keys in the menu
+    let replace = |target: char, into: char, keys: &[KeyCode]| {
+        ged!(mut &editor)
+            .try_doc_mut()
+            .unwrap()
+            .move_to(&Loc::at(1, 15));
+        let mut all = vec![KeyCode::Char(target), KeyCode::Enter];
+        all.extend([KeyCode::Char(into), KeyCode::Enter]);
+        all.extend(keys);
+        let all: Vec<_> = all
+            .into_iter()
+            .map(|code| (KeyModifiers::NONE, code))
+            .collect();
+        queue(&editor, &all);
+        // The menu is left once everything else is dealt with (played back like a macro,
+        // so that it isn't read ahead of time and taken as stopping the replacing)
+        let leave = Event::Key(KeyEvent::new(KeyCode::Esc, KeyModifiers::NONE));
+        ged!(mut &editor).macro_man.sequence = vec![leave];
+        ged!(mut &editor).macro_man.play(1);
+        ged!(mut &editor).replace(&lua).unwrap();
+        let editor = ged!(&editor);
+        let doc = editor.try_doc().unwrap();
+        (doc.file.to_string(), doc.char_loc(), doc.offset)
+    };
+    let untouched = replace('a', 'c', &[]);
+    // Replacing lots of occurrences has to be confirmed, cancelling leaves everything as it was
+    assert_eq!(replace('a', 'c', &[KeyCode::Tab, KeyCode::Esc]), untouched);
+    let (text, loc, _) = replace('a', 'c', &[KeyCode::Tab, KeyCode::Enter]);
+    assert_eq!(text, "c b c\n".repeat(30));
+    assert_eq!(loc, Loc::at(4, 29));
+    assert!(
+        matches!(&ged!(&editor).feedback, Feedback::Info(msg) if msg == "Replaced 60 occurrences")
+    );
+    // Fewer occurrences than the threshold are replaced straight away
+    lua.load("replace.confirm_threshold = 100").exec().unwrap();
+    let (text, _, _) = replace('c', 'd', &[KeyCode::Tab]);
+    assert_eq!(text, "d b d\n".repeat(30));
+    // As is everything, when replacing all doesn't need confirming
+    lua.load("replace.confirm_threshold = 10").exec().unwrap();
+    lua.load("replace.confirm_all = false").exec().unwrap();
+    let (text, _, _) = replace('d', 'e', &[KeyCode::Tab]);
+    assert_eq!(text, "e b e\n".repeat(30));
+}
+
+#[test]
+fn block_selection_guides() {
+    let lua = Lua::new();
+    let (editor, backend) = editor(&lua);
+    let lines = [
+        "abcdefghij",
+        "ab",
+        "a long line here",
+        "x",
+        "medium one",
+        "short",
+        "",
+        "abcdefghijklmnop",
+    ];
+    let path = file("ragged.txt", &(lines.join("\n") + "\n"));
+    open(&editor, &path);
+    // Select a block of two lines and four columns upwards, onto a line shorter than it
+    {
+        let mut editor = ged!(mut &editor);
+        let doc = editor.try_doc_mut().unwrap();
+        doc.move_to(&Loc::at(2, 4));
+        doc.select_block_to(&Loc::at(6, 3));
+    }
+    let screen = act(&editor, &lua, &backend, &[]);
+    let status = |screen: &Screen, text: &str| (0..H).any(|y| screen.row(y).contains(text));
+    assert!(status(&screen, "BLOCK 2×4"));
+    let start = column(&screen, 1, "abcdefghij");
+    let cell = |screen: &Screen, x: usize, y: usize| *screen.cell(start + x, y + 1).unwrap();
+    let text =
+        |screen: &Screen, y: usize| screen.row(y + 1).chars().skip(start).collect::<String>();
+    // Guides are drawn past the end of short lines and behind the text of longer ones,
+    // on the two rows either side of the block
+    let guided = |screen: &Screen, y: usize, cols: [usize; 2]| {
+        for x in cols {
+            let (guide, plain) = (cell(screen, x, y), cell(screen, x + 1, y));
+            match lines[y].chars().nth(x) {
+                Some(ch) => assert!(guide.ch == ch && guide.bg != plain.bg, "{x}, {y}"),
+                None => assert_eq!(guide.ch, '│', "{x}, {y}"),
+            }
+        }
+    };
+    for y in [1, 2, 5, 6] {
+        guided(&screen, y, [2, 5]);
+    }
+    for y in [0, 7] {
+        assert!(!text(&screen, y).contains('│'));
+        assert_eq!(cell(&screen, 2, y).bg, cell(&screen, 3, y).bg);
+    }
+    // Snapping to the end goes to the end of the longest line in the block
+    let screen = act(&editor, &lua, &backend, &[Action::MoveEnd]);
+    assert!(status(&screen, "BLOCK 2×8"));
+    for y in [1, 2, 5, 6] {
+        guided(&screen, y, [2, 9]);
+    }
+    // Leaving the block leaves the status line and the rows around it as they were
+    lua.load("editor:cancel_selection()").exec().unwrap();
+    let screen = act(&editor, &lua, &backend, &[]);
+    assert!(!status(&screen, "BLOCK"));
+    assert!(!text(&screen, 1).contains('│'));
+}
+
+/// The error message shown below the status line (if there is one)
+fn error_message(editor: &AnyUserData) -> String {
+    match &ged!(&editor).feedback {
+        Feedback::Error(msg) => msg.clone(),
+        _ => String::new(),
+    }
+}
+
+#[test]
+fn config_errors() {
+    let lua = Lua::new();
+    let (editor, backend) = editor(&lua);
+    let path = file("config.txt", "first line\n");
+    open(&editor, &path);
+    // Invalid settings are caught as the file is read, naming the setting and the line
+    let config = file(
+        "colour.oxrc",
+        "document.tab_width = 4\ncolors.editor_bg = 'blu'\n",
+    );
+    assert!(ged!(mut &editor).load_config(&config, &lua).is_some());
+    let error = error_message(&editor);
+    assert!(error.contains("on line 2"));
+    assert!(error.contains("colors.editor_bg"));
+    // The editor carries on with the defaults, saying so in the status line
+    let screen = act(&editor, &lua, &backend, &[]);
+    assert!(screen
+        .row(H - 2)
+        .contains("config error — running with defaults"));
+    assert!(screen.row(H - 2).contains("config.txt"));
+    assert!(ged!(&editor).config_error.is_some());
+    // Syntax errors say where they are too
+    let config = file("syntax.oxrc", "-- comment\n\nlocal = 1\n");
+    assert!(ged!(mut &editor).load_config(&config, &lua).is_some());
+    assert!(error_message(&editor).contains("on line 3"));
+    // Once the file is fixed, the warning goes away
+    let config = file("fixed.oxrc", "colors.editor_bg = 'blue'\n");
+    assert!(ged!(mut &editor).load_config(&config, &lua).is_none());
+    let screen = act(&editor, &lua, &backend, &[]);
+    assert!(!screen.row(H - 2).contains("config error"));
+}
+
+#[test]
+fn commit_message_rulers() {
+    let lua = Lua::new();
+    let (editor, backend) = editor(&lua);
+    let path = file(
+        "COMMIT_EDITMSG",
+        "Subject\n\n# Please enter the commit message\n",
+    );
+    open(&editor, &path);
+    let screen = act(&editor, &lua, &backend, &[]);
+    let ptr = ged!(&editor).ptr.clone();
+    let doc = ged!(&editor).files.get_atom(ptr.clone()).unwrap().1;
+    assert_eq!(ged!(&editor).git_operation(&ptr, doc), Some("commit"));
+    let colors = config!(ged!(&editor).config, colors);
+    let ruler = colors.line_number_bg.to_color().unwrap();
+    let editor_bg = colors.editor_bg.to_color().unwrap();
+    drop(colors);
+    // Columns 51 and 73 are marked, on short lines as well as long ones
+    let start = column(&screen, 1, "Subject");
+    for y in 1..=3 {
+        assert_eq!(screen.cell(start + 50, y).unwrap().bg, ruler);
+        assert_eq!(screen.cell(start + 72, y).unwrap().bg, ruler);
+        assert_eq!(screen.cell(start + 49, y).unwrap().bg, editor_bg);
+    }
+}
+
+#[test]
+fn pasted_paths() {
+    use ox::editor::pasted_files;
+    let plain = file("dropped.txt", "");
+    let spaced = file("dropped file.txt", "");
+    // Quoted paths, escaped spaces and file addresses all name files
+    let quoted = format!("'{spaced}' {plain}");
+    assert_eq!(
+        pasted_files(&quoted),
+        Some(vec![spaced.clone(), plain.clone()])
+    );
+    let escaped = spaced.replace(' ', "\\ ");
+    assert_eq!(pasted_files(&escaped), Some(vec![spaced.clone()]));
+    let uri = format!("file://{}", spaced.replace(' ', "%20"));
+    assert_eq!(pasted_files(&uri), Some(vec![spaced.clone()]));
+    // Anything that isn't an existing file is left as text
+    assert_eq!(pasted_files(&spaced), None);
+    assert_eq!(pasted_files(&format!("{plain} missing.txt")), None);
+    assert_eq!(pasted_files(&format!("'{plain}")), None);
+    assert_eq!(pasted_files(env!("CARGO_TARGET_TMPDIR")), None);
+    assert_eq!(pasted_files("  "), None);
+}
+
+/// How many changes there are to undo in the document being edited
+fn undo_depth(editor: &AnyUserData) -> usize {
+    ged!(&editor).try_doc().unwrap().event_mgmt.ptr
+}
+
+#[test]
+fn bulk_edits_undo_together() {
+    let lua = Lua::new();
+    let (editor, backend) = editor(&lua);
+    let path = file("bulk.txt", "one two one\none three one\n");
+    open(&editor, &path);
+    act(&editor, &lua, &backend, &[Action::Commit]);
+    let depth = undo_depth(&editor);
+    // Replacing everything is a single change, undone and redone in one go
+    ged!(mut &editor).replace_all("one", "four");
+    assert_eq!(undo_depth(&editor), depth + 1);
+    let screen = act(&editor, &lua, &backend, &[Action::Undo]);
+    assert!(screen.row(1).contains("one two one"));
+    assert!(screen.row(2).contains("one three one"));
+    let screen = act(&editor, &lua, &backend, &[Action::Redo]);
+    assert!(screen.row(1).contains("four two four"));
+    assert!(screen.row(2).contains("four three four"));
+    assert_eq!(undo_depth(&editor), depth + 1);
+    // Removing a selection is a change of its own, apart from what is typed next
+    let select = Action::Select(Direction::Right);
+    let screen = act(
+        &editor,
+        &lua,
+        &backend,
+        &[Action::MoveTop, select.clone(), select.clone(), select],
+    );
+    assert!(screen.row(1).contains("four two four"));
+    act(&editor, &lua, &backend, &[Action::Backspace]);
+    assert_eq!(undo_depth(&editor), depth + 2);
+    let screen = act(
+        &editor,
+        &lua,
+        &backend,
+        &[Action::Backspace, Action::Delete, Action::Undo],
+    );
+    assert!(screen.row(1).contains("r two four"));
+    let screen = act(&editor, &lua, &backend, &[Action::Undo]);
+    assert!(screen.row(1).contains("four two four"));
+}
+
+/// Press a key, just as if it were typed (running the key bindings in the configuration)
+fn press(editor: &AnyUserData, lua: &Lua, modifiers: KeyModifiers, code: KeyCode) {
+    let event = Event::Key(KeyEvent::new(code, modifiers));
+    ox::handle_event(editor, &event, lua).unwrap();
+}
+
+/// Type a count for the next key to be repeated by
+fn count(editor: &AnyUserData, lua: &Lua, n: &str) {
+    press(editor, lua, KeyModifiers::ALT, KeyCode::Char('0'));
+    for digit in n.chars() {
+        press(editor, lua, KeyModifiers::NONE, KeyCode::Char(digit));
+    }
+}
+
+#[test]
+fn counts_repeat_keys() {
+    let lua = Lua::new();
+    let (editor, backend) = editor(&lua);
+    let lines: Vec<String> = (1..=10).map(|n| format!("line {n}\n")).collect();
+    let path = file("counted.txt", &lines.concat());
+    open(&editor, &path);
+    act(&editor, &lua, &backend, &[Action::Commit]);
+    // The count is shown while it waits for a key
+    count(&editor, &lua, "3");
+    let screen = act(&editor, &lua, &backend, &[]);
+    assert!(screen.row(H - 2).contains("count: 3"));
+    // Deleting a line three times over is undone in one go
+    let depth = undo_depth(&editor);
+    press(&editor, &lua, KeyModifiers::ALT, KeyCode::Char('d'));
+    let screen = act(&editor, &lua, &backend, &[]);
+    assert!(screen.row(1).contains("line 4"));
+    assert!(!screen.row(H - 2).contains("count:"));
+    assert_eq!(undo_depth(&editor), depth + 1);
+    let screen = act(&editor, &lua, &backend, &[Action::Undo]);
+    assert!(screen.row(1).contains("line 1"));
+    assert!(screen.row(4).contains("line 4"));
+    // Escape cancels a count, so the key after it happens once
+    // (undoing left the cursor at the end of the lines brought back)
+    let y = ged!(&editor).try_doc().unwrap().loc().y;
+    count(&editor, &lua, "5");
+    press(&editor, &lua, KeyModifiers::NONE, KeyCode::Esc);
+    press(&editor, &lua, KeyModifiers::NONE, KeyCode::Down);
+    assert_eq!(ged!(&editor).try_doc().unwrap().loc().y, y + 1);
+    // Absurd counts stop at the end of the document
+    count(&editor, &lua, "1000000000");
+    press(&editor, &lua, KeyModifiers::NONE, KeyCode::Down);
+    let doc_len = ged!(&editor).try_doc().unwrap().len_lines();
+    assert!(ged!(&editor).try_doc().unwrap().loc().y + 1 >= doc_len);
+    assert_eq!(ged!(&editor).count.pending, None);
+}
+
+#[test]
+fn help_document() {
+    let lua = Lua::new();
+    let (editor, backend) = editor(&lua);
+    let path = file("helped.txt", "first line\n");
+    open(&editor, &path);
+    ged!(mut &editor).toggle_help(&lua).unwrap();
+    let screen = act(&editor, &lua, &backend, &[]);
+    assert!(screen.row(0).contains("[help]"));
+    assert!(ged!(&editor).showing_help());
+    // Each binding is listed with what it does, worked out from the configuration
+    let text = ged!(&editor).try_doc().unwrap().file.to_string();
+    let line = text.lines().find(|l| l.starts_with("Ctrl + H ")).unwrap();
+    assert!(line.contains("toggle help"));
+    assert!(ged!(&editor).try_doc().unwrap().info.read_only);
+    // It can't be saved, and q closes it
+    assert!(!ged!(&editor)
+        .files
+        .get(ged!(&editor).ptr.clone())
+        .unwrap()
+        .savable());
+    ged!(mut &editor)
+        .handle_key_event(&lua, KeyModifiers::NONE, KeyCode::Char('q'))
+        .unwrap();
+    assert!(!ged!(&editor).showing_help());
+    let screen = act(&editor, &lua, &backend, &[]);
+    assert!(!screen.row(0).contains("[help]"));
+}
+
+#[test]
+fn memory_backend() {
+    use ox::backend::Backend;
+    let mut backend = MemoryBackend::new(10, 3);
+    backend.write("\x1b[2;3Hab\x1b[1;38;5;9mc").unwrap();
+    let screen = backend.screen();
+    assert_eq!(screen.row(1), "  abc     ");
+    assert!(screen.cell(4, 1).unwrap().bold);
+    assert_eq!(screen.cell(4, 1).unwrap().fg, crossterm::style::Color::Red);
+    assert_eq!(screen.cursor, (5, 1));
+    // Escape codes split across writes are put back together
+    backend.write("\x1b[3").unwrap();
+    backend.write(";1Hz").unwrap();
+    assert_eq!(backend.screen().row(2), "z         ");
+    assert_eq!(backend.size().unwrap().h, 2);
+}